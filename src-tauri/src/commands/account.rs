@@ -85,40 +85,175 @@ pub async fn delete_account(
     Ok(())
 }
 
-/// Batch delete multiple accounts
-#[tauri::command]
-pub async fn delete_accounts(
-    app: tauri::AppHandle,
+/// Emitted once per batch account operation with `{ operation, account_ids }`
+pub const ACCOUNTS_CHANGED_EVENT: &str = "accounts://changed";
+
+/// Apply a batch operation, reload the token pool once and emit a single change event
+async fn run_account_batch(
+    app: &tauri::AppHandle,
     proxy_state: tauri::State<'_, crate::commands::proxy::ProxyServiceState>,
     account_ids: Vec<String>,
-) -> AppResult<()> {
+    op: modules::account::BatchOperation,
+    force: bool,
+) -> AppResult<Vec<modules::account::BatchItemResult>> {
+    use tauri::Emitter;
+
     modules::logger::log_info(&format!(
-        "Batch delete request received: {} accounts",
+        "Batch {} request received: {} accounts",
+        op.name(),
         account_ids.len()
     ));
 
-    // [FIX] Remove from TokenManager FIRST to prevent resurrection via persist_token()
-    {
-        let instance_lock = proxy_state.instance.read().await;
-        if let Some(instance) = instance_lock.as_ref() {
-            instance.token_manager.remove_accounts(&account_ids);
+    let token_manager = proxy_state
+        .instance
+        .read()
+        .await
+        .as_ref()
+        .map(|instance| instance.token_manager.clone());
+    // 仅在代理运行时保护最后一个可用账号
+    let protect_last_usable = token_manager.is_some() && !force;
+    let is_delete = matches!(op, modules::account::BatchOperation::Delete);
+
+    let result = modules::account::apply_batch(&account_ids, &op, protect_last_usable, |ids| {
+        // [FIX] Remove from TokenManager FIRST to prevent resurrection via persist_token()
+        if is_delete {
+            if let Some(token_manager) = token_manager.as_ref() {
+                token_manager.remove_accounts(ids);
+            }
         }
+    });
+
+    // 无论成功与否都重新加载一次, 保证号池与磁盘一致
+    let _ = crate::commands::proxy::reload_proxy_accounts(proxy_state).await;
+
+    let results = result.map_err(|e| {
+        modules::logger::log_error(&format!("Batch {} failed: {}", op.name(), e));
+        AppError::Account(e)
+    })?;
+
+    let changed: Vec<&str> = results
+        .iter()
+        .filter(|r| r.ok)
+        .map(|r| r.account_id.as_str())
+        .collect();
+    if !changed.is_empty() {
+        crate::modules::tray::update_tray_menus(app);
+        let _ = app.emit(
+            ACCOUNTS_CHANGED_EVENT,
+            serde_json::json!({ "operation": op.name(), "account_ids": changed }),
+        );
     }
-    
-    // Now delete account files and index entries
-    modules::account::delete_accounts(&account_ids)
-        .map_err(|e| {
-            modules::logger::log_error(&format!("Batch delete failed: {}", e));
-            AppError::Account(e)
-        })?;
 
-    // Force tray sync
-    crate::modules::tray::update_tray_menus(&app);
+    Ok(results)
+}
 
-    // Reload token pool (will not find the deleted accounts)
-    let _ = crate::commands::proxy::reload_proxy_accounts(proxy_state).await;
+/// Batch delete multiple accounts
+#[tauri::command]
+pub async fn delete_accounts(
+    app: tauri::AppHandle,
+    proxy_state: tauri::State<'_, crate::commands::proxy::ProxyServiceState>,
+    account_ids: Vec<String>,
+    force: Option<bool>,
+) -> AppResult<Vec<modules::account::BatchItemResult>> {
+    run_account_batch(
+        &app,
+        proxy_state,
+        account_ids,
+        modules::account::BatchOperation::Delete,
+        force.unwrap_or(false),
+    )
+    .await
+}
 
-    Ok(())
+/// Batch pause accounts (exclude them from the proxy pool)
+#[tauri::command]
+pub async fn pause_accounts(
+    app: tauri::AppHandle,
+    proxy_state: tauri::State<'_, crate::commands::proxy::ProxyServiceState>,
+    account_ids: Vec<String>,
+    force: Option<bool>,
+) -> AppResult<Vec<modules::account::BatchItemResult>> {
+    run_account_batch(
+        &app,
+        proxy_state,
+        account_ids,
+        modules::account::BatchOperation::Pause,
+        force.unwrap_or(false),
+    )
+    .await
+}
+
+/// Batch set the scheduling priority of accounts
+#[tauri::command]
+pub async fn set_accounts_priority(
+    app: tauri::AppHandle,
+    proxy_state: tauri::State<'_, crate::commands::proxy::ProxyServiceState>,
+    account_ids: Vec<String>,
+    priority: i32,
+) -> AppResult<Vec<modules::account::BatchItemResult>> {
+    run_account_batch(
+        &app,
+        proxy_state,
+        account_ids,
+        modules::account::BatchOperation::SetPriority(priority),
+        false,
+    )
+    .await
+}
+
+/// Batch add / remove account tags
+#[tauri::command]
+pub async fn set_accounts_tags(
+    app: tauri::AppHandle,
+    proxy_state: tauri::State<'_, crate::commands::proxy::ProxyServiceState>,
+    account_ids: Vec<String>,
+    add: Vec<String>,
+    remove: Vec<String>,
+) -> AppResult<Vec<modules::account::BatchItemResult>> {
+    run_account_batch(
+        &app,
+        proxy_state,
+        account_ids,
+        modules::account::BatchOperation::SetTags { add, remove },
+        false,
+    )
+    .await
+}
+
+/// Set or clear (empty / null) the free-text notes of an account
+#[tauri::command]
+pub async fn set_account_notes(
+    app: tauri::AppHandle,
+    proxy_state: tauri::State<'_, crate::commands::proxy::ProxyServiceState>,
+    account_id: String,
+    notes: Option<String>,
+) -> AppResult<Vec<modules::account::BatchItemResult>> {
+    run_account_batch(
+        &app,
+        proxy_state,
+        vec![account_id],
+        modules::account::BatchOperation::SetNotes(notes),
+        false,
+    )
+    .await
+}
+
+/// Per-account notes, last upstream error and live pool state
+#[tauri::command]
+pub async fn get_account_health(
+    proxy_state: tauri::State<'_, crate::commands::proxy::ProxyServiceState>,
+) -> AppResult<Vec<crate::proxy::token_manager::AccountHealth>> {
+    let accounts = modules::list_accounts().await.map_err(AppError::Account)?;
+    let token_manager = proxy_state
+        .instance
+        .read()
+        .await
+        .as_ref()
+        .map(|instance| instance.token_manager.clone());
+    Ok(accounts
+        .iter()
+        .map(|account| crate::proxy::token_manager::AccountHealth::new(account, token_manager.as_deref()))
+        .collect())
 }
 
 /// Reorder accounts list
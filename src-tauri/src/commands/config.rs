@@ -25,32 +25,98 @@ pub async fn save_config(
     proxy_state: tauri::State<'_, crate::commands::proxy::ProxyServiceState>,
     config: AppConfig,
 ) -> AppResult<()> {
-    modules::save_app_config(&config)
-        .map_err(AppError::Config)?;
+    {
+        let instance_lock = proxy_state.instance.read().await;
+        match instance_lock.as_ref() {
+            // Mapping swap and save run as one serialized update; rolled back if saving fails
+            Some(instance) => instance
+                .axum_server
+                .replace_mapping(config.proxy.custom_mapping.clone(), |_| {
+                    modules::save_app_config(&config)
+                })
+                .await
+                .map_err(AppError::Config)?,
+            None => modules::save_app_config(&config).map_err(AppError::Config)?,
+        }
+    }
 
     // Notify tray that config was updated
     let _ = app.emit("config://updated", ());
 
-    // Hot-reload running service
-    let instance_lock = proxy_state.instance.read().await;
-    if let Some(instance) = instance_lock.as_ref() {
+    // Hot-reload running service (mapping already swapped above)
+    hot_reload_proxy_settings(&proxy_state, &config).await;
+
+    Ok(())
+}
+
+/// Restore a config section (mapping / scheduling / zai / experimental / all)
+/// from the last-known-good backup, then hot-reload the running service
+#[tauri::command]
+pub async fn restore_config_backup(
+    app: tauri::AppHandle,
+    proxy_state: tauri::State<'_, crate::commands::proxy::ProxyServiceState>,
+    section: String,
+) -> AppResult<AppConfig> {
+    let config = modules::config::restore_config_backup(&section)
+        .map_err(AppError::Config)?;
+
+    let _ = app.emit("config://updated", ());
+    hot_reload_proxy_config(&proxy_state, &config).await;
+
+    Ok(config)
+}
+
+/// Warning recorded when the config file was recovered from backup at load time
+#[tauri::command]
+pub async fn get_config_recovery_warning() -> AppResult<Option<String>> {
+    Ok(modules::config::config_recovery_warning())
+}
+
+async fn hot_reload_proxy_config(
+    proxy_state: &crate::commands::proxy::ProxyServiceState,
+    config: &AppConfig,
+) {
+    if let Some(instance) = proxy_state.instance.read().await.as_ref() {
         // Update model mapping
         instance.axum_server.update_mapping(&config.proxy).await;
+    }
+    hot_reload_proxy_settings(proxy_state, config).await;
+}
+
+/// Hot-reload everything except the model mapping
+async fn hot_reload_proxy_settings(
+    proxy_state: &crate::commands::proxy::ProxyServiceState,
+    config: &AppConfig,
+) {
+    let instance_lock = proxy_state.instance.read().await;
+    if let Some(instance) = instance_lock.as_ref() {
         // Update upstream proxy
         instance
             .axum_server
             .update_proxy(config.proxy.upstream_proxy.clone())
             .await;
+        instance
+            .axum_server
+            .update_upstream_timeouts(&config.proxy)
+            .await;
         // Update security (auth)
         instance.axum_server.update_security(&config.proxy).await;
         // Update z.ai config
         instance.axum_server.update_zai(&config.proxy).await;
+        instance.axum_server.update_providers(&config.proxy).await;
         // Update experimental config
         instance.axum_server.update_experimental(&config.proxy).await;
         // Update debug logging config
         instance.axum_server.update_debug_logging(&config.proxy).await;
         // Update User-Agent config
         instance.axum_server.update_user_agent(&config.proxy).await;
+        crate::proxy::common::thinking_capability::set_overrides(&config.proxy.thinking_overrides);
+        crate::proxy::common::post_process::set_config(&config.proxy.post_process);
+        crate::proxy::key_budget::set_config(&config.proxy.key_budgets);
+        crate::proxy::config::update_retry_config(config.proxy.retry);
+        crate::proxy::image_store::set_ttl_secs(config.proxy.image_url_ttl_secs);
+        crate::proxy::common::thinking_defaults::set_config(&config.proxy);
+        crate::proxy::common::system_injection::set_config(&config.proxy);
         // Update circuit breaker config
         instance.token_manager.update_circuit_breaker_config(config.circuit_breaker.clone()).await;
         // Update sticky scheduling config
@@ -58,8 +124,6 @@ pub async fn save_config(
         
         tracing::debug!("Hot-reloaded proxy service configuration");
     }
-
-    Ok(())
 }
 
 // ============================================================================
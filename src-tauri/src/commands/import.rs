@@ -56,6 +56,29 @@ pub async fn import_from_db(app: tauri::AppHandle) -> AppResult<Account> {
     Ok(account)
 }
 
+/// Import accounts from the official Antigravity client.
+/// Without `confirmed` only lists candidates; with it imports the confirmed candidate ids.
+#[tauri::command]
+pub async fn import_from_antigravity(
+    app: tauri::AppHandle,
+    confirmed: Option<Vec<String>>,
+) -> AppResult<modules::antigravity_import::AntigravityImportReport> {
+    let report = modules::antigravity_import::import_from_antigravity(confirmed.as_deref()).await;
+
+    if !report.imported.is_empty() {
+        for mut account in report.imported.clone() {
+            let _ = internal_refresh_account_quota(&app, &mut account).await;
+        }
+
+        crate::modules::tray::update_tray_menus(&app);
+
+        let proxy_state = app.state::<crate::commands::proxy::ProxyServiceState>();
+        let _ = crate::commands::proxy::reload_proxy_accounts(proxy_state).await;
+    }
+
+    Ok(report)
+}
+
 /// Import from custom database path
 #[tauri::command]
 #[allow(dead_code)]
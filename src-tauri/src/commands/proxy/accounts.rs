@@ -1,40 +1,101 @@
 // Proxy Account Operations Commands
 
-use tauri::State;
+use tauri::{Emitter, State};
+use std::sync::Arc;
+use super::error::ProxyCommandError;
 use super::types::ProxyServiceState;
+use crate::proxy::token_manager::{AccountVerification, VerificationProgress};
+use crate::proxy::TokenManager;
+
+/// 账号体检进度事件
+pub const VERIFICATION_PROGRESS_EVENT: &str = "proxy://verification-progress";
+
+pub(crate) fn emit_verification_progress(app_handle: Option<&tauri::AppHandle>, progress: &VerificationProgress) {
+    if let Some(handle) = app_handle {
+        let _ = handle.emit(VERIFICATION_PROGRESS_EVENT, progress);
+    }
+}
+
+/// TokenManager of the running proxy instance
+pub(crate) async fn running_token_manager(
+    state: &ProxyServiceState,
+) -> Result<Arc<TokenManager>, ProxyCommandError> {
+    state
+        .instance
+        .read()
+        .await
+        .as_ref()
+        .map(|instance| instance.token_manager.clone())
+        .ok_or(ProxyCommandError::NotRunning)
+}
+
+/// 账号必须在号池中 (已禁用 / 未加载的账号不在其中)
+pub(crate) fn ensure_in_pool(token_manager: &TokenManager, account_id: &str) -> Result<(), ProxyCommandError> {
+    if token_manager.has_account(account_id) {
+        Ok(())
+    } else {
+        Err(ProxyCommandError::AccountNotFound { id: account_id.to_string() })
+    }
+}
 
 /// Reload accounts (called when main app adds/deletes accounts)
 #[tauri::command]
 pub async fn reload_proxy_accounts(
     state: State<'_, ProxyServiceState>,
-) -> Result<usize, String> {
-    let instance_lock = state.instance.read().await;
+) -> Result<usize, ProxyCommandError> {
+    let token_manager = running_token_manager(&state).await?;
 
-    if let Some(instance) = instance_lock.as_ref() {
-        // [FIX #820] Clear stale session bindings before reloading accounts
-        instance.token_manager.clear_all_sessions();
+    // [FIX #820] Clear stale session bindings before reloading accounts
+    token_manager.clear_all_sessions();
 
-        // Reload accounts
-        let count = instance.token_manager.load_accounts().await
-            .map_err(|e| format!("重新加载账号失败: {}", e))?;
-        Ok(count)
-    } else {
-        Err("服务未运行".to_string())
-    }
+    // Reload accounts
+    let count = token_manager.load_accounts().await
+        .map_err(|e| ProxyCommandError::internal(format!("重新加载账号失败: {}", e)))?;
+    Ok(count)
+}
+
+/// 体检所有账号 (刷新 token + 能力探测), 标记失效账号并返回结果
+/// `force` 为 true 时忽略缓存重新探测
+#[tauri::command]
+pub async fn verify_all_accounts(
+    state: State<'_, ProxyServiceState>,
+    app_handle: tauri::AppHandle,
+    force: Option<bool>,
+) -> Result<Vec<AccountVerification>, ProxyCommandError> {
+    let (token_manager, cache_ttl_secs) = {
+        let instance_lock = state.instance.read().await;
+        let instance = instance_lock.as_ref().ok_or(ProxyCommandError::NotRunning)?;
+        (
+            instance.token_manager.clone(),
+            instance.config.startup_verification.cache_ttl_secs,
+        )
+    };
+
+    Ok(token_manager
+        .verify_all_accounts(cache_ttl_secs, force.unwrap_or(false), |progress| {
+            emit_verification_progress(Some(&app_handle), progress)
+        })
+        .await)
+}
+
+/// 重新探测账号项目是否已开通, 成功后清除 project_setup_required 状态
+#[tauri::command]
+pub async fn retry_project_setup(
+    state: State<'_, ProxyServiceState>,
+    account_id: String,
+) -> Result<(), ProxyCommandError> {
+    let token_manager = running_token_manager(&state).await?;
+    ensure_in_pool(&token_manager, &account_id)?;
+    Ok(token_manager.retry_project_setup(&account_id).await?)
 }
 
 /// Clear all session sticky bindings
 #[tauri::command]
 pub async fn clear_proxy_session_bindings(
     state: State<'_, ProxyServiceState>,
-) -> Result<(), String> {
-    let instance_lock = state.instance.read().await;
-    if let Some(instance) = instance_lock.as_ref() {
-        instance.token_manager.clear_all_sessions();
-        Ok(())
-    } else {
-        Err("服务未运行".to_string())
-    }
+) -> Result<(), ProxyCommandError> {
+    running_token_manager(&state).await?.clear_all_sessions();
+    Ok(())
 }
 
 // ===== [FIX #820] Fixed Account Mode Commands =====
@@ -45,7 +106,7 @@ pub async fn clear_proxy_session_bindings(
 pub async fn set_preferred_account(
     state: State<'_, ProxyServiceState>,
     account_id: Option<String>,
-) -> Result<(), String> {
+) -> Result<(), ProxyCommandError> {
     let instance_lock = state.instance.read().await;
     if let Some(instance) = instance_lock.as_ref() {
         // Filter empty strings to None
@@ -56,10 +117,10 @@ pub async fn set_preferred_account(
 
         // 2. Persist to config file (fix Issue #820 auto-close problem)
         let mut app_config = crate::modules::config::load_app_config()
-            .map_err(|e| format!("Failed to load config: {}", e))?;
+            .map_err(|e| ProxyCommandError::internal(format!("Failed to load config: {}", e)))?;
         app_config.proxy.preferred_account_id = cleaned_id.clone();
         crate::modules::config::save_app_config(&app_config)
-            .map_err(|e| format!("Failed to save config: {}", e))?;
+            .map_err(|e| ProxyCommandError::io(format!("Failed to save config: {}", e)))?;
 
         if let Some(ref id) = cleaned_id {
             tracing::info!("🔒 [FIX #820] Fixed account mode enabled and persisted: {}", id);
@@ -69,7 +130,7 @@ pub async fn set_preferred_account(
 
         Ok(())
     } else {
-        Err("服务未运行".to_string())
+        Err(ProxyCommandError::NotRunning)
     }
 }
 
@@ -77,7 +138,7 @@ pub async fn set_preferred_account(
 #[tauri::command]
 pub async fn get_preferred_account(
     state: State<'_, ProxyServiceState>,
-) -> Result<Option<String>, String> {
+) -> Result<Option<String>, ProxyCommandError> {
     let instance_lock = state.instance.read().await;
     if let Some(instance) = instance_lock.as_ref() {
         Ok(instance.token_manager.get_preferred_account().await)
@@ -91,25 +152,15 @@ pub async fn get_preferred_account(
 pub async fn clear_proxy_rate_limit(
     state: State<'_, ProxyServiceState>,
     account_id: String,
-) -> Result<bool, String> {
-    let instance_lock = state.instance.read().await;
-    if let Some(instance) = instance_lock.as_ref() {
-        Ok(instance.token_manager.clear_rate_limit(&account_id))
-    } else {
-        Err("服务未运行".to_string())
-    }
+) -> Result<bool, ProxyCommandError> {
+    Ok(running_token_manager(&state).await?.clear_rate_limit(&account_id))
 }
 
 /// Clear all rate limit records
 #[tauri::command]
 pub async fn clear_all_proxy_rate_limits(
     state: State<'_, ProxyServiceState>,
-) -> Result<(), String> {
-    let instance_lock = state.instance.read().await;
-    if let Some(instance) = instance_lock.as_ref() {
-        instance.token_manager.clear_all_rate_limits();
-        Ok(())
-    } else {
-        Err("服务未运行".to_string())
-    }
+) -> Result<(), ProxyCommandError> {
+    running_token_manager(&state).await?.clear_all_rate_limits();
+    Ok(())
 }
@@ -0,0 +1,17 @@
+// Proxy Benchmark Command (diagnostics)
+
+use crate::proxy::benchmark::{BenchmarkConfig, BenchmarkReport};
+
+/// Run an in-process benchmark against a mock upstream through the real handler stack
+#[tauri::command]
+pub async fn run_proxy_benchmark(
+    config: Option<BenchmarkConfig>,
+) -> Result<BenchmarkReport, String> {
+    let config = config.unwrap_or_default();
+    tracing::info!(
+        "[Benchmark] Starting: {} req/protocol, concurrency {}",
+        config.requests_per_protocol,
+        config.concurrency
+    );
+    crate::proxy::benchmark::run_benchmark(config).await
+}
@@ -1,6 +1,7 @@
 // Proxy Config and Mapping Commands
 
 use tauri::State;
+use super::error::ProxyCommandError;
 use super::types::ProxyServiceState;
 use crate::proxy::ProxyConfig;
 
@@ -15,47 +16,44 @@ pub fn generate_api_key() -> String {
 pub async fn update_model_mapping(
     config: ProxyConfig,
     state: State<'_, ProxyServiceState>,
-) -> Result<(), String> {
+) -> Result<(), ProxyCommandError> {
     let instance_lock = state.instance.read().await;
-    
-    // 1. If service is running, update mapping in memory
+
+    // 1. If service is running, swap the mapping in memory and persist it (rolled back on save failure)
     if let Some(instance) = instance_lock.as_ref() {
-        instance.axum_server.update_mapping(&config).await;
+        instance
+            .axum_server
+            .replace_mapping(config.custom_mapping, crate::modules::config::save_custom_mapping)
+            .await?;
         tracing::debug!("后端服务已接收全量模型映射配置");
+        return Ok(());
     }
-    
-    // 2. Save to global config persistence
-    let mut app_config = crate::modules::config::load_app_config().map_err(|e| e)?;
-    app_config.proxy.custom_mapping = config.custom_mapping;
-    crate::modules::config::save_app_config(&app_config).map_err(|e| e)?;
-    
+
+    // 2. Otherwise only save to global config persistence
+    crate::modules::config::save_custom_mapping(&config.custom_mapping)?;
     Ok(())
 }
 
-/// Get current scheduling config
+/// Get the learned / pinned thinking capability table
 #[tauri::command]
-pub async fn get_proxy_scheduling_config(
-    state: State<'_, ProxyServiceState>,
-) -> Result<crate::proxy::sticky_config::StickySessionConfig, String> {
-    let instance_lock = state.instance.read().await;
-    if let Some(instance) = instance_lock.as_ref() {
-        Ok(instance.token_manager.get_sticky_config().await)
-    } else {
-        Ok(crate::proxy::sticky_config::StickySessionConfig::default())
-    }
+pub async fn get_thinking_capabilities(
+) -> Result<Vec<crate::proxy::common::thinking_capability::ThinkingCapabilityEntry>, ProxyCommandError> {
+    Ok(crate::proxy::common::thinking_capability::capability_table())
 }
 
-/// Update scheduling config
+/// Pin (or clear with `None`) the thinking capability of a physical model
 #[tauri::command]
-pub async fn update_proxy_scheduling_config(
-    state: State<'_, ProxyServiceState>,
-    config: crate::proxy::sticky_config::StickySessionConfig,
-) -> Result<(), String> {
-    let instance_lock = state.instance.read().await;
-    if let Some(instance) = instance_lock.as_ref() {
-        instance.token_manager.update_sticky_config(config).await;
-        Ok(())
-    } else {
-        Err("服务未运行，无法更新实时配置".to_string())
+pub async fn set_thinking_override(model: String, supported: Option<bool>) -> Result<(), ProxyCommandError> {
+    let mut app_config = crate::modules::config::load_app_config()?;
+    match supported {
+        Some(v) => {
+            app_config.proxy.thinking_overrides.insert(model, v);
+        }
+        None => {
+            app_config.proxy.thinking_overrides.remove(&model);
+        }
     }
+    crate::modules::config::save_app_config(&app_config)?;
+    crate::proxy::common::thinking_capability::set_overrides(&app_config.proxy.thinking_overrides);
+    Ok(())
 }
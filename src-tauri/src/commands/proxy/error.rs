@@ -0,0 +1,253 @@
+// Proxy Command Errors
+// 反代命令层 (lifecycle / accounts / config / logs) 的统一错误类型。
+// 序列化格式与 AppError 一致: { "type": "Variant", "message": "...", "details": {...} },
+// 其中 message 保留迁移前的可读文本, 现有按字符串展示错误的界面无需修改即可继续工作。
+
+use crate::error::{AppError, FieldViolation};
+use serde::Serialize;
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum ProxyCommandError {
+    #[error("服务未运行")]
+    NotRunning,
+
+    #[error("服务已在运行中")]
+    AlreadyRunning,
+
+    #[error("服务正在启动中，请稍候...")]
+    Starting,
+
+    #[error("启动管理服务器失败: {reason}")]
+    PortInUse { port: u16, reason: String },
+
+    #[error("账号 {id} 不在号池中")]
+    AccountNotFound { id: String },
+
+    #[error("{}", join_violations(.violations))]
+    ValidationFailed { violations: Vec<FieldViolation> },
+
+    #[error("{msg}")]
+    Io { msg: String },
+
+    #[error("{msg}")]
+    Internal { msg: String },
+}
+
+fn join_violations(violations: &[FieldViolation]) -> String {
+    violations
+        .iter()
+        .map(|v| format!("{}: {}", v.field, v.message))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+impl ProxyCommandError {
+    pub fn internal(msg: impl Into<String>) -> Self {
+        ProxyCommandError::Internal { msg: msg.into() }
+    }
+
+    pub fn io(msg: impl Into<String>) -> Self {
+        ProxyCommandError::Io { msg: msg.into() }
+    }
+
+    pub fn invalid(field: impl Into<String>, message: impl Into<String>) -> Self {
+        ProxyCommandError::ValidationFailed {
+            violations: vec![FieldViolation::new(field, message)],
+        }
+    }
+
+    /// 管理服务器启动失败: 端口被占用时归类为 PortInUse, 其余为 Internal (文本与旧版一致)
+    pub fn admin_start_failed(port: u16, reason: String) -> Self {
+        let lower = reason.to_lowercase();
+        let in_use = lower.contains("address already in use")
+            || lower.contains("only one usage of each socket address")
+            || lower.contains("os error 98")
+            || lower.contains("os error 48")
+            || lower.contains("os error 10048");
+        if in_use {
+            ProxyCommandError::PortInUse { port, reason }
+        } else {
+            ProxyCommandError::internal(format!("启动管理服务器失败: {}", reason))
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            ProxyCommandError::NotRunning => "NotRunning",
+            ProxyCommandError::AlreadyRunning => "AlreadyRunning",
+            ProxyCommandError::Starting => "Starting",
+            ProxyCommandError::PortInUse { .. } => "PortInUse",
+            ProxyCommandError::AccountNotFound { .. } => "AccountNotFound",
+            ProxyCommandError::ValidationFailed { .. } => "ValidationFailed",
+            ProxyCommandError::Io { .. } => "Io",
+            ProxyCommandError::Internal { .. } => "Internal",
+        }
+    }
+}
+
+/// Format: { "type": "Variant", "message": "<legacy text>", "details": {...} }
+impl Serialize for ProxyCommandError {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeStruct;
+
+        let details = match self {
+            ProxyCommandError::PortInUse { port, .. } => Some(serde_json::json!({ "port": port })),
+            ProxyCommandError::AccountNotFound { id } => Some(serde_json::json!({ "accountId": id })),
+            ProxyCommandError::ValidationFailed { violations } => {
+                Some(serde_json::json!({ "violations": violations }))
+            }
+            _ => None,
+        };
+
+        let field_count = if details.is_some() { 3 } else { 2 };
+        let mut state = serializer.serialize_struct("ProxyCommandError", field_count)?;
+        state.serialize_field("type", self.kind())?;
+        state.serialize_field("message", &self.to_string())?;
+        if let Some(ref d) = details {
+            state.serialize_field("details", d)?;
+        }
+        state.end()
+    }
+}
+
+// ============================================================================
+// Conversions from internal error types
+// ============================================================================
+
+/// 底层模块仍返回 Result<_, String>
+impl From<String> for ProxyCommandError {
+    fn from(msg: String) -> Self {
+        ProxyCommandError::Internal { msg }
+    }
+}
+
+impl From<std::io::Error> for ProxyCommandError {
+    fn from(e: std::io::Error) -> Self {
+        ProxyCommandError::io(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for ProxyCommandError {
+    fn from(e: serde_json::Error) -> Self {
+        ProxyCommandError::internal(format!("JSON error: {}", e))
+    }
+}
+
+impl From<AppError> for ProxyCommandError {
+    fn from(e: AppError) -> Self {
+        match e {
+            AppError::ConfigViolations(violations) => ProxyCommandError::ValidationFailed { violations },
+            AppError::AccountNotFound(id) => ProxyCommandError::AccountNotFound { id },
+            AppError::Io(e) => e.into(),
+            other => ProxyCommandError::internal(other.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::proxy::types::ProxyServiceState;
+
+    #[test]
+    fn test_serialized_shape_keeps_legacy_message() {
+        let json = serde_json::to_value(ProxyCommandError::NotRunning).unwrap();
+        assert_eq!(json, serde_json::json!({ "type": "NotRunning", "message": "服务未运行" }));
+
+        let json = serde_json::to_value(ProxyCommandError::AlreadyRunning).unwrap();
+        assert_eq!(json, serde_json::json!({ "type": "AlreadyRunning", "message": "服务已在运行中" }));
+        let json = serde_json::to_value(ProxyCommandError::Starting).unwrap();
+        assert_eq!(json["type"], "Starting");
+
+        let json = serde_json::to_value(ProxyCommandError::AccountNotFound { id: "acc-1".to_string() }).unwrap();
+        assert_eq!(json["type"], "AccountNotFound");
+        assert_eq!(json["message"], "账号 acc-1 不在号池中");
+        assert_eq!(json["details"]["accountId"], "acc-1");
+
+        let json = serde_json::to_value(ProxyCommandError::invalid("json_data", "expected array")).unwrap();
+        assert_eq!(json["type"], "ValidationFailed");
+        assert_eq!(json["message"], "json_data: expected array");
+        assert_eq!(json["details"]["violations"][0]["field"], "json_data");
+    }
+
+    #[test]
+    fn test_admin_start_failure_classification() {
+        let bind = "Failed to bind address 127.0.0.1:8045: Address already in use (os error 98)".to_string();
+        let err = ProxyCommandError::admin_start_failed(8045, bind.clone());
+        assert_eq!(err, ProxyCommandError::PortInUse { port: 8045, reason: bind.clone() });
+        assert_eq!(err.to_string(), format!("启动管理服务器失败: {}", bind));
+        assert_eq!(serde_json::to_value(&err).unwrap()["details"]["port"], 8045);
+
+        let windows = "Failed to bind address 0.0.0.0:8045: Only one usage of each socket address (os error 10048)";
+        assert!(matches!(
+            ProxyCommandError::admin_start_failed(8045, windows.to_string()),
+            ProxyCommandError::PortInUse { port: 8045, .. }
+        ));
+        assert!(matches!(
+            ProxyCommandError::admin_start_failed(8045, "Permission denied (os error 13)".to_string()),
+            ProxyCommandError::Internal { .. }
+        ));
+    }
+
+    #[test]
+    fn test_internal_error_conversions() {
+        let violations = vec![FieldViolation::new("selected_accounts[0]", "unknown account")];
+        assert_eq!(
+            ProxyCommandError::from(AppError::ConfigViolations(violations.clone())),
+            ProxyCommandError::ValidationFailed { violations }
+        );
+        assert_eq!(
+            ProxyCommandError::from(AppError::AccountNotFound("acc-2".to_string())),
+            ProxyCommandError::AccountNotFound { id: "acc-2".to_string() }
+        );
+        let io = std::io::Error::new(std::io::ErrorKind::PermissionDenied, "denied");
+        assert!(matches!(ProxyCommandError::from(io), ProxyCommandError::Io { .. }));
+        assert!(matches!(
+            ProxyCommandError::from(AppError::Io(std::io::Error::new(std::io::ErrorKind::NotFound, "gone"))),
+            ProxyCommandError::Io { .. }
+        ));
+        assert_eq!(
+            ProxyCommandError::from("Failed to load config".to_string()),
+            ProxyCommandError::internal("Failed to load config")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_commands_map_failures_to_variants() {
+        let state = ProxyServiceState::new();
+
+        // lifecycle: 管理服务器未启动时停止
+        assert_eq!(
+            super::super::lifecycle::internal_stop_admin_server(&state).await,
+            Err(ProxyCommandError::NotRunning)
+        );
+
+        // accounts: 服务未运行 / 账号不在号池中
+        assert_eq!(
+            super::super::accounts::running_token_manager(&state).await.err(),
+            Some(ProxyCommandError::NotRunning)
+        );
+        let token_manager = crate::proxy::TokenManager::new(std::env::temp_dir());
+        assert_eq!(
+            super::super::accounts::ensure_in_pool(&token_manager, "missing-acc"),
+            Err(ProxyCommandError::AccountNotFound { id: "missing-acc".to_string() })
+        );
+
+        // logs: 非法 JSON 与写入失败
+        assert!(matches!(
+            super::super::logs::parse_logs_json("{not json"),
+            Err(ProxyCommandError::ValidationFailed { .. })
+        ));
+        let missing_dir = std::env::temp_dir()
+            .join("proxy-command-error-missing-dir")
+            .join("diagnostics.json");
+        assert!(matches!(
+            super::super::logs::write_export_file(&missing_dir.to_string_lossy(), "{}"),
+            Err(ProxyCommandError::Io { .. })
+        ));
+    }
+}
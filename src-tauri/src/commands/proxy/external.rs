@@ -1,116 +1,16 @@
 // External Provider Commands (z.ai)
 
-use tokio::time::Duration;
-
-/// Join base URL with path
-fn join_base_url(base: &str, path: &str) -> String {
-    let base = base.trim_end_matches('/');
-    let path = if path.starts_with('/') {
-        path.to_string()
-    } else {
-        format!("/{}", path)
-    };
-    format!("{}{}", base, path)
-}
-
-/// Extract model IDs from JSON response
-fn extract_model_ids(value: &serde_json::Value) -> Vec<String> {
-    let mut out = Vec::new();
-
-    fn push_from_item(out: &mut Vec<String>, item: &serde_json::Value) {
-        match item {
-            serde_json::Value::String(s) => out.push(s.to_string()),
-            serde_json::Value::Object(map) => {
-                if let Some(id) = map.get("id").and_then(|v| v.as_str()) {
-                    out.push(id.to_string());
-                } else if let Some(name) = map.get("name").and_then(|v| v.as_str()) {
-                    out.push(name.to_string());
-                }
-            }
-            _ => {}
-        }
-    }
-
-    match value {
-        serde_json::Value::Array(arr) => {
-            for item in arr {
-                push_from_item(&mut out, item);
-            }
-        }
-        serde_json::Value::Object(map) => {
-            if let Some(data) = map.get("data") {
-                if let serde_json::Value::Array(arr) = data {
-                    for item in arr {
-                        push_from_item(&mut out, item);
-                    }
-                }
-            }
-            if let Some(models) = map.get("models") {
-                match models {
-                    serde_json::Value::Array(arr) => {
-                        for item in arr {
-                            push_from_item(&mut out, item);
-                        }
-                    }
-                    other => push_from_item(&mut out, other),
-                }
-            }
-        }
-        _ => {}
-    }
-
-    out
-}
+use crate::proxy::providers::zai_models::{self, ZaiModelList};
 
 /// Fetch available models from the configured z.ai Anthropic-compatible API (`/v1/models`).
+///
+/// 结果在 `zai.models_cache_ttl_secs` 内复用缓存; 上游失败时返回带 `stale` 标记的旧快照。
 #[tauri::command]
 pub async fn fetch_zai_models(
     zai: crate::proxy::ZaiConfig,
     upstream_proxy: crate::proxy::config::UpstreamProxyConfig,
     request_timeout: u64,
-) -> Result<Vec<String>, String> {
-    if zai.base_url.trim().is_empty() {
-        return Err("z.ai base_url is empty".to_string());
-    }
-    if zai.api_key.trim().is_empty() {
-        return Err("z.ai api_key is not set".to_string());
-    }
-
-    let url = join_base_url(&zai.base_url, "/v1/models");
-
-    let mut builder = reqwest::Client::builder().timeout(Duration::from_secs(request_timeout.max(5)));
-    if upstream_proxy.enabled && !upstream_proxy.url.is_empty() {
-        let proxy = reqwest::Proxy::all(&upstream_proxy.url)
-            .map_err(|e| format!("Invalid upstream proxy url: {}", e))?;
-        builder = builder.proxy(proxy);
-    }
-    let client = builder
-        .build()
-        .map_err(|e| format!("Failed to build HTTP client: {}", e))?;
-
-    let resp = client
-        .get(&url)
-        .header("Authorization", format!("Bearer {}", zai.api_key))
-        .header("x-api-key", zai.api_key)
-        .header("anthropic-version", "2023-06-01")
-        .header("accept", "application/json")
-        .send()
-        .await
-        .map_err(|e| format!("Upstream request failed: {}", e))?;
-
-    let status = resp.status();
-    let text = resp.text().await.map_err(|e| format!("Failed to read response: {}", e))?;
-
-    if !status.is_success() {
-        let preview = if text.len() > 4000 { &text[..4000] } else { &text };
-        return Err(format!("Upstream returned {}: {}", status, preview));
-    }
-
-    let json: serde_json::Value =
-        serde_json::from_str(&text).map_err(|e| format!("Invalid JSON response: {}", e))?;
-    let mut models = extract_model_ids(&json);
-    models.retain(|s| !s.trim().is_empty());
-    models.sort();
-    models.dedup();
-    Ok(models)
+    force_refresh: Option<bool>,
+) -> Result<ZaiModelList, String> {
+    zai_models::list_models(&zai, &upstream_proxy, request_timeout, force_refresh.unwrap_or(false)).await
 }
@@ -5,7 +5,8 @@ use std::sync::Arc;
 use std::sync::atomic::Ordering;
 use crate::proxy::{ProxyConfig, TokenManager};
 use crate::proxy::monitor::ProxyMonitor;
-use super::types::{ProxyStatus, ProxyServiceState, ProxyServiceInstance, AdminServerInstance, StartingGuard};
+use super::error::ProxyCommandError;
+use super::types::{ProxyStatus, ProxyServiceState, ProxyServiceInstance, AdminServerInstance, AdminServerStatus, StartingGuard};
 
 /// Start proxy service (Tauri command)
 #[tauri::command]
@@ -14,7 +15,7 @@ pub async fn start_proxy_service(
     state: State<'_, ProxyServiceState>,
     cf_state: State<'_, crate::commands::cloudflared::CloudflaredState>,
     app_handle: tauri::AppHandle,
-) -> Result<ProxyStatus, String> {
+) -> Result<ProxyStatus, ProxyCommandError> {
     internal_start_proxy_service(
         config,
         &state,
@@ -29,18 +30,18 @@ pub async fn internal_start_proxy_service(
     state: &ProxyServiceState,
     integration: crate::modules::integration::SystemManager,
     cloudflared_state: Arc<crate::commands::cloudflared::CloudflaredState>,
-) -> Result<ProxyStatus, String> {
+) -> Result<ProxyStatus, ProxyCommandError> {
     // 1. Check state and lock
     {
         let instance_lock = state.instance.read().await;
         if instance_lock.is_some() {
-            return Err("服务已在运行中".to_string());
+            return Err(ProxyCommandError::AlreadyRunning);
         }
     }
 
     // 2. Check if starting (prevent deadlock & concurrent starts)
     if state.starting.compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst).is_err() {
-        return Err("服务正在启动中，请稍候...".to_string());
+        return Err(ProxyCommandError::Starting);
     }
 
     // Use custom Drop guard to ensure starting flag is reset
@@ -73,7 +74,7 @@ pub async fn internal_start_proxy_service(
         let admin_lock = state.admin_server.read().await;
         // SAFETY: ensure_admin_server called above guarantees this is Some
         let server = admin_lock.as_ref()
-            .ok_or_else(|| "Final check: Admin server not initialized".to_string())?
+            .ok_or_else(|| ProxyCommandError::internal("Final check: Admin server not initialized"))?
             .axum_server.clone();
         (server.clone(), server.token_manager.clone())
     };
@@ -82,25 +83,55 @@ pub async fn internal_start_proxy_service(
     // safe to call (will restart task if running)
     token_manager.start_auto_cleanup().await;
     token_manager.update_sticky_config(config.scheduling.clone()).await;
+    crate::proxy::common::redact::set_allow_emails(config.expose_account_emails_in_errors);
+    crate::proxy::common::redact::set_account_header_privacy(config.account_header_privacy);
+    crate::proxy::common::thinking_capability::set_overrides(&config.thinking_overrides);
+    crate::proxy::common::post_process::set_config(&config.post_process);
+    crate::proxy::key_budget::set_config(&config.key_budgets);
+    crate::proxy::config::update_retry_config(config.retry);
+    crate::proxy::image_store::set_ttl_secs(config.image_url_ttl_secs);
+    crate::proxy::common::thinking_defaults::set_config(&config);
+    crate::proxy::common::system_injection::set_config(&config);
+    axum_server.update_providers(&config).await;
     
     // Load circuit breaker config from main config
     let app_config = crate::modules::config::load_app_config().unwrap_or_else(|_| crate::models::AppConfig::new());
     token_manager.update_circuit_breaker_config(app_config.circuit_breaker).await;
 
     // 3. Load accounts (refresh from disk)
-    let active_accounts = token_manager.load_accounts().await
+    let mut active_accounts = token_manager.load_accounts().await
         .unwrap_or(0);
+
+    // 3.1 可选的启动体检: 就绪前标记失效账号 (结果在 TTL 内缓存, 重启不重复探测)
+    if config.startup_verification.enabled && active_accounts > 0 {
+        let app_handle = if let crate::modules::integration::SystemManager::Desktop(ref h) = integration {
+            Some(h.clone())
+        } else {
+            None
+        };
+        token_manager
+            .verify_all_accounts(config.startup_verification.cache_ttl_secs, false, |progress| {
+                super::accounts::emit_verification_progress(app_handle.as_ref(), progress)
+            })
+            .await;
+        active_accounts = token_manager.len();
+    }
     
     if active_accounts == 0 {
-        let zai_enabled = config.zai.enabled
-            && !matches!(config.zai.dispatch_mode, crate::proxy::ZaiDispatchMode::Off);
-        if !zai_enabled {
+        let has_providers =
+            !crate::proxy::config::effective_providers(&config.zai, &config.providers).is_empty();
+        if !has_providers {
             tracing::warn!("沒有可用賬號，反代邏輯將暫停，請通過管理界面添加。");
             return Ok(ProxyStatus {
                 running: false,
                 port: config.port,
                 base_url: format!("http://127.0.0.1:{}", config.port),
+                endpoints: None,
                 active_accounts: 0,
+                uptime_secs: 0,
+                upstream_warning: crate::proxy::failure_patterns::current_warning(),
+                admin_server: state.admin_server_status().await,
+                maintenance: crate::proxy::maintenance::status(),
             });
         }
     }
@@ -117,6 +148,9 @@ pub async fn internal_start_proxy_service(
     
     // Ensure the server is logically running
     axum_server.set_running(true).await;
+
+    // 额外监听端口: 单个端口绑定失败只记录在其状态中, 不影响主服务
+    axum_server.start_listeners(&config).await;
     
     *instance_lock = Some(instance);
     
@@ -124,7 +158,12 @@ pub async fn internal_start_proxy_service(
         running: true,
         port: config.port,
         base_url: format!("http://127.0.0.1:{}", config.port),
+        endpoints: Some(crate::proxy::ports::ProxyEndpoints::for_port(config.port)),
         active_accounts,
+        uptime_secs: crate::proxy::server::uptime_secs(),
+        upstream_warning: crate::proxy::failure_patterns::current_warning(),
+        admin_server: state.admin_server_status().await,
+        maintenance: crate::proxy::maintenance::status(),
     })
 }
 
@@ -134,7 +173,7 @@ pub async fn ensure_admin_server(
     state: &ProxyServiceState,
     integration: crate::modules::integration::SystemManager,
     cloudflared_state: Arc<crate::commands::cloudflared::CloudflaredState>,
-) -> Result<(), String> {
+) -> Result<(), ProxyCommandError> {
     let mut admin_lock = state.admin_server.write().await;
     if admin_lock.is_some() {
         return Ok(());
@@ -160,14 +199,17 @@ pub async fn ensure_admin_server(
     // Load account data for admin interface stats
     let _ = token_manager.load_accounts().await;
 
+    let admin_port = config.admin_port();
     let (axum_server, server_handle) =
         match crate::proxy::AxumServer::start(
             config.get_bind_address().to_string(),
-            config.port,
+            admin_port,
             token_manager,
             config.custom_mapping.clone(),
             config.request_timeout,
             config.upstream_proxy.clone(),
+            config.upstream_timeouts.clone(),
+            config.upstream_pool.clone(),
             config.user_agent_override.clone(),
             crate::proxy::ProxySecurityConfig::from_proxy_config(&config),
             config.zai.clone(),
@@ -178,32 +220,131 @@ pub async fn ensure_admin_server(
             cloudflared_state,
         ).await {
             Ok((server, handle)) => (server, handle),
-            Err(e) => return Err(format!("启动管理服务器失败: {}", e)),
+            Err(e) => {
+                *state.admin_status.write().await = AdminServerStatus {
+                    running: false,
+                    port: admin_port,
+                    base_url: format!("http://127.0.0.1:{}", admin_port),
+                    error: Some(e.clone()),
+                };
+                return Err(ProxyCommandError::admin_start_failed(admin_port, e));
+            }
         };
 
     *admin_lock = Some(AdminServerInstance {
+        port: admin_port,
         axum_server,
         server_handle,
     });
+    *state.admin_status.write().await = AdminServerStatus {
+        running: true,
+        port: admin_port,
+        base_url: format!("http://127.0.0.1:{}", admin_port),
+        error: None,
+    };
 
     Ok(())
 }
 
+/// Stop the admin server. The proxy service runs on top of it, so it is stopped too.
+pub async fn internal_stop_admin_server(state: &ProxyServiceState) -> Result<(), ProxyCommandError> {
+    stop_proxy_instance(state).await;
+
+    let Some(mut admin) = state.admin_server.write().await.take() else {
+        return Err(ProxyCommandError::NotRunning);
+    };
+    admin.axum_server.stop();
+    // 等待连接排空后再释放端口, 以便立即以新端口 / 同端口重启
+    if tokio::time::timeout(std::time::Duration::from_secs(5), &mut admin.server_handle)
+        .await
+        .is_err()
+    {
+        tracing::warn!("Admin server on port {} did not drain within 5s, aborting", admin.port);
+        admin.server_handle.abort();
+        let _ = admin.server_handle.await;
+    }
+    state.admin_status.write().await.running = false;
+    tracing::info!("Admin server on port {} stopped", admin.port);
+
+    Ok(())
+}
+
+/// Stop admin server (Tauri command)
+#[tauri::command]
+pub async fn stop_admin_server(state: State<'_, ProxyServiceState>) -> Result<(), ProxyCommandError> {
+    internal_stop_admin_server(&state).await
+}
+
+/// Restart admin server with the saved config (e.g. after changing its port).
+/// The proxy service is started again if it was running.
+#[tauri::command]
+pub async fn restart_admin_server(
+    state: State<'_, ProxyServiceState>,
+    cf_state: State<'_, crate::commands::cloudflared::CloudflaredState>,
+    app_handle: tauri::AppHandle,
+) -> Result<ProxyStatus, ProxyCommandError> {
+    let config = crate::modules::config::load_app_config()?.proxy;
+    let proxy_was_running = state.instance.read().await.is_some();
+
+    if state.admin_server.read().await.is_some() {
+        internal_stop_admin_server(&state).await?;
+    }
+
+    let integration = crate::modules::integration::SystemManager::Desktop(app_handle);
+    let cloudflared_state = Arc::new(cf_state.inner().clone());
+    ensure_admin_server(config.clone(), &state, integration.clone(), cloudflared_state.clone()).await?;
+
+    if proxy_was_running {
+        return internal_start_proxy_service(config, &state, integration, cloudflared_state).await;
+    }
+    Ok(super::status::get_proxy_status(state).await?)
+}
+
 /// Stop proxy service
+///
+/// `stop_admin`: also stop the admin server (default keeps it running for the Web UI)
 #[tauri::command]
 pub async fn stop_proxy_service(
     state: State<'_, ProxyServiceState>,
-) -> Result<(), String> {
-    let mut instance_lock = state.instance.write().await;
-    
-    if instance_lock.is_none() {
-        return Err("服务未运行".to_string());
+    stop_admin: Option<bool>,
+) -> Result<(), ProxyCommandError> {
+    if !stop_proxy_instance(&state).await {
+        return Err(ProxyCommandError::NotRunning);
     }
-    
-    // Stop Axum server (logical stop only, don't kill process)
-    if let Some(instance) = instance_lock.take() {
-        instance.axum_server.set_running(false).await;
+
+    if stop_admin.unwrap_or(false) {
+        internal_stop_admin_server(&state).await?;
     }
-    
+
     Ok(())
 }
+
+/// Logical stop of the proxy instance; returns false when it was not running
+async fn stop_proxy_instance(state: &ProxyServiceState) -> bool {
+    let mut instance_lock = state.instance.write().await;
+
+    // Stop Axum server (logical stop only, don't kill process)
+    match instance_lock.take() {
+        Some(instance) => {
+            instance.axum_server.set_running(false).await;
+            instance.axum_server.stop_listeners().await;
+            crate::proxy::image_store::clear();
+            let _ = tokio::task::spawn_blocking(crate::proxy::key_budget::flush).await;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Enter or leave maintenance mode
+///
+/// New requests get a protocol-specific 503 with Retry-After while in-flight requests
+/// (including streams) finish; `duration_secs` exits automatically when it elapses.
+#[tauri::command]
+pub async fn set_maintenance_mode(
+    enabled: bool,
+    message: Option<String>,
+    duration_secs: Option<u64>,
+) -> Result<crate::proxy::maintenance::MaintenanceStatus, ProxyCommandError> {
+    Ok(crate::proxy::maintenance::set(enabled, message, duration_secs))
+}
@@ -1,69 +1,134 @@
 // Proxy Logs Commands (Paginated, Filtered, Export)
 
+use crate::modules::log_export::{self, ExportProgress, ExportStatus};
 use crate::proxy::monitor::ProxyRequestLog;
+use super::error::ProxyCommandError;
+use tauri::ipc::Channel;
 
 /// Get proxy request logs (paginated)
 #[tauri::command]
 pub async fn get_proxy_logs_paginated(
     limit: Option<usize>,
     offset: Option<usize>,
-) -> Result<Vec<ProxyRequestLog>, String> {
-    crate::modules::proxy_db::get_logs_summary(
+) -> Result<Vec<ProxyRequestLog>, ProxyCommandError> {
+    Ok(crate::modules::proxy_db::get_logs_summary(
         limit.unwrap_or(20),
         offset.unwrap_or(0)
-    )
+    )?)
 }
 
 /// Get single log detail
 #[tauri::command]
 pub async fn get_proxy_log_detail(
     log_id: String,
-) -> Result<ProxyRequestLog, String> {
-    crate::modules::proxy_db::get_log_detail(&log_id)
+) -> Result<ProxyRequestLog, ProxyCommandError> {
+    Ok(crate::modules::proxy_db::get_log_detail(&log_id)?)
 }
 
 /// Get total log count
 #[tauri::command]
-pub async fn get_proxy_logs_count() -> Result<u64, String> {
-    crate::modules::proxy_db::get_logs_count()
+pub async fn get_proxy_logs_count() -> Result<u64, ProxyCommandError> {
+    Ok(crate::modules::proxy_db::get_logs_count()?)
 }
 
 /// Export all logs to file
+///
+/// 后台分批写入, 立即返回导出任务 ID; 进度通过 `on_progress` 推送, 也可用 `get_export_status` 查询。
 #[tauri::command]
 pub async fn export_proxy_logs(
     file_path: String,
-) -> Result<usize, String> {
-    let logs = crate::modules::proxy_db::get_all_logs_for_export()?;
-    let count = logs.len();
-    
-    let json = serde_json::to_string_pretty(&logs)
-        .map_err(|e| format!("Failed to serialize logs: {}", e))?;
-    
-    std::fs::write(&file_path, json)
-        .map_err(|e| format!("Failed to write file: {}", e))?;
-    
-    Ok(count)
+    on_progress: Option<Channel<ExportProgress>>,
+) -> Result<String, ProxyCommandError> {
+    let total = crate::modules::proxy_db::get_logs_count()?;
+    Ok(log_export::start(file_path, total, log_export::db_source(), move |progress| {
+        if let Some(channel) = &on_progress {
+            let _ = channel.send(progress);
+        }
+    })?)
 }
 
-/// Export specified logs JSON to file
+/// Export a diagnostics bundle (stats + aggregated upstream failure signatures + account notes) to file
+#[tauri::command]
+pub async fn export_proxy_diagnostics(
+    state: tauri::State<'_, super::ProxyServiceState>,
+    file_path: String,
+) -> Result<(), ProxyCommandError> {
+    let stats = match state.monitor.read().await.as_ref() {
+        Some(monitor) => monitor.get_stats().await,
+        None => Default::default(),
+    };
+    let account_notes = diagnostics_account_notes(crate::modules::list_accounts().await.unwrap_or_default());
+    let bundle = serde_json::json!({
+        "app_version": env!("CARGO_PKG_VERSION"),
+        "generated_at": chrono::Utc::now().timestamp(),
+        "stats": stats,
+        "upstream_warning": crate::proxy::failure_patterns::current_warning(),
+        "failure_signatures": crate::proxy::failure_patterns::aggregated_signatures(),
+        "account_notes": account_notes,
+    });
+
+    let json = serde_json::to_string_pretty(&bundle)
+        .map_err(|e| ProxyCommandError::internal(format!("Failed to serialize diagnostics: {}", e)))?;
+
+    write_export_file(&file_path, &json)
+}
+
+/// 诊断包中的账号备注: 只带备注与最近失败, 不含 token; 最近失败的上游原文经脱敏后再导出
+fn diagnostics_account_notes(accounts: Vec<crate::models::Account>) -> Vec<serde_json::Value> {
+    accounts
+        .into_iter()
+        .filter(|a| a.notes.is_some() || a.last_error.is_some())
+        .map(|a| {
+            let last_error = a.last_error.map(|mut e| {
+                e.message = crate::proxy::common::redact::sanitize_upstream_error(&e.message, None);
+                e
+            });
+            serde_json::json!({ "account_id": a.id, "notes": a.notes, "last_error": last_error })
+        })
+        .collect()
+}
+
+pub(crate) fn write_export_file(file_path: &str, contents: &str) -> Result<(), ProxyCommandError> {
+    std::fs::write(file_path, contents)
+        .map_err(|e| ProxyCommandError::io(format!("Failed to write file: {}", e)))
+}
+
+/// 前端传入的日志 JSON 必须是数组
+pub(crate) fn parse_logs_json(json_data: &str) -> Result<Vec<serde_json::Value>, ProxyCommandError> {
+    serde_json::from_str(json_data)
+        .map_err(|e| ProxyCommandError::invalid("json_data", format!("Failed to parse JSON: {}", e)))
+}
+
+/// Export specified logs JSON to file (same job model as `export_proxy_logs`)
 #[tauri::command]
 pub async fn export_proxy_logs_json(
     file_path: String,
     json_data: String,
-) -> Result<usize, String> {
-    // Parse to count items
-    let logs: Vec<serde_json::Value> = serde_json::from_str(&json_data)
-        .map_err(|e| format!("Failed to parse JSON: {}", e))?;
-    let count = logs.len();
-    
-    // Pretty print
-    let pretty_json = serde_json::to_string_pretty(&logs)
-        .map_err(|e| format!("Failed to serialize: {}", e))?;
-    
-    std::fs::write(&file_path, pretty_json)
-        .map_err(|e| format!("Failed to write file: {}", e))?;
-    
-    Ok(count)
+    on_progress: Option<Channel<ExportProgress>>,
+) -> Result<String, ProxyCommandError> {
+    let logs: Vec<serde_json::Value> = parse_logs_json(&json_data)?
+        .into_iter()
+        .map(log_export::with_local_time)
+        .collect();
+    let total = logs.len() as u64;
+
+    Ok(log_export::start(file_path, total, log_export::chunked(logs), move |progress| {
+        if let Some(channel) = &on_progress {
+            let _ = channel.send(progress);
+        }
+    })?)
+}
+
+/// Get progress / result of a log export job
+#[tauri::command]
+pub async fn get_export_status(job_id: String) -> Result<ExportStatus, ProxyCommandError> {
+    Ok(log_export::status(&job_id)?)
+}
+
+/// Cancel a running log export job
+#[tauri::command]
+pub async fn cancel_export(job_id: String) -> Result<(), ProxyCommandError> {
+    Ok(log_export::cancel(&job_id)?)
 }
 
 /// Get log count with filter
@@ -71,8 +136,8 @@ pub async fn export_proxy_logs_json(
 pub async fn get_proxy_logs_count_filtered(
     filter: String,
     errors_only: bool,
-) -> Result<u64, String> {
-    crate::modules::proxy_db::get_logs_count_filtered(&filter, errors_only)
+) -> Result<u64, ProxyCommandError> {
+    Ok(crate::modules::proxy_db::get_logs_count_filtered(&filter, errors_only)?)
 }
 
 /// Get filtered paginated logs
@@ -82,6 +147,30 @@ pub async fn get_proxy_logs_filtered(
     errors_only: bool,
     limit: usize,
     offset: usize,
-) -> Result<Vec<ProxyRequestLog>, String> {
-    crate::modules::proxy_db::get_logs_filtered(&filter, errors_only, limit, offset)
+) -> Result<Vec<ProxyRequestLog>, ProxyCommandError> {
+    Ok(crate::modules::proxy_db::get_logs_filtered(&filter, errors_only, limit, offset)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diagnostics_account_notes_sanitize_last_error() {
+        let token = crate::models::TokenData::new(String::new(), String::new(), 3600, None, None, None);
+        let mut account = crate::models::Account::new("acc-1".to_string(), "acc-1@example.com".to_string(), token.clone());
+        account.last_error = Some(crate::models::AccountLastError::new(
+            403,
+            r#"{"error":{"message":"Permission denied on resource projects/secret-project-42 for Bearer ya29.a0AfH6SMBx"}}"#,
+        ));
+        let quiet = crate::models::Account::new("acc-2".to_string(), "acc-2@example.com".to_string(), token);
+
+        let notes = diagnostics_account_notes(vec![account, quiet]);
+        assert_eq!(notes.len(), 1);
+        let message = notes[0]["last_error"]["message"].as_str().unwrap();
+        assert!(!message.contains("secret-project-42"), "{}", message);
+        assert!(!message.contains("ya29."), "{}", message);
+        assert!(message.contains("Permission denied"));
+        assert_eq!(notes[0]["last_error"]["status"], 403);
+    }
 }
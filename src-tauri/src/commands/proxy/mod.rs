@@ -2,14 +2,18 @@
 // Organized into logical submodules for maintainability
 
 pub mod accounts;
+pub mod benchmark;
 pub mod config;
+mod error;
 pub mod external;
 pub mod lifecycle;
 pub mod logs;
+pub mod scheduling;
 pub mod status;
 mod types;
 
 // Re-export types (these don't need #[tauri::command])
+pub use error::ProxyCommandError;
 pub use types::{AdminServerInstance, ProxyServiceInstance, ProxyServiceState, ProxyStatus};
 
 // Re-export internal helpers (non-command functions)
@@ -0,0 +1,144 @@
+// Proxy Scheduling Config Commands
+
+use std::collections::HashSet;
+use tauri::State;
+use super::types::ProxyServiceState;
+use crate::error::{AppError, AppResult};
+use crate::proxy::common::model_mapping;
+use crate::proxy::config::{BackgroundRoutingConfig, RetryConfig};
+use crate::proxy::sticky_config::{StickySessionConfig, ValidationContext};
+
+/// 收集校验所需的账号 ID 与已知模型名
+fn build_validation_context(
+    custom_mapping: &std::collections::HashMap<String, String>,
+) -> AppResult<ValidationContext> {
+    let accounts = crate::modules::account::list_accounts().map_err(AppError::Account)?;
+
+    let mut known_models: HashSet<String> = model_mapping::get_supported_models().into_iter().collect();
+    for model in model_mapping::get_supported_models() {
+        known_models.insert(model_mapping::map_claude_model_to_gemini(&model));
+    }
+    for (from, to) in custom_mapping {
+        known_models.insert(from.clone());
+        known_models.insert(to.clone());
+    }
+    // 指定模式下的模型列表来自账号配额信息
+    for account in &accounts {
+        if let Some(quota) = &account.quota {
+            known_models.extend(quota.models.iter().map(|m| m.name.clone()));
+        }
+    }
+    let normalized: Vec<String> = known_models
+        .iter()
+        .filter_map(|m| model_mapping::normalize_to_standard_id(m))
+        .collect();
+    known_models.extend(normalized);
+
+    Ok(ValidationContext {
+        account_ids: accounts.into_iter().map(|a| a.id).collect(),
+        known_models: known_models.into_iter().map(|m| m.to_lowercase()).collect(),
+    })
+}
+
+/// Get current scheduling config
+#[tauri::command]
+pub async fn get_proxy_scheduling_config(
+    state: State<'_, ProxyServiceState>,
+) -> AppResult<StickySessionConfig> {
+    let instance_lock = state.instance.read().await;
+    if let Some(instance) = instance_lock.as_ref() {
+        Ok(instance.token_manager.get_sticky_config().await)
+    } else {
+        // 服务未运行时返回已保存的配置
+        let app_config = crate::modules::config::load_app_config().map_err(AppError::Config)?;
+        Ok(app_config.proxy.scheduling)
+    }
+}
+
+/// 校验并保存调度配置, 返回实际保存的规范化配置
+///
+/// 调度配置的唯一写入入口 (Tauri 命令与 Web 管理接口共用), 前端不再另行保存整份配置。
+pub fn save_scheduling_config(config: StickySessionConfig) -> AppResult<StickySessionConfig> {
+    let mut app_config = crate::modules::config::load_app_config().map_err(AppError::Config)?;
+
+    let ctx = build_validation_context(&app_config.proxy.custom_mapping)?;
+    let normalized = config.validate(&ctx).map_err(AppError::ConfigViolations)?;
+
+    app_config.proxy.scheduling = normalized.clone();
+    crate::modules::config::save_app_config(&app_config).map_err(AppError::Config)?;
+    Ok(normalized)
+}
+
+/// Update scheduling config
+///
+/// 校验失败时返回 `ConfigViolations` (列出全部不合法字段); 成功时返回实际保存的规范化配置。
+#[tauri::command]
+pub async fn update_proxy_scheduling_config(
+    state: State<'_, ProxyServiceState>,
+    config: StickySessionConfig,
+) -> AppResult<StickySessionConfig> {
+    let normalized = save_scheduling_config(config)?;
+
+    let instance_lock = state.instance.read().await;
+    if let Some(instance) = instance_lock.as_ref() {
+        instance.token_manager.update_sticky_config(normalized.clone()).await;
+    }
+
+    Ok(normalized)
+}
+
+/// Get current retry policy
+#[tauri::command]
+pub async fn get_proxy_retry_config() -> AppResult<RetryConfig> {
+    let app_config = crate::modules::config::load_app_config().map_err(AppError::Config)?;
+    Ok(app_config.proxy.retry)
+}
+
+/// Update retry policy
+///
+/// 与调度配置一同保存; 立即写入全局配置, 运行中的服务对新请求即时生效, 无需重启。
+#[tauri::command]
+pub async fn update_proxy_retry_config(config: RetryConfig) -> AppResult<RetryConfig> {
+    let validated = config.validate().map_err(AppError::ConfigViolations)?;
+
+    let mut app_config = crate::modules::config::load_app_config().map_err(AppError::Config)?;
+    app_config.proxy.retry = validated;
+    crate::modules::config::save_app_config(&app_config).map_err(AppError::Config)?;
+
+    crate::proxy::config::update_retry_config(validated);
+    Ok(validated)
+}
+
+/// Get background task routing
+#[tauri::command]
+pub async fn get_background_routing() -> AppResult<BackgroundRoutingConfig> {
+    let app_config = crate::modules::config::load_app_config().map_err(AppError::Config)?;
+    Ok(app_config.proxy.experimental.background_routing)
+}
+
+/// 校验并保存后台任务路由, 返回保存后的完整配置 (供调用方热更新实验性配置)
+pub fn save_background_routing(config: BackgroundRoutingConfig) -> AppResult<crate::models::AppConfig> {
+    let validated = config.validate().map_err(AppError::ConfigViolations)?;
+
+    let mut app_config = crate::modules::config::load_app_config().map_err(AppError::Config)?;
+    app_config.proxy.experimental.background_routing = validated;
+    crate::modules::config::save_app_config(&app_config).map_err(AppError::Config)?;
+    Ok(app_config)
+}
+
+/// Update background task routing
+///
+/// 运行中的服务对新请求即时生效, 无需重启。
+#[tauri::command]
+pub async fn update_background_routing(
+    state: State<'_, ProxyServiceState>,
+    config: BackgroundRoutingConfig,
+) -> AppResult<BackgroundRoutingConfig> {
+    let app_config = save_background_routing(config)?;
+
+    let instance_lock = state.instance.read().await;
+    if let Some(instance) = instance_lock.as_ref() {
+        instance.axum_server.update_experimental(&app_config.proxy).await;
+    }
+    Ok(app_config.proxy.experimental.background_routing)
+}
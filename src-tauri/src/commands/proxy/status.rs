@@ -2,67 +2,128 @@
 
 use tauri::State;
 use std::sync::atomic::Ordering;
-use crate::proxy::monitor::{ProxyStats, ProxyRequestLog};
+use crate::proxy::monitor::{ProxyRequestLog, ProxyStatsReport, StatsSection};
+use crate::proxy::config::ListenerProtocol;
+use crate::proxy::ports::ProxyEndpoints;
 use super::types::{ProxyStatus, ProxyServiceState};
 
 /// Get proxy service status
+/// 只读取原子量与缓存值, 适合高频轮询; 详细统计见 get_proxy_stats
 #[tauri::command]
 pub async fn get_proxy_status(
     state: State<'_, ProxyServiceState>,
 ) -> Result<ProxyStatus, String> {
+    Ok(status_snapshot(&state).await)
+}
+
+pub(crate) async fn status_snapshot(state: &ProxyServiceState) -> ProxyStatus {
     // Check starting flag first to avoid being blocked by write lock
     if state.starting.load(Ordering::SeqCst) {
-        return Ok(ProxyStatus {
-            running: false,
-            port: 0,
-            base_url: "starting".to_string(),
-            active_accounts: 0,
-        });
+        return idle_status(state, "starting").await;
     }
 
     // Use try_read to avoid queuing delay
     let lock_res = state.instance.try_read();
-    
+
     match lock_res {
-        Ok(instance_lock) => {
-            match instance_lock.as_ref() {
-                Some(instance) => Ok(ProxyStatus {
-                    running: true,
-                    port: instance.config.port,
-                    base_url: format!("http://127.0.0.1:{}", instance.config.port),
-                    active_accounts: instance.token_manager.effective_len().await,
-                }),
-                None => Ok(ProxyStatus {
-                    running: false,
-                    port: 0,
-                    base_url: String::new(),
-                    active_accounts: 0,
-                }),
-            }
+        Ok(instance_lock) => match instance_lock.as_ref() {
+            Some(instance) => ProxyStatus {
+                running: true,
+                port: instance.config.port,
+                base_url: format!("http://127.0.0.1:{}", instance.config.port),
+                endpoints: Some(ProxyEndpoints::for_port(instance.config.port)),
+                active_accounts: instance.token_manager.effective_len_cached(),
+                uptime_secs: crate::proxy::server::uptime_secs(),
+                upstream_warning: crate::proxy::failure_patterns::current_warning(),
+                admin_server: state.admin_server_status().await,
+                maintenance: crate::proxy::maintenance::status(),
+            },
+            None => idle_status(state, "").await,
         },
-        Err(_) => {
-            // If can't get lock, a write operation is in progress
-            Ok(ProxyStatus {
-                running: false,
-                port: 0,
-                base_url: "busy".to_string(),
-                active_accounts: 0,
-            })
-        }
+        // If can't get lock, a write operation is in progress
+        Err(_) => idle_status(state, "busy").await,
     }
 }
 
+async fn idle_status(state: &ProxyServiceState, base_url: &str) -> ProxyStatus {
+    ProxyStatus {
+        running: false,
+        port: 0,
+        base_url: base_url.to_string(),
+        endpoints: None,
+        active_accounts: 0,
+        uptime_secs: 0,
+        upstream_warning: crate::proxy::failure_patterns::current_warning(),
+        admin_server: state.admin_server_status().await,
+        maintenance: crate::proxy::maintenance::status(),
+    }
+}
+
+/// Base URL for a protocol, exactly as clients expect it (OpenAI includes the /v1 suffix)
+/// 服务未运行时按已保存配置的端口生成
+#[tauri::command]
+pub async fn copy_endpoint_url(
+    state: State<'_, ProxyServiceState>,
+    protocol: ListenerProtocol,
+) -> Result<String, String> {
+    let running_port = state
+        .instance
+        .read()
+        .await
+        .as_ref()
+        .map(|instance| instance.config.port);
+    let port = match running_port {
+        Some(port) => port,
+        None => crate::modules::config::load_app_config()?.proxy.port,
+    };
+    Ok(ProxyEndpoints::for_port(port).url(protocol).to_string())
+}
+
 /// Get proxy service stats
+/// sections 指定需要的分区 (accounts / latency / models / listeners / warm_pool / key_budgets);
+/// 未指定时按旧行为只返回 latency 分区 (已废弃)
 #[tauri::command]
 pub async fn get_proxy_stats(
     state: State<'_, ProxyServiceState>,
-) -> Result<ProxyStats, String> {
-    let monitor_lock = state.monitor.read().await;
-    if let Some(monitor) = monitor_lock.as_ref() {
-        Ok(monitor.get_stats().await)
-    } else {
-        Ok(ProxyStats::default())
+    sections: Option<Vec<StatsSection>>,
+) -> Result<ProxyStatsReport, String> {
+    let sections = sections.unwrap_or_else(|| {
+        crate::proxy::monitor::warn_legacy_stats_call();
+        vec![StatsSection::Latency]
+    });
+
+    // 不在持有 instance 锁期间做重查询
+    let server = state
+        .instance
+        .read()
+        .await
+        .as_ref()
+        .map(|instance| instance.axum_server.clone());
+    if let Some(server) = server {
+        return Ok(server.stats_report(&sections).await);
     }
+
+    let monitor = state.monitor.read().await.clone();
+    Ok(ProxyStatsReport::collect(monitor.as_deref(), None, &sections).await)
+}
+
+/// Get per-API-key daily budget usage
+/// 只列出配置了非零预算的 Key (Key 已脱敏)
+#[tauri::command]
+pub async fn get_key_budget_status() -> Result<Vec<crate::proxy::key_budget::KeyBudgetStatus>, String> {
+    Ok(crate::proxy::key_budget::status())
+}
+
+/// Token estimation calibration: global factor plus per (family × has images) buckets
+#[tauri::command]
+pub async fn get_calibration_state() -> Result<crate::proxy::mappers::estimation_calibrator::CalibrationState, String> {
+    Ok(crate::proxy::mappers::estimation_calibrator::get_calibrator().state())
+}
+
+/// Per-model health: success rate, common error signatures and median latency (last 6 hours)
+#[tauri::command]
+pub async fn get_model_health() -> Result<Vec<crate::proxy::model_health::ModelHealth>, String> {
+    Ok(crate::proxy::model_health::snapshot())
 }
 
 /// Get proxy request logs
@@ -103,3 +164,67 @@ pub async fn clear_proxy_logs(
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::sticky_config::SchedulingMode;
+    use crate::proxy::TokenManager;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    // 流式请求长时间持有读锁, 同时有写操作 (启动 / 热更新) 排队时,
+    // tokio RwLock 会阻塞新的读者; 状态接口不能因此排队
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_status_not_blocked_while_locks_are_held() {
+        let state = Arc::new(ProxyServiceState::new());
+        let token_manager = Arc::new(TokenManager::new(std::env::temp_dir()));
+        {
+            let mut sticky = token_manager.sticky_config.write().await;
+            sticky.mode = SchedulingMode::Selected;
+            sticky.selected_accounts = vec!["a".to_string(), "b".to_string()];
+        }
+        assert_eq!(token_manager.effective_len_cached(), 2);
+
+        // 模拟进行中的流式请求
+        let instance_guard = state.instance.read().await;
+        let sticky_guard = token_manager.sticky_config.read().await;
+
+        // 排队中的写者
+        let writers = {
+            let instance = state.instance.clone();
+            let sticky = token_manager.sticky_config.clone();
+            tokio::spawn(async move {
+                let _instance = instance.write().await;
+                let _sticky = sticky.write().await;
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let mut pollers = Vec::new();
+        for _ in 0..8 {
+            let state = state.clone();
+            let token_manager = token_manager.clone();
+            pollers.push(tokio::spawn(async move {
+                for _ in 0..200 {
+                    let status =
+                        tokio::time::timeout(Duration::from_millis(200), status_snapshot(&state))
+                            .await
+                            .expect("get_proxy_status blocked on a held lock");
+                    assert!(!status.running);
+                    assert_eq!(token_manager.effective_len_cached(), 2);
+                }
+            }));
+        }
+        for poller in pollers {
+            poller.await.unwrap();
+        }
+
+        drop(sticky_guard);
+        drop(instance_guard);
+        tokio::time::timeout(Duration::from_secs(1), writers)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+}
@@ -12,7 +12,34 @@ pub struct ProxyStatus {
     pub running: bool,
     pub port: u16,
     pub base_url: String,
+    /// 各协议可直接粘贴到客户端的 base URL (服务未运行时为空)
+    #[serde(default)]
+    pub endpoints: Option<crate::proxy::ports::ProxyEndpoints>,
+    /// 有效账号数 (缓存值, 不等待锁)
     pub active_accounts: usize,
+    /// 服务运行时长 (秒)
+    #[serde(default)]
+    pub uptime_secs: u64,
+    /// 同一上游错误签名在窗口内占比过高时的持久警告
+    #[serde(default)]
+    pub upstream_warning: Option<crate::proxy::failure_patterns::FailurePatternWarning>,
+    /// 管理服务 (Web UI / 管理 API) 的端口与运行状态
+    #[serde(default)]
+    pub admin_server: AdminServerStatus,
+    /// 维护模式状态与剩余的进行中请求数
+    #[serde(default)]
+    pub maintenance: crate::proxy::maintenance::MaintenanceStatus,
+}
+
+/// 管理服务状态
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AdminServerStatus {
+    pub running: bool,
+    pub port: u16,
+    pub base_url: String,
+    /// 绑定失败等错误 (与监听端口的错误格式一致)
+    #[serde(default)]
+    pub error: Option<String>,
 }
 
 /// Proxy service global state
@@ -21,11 +48,14 @@ pub struct ProxyServiceState {
     pub instance: Arc<RwLock<Option<ProxyServiceInstance>>>,
     pub monitor: Arc<RwLock<Option<Arc<crate::proxy::monitor::ProxyMonitor>>>>,
     pub admin_server: Arc<RwLock<Option<AdminServerInstance>>>,
+    /// 最近一次启动 / 停止管理服务的结果
+    pub admin_status: Arc<RwLock<AdminServerStatus>>,
     pub starting: Arc<AtomicBool>,
 }
 
 /// Admin server instance
 pub struct AdminServerInstance {
+    pub port: u16,
     pub axum_server: crate::proxy::AxumServer,
     pub server_handle: tokio::task::JoinHandle<()>,
 }
@@ -44,9 +74,21 @@ impl ProxyServiceState {
             instance: Arc::new(RwLock::new(None)),
             monitor: Arc::new(RwLock::new(None)),
             admin_server: Arc::new(RwLock::new(None)),
+            admin_status: Arc::new(RwLock::new(AdminServerStatus::default())),
             starting: Arc::new(AtomicBool::new(false)),
         }
     }
+
+    /// 管理服务状态; 服务任务意外退出时报告为未运行
+    pub async fn admin_server_status(&self) -> AdminServerStatus {
+        let mut status = self.admin_status.read().await.clone();
+        if let Ok(lock) = self.admin_server.try_read() {
+            status.running = lock
+                .as_ref()
+                .is_some_and(|admin| !admin.server_handle.is_finished());
+        }
+        status
+    }
 }
 
 /// Guard to reset starting flag on drop
@@ -53,6 +53,9 @@ pub enum AppError {
     #[error("Invalid input: {field} - {message}")]
     InvalidInput { field: String, message: String },
 
+    #[error("Invalid configuration: {} violation(s)", .0.len())]
+    ConfigViolations(Vec<FieldViolation>),
+
     // ============================================================================
     // Authentication & Authorization Errors
     // ============================================================================
@@ -117,6 +120,23 @@ pub enum AppError {
     Unknown(String),
 }
 
+/// A single invalid field reported by config validation
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldViolation {
+    /// Dotted path of the offending field, e.g. `selected_models.acc-1[0]`
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldViolation {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
 /// Structured JSON serialization for frontend consumption
 /// Format: { "type": "ErrorVariant", "message": "...", "details": {...} }
 impl Serialize for AppError {
@@ -158,6 +178,15 @@ impl Serialize for AppError {
                 format!("Invalid input: {} - {}", field, message),
                 Some(serde_json::json!({ "field": field, "message": message })),
             ),
+            AppError::ConfigViolations(violations) => (
+                "ConfigViolations",
+                violations
+                    .iter()
+                    .map(|v| format!("{}: {}", v.field, v.message))
+                    .collect::<Vec<_>>()
+                    .join("; "),
+                Some(serde_json::json!({ "violations": violations })),
+            ),
 
             // Auth errors
             AppError::OAuth(msg) => ("OAuth", msg.clone(), None),
@@ -370,6 +399,18 @@ mod tests {
         assert!(json.contains("\"retryAfter\":60"));
     }
 
+    #[test]
+    fn test_config_violations_serialization() {
+        let error = AppError::ConfigViolations(vec![
+            FieldViolation::new("max_wait_seconds", "must be between 1 and 600"),
+            FieldViolation::new("selected_accounts[0]", "unknown account id"),
+        ]);
+        let json: serde_json::Value = serde_json::to_value(&error).unwrap();
+        assert_eq!(json["type"], "ConfigViolations");
+        assert_eq!(json["details"]["violations"].as_array().unwrap().len(), 2);
+        assert_eq!(json["details"]["violations"][1]["field"], "selected_accounts[0]");
+    }
+
     #[test]
     fn test_is_retryable() {
         assert!(AppError::Timeout.is_retryable());
@@ -60,6 +60,8 @@ pub fn run() {
     // Initialize token stats database
     if let Err(e) = modules::token_stats::init_db() {
         error!("Failed to initialize token stats database: {}", e);
+    } else if let Err(e) = modules::stats_journal::init() {
+        error!("Failed to initialize token stats journal: {}", e);
     }
 
     // Initialize security database
@@ -287,7 +289,7 @@ pub fn run() {
                     ).await {
                         error!("Failed to start admin server: {}", e);
                     } else {
-                        info!("Admin server (port {}) started successfully", config.proxy.port);
+                        info!("Admin server (port {}) started successfully", config.proxy.admin_port());
                     }
 
                     // 2. 自动启动转发逻辑
@@ -333,6 +335,11 @@ pub fn run() {
             commands::account::add_account,
             commands::account::delete_account,
             commands::account::delete_accounts,
+            commands::account::pause_accounts,
+            commands::account::set_accounts_priority,
+            commands::account::set_accounts_tags,
+            commands::account::set_account_notes,
+            commands::account::get_account_health,
             commands::account::reorder_accounts,
             commands::account::switch_account,
             commands::account::get_current_account,
@@ -357,6 +364,8 @@ pub fn run() {
             // Config commands
             commands::config::load_config,
             commands::config::save_config,
+            commands::config::restore_config_backup,
+            commands::config::get_config_recovery_warning,
             commands::config::get_http_api_settings,
             commands::config::save_http_api_settings,
             // OAuth commands
@@ -368,6 +377,7 @@ pub fn run() {
             // Import commands
             commands::import::import_v1_accounts,
             commands::import::import_from_db,
+            commands::import::import_from_antigravity,
             commands::import::import_custom_db,
             commands::import::sync_account_from_db,
             // System commands
@@ -401,24 +411,43 @@ pub fn run() {
             // Proxy service commands
             commands::proxy::lifecycle::start_proxy_service,
             commands::proxy::lifecycle::stop_proxy_service,
+            commands::proxy::lifecycle::stop_admin_server,
+            commands::proxy::lifecycle::restart_admin_server,
+            commands::proxy::lifecycle::set_maintenance_mode,
             commands::proxy::status::get_proxy_status,
             commands::proxy::status::get_proxy_stats,
+            commands::proxy::status::get_key_budget_status,
+            commands::proxy::status::copy_endpoint_url,
+            commands::proxy::status::get_calibration_state,
+            commands::proxy::status::get_model_health,
             commands::proxy::status::get_proxy_logs,
             commands::proxy::logs::get_proxy_logs_paginated,
             commands::proxy::logs::get_proxy_log_detail,
             commands::proxy::logs::get_proxy_logs_count,
             commands::proxy::logs::export_proxy_logs,
             commands::proxy::logs::export_proxy_logs_json,
+            commands::proxy::logs::get_export_status,
+            commands::proxy::logs::cancel_export,
+            commands::proxy::logs::export_proxy_diagnostics,
             commands::proxy::logs::get_proxy_logs_count_filtered,
             commands::proxy::logs::get_proxy_logs_filtered,
             commands::proxy::status::set_proxy_monitor_enabled,
             commands::proxy::status::clear_proxy_logs,
+            commands::proxy::benchmark::run_proxy_benchmark,
             commands::proxy::config::generate_api_key,
             commands::proxy::accounts::reload_proxy_accounts,
+            commands::proxy::accounts::verify_all_accounts,
+            commands::proxy::accounts::retry_project_setup,
             commands::proxy::config::update_model_mapping,
             commands::proxy::external::fetch_zai_models,
-            commands::proxy::config::get_proxy_scheduling_config,
-            commands::proxy::config::update_proxy_scheduling_config,
+            commands::proxy::scheduling::get_proxy_scheduling_config,
+            commands::proxy::scheduling::update_proxy_scheduling_config,
+            commands::proxy::scheduling::get_proxy_retry_config,
+            commands::proxy::scheduling::update_proxy_retry_config,
+            commands::proxy::scheduling::get_background_routing,
+            commands::proxy::scheduling::update_background_routing,
+            commands::proxy::config::get_thinking_capabilities,
+            commands::proxy::config::set_thinking_override,
             commands::proxy::accounts::clear_proxy_session_bindings,
             commands::proxy::accounts::set_preferred_account,
             commands::proxy::accounts::get_preferred_account,
@@ -46,10 +46,69 @@ pub struct Account {
     /// Reason for temporary validation block
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub validation_blocked_reason: Option<String>,
+    /// Google Cloud project not onboarded for the API (403); excluded from the proxy pool until re-probed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project_setup_required: Option<ProjectSetupRequired>,
+    /// 账号标签 (如从官方客户端导入的账号带有 "imported")
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// 调度优先级 (越大越优先, 默认 0); 仅在负载与订阅等级之间参与排序
+    #[serde(default)]
+    pub priority: i32,
+    /// 用户备注 (如 "工作项目, 勿消耗")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    /// 最近一次上游失败; 下一次成功请求后自动清除
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<AccountLastError>,
     pub created_at: i64,
     pub last_used: i64,
 }
 
+/// 项目未开通 (onboarding / GCA tier) 状态, 需要用户按引导完成设置后重试
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProjectSetupRequired {
+    /// 上游错误摘要
+    pub reason: String,
+    /// 从错误中提取的项目 ID
+    #[serde(default)]
+    pub project_id: Option<String>,
+    /// 从错误中提取的开通 / 验证链接
+    #[serde(default)]
+    pub setup_url: Option<String>,
+    /// 检测时间 (unix 秒)
+    pub detected_at: i64,
+}
+
+/// 账号最近一次上游失败
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountLastError {
+    /// 发生时间 (unix 秒)
+    pub at: i64,
+    pub status: u16,
+    /// 截断后的错误信息
+    pub message: String,
+}
+
+impl AccountLastError {
+    /// 错误信息最大字符数
+    const MAX_MESSAGE_CHARS: usize = 300;
+
+    pub fn new(status: u16, message: &str) -> Self {
+        let message = message.trim();
+        let message = if message.chars().count() > Self::MAX_MESSAGE_CHARS {
+            format!("{}...", message.chars().take(Self::MAX_MESSAGE_CHARS).collect::<String>())
+        } else {
+            message.to_string()
+        };
+        Self {
+            at: chrono::Utc::now().timestamp(),
+            status,
+            message,
+        }
+    }
+}
+
 impl Account {
     pub fn new(id: String, email: String, token: TokenData) -> Self {
         let now = chrono::Utc::now().timestamp();
@@ -71,6 +130,11 @@ impl Account {
             validation_blocked: false,
             validation_blocked_until: None,
             validation_blocked_reason: None,
+            project_setup_required: None,
+            tags: Vec::new(),
+            priority: 0,
+            notes: None,
+            last_error: None,
             created_at: now,
             last_used: now,
         }
@@ -31,6 +31,10 @@ pub struct AppConfig {
     pub circuit_breaker: CircuitBreakerConfig, // [NEW] Circuit breaker configuration
     #[serde(default = "default_validation_block_minutes")]
     pub validation_block_minutes: u32, // [NEW] Minutes to block account after VALIDATION_REQUIRED error
+    #[serde(default = "default_quota_timezone")]
+    pub quota_timezone: String, // IANA zone whose midnight resets daily quota estimates and key budgets
+    #[serde(default = "default_display_timezone")]
+    pub display_timezone: String, // Zone for stats buckets and exported log times ("local" = system zone)
 }
 
 /// Scheduled warmup configuration
@@ -170,6 +174,14 @@ fn default_validation_block_minutes() -> u32 {
     10 // Default 10 minutes
 }
 
+fn default_quota_timezone() -> String {
+    crate::utils::time::DEFAULT_QUOTA_TIMEZONE.to_string()
+}
+
+fn default_display_timezone() -> String {
+    crate::utils::time::LOCAL_TIMEZONE.to_string()
+}
+
 impl CircuitBreakerConfig {
     pub fn new() -> Self {
         Self {
@@ -206,6 +218,8 @@ impl AppConfig {
             pinned_quota_models: PinnedQuotaModelsConfig::default(),
             circuit_breaker: CircuitBreakerConfig::default(),
             validation_block_minutes: default_validation_block_minutes(),
+            quota_timezone: default_quota_timezone(),
+            display_timezone: default_display_timezone(),
         }
     }
 }
@@ -0,0 +1,375 @@
+//! Batch account operations.
+//!
+//! All changes of one batch are validated first and persisted under a single
+//! `ACCOUNT_INDEX_LOCK` acquisition; if any write fails, the files already
+//! written are restored so the batch is all-or-nothing.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use serde::Serialize;
+
+use super::crud::ACCOUNT_INDEX_LOCK;
+use super::storage::{
+    accounts_dir_in, get_data_dir, load_account_in, load_account_index_in, save_account_in,
+    save_account_index_in,
+};
+use crate::models::Account;
+
+/// A batch operation applied to every selected account.
+#[derive(Debug, Clone)]
+pub enum BatchOperation {
+    /// Exclude from the proxy pool (`proxy_disabled`)
+    Pause,
+    SetPriority(i32),
+    SetTags { add: Vec<String>, remove: Vec<String> },
+    /// Free-text notes; empty clears them
+    SetNotes(Option<String>),
+    Delete,
+}
+
+impl BatchOperation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Pause => "pause",
+            Self::SetPriority(_) => "set_priority",
+            Self::SetTags { .. } => "set_tags",
+            Self::SetNotes(_) => "set_notes",
+            Self::Delete => "delete",
+        }
+    }
+
+    /// Whether the operation can take accounts out of the proxy pool
+    fn removes_from_pool(&self) -> bool {
+        matches!(self, Self::Pause | Self::Delete)
+    }
+
+    fn apply(&self, account: &mut Account) {
+        match self {
+            Self::Pause => {
+                if !account.proxy_disabled {
+                    account.proxy_disabled = true;
+                    account.proxy_disabled_reason = Some("Paused by user".to_string());
+                    account.proxy_disabled_at = Some(chrono::Utc::now().timestamp());
+                }
+            }
+            Self::SetPriority(priority) => account.priority = *priority,
+            Self::SetTags { add, remove } => {
+                let remove: HashSet<&str> = remove.iter().map(|t| t.trim()).collect();
+                account.tags.retain(|t| !remove.contains(t.as_str()));
+                for tag in add.iter().map(|t| t.trim()).filter(|t| !t.is_empty()) {
+                    if !remove.contains(tag) && !account.tags.iter().any(|t| t == tag) {
+                        account.tags.push(tag.to_string());
+                    }
+                }
+            }
+            Self::SetNotes(notes) => {
+                account.notes = notes
+                    .as_deref()
+                    .map(str::trim)
+                    .filter(|n| !n.is_empty())
+                    .map(str::to_string);
+            }
+            Self::Delete => {}
+        }
+    }
+}
+
+/// Per-account outcome of a batch operation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BatchItemResult {
+    pub account_id: String,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl BatchItemResult {
+    fn ok(account_id: &str) -> Self {
+        Self { account_id: account_id.to_string(), ok: true, error: None }
+    }
+
+    fn failed(account_id: &str, error: String) -> Self {
+        Self { account_id: account_id.to_string(), ok: false, error: Some(error) }
+    }
+}
+
+/// Account usable by the proxy pool
+fn is_usable(account: &Account) -> bool {
+    !account.disabled && !account.proxy_disabled
+}
+
+/// Refuse a batch that would leave no usable account while some are usable now.
+fn check_last_usable(all: &[Account], changed: &[Account], op: &BatchOperation) -> Result<(), String> {
+    if !op.removes_from_pool() {
+        return Ok(());
+    }
+    let usable_before = all.iter().filter(|a| is_usable(a)).count();
+    let changed_ids: HashSet<&str> = changed.iter().map(|a| a.id.as_str()).collect();
+    let usable_after = match op {
+        BatchOperation::Delete => all
+            .iter()
+            .filter(|a| !changed_ids.contains(a.id.as_str()) && is_usable(a))
+            .count(),
+        _ => all
+            .iter()
+            .map(|a| changed.iter().find(|c| c.id == a.id).unwrap_or(a))
+            .filter(|a| is_usable(a))
+            .count(),
+    };
+    if usable_before > 0 && usable_after == 0 {
+        return Err(format!(
+            "Refusing to {} the last usable account while the proxy is running (pass force to override)",
+            op.name().replace('_', " ")
+        ));
+    }
+    Ok(())
+}
+
+/// Apply `op` to `account_ids` atomically.
+///
+/// Unknown or unreadable ids are reported per id and skipped; the remaining
+/// accounts are persisted all-or-nothing. `before_persist` receives the ids
+/// about to change (e.g. to drop deleted accounts from the token pool first).
+/// With `protect_last_usable` the batch is rejected when it would leave the
+/// proxy pool without a usable account.
+pub fn apply_batch(
+    account_ids: &[String],
+    op: &BatchOperation,
+    protect_last_usable: bool,
+    before_persist: impl FnOnce(&[String]),
+) -> Result<Vec<BatchItemResult>, String> {
+    let _lock = ACCOUNT_INDEX_LOCK.lock();
+    apply_batch_in(&get_data_dir()?, account_ids, op, protect_last_usable, before_persist)
+}
+
+/// `apply_batch` against an explicit data directory; the caller holds `ACCOUNT_INDEX_LOCK`.
+fn apply_batch_in(
+    data_dir: &Path,
+    account_ids: &[String],
+    op: &BatchOperation,
+    protect_last_usable: bool,
+    before_persist: impl FnOnce(&[String]),
+) -> Result<Vec<BatchItemResult>, String> {
+    let mut index = load_account_index_in(data_dir)?;
+
+    let mut seen = HashSet::new();
+    let mut results = Vec::new();
+    let mut originals = Vec::new();
+    for id in account_ids.iter().filter(|id| seen.insert(id.as_str())) {
+        if !index.accounts.iter().any(|s| &s.id == id) {
+            results.push(BatchItemResult::failed(id, "account not found".to_string()));
+            continue;
+        }
+        match load_account_in(data_dir, id) {
+            Ok(account) => originals.push(account),
+            Err(e) => results.push(BatchItemResult::failed(id, e)),
+        }
+    }
+
+    let changed: Vec<Account> = originals
+        .iter()
+        .cloned()
+        .map(|mut account| {
+            op.apply(&mut account);
+            account
+        })
+        .collect();
+
+    if protect_last_usable {
+        let all: Vec<Account> = index
+            .accounts
+            .iter()
+            .filter_map(|s| {
+                originals
+                    .iter()
+                    .find(|a| a.id == s.id)
+                    .cloned()
+                    .or_else(|| load_account_in(data_dir, &s.id).ok())
+            })
+            .collect();
+        check_last_usable(&all, &changed, op)?;
+    }
+
+    let changed_ids: Vec<String> = changed.iter().map(|a| a.id.clone()).collect();
+    before_persist(&changed_ids);
+
+    if let BatchOperation::Delete = op {
+        index.accounts.retain(|s| !changed_ids.contains(&s.id));
+        if index
+            .current_account_id
+            .as_ref()
+            .is_some_and(|current| changed_ids.contains(current))
+        {
+            index.current_account_id = index.accounts.first().map(|s| s.id.clone());
+        }
+        // 索引是唯一的事实来源: 索引写入成功即视为删除完成, 账号文件尽力清理
+        save_account_index_in(data_dir, &index)?;
+        let accounts_dir = accounts_dir_in(data_dir)?;
+        for id in &changed_ids {
+            let _ = fs::remove_file(accounts_dir.join(format!("{}.json", id)));
+        }
+    } else {
+        for (written, account) in changed.iter().enumerate() {
+            if let Err(e) = save_account_in(data_dir, account) {
+                rollback(data_dir, &originals[..written]);
+                return Err(format!("Batch {} aborted at {}: {}", op.name(), account.id, e));
+            }
+        }
+        if let BatchOperation::Pause = op {
+            for summary in index.accounts.iter_mut().filter(|s| changed_ids.contains(&s.id)) {
+                summary.proxy_disabled = true;
+            }
+            if let Err(e) = save_account_index_in(data_dir, &index) {
+                rollback(data_dir, &originals);
+                return Err(format!("Batch {} aborted: {}", op.name(), e));
+            }
+        }
+    }
+
+    results.extend(changed_ids.iter().map(|id| BatchItemResult::ok(id)));
+    // 按请求顺序返回
+    results.sort_by_key(|r| account_ids.iter().position(|id| *id == r.account_id));
+    Ok(results)
+}
+
+fn rollback(data_dir: &Path, originals: &[Account]) {
+    for account in originals {
+        if let Err(e) = save_account_in(data_dir, account) {
+            crate::modules::logger::log_error(&format!(
+                "Failed to roll back account {} after batch error: {}",
+                account.id, e
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::TokenData;
+
+    fn account(id: &str) -> Account {
+        let token = TokenData::new(String::new(), String::new(), 3600, None, None, None);
+        Account::new(id.to_string(), format!("{}@example.com", id), token)
+    }
+
+    #[test]
+    fn test_set_tags_adds_and_removes() {
+        let mut acc = account("a");
+        acc.tags = vec!["old".to_string(), "keep".to_string()];
+        BatchOperation::SetTags {
+            add: vec!["new".to_string(), " keep ".to_string(), "".to_string()],
+            remove: vec!["old".to_string()],
+        }
+        .apply(&mut acc);
+        assert_eq!(acc.tags, vec!["keep".to_string(), "new".to_string()]);
+    }
+
+    #[test]
+    fn test_set_notes_trims_and_clears() {
+        let mut acc = account("a");
+        BatchOperation::SetNotes(Some("  work project, don't burn \n".to_string())).apply(&mut acc);
+        assert_eq!(acc.notes.as_deref(), Some("work project, don't burn"));
+        BatchOperation::SetNotes(Some("   ".to_string())).apply(&mut acc);
+        assert_eq!(acc.notes, None);
+    }
+
+    /// Data dir holding the given accounts, all listed in the index
+    fn data_dir_with(accounts: &[Account]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let mut index = crate::models::AccountIndex::new();
+        for acc in accounts {
+            save_account_in(dir.path(), acc).unwrap();
+            index.accounts.push(crate::models::AccountSummary {
+                id: acc.id.clone(),
+                email: acc.email.clone(),
+                name: None,
+                disabled: acc.disabled,
+                proxy_disabled: acc.proxy_disabled,
+                created_at: acc.created_at,
+                last_used: acc.last_used,
+            });
+        }
+        index.current_account_id = accounts.first().map(|a| a.id.clone());
+        save_account_index_in(dir.path(), &index).unwrap();
+        dir
+    }
+
+    fn ids(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[test]
+    fn test_batch_changes_are_persisted() {
+        let dir = data_dir_with(&[account("a"), account("b"), account("c")]);
+
+        let results = apply_batch_in(
+            dir.path(),
+            &ids(&["b", "missing", "a"]),
+            &BatchOperation::Pause,
+            true,
+            |_| {},
+        )
+        .unwrap();
+        assert_eq!(
+            results,
+            vec![
+                BatchItemResult::ok("b"),
+                BatchItemResult::failed("missing", "account not found".to_string()),
+                BatchItemResult::ok("a"),
+            ]
+        );
+        assert!(load_account_in(dir.path(), "a").unwrap().proxy_disabled);
+        assert!(!load_account_in(dir.path(), "c").unwrap().proxy_disabled);
+        let index = load_account_index_in(dir.path()).unwrap();
+        let paused: Vec<&str> = index
+            .accounts
+            .iter()
+            .filter(|s| s.proxy_disabled)
+            .map(|s| s.id.as_str())
+            .collect();
+        assert_eq!(paused, vec!["a", "b"]);
+
+        let mut removed = Vec::new();
+        apply_batch_in(dir.path(), &ids(&["a"]), &BatchOperation::Delete, false, |changed| {
+            removed = changed.to_vec()
+        })
+        .unwrap();
+        assert_eq!(removed, ids(&["a"]));
+        let index = load_account_index_in(dir.path()).unwrap();
+        assert!(index.accounts.iter().all(|s| s.id != "a"));
+        assert_eq!(index.current_account_id.as_deref(), Some("b"));
+        assert!(load_account_in(dir.path(), "a").is_err());
+    }
+
+    #[test]
+    fn test_failed_batch_rolls_back_written_accounts() {
+        let dir = data_dir_with(&[account("a"), account("b"), account("c")]);
+        // 索引临时文件位置被目录占用, 写索引必然失败
+        fs::create_dir(dir.path().join("accounts.json.tmp")).unwrap();
+
+        let err = apply_batch_in(dir.path(), &ids(&["a", "b"]), &BatchOperation::Pause, false, |_| {})
+            .unwrap_err();
+        assert!(err.contains("Batch pause aborted"), "{}", err);
+        for id in ["a", "b"] {
+            let acc = load_account_in(dir.path(), id).unwrap();
+            assert!(!acc.proxy_disabled, "{} was not rolled back", id);
+            assert_eq!(acc.proxy_disabled_reason, None);
+        }
+    }
+
+    #[test]
+    fn test_last_usable_account_is_protected() {
+        let mut disabled = account("b");
+        disabled.disabled = true;
+        let all = vec![account("a"), disabled];
+
+        let mut paused = all[0].clone();
+        BatchOperation::Pause.apply(&mut paused);
+        assert!(check_last_usable(&all, &[paused], &BatchOperation::Pause).is_err());
+        assert!(check_last_usable(&all, &[all[1].clone()], &BatchOperation::Delete).is_ok());
+        assert!(check_last_usable(&all, &[all[0].clone()], &BatchOperation::SetPriority(3)).is_ok());
+    }
+}
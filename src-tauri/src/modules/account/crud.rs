@@ -116,6 +116,17 @@ pub fn upsert_account(
     add_account(email, name, token)
 }
 
+/// Add a tag to an account (no-op if already present).
+pub fn tag_account(account_id: &str, tag: &str) -> Result<Account, String> {
+    let _lock = ACCOUNT_INDEX_LOCK.lock();
+    let mut account = load_account(account_id)?;
+    if !account.tags.iter().any(|t| t == tag) {
+        account.tags.push(tag.to_string());
+        save_account(&account)?;
+    }
+    Ok(account)
+}
+
 /// Delete account.
 pub fn delete_account(account_id: &str) -> Result<(), String> {
     let _lock = ACCOUNT_INDEX_LOCK.lock();
@@ -145,33 +156,6 @@ pub fn delete_account(account_id: &str) -> Result<(), String> {
     Ok(())
 }
 
-/// Batch delete accounts (atomic index operation).
-pub fn delete_accounts(account_ids: &[String]) -> Result<(), String> {
-    let _lock = ACCOUNT_INDEX_LOCK.lock();
-    let mut index = load_account_index()?;
-
-    let accounts_dir = get_accounts_dir()?;
-
-    for account_id in account_ids {
-        index.accounts.retain(|s| &s.id != account_id);
-
-        if index.current_account_id.as_deref() == Some(account_id) {
-            index.current_account_id = None;
-        }
-
-        let account_path = accounts_dir.join(format!("{}.json", account_id));
-        if account_path.exists() {
-            let _ = fs::remove_file(&account_path);
-        }
-    }
-
-    if index.current_account_id.is_none() {
-        index.current_account_id = index.accounts.first().map(|s| s.id.clone());
-    }
-
-    save_account_index(&index)
-}
-
 /// Reorder account list.
 pub fn reorder_accounts(account_ids: &[String]) -> Result<(), String> {
     let _lock = ACCOUNT_INDEX_LOCK.lock();
@@ -8,10 +8,12 @@
 //!
 //! - `storage` - File system operations for accounts
 //! - `crud` - Create, update, delete, reorder operations
+//! - `batch` - Atomic multi-account operations (pause, priority, tags, delete)
 //! - `device` - Device profile binding and management
 //! - `quota` - Quota fetching and protection logic
 //! - `switch` - Account switching logic
 
+mod batch;
 mod crud;
 mod device;
 mod quota;
@@ -19,8 +21,9 @@ pub mod storage;  // [FIX] Made public for TokenManager to check account index
 mod switch;
 
 // Re-export public API
+pub use batch::{apply_batch, BatchItemResult, BatchOperation};
 pub use crud::{
-    add_account, delete_account, delete_accounts, export_accounts_by_ids, reorder_accounts,
+    add_account, delete_account, export_accounts_by_ids, reorder_accounts, tag_account,
     upsert_account,
 };
 pub use device::{
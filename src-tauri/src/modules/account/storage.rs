@@ -1,7 +1,12 @@
 //! File system storage operations for accounts.
 
 use std::fs;
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
 
 use crate::models::{Account, AccountIndex};
 
@@ -9,6 +14,13 @@ const DATA_DIR: &str = ".antigravity_tools";
 const ACCOUNTS_INDEX: &str = "accounts.json";
 const ACCOUNTS_DIR: &str = "accounts";
 
+/// 账号文件路径 -> 写入锁: 同一账号文件的保存与读改写串行执行, 避免并发写入互相覆盖
+static ACCOUNT_FILE_LOCKS: Lazy<DashMap<PathBuf, Arc<Mutex<()>>>> = Lazy::new(DashMap::new);
+
+fn account_file_lock(path: &Path) -> Arc<Mutex<()>> {
+    ACCOUNT_FILE_LOCKS.entry(path.to_path_buf()).or_default().clone()
+}
+
 /// Get data directory path.
 pub fn get_data_dir() -> Result<PathBuf, String> {
     let home = dirs::home_dir().ok_or("failed_to_get_home_dir")?;
@@ -24,7 +36,11 @@ pub fn get_data_dir() -> Result<PathBuf, String> {
 
 /// Get accounts directory path.
 pub fn get_accounts_dir() -> Result<PathBuf, String> {
-    let data_dir = get_data_dir()?;
+    accounts_dir_in(&get_data_dir()?)
+}
+
+/// 指定数据目录下的账号目录 (不存在时创建)
+pub fn accounts_dir_in(data_dir: &Path) -> Result<PathBuf, String> {
     let accounts_dir = data_dir.join(ACCOUNTS_DIR);
 
     if !accounts_dir.exists() {
@@ -37,7 +53,10 @@ pub fn get_accounts_dir() -> Result<PathBuf, String> {
 
 /// Load account index.
 pub fn load_account_index() -> Result<AccountIndex, String> {
-    let data_dir = get_data_dir()?;
+    load_account_index_in(&get_data_dir()?)
+}
+
+pub fn load_account_index_in(data_dir: &Path) -> Result<AccountIndex, String> {
     let index_path = data_dir.join(ACCOUNTS_INDEX);
 
     if !index_path.exists() {
@@ -63,9 +82,21 @@ pub fn load_account_index() -> Result<AccountIndex, String> {
     Ok(index)
 }
 
+/// 读取指定数据目录下账号索引中的全部账号 ID (静默, 不记录日志)
+///
+/// 索引缺失或无法解析时返回 None, 调用方不应据此清理任何引用。
+pub fn account_ids_in(data_dir: &Path) -> Option<HashSet<String>> {
+    let content = fs::read_to_string(data_dir.join(ACCOUNTS_INDEX)).ok()?;
+    let index: AccountIndex = serde_json::from_str(&content).ok()?;
+    Some(index.accounts.into_iter().map(|a| a.id).collect())
+}
+
 /// Save account index (atomic write).
 pub fn save_account_index(index: &AccountIndex) -> Result<(), String> {
-    let data_dir = get_data_dir()?;
+    save_account_index_in(&get_data_dir()?, index)
+}
+
+pub fn save_account_index_in(data_dir: &Path, index: &AccountIndex) -> Result<(), String> {
     let index_path = data_dir.join(ACCOUNTS_INDEX);
     let temp_path = data_dir.join(format!("{}.tmp", ACCOUNTS_INDEX));
 
@@ -81,7 +112,11 @@ pub fn save_account_index(index: &AccountIndex) -> Result<(), String> {
 
 /// Load account data.
 pub fn load_account(account_id: &str) -> Result<Account, String> {
-    let accounts_dir = get_accounts_dir()?;
+    load_account_in(&get_data_dir()?, account_id)
+}
+
+pub fn load_account_in(data_dir: &Path, account_id: &str) -> Result<Account, String> {
+    let accounts_dir = accounts_dir_in(data_dir)?;
     let account_path = accounts_dir.join(format!("{}.json", account_id));
 
     if !account_path.exists() {
@@ -97,13 +132,38 @@ pub fn load_account(account_id: &str) -> Result<Account, String> {
 
 /// Save account data.
 pub fn save_account(account: &Account) -> Result<(), String> {
-    let accounts_dir = get_accounts_dir()?;
+    save_account_in(&get_data_dir()?, account)
+}
+
+pub fn save_account_in(data_dir: &Path, account: &Account) -> Result<(), String> {
+    let accounts_dir = accounts_dir_in(data_dir)?;
     let account_path = accounts_dir.join(format!("{}.json", account.id));
 
+    let lock = account_file_lock(&account_path);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+    write_account_file(&account_path, account)
+}
+
+/// 在账号文件锁内读取、修改并保存账号, 与其他保存操作串行执行
+pub fn update_account_in(
+    data_dir: &Path,
+    account_id: &str,
+    update: impl FnOnce(&mut Account),
+) -> Result<(), String> {
+    let account_path = accounts_dir_in(data_dir)?.join(format!("{}.json", account_id));
+
+    let lock = account_file_lock(&account_path);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+    let mut account = load_account_in(data_dir, account_id)?;
+    update(&mut account);
+    write_account_file(&account_path, &account)
+}
+
+fn write_account_file(account_path: &Path, account: &Account) -> Result<(), String> {
     let content = serde_json::to_string_pretty(account)
         .map_err(|e| format!("failed_to_serialize_account_data: {}", e))?;
 
-    fs::write(&account_path, content)
+    fs::write(account_path, content)
         .map_err(|e| format!("failed_to_save_account_data: {}", e))
 }
 
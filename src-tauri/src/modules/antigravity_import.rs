@@ -0,0 +1,389 @@
+//! 从官方 Antigravity 客户端一次性导入账号
+//!
+//! 扫描官方客户端的本地存储 (state.vscdb), 提取已授权账号的 refresh_token,
+//! 由用户逐个确认后通过刷新探测验证, 再走常规的账号添加流程并打上 `imported` 标签。
+//! 源文件只复制到临时目录后读取, 从不修改; 安装缺失、文件被锁或部分不可读时按条目返回结果。
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::models::{Account, TokenData};
+use crate::modules::{account, db, oauth};
+use crate::utils::protobuf;
+
+/// 导入账号的标签
+pub const IMPORTED_TAG: &str = "imported";
+
+/// 旧版 (< 1.16.5) 登录状态
+const LEGACY_STATE_KEY: &str = "jetskiStateSync.agentManagerInitState";
+/// 新版 (>= 1.16.5) OAuth token
+const UNIFIED_OAUTH_KEY: &str = "antigravityUnifiedStateSync.oauthToken";
+
+/// 单个条目的处理结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportItemStatus {
+    /// 已找到, 等待用户确认
+    PendingConfirmation,
+    Imported,
+    /// 用户未确认
+    Skipped,
+    /// 刷新探测或保存失败
+    Failed,
+    /// 存储不存在
+    NotFound,
+    /// 存储被锁定或无法解析
+    Unreadable,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportItemResult {
+    /// 来源 (存储路径与键)
+    pub source: String,
+    /// 候选账号 ID, 确认导入时回传 (refresh_token 的摘要, 不暴露 token 本身)
+    pub candidate_id: Option<String>,
+    pub email: Option<String>,
+    pub project_id: Option<String>,
+    /// 本管理器中已存在相同 refresh_token 的账号
+    pub already_imported: bool,
+    pub status: ImportItemStatus,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AntigravityImportReport {
+    pub items: Vec<ImportItemResult>,
+    pub imported: Vec<Account>,
+}
+
+/// 从存储中读出的凭据
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct StoredCredential {
+    source: String,
+    refresh_token: String,
+    email: Option<String>,
+}
+
+impl StoredCredential {
+    fn candidate_id(&self) -> String {
+        candidate_id(&self.refresh_token)
+    }
+}
+
+fn candidate_id(refresh_token: &str) -> String {
+    let digest = Sha256::digest(refresh_token.as_bytes());
+    digest[..8].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// 扫描官方客户端存储; `confirmed` 为 None 时只列出候选账号, 否则导入已确认的候选
+pub async fn import_from_antigravity(confirmed: Option<&[String]>) -> AntigravityImportReport {
+    let mut report = AntigravityImportReport::default();
+    let mut credentials = Vec::new();
+
+    for path in store_paths() {
+        match read_store(&path) {
+            Ok((found, errors)) => {
+                credentials.extend(found);
+                report.items.extend(errors);
+            }
+            Err(item) => report.items.push(item),
+        }
+    }
+
+    // 多个来源中的同一账号只处理一次
+    let mut seen = HashSet::new();
+    credentials.retain(|c| seen.insert(c.refresh_token.clone()));
+
+    let existing: HashSet<String> = account::list_accounts()
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .map(|a| a.token.refresh_token)
+        .collect();
+
+    for credential in credentials {
+        let mut item = ImportItemResult {
+            source: credential.source.clone(),
+            candidate_id: Some(credential.candidate_id()),
+            email: credential.email.clone(),
+            project_id: None,
+            already_imported: existing.contains(&credential.refresh_token),
+            status: ImportItemStatus::PendingConfirmation,
+            message: None,
+        };
+
+        let Some(confirmed) = confirmed else {
+            report.items.push(item);
+            continue;
+        };
+        if !confirmed.contains(&credential.candidate_id()) {
+            item.status = ImportItemStatus::Skipped;
+            item.message = Some("Not confirmed by user".to_string());
+            report.items.push(item);
+            continue;
+        }
+
+        match import_credential(&credential).await {
+            Ok(imported) => {
+                item.status = ImportItemStatus::Imported;
+                item.email = Some(imported.email.clone());
+                item.project_id = imported.token.project_id.clone();
+                report.imported.push(imported);
+            }
+            Err(e) => {
+                crate::modules::logger::log_warn(&format!(
+                    "Antigravity import failed for {}: {}",
+                    credential.email.as_deref().unwrap_or("unknown account"),
+                    e
+                ));
+                item.status = ImportItemStatus::Failed;
+                item.message = Some(e);
+            }
+        }
+        report.items.push(item);
+    }
+
+    report
+}
+
+/// 刷新探测通过后按常规流程添加账号并打标签
+async fn import_credential(credential: &StoredCredential) -> Result<Account, String> {
+    let token_resp = oauth::refresh_access_token(&credential.refresh_token, None)
+        .await
+        .map_err(|e| format!("Refresh probe failed: {}", e))?;
+    let user_info = oauth::get_user_info(&token_resp.access_token, None).await?;
+    let project_id = crate::proxy::project_resolver::fetch_project_id(&token_resp.access_token)
+        .await
+        .ok();
+
+    let token_data = TokenData::new(
+        token_resp.access_token,
+        credential.refresh_token.clone(),
+        token_resp.expires_in,
+        Some(user_info.email.clone()),
+        project_id,
+        None,
+    );
+    let imported = account::upsert_account(user_info.email.clone(), user_info.name, token_data)?;
+    crate::modules::logger::log_info(&format!("Imported account from Antigravity: {}", imported.email));
+    account::tag_account(&imported.id, IMPORTED_TAG)
+}
+
+/// 官方客户端存储的候选位置 (--user-data-dir / 便携模式 / 系统默认路径)
+fn store_paths() -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    if let Ok(path) = db::get_db_path() {
+        paths.push(path);
+    }
+    if let Some(path) = default_store_path() {
+        if !paths.contains(&path) {
+            paths.push(path);
+        }
+    }
+    paths
+}
+
+fn default_store_path() -> Option<PathBuf> {
+    #[cfg(target_os = "macos")]
+    {
+        dirs::home_dir().map(|h| h.join("Library/Application Support/Antigravity/User/globalStorage/state.vscdb"))
+    }
+    #[cfg(target_os = "windows")]
+    {
+        std::env::var("APPDATA")
+            .ok()
+            .map(|a| PathBuf::from(a).join("Antigravity\\User\\globalStorage\\state.vscdb"))
+    }
+    #[cfg(target_os = "linux")]
+    {
+        dirs::home_dir().map(|h| h.join(".config/Antigravity/User/globalStorage/state.vscdb"))
+    }
+}
+
+fn store_item(path: &Path, key: Option<&str>, status: ImportItemStatus, message: String) -> ImportItemResult {
+    let source = match key {
+        Some(key) => format!("{} ({})", path.display(), key),
+        None => path.display().to_string(),
+    };
+    ImportItemResult {
+        source,
+        candidate_id: None,
+        email: None,
+        project_id: None,
+        already_imported: false,
+        status,
+        message: Some(message),
+    }
+}
+
+/// 复制到临时目录后读取, 返回凭据与各个键的解析错误; 整个存储不可用时返回 Err
+fn read_store(path: &Path) -> Result<(Vec<StoredCredential>, Vec<ImportItemResult>), ImportItemResult> {
+    if !path.exists() {
+        return Err(store_item(path, None, ImportItemStatus::NotFound, "Antigravity is not installed or has never signed in".to_string()));
+    }
+
+    let temp_dir = std::env::temp_dir().join(format!("antigravity-import-{}", uuid::Uuid::new_v4()));
+    let result = copy_store(path, &temp_dir)
+        .map_err(|e| store_item(path, None, ImportItemStatus::Unreadable, format!("Store is locked or unreadable: {}", e)))
+        .and_then(|copy| {
+            read_entries(&copy)
+                .map_err(|e| store_item(path, None, ImportItemStatus::Unreadable, e))
+        });
+    let _ = fs::remove_dir_all(&temp_dir);
+    let entries = result?;
+
+    let mut credentials = Vec::new();
+    let mut errors = Vec::new();
+    for (key, value) in entries {
+        let parsed = if key == LEGACY_STATE_KEY {
+            parse_legacy_state(&value)
+        } else {
+            parse_unified_oauth(&value).map(|rt| (rt, None))
+        };
+        match parsed {
+            Ok((refresh_token, email)) => credentials.push(StoredCredential {
+                source: format!("{} ({})", path.display(), key),
+                refresh_token,
+                email,
+            }),
+            Err(e) => errors.push(store_item(path, Some(key), ImportItemStatus::Unreadable, e)),
+        }
+    }
+    Ok((credentials, errors))
+}
+
+/// 复制数据库及其 WAL 文件 (包含尚未合并的写入)
+fn copy_store(path: &Path, temp_dir: &Path) -> Result<PathBuf, String> {
+    fs::create_dir_all(temp_dir).map_err(|e| e.to_string())?;
+    let copy = temp_dir.join("state.vscdb");
+    fs::copy(path, &copy).map_err(|e| e.to_string())?;
+    let wal = PathBuf::from(format!("{}-wal", path.display()));
+    if wal.exists() {
+        fs::copy(&wal, temp_dir.join("state.vscdb-wal")).map_err(|e| e.to_string())?;
+    }
+    Ok(copy)
+}
+
+fn read_entries(copy: &Path) -> Result<Vec<(&'static str, String)>, String> {
+    let conn = rusqlite::Connection::open(copy).map_err(|e| format!("Failed to open database: {}", e))?;
+    let mut entries = Vec::new();
+    for key in [UNIFIED_OAUTH_KEY, LEGACY_STATE_KEY] {
+        let value = conn.query_row("SELECT value FROM ItemTable WHERE key = ?", [key], |row| row.get::<_, String>(0));
+        match value {
+            Ok(value) => entries.push((key, value)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => {}
+            Err(e) => return Err(format!("Failed to read {}: {}", key, e)),
+        }
+    }
+    if entries.is_empty() {
+        return Err("No signed-in account found in the Antigravity store".to_string());
+    }
+    Ok(entries)
+}
+
+fn decode_b64(value: &str) -> Result<Vec<u8>, String> {
+    general_purpose::STANDARD
+        .decode(value.trim())
+        .map_err(|e| format!("Base64 decoding failed: {}", e))
+}
+
+fn refresh_token_from_oauth_info(oauth_info: &[u8]) -> Result<String, String> {
+    let refresh = protobuf::find_field(oauth_info, 3)?
+        .ok_or("Refresh Token not included in data (Field 3)")?;
+    let refresh = String::from_utf8(refresh).map_err(|_| "Refresh Token is not UTF-8 encoded".to_string())?;
+    if refresh.is_empty() {
+        return Err("Refresh Token is empty".to_string());
+    }
+    Ok(refresh)
+}
+
+/// 旧版: Field 6 = OAuthTokenInfo, Field 2 = email
+fn parse_legacy_state(value: &str) -> Result<(String, Option<String>), String> {
+    let blob = decode_b64(value)?;
+    let oauth_info = protobuf::find_field(&blob, 6)?.ok_or("OAuth data not found (Field 6)")?;
+    let email = protobuf::find_field(&blob, 2)?
+        .and_then(|e| String::from_utf8(e).ok())
+        .filter(|e| !e.is_empty());
+    Ok((refresh_token_from_oauth_info(&oauth_info)?, email))
+}
+
+/// 新版: Outer.1 -> Inner.2 -> Inner2.1 = base64(OAuthTokenInfo)
+fn parse_unified_oauth(value: &str) -> Result<String, String> {
+    let outer = decode_b64(value)?;
+    let inner = protobuf::find_field(&outer, 1)?.ok_or("OAuth token entry not found")?;
+    let inner2 = protobuf::find_field(&inner, 2)?.ok_or("OAuth token value not found")?;
+    let info_b64 = protobuf::find_field(&inner2, 1)?.ok_or("OAuth token info not found")?;
+    let info_b64 = String::from_utf8(info_b64).map_err(|_| "OAuth token info is not UTF-8 encoded".to_string())?;
+    refresh_token_from_oauth_info(&decode_b64(&info_b64)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_both_store_formats() {
+        let legacy = [
+            protobuf::create_email_field("user@example.com"),
+            protobuf::create_oauth_field("ya29.access", "1//legacy-refresh", 1_700_000_000),
+        ]
+        .concat();
+        let (refresh, email) = parse_legacy_state(&general_purpose::STANDARD.encode(legacy)).unwrap();
+        assert_eq!(refresh, "1//legacy-refresh");
+        assert_eq!(email.as_deref(), Some("user@example.com"));
+
+        let info = protobuf::create_oauth_info("ya29.access", "1//unified-refresh", 1_700_000_000);
+        let inner2 = protobuf::encode_string_field(1, &general_purpose::STANDARD.encode(info));
+        let inner = [
+            protobuf::encode_string_field(1, "oauthTokenInfoSentinelKey"),
+            protobuf::encode_len_delim_field(2, &inner2),
+        ]
+        .concat();
+        let outer = protobuf::encode_len_delim_field(1, &inner);
+        assert_eq!(parse_unified_oauth(&general_purpose::STANDARD.encode(outer)).unwrap(), "1//unified-refresh");
+
+        assert!(parse_legacy_state("not base64!").is_err());
+        assert!(parse_unified_oauth(&general_purpose::STANDARD.encode([0x0a, 0x00])).is_err());
+    }
+
+    #[test]
+    fn test_read_store_never_touches_source() {
+        let dir = std::env::temp_dir().join(format!("antigravity-import-test-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let missing = read_store(&dir.join("missing.vscdb")).unwrap_err();
+        assert_eq!(missing.status, ImportItemStatus::NotFound);
+
+        let path = dir.join("state.vscdb");
+        {
+            let conn = rusqlite::Connection::open(&path).unwrap();
+            conn.execute("CREATE TABLE ItemTable (key TEXT PRIMARY KEY, value TEXT)", []).unwrap();
+            let legacy = protobuf::create_oauth_field("ya29.access", "1//legacy-refresh", 0);
+            conn.execute(
+                "INSERT INTO ItemTable (key, value) VALUES (?, ?)",
+                [LEGACY_STATE_KEY, &general_purpose::STANDARD.encode(legacy)],
+            )
+            .unwrap();
+            // 部分可读: 新版键损坏
+            conn.execute("INSERT INTO ItemTable (key, value) VALUES (?, ?)", [UNIFIED_OAUTH_KEY, "garbage"])
+                .unwrap();
+        }
+        let before = fs::read(&path).unwrap();
+
+        let (credentials, errors) = read_store(&path).unwrap();
+        assert_eq!(credentials.len(), 1);
+        assert_eq!(credentials[0].refresh_token, "1//legacy-refresh");
+        assert_eq!(credentials[0].candidate_id(), candidate_id("1//legacy-refresh"));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].status, ImportItemStatus::Unreadable);
+        assert!(errors[0].source.contains(UNIFIED_OAUTH_KEY));
+
+        assert_eq!(fs::read(&path).unwrap(), before);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -1,28 +1,117 @@
 use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
 use serde_json;
 
 use crate::models::AppConfig;
 use super::account::get_data_dir;
 
 const CONFIG_FILE: &str = "gui_config.json";
+const CONFIG_TEMP_FILE: &str = "gui_config.json.tmp";
+/// 最近一次可成功解析的配置 (last-known-good)
+const CONFIG_BACKUP_FILE: &str = "gui_config.json.bak";
+
+/// 可从备份中单独恢复的配置分区
+pub const RESTORABLE_SECTIONS: [&str; 5] = ["mapping", "scheduling", "zai", "experimental", "all"];
+
+/// 启动时若主配置损坏并从备份恢复, 记录警告供前端查询
+static RECOVERY_WARNING: Mutex<Option<String>> = Mutex::new(None);
 
 /// Load application configuration
 pub fn load_app_config() -> Result<AppConfig, String> {
     let data_dir = get_data_dir()?;
+    let config = load_app_config_from(&data_dir)?;
+    // 无效时区保留默认值, 不阻止启动
+    if let Err(e) = crate::utils::time::set_zones(&config.quota_timezone, &config.display_timezone) {
+        crate::modules::logger::log_warn(&format!("Ignoring invalid time zone setting: {}", e));
+    }
+    Ok(config)
+}
+
+/// Save application configuration
+pub fn save_app_config(config: &AppConfig) -> Result<(), String> {
+    crate::utils::time::set_zones(&config.quota_timezone, &config.display_timezone)?;
+    let data_dir = get_data_dir()?;
+    save_app_config_to(&data_dir, config)
+}
+
+/// 只更新并保存配置中的自定义模型映射
+pub fn save_custom_mapping(mapping: &std::collections::HashMap<String, String>) -> Result<(), String> {
+    let mut config = load_app_config()?;
+    config.proxy.custom_mapping = mapping.clone();
+    save_app_config(&config)
+}
+
+/// 获取最近一次配置恢复的警告信息 (无恢复时为 None)
+pub fn config_recovery_warning() -> Option<String> {
+    RECOVERY_WARNING.lock().ok().and_then(|w| w.clone())
+}
+
+/// 用备份中的指定分区覆盖当前配置并保存, 返回恢复后的完整配置
+pub fn restore_config_backup(section: &str) -> Result<AppConfig, String> {
+    let data_dir = get_data_dir()?;
+    restore_config_backup_in(&data_dir, section)
+}
+
+fn load_app_config_from(data_dir: &Path) -> Result<AppConfig, String> {
     let config_path = data_dir.join(CONFIG_FILE);
-    
+
     if !config_path.exists() {
-        let config = AppConfig::new();
-        let _ = save_app_config(&config);
+        let mut config = AppConfig::new();
+        // 首次运行: 避开已被其他本地工具占用的默认端口
+        config.proxy.port = crate::proxy::ports::pick_default_port();
+        let _ = save_app_config_to(data_dir, &config);
         return Ok(config);
     }
-    
-    let content = fs::read_to_string(&config_path)
-        .map_err(|e| format!("failed_to_read_config_file: {}", e))?;
-    
-    let mut v: serde_json::Value = serde_json::from_str(&content)
+
+    let primary = fs::read_to_string(&config_path)
+        .map_err(|e| format!("failed_to_read_config_file: {}", e))
+        .and_then(|content| parse_app_config(&content));
+
+    let (mut config, mut modified) = match primary {
+        Ok(parsed) => parsed,
+        Err(primary_err) => {
+            // 主配置损坏 (例如写入过程中断电), 回退到 last-known-good 备份
+            let backup_path = data_dir.join(CONFIG_BACKUP_FILE);
+            let content = fs::read_to_string(&backup_path).map_err(|_| primary_err.clone())?;
+            let (config, _) = parse_app_config(&content).map_err(|e| {
+                format!("{} (backup also unusable: {})", primary_err, e)
+            })?;
+
+            let warning = format!(
+                "Config file {} is corrupted ({}), recovered from last-known-good backup",
+                CONFIG_FILE, primary_err
+            );
+            crate::modules::logger::log_warn(&warning);
+            if let Ok(mut slot) = RECOVERY_WARNING.lock() {
+                *slot = Some(warning);
+            }
+
+            // 保留损坏文件以便排查, 然后用备份内容修复主配置
+            let _ = fs::rename(&config_path, data_dir.join(format!("{}.corrupt", CONFIG_FILE)));
+            (config, true)
+        }
+    };
+
+    // 账号删除后调度配置中残留的 ID 在加载时清理 (索引不可读时不动)
+    if let Some(ids) = super::account::storage::account_ids_in(data_dir) {
+        modified |= config.proxy.scheduling.prune_unknown_accounts(&ids);
+    }
+
+    // If migration or recovery occurred, auto-save once to clean up the file
+    if modified {
+        let _ = save_app_config_to(data_dir, &config);
+    }
+
+    Ok(config)
+}
+
+/// 解析配置内容并执行旧字段迁移, 返回 (配置, 是否发生迁移)
+fn parse_app_config(content: &str) -> Result<(AppConfig, bool), String> {
+    let mut v: serde_json::Value = serde_json::from_str(content)
         .map_err(|e| format!("failed_to_parse_config_file: {}", e))?;
-    
+
     let mut modified = false;
 
     // Migration logic
@@ -66,25 +155,213 @@ pub fn load_app_config() -> Result<AppConfig, String> {
         }
     }
 
-    let config: AppConfig = serde_json::from_value(v)
+    let mut config: AppConfig = serde_json::from_value(v)
         .map_err(|e| format!("failed_to_convert_config_after_migration: {}", e))?;
-    
-    // If migration occurred, auto-save once to clean up the file
-    if modified {
-        let _ = save_app_config(&config);
+
+    // 旧版本调度配置没有范围校验, 加载时拉回合法区间
+    if config.proxy.scheduling.migrate_legacy() {
+        modified = true;
     }
 
-    Ok(config)
+    Ok((config, modified))
 }
 
-/// Save application configuration
-pub fn save_app_config(config: &AppConfig) -> Result<(), String> {
-    let data_dir = get_data_dir()?;
+/// 原子写入: 先写临时文件并 fsync, 备份当前可用配置, 再 rename 替换
+fn save_app_config_to(data_dir: &Path, config: &AppConfig) -> Result<(), String> {
     let config_path = data_dir.join(CONFIG_FILE);
-    
-    let content = serde_json::to_string_pretty(config)
+    let temp_path = data_dir.join(CONFIG_TEMP_FILE);
+
+    // 端口历史以磁盘上的配置为准 (调用方持有的副本可能已过期), 端口变化时记入旧端口
+    let existing = fs::read_to_string(&config_path).ok();
+    let mut config = config.clone();
+    if let Some((previous, _)) = existing.as_deref().and_then(|c| parse_app_config(c).ok()) {
+        config.proxy.port_history = previous.proxy.port_history;
+        crate::proxy::ports::record_port_change(
+            &mut config.proxy.port_history,
+            previous.proxy.port,
+            config.proxy.port,
+        );
+    }
+
+    let content = serde_json::to_string_pretty(&config)
         .map_err(|e| format!("failed_to_serialize_config: {}", e))?;
-    
-    fs::write(&config_path, content)
+
+    let mut file = fs::File::create(&temp_path)
+        .map_err(|e| format!("failed_to_write_temp_config_file: {}", e))?;
+    file.write_all(content.as_bytes())
+        .and_then(|_| file.sync_all())
+        .map_err(|e| format!("failed_to_write_temp_config_file: {}", e))?;
+    drop(file);
+
+    // 仅当现有文件可以解析时才更新备份, 避免用损坏内容覆盖 last-known-good
+    if let Some(existing) = existing {
+        if parse_app_config(&existing).is_ok() {
+            let _ = fs::write(data_dir.join(CONFIG_BACKUP_FILE), existing);
+        }
+    }
+
+    fs::rename(&temp_path, &config_path)
         .map_err(|e| format!("failed_to_save_config: {}", e))
 }
+
+fn restore_config_backup_in(data_dir: &Path, section: &str) -> Result<AppConfig, String> {
+    if !RESTORABLE_SECTIONS.contains(&section) {
+        return Err(format!(
+            "unknown_config_section: {} (expected one of {})",
+            section,
+            RESTORABLE_SECTIONS.join(", ")
+        ));
+    }
+
+    let content = fs::read_to_string(data_dir.join(CONFIG_BACKUP_FILE))
+        .map_err(|e| format!("config_backup_not_found: {}", e))?;
+    let (backup, _) = parse_app_config(&content)?;
+
+    let config = match section {
+        "all" => backup,
+        _ => {
+            let mut config = load_app_config_from(data_dir)?;
+            match section {
+                "mapping" => config.proxy.custom_mapping = backup.proxy.custom_mapping,
+                "scheduling" => config.proxy.scheduling = backup.proxy.scheduling,
+                "zai" => config.proxy.zai = backup.proxy.zai,
+                _ => config.proxy.experimental = backup.proxy.experimental,
+            }
+            config
+        }
+    };
+
+    save_app_config_to(data_dir, &config)?;
+    crate::modules::logger::log_info(&format!("Restored config section '{}' from backup", section));
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_data_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("abv_config_{}_{}", name, uuid::Uuid::new_v4().simple()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn config_with_mapping(from: &str, to: &str) -> AppConfig {
+        let mut config = AppConfig::new();
+        config.proxy.custom_mapping.insert(from.to_string(), to.to_string());
+        config
+    }
+
+    #[test]
+    fn test_truncated_config_recovers_from_backup() {
+        let dir = temp_data_dir("truncated");
+        save_app_config_to(&dir, &config_with_mapping("gpt-4", "gemini-2.5-pro")).unwrap();
+        save_app_config_to(&dir, &config_with_mapping("gpt-4", "gemini-2.5-flash")).unwrap();
+
+        // 模拟写入过程中断电: 主配置只剩一半
+        let config_path = dir.join(CONFIG_FILE);
+        let content = fs::read_to_string(&config_path).unwrap();
+        fs::write(&config_path, &content[..content.len() / 2]).unwrap();
+
+        let config = load_app_config_from(&dir).expect("should fall back to backup");
+        assert_eq!(
+            config.proxy.custom_mapping.get("gpt-4").map(String::as_str),
+            Some("gemini-2.5-pro")
+        );
+        assert!(config_recovery_warning().is_some());
+
+        // 主配置已被修复, 损坏文件保留以便排查
+        assert!(parse_app_config(&fs::read_to_string(&config_path).unwrap()).is_ok());
+        assert!(dir.join(format!("{}.corrupt", CONFIG_FILE)).exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_truncated_config_without_backup_fails() {
+        let dir = temp_data_dir("no_backup");
+        fs::write(dir.join(CONFIG_FILE), "{\"proxy\": {\"enab").unwrap();
+
+        assert!(load_app_config_from(&dir).is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_save_is_atomic_and_keeps_last_known_good() {
+        let dir = temp_data_dir("atomic");
+        save_app_config_to(&dir, &config_with_mapping("a", "first")).unwrap();
+        assert!(!dir.join(CONFIG_BACKUP_FILE).exists());
+
+        save_app_config_to(&dir, &config_with_mapping("a", "second")).unwrap();
+        assert!(!dir.join(CONFIG_TEMP_FILE).exists());
+
+        let (backup, _) = parse_app_config(&fs::read_to_string(dir.join(CONFIG_BACKUP_FILE)).unwrap()).unwrap();
+        assert_eq!(backup.proxy.custom_mapping.get("a").map(String::as_str), Some("first"));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_restore_single_section_from_backup() {
+        let dir = temp_data_dir("restore");
+        let mut old = config_with_mapping("claude-opus", "gemini-3-pro");
+        old.proxy.experimental.enable_usage_scaling = false;
+        save_app_config_to(&dir, &old).unwrap();
+
+        let mut current = config_with_mapping("claude-opus", "broken-model");
+        current.proxy.experimental.enable_usage_scaling = true;
+        save_app_config_to(&dir, &current).unwrap();
+
+        let restored = restore_config_backup_in(&dir, "mapping").unwrap();
+        assert_eq!(
+            restored.proxy.custom_mapping.get("claude-opus").map(String::as_str),
+            Some("gemini-3-pro")
+        );
+        // 其他分区保持当前值
+        assert!(restored.proxy.experimental.enable_usage_scaling);
+
+        assert!(restore_config_backup_in(&dir, "unknown").is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_port_change_is_recorded_in_history() {
+        let dir = temp_data_dir("port_history");
+        let mut config = AppConfig::new();
+        config.proxy.port = 8045;
+        save_app_config_to(&dir, &config).unwrap();
+
+        // 调用方持有的旧副本 (port_history 为空) 保存时不会丢失历史
+        config.proxy.port = 8046;
+        save_app_config_to(&dir, &config).unwrap();
+        config.proxy.port = 8047;
+        save_app_config_to(&dir, &config).unwrap();
+
+        let saved = load_app_config_from(&dir).unwrap();
+        assert_eq!(saved.proxy.port, 8047);
+        assert_eq!(saved.proxy.port_history, vec![8046, 8045]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_load_prunes_deleted_scheduling_accounts() {
+        let dir = temp_data_dir("prune_accounts");
+        let mut config = AppConfig::new();
+        config.proxy.scheduling.selected_accounts = vec!["kept".into(), "deleted".into()];
+        save_app_config_to(&dir, &config).unwrap();
+
+        // 账号索引缺失时不清理
+        assert_eq!(load_app_config_from(&dir).unwrap().proxy.scheduling.selected_accounts.len(), 2);
+
+        fs::write(
+            dir.join("accounts.json"),
+            r#"{"version":"2.0","accounts":[{"id":"kept","email":"a@b.c","name":null,"created_at":0,"last_used":0}],"current_account_id":null}"#,
+        )
+        .unwrap();
+        let loaded = load_app_config_from(&dir).unwrap();
+        assert_eq!(loaded.proxy.scheduling.selected_accounts, vec!["kept"]);
+
+        // 清理结果已写回文件
+        let (saved, _) = parse_app_config(&fs::read_to_string(dir.join(CONFIG_FILE)).unwrap()).unwrap();
+        assert_eq!(saved.proxy.scheduling.selected_accounts, vec!["kept"]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
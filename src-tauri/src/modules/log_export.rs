@@ -0,0 +1,422 @@
+// Proxy Log Export Jobs
+// 大量日志分批写入目标目录下的临时文件, 全部写完后再重命名到目标路径,
+// 中途取消或失败不会在用户选择的位置留下半个文件。同一时间只运行一个导出任务。
+
+use crate::proxy::monitor::ProxyRequestLog;
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+/// 每批读取/写入的行数
+const EXPORT_BATCH_SIZE: usize = 500;
+/// 保留的已结束任务状态数
+const MAX_FINISHED_JOBS: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportState {
+    Running,
+    Completed,
+    Cancelled,
+    Failed,
+}
+
+/// 进度事件 (每写完一批发送一次)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportProgress {
+    pub job_id: String,
+    pub rows_done: u64,
+    pub total: u64,
+}
+
+/// 导出任务状态 (get_export_status 返回)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportStatus {
+    pub job_id: String,
+    pub state: ExportState,
+    pub rows_done: u64,
+    pub total: u64,
+    pub file_path: String,
+    pub error: Option<String>,
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+}
+
+struct ActiveJob {
+    job_id: String,
+    cancel: CancellationToken,
+}
+
+static ACTIVE: Lazy<Mutex<Option<ActiveJob>>> = Lazy::new(|| Mutex::new(None));
+static JOBS: Lazy<DashMap<String, ExportStatus>> = Lazy::new(DashMap::new);
+
+/// 逐条写出 JSON 数组, 输出与 `serde_json::to_string_pretty(&Vec<T>)` 完全一致
+struct JsonArrayWriter<W: Write> {
+    out: W,
+    count: u64,
+}
+
+impl<W: Write> JsonArrayWriter<W> {
+    fn new(mut out: W) -> Result<Self, String> {
+        out.write_all(b"[").map_err(|e| format!("Failed to write file: {}", e))?;
+        Ok(Self { out, count: 0 })
+    }
+
+    fn push<T: Serialize>(&mut self, item: &T) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(item)
+            .map_err(|e| format!("Failed to serialize logs: {}", e))?;
+        let separator: &[u8] = if self.count == 0 { b"\n  " } else { b",\n  " };
+        // JSON 字符串内的换行已转义, 这里的换行只来自缩进格式
+        self.out
+            .write_all(separator)
+            .and_then(|_| self.out.write_all(json.replace('\n', "\n  ").as_bytes()))
+            .map_err(|e| format!("Failed to write file: {}", e))?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<W, String> {
+        let closing: &[u8] = if self.count == 0 { b"]" } else { b"\n]" };
+        self.out
+            .write_all(closing)
+            .map_err(|e| format!("Failed to write file: {}", e))?;
+        Ok(self.out)
+    }
+}
+
+/// 目标文件同目录下的临时文件 (保证 rename 不跨文件系统)
+fn temp_path_for(file_path: &Path, job_id: &str) -> PathBuf {
+    let name = file_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| "export.json".to_string());
+    file_path.with_file_name(format!(".{}.{}.part", name, job_id))
+}
+
+/// 把所有批次写入临时文件, 完成后重命名到目标路径; 取消或失败时删除临时文件
+fn write_batches<T, S>(
+    file_path: &Path,
+    temp_path: &Path,
+    source: S,
+    cancel: &CancellationToken,
+    mut on_batch: impl FnMut(u64),
+) -> Result<ExportState, String>
+where
+    T: Serialize,
+    S: Iterator<Item = Result<Vec<T>, String>>,
+{
+    let write = || -> Result<ExportState, String> {
+        let file = File::create(temp_path).map_err(|e| format!("Failed to create file: {}", e))?;
+        let mut writer = JsonArrayWriter::new(BufWriter::new(file))?;
+        for batch in source {
+            if cancel.is_cancelled() {
+                return Ok(ExportState::Cancelled);
+            }
+            for item in &batch? {
+                writer.push(item)?;
+            }
+            on_batch(writer.count);
+        }
+        if cancel.is_cancelled() {
+            return Ok(ExportState::Cancelled);
+        }
+        let file = writer
+            .finish()?
+            .into_inner()
+            .map_err(|e| format!("Failed to write file: {}", e))?;
+        file.sync_all().map_err(|e| format!("Failed to write file: {}", e))?;
+        Ok(ExportState::Completed)
+    };
+
+    let result = write().and_then(|state| {
+        if state == ExportState::Completed {
+            std::fs::rename(temp_path, file_path)
+                .map_err(|e| format!("Failed to move export into place: {}", e))?;
+        }
+        Ok(state)
+    });
+    if result != Ok(ExportState::Completed) {
+        let _ = std::fs::remove_file(temp_path);
+    }
+    result
+}
+
+/// 导出的日志行: 原有字段之外附带展示时区 (display_timezone) 下的请求时间
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportedLog {
+    #[serde(flatten)]
+    pub log: ProxyRequestLog,
+    pub local_time: String,
+}
+
+impl From<ProxyRequestLog> for ExportedLog {
+    fn from(log: ProxyRequestLog) -> Self {
+        let local_time = local_time(log.timestamp);
+        Self { log, local_time }
+    }
+}
+
+/// 毫秒时间戳在展示时区下的 RFC 3339 时间
+pub fn local_time(timestamp_ms: i64) -> String {
+    crate::utils::time::display_zone().rfc3339(timestamp_ms.div_euclid(1000))
+}
+
+/// 前端传入的日志对象补上 `local_time` (已有 local_time 或缺少 timestamp 时不改动)
+pub fn with_local_time(mut log: serde_json::Value) -> serde_json::Value {
+    let timestamp = log.get("timestamp").and_then(|t| t.as_i64());
+    if let (Some(obj), Some(ts)) = (log.as_object_mut(), timestamp) {
+        obj.entry("local_time").or_insert_with(|| local_time(ts).into());
+    }
+    log
+}
+
+/// 数据库中的全部日志, 按页读取 (最新的在前)
+pub fn db_source() -> impl Iterator<Item = Result<Vec<ExportedLog>, String>> + Send {
+    let mut cursor: Option<(i64, String)> = None;
+    let mut done = false;
+    std::iter::from_fn(move || {
+        if done {
+            return None;
+        }
+        let before = cursor.as_ref().map(|(ts, id)| (*ts, id.as_str()));
+        match crate::modules::proxy_db::get_logs_for_export_page(before, EXPORT_BATCH_SIZE) {
+            Ok(page) => {
+                done = page.len() < EXPORT_BATCH_SIZE;
+                let last = page.last()?;
+                cursor = Some((last.timestamp, last.id.clone()));
+                Some(Ok(page.into_iter().map(ExportedLog::from).collect()))
+            }
+            Err(e) => {
+                done = true;
+                Some(Err(e))
+            }
+        }
+    })
+}
+
+/// 已在内存中的数据, 按批切分
+pub fn chunked<T: Send>(items: Vec<T>) -> impl Iterator<Item = Result<Vec<T>, String>> + Send {
+    let mut iter = items.into_iter();
+    std::iter::from_fn(move || {
+        let batch: Vec<T> = iter.by_ref().take(EXPORT_BATCH_SIZE).collect();
+        (!batch.is_empty()).then_some(Ok(batch))
+    })
+}
+
+/// 只保留最近的若干个已结束任务
+fn prune_finished_jobs() {
+    let mut finished: Vec<(String, i64)> = JOBS
+        .iter()
+        .filter(|j| j.state != ExportState::Running)
+        .map(|j| (j.job_id.clone(), j.started_at))
+        .collect();
+    if finished.len() <= MAX_FINISHED_JOBS {
+        return;
+    }
+    finished.sort_by_key(|(_, started_at)| std::cmp::Reverse(*started_at));
+    for (job_id, _) in finished.into_iter().skip(MAX_FINISHED_JOBS) {
+        JOBS.remove(&job_id);
+    }
+}
+
+/// 启动导出任务并立即返回任务 ID; 已有任务在运行时返回错误
+pub fn start<T, S>(
+    file_path: String,
+    total: u64,
+    source: S,
+    on_progress: impl Fn(ExportProgress) + Send + 'static,
+) -> Result<String, String>
+where
+    T: Serialize,
+    S: Iterator<Item = Result<Vec<T>, String>> + Send + 'static,
+{
+    let job_id = uuid::Uuid::new_v4().simple().to_string();
+    let cancel = CancellationToken::new();
+    {
+        let mut active = ACTIVE.lock().map_err(|e| e.to_string())?;
+        if let Some(job) = active.as_ref() {
+            return Err(format!("Another log export is already running (job {})", job.job_id));
+        }
+        *active = Some(ActiveJob {
+            job_id: job_id.clone(),
+            cancel: cancel.clone(),
+        });
+    }
+
+    prune_finished_jobs();
+    JOBS.insert(
+        job_id.clone(),
+        ExportStatus {
+            job_id: job_id.clone(),
+            state: ExportState::Running,
+            rows_done: 0,
+            total,
+            file_path: file_path.clone(),
+            error: None,
+            started_at: chrono::Utc::now().timestamp(),
+            finished_at: None,
+        },
+    );
+
+    let id = job_id.clone();
+    tokio::task::spawn_blocking(move || {
+        let target = PathBuf::from(&file_path);
+        let temp_path = temp_path_for(&target, &id);
+        let result = write_batches(&target, &temp_path, source, &cancel, |rows_done| {
+            if let Some(mut status) = JOBS.get_mut(&id) {
+                status.rows_done = rows_done;
+            }
+            on_progress(ExportProgress {
+                job_id: id.clone(),
+                rows_done,
+                total,
+            });
+        });
+
+        if let Some(mut status) = JOBS.get_mut(&id) {
+            match result {
+                Ok(state) => status.state = state,
+                Err(e) => {
+                    tracing::warn!("[LogExport] Export {} failed: {}", id, e);
+                    status.state = ExportState::Failed;
+                    status.error = Some(e);
+                }
+            }
+            status.finished_at = Some(chrono::Utc::now().timestamp());
+        }
+        if let Ok(mut active) = ACTIVE.lock() {
+            if active.as_ref().is_some_and(|job| job.job_id == id) {
+                *active = None;
+            }
+        }
+    });
+
+    Ok(job_id)
+}
+
+pub fn status(job_id: &str) -> Result<ExportStatus, String> {
+    JOBS.get(job_id)
+        .map(|s| s.clone())
+        .ok_or_else(|| format!("Unknown export job: {}", job_id))
+}
+
+/// 取消正在运行的导出任务 (已写入的临时文件会被删除)
+pub fn cancel(job_id: &str) -> Result<(), String> {
+    let active = ACTIVE.lock().map_err(|e| e.to_string())?;
+    match active.as_ref() {
+        Some(job) if job.job_id == job_id => {
+            job.cancel.cancel();
+            Ok(())
+        }
+        _ => Err(format!("Export job {} is not running", job_id)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "abv_export_{}_{}",
+            name,
+            uuid::Uuid::new_v4().simple()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_streamed_output_matches_pretty_json() {
+        let many: Vec<serde_json::Value> = (0..1200)
+            .map(|i| serde_json::json!({"id": i, "body": "line\nbreak", "nested": {"k": [i]}}))
+            .collect();
+        for items in [vec![], vec![serde_json::json!({"a": 1})], many] {
+            let mut writer = JsonArrayWriter::new(Vec::new()).unwrap();
+            for item in &items {
+                writer.push(item).unwrap();
+            }
+            let out = String::from_utf8(writer.finish().unwrap()).unwrap();
+            assert_eq!(out, serde_json::to_string_pretty(&items).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_completed_export_is_renamed_into_place() {
+        let dir = temp_dir("complete");
+        let target = dir.join("logs.json");
+        let temp = temp_path_for(&target, "job");
+        let mut progress = Vec::new();
+
+        let state = write_batches(
+            &target,
+            &temp,
+            chunked((0..1200).collect::<Vec<u32>>()),
+            &CancellationToken::new(),
+            |done| progress.push(done),
+        )
+        .unwrap();
+
+        assert_eq!(state, ExportState::Completed);
+        assert_eq!(progress, vec![500, 1000, 1200]);
+        assert!(!temp.exists());
+        let written: Vec<u32> = serde_json::from_str(&std::fs::read_to_string(&target).unwrap()).unwrap();
+        assert_eq!(written.len(), 1200);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_cancelled_or_failed_export_leaves_no_file() {
+        let dir = temp_dir("abort");
+        let target = dir.join("logs.json");
+        let temp = temp_path_for(&target, "job");
+
+        let cancel = CancellationToken::new();
+        let state = write_batches(&target, &temp, chunked((0..1200).collect::<Vec<u32>>()), &cancel, |done| {
+            if done >= 500 {
+                cancel.cancel();
+            }
+        })
+        .unwrap();
+        assert_eq!(state, ExportState::Cancelled);
+
+        let failing = vec![Ok(vec![1u32]), Err("database is locked".to_string())].into_iter();
+        let err = write_batches(&target, &temp, failing, &CancellationToken::new(), |_| {}).unwrap_err();
+        assert_eq!(err, "database is locked");
+
+        assert!(!target.exists());
+        assert!(!temp.exists());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_only_one_export_runs_at_a_time() {
+        let dir = temp_dir("single");
+        let slow = std::iter::from_fn(|| {
+            std::thread::sleep(Duration::from_millis(20));
+            Some(Ok(vec![0u32]))
+        });
+        let job_id = start(dir.join("a.json").to_string_lossy().to_string(), 0, slow, |_| {}).unwrap();
+
+        let err = start(dir.join("b.json").to_string_lossy().to_string(), 0, chunked(vec![1u32]), |_| {})
+            .unwrap_err();
+        assert!(err.contains(&job_id));
+
+        cancel(&job_id).unwrap();
+        for _ in 0..100 {
+            if status(&job_id).unwrap().state != ExportState::Running {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(status(&job_id).unwrap().state, ExportState::Cancelled);
+        assert!(!dir.join("a.json").exists());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -8,13 +8,16 @@ pub mod version;
 pub mod oauth;
 pub mod oauth_server;
 pub mod migration;
+pub mod antigravity_import;
 pub mod tray;
 pub mod i18n;
 pub mod proxy_db;
+pub mod log_export;
 pub mod device;
 pub mod update_checker;
 pub mod scheduler;
 pub mod token_stats;
+pub mod stats_journal;
 pub mod cloudflared;
 pub mod integration;
 pub mod account_service;
@@ -1,4 +1,4 @@
-use crate::proxy::monitor::ProxyRequestLog;
+use crate::proxy::monitor::{ProxyRequestLog, Termination};
 use rusqlite::{params, Connection};
 use std::path::PathBuf;
 
@@ -60,6 +60,17 @@ pub fn init_db() -> Result<(), String> {
     let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN mapped_model TEXT", []);
     let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN protocol TEXT", []);
     let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN client_ip TEXT", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN raw_upstream TEXT", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN service_tier TEXT", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN upstream_response_id TEXT", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN upstream_model_version TEXT", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN provider_decision TEXT", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN raw_messages INTEGER", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN termination TEXT", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN system_injections TEXT", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN account_rotations TEXT", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN input_bytes INTEGER", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN output_bytes INTEGER", []);
 
     conn.execute(
         "CREATE INDEX IF NOT EXISTS idx_timestamp ON request_logs (timestamp DESC)",
@@ -81,8 +92,8 @@ pub fn save_log(log: &ProxyRequestLog) -> Result<(), String> {
     let conn = connect_db()?;
 
     conn.execute(
-        "INSERT INTO request_logs (id, timestamp, method, url, status, duration, model, error, request_body, response_body, input_tokens, output_tokens, account_email, mapped_model, protocol, client_ip)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
+        "INSERT INTO request_logs (id, timestamp, method, url, status, duration, model, error, request_body, response_body, input_tokens, output_tokens, account_email, mapped_model, protocol, client_ip, raw_upstream, service_tier, upstream_response_id, upstream_model_version, provider_decision, raw_messages, termination, system_injections, account_rotations, input_bytes, output_bytes)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25, ?26, ?27)",
         params![
             log.id,
             log.timestamp,
@@ -100,12 +111,43 @@ pub fn save_log(log: &ProxyRequestLog) -> Result<(), String> {
             log.mapped_model,
             log.protocol,
             log.client_ip,
+            log.raw_upstream,
+            log.service_tier,
+            log.upstream_response_id,
+            log.upstream_model_version,
+            log.provider_decision.as_ref().and_then(|d| serde_json::to_string(d).ok()),
+            log.raw_messages,
+            log.termination.as_str(),
+            log.system_injections.as_ref().and_then(|names| serde_json::to_string(names).ok()),
+            log.account_rotations.as_ref().and_then(|r| serde_json::to_string(r).ok()),
+            log.input_bytes,
+            log.output_bytes,
         ],
     ).map_err(|e| e.to_string())?;
 
     Ok(())
 }
 
+/// provider_decision 列以 JSON 文本存储
+fn parse_provider_decision(raw: Option<String>) -> Option<crate::proxy::providers::ProviderDecision> {
+    raw.and_then(|s| serde_json::from_str(&s).ok())
+}
+
+/// 系统提示注入模板名 (JSON 数组)
+fn parse_system_injections(raw: Option<String>) -> Option<Vec<String>> {
+    raw.and_then(|s| serde_json::from_str(&s).ok())
+}
+
+/// 换号记录 (JSON 数组)
+fn parse_account_rotations(raw: Option<String>) -> Option<Vec<String>> {
+    raw.and_then(|s| serde_json::from_str(&s).ok())
+}
+
+/// termination 列为空 (旧版本写入的日志) 时视为 unknown
+fn parse_termination(raw: Option<String>) -> Termination {
+    Termination::parse(raw.as_deref())
+}
+
 /// Get logs summary (without large request_body and response_body fields) with pagination
 pub fn get_logs_summary(limit: usize, offset: usize) -> Result<Vec<ProxyRequestLog>, String> {
     let conn = connect_db()?;
@@ -114,7 +156,9 @@ pub fn get_logs_summary(limit: usize, offset: usize) -> Result<Vec<ProxyRequestL
         .prepare(
             "SELECT id, timestamp, method, url, status, duration, model, error, 
                 NULL as request_body, NULL as response_body,
-                input_tokens, output_tokens, account_email, mapped_model, protocol, client_ip
+                input_tokens, output_tokens, account_email, mapped_model, protocol, client_ip,
+                provider_decision, raw_messages, termination, system_injections, account_rotations,
+                input_bytes, output_bytes
          FROM request_logs 
          ORDER BY timestamp DESC 
          LIMIT ?1 OFFSET ?2",
@@ -140,6 +184,17 @@ pub fn get_logs_summary(limit: usize, offset: usize) -> Result<Vec<ProxyRequestL
                 input_tokens: row.get(10).unwrap_or(None),
                 output_tokens: row.get(11).unwrap_or(None),
                 protocol: row.get(14).unwrap_or(None),
+                raw_upstream: None,
+                service_tier: None,
+                upstream_response_id: None,
+                upstream_model_version: None,
+                provider_decision: parse_provider_decision(row.get(16).unwrap_or(None)),
+                raw_messages: row.get(17).unwrap_or(None),
+                system_injections: parse_system_injections(row.get(19).unwrap_or(None)),
+                account_rotations: parse_account_rotations(row.get(20).unwrap_or(None)),
+                input_bytes: row.get(21).unwrap_or(None),
+                output_bytes: row.get(22).unwrap_or(None),
+                termination: parse_termination(row.get(18).unwrap_or(None)),
             })
         })
         .map_err(|e| e.to_string())?;
@@ -160,12 +215,13 @@ pub fn get_stats() -> Result<crate::proxy::monitor::ProxyStats, String> {
     let conn = connect_db()?;
 
     // Optimized: Use single query instead of three separate queries
+    // 客户端断开 (499) 不计入错误
     let (total_requests, success_count, error_count, avg_latency): (u64, u64, u64, f64) = conn
         .query_row(
             "SELECT 
             COUNT(*) as total,
             COALESCE(SUM(CASE WHEN status >= 200 AND status < 400 THEN 1 ELSE 0 END), 0) as success,
-            COALESCE(SUM(CASE WHEN status < 200 OR status >= 400 THEN 1 ELSE 0 END), 0) as error,
+            COALESCE(SUM(CASE WHEN status < 200 OR (status >= 400 AND status != 499) THEN 1 ELSE 0 END), 0) as error,
             COALESCE(AVG(duration), 0.0) as avg_latency
          FROM request_logs",
             [],
@@ -173,11 +229,27 @@ pub fn get_stats() -> Result<crate::proxy::monitor::ProxyStats, String> {
         )
         .map_err(|e| e.to_string())?;
 
+    // 按终止方式拆分; 旧日志没有 termination 列的值, 归入 unknown
+    let mut stmt = conn
+        .prepare(
+            "SELECT COALESCE(termination, 'unknown'), COUNT(*)
+         FROM request_logs
+         GROUP BY 1",
+        )
+        .map_err(|e| e.to_string())?;
+    let terminations = stmt
+        .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, u64>(1)?)))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<std::collections::HashMap<_, _>, _>>()
+        .map_err(|e| e.to_string())?;
+
     Ok(crate::proxy::monitor::ProxyStats {
         total_requests,
         success_count,
         error_count,
         avg_latency,
+        terminations,
+        ..Default::default()
     })
 }
 
@@ -189,7 +261,9 @@ pub fn get_log_detail(log_id: &str) -> Result<ProxyRequestLog, String> {
         .prepare(
             "SELECT id, timestamp, method, url, status, duration, model, error, 
                 request_body, response_body, input_tokens, output_tokens, 
-                account_email, mapped_model, protocol, client_ip
+                account_email, mapped_model, protocol, client_ip, raw_upstream, service_tier,
+                upstream_response_id, upstream_model_version, provider_decision, raw_messages, termination,
+                system_injections, account_rotations, input_bytes, output_bytes
          FROM request_logs 
          WHERE id = ?1",
         )
@@ -213,6 +287,17 @@ pub fn get_log_detail(log_id: &str) -> Result<ProxyRequestLog, String> {
             input_tokens: row.get(10).unwrap_or(None),
             output_tokens: row.get(11).unwrap_or(None),
             protocol: row.get(14).unwrap_or(None),
+            raw_upstream: row.get(16).unwrap_or(None),
+            service_tier: row.get(17).unwrap_or(None),
+            upstream_response_id: row.get(18).unwrap_or(None),
+            upstream_model_version: row.get(19).unwrap_or(None),
+            provider_decision: parse_provider_decision(row.get(20).unwrap_or(None)),
+            raw_messages: row.get(21).unwrap_or(None),
+            system_injections: parse_system_injections(row.get(23).unwrap_or(None)),
+            account_rotations: parse_account_rotations(row.get(24).unwrap_or(None)),
+            input_bytes: row.get(25).unwrap_or(None),
+            output_bytes: row.get(26).unwrap_or(None),
+            termination: parse_termination(row.get(22).unwrap_or(None)),
         })
     })
     .map_err(|e| e.to_string())
@@ -276,14 +361,14 @@ pub fn get_logs_count() -> Result<u64, String> {
 
 /// Get count of logs matching search filter
 /// filter: search text to match in url, method, model, or status
-/// errors_only: if true, only count logs with status < 200 or >= 400
+/// errors_only: if true, only count logs with status < 200 or >= 400 (客户端断开 499 除外)
 pub fn get_logs_count_filtered(filter: &str, errors_only: bool) -> Result<u64, String> {
     let conn = connect_db()?;
 
     let filter_pattern = format!("%{}%", filter);
 
     let sql = if errors_only {
-        "SELECT COUNT(*) FROM request_logs WHERE (status < 200 OR status >= 400)"
+        "SELECT COUNT(*) FROM request_logs WHERE (status < 200 OR status >= 400) AND status != 499"
     } else if filter.is_empty() {
         "SELECT COUNT(*) FROM request_logs"
     } else {
@@ -305,7 +390,7 @@ pub fn get_logs_count_filtered(filter: &str, errors_only: bool) -> Result<u64, S
 
 /// Get logs with search filter and pagination
 /// filter: search text to match in url, method, model, or status
-/// errors_only: if true, only return logs with status < 200 or >= 400
+/// errors_only: if true, only return logs with status < 200 or >= 400 (客户端断开 499 除外)
 pub fn get_logs_filtered(
     filter: &str,
     errors_only: bool,
@@ -319,22 +404,22 @@ pub fn get_logs_filtered(
     let sql = if errors_only {
         "SELECT id, timestamp, method, url, status, duration, model, error, 
                 NULL as request_body, NULL as response_body,
-                input_tokens, output_tokens, account_email, mapped_model, protocol, client_ip
+                input_tokens, output_tokens, account_email, mapped_model, protocol, client_ip, termination
          FROM request_logs 
-         WHERE (status < 200 OR status >= 400)
+         WHERE (status < 200 OR status >= 400) AND status != 499
          ORDER BY timestamp DESC 
          LIMIT ?1 OFFSET ?2"
     } else if filter.is_empty() {
         "SELECT id, timestamp, method, url, status, duration, model, error, 
                 NULL as request_body, NULL as response_body,
-                input_tokens, output_tokens, account_email, mapped_model, protocol, client_ip
+                input_tokens, output_tokens, account_email, mapped_model, protocol, client_ip, termination
          FROM request_logs 
          ORDER BY timestamp DESC 
          LIMIT ?1 OFFSET ?2"
     } else {
         "SELECT id, timestamp, method, url, status, duration, model, error, 
                 NULL as request_body, NULL as response_body,
-                input_tokens, output_tokens, account_email, mapped_model, protocol, client_ip
+                input_tokens, output_tokens, account_email, mapped_model, protocol, client_ip, termination
          FROM request_logs 
          WHERE (url LIKE ?3 OR method LIKE ?3 OR model LIKE ?3 OR CAST(status AS TEXT) LIKE ?3 OR account_email LIKE ?3 OR client_ip LIKE ?3)
          ORDER BY timestamp DESC 
@@ -362,6 +447,17 @@ pub fn get_logs_filtered(
                     input_tokens: row.get(10).unwrap_or(None),
                     output_tokens: row.get(11).unwrap_or(None),
                     protocol: row.get(14).unwrap_or(None),
+                    raw_upstream: None,
+                    service_tier: None,
+                    upstream_response_id: None,
+                    upstream_model_version: None,
+                    provider_decision: None,
+                    raw_messages: None,
+                    system_injections: None,
+                    account_rotations: None,
+                    input_bytes: None,
+                    output_bytes: None,
+                    termination: parse_termination(row.get(16).unwrap_or(None)),
                 })
             })
             .map_err(|e| e.to_string())?;
@@ -387,6 +483,17 @@ pub fn get_logs_filtered(
                     input_tokens: row.get(10).unwrap_or(None),
                     output_tokens: row.get(11).unwrap_or(None),
                     protocol: row.get(14).unwrap_or(None),
+                    raw_upstream: None,
+                    service_tier: None,
+                    upstream_response_id: None,
+                    upstream_model_version: None,
+                    provider_decision: None,
+                    raw_messages: None,
+                    system_injections: None,
+                    account_rotations: None,
+                    input_bytes: None,
+                    output_bytes: None,
+                    termination: parse_termination(row.get(16).unwrap_or(None)),
                 })
             })
             .map_err(|e| e.to_string())?;
@@ -412,6 +519,17 @@ pub fn get_logs_filtered(
                     input_tokens: row.get(10).unwrap_or(None),
                     output_tokens: row.get(11).unwrap_or(None),
                     protocol: row.get(14).unwrap_or(None),
+                    raw_upstream: None,
+                    service_tier: None,
+                    upstream_response_id: None,
+                    upstream_model_version: None,
+                    provider_decision: None,
+                    raw_messages: None,
+                    system_injections: None,
+                    account_rotations: None,
+                    input_bytes: None,
+                    output_bytes: None,
+                    termination: parse_termination(row.get(16).unwrap_or(None)),
                 })
             })
             .map_err(|e| e.to_string())?;
@@ -421,41 +539,64 @@ pub fn get_logs_filtered(
     Ok(logs)
 }
 
-/// Get all logs with full details for export
-pub fn get_all_logs_for_export() -> Result<Vec<ProxyRequestLog>, String> {
+/// Get one page of logs with full details for export (newest first)
+///
+/// `before` is the (timestamp, id) of the last row of the previous page; pages are
+/// independent queries so a long export never holds a read transaction open.
+pub fn get_logs_for_export_page(
+    before: Option<(i64, &str)>,
+    limit: usize,
+) -> Result<Vec<ProxyRequestLog>, String> {
     let conn = connect_db()?;
 
+    let (cursor_ts, cursor_id) = before.unwrap_or((i64::MAX, ""));
     let mut stmt = conn
         .prepare(
-            "SELECT id, timestamp, method, url, status, duration, model, error, 
-                request_body, response_body, input_tokens, output_tokens, 
-                account_email, mapped_model, protocol, client_ip
-         FROM request_logs 
-         ORDER BY timestamp DESC",
+            "SELECT id, timestamp, method, url, status, duration, model, error,
+                request_body, response_body, input_tokens, output_tokens,
+                account_email, mapped_model, protocol, client_ip, termination
+         FROM request_logs
+         WHERE ?1 OR timestamp < ?2 OR (timestamp = ?2 AND id < ?3)
+         ORDER BY timestamp DESC, id DESC
+         LIMIT ?4",
         )
         .map_err(|e| e.to_string())?;
 
     let logs_iter = stmt
-        .query_map([], |row| {
-            Ok(ProxyRequestLog {
-                id: row.get(0)?,
-                timestamp: row.get(1)?,
-                method: row.get(2)?,
-                url: row.get(3)?,
-                status: row.get(4)?,
-                duration: row.get(5)?,
-                model: row.get(6)?,
-                mapped_model: row.get(13).unwrap_or(None),
-                account_email: row.get(12).unwrap_or(None),
-                client_ip: row.get(15).unwrap_or(None),
-                error: row.get(7)?,
-                request_body: row.get(8).unwrap_or(None),
-                response_body: row.get(9).unwrap_or(None),
-                input_tokens: row.get(10).unwrap_or(None),
-                output_tokens: row.get(11).unwrap_or(None),
-                protocol: row.get(14).unwrap_or(None),
-            })
-        })
+        .query_map(
+            params![before.is_none(), cursor_ts, cursor_id, limit],
+            |row| {
+                Ok(ProxyRequestLog {
+                    id: row.get(0)?,
+                    timestamp: row.get(1)?,
+                    method: row.get(2)?,
+                    url: row.get(3)?,
+                    status: row.get(4)?,
+                    duration: row.get(5)?,
+                    model: row.get(6)?,
+                    mapped_model: row.get(13).unwrap_or(None),
+                    account_email: row.get(12).unwrap_or(None),
+                    client_ip: row.get(15).unwrap_or(None),
+                    error: row.get(7)?,
+                    request_body: row.get(8).unwrap_or(None),
+                    response_body: row.get(9).unwrap_or(None),
+                    input_tokens: row.get(10).unwrap_or(None),
+                    output_tokens: row.get(11).unwrap_or(None),
+                    protocol: row.get(14).unwrap_or(None),
+                    raw_upstream: None,
+                    service_tier: None,
+                    upstream_response_id: None,
+                    upstream_model_version: None,
+                    provider_decision: None,
+                    raw_messages: None,
+                    system_injections: None,
+                    account_rotations: None,
+                    input_bytes: None,
+                    output_bytes: None,
+                    termination: parse_termination(row.get(16).unwrap_or(None)),
+                })
+            },
+        )
         .map_err(|e| e.to_string())?;
 
     let mut logs = Vec::new();
@@ -483,7 +624,7 @@ pub fn get_logs_by_ids(ids: &[String]) -> Result<Vec<ProxyRequestLog>, String> {
     let sql = format!(
         "SELECT id, timestamp, method, url, status, duration, model, error, 
                 request_body, response_body, input_tokens, output_tokens, 
-                account_email, mapped_model, protocol, client_ip
+                account_email, mapped_model, protocol, client_ip, termination
          FROM request_logs 
          WHERE id IN ({})
          ORDER BY timestamp DESC",
@@ -514,6 +655,17 @@ pub fn get_logs_by_ids(ids: &[String]) -> Result<Vec<ProxyRequestLog>, String> {
                 input_tokens: row.get(10).unwrap_or(None),
                 output_tokens: row.get(11).unwrap_or(None),
                 protocol: row.get(14).unwrap_or(None),
+                raw_upstream: None,
+                service_tier: None,
+                upstream_response_id: None,
+                upstream_model_version: None,
+                provider_decision: None,
+                raw_messages: None,
+                system_injections: None,
+                account_rotations: None,
+                input_bytes: None,
+                output_bytes: None,
+                termination: parse_termination(row.get(16).unwrap_or(None)),
             })
         })
         .map_err(|e| e.to_string())?;
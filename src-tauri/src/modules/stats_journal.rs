@@ -0,0 +1,369 @@
+// Token 统计增量日志 (append-only journal)
+// 热路径只做一次内存 channel send; 后台线程把记录追加为 JSONL 并批量 fsync,
+// 定期汇总进 token_stats 数据库后截断日志。进程崩溃最多丢失最后一批未落盘的记录,
+// 启动时回放残留日志。每条记录带单调递增的 seq, 数据库记录已汇总的水位,
+// 因此 "汇总已提交但日志未截断" 时回放也不会重复计数。
+
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use crate::modules::token_stats;
+
+/// 累计多少条未落盘记录后立即 fsync
+const FSYNC_BATCH: usize = 32;
+/// 未满批次时的最长落盘间隔
+const FSYNC_INTERVAL: Duration = Duration::from_millis(500);
+/// 汇总进数据库并截断日志的间隔
+const ROLLUP_INTERVAL: Duration = Duration::from_secs(5);
+
+static SENDER: OnceLock<Sender<UsageRecord>> = OnceLock::new();
+
+/// 一条 token 用量增量
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageRecord {
+    /// 写入日志时分配, 热路径为 0
+    #[serde(default)]
+    pub seq: u64,
+    pub ts: i64,
+    pub account: String,
+    pub model: String,
+    pub input: u32,
+    pub output: u32,
+}
+
+pub fn get_journal_path() -> Result<PathBuf, String> {
+    let data_dir = crate::modules::account::get_data_dir()?;
+    Ok(data_dir.join("token_stats.journal"))
+}
+
+/// 回放残留日志并启动后台汇总线程 (需在 token_stats::init_db 之后调用)
+pub fn init() -> Result<(), String> {
+    if SENDER.get().is_some() {
+        return Ok(());
+    }
+    let writer = JournalWriter::open(get_journal_path()?, token_stats::get_db_path()?)?;
+    let (tx, rx) = mpsc::channel();
+    if SENDER.set(tx).is_err() {
+        return Ok(());
+    }
+    std::thread::Builder::new()
+        .name("stats-journal".to_string())
+        .spawn(move || run(writer, rx))
+        .map_err(|e| format!("Failed to spawn stats journal thread: {}", e))?;
+    Ok(())
+}
+
+/// 记录一次 token 用量。日志已初始化时只做 channel send, 否则直接写库
+pub fn record_usage(account_email: &str, model: &str, input_tokens: u32, output_tokens: u32) {
+    let record = UsageRecord {
+        seq: 0,
+        ts: chrono::Utc::now().timestamp(),
+        account: account_email.to_string(),
+        model: model.to_string(),
+        input: input_tokens,
+        output: output_tokens,
+    };
+    let record = match SENDER.get() {
+        Some(tx) => match tx.send(record) {
+            Ok(()) => return,
+            Err(mpsc::SendError(record)) => record,
+        },
+        None => record,
+    };
+    if let Err(e) =
+        token_stats::record_usage(&record.account, &record.model, record.input, record.output)
+    {
+        tracing::debug!("Failed to record token stats: {}", e);
+    }
+}
+
+fn run(mut writer: JournalWriter, rx: Receiver<UsageRecord>) {
+    let mut last_rollup = Instant::now();
+    loop {
+        match rx.recv_timeout(FSYNC_INTERVAL) {
+            Ok(record) => {
+                if let Err(e) = writer.append(record) {
+                    tracing::error!("[StatsJournal] Failed to append record: {}", e);
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                if let Err(e) = writer.rollup() {
+                    tracing::error!("[StatsJournal] Final rollup failed: {}", e);
+                }
+                return;
+            }
+        }
+
+        if writer.last_sync.elapsed() >= FSYNC_INTERVAL {
+            if let Err(e) = writer.sync() {
+                tracing::error!("[StatsJournal] fsync failed: {}", e);
+            }
+        }
+        if last_rollup.elapsed() >= ROLLUP_INTERVAL {
+            // 失败时保留 pending 与日志, 下个周期重试
+            if let Err(e) = writer.rollup() {
+                tracing::warn!("[StatsJournal] Rollup failed, will retry: {}", e);
+            }
+            last_rollup = Instant::now();
+        }
+    }
+}
+
+struct JournalWriter {
+    db_path: PathBuf,
+    file: File,
+    /// 已写入日志、尚未汇总进数据库的记录
+    pending: Vec<UsageRecord>,
+    unsynced: usize,
+    last_sync: Instant,
+    next_seq: u64,
+}
+
+impl JournalWriter {
+    fn open(journal_path: PathBuf, db_path: PathBuf) -> Result<Self, String> {
+        let last_seq = replay(&journal_path, &db_path)?;
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&journal_path)
+            .map_err(|e| format!("Failed to open stats journal: {}", e))?;
+        Ok(Self {
+            db_path,
+            file,
+            pending: Vec::new(),
+            unsynced: 0,
+            last_sync: Instant::now(),
+            next_seq: last_seq + 1,
+        })
+    }
+
+    fn append(&mut self, mut record: UsageRecord) -> Result<(), String> {
+        record.seq = self.next_seq;
+        let mut line = serde_json::to_string(&record).map_err(|e| e.to_string())?;
+        line.push('\n');
+        self.file
+            .write_all(line.as_bytes())
+            .map_err(|e| e.to_string())?;
+        self.next_seq += 1;
+        self.pending.push(record);
+        self.unsynced += 1;
+        if self.unsynced >= FSYNC_BATCH {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<(), String> {
+        if self.unsynced > 0 {
+            self.file.sync_data().map_err(|e| e.to_string())?;
+            self.unsynced = 0;
+        }
+        self.last_sync = Instant::now();
+        Ok(())
+    }
+
+    /// 汇总 pending 进数据库, 成功后截断日志
+    fn rollup(&mut self) -> Result<(), String> {
+        self.sync()?;
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        apply_records(&self.db_path, &self.pending)?;
+        self.pending.clear();
+        self.file.set_len(0).map_err(|e| e.to_string())?;
+        self.file.sync_data().map_err(|e| e.to_string())?;
+        Ok(())
+    }
+}
+
+/// 在一个事务内写入水位之后的记录并推进水位, 返回新水位
+fn apply_records(db_path: &Path, records: &[UsageRecord]) -> Result<u64, String> {
+    let mut conn = token_stats::connect_db_at(db_path)?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let last_seq = tx
+        .query_row("SELECT last_seq FROM journal_state WHERE id = 0", [], |row| {
+            row.get::<_, i64>(0)
+        })
+        .optional()
+        .map_err(|e| e.to_string())?
+        .unwrap_or(0) as u64;
+
+    let mut max_seq = last_seq;
+    for record in records.iter().filter(|r| r.seq > last_seq) {
+        token_stats::insert_usage(
+            &tx,
+            record.ts,
+            &record.account,
+            &record.model,
+            record.input,
+            record.output,
+        )?;
+        max_seq = max_seq.max(record.seq);
+    }
+
+    tx.execute(
+        "INSERT INTO journal_state (id, last_seq) VALUES (0, ?1)
+         ON CONFLICT(id) DO UPDATE SET last_seq = ?1",
+        [max_seq as i64],
+    )
+    .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(max_seq)
+}
+
+/// 回放日志中尚未汇总的记录并截断日志, 返回已汇总的最大 seq
+fn replay(journal_path: &Path, db_path: &Path) -> Result<u64, String> {
+    let mut records = Vec::new();
+    if journal_path.exists() {
+        let file = File::open(journal_path).map_err(|e| e.to_string())?;
+        for line in BufReader::new(file).lines() {
+            let line = line.map_err(|e| e.to_string())?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            // 崩溃时最后一行可能只写了一半
+            match serde_json::from_str::<UsageRecord>(&line) {
+                Ok(record) => records.push(record),
+                Err(e) => tracing::warn!("[StatsJournal] Skipping torn journal line: {}", e),
+            }
+        }
+    }
+
+    let last_seq = apply_records(db_path, &records)?;
+    if !records.is_empty() {
+        tracing::info!(
+            "[StatsJournal] Replayed {} journal record(s) into token stats",
+            records.len()
+        );
+    }
+    if journal_path.exists() {
+        File::create(journal_path)
+            .and_then(|f| f.sync_all())
+            .map_err(|e| format!("Failed to truncate stats journal: {}", e))?;
+    }
+    Ok(last_seq)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_paths(name: &str) -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!(
+            "abv_journal_{}_{}",
+            name,
+            uuid::Uuid::new_v4().simple()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let db_path = dir.join("token_stats.db");
+        token_stats::init_db_at(&db_path).unwrap();
+        (dir.join("token_stats.journal"), db_path)
+    }
+
+    fn record(i: u32) -> UsageRecord {
+        UsageRecord {
+            seq: 0,
+            ts: 1_700_000_000 + (i as i64) * 600,
+            account: format!("user{}@example.com", i % 3),
+            model: "gemini-3-flash".to_string(),
+            input: 100 + i,
+            output: 10 + i * 2,
+        }
+    }
+
+    fn totals(db_path: &Path) -> (i64, i64, i64, i64, i64) {
+        let conn = token_stats::connect_db_at(db_path).unwrap();
+        let (raw_in, raw_out, raw_count) = conn
+            .query_row(
+                "SELECT COALESCE(SUM(input_tokens), 0), COALESCE(SUM(output_tokens), 0), COUNT(*) FROM token_usage",
+                [],
+                |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+            )
+            .unwrap();
+        let (hourly_tokens, hourly_count) = conn
+            .query_row(
+                "SELECT COALESCE(SUM(total_tokens), 0), COALESCE(SUM(request_count), 0) FROM token_stats_hourly",
+                [],
+                |r| Ok((r.get(0)?, r.get(1)?)),
+            )
+            .unwrap();
+        (raw_in, raw_out, raw_count, hourly_tokens, hourly_count)
+    }
+
+    fn expected(n: u32) -> (i64, i64, i64, i64, i64) {
+        let input: i64 = (0..n).map(|i| record(i).input as i64).sum();
+        let output: i64 = (0..n).map(|i| record(i).output as i64).sum();
+        (input, output, n as i64, input + output, n as i64)
+    }
+
+    #[test]
+    fn test_replay_after_dropped_aggregator_reconstructs_exact_totals() {
+        let (journal, db) = temp_paths("crash");
+
+        let mut writer = JournalWriter::open(journal.clone(), db.clone()).unwrap();
+        for i in 0..40 {
+            writer.append(record(i)).unwrap();
+        }
+        writer.rollup().unwrap();
+        for i in 40..100 {
+            writer.append(record(i)).unwrap();
+        }
+        // 模拟崩溃: 不汇总直接丢弃
+        drop(writer);
+        assert_eq!(totals(&db), expected(40));
+
+        let last_seq = replay(&journal, &db).unwrap();
+        assert_eq!(last_seq, 100);
+        assert_eq!(totals(&db), expected(100));
+        assert_eq!(std::fs::metadata(&journal).unwrap().len(), 0);
+
+        // 重新打开后 seq 继续递增
+        let mut writer = JournalWriter::open(journal.clone(), db.clone()).unwrap();
+        writer.append(record(100)).unwrap();
+        writer.rollup().unwrap();
+        assert_eq!(totals(&db), expected(101));
+    }
+
+    #[test]
+    fn test_replay_does_not_double_count_rolled_up_records() {
+        let (journal, db) = temp_paths("committed");
+
+        let mut writer = JournalWriter::open(journal.clone(), db.clone()).unwrap();
+        for i in 0..10 {
+            writer.append(record(i)).unwrap();
+        }
+        // 汇总事务已提交, 但截断前崩溃
+        apply_records(&db, &writer.pending).unwrap();
+        for i in 10..15 {
+            writer.append(record(i)).unwrap();
+        }
+        drop(writer);
+
+        replay(&journal, &db).unwrap();
+        assert_eq!(totals(&db), expected(15));
+    }
+
+    #[test]
+    fn test_replay_skips_torn_last_line() {
+        let (journal, db) = temp_paths("torn");
+
+        let mut writer = JournalWriter::open(journal.clone(), db.clone()).unwrap();
+        for i in 0..5 {
+            writer.append(record(i)).unwrap();
+        }
+        drop(writer);
+        let mut file = OpenOptions::new().append(true).open(&journal).unwrap();
+        file.write_all(b"{\"seq\":6,\"ts\":17000").unwrap();
+        drop(file);
+
+        replay(&journal, &db).unwrap();
+        assert_eq!(totals(&db), expected(5));
+    }
+}
@@ -1,6 +1,8 @@
 use rusqlite::{params, Connection};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+use crate::utils::time::{self, Zone};
 
 /// Aggregated token statistics
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -61,7 +63,10 @@ pub(crate) fn get_db_path() -> Result<PathBuf, String> {
 }
 
 fn connect_db() -> Result<Connection, String> {
-    let db_path = get_db_path()?;
+    connect_db_at(&get_db_path()?)
+}
+
+pub(crate) fn connect_db_at(db_path: &Path) -> Result<Connection, String> {
     let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
 
     // Enable WAL mode for better concurrency
@@ -77,7 +82,11 @@ fn connect_db() -> Result<Connection, String> {
 
 /// Initialize the token stats database
 pub fn init_db() -> Result<(), String> {
-    let conn = connect_db()?;
+    init_db_at(&get_db_path()?)
+}
+
+pub(crate) fn init_db_at(db_path: &Path) -> Result<(), String> {
+    let conn = connect_db_at(db_path)?;
 
     // Create main usage table
     conn.execute(
@@ -122,6 +131,16 @@ pub fn init_db() -> Result<(), String> {
     )
     .map_err(|e| e.to_string())?;
 
+    // 统计日志回放水位 (见 stats_journal), 单行表
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS journal_state (
+            id INTEGER PRIMARY KEY CHECK (id = 0),
+            last_seq INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )
+    .map_err(|e| e.to_string())?;
+
     Ok(())
 }
 
@@ -133,7 +152,25 @@ pub fn record_usage(
     output_tokens: u32,
 ) -> Result<(), String> {
     let conn = connect_db()?;
-    let timestamp = chrono::Utc::now().timestamp();
+    insert_usage(
+        &conn,
+        chrono::Utc::now().timestamp(),
+        account_email,
+        model,
+        input_tokens,
+        output_tokens,
+    )
+}
+
+/// Write one usage row and fold it into the hourly bucket of `timestamp`
+pub(crate) fn insert_usage(
+    conn: &Connection,
+    timestamp: i64,
+    account_email: &str,
+    model: &str,
+    input_tokens: u32,
+    output_tokens: u32,
+) -> Result<(), String> {
     let total_tokens = input_tokens + output_tokens;
 
     // Insert into raw usage table
@@ -143,7 +180,10 @@ pub fn record_usage(
         params![timestamp, account_email, model, input_tokens, output_tokens, total_tokens],
     ).map_err(|e| e.to_string())?;
 
-    let hour_bucket = chrono::Utc::now().format("%Y-%m-%d %H:00").to_string();
+    let hour_bucket = chrono::DateTime::from_timestamp(timestamp, 0)
+        .unwrap_or_else(chrono::Utc::now)
+        .format("%Y-%m-%d %H:00")
+        .to_string();
     conn.execute(
         "INSERT INTO token_stats_hourly (hour_bucket, account_email, total_input_tokens, total_output_tokens, total_tokens, request_count)
          VALUES (?1, ?2, ?3, ?4, ?5, 1)
@@ -158,121 +198,129 @@ pub fn record_usage(
     Ok(())
 }
 
-/// Get hourly aggregated stats for a time range
-pub fn get_hourly_stats(hours: i64) -> Result<Vec<TokenStatsAggregated>, String> {
-    let conn = connect_db()?;
-    let cutoff = chrono::Utc::now() - chrono::Duration::hours(hours);
-    let cutoff_bucket = cutoff.format("%Y-%m-%d %H:00").to_string();
+/// 聚合粒度 (秒): 所有时区偏移都是 15 分钟的整数倍, 同一槽位内的记录必然落在同一个本地小时
+const SLOT_SECS: i64 = 900;
 
+/// 按 15 分钟槽位读取用量, 再在 Rust 中按展示时区的 `label` 合并成周期
+fn aggregate_by_period(
+    conn: &Connection,
+    cutoff: i64,
+    label: impl Fn(i64) -> String,
+) -> Result<Vec<TokenStatsAggregated>, String> {
     let mut stmt = conn
         .prepare(
-            "SELECT hour_bucket, 
-                SUM(total_input_tokens) as input, 
-                SUM(total_output_tokens) as output,
+            "SELECT timestamp / ?2 as slot,
+                SUM(input_tokens) as input,
+                SUM(output_tokens) as output,
                 SUM(total_tokens) as total,
-                SUM(request_count) as count
-         FROM token_stats_hourly 
-         WHERE hour_bucket >= ?1
-         GROUP BY hour_bucket
-         ORDER BY hour_bucket ASC",
+                COUNT(*) as count
+         FROM token_usage
+         WHERE timestamp >= ?1
+         GROUP BY slot",
         )
         .map_err(|e| e.to_string())?;
 
     let rows = stmt
-        .query_map([cutoff_bucket], |row| {
-            Ok(TokenStatsAggregated {
-                period: row.get(0)?,
-                total_input_tokens: row.get(1)?,
-                total_output_tokens: row.get(2)?,
-                total_tokens: row.get(3)?,
-                request_count: row.get(4)?,
-            })
+        .query_map(params![cutoff, SLOT_SECS], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, u64>(1)?,
+                row.get::<_, u64>(2)?,
+                row.get::<_, u64>(3)?,
+                row.get::<_, u64>(4)?,
+            ))
         })
         .map_err(|e| e.to_string())?;
 
-    let mut result = Vec::new();
+    let mut periods: std::collections::BTreeMap<String, TokenStatsAggregated> =
+        std::collections::BTreeMap::new();
     for row in rows {
-        result.push(row.map_err(|e| e.to_string())?);
+        let (slot, input, output, total, count) = row.map_err(|e| e.to_string())?;
+        let period = label(slot * SLOT_SECS);
+        let entry = periods.entry(period.clone()).or_insert_with(|| TokenStatsAggregated {
+            period,
+            total_input_tokens: 0,
+            total_output_tokens: 0,
+            total_tokens: 0,
+            request_count: 0,
+        });
+        entry.total_input_tokens += input;
+        entry.total_output_tokens += output;
+        entry.total_tokens += total;
+        entry.request_count += count;
     }
-    Ok(result)
+    Ok(periods.into_values().collect())
 }
 
-/// Get daily aggregated stats for a time range
-pub fn get_daily_stats(days: i64) -> Result<Vec<TokenStatsAggregated>, String> {
-    let conn = connect_db()?;
-    let cutoff = chrono::Utc::now() - chrono::Duration::days(days);
-    let cutoff_bucket = cutoff.format("%Y-%m-%d").to_string();
-
+/// 按展示时区的周期与 `key_column` (model / account_email) 合并用量
+fn trend_by_period(
+    conn: &Connection,
+    cutoff: i64,
+    key_column: &str,
+    label: impl Fn(i64) -> String,
+) -> Result<std::collections::BTreeMap<String, std::collections::HashMap<String, u64>>, String> {
     let mut stmt = conn
-        .prepare(
-            "SELECT substr(hour_bucket, 1, 10) as day_bucket, 
-                SUM(total_input_tokens) as input, 
-                SUM(total_output_tokens) as output,
-                SUM(total_tokens) as total,
-                SUM(request_count) as count
-         FROM token_stats_hourly 
-         WHERE substr(hour_bucket, 1, 10) >= ?1
-         GROUP BY day_bucket
-         ORDER BY day_bucket ASC",
-        )
+        .prepare(&format!(
+            "SELECT timestamp / ?2 as slot,
+                {key} as series,
+                SUM(total_tokens) as total
+         FROM token_usage
+         WHERE timestamp >= ?1
+         GROUP BY slot, {key}",
+            key = key_column
+        ))
         .map_err(|e| e.to_string())?;
 
+    let mut trend_map: std::collections::BTreeMap<String, std::collections::HashMap<String, u64>> =
+        std::collections::BTreeMap::new();
+
     let rows = stmt
-        .query_map([cutoff_bucket], |row| {
-            Ok(TokenStatsAggregated {
-                period: row.get(0)?,
-                total_input_tokens: row.get(1)?,
-                total_output_tokens: row.get(2)?,
-                total_tokens: row.get(3)?,
-                request_count: row.get(4)?,
-            })
+        .query_map(params![cutoff, SLOT_SECS], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, u64>(2)?,
+            ))
         })
         .map_err(|e| e.to_string())?;
 
-    let mut result = Vec::new();
     for row in rows {
-        result.push(row.map_err(|e| e.to_string())?);
+        let (slot, series, total) = row.map_err(|e| e.to_string())?;
+        *trend_map
+            .entry(label(slot * SLOT_SECS))
+            .or_default()
+            .entry(series)
+            .or_default() += total;
     }
-    Ok(result)
+    Ok(trend_map)
 }
 
-/// Get weekly aggregated stats
-pub fn get_weekly_stats(weeks: i64) -> Result<Vec<TokenStatsAggregated>, String> {
-    let conn = connect_db()?;
-    let cutoff = chrono::Utc::now() - chrono::Duration::weeks(weeks);
-    let cutoff_timestamp = cutoff.timestamp();
+/// 展示时区中 `days` 天前那一天的零点
+fn daily_cutoff(zone: Zone, days: i64) -> i64 {
+    let now = chrono::Utc::now().timestamp();
+    zone.day_start(zone.date_at(now - days * 24 * 3600))
+}
 
-    let mut stmt = conn
-        .prepare(
-            "SELECT strftime('%Y-W%W', datetime(timestamp, 'unixepoch')) as week_bucket,
-                SUM(input_tokens) as input, 
-                SUM(output_tokens) as output,
-                SUM(total_tokens) as total,
-                COUNT(*) as count
-         FROM token_usage 
-         WHERE timestamp >= ?1
-         GROUP BY week_bucket
-         ORDER BY week_bucket ASC",
-        )
-        .map_err(|e| e.to_string())?;
+/// Get hourly aggregated stats for a time range
+pub fn get_hourly_stats(hours: i64) -> Result<Vec<TokenStatsAggregated>, String> {
+    let conn = connect_db()?;
+    let zone = time::display_zone();
+    let cutoff = chrono::Utc::now().timestamp() - hours * 3600;
+    aggregate_by_period(&conn, cutoff, |ts| zone.hour_label(ts))
+}
 
-    let rows = stmt
-        .query_map([cutoff_timestamp], |row| {
-            Ok(TokenStatsAggregated {
-                period: row.get(0)?,
-                total_input_tokens: row.get(1)?,
-                total_output_tokens: row.get(2)?,
-                total_tokens: row.get(3)?,
-                request_count: row.get(4)?,
-            })
-        })
-        .map_err(|e| e.to_string())?;
+/// Get daily aggregated stats for a time range
+pub fn get_daily_stats(days: i64) -> Result<Vec<TokenStatsAggregated>, String> {
+    let conn = connect_db()?;
+    let zone = time::display_zone();
+    aggregate_by_period(&conn, daily_cutoff(zone, days), |ts| zone.day_label(ts))
+}
 
-    let mut result = Vec::new();
-    for row in rows {
-        result.push(row.map_err(|e| e.to_string())?);
-    }
-    Ok(result)
+/// Get weekly aggregated stats
+pub fn get_weekly_stats(weeks: i64) -> Result<Vec<TokenStatsAggregated>, String> {
+    let conn = connect_db()?;
+    let zone = time::display_zone();
+    aggregate_by_period(&conn, daily_cutoff(zone, weeks * 7), |ts| zone.week_label(ts))
 }
 
 /// Get per-account statistics for a time range
@@ -389,37 +437,9 @@ pub fn get_model_stats(hours: i64) -> Result<Vec<ModelTokenStats>, String> {
 
 pub fn get_model_trend_hourly(hours: i64) -> Result<Vec<ModelTrendPoint>, String> {
     let conn = connect_db()?;
+    let zone = time::display_zone();
     let cutoff = chrono::Utc::now().timestamp() - (hours * 3600);
-
-    let mut stmt = conn
-        .prepare(
-            "SELECT strftime('%Y-%m-%d %H:00', datetime(timestamp, 'unixepoch')) as hour_bucket,
-                model,
-                SUM(total_tokens) as total
-         FROM token_usage
-         WHERE timestamp >= ?1
-         GROUP BY hour_bucket, model
-         ORDER BY hour_bucket ASC",
-        )
-        .map_err(|e| e.to_string())?;
-
-    let mut trend_map: std::collections::BTreeMap<String, std::collections::HashMap<String, u64>> =
-        std::collections::BTreeMap::new();
-
-    let rows = stmt
-        .query_map([cutoff], |row| {
-            Ok((
-                row.get::<_, String>(0)?,
-                row.get::<_, String>(1)?,
-                row.get::<_, u64>(2)?,
-            ))
-        })
-        .map_err(|e| e.to_string())?;
-
-    for row in rows {
-        let (period, model, total) = row.map_err(|e| e.to_string())?;
-        trend_map.entry(period).or_default().insert(model, total);
-    }
+    let trend_map = trend_by_period(&conn, cutoff, "model", |ts| zone.hour_label(ts))?;
 
     Ok(trend_map
         .into_iter()
@@ -429,37 +449,8 @@ pub fn get_model_trend_hourly(hours: i64) -> Result<Vec<ModelTrendPoint>, String
 
 pub fn get_model_trend_daily(days: i64) -> Result<Vec<ModelTrendPoint>, String> {
     let conn = connect_db()?;
-    let cutoff = chrono::Utc::now().timestamp() - (days * 24 * 3600);
-
-    let mut stmt = conn
-        .prepare(
-            "SELECT strftime('%Y-%m-%d', datetime(timestamp, 'unixepoch')) as day_bucket,
-                model,
-                SUM(total_tokens) as total
-         FROM token_usage
-         WHERE timestamp >= ?1
-         GROUP BY day_bucket, model
-         ORDER BY day_bucket ASC",
-        )
-        .map_err(|e| e.to_string())?;
-
-    let mut trend_map: std::collections::BTreeMap<String, std::collections::HashMap<String, u64>> =
-        std::collections::BTreeMap::new();
-
-    let rows = stmt
-        .query_map([cutoff], |row| {
-            Ok((
-                row.get::<_, String>(0)?,
-                row.get::<_, String>(1)?,
-                row.get::<_, u64>(2)?,
-            ))
-        })
-        .map_err(|e| e.to_string())?;
-
-    for row in rows {
-        let (period, model, total) = row.map_err(|e| e.to_string())?;
-        trend_map.entry(period).or_default().insert(model, total);
-    }
+    let zone = time::display_zone();
+    let trend_map = trend_by_period(&conn, daily_cutoff(zone, days), "model", |ts| zone.day_label(ts))?;
 
     Ok(trend_map
         .into_iter()
@@ -469,37 +460,9 @@ pub fn get_model_trend_daily(days: i64) -> Result<Vec<ModelTrendPoint>, String>
 
 pub fn get_account_trend_hourly(hours: i64) -> Result<Vec<AccountTrendPoint>, String> {
     let conn = connect_db()?;
+    let zone = time::display_zone();
     let cutoff = chrono::Utc::now().timestamp() - (hours * 3600);
-
-    let mut stmt = conn
-        .prepare(
-            "SELECT strftime('%Y-%m-%d %H:00', datetime(timestamp, 'unixepoch')) as hour_bucket,
-                account_email,
-                SUM(total_tokens) as total
-         FROM token_usage
-         WHERE timestamp >= ?1
-         GROUP BY hour_bucket, account_email
-         ORDER BY hour_bucket ASC",
-        )
-        .map_err(|e| e.to_string())?;
-
-    let mut trend_map: std::collections::BTreeMap<String, std::collections::HashMap<String, u64>> =
-        std::collections::BTreeMap::new();
-
-    let rows = stmt
-        .query_map([cutoff], |row| {
-            Ok((
-                row.get::<_, String>(0)?,
-                row.get::<_, String>(1)?,
-                row.get::<_, u64>(2)?,
-            ))
-        })
-        .map_err(|e| e.to_string())?;
-
-    for row in rows {
-        let (period, account, total) = row.map_err(|e| e.to_string())?;
-        trend_map.entry(period).or_default().insert(account, total);
-    }
+    let trend_map = trend_by_period(&conn, cutoff, "account_email", |ts| zone.hour_label(ts))?;
 
     Ok(trend_map
         .into_iter()
@@ -512,37 +475,10 @@ pub fn get_account_trend_hourly(hours: i64) -> Result<Vec<AccountTrendPoint>, St
 
 pub fn get_account_trend_daily(days: i64) -> Result<Vec<AccountTrendPoint>, String> {
     let conn = connect_db()?;
-    let cutoff = chrono::Utc::now().timestamp() - (days * 24 * 3600);
-
-    let mut stmt = conn
-        .prepare(
-            "SELECT strftime('%Y-%m-%d', datetime(timestamp, 'unixepoch')) as day_bucket,
-                account_email,
-                SUM(total_tokens) as total
-         FROM token_usage
-         WHERE timestamp >= ?1
-         GROUP BY day_bucket, account_email
-         ORDER BY day_bucket ASC",
-        )
-        .map_err(|e| e.to_string())?;
-
-    let mut trend_map: std::collections::BTreeMap<String, std::collections::HashMap<String, u64>> =
-        std::collections::BTreeMap::new();
-
-    let rows = stmt
-        .query_map([cutoff], |row| {
-            Ok((
-                row.get::<_, String>(0)?,
-                row.get::<_, String>(1)?,
-                row.get::<_, u64>(2)?,
-            ))
-        })
-        .map_err(|e| e.to_string())?;
-
-    for row in rows {
-        let (period, account, total) = row.map_err(|e| e.to_string())?;
-        trend_map.entry(period).or_default().insert(account, total);
-    }
+    let zone = time::display_zone();
+    let trend_map = trend_by_period(&conn, daily_cutoff(zone, days), "account_email", |ts| {
+        zone.day_label(ts)
+    })?;
 
     Ok(trend_map
         .into_iter()
@@ -563,4 +499,40 @@ mod tests {
         // For now, just verify the module compiles
         assert!(true);
     }
+
+    #[test]
+    fn test_buckets_follow_display_zone_across_fall_back() {
+        let dir = std::env::temp_dir().join(format!(
+            "abv_token_stats_{}",
+            uuid::Uuid::new_v4().simple()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let db_path = dir.join("token_stats.db");
+        init_db_at(&db_path).unwrap();
+        let conn = connect_db_at(&db_path).unwrap();
+
+        // 洛杉矶 2026-11-01: 07:30 UTC = 00:30 PDT; 08:30 / 09:30 UTC 都是本地 01:30 (PDT / PST)
+        let zone = Zone::parse("America/Los_Angeles").unwrap();
+        let first = 1_793_521_800; // 2026-11-01 08:30:00 UTC
+        for (ts, model) in [(first - 3600, "a"), (first, "a"), (first + 3600, "b"), (first + 3600, "a")] {
+            insert_usage(&conn, ts, "user@example.com", model, 10, 5).unwrap();
+        }
+
+        let hourly = aggregate_by_period(&conn, 0, |ts| zone.hour_label(ts)).unwrap();
+        let periods: Vec<&str> = hourly.iter().map(|p| p.period.as_str()).collect();
+        assert_eq!(
+            periods,
+            vec!["2026-11-01 00:00", "2026-11-01 01:00", "2026-11-01 01:00 -08:00"]
+        );
+        assert_eq!(hourly[2].request_count, 2);
+
+        let daily = aggregate_by_period(&conn, 0, |ts| zone.day_label(ts)).unwrap();
+        assert_eq!(daily.len(), 1);
+        assert_eq!(daily[0].total_tokens, 60);
+
+        let trend = trend_by_period(&conn, 0, "model", |ts| zone.hour_label(ts)).unwrap();
+        assert_eq!(trend["2026-11-01 01:00 -08:00"]["b"], 15);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
@@ -32,6 +32,61 @@ impl AudioProcessor {
         const MAX_SIZE: usize = 15 * 1024 * 1024; // 15MB
         size_bytes > MAX_SIZE
     }
+
+    /// 解析 PCM 音频的采样率 (如 Gemini 的 `audio/L16;codec=pcm;rate=24000`), 非 PCM 返回 None
+    pub fn pcm_sample_rate(mime_type: &str) -> Option<u32> {
+        let mut params = mime_type.split(';').map(str::trim);
+        let base = params.next()?.to_ascii_lowercase();
+        if base != "audio/l16" && base != "audio/pcm" {
+            return None;
+        }
+        let rate = params.find_map(|param| {
+            let (key, value) = param.split_once('=')?;
+            if key.trim().eq_ignore_ascii_case("rate") {
+                value.trim().parse().ok()
+            } else {
+                None
+            }
+        });
+        Some(rate.unwrap_or(24_000))
+    }
+
+    /// 将 16-bit 小端 PCM 封装为 WAV 容器
+    pub fn pcm_to_wav(pcm: &[u8], sample_rate: u32, channels: u16) -> Vec<u8> {
+        let mut wav = Self::wav_header(sample_rate, channels, pcm.len() as u32);
+        wav.reserve(pcm.len());
+        wav.extend_from_slice(pcm);
+        wav
+    }
+
+    /// 流式输出用的 WAV 头: 总长度未知, RIFF 与 data 长度按惯例写为 0xFFFFFFFF
+    pub fn wav_stream_header(sample_rate: u32, channels: u16) -> Vec<u8> {
+        let mut header = Self::wav_header(sample_rate, channels, 0);
+        header[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        header[40..44].copy_from_slice(&u32::MAX.to_le_bytes());
+        header
+    }
+
+    fn wav_header(sample_rate: u32, channels: u16, data_len: u32) -> Vec<u8> {
+        const BITS_PER_SAMPLE: u16 = 16;
+        let block_align = channels * BITS_PER_SAMPLE / 8;
+        let byte_rate = sample_rate * block_align as u32;
+
+        let mut wav = Vec::with_capacity(44);
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        wav.extend_from_slice(&channels.to_le_bytes());
+        wav.extend_from_slice(&sample_rate.to_le_bytes());
+        wav.extend_from_slice(&byte_rate.to_le_bytes());
+        wav.extend_from_slice(&block_align.to_le_bytes());
+        wav.extend_from_slice(&BITS_PER_SAMPLE.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&data_len.to_le_bytes());
+        wav
+    }
 }
 
 #[cfg(test)]
@@ -59,6 +114,34 @@ mod tests {
         assert!(!AudioProcessor::exceeds_size_limit(15 * 1024 * 1024)); // 刚好等于限制
     }
 
+    #[test]
+    fn test_pcm_sample_rate() {
+        assert_eq!(AudioProcessor::pcm_sample_rate("audio/L16;codec=pcm;rate=16000"), Some(16000));
+        assert_eq!(AudioProcessor::pcm_sample_rate("audio/pcm"), Some(24000));
+        assert_eq!(AudioProcessor::pcm_sample_rate("audio/wav"), None);
+    }
+
+    #[test]
+    fn test_pcm_to_wav_header() {
+        let wav = AudioProcessor::pcm_to_wav(&[1, 0, 2, 0], 24000, 1);
+        assert_eq!(wav.len(), 48);
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(wav[4..8].try_into().unwrap()), 40);
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(u32::from_le_bytes(wav[24..28].try_into().unwrap()), 24000);
+        assert_eq!(u32::from_le_bytes(wav[28..32].try_into().unwrap()), 48000);
+        assert_eq!(&wav[36..40], b"data");
+        assert_eq!(u32::from_le_bytes(wav[40..44].try_into().unwrap()), 4);
+        assert_eq!(&wav[44..], &[1, 0, 2, 0]);
+
+        let header = AudioProcessor::wav_stream_header(24000, 1);
+        assert_eq!(header.len(), 44);
+        assert_eq!(&header[..4], b"RIFF");
+        assert_eq!(&header[4..8], &[0xFF; 4]);
+        assert_eq!(&header[8..40], &wav[8..40]);
+        assert_eq!(&header[40..44], &[0xFF; 4]);
+    }
+
     #[test]
     fn test_base64_encoding() {
         let data = b"test audio data";
@@ -0,0 +1,771 @@
+// 进程内反代基准测试 (诊断功能)
+// 通过 mock 上游 + 真实 handler/mapper 链路, 测量单实例可承载的并发会话数
+
+use axum::body::Body as AxumBody;
+use axum::extract::{Json, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use futures::{Stream, StreamExt};
+use pin_project::pin_project;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::sync::{RwLock, Semaphore};
+
+use crate::proxy::server::AppState;
+use crate::proxy::token_manager::ProxyToken;
+use crate::proxy::upstream::client::UpstreamClient;
+use crate::proxy::upstream::transport::{UpstreamCall, UpstreamTransport};
+use crate::proxy::TokenManager;
+
+/// 基准测试参数 (所有字段均有默认值, 前端可只传需要修改的部分)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchmarkConfig {
+    /// 同时在途的请求数 (模拟并发会话)
+    #[serde(default = "default_concurrency")]
+    pub concurrency: usize,
+    /// 每种协议发送的请求总数
+    #[serde(default = "default_requests_per_protocol")]
+    pub requests_per_protocol: usize,
+    /// 参与测试的协议
+    #[serde(default = "default_protocols")]
+    pub protocols: Vec<BenchmarkProtocol>,
+    /// 每个请求的历史消息条数
+    #[serde(default = "default_message_count")]
+    pub message_count: usize,
+    /// 每条历史消息的字符数
+    #[serde(default = "default_message_chars")]
+    pub message_chars: usize,
+    /// mock 上游每个响应的 SSE chunk 数
+    #[serde(default = "default_chunk_count")]
+    pub chunk_count: usize,
+    /// 每个 chunk 的文本字符数
+    #[serde(default = "default_chunk_chars")]
+    pub chunk_chars: usize,
+    /// chunk 之间的间隔 (毫秒), 0 表示尽快输出
+    #[serde(default = "default_chunk_interval_ms")]
+    pub chunk_interval_ms: u64,
+    /// 合成账号数量
+    #[serde(default = "default_accounts")]
+    pub accounts: usize,
+}
+
+fn default_concurrency() -> usize {
+    16
+}
+
+fn default_requests_per_protocol() -> usize {
+    64
+}
+
+fn default_protocols() -> Vec<BenchmarkProtocol> {
+    vec![BenchmarkProtocol::Claude, BenchmarkProtocol::Openai]
+}
+
+fn default_message_count() -> usize {
+    8
+}
+
+fn default_message_chars() -> usize {
+    2000
+}
+
+fn default_chunk_count() -> usize {
+    40
+}
+
+fn default_chunk_chars() -> usize {
+    64
+}
+
+fn default_chunk_interval_ms() -> u64 {
+    5
+}
+
+fn default_accounts() -> usize {
+    4
+}
+
+impl Default for BenchmarkConfig {
+    fn default() -> Self {
+        Self {
+            concurrency: default_concurrency(),
+            requests_per_protocol: default_requests_per_protocol(),
+            protocols: default_protocols(),
+            message_count: default_message_count(),
+            message_chars: default_message_chars(),
+            chunk_count: default_chunk_count(),
+            chunk_chars: default_chunk_chars(),
+            chunk_interval_ms: default_chunk_interval_ms(),
+            accounts: default_accounts(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BenchmarkProtocol {
+    Claude,
+    Openai,
+}
+
+impl BenchmarkProtocol {
+    fn as_str(&self) -> &'static str {
+        match self {
+            BenchmarkProtocol::Claude => "claude",
+            BenchmarkProtocol::Openai => "openai",
+        }
+    }
+}
+
+/// 单个协议的测试结果
+///
+/// transform/stream 时间统计的是 handler future 与响应 body stream 的 poll 耗时,
+/// mock 上游的 chunk 间隔等待不计入, 因此近似于 mapper 实际消耗的 CPU 时间
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProtocolBenchmarkReport {
+    pub protocol: BenchmarkProtocol,
+    pub requests: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub throughput_rps: f64,
+    pub latency_p50_ms: f64,
+    pub latency_p99_ms: f64,
+    pub latency_max_ms: f64,
+    /// 请求转换阶段 (handler 入口 → 返回响应头) 的总 poll 耗时
+    pub transform_cpu_ms: f64,
+    /// 流式转换阶段 (消费响应 body) 的总 poll 耗时
+    pub stream_cpu_ms: f64,
+    pub transform_cpu_per_request_ms: f64,
+    pub stream_cpu_per_request_ms: f64,
+    pub first_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchmarkReport {
+    pub config: BenchmarkConfig,
+    pub total_requests: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub wall_time_ms: f64,
+    pub throughput_rps: f64,
+    pub baseline_memory_bytes: u64,
+    pub peak_memory_bytes: u64,
+    pub protocols: Vec<ProtocolBenchmarkReport>,
+    /// 便于在 UI 中直接打印的文本摘要
+    pub summary: String,
+}
+
+// ===== Mock 上游 =====
+
+/// 进程内 mock 上游: 按配置的速率回放预生成的 v1internal SSE 记录
+pub struct MockUpstream {
+    chunks: Arc<Vec<Bytes>>,
+    full_text: String,
+    chunk_interval: Duration,
+    calls: AtomicUsize,
+}
+
+impl MockUpstream {
+    pub fn new(config: &BenchmarkConfig) -> Self {
+        let chunk_text = "lorem ipsum dolor sit amet "
+            .chars()
+            .cycle()
+            .take(config.chunk_chars.max(1))
+            .collect::<String>();
+        let chunk_count = config.chunk_count.max(1);
+
+        let mut chunks = Vec::with_capacity(chunk_count);
+        for i in 0..chunk_count {
+            let is_last = i + 1 == chunk_count;
+            let mut candidate = json!({
+                "content": { "role": "model", "parts": [{ "text": chunk_text }] },
+                "index": 0
+            });
+            let mut response = json!({
+                "modelVersion": "gemini-2.5-flash",
+                "responseId": "benchmark"
+            });
+            if is_last {
+                candidate["finishReason"] = json!("STOP");
+                response["usageMetadata"] = Self::usage(config, chunk_count);
+            }
+            response["candidates"] = json!([candidate]);
+            let line = format!("data: {}\n\n", json!({ "response": response }));
+            chunks.push(Bytes::from(line));
+        }
+
+        Self {
+            chunks: Arc::new(chunks),
+            full_text: chunk_text.repeat(chunk_count),
+            chunk_interval: Duration::from_millis(config.chunk_interval_ms),
+            calls: AtomicUsize::new(0),
+        }
+    }
+
+    /// 上游收到的调用次数
+    pub fn call_count(&self) -> usize {
+        self.calls.load(Ordering::Relaxed)
+    }
+
+    fn usage(config: &BenchmarkConfig, chunk_count: usize) -> Value {
+        let prompt = (config.message_count * config.message_chars / 4) as u64;
+        let completion = (chunk_count * config.chunk_chars / 4) as u64;
+        json!({
+            "promptTokenCount": prompt,
+            "candidatesTokenCount": completion,
+            "totalTokenCount": prompt + completion
+        })
+    }
+
+    fn sse_response(&self) -> Result<reqwest::Response, String> {
+        let chunks = self.chunks.clone();
+        let interval = self.chunk_interval;
+        let stream = async_stream::stream! {
+            for (i, chunk) in chunks.iter().enumerate() {
+                if i > 0 && !interval.is_zero() {
+                    tokio::time::sleep(interval).await;
+                }
+                yield Ok::<Bytes, std::io::Error>(chunk.clone());
+            }
+        };
+
+        axum::http::Response::builder()
+            .status(200)
+            .header("content-type", "text/event-stream")
+            .body(reqwest::Body::wrap_stream(stream))
+            .map(reqwest::Response::from)
+            .map_err(|e| format!("Failed to build mock response: {}", e))
+    }
+
+    fn json_response(&self) -> Result<reqwest::Response, String> {
+        let body = json!({
+            "response": {
+                "candidates": [{
+                    "content": { "role": "model", "parts": [{ "text": self.full_text }] },
+                    "finishReason": "STOP",
+                    "index": 0
+                }],
+                "usageMetadata": { "promptTokenCount": 0, "candidatesTokenCount": 0, "totalTokenCount": 0 },
+                "modelVersion": "gemini-2.5-flash"
+            }
+        });
+
+        axum::http::Response::builder()
+            .status(200)
+            .header("content-type", "application/json")
+            .body(reqwest::Body::from(body.to_string()))
+            .map(reqwest::Response::from)
+            .map_err(|e| format!("Failed to build mock response: {}", e))
+    }
+}
+
+impl UpstreamTransport for MockUpstream {
+    fn send(&self, call: UpstreamCall) -> BoxFuture<'_, Result<reqwest::Response, String>> {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        Box::pin(async move {
+            if call.method == "streamGenerateContent" {
+                self.sse_response()
+            } else {
+                self.json_response()
+            }
+        })
+    }
+}
+
+// ===== Poll 计时 =====
+
+/// 统计内部 Future / Stream 在 poll 中实际花费的时间 (不含 Pending 等待)
+#[pin_project]
+struct PollTimed<T> {
+    #[pin]
+    inner: T,
+    spent_ns: Arc<AtomicU64>,
+}
+
+impl<T> PollTimed<T> {
+    fn new(inner: T, spent_ns: Arc<AtomicU64>) -> Self {
+        Self { inner, spent_ns }
+    }
+}
+
+impl<T: Future> Future for PollTimed<T> {
+    type Output = T::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let started = Instant::now();
+        let result = this.inner.poll(cx);
+        this.spent_ns
+            .fetch_add(started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        result
+    }
+}
+
+impl<T: Stream> Stream for PollTimed<T> {
+    type Item = T::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let started = Instant::now();
+        let result = this.inner.poll_next(cx);
+        this.spent_ns
+            .fetch_add(started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        result
+    }
+}
+
+// ===== 合成请求 =====
+
+fn synthetic_history(config: &BenchmarkConfig, index: usize) -> Vec<(&'static str, String)> {
+    let filler = "The quick brown fox jumps over the lazy dog. "
+        .chars()
+        .cycle()
+        .take(config.message_chars)
+        .collect::<String>();
+    let count = config.message_count.max(1);
+    // 保证最后一条是 user 消息
+    let first_is_user = count % 2 == 1;
+
+    (0..count)
+        .map(|i| {
+            let role = if (i % 2 == 0) == first_is_user { "user" } else { "assistant" };
+            let text = if i == 0 {
+                format!("[benchmark session {}] {}", index, filler)
+            } else {
+                filler.clone()
+            };
+            (role, text)
+        })
+        .collect()
+}
+
+fn claude_request(config: &BenchmarkConfig, index: usize) -> Value {
+    let messages: Vec<Value> = synthetic_history(config, index)
+        .into_iter()
+        .map(|(role, text)| json!({ "role": role, "content": [{ "type": "text", "text": text }] }))
+        .collect();
+
+    json!({
+        "model": "claude-sonnet-4-5",
+        "max_tokens": 4096,
+        "stream": true,
+        "system": "You are a helpful coding assistant.",
+        "messages": messages
+    })
+}
+
+fn openai_request(config: &BenchmarkConfig, index: usize) -> Value {
+    let mut messages = vec![json!({ "role": "system", "content": "You are a helpful coding assistant." })];
+    messages.extend(
+        synthetic_history(config, index)
+            .into_iter()
+            .map(|(role, text)| json!({ "role": role, "content": text })),
+    );
+
+    json!({
+        "model": "gpt-4o",
+        "stream": true,
+        "messages": messages
+    })
+}
+
+// ===== 运行 =====
+
+fn synthetic_token_manager(accounts: usize, data_dir: &Path) -> Arc<TokenManager> {
+    let manager = TokenManager::new(data_dir.to_path_buf());
+    let now = chrono::Utc::now().timestamp();
+
+    for i in 0..accounts.max(1) {
+        let account_id = format!("benchmark-{}", i);
+        manager.tokens.insert(
+            account_id.clone(),
+            ProxyToken {
+                account_id: account_id.clone(),
+                access_token: format!("benchmark-token-{}", i),
+                refresh_token: String::new(),
+                expires_in: 86400,
+                timestamp: now + 86400,
+                email: format!("benchmark-{}@example.invalid", i),
+                account_path: data_dir.join(format!("{}.json", account_id)),
+                project_id: Some("benchmark-project".to_string()),
+                subscription_tier: Some("ULTRA".to_string()),
+                remaining_quota: None,
+                protected_models: Default::default(),
+                health_score: 1.0,
+                model_quotas: Default::default(),
+                verification_needed: false,
+                verification_url: None,
+                reset_time: None,
+                validation_blocked: false,
+                validation_blocked_until: 0,
+                is_forbidden: false,
+                project_setup: None,
+                priority: 0,
+            },
+        );
+    }
+
+    Arc::new(manager)
+}
+
+/// 合成账号的 AppState, 账号文件路径指向 `data_dir` (调用方持有临时目录的 guard)
+pub(crate) fn benchmark_state(upstream: Arc<MockUpstream>, accounts: usize, data_dir: &Path) -> AppState {
+    let proxy_config = crate::proxy::ProxyConfig::default();
+    let integration = crate::modules::integration::SystemManager::Headless;
+
+    AppState {
+        token_manager: synthetic_token_manager(accounts, data_dir),
+        custom_mapping: Arc::new(RwLock::new(Default::default())),
+        request_timeout: 300,
+        thought_signature_map: Arc::new(tokio::sync::Mutex::new(Default::default())),
+        upstream_proxy: Arc::new(RwLock::new(Default::default())),
+        upstream: Arc::new(UpstreamClient::with_transport(upstream)),
+        zai: Arc::new(RwLock::new(Default::default())),
+        providers: Arc::new(RwLock::new(Vec::new())),
+        provider_rr: Arc::new(AtomicUsize::new(0)),
+        zai_vision_mcp: Arc::new(crate::proxy::zai_vision_mcp::ZaiVisionMcpState::new()),
+        monitor: Arc::new(crate::proxy::monitor::ProxyMonitor::new(0, None)),
+        experimental: Arc::new(RwLock::new(proxy_config.experimental.clone())),
+        debug_logging: Arc::new(RwLock::new(Default::default())),
+        switching: Arc::new(RwLock::new(false)),
+        integration: integration.clone(),
+        account_service: Arc::new(crate::modules::account_service::AccountService::new(
+            integration,
+        )),
+        security: Arc::new(RwLock::new(
+            crate::proxy::ProxySecurityConfig::from_proxy_config(&proxy_config),
+        )),
+        cloudflared_state: Arc::new(crate::commands::cloudflared::CloudflaredState::new()),
+        is_running: Arc::new(RwLock::new(true)),
+        port: 0,
+        listeners: Arc::new(crate::proxy::server::listeners::ListenerRegistry::new(
+            Arc::new(RwLock::new(Default::default())),
+        )),
+    }
+}
+
+struct RequestOutcome {
+    latency: Duration,
+    error: Option<String>,
+}
+
+async fn dispatch(state: AppState, protocol: BenchmarkProtocol, body: Value) -> Response {
+    match protocol {
+        BenchmarkProtocol::Claude => {
+            crate::proxy::handlers::claude::handle_messages(State(state), HeaderMap::new(), None, Json(body))
+                .await
+        }
+        BenchmarkProtocol::Openai => {
+            match crate::proxy::handlers::openai::handle_chat_completions(
+                State(state),
+                HeaderMap::new(),
+                Json(body),
+            )
+                .await
+            {
+                Ok(resp) => resp.into_response(),
+                Err(err) => err.into_response(),
+            }
+        }
+    }
+}
+
+async fn run_one(
+    state: AppState,
+    protocol: BenchmarkProtocol,
+    body: Value,
+    transform_ns: Arc<AtomicU64>,
+    stream_ns: Arc<AtomicU64>,
+) -> RequestOutcome {
+    let started = Instant::now();
+    let response = PollTimed::new(dispatch(state, protocol, body), transform_ns).await;
+    let status = response.status();
+
+    let mut error = (status != StatusCode::OK).then(|| format!("HTTP {}", status));
+    let mut body = PollTimed::new(AxumBody::into_data_stream(response.into_body()), stream_ns);
+    while let Some(chunk) = body.next().await {
+        if let Err(e) = chunk {
+            error.get_or_insert_with(|| format!("Body error: {}", e));
+            break;
+        }
+    }
+
+    RequestOutcome {
+        latency: started.elapsed(),
+        error,
+    }
+}
+
+fn percentile_ms(sorted: &[Duration], pct: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = ((pct / 100.0) * sorted.len() as f64).ceil() as usize;
+    let idx = rank.clamp(1, sorted.len()) - 1;
+    sorted[idx].as_secs_f64() * 1000.0
+}
+
+fn current_memory_bytes() -> u64 {
+    let Ok(pid) = sysinfo::get_current_pid() else {
+        return 0;
+    };
+    let mut system = sysinfo::System::new();
+    system.refresh_processes_specifics(
+        sysinfo::ProcessesToUpdate::Some(&[pid]),
+        sysinfo::ProcessRefreshKind::new().with_memory(),
+    );
+    system.process(pid).map(|p| p.memory()).unwrap_or(0)
+}
+
+async fn run_protocol(
+    state: &AppState,
+    config: &BenchmarkConfig,
+    protocol: BenchmarkProtocol,
+) -> ProtocolBenchmarkReport {
+    let semaphore = Arc::new(Semaphore::new(config.concurrency.max(1)));
+    let transform_ns = Arc::new(AtomicU64::new(0));
+    let stream_ns = Arc::new(AtomicU64::new(0));
+    let started = Instant::now();
+
+    let mut tasks = Vec::with_capacity(config.requests_per_protocol);
+    for index in 0..config.requests_per_protocol {
+        let body = match protocol {
+            BenchmarkProtocol::Claude => claude_request(config, index),
+            BenchmarkProtocol::Openai => openai_request(config, index),
+        };
+        let state = state.clone();
+        let semaphore = semaphore.clone();
+        let transform_ns = transform_ns.clone();
+        let stream_ns = stream_ns.clone();
+        tasks.push(tokio::spawn(async move {
+            let _permit = semaphore.acquire_owned().await.ok();
+            run_one(state, protocol, body, transform_ns, stream_ns).await
+        }));
+    }
+
+    let mut latencies = Vec::with_capacity(tasks.len());
+    let mut failed = 0;
+    let mut first_error = None;
+    for task in tasks {
+        match task.await {
+            Ok(outcome) => {
+                latencies.push(outcome.latency);
+                if let Some(err) = outcome.error {
+                    failed += 1;
+                    first_error.get_or_insert(err);
+                }
+            }
+            Err(e) => {
+                failed += 1;
+                first_error.get_or_insert(format!("Task panicked: {}", e));
+            }
+        }
+    }
+    latencies.sort();
+
+    let elapsed = started.elapsed().as_secs_f64();
+    let requests = config.requests_per_protocol;
+    let transform_cpu_ms = transform_ns.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+    let stream_cpu_ms = stream_ns.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+    let per_request = |total: f64| if requests == 0 { 0.0 } else { total / requests as f64 };
+
+    ProtocolBenchmarkReport {
+        protocol,
+        requests,
+        succeeded: requests - failed,
+        failed,
+        throughput_rps: if elapsed > 0.0 { requests as f64 / elapsed } else { 0.0 },
+        latency_p50_ms: percentile_ms(&latencies, 50.0),
+        latency_p99_ms: percentile_ms(&latencies, 99.0),
+        latency_max_ms: latencies.last().map(|d| d.as_secs_f64() * 1000.0).unwrap_or(0.0),
+        transform_cpu_ms,
+        stream_cpu_ms,
+        transform_cpu_per_request_ms: per_request(transform_cpu_ms),
+        stream_cpu_per_request_ms: per_request(stream_cpu_ms),
+        first_error,
+    }
+}
+
+fn render_summary(report: &BenchmarkReport) -> String {
+    let mut lines = vec![
+        format!(
+            "Proxy benchmark: {} requests ({} failed) in {:.0} ms, {:.1} req/s, concurrency {}",
+            report.total_requests,
+            report.failed,
+            report.wall_time_ms,
+            report.throughput_rps,
+            report.config.concurrency
+        ),
+        format!(
+            "Memory: baseline {:.1} MiB, peak {:.1} MiB",
+            report.baseline_memory_bytes as f64 / 1048576.0,
+            report.peak_memory_bytes as f64 / 1048576.0
+        ),
+    ];
+    for p in &report.protocols {
+        lines.push(format!(
+            "[{}] {:.1} req/s | p50 {:.1} ms | p99 {:.1} ms | transform {:.2} ms/req | stream {:.2} ms/req | failed {}",
+            p.protocol.as_str(),
+            p.throughput_rps,
+            p.latency_p50_ms,
+            p.latency_p99_ms,
+            p.transform_cpu_per_request_ms,
+            p.stream_cpu_per_request_ms,
+            p.failed
+        ));
+        if let Some(err) = &p.first_error {
+            lines.push(format!("[{}] first error: {}", p.protocol.as_str(), err));
+        }
+    }
+    lines.join("\n")
+}
+
+/// 运行基准测试: 按协议依次压测, 期间采样进程内存峰值
+pub async fn run_benchmark(config: BenchmarkConfig) -> Result<BenchmarkReport, String> {
+    if config.requests_per_protocol == 0 || config.protocols.is_empty() {
+        return Err("Benchmark needs at least one protocol and one request".to_string());
+    }
+
+    // 临时数据目录随 guard 删除
+    let data_dir = tempfile::Builder::new()
+        .prefix("abv_benchmark_")
+        .tempdir()
+        .map_err(|e| format!("Failed to create benchmark data dir: {}", e))?;
+    let upstream = Arc::new(MockUpstream::new(&config));
+    let state = benchmark_state(upstream.clone(), config.accounts, data_dir.path());
+
+    let baseline_memory_bytes = current_memory_bytes();
+    let peak_memory = Arc::new(AtomicU64::new(baseline_memory_bytes));
+    let sampler = {
+        let peak_memory = peak_memory.clone();
+        tokio::spawn(async move {
+            loop {
+                peak_memory.fetch_max(current_memory_bytes(), Ordering::Relaxed);
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+    };
+
+    let started = Instant::now();
+    let mut protocols = Vec::with_capacity(config.protocols.len());
+    for protocol in &config.protocols {
+        protocols.push(run_protocol(&state, &config, *protocol).await);
+    }
+    let wall_time = started.elapsed().as_secs_f64();
+
+    sampler.abort();
+    peak_memory.fetch_max(current_memory_bytes(), Ordering::Relaxed);
+
+    // 合成账号会在全局选择状态 (健康信号 / 吞吐 / 限速槽) 中留下记录, 结束后移除,
+    // 避免影响真实账号的调度
+    let synthetic_ids: Vec<String> = state
+        .token_manager
+        .tokens
+        .iter()
+        .map(|entry| entry.key().clone())
+        .collect();
+    state.token_manager.remove_accounts(&synthetic_ids);
+
+    let total_requests: usize = protocols.iter().map(|p| p.requests).sum();
+    let failed: usize = protocols.iter().map(|p| p.failed).sum();
+    tracing::info!(
+        "[Benchmark] {} requests finished, {} upstream calls served by mock",
+        total_requests,
+        upstream.call_count()
+    );
+
+    let mut report = BenchmarkReport {
+        config,
+        total_requests,
+        succeeded: total_requests - failed,
+        failed,
+        wall_time_ms: wall_time * 1000.0,
+        throughput_rps: if wall_time > 0.0 { total_requests as f64 / wall_time } else { 0.0 },
+        baseline_memory_bytes,
+        peak_memory_bytes: peak_memory.load(Ordering::Relaxed),
+        protocols,
+        summary: String::new(),
+    };
+    report.summary = render_summary(&report);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentile_ms() {
+        let samples: Vec<Duration> = (1..=100).map(Duration::from_millis).collect();
+        assert_eq!(percentile_ms(&samples, 50.0), 50.0);
+        assert_eq!(percentile_ms(&samples, 99.0), 99.0);
+        assert_eq!(percentile_ms(&[], 99.0), 0.0);
+    }
+
+    #[test]
+    fn test_synthetic_history_ends_with_user() {
+        for count in 1..6 {
+            let config = BenchmarkConfig {
+                message_count: count,
+                message_chars: 16,
+                ..Default::default()
+            };
+            let history = synthetic_history(&config, 0);
+            assert_eq!(history.len(), count);
+            assert_eq!(history.last().unwrap().0, "user");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mock_upstream_replays_sse_transcript() {
+        let config = BenchmarkConfig {
+            chunk_count: 3,
+            chunk_chars: 8,
+            chunk_interval_ms: 0,
+            ..Default::default()
+        };
+        let upstream = MockUpstream::new(&config);
+        let resp = upstream
+            .send(UpstreamCall {
+                method: "streamGenerateContent".to_string(),
+                access_token: String::new(),
+                body: json!({}),
+                query_string: Some("alt=sse".to_string()),
+                extra_headers: Default::default(),
+            })
+            .await
+            .unwrap();
+
+        let text = resp.text().await.unwrap();
+        let events: Vec<&str> = text.split("\n\n").filter(|s| !s.is_empty()).collect();
+        assert_eq!(events.len(), 3);
+        assert!(events[2].contains("\"finishReason\":\"STOP\""));
+        assert_eq!(upstream.call_count(), 1);
+    }
+
+    // 会初始化真实数据目录下的 proxy DB / 配置文件, 默认不运行
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore = "touches the app data dir"]
+    async fn test_benchmark_runs_through_real_handlers() {
+        let config = BenchmarkConfig {
+            requests_per_protocol: 8,
+            concurrency: 4,
+            chunk_count: 5,
+            ..Default::default()
+        };
+        let report = run_benchmark(config).await.unwrap();
+
+        assert_eq!(report.total_requests, 16);
+        assert_eq!(report.failed, 0, "{}", report.summary);
+        assert!(report.protocols.iter().all(|p| p.transform_cpu_ms > 0.0));
+    }
+}
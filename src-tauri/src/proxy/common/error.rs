@@ -36,6 +36,11 @@ impl IntoResponse for ProxyError {
             }
         });
 
-        (status, Json(body)).into_response()
+        let response = (status, Json(body)).into_response();
+        if matches!(self, ProxyError::TransformError(_)) {
+            crate::proxy::handlers::common::mark_local_failure(response)
+        } else {
+            response
+        }
     }
 }
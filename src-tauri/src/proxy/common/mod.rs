@@ -8,3 +8,9 @@ pub mod json_schema;
 pub mod tool_adapter;
 pub mod tool_adapters;
 pub mod schema_cache;
+pub mod redact;
+pub mod thinking_capability;
+pub mod post_process;
+pub mod project_setup;
+pub mod thinking_defaults;
+pub mod system_injection;
@@ -152,6 +152,49 @@ pub async fn get_all_dynamic_models(
     sorted_ids
 }
 
+/// 后台任务 (标题生成 / 摘要等) 使用的虚拟模型 ID
+const VIRTUAL_MODELS: &[&str] = &[crate::proxy::handlers::claude::INTERNAL_BACKGROUND_TASK];
+
+/// 模型列表中的一项
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListedModel {
+    pub id: String,
+    /// 自定义映射别名与虚拟模型实际路由到的物理模型
+    pub mapped_to: Option<String>,
+}
+
+/// 模型 ID 实际路由到的物理模型 (与 resolve_model_route 相同规则, 不记录日志)
+pub fn physical_model(model: &str, custom_mapping: &HashMap<String, String>) -> String {
+    match match_custom_mapping(model, custom_mapping) {
+        Some((_, target)) => target.to_string(),
+        None => map_claude_model_to_gemini(model),
+    }
+}
+
+/// `/v1/models` 列出的模型: 内置列表、自定义映射别名与虚拟模型 (按 ID 去重)
+///
+/// 通配规则不是可请求的模型名, 不列出; 别名与内置名称重复时只保留一项并标注其映射目标
+pub async fn list_models_with_routes(
+    custom_mapping: &tokio::sync::RwLock<HashMap<String, String>>,
+) -> Vec<ListedModel> {
+    let model_ids = get_all_dynamic_models(custom_mapping).await;
+    let mapping = custom_mapping.read().await;
+    model_ids
+        .into_iter()
+        .filter(|id| !id.contains('*'))
+        .map(|id| {
+            let mapped_to = if let Some(target) = mapping.get(&id) {
+                Some(target.clone())
+            } else if VIRTUAL_MODELS.contains(&id.as_str()) {
+                Some(physical_model(&id, &mapping))
+            } else {
+                None
+            };
+            ListedModel { id, mapped_to }
+        })
+        .collect()
+}
+
 /// Wildcard matching - supports multiple wildcards
 ///
 /// **Note**: Matching is **case-sensitive**. Pattern `GPT-4*` will NOT match `gpt-4-turbo`.
@@ -198,25 +241,17 @@ fn wildcard_match(pattern: &str, text: &str) -> bool {
     true
 }
 
-/// 核心模型路由解析引擎
-/// 优先级：精确匹配 > 通配符匹配 > 系统默认映射
-/// 
-/// # 参数
-/// - `original_model`: 原始模型名称
-/// - `custom_mapping`: 用户自定义映射表
-/// 
-/// # 返回
-/// 映射后的目标模型名称
-pub fn resolve_model_route(
+/// 自定义映射中命中的规则 (pattern, target)
+/// 优先级：精确匹配 > 通配符匹配 (非通配字符最多者胜出); 未命中返回 None
+pub fn match_custom_mapping<'a>(
     original_model: &str,
-    custom_mapping: &std::collections::HashMap<String, String>,
-) -> String {
+    custom_mapping: &'a std::collections::HashMap<String, String>,
+) -> Option<(&'a str, &'a str)> {
     // 1. 精确匹配 (最高优先级)
-    if let Some(target) = custom_mapping.get(original_model) {
-        crate::modules::logger::log_info(&format!("[Router] 精确映射: {} -> {}", original_model, target));
-        return target.clone();
+    if let Some((pattern, target)) = custom_mapping.get_key_value(original_model) {
+        return Some((pattern.as_str(), target.as_str()));
     }
-    
+
     // 2. Wildcard match - most specific (highest non-wildcard chars) wins
     // Note: When multiple patterns have the SAME specificity, HashMap iteration order
     // determines the result (non-deterministic). Users can avoid this by making patterns
@@ -236,11 +271,61 @@ pub fn resolve_model_route(
         }
     }
 
-    if let Some((pattern, target, _)) = best_match {
-        crate::modules::logger::log_info(&format!(
-            "[Router] Wildcard match: {} -> {} (rule: {})",
-            original_model, target, pattern
-        ));
+    best_match.map(|(pattern, target, _)| (pattern, target))
+}
+
+/// 自定义映射的更新互斥: 内存替换、持久化与派生映射的刷新作为一个整体串行执行,
+/// 避免两个更新交错导致内存与磁盘上的映射不一致
+static MAPPING_UPDATE_LOCK: Lazy<tokio::sync::Mutex<()>> = Lazy::new(|| tokio::sync::Mutex::new(()));
+
+/// 映射更新的互斥守卫; 调用方在刷新派生映射 (各端口的叠加映射) 后再释放
+pub type MappingUpdateGuard = tokio::sync::MutexGuard<'static, ()>;
+
+/// 热更新自定义映射
+/// 新映射在锁外完整构建, 一次写锁内整体替换, 读者只会看到完整的旧映射或新映射;
+/// 替换后再持久化, 持久化失败时回滚为旧映射并返回错误。
+/// 成功时返回仍持有的更新守卫, 以免并发更新在派生映射刷新前插入, 使各端口停留在旧映射上。
+pub async fn swap_custom_mapping<F>(
+    mapping: &tokio::sync::RwLock<HashMap<String, String>>,
+    new_mapping: HashMap<String, String>,
+    persist: F,
+) -> Result<MappingUpdateGuard, String>
+where
+    F: FnOnce(&HashMap<String, String>) -> Result<(), String>,
+{
+    let guard = MAPPING_UPDATE_LOCK.lock().await;
+    let previous = std::mem::replace(&mut *mapping.write().await, new_mapping.clone());
+
+    if let Err(e) = persist(&new_mapping) {
+        *mapping.write().await = previous;
+        tracing::error!("[Router] Failed to persist model mapping, rolled back: {}", e);
+        return Err(e);
+    }
+    Ok(guard)
+}
+
+/// 核心模型路由解析引擎
+/// 优先级：精确匹配 > 通配符匹配 > 系统默认映射
+/// 
+/// # 参数
+/// - `original_model`: 原始模型名称
+/// - `custom_mapping`: 用户自定义映射表
+/// 
+/// # 返回
+/// 映射后的目标模型名称
+pub fn resolve_model_route(
+    original_model: &str,
+    custom_mapping: &std::collections::HashMap<String, String>,
+) -> String {
+    if let Some((pattern, target)) = match_custom_mapping(original_model, custom_mapping) {
+        if pattern == original_model {
+            crate::modules::logger::log_info(&format!("[Router] 精确映射: {} -> {}", original_model, target));
+        } else {
+            crate::modules::logger::log_info(&format!(
+                "[Router] Wildcard match: {} -> {} (rule: {})",
+                original_model, target, pattern
+            ));
+        }
         return target.to_string();
     }
     
@@ -286,6 +371,25 @@ pub fn normalize_to_standard_id(model_name: &str) -> Option<String> {
 mod tests {
     use super::*;
 
+    #[tokio::test]
+    async fn test_list_models_includes_aliases_and_virtual_models() {
+        let mapping = tokio::sync::RwLock::new(HashMap::from([
+            ("my-alias".to_string(), "gemini-3-flash".to_string()),
+            ("claude-sonnet-4-5".to_string(), "gemini-3-pro-high".to_string()),
+            ("gpt-4*".to_string(), "gemini-3-flash".to_string()),
+        ]));
+        let models = list_models_with_routes(&mapping).await;
+        let find = |id: &str| models.iter().filter(|m| m.id == id).collect::<Vec<_>>();
+
+        assert_eq!(find("my-alias")[0].mapped_to.as_deref(), Some("gemini-3-flash"));
+        let overridden = find("claude-sonnet-4-5");
+        assert_eq!(overridden.len(), 1);
+        assert_eq!(overridden[0].mapped_to.as_deref(), Some("gemini-3-pro-high"));
+        assert_eq!(find("internal-background-task")[0].mapped_to.as_deref(), Some("gemini-2.5-flash"));
+        assert!(find("gpt-4*").is_empty());
+        assert!(find("gemini-3-flash")[0].mapped_to.is_none());
+    }
+
     #[test]
     fn test_model_mapping() {
         assert_eq!(
@@ -365,4 +469,57 @@ mod tests {
         // Multi-wildcard: "a*b*c" (3)
         assert_eq!(resolve_model_route("a-test-b-foo-c", &custom), "multi-wild");
     }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_mapping_swap_is_atomic_for_readers() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+
+        fn mapping_version(version: usize) -> HashMap<String, String> {
+            let mut m = HashMap::new();
+            m.insert("*".to_string(), "gemini-3-flash".to_string());
+            for i in 0..20 {
+                m.insert(format!("model-{}-v{}", i, version), format!("target-{}", version));
+            }
+            m
+        }
+
+        let mapping = Arc::new(tokio::sync::RwLock::new(mapping_version(0)));
+        let done = Arc::new(AtomicBool::new(false));
+
+        let reader = {
+            let mapping = mapping.clone();
+            let done = done.clone();
+            tokio::spawn(async move {
+                let mut resolved = 0u64;
+                while !done.load(Ordering::Relaxed) {
+                    let guard = mapping.read().await;
+                    let (_, target) = match_custom_mapping("claude-opus-4", &guard)
+                        .expect("default route missing during mapping update");
+                    assert_eq!(target, "gemini-3-flash");
+                    drop(guard);
+                    resolved += 1;
+                    tokio::task::yield_now().await;
+                }
+                resolved
+            })
+        };
+
+        for version in 1..=1000 {
+            drop(
+                swap_custom_mapping(&mapping, mapping_version(version), |_| Ok(()))
+                    .await
+                    .unwrap(),
+            );
+            tokio::task::yield_now().await;
+        }
+        done.store(true, Ordering::Relaxed);
+        assert!(reader.await.unwrap() > 0);
+        assert!(mapping.read().await.contains_key("model-0-v1000"));
+
+        // 持久化失败: 回滚为旧映射
+        let err = swap_custom_mapping(&mapping, HashMap::new(), |_| Err("disk full".to_string())).await;
+        assert_eq!(err.unwrap_err(), "disk full");
+        assert!(mapping.read().await.contains_key("*"));
+    }
 }
@@ -0,0 +1,439 @@
+// 助手文本后处理
+// 按顺序应用查找替换规则 (字面量或正则), 并裁剪 text 块末尾的停止短语; 不处理 thinking 与工具参数。
+// 流式响应中匹配可能跨越分片: StreamTextFilter 保留不少于最长模式长度的尾部缓冲, 块结束时再输出。
+
+use crate::proxy::config::{PostProcessConfig, ReplaceRule};
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+/// 正则无法预知匹配长度, 流式时按该窗口 (字符数) 缓冲; 更长的匹配跨分片时可能漏掉
+const REGEX_WINDOW_CHARS: usize = 256;
+
+struct Registry {
+    global: Option<Arc<TextPostProcessor>>,
+    /// 模型名 (小写) -> 全局规则 + 该模型规则
+    per_model: HashMap<String, Arc<TextPostProcessor>>,
+}
+
+/// 当前生效的规则 (None = 关闭)
+static REGISTRY: Lazy<RwLock<Option<Registry>>> = Lazy::new(|| RwLock::new(None));
+/// 规则名 -> 命中次数 (启动以来)
+static HITS: Lazy<DashMap<String, u64>> = Lazy::new(DashMap::new);
+
+impl Registry {
+    /// 按配置编译规则; 关闭时返回 None
+    fn build(config: &PostProcessConfig) -> Option<Self> {
+        config.enabled.then(|| {
+            let global_rules = compile_rules(&config.global.rules);
+            let per_model = config
+                .per_model
+                .iter()
+                .filter_map(|(model, profile)| {
+                    let mut rules = global_rules.clone();
+                    rules.extend(compile_rules(&profile.rules));
+                    let mut stop_phrases = config.global.stop_phrases.clone();
+                    stop_phrases.extend(profile.stop_phrases.iter().cloned());
+                    TextPostProcessor::new(rules, &stop_phrases).map(|p| (model.to_lowercase(), Arc::new(p)))
+                })
+                .collect();
+            Registry {
+                global: TextPostProcessor::new(global_rules, &config.global.stop_phrases).map(Arc::new),
+                per_model,
+            }
+        })
+    }
+
+    fn lookup(&self, models: &[&str]) -> Option<Arc<TextPostProcessor>> {
+        models
+            .iter()
+            .find_map(|m| self.per_model.get(&m.to_lowercase()).cloned())
+            .or_else(|| self.global.clone())
+    }
+}
+
+/// 应用配置 (服务启动与热更新时调用)
+pub fn set_config(config: &PostProcessConfig) {
+    let registry = Registry::build(config);
+    *REGISTRY.write().unwrap_or_else(|e| e.into_inner()) = registry;
+}
+
+/// 按模型名 (依次尝试, 如请求模型名与映射后的模型名) 取生效的处理器; 关闭或无规则时返回 None
+pub fn for_model(models: &[&str]) -> Option<Arc<TextPostProcessor>> {
+    REGISTRY
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()?
+        .lookup(models)
+}
+
+/// 各规则的命中次数 (停止短语以 `stop:` 前缀区分)
+pub fn hit_counts() -> HashMap<String, u64> {
+    HITS.iter().map(|e| (e.key().clone(), *e.value())).collect()
+}
+
+fn record_hit(label: &str, count: usize) {
+    if count > 0 {
+        *HITS.entry(label.to_string()).or_insert(0) += count as u64;
+    }
+}
+
+fn compile_rules(rules: &[ReplaceRule]) -> Vec<CompiledRule> {
+    rules.iter().filter_map(CompiledRule::compile).collect()
+}
+
+#[derive(Debug, Clone)]
+enum Matcher {
+    Literal(String),
+    Regex(Regex),
+}
+
+#[derive(Debug, Clone)]
+struct CompiledRule {
+    label: String,
+    matcher: Matcher,
+    replace: String,
+}
+
+impl CompiledRule {
+    fn compile(rule: &ReplaceRule) -> Option<Self> {
+        if rule.find.is_empty() {
+            return None;
+        }
+        let matcher = if rule.regex {
+            match Regex::new(&rule.find) {
+                Ok(re) => Matcher::Regex(re),
+                Err(e) => {
+                    tracing::warn!("[PostProcess] Skipping invalid regex {:?}: {}", rule.find, e);
+                    return None;
+                }
+            }
+        } else {
+            Matcher::Literal(rule.find.clone())
+        };
+        Some(Self {
+            label: rule
+                .name
+                .clone()
+                .filter(|n| !n.is_empty())
+                .unwrap_or_else(|| rule.find.clone()),
+            matcher,
+            replace: rule.replace.clone(),
+        })
+    }
+
+    /// 流式缓冲需要保留的字符数
+    fn window(&self) -> usize {
+        match &self.matcher {
+            Matcher::Literal(find) => find.chars().count(),
+            Matcher::Regex(_) => REGEX_WINDOW_CHARS,
+        }
+    }
+
+    /// text 中的所有匹配: (起始字节, 结束字节, 替换文本)
+    ///
+    /// context 为此前已处理的文本末尾, 只用于正则的 `^` / `\b` 等边界判断, 不会被匹配或替换。
+    /// 空匹配会被忽略。
+    fn matches(&self, text: &str, context: &str) -> Vec<(usize, usize, String)> {
+        match &self.matcher {
+            Matcher::Literal(find) => text
+                .match_indices(find.as_str())
+                .map(|(start, m)| (start, start + m.len(), self.replace.clone()))
+                .collect(),
+            Matcher::Regex(re) => {
+                let haystack = format!("{}{}", context, text);
+                let offset = context.len();
+                let mut found = Vec::new();
+                let mut pos = offset;
+                while pos <= haystack.len() {
+                    let Some(caps) = re.captures_at(&haystack, pos) else {
+                        break;
+                    };
+                    let m = caps.get(0).expect("group 0 always present");
+                    if m.start() == m.end() {
+                        match haystack[m.end()..].chars().next() {
+                            Some(c) => pos = m.end() + c.len_utf8(),
+                            None => break,
+                        }
+                        continue;
+                    }
+                    let mut replacement = String::new();
+                    caps.expand(&self.replace, &mut replacement);
+                    found.push((m.start() - offset, m.end() - offset, replacement));
+                    pos = m.end();
+                }
+                found
+            }
+        }
+    }
+
+    fn apply(&self, text: &str, context: &str) -> String {
+        let matches = self.matches(text, context);
+        if matches.is_empty() {
+            return text.to_string();
+        }
+        record_hit(&self.label, matches.len());
+
+        let mut out = String::with_capacity(text.len());
+        let mut last = 0;
+        for (start, end, replacement) in matches {
+            out.push_str(&text[last..start]);
+            out.push_str(&replacement);
+            last = end;
+        }
+        out.push_str(&text[last..]);
+        out
+    }
+}
+
+/// 一组已编译的后处理规则
+#[derive(Debug)]
+pub struct TextPostProcessor {
+    rules: Vec<CompiledRule>,
+    stop_phrases: Vec<String>,
+    /// 流式缓冲窗口 (字符数): 最长字面量 / 停止短语, 有正则时为 REGEX_WINDOW_CHARS
+    window: usize,
+}
+
+impl TextPostProcessor {
+    fn new(rules: Vec<CompiledRule>, stop_phrases: &[String]) -> Option<Self> {
+        let stop_phrases: Vec<String> = stop_phrases
+            .iter()
+            .map(|p| p.trim().to_string())
+            .filter(|p| !p.is_empty())
+            .collect();
+        if rules.is_empty() && stop_phrases.is_empty() {
+            return None;
+        }
+        let window = rules
+            .iter()
+            .map(CompiledRule::window)
+            .chain(stop_phrases.iter().map(|p| p.chars().count()))
+            .max()
+            .unwrap_or(0);
+        Some(Self {
+            rules,
+            stop_phrases,
+            window,
+        })
+    }
+
+    /// 处理一个完整的 text 块 (非流式)
+    pub fn apply(&self, text: &str) -> String {
+        let replaced = self.replace(text, "");
+        self.trim_stop_phrases(replaced)
+    }
+
+    fn replace(&self, text: &str, context: &str) -> String {
+        self.rules
+            .iter()
+            .fold(text.to_string(), |acc, rule| rule.apply(&acc, context))
+    }
+
+    /// 裁剪末尾的停止短语 (忽略其后的空白), 连同其前面的空白一起去掉
+    fn trim_stop_phrases(&self, mut text: String) -> String {
+        loop {
+            let body = text.trim_end();
+            let Some(phrase) = self.stop_phrases.iter().find(|p| body.ends_with(p.as_str())) else {
+                break;
+            };
+            record_hit(&format!("stop:{}", phrase), 1);
+            let keep = body.len() - phrase.len();
+            text.truncate(keep);
+            text.truncate(text.trim_end().len());
+        }
+        text
+    }
+}
+
+/// 流式 text 块的后处理缓冲
+pub struct StreamTextFilter {
+    processor: Arc<TextPostProcessor>,
+    /// 尚未输出的原始文本
+    pending: String,
+    /// 已处理原始文本的末尾 (正则边界判断的上下文)
+    context: String,
+}
+
+impl StreamTextFilter {
+    pub fn new(processor: Arc<TextPostProcessor>) -> Self {
+        Self {
+            processor,
+            pending: String::new(),
+            context: String::new(),
+        }
+    }
+
+    /// 追加一个分片, 返回已可安全输出的文本 (可能为空)
+    ///
+    /// 保留末尾空白及其前 `window` 个字符, 并且不会把跨越切分点的匹配拆开。
+    pub fn push(&mut self, chunk: &str) -> String {
+        self.pending.push_str(chunk);
+
+        let body = self.pending.trim_end();
+        let window = self.processor.window;
+        let mut split = if window == 0 {
+            body.len()
+        } else {
+            match body.char_indices().rev().nth(window - 1) {
+                Some((idx, _)) => idx,
+                None => return String::new(),
+            }
+        };
+
+        let spans: Vec<(usize, usize)> = self
+            .processor
+            .rules
+            .iter()
+            .flat_map(|rule| rule.matches(&self.pending, &self.context))
+            .map(|(start, end, _)| (start, end))
+            .collect();
+        while let Some(start) = spans
+            .iter()
+            .filter(|(start, end)| *start < split && *end > split)
+            .map(|(start, _)| *start)
+            .min()
+        {
+            split = start;
+        }
+
+        if split == 0 {
+            return String::new();
+        }
+        let raw: String = self.pending.drain(..split).collect();
+        let out = self.processor.replace(&raw, &self.context);
+        self.remember(&raw);
+        out
+    }
+
+    /// 块结束: 输出剩余缓冲并裁剪末尾停止短语
+    pub fn finish(&mut self) -> String {
+        let raw = std::mem::take(&mut self.pending);
+        let out = self.processor.replace(&raw, &self.context);
+        self.context.clear();
+        self.processor.trim_stop_phrases(out)
+    }
+
+    fn remember(&mut self, raw: &str) {
+        self.context.push_str(raw);
+        let keep = self.processor.window.max(1);
+        if let Some((idx, _)) = self.context.char_indices().rev().nth(keep - 1) {
+            self.context.drain(..idx);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::config::PostProcessProfile;
+
+    fn processor(rules: Vec<ReplaceRule>, stop_phrases: &[&str]) -> Arc<TextPostProcessor> {
+        let stop_phrases: Vec<String> = stop_phrases.iter().map(|s| s.to_string()).collect();
+        Arc::new(TextPostProcessor::new(compile_rules(&rules), &stop_phrases).unwrap())
+    }
+
+    fn rule(find: &str, replace: &str, regex: bool) -> ReplaceRule {
+        ReplaceRule {
+            name: None,
+            find: find.to_string(),
+            replace: replace.to_string(),
+            regex,
+        }
+    }
+
+    fn stream(processor: &Arc<TextPostProcessor>, chunks: &[&str]) -> String {
+        let mut filter = StreamTextFilter::new(processor.clone());
+        let mut out: String = chunks.iter().map(|c| filter.push(c)).collect();
+        out.push_str(&filter.finish());
+        out
+    }
+
+    #[test]
+    fn test_streaming_matches_non_streaming_at_every_split() {
+        let p = processor(
+            vec![rule("As a large language model trained by Google, ", "", false)],
+            &["Let me know if you need anything else."],
+        );
+        let text = "thought: As a large language model trained by Google, the answer is 42. \
+                    It stays.\n\nLet me know if you need anything else.\n";
+        let expected = p.apply(text);
+        assert_eq!(expected, "thought: the answer is 42. It stays.");
+
+        for (split, _) in text.char_indices().skip(1) {
+            let (a, b) = text.split_at(split);
+            assert_eq!(stream(&p, &[a, b]), expected, "split at {}", split);
+        }
+        let chunks: Vec<String> = text.chars().map(|c| c.to_string()).collect();
+        let chunks: Vec<&str> = chunks.iter().map(String::as_str).collect();
+        assert_eq!(stream(&p, &chunks), expected);
+    }
+
+    #[test]
+    fn test_streaming_regex_anchor_only_matches_block_start() {
+        let p = processor(vec![rule(r"^thought:\s*", "", true)], &[]);
+        let text = format!("thought: {} thought: kept", "x".repeat(600));
+        let expected = p.apply(&text);
+        assert_eq!(expected, format!("{} thought: kept", "x".repeat(600)));
+
+        let chunks: Vec<String> = text
+            .as_bytes()
+            .chunks(7)
+            .map(|c| String::from_utf8(c.to_vec()).unwrap())
+            .collect();
+        let chunks: Vec<&str> = chunks.iter().map(String::as_str).collect();
+        assert_eq!(stream(&p, &chunks), expected);
+    }
+
+    #[test]
+    fn test_regex_replacement_expands_groups() {
+        let p = processor(vec![rule(r"\[(\d+)\]", "($1)", true)], &[]);
+        assert_eq!(p.apply("see [1] and [23]"), "see (1) and (23)");
+    }
+
+    #[test]
+    fn test_hits_are_counted_per_rule() {
+        let p = processor(
+            vec![ReplaceRule {
+                name: Some("test-hit-counter".to_string()),
+                ..rule("foo", "bar", false)
+            }],
+            &["test-stop-counter"],
+        );
+        p.apply("foo foo foo test-stop-counter");
+
+        let hits = hit_counts();
+        assert!(hits["test-hit-counter"] >= 3);
+        assert!(hits["stop:test-stop-counter"] >= 1);
+    }
+
+    #[test]
+    fn test_registry_disabled_by_default_and_per_model_rules() {
+        assert!(!PostProcessConfig::default().enabled);
+
+        let config = PostProcessConfig {
+            enabled: true,
+            global: PostProcessProfile {
+                rules: vec![rule("alpha", "a", false)],
+                stop_phrases: Vec::new(),
+            },
+            per_model: HashMap::from([(
+                "Gemini-3-Flash".to_string(),
+                PostProcessProfile {
+                    rules: vec![rule("beta", "b", false)],
+                    stop_phrases: Vec::new(),
+                },
+            )]),
+        };
+        // 直接构建注册表, 不改动全局状态 (其他测试可能并发读取)
+        let registry = Registry::build(&config).unwrap();
+        let global = registry.lookup(&["claude-sonnet-4-5"]).unwrap();
+        let flash = registry.lookup(&["claude-sonnet-4-5", "gemini-3-flash"]).unwrap();
+
+        assert_eq!(global.apply("alpha beta"), "a beta");
+        assert_eq!(flash.apply("alpha beta"), "a b");
+        assert!(Registry::build(&PostProcessConfig::default()).is_none());
+    }
+}
@@ -0,0 +1,151 @@
+// 项目未开通错误识别
+// 新账号的 Google Cloud 项目尚未为 Cloud Code / Gemini Code Assist 开通 (onboarding 或 GCA tier 缺失) 时,
+// 上游返回特征明显的 403。这类错误不是限流也不是封号, 用户按链接完成开通后即可恢复,
+// 因此单独识别, 并尽量提取项目 ID 与开通链接用于引导。
+
+use crate::models::ProjectSetupRequired;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_json::Value;
+
+/// 错误消息中的特征片段 (小写匹配)
+const SETUP_MARKERS: &[&str] = &[
+    "service_disabled",
+    "has not been used in project",
+    "it is disabled. enable it",
+    "not been onboarded",
+    "not onboarded",
+    "onboarduser",
+    "no gca tier",
+    "gemini code assist is not enabled",
+    "cloudaicompanion.googleapis.com has not been",
+];
+
+static URL_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r#"https://[^\s"'<>\\]+"#).unwrap());
+static PROJECT_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?:in project|for project|project=|projects/)\s*([A-Za-z0-9][A-Za-z0-9_.:-]*)").unwrap());
+
+/// 识别项目未开通错误; 不匹配时返回 None
+pub fn detect(status: u16, error_text: &str) -> Option<ProjectSetupRequired> {
+    if status != 403 {
+        return None;
+    }
+    let lower = error_text.to_ascii_lowercase();
+    if !SETUP_MARKERS.iter().any(|m| lower.contains(m)) {
+        return None;
+    }
+
+    let json = extract_json(error_text);
+    let message = json
+        .as_ref()
+        .and_then(|v| v.pointer("/error/message"))
+        .and_then(|v| v.as_str())
+        .unwrap_or(error_text);
+    let mut reason: String = message.chars().take(300).collect();
+    if reason.len() < message.len() {
+        reason.push('…');
+    }
+
+    Some(ProjectSetupRequired {
+        reason,
+        project_id: json
+            .as_ref()
+            .and_then(metadata_project)
+            .or_else(|| capture_project(error_text)),
+        setup_url: json
+            .as_ref()
+            .and_then(metadata_url)
+            .or_else(|| URL_RE.find(error_text).map(|m| m.as_str().trim_end_matches(['.', ',', ')']).to_string())),
+        detected_at: chrono::Utc::now().timestamp(),
+    })
+}
+
+/// 错误文本可能带前缀 (如 "loadCodeAssist 返回错误 403: {...}"), 取第一个 '{' 起的 JSON
+fn extract_json(error_text: &str) -> Option<Value> {
+    let start = error_text.find('{')?;
+    serde_json::from_str(&error_text[start..]).ok()
+}
+
+fn details(json: &Value) -> impl Iterator<Item = &Value> {
+    json.pointer("/error/details")
+        .and_then(|d| d.as_array())
+        .into_iter()
+        .flatten()
+}
+
+/// ErrorInfo.metadata.consumer = "projects/123456"
+fn metadata_project(json: &Value) -> Option<String> {
+    details(json)
+        .filter_map(|d| d.pointer("/metadata/consumer").and_then(|v| v.as_str()))
+        .find_map(|consumer| consumer.strip_prefix("projects/").map(|p| p.to_string()))
+}
+
+/// ErrorInfo.metadata.activationUrl 或 Help.links[].url
+fn metadata_url(json: &Value) -> Option<String> {
+    details(json).find_map(|d| {
+        d.pointer("/metadata/activationUrl")
+            .and_then(|v| v.as_str())
+            .or_else(|| {
+                d.get("links")
+                    .and_then(|l| l.as_array())
+                    .and_then(|links| links.iter().find_map(|l| l.get("url").and_then(|u| u.as_str())))
+            })
+            .map(|s| s.to_string())
+    })
+}
+
+fn capture_project(text: &str) -> Option<String> {
+    PROJECT_RE
+        .captures(text)
+        .and_then(|c| c.get(1))
+        .map(|m| m.as_str().trim_end_matches(['.', ',']).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVICE_DISABLED: &str = r#"{
+      "error": {
+        "code": 403,
+        "message": "Cloud Code Private API has not been used in project 123456789 before or it is disabled. Enable it by visiting https://console.developers.google.com/apis/api/cloudcode-pa.googleapis.com/overview?project=123456789 then retry.",
+        "status": "PERMISSION_DENIED",
+        "details": [
+          {
+            "@type": "type.googleapis.com/google.rpc.ErrorInfo",
+            "reason": "SERVICE_DISABLED",
+            "metadata": {
+              "consumer": "projects/123456789",
+              "activationUrl": "https://console.developers.google.com/apis/api/cloudcode-pa.googleapis.com/overview?project=123456789"
+            }
+          }
+        ]
+      }
+    }"#;
+
+    #[test]
+    fn test_detects_service_disabled_with_metadata() {
+        let setup = detect(403, SERVICE_DISABLED).expect("should detect");
+        assert_eq!(setup.project_id.as_deref(), Some("123456789"));
+        assert_eq!(
+            setup.setup_url.as_deref(),
+            Some("https://console.developers.google.com/apis/api/cloudcode-pa.googleapis.com/overview?project=123456789")
+        );
+        assert!(setup.reason.starts_with("Cloud Code Private API has not been used"));
+    }
+
+    #[test]
+    fn test_detects_prefixed_plain_text_error() {
+        let text = "loadCodeAssist 返回错误 403 Forbidden: User is not onboarded for project my-proj-42. See https://codeassist.google.com/setup.";
+        let setup = detect(403, text).expect("should detect");
+        assert_eq!(setup.project_id.as_deref(), Some("my-proj-42"));
+        assert_eq!(setup.setup_url.as_deref(), Some("https://codeassist.google.com/setup"));
+    }
+
+    #[test]
+    fn test_ignores_other_errors() {
+        assert!(detect(403, r#"{"error":{"message":"VALIDATION_REQUIRED"}}"#).is_none());
+        assert!(detect(403, "account suspended").is_none());
+        assert!(detect(429, SERVICE_DISABLED).is_none());
+    }
+}
@@ -0,0 +1,292 @@
+// 系统提示注入
+// 按 API Key 或自定义映射条目附加命名的系统提示模板 (客户端无感知)。
+// 模板放到客户端 system 内容之前或之后, 并以分隔标记包裹: Claude 走 build_system_instruction,
+// OpenAI / Gemini 原生协议写入 v1internal 请求的 systemInstruction, z.ai 等透传 provider 写入 Anthropic system;
+// 日志只记录模板名, 调试载荷记录全文。
+
+use crate::proxy::config::{InjectionPosition, ProxyConfig, SystemInjectionProfile};
+use crate::proxy::mappers::claude::models::{SystemBlock, SystemPrompt};
+use crate::proxy::mappers::context_manager::estimate_tokens_from_str;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+#[derive(Debug, Default)]
+struct Injections {
+    profiles: HashMap<String, SystemInjectionProfile>,
+    /// API Key -> 模板名
+    keys: HashMap<String, Vec<String>>,
+    /// 自定义映射条目 (与 custom_mapping 的 key 相同) -> 模板名
+    mappings: HashMap<String, Vec<String>>,
+}
+
+static INJECTIONS: Lazy<RwLock<Injections>> = Lazy::new(|| RwLock::new(Injections::default()));
+
+/// 一次请求实际注入的模板
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SystemInjection {
+    pub name: String,
+    pub position: InjectionPosition,
+    pub text: String,
+}
+
+impl SystemInjection {
+    /// 带分隔标记的注入文本
+    pub fn wrapped_text(&self) -> String {
+        format!(
+            "\n==== [INJECTED: {name}] ====\n{text}\n==== [END INJECTED: {name}] ====",
+            name = self.name,
+            text = self.text
+        )
+    }
+}
+
+/// 本次请求应用的模板名 (响应扩展, 监控中间件据此写入日志)
+#[derive(Debug, Clone)]
+pub struct AppliedInjections(pub Vec<String>);
+
+impl Injections {
+    fn from_config(config: &ProxyConfig) -> Self {
+        Self {
+            profiles: config.system_injection_profiles.clone(),
+            keys: config.key_system_injections.clone(),
+            mappings: config.mapping_system_injections.clone(),
+        }
+    }
+
+    /// Key 级模板在前, 映射条目级在后; 同名模板只注入一次, 未定义的模板名忽略
+    fn resolve(&self, api_key: Option<&str>, mapping_rule: Option<&str>) -> Vec<SystemInjection> {
+        let key_names = api_key.and_then(|key| self.keys.get(key)).into_iter().flatten();
+        let mapping_names = mapping_rule.and_then(|rule| self.mappings.get(rule)).into_iter().flatten();
+
+        let mut resolved: Vec<SystemInjection> = Vec::new();
+        for name in key_names.chain(mapping_names) {
+            if resolved.iter().any(|i| &i.name == name) {
+                continue;
+            }
+            match self.profiles.get(name) {
+                Some(profile) => resolved.push(SystemInjection {
+                    name: name.clone(),
+                    position: profile.position,
+                    text: profile.text.clone(),
+                }),
+                None => tracing::warn!("[SystemInjection] Unknown profile '{}' ignored", name),
+            }
+        }
+        resolved
+    }
+}
+
+/// 应用配置 (启动与热更新时调用)
+pub fn set_config(config: &ProxyConfig) {
+    if let Ok(mut injections) = INJECTIONS.write() {
+        *injections = Injections::from_config(config);
+    }
+}
+
+/// 按 API Key 与客户端模型 (匹配规则与 resolve_model_route 一致) 解析要注入的模板
+pub fn resolve_for_route(
+    api_key: Option<&str>,
+    original_model: &str,
+    custom_mapping: &HashMap<String, String>,
+) -> Vec<SystemInjection> {
+    let mapping_rule = super::model_mapping::match_custom_mapping(original_model, custom_mapping).map(|(rule, _)| rule);
+    INJECTIONS
+        .read()
+        .map(|injections| injections.resolve(api_key, mapping_rule))
+        .unwrap_or_default()
+}
+
+/// 指定位置的注入, 作为 Gemini systemInstruction 的 parts
+pub fn injected_parts(injections: &[SystemInjection], position: InjectionPosition) -> Vec<Value> {
+    injections
+        .iter()
+        .filter(|i| i.position == position)
+        .map(|i| json!({"text": i.wrapped_text()}))
+        .collect()
+}
+
+/// 写入 v1internal 内层请求的 systemInstruction (OpenAI / Gemini 原生协议):
+/// `Prepend` 放在 Antigravity 身份之后、客户端 system 之前, `Append` 放在最后。
+/// 请求没有 systemInstruction (如图像生成) 时不注入, 返回 false
+pub fn apply_to_system_instruction(request: &mut Value, injections: &[SystemInjection]) -> bool {
+    if injections.is_empty() {
+        return false;
+    }
+    let Some(parts) = request
+        .get_mut("systemInstruction")
+        .and_then(|s| s.get_mut("parts"))
+        .and_then(|p| p.as_array_mut())
+    else {
+        return false;
+    };
+    let insert_at = parts
+        .first()
+        .and_then(|p| p.get("text"))
+        .and_then(|t| t.as_str())
+        .is_some_and(|t| t.starts_with("You are Antigravity"))
+        as usize;
+    parts.splice(insert_at..insert_at, injected_parts(injections, InjectionPosition::Prepend));
+    parts.extend(injected_parts(injections, InjectionPosition::Append));
+    true
+}
+
+/// 写入 Anthropic 格式的 system (z.ai 等透传 provider), 以独立 text block 放在客户端 system 前后
+pub fn apply_to_claude_system(system: &mut Option<SystemPrompt>, injections: &[SystemInjection]) {
+    if injections.is_empty() {
+        return;
+    }
+    let text_block = |text: String| SystemBlock {
+        block_type: "text".to_string(),
+        text,
+    };
+    let injected = |position: InjectionPosition| -> Vec<SystemBlock> {
+        injections
+            .iter()
+            .filter(|i| i.position == position)
+            .map(|i| text_block(i.wrapped_text()))
+            .collect()
+    };
+    let mut blocks = injected(InjectionPosition::Prepend);
+    match system.take() {
+        Some(SystemPrompt::String(text)) if !text.is_empty() => blocks.push(text_block(text)),
+        Some(SystemPrompt::Array(client_blocks)) => blocks.extend(client_blocks),
+        _ => {}
+    }
+    blocks.extend(injected(InjectionPosition::Append));
+    *system = Some(SystemPrompt::Array(blocks));
+}
+
+/// 注入内容的估算 token 数 (用量缩放隐藏注入时从 input_tokens 中扣除)
+pub fn estimated_tokens(injections: &[SystemInjection]) -> u32 {
+    injections.iter().map(|i| estimate_tokens_from_str(&i.wrapped_text())).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(text: &str, position: InjectionPosition) -> SystemInjectionProfile {
+        SystemInjectionProfile {
+            text: text.to_string(),
+            position,
+        }
+    }
+
+    #[test]
+    fn test_key_and_alias_profiles_combine() {
+        let mut config = ProxyConfig::default();
+        config.system_injection_profiles = HashMap::from([
+            ("safety".to_string(), profile("Keep answers suitable for children.", InjectionPosition::Prepend)),
+            ("style".to_string(), profile("Write in short, plain sentences.", InjectionPosition::Append)),
+        ]);
+        config.key_system_injections = HashMap::from([("sk-kids".to_string(), vec!["safety".to_string()])]);
+        config.mapping_system_injections = HashMap::from([(
+            "gemini-writer".to_string(),
+            vec!["style".to_string(), "safety".to_string(), "missing".to_string()],
+        )]);
+        let injections = Injections::from_config(&config);
+
+        let resolved = injections.resolve(Some("sk-kids"), Some("gemini-writer"));
+        let names: Vec<&str> = resolved.iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(names, ["safety", "style"]);
+        assert_eq!(injections.resolve(Some("sk-other"), Some("gemini-writer")).len(), 2);
+        assert_eq!(injections.resolve(Some("sk-kids"), None).len(), 1);
+        assert!(injections.resolve(None, None).is_empty());
+
+        assert!(estimated_tokens(&resolved) > 0);
+    }
+
+    fn injections() -> Vec<SystemInjection> {
+        vec![
+            SystemInjection {
+                name: "safety".to_string(),
+                position: InjectionPosition::Prepend,
+                text: "Keep answers suitable for children.".to_string(),
+            },
+            SystemInjection {
+                name: "style".to_string(),
+                position: InjectionPosition::Append,
+                text: "Write in short, plain sentences.".to_string(),
+            },
+        ]
+    }
+
+    fn texts(parts: &Value) -> Vec<String> {
+        parts
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| p["text"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_openai_request_injections_wrap_client_system() {
+        let request: crate::proxy::mappers::openai::OpenAIRequest = serde_json::from_value(json!({
+            "model": "gpt-4",
+            "messages": [
+                {"role": "system", "content": "Client system"},
+                {"role": "user", "content": "Hi"}
+            ]
+        }))
+        .unwrap();
+        let mut body = crate::proxy::mappers::openai::transform_openai_request(&request, "proj", "gemini-2.5-flash");
+        assert!(apply_to_system_instruction(&mut body["request"], &injections()));
+
+        let texts = texts(&body["request"]["systemInstruction"]["parts"]);
+        assert!(texts[0].starts_with("You are Antigravity"));
+        assert!(texts[1].contains("[INJECTED: safety]"));
+        assert_eq!(texts[2], "Client system");
+        assert!(texts[3].contains("[INJECTED: style]"));
+    }
+
+    #[test]
+    fn test_gemini_request_injections_wrap_client_system() {
+        let body = json!({
+            "contents": [{"role": "user", "parts": [{"text": "Hi"}]}],
+            "systemInstruction": {"parts": [{"text": "Client system"}]}
+        });
+        let mut wrapped = crate::proxy::mappers::gemini::wrap_request(&body, "proj", "gemini-2.5-flash", None);
+        assert!(apply_to_system_instruction(&mut wrapped["request"], &injections()));
+
+        let texts = texts(&wrapped["request"]["systemInstruction"]["parts"]);
+        assert_eq!(texts.len(), 4);
+        assert!(texts[0].starts_with("You are Antigravity"));
+        assert!(texts[1].contains("[INJECTED: safety]"));
+        assert_eq!(texts[2], "Client system");
+        assert!(texts[3].contains("[INJECTED: style]"));
+
+        // 没有 systemInstruction (图像生成) 时不注入
+        let mut image_request = json!({"contents": []});
+        assert!(!apply_to_system_instruction(&mut image_request, &injections()));
+        assert!(image_request.get("systemInstruction").is_none());
+    }
+
+    #[test]
+    fn test_provider_claude_system_injections() {
+        let mut system = Some(SystemPrompt::String("Client system".to_string()));
+        apply_to_claude_system(&mut system, &injections());
+        let Some(SystemPrompt::Array(blocks)) = &system else {
+            panic!("expected system blocks");
+        };
+        let texts: Vec<&str> = blocks.iter().map(|b| b.text.as_str()).collect();
+        assert_eq!(texts.len(), 3);
+        assert!(texts[0].contains("[INJECTED: safety]"));
+        assert_eq!(texts[1], "Client system");
+        assert!(texts[2].contains("[INJECTED: style]"));
+        assert!(blocks.iter().all(|b| b.block_type == "text"));
+
+        // 客户端没有 system 时只写入注入内容
+        let mut system = None;
+        apply_to_claude_system(&mut system, &injections());
+        assert!(matches!(&system, Some(SystemPrompt::Array(blocks)) if blocks.len() == 2));
+
+        // 无注入时保持原样
+        let mut system = Some(SystemPrompt::String("Client system".to_string()));
+        apply_to_claude_system(&mut system, &[]);
+        assert!(matches!(&system, Some(SystemPrompt::String(text)) if text == "Client system"));
+    }
+}
@@ -0,0 +1,246 @@
+// 模型 thinking 能力表
+// 静态规则作为初始值; 上游明确拒绝 thinkingConfig 时在运行期学习为 "不支持" (带 TTL),
+// TTL 过期后下一次请求会重新携带 thinking 作为探测。用户可固定覆盖 (ProxyConfig.thinking_overrides)。
+
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// 学习到的 "不支持 thinking" 有效期
+const LEARNED_TTL_SECS: i64 = 6 * 3600;
+
+/// model (小写) -> 学习记录
+static LEARNED: Lazy<DashMap<String, LearnedCapability>> = Lazy::new(DashMap::new);
+/// model (小写) -> 用户固定的支持状态
+static OVERRIDES: Lazy<DashMap<String, bool>> = Lazy::new(DashMap::new);
+
+#[derive(Debug, Clone)]
+struct LearnedCapability {
+    expires_at: i64,
+    reason: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CapabilitySource {
+    Seed,
+    Learned,
+    Override,
+}
+
+/// 能力表条目 (get_thinking_capabilities 返回)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThinkingCapabilityEntry {
+    pub model: String,
+    pub supports_thinking: bool,
+    pub source: CapabilitySource,
+    /// 学习记录的过期时间 (unix 秒)
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+    /// 学习时的上游错误摘要
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+/// 静态规则 (原 transform 中的白名单)
+pub fn seed_supports_thinking(model: &str) -> bool {
+    let m = model.to_lowercase();
+    m.contains("-thinking")
+        || m.starts_with("claude-")
+        || m.contains("gemini-2.0-pro")
+        || m.contains("gemini-3-pro")
+}
+
+/// 物理模型可接受的 thinkingBudget 范围
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BudgetRange {
+    pub min: u32,
+    pub max: u32,
+    /// 可以用 thinkingBudget: 0 关闭 thinking (Gemini Pro 系列不允许)
+    pub can_disable: bool,
+}
+
+impl BudgetRange {
+    pub fn clamp(&self, budget: u32) -> u32 {
+        budget.clamp(self.min, self.max)
+    }
+}
+
+/// 已知模型族的 thinkingBudget 范围 (未知模型返回 None, 不做裁剪)
+pub fn budget_range(model: &str) -> Option<BudgetRange> {
+    let m = model.to_lowercase();
+    let (min, max, can_disable) = if m.contains("gemini-3") && m.contains("pro") {
+        (512, 32768, false)
+    } else if m.contains("gemini") && m.contains("pro") {
+        (128, 32768, false)
+    } else if m.contains("gemini") && m.contains("flash-lite") {
+        (512, 24576, true)
+    } else if m.contains("gemini") && m.contains("flash") {
+        (1, 24576, true)
+    } else if m.starts_with("claude-") {
+        (1024, 32768, true)
+    } else {
+        return None;
+    };
+    Some(BudgetRange { min, max, can_disable })
+}
+
+/// 物理模型当前是否携带 thinkingConfig: 覆盖 > 学习记录 (未过期) > 静态规则
+pub fn supports_thinking(model: &str) -> bool {
+    let key = model.to_lowercase();
+    if let Some(pinned) = OVERRIDES.get(&key) {
+        return *pinned;
+    }
+    let now = chrono::Utc::now().timestamp();
+    if let Some(learned) = LEARNED.get(&key) {
+        if learned.expires_at > now {
+            return false;
+        }
+    }
+    // 过期后移除, 下一次请求即为重新探测
+    if LEARNED.remove_if(&key, |_, l| l.expires_at <= now).is_some() {
+        tracing::info!(
+            "[Thinking-Capability] Learned no-thinking for {} expired, probing with thinking again",
+            model
+        );
+    }
+    seed_supports_thinking(model)
+}
+
+/// 明确表示 "该模型不支持 thinking" 的上游错误措辞 (小写)
+const THINKING_UNSUPPORTED_PHRASES: [&str; 6] = [
+    "thinking is not supported",
+    "thinking is not enabled for",
+    "thinking is not available for this model",
+    "does not support thinking",
+    "thinking_budget is not supported",
+    "thinking budget is not supported",
+];
+
+/// 上游 400 是否表示该模型不支持 thinking (而非签名 / 历史问题)
+/// 只认 thinkingConfig 字段不被识别或明确的 "不支持 thinking" 措辞; 任何提到签名的错误都不算
+pub fn is_thinking_unsupported_error(error_text: &str) -> bool {
+    let t = error_text.to_lowercase();
+    if t.contains("unknown name \\\"thinkingconfig\\\"") || t.contains("unknown name \"thinkingconfig\"") {
+        return true;
+    }
+    if t.contains("signature") {
+        return false;
+    }
+    THINKING_UNSUPPORTED_PHRASES.iter().any(|p| t.contains(p))
+}
+
+/// 能力表是否明确记录了该模型不支持 thinking (用户固定或未过期的学习记录), 不看静态规则。
+/// OpenAI / Gemini 协议由客户端或模型名决定是否携带 thinking, 只用它剔除已知会被拒绝的 thinkingConfig
+pub fn is_known_unsupported(model: &str) -> bool {
+    let key = model.to_lowercase();
+    if let Some(pinned) = OVERRIDES.get(&key) {
+        return !*pinned;
+    }
+    let now = chrono::Utc::now().timestamp();
+    LEARNED.get(&key).is_some_and(|learned| learned.expires_at > now)
+}
+
+/// 记录模型不支持 thinking (用户固定了覆盖时不记录)
+pub fn record_no_thinking(model: &str, reason: &str) {
+    let key = model.to_lowercase();
+    if OVERRIDES.contains_key(&key) {
+        return;
+    }
+    let summary: String = super::redact::sanitize_upstream_error(reason, None)
+        .chars()
+        .take(200)
+        .collect();
+    tracing::warn!(
+        "[Thinking-Capability] Upstream rejected thinking for {}, disabling for {}s",
+        model,
+        LEARNED_TTL_SECS
+    );
+    LEARNED.insert(
+        key,
+        LearnedCapability {
+            expires_at: chrono::Utc::now().timestamp() + LEARNED_TTL_SECS,
+            reason: summary,
+        },
+    );
+}
+
+/// 应用用户固定的覆盖 (启动与热更新时调用)
+pub fn set_overrides(overrides: &HashMap<String, bool>) {
+    OVERRIDES.clear();
+    for (model, supported) in overrides {
+        OVERRIDES.insert(model.to_lowercase(), *supported);
+    }
+}
+
+/// 当前能力表: 覆盖与未过期的学习记录 (静态规则不逐一列出)
+pub fn capability_table() -> Vec<ThinkingCapabilityEntry> {
+    let now = chrono::Utc::now().timestamp();
+    let mut entries: Vec<ThinkingCapabilityEntry> = OVERRIDES
+        .iter()
+        .map(|e| ThinkingCapabilityEntry {
+            model: e.key().clone(),
+            supports_thinking: *e.value(),
+            source: CapabilitySource::Override,
+            expires_at: None,
+            reason: None,
+        })
+        .collect();
+    for e in LEARNED.iter() {
+        if e.value().expires_at <= now || OVERRIDES.contains_key(e.key()) {
+            continue;
+        }
+        entries.push(ThinkingCapabilityEntry {
+            model: e.key().clone(),
+            supports_thinking: false,
+            source: CapabilitySource::Learned,
+            expires_at: Some(e.value().expires_at),
+            reason: Some(e.value().reason.clone()),
+        });
+    }
+    entries.sort_by(|a, b| a.model.cmp(&b.model));
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_learned_no_thinking_and_expiry() {
+        let model = "gemini-3-pro-capability-test";
+        assert!(supports_thinking(model));
+
+        let err = r#"{"error":{"code":400,"message":"Thinking is not supported for this model.","status":"INVALID_ARGUMENT"}}"#;
+        assert!(is_thinking_unsupported_error(err));
+        assert!(!is_thinking_unsupported_error("Invalid `signature` in thinking block"));
+        assert!(!is_thinking_unsupported_error("Request contains an invalid argument."));
+        // 提到 thinking 但并非 "不支持" 的错误不学习
+        assert!(!is_thinking_unsupported_error(
+            "Thought signature is not supported for function calls without thinking"
+        ));
+        assert!(!is_thinking_unsupported_error(
+            "Unsupported value for temperature when thinking is enabled"
+        ));
+        assert!(!is_thinking_unsupported_error(
+            "messages.1.content.0.type: Expected `thinking` or `redacted_thinking`, but found `text`. This is not supported."
+        ));
+        assert!(is_thinking_unsupported_error(
+            r#"Invalid JSON payload received. Unknown name "thinkingConfig" at 'generation_config': Cannot find field."#
+        ));
+        assert!(!is_known_unsupported(model));
+
+        record_no_thinking(model, err);
+        assert!(!supports_thinking(model));
+        assert!(is_known_unsupported(model));
+        let entry = capability_table().into_iter().find(|e| e.model == model).unwrap();
+        assert_eq!(entry.source, CapabilitySource::Learned);
+
+        // TTL 过期后回到静态规则 (重新探测)
+        LEARNED.get_mut(model).unwrap().expires_at = 0;
+        assert!(supports_thinking(model));
+        assert!(LEARNED.get(model).is_none());
+        assert!(capability_table().iter().all(|e| e.model != model));
+    }
+}
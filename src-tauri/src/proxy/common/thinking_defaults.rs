@@ -0,0 +1,265 @@
+// thinking 默认开启策略
+// 客户端未显式携带 thinking 时, 部分模型会被代理自动开启 thinking, 延迟与 token 消耗随之变化。
+// 策略按物理模型配置 (ProxyConfig.thinking_defaults), 可被自定义映射条目覆盖 (mapping_thinking_defaults),
+// 都未配置时沿用内置规则。实验开关 never_auto_enable_thinking 全局禁止自动开启。
+// 开启后客户端未指定 budget_tokens 时, 按物理模型使用 thinking_budget_defaults 中的默认预算。
+
+use crate::proxy::config::{ProxyConfig, ThinkingDefaultPolicy};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+/// 代理替客户端开启了 thinking 时写入的响应头 (值为策略来源)
+pub const AUTO_ENABLED_HEADER: &str = "X-Thinking-Auto-Enabled";
+
+#[derive(Debug, Default)]
+struct Policies {
+    /// 物理模型 (小写) -> 策略
+    models: HashMap<String, ThinkingDefaultPolicy>,
+    /// 自定义映射条目 (与 custom_mapping 的 key 相同) -> 策略
+    mappings: HashMap<String, ThinkingDefaultPolicy>,
+    /// 物理模型 (小写) -> 默认 thinking 预算
+    budgets: HashMap<String, u32>,
+    never_auto_enable: bool,
+}
+
+static POLICIES: Lazy<RwLock<Policies>> = Lazy::new(|| RwLock::new(Policies::default()));
+
+/// 策略来源
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicySource {
+    Mapping,
+    Model,
+    Builtin,
+}
+
+impl PolicySource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PolicySource::Mapping => "mapping",
+            PolicySource::Model => "model",
+            PolicySource::Builtin => "builtin",
+        }
+    }
+}
+
+/// 一次请求的 thinking 决策 (写入调试载荷)
+#[derive(Debug, Clone, Serialize)]
+pub struct ThinkingDecision {
+    pub physical_model: String,
+    pub policy: ThinkingDefaultPolicy,
+    pub source: PolicySource,
+    /// 命中的自定义映射条目
+    pub mapping_rule: Option<String>,
+    /// 客户端的请求 (None = 未指定)
+    pub client_requested: Option<bool>,
+    pub enabled: bool,
+    /// 客户端未请求, 由代理开启
+    pub auto_enabled: bool,
+    /// 全局 "从不自动开启" 阻止了本次自动开启
+    pub suppressed_by_global: bool,
+}
+
+impl Policies {
+    fn from_config(config: &ProxyConfig) -> Self {
+        Self {
+            models: config
+                .thinking_defaults
+                .iter()
+                .map(|(model, policy)| (model.to_lowercase(), *policy))
+                .collect(),
+            mappings: config.mapping_thinking_defaults.clone(),
+            budgets: config
+                .thinking_budget_defaults
+                .iter()
+                .map(|(model, budget)| (model.to_lowercase(), *budget))
+                .collect(),
+            never_auto_enable: config.experimental.never_auto_enable_thinking,
+        }
+    }
+
+    /// 生效的策略: 映射条目 > 物理模型配置 > 内置规则
+    fn policy_for(&self, mapping_rule: Option<&str>, physical_model: &str) -> (ThinkingDefaultPolicy, PolicySource) {
+        if let Some(policy) = mapping_rule.and_then(|rule| self.mappings.get(rule)) {
+            return (*policy, PolicySource::Mapping);
+        }
+        if let Some(policy) = self.models.get(&physical_model.to_lowercase()) {
+            return (*policy, PolicySource::Model);
+        }
+        (builtin_policy(physical_model), PolicySource::Builtin)
+    }
+
+    fn decide(&self, mapping_rule: Option<&str>, physical_model: &str, client_requested: Option<bool>) -> ThinkingDecision {
+        let (policy, source) = self.policy_for(mapping_rule, physical_model);
+        let capable = super::thinking_capability::supports_thinking(physical_model);
+
+        let (wanted, suppressed_by_global) = match (policy, client_requested) {
+            (ThinkingDefaultPolicy::Off, _) => (false, false),
+            (_, Some(requested)) => (requested, false),
+            (ThinkingDefaultPolicy::ClientOnly, None) => (false, false),
+            (ThinkingDefaultPolicy::On, None) if self.never_auto_enable => (false, true),
+            (ThinkingDefaultPolicy::On, None) => (true, false),
+        };
+        let enabled = wanted && capable;
+
+        ThinkingDecision {
+            physical_model: physical_model.to_string(),
+            policy,
+            source,
+            mapping_rule: mapping_rule.map(|r| r.to_string()),
+            client_requested,
+            enabled,
+            auto_enabled: enabled && client_requested.is_none(),
+            suppressed_by_global,
+        }
+    }
+}
+
+/// 应用配置 (启动与热更新时调用)
+pub fn set_config(config: &ProxyConfig) {
+    if let Ok(mut policies) = POLICIES.write() {
+        *policies = Policies::from_config(config);
+    }
+}
+
+/// 内置规则 (未配置时的默认值)
+///
+/// Claude Code v2.0.67+ enables thinking by default for Opus 4.5 models.
+/// PR #1641: Also enables thinking for Opus 4.6 models.
+/// [FIX #1557] Gemini Pro families and explicit `-thinking` variants are enabled as well.
+pub fn builtin_policy(model: &str) -> ThinkingDefaultPolicy {
+    let model_lower = model.to_lowercase();
+    let enabled = model_lower.contains("opus-4-6")
+        || model_lower.contains("opus-4.6")
+        || model_lower.contains("opus-4-5")
+        || model_lower.contains("opus-4.5")
+        || model_lower.contains("gemini-2.0-pro")
+        || model_lower.contains("gemini-3-pro")
+        || model_lower.contains("-thinking");
+    if enabled {
+        ThinkingDefaultPolicy::On
+    } else {
+        ThinkingDefaultPolicy::ClientOnly
+    }
+}
+
+/// 计算 thinking 决策; 目标模型不支持 thinking 时不会开启
+pub fn decide(mapping_rule: Option<&str>, physical_model: &str, client_requested: Option<bool>) -> ThinkingDecision {
+    match POLICIES.read() {
+        Ok(policies) => policies.decide(mapping_rule, physical_model, client_requested),
+        Err(_) => Policies::default().decide(mapping_rule, physical_model, client_requested),
+    }
+}
+
+/// 按客户端模型与自定义映射计算决策 (物理模型解析规则与 resolve_model_route 一致)
+pub fn decide_for_route(
+    original_model: &str,
+    custom_mapping: &HashMap<String, String>,
+    client_requested: Option<bool>,
+) -> ThinkingDecision {
+    match super::model_mapping::match_custom_mapping(original_model, custom_mapping) {
+        Some((rule, target)) => decide(Some(rule), target, client_requested),
+        None => decide(
+            None,
+            &super::model_mapping::map_claude_model_to_gemini(original_model),
+            client_requested,
+        ),
+    }
+}
+
+/// 客户端未指定 budget_tokens 时该物理模型的默认 thinking 预算 (未配置返回 None)
+pub fn default_budget(physical_model: &str) -> Option<u32> {
+    POLICIES
+        .read()
+        .ok()
+        .and_then(|policies| policies.budgets.get(&physical_model.to_lowercase()).copied())
+}
+
+/// 客户端未指定 thinking 时是否开启 (无映射信息的调用方, 如预热 / 摘要请求)
+pub fn default_enabled(physical_model: &str) -> bool {
+    decide(None, physical_model, None).enabled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAPABLE: &str = "gemini-3-pro-defaults-test";
+    const NOT_CAPABLE: &str = "gemini-2.5-flash-defaults-test";
+
+    fn policies(models: &[(&str, ThinkingDefaultPolicy)], never_auto_enable: bool) -> Policies {
+        let mut config = ProxyConfig::default();
+        config.thinking_defaults = models.iter().map(|(m, p)| (m.to_string(), *p)).collect();
+        config
+            .mapping_thinking_defaults
+            .insert("my-fast-*".to_string(), ThinkingDefaultPolicy::ClientOnly);
+        config.experimental.never_auto_enable_thinking = never_auto_enable;
+        Policies::from_config(&config)
+    }
+
+    #[test]
+    fn test_builtin_defaults() {
+        let p = policies(&[], false);
+        let builtin = p.decide(None, CAPABLE, None);
+        assert_eq!((builtin.policy, builtin.source), (ThinkingDefaultPolicy::On, PolicySource::Builtin));
+        assert!(builtin.enabled && builtin.auto_enabled);
+        assert_eq!(builtin_policy("claude-opus-4-6-thinking"), ThinkingDefaultPolicy::On);
+        assert_eq!(p.decide(None, NOT_CAPABLE, None).policy, ThinkingDefaultPolicy::ClientOnly);
+        assert!(!p.decide(None, NOT_CAPABLE, None).enabled);
+    }
+
+    #[test]
+    fn test_on_policy() {
+        let p = policies(&[(CAPABLE, ThinkingDefaultPolicy::On), (NOT_CAPABLE, ThinkingDefaultPolicy::On)], false);
+        assert!(p.decide(None, CAPABLE, None).auto_enabled);
+        assert!(!p.decide(None, CAPABLE, Some(false)).enabled);
+        // 目标模型不支持 thinking 时不会开启
+        let not_capable = p.decide(None, NOT_CAPABLE, None);
+        assert_eq!(not_capable.source, PolicySource::Model);
+        assert!(!not_capable.enabled && !not_capable.auto_enabled);
+    }
+
+    #[test]
+    fn test_off_policy_ignores_client_request() {
+        let p = policies(&[(CAPABLE, ThinkingDefaultPolicy::Off), (NOT_CAPABLE, ThinkingDefaultPolicy::Off)], false);
+        assert!(!p.decide(None, CAPABLE, Some(true)).enabled);
+        assert!(!p.decide(None, CAPABLE, None).enabled);
+        assert!(!p.decide(None, NOT_CAPABLE, Some(true)).enabled);
+    }
+
+    #[test]
+    fn test_client_only_policy() {
+        let p = policies(
+            &[(CAPABLE, ThinkingDefaultPolicy::ClientOnly), (NOT_CAPABLE, ThinkingDefaultPolicy::ClientOnly)],
+            false,
+        );
+        assert!(!p.decide(None, CAPABLE, None).enabled);
+        let requested = p.decide(None, CAPABLE, Some(true));
+        assert!(requested.enabled && !requested.auto_enabled);
+        assert!(!p.decide(None, NOT_CAPABLE, Some(true)).enabled);
+    }
+
+    #[test]
+    fn test_mapping_override_and_global_never_auto_enable() {
+        let p = policies(&[(CAPABLE, ThinkingDefaultPolicy::On)], false);
+        let routed = p.decide(Some("my-fast-*"), CAPABLE, None);
+        assert_eq!((routed.policy, routed.source), (ThinkingDefaultPolicy::ClientOnly, PolicySource::Mapping));
+        assert!(!routed.enabled);
+
+        let p = policies(&[], true);
+        let suppressed = p.decide(None, CAPABLE, None);
+        assert!(!suppressed.enabled && suppressed.suppressed_by_global);
+        assert!(p.decide(None, CAPABLE, Some(true)).enabled);
+    }
+
+    #[test]
+    fn test_budget_defaults_are_case_insensitive() {
+        let mut config = ProxyConfig::default();
+        config.thinking_budget_defaults.insert("Gemini-3-Pro-High".to_string(), 8192);
+        let p = Policies::from_config(&config);
+        assert_eq!(p.budgets.get("gemini-3-pro-high"), Some(&8192));
+        assert_eq!(p.budgets.get("gemini-3-flash"), None);
+    }
+}
@@ -63,6 +63,171 @@ fn default_thinking_budget_custom_value() -> u32 {
     24576 // [FIX #1592] Safe default for Gemini models
 }
 
+// ============================================================================
+// RETRY POLICY CONFIG
+// ============================================================================
+
+/// Global retry policy (hot-reloaded, read once per request by the handlers)
+static RETRY_CONFIG: Lazy<RwLock<RetryConfig>> = Lazy::new(|| RwLock::new(RetryConfig::default()));
+
+#[cfg(test)]
+tokio::task_local! {
+    /// 测试用重试策略: 只作用于 scope 内的任务, 不改动全局配置, 并行测试互不影响
+    static RETRY_CONFIG_OVERRIDE: RetryConfig;
+}
+
+/// Get current retry policy
+pub fn get_retry_config() -> RetryConfig {
+    retry_config_override().unwrap_or_else(|| *RETRY_CONFIG.read().unwrap())
+}
+
+#[cfg(test)]
+fn retry_config_override() -> Option<RetryConfig> {
+    RETRY_CONFIG_OVERRIDE.try_with(|config| *config).ok()
+}
+
+#[cfg(not(test))]
+fn retry_config_override() -> Option<RetryConfig> {
+    None
+}
+
+/// 在指定重试策略下运行 future (仅测试)
+#[cfg(test)]
+pub async fn with_retry_config<F: std::future::Future>(config: RetryConfig, future: F) -> F::Output {
+    RETRY_CONFIG_OVERRIDE.scope(config, future).await
+}
+
+/// Update retry policy
+pub fn update_retry_config(config: RetryConfig) {
+    let mut guard = RETRY_CONFIG.write().unwrap();
+    *guard = config;
+}
+
+pub const RETRY_MAX_ATTEMPTS_RANGE: std::ops::RangeInclusive<usize> = 1..=10;
+pub const RETRY_PEEK_TIMEOUT_SECS_RANGE: std::ops::RangeInclusive<u64> = 5..=600;
+pub const RETRY_BASE_DELAY_MS_RANGE: std::ops::RangeInclusive<u64> = 0..=60_000;
+pub const RETRY_STALL_TIMEOUT_SECS_RANGE: std::ops::RangeInclusive<u64> = 10..=3600;
+pub const RETRY_STALL_RETRY_CHARS_RANGE: std::ops::RangeInclusive<usize> = 0..=10_000;
+
+/// 内置退避策略的基准延迟 (429 线性退避的起点)
+pub const DEFAULT_RETRY_BASE_DELAY_MS: u64 = 2000;
+
+/// 主链路 (Claude messages / OpenAI chat 等) 的重试策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryConfig {
+    /// 单个请求的最大尝试次数 (仍受账号池大小约束: 最多为账号数 + 1; 1 表示不重试)
+    pub max_attempts: usize,
+    /// 流式响应等待首个数据块的超时 (秒), 超时视为空响应并换号重试
+    pub peek_timeout_secs: u64,
+    /// 退避基准延迟 (毫秒); 内置的线性 / 指数退避按 `base_delay_ms / 2000` 等比缩放,
+    /// 上游返回的 Retry-After 不受影响
+    pub base_delay_ms: u64,
+    /// 流中途停滞看门狗 (秒): 超过该时长没有任何上游数据即判定停滞, 0 表示关闭
+    pub stall_timeout_secs: u64,
+    /// 流式响应先缓存到产出该数量的输出字符再开始下发;
+    /// 缓存期间停滞时透明换号重试, 之后停滞则以错误事件结束 (默认 0: 不缓存, 首包即下发)
+    pub stall_retry_chars: usize,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            peek_timeout_secs: 60,
+            base_delay_ms: DEFAULT_RETRY_BASE_DELAY_MS,
+            stall_timeout_secs: 120,
+            stall_retry_chars: 0,
+        }
+    }
+}
+
+impl RetryConfig {
+    /// 校验取值范围, 失败时列出全部不合法字段
+    pub fn validate(self) -> Result<Self, Vec<crate::error::FieldViolation>> {
+        use crate::error::FieldViolation;
+
+        let mut violations = Vec::new();
+        if !RETRY_MAX_ATTEMPTS_RANGE.contains(&self.max_attempts) {
+            violations.push(FieldViolation::new(
+                "max_attempts",
+                format!(
+                    "must be between {} and {}",
+                    RETRY_MAX_ATTEMPTS_RANGE.start(),
+                    RETRY_MAX_ATTEMPTS_RANGE.end()
+                ),
+            ));
+        }
+        if !RETRY_PEEK_TIMEOUT_SECS_RANGE.contains(&self.peek_timeout_secs) {
+            violations.push(FieldViolation::new(
+                "peek_timeout_secs",
+                format!(
+                    "must be between {} and {}",
+                    RETRY_PEEK_TIMEOUT_SECS_RANGE.start(),
+                    RETRY_PEEK_TIMEOUT_SECS_RANGE.end()
+                ),
+            ));
+        }
+        if !RETRY_BASE_DELAY_MS_RANGE.contains(&self.base_delay_ms) {
+            violations.push(FieldViolation::new(
+                "base_delay_ms",
+                format!(
+                    "must be between {} and {}",
+                    RETRY_BASE_DELAY_MS_RANGE.start(),
+                    RETRY_BASE_DELAY_MS_RANGE.end()
+                ),
+            ));
+        }
+
+        if self.stall_timeout_secs != 0 && !RETRY_STALL_TIMEOUT_SECS_RANGE.contains(&self.stall_timeout_secs) {
+            violations.push(FieldViolation::new(
+                "stall_timeout_secs",
+                format!(
+                    "must be 0 (disabled) or between {} and {}",
+                    RETRY_STALL_TIMEOUT_SECS_RANGE.start(),
+                    RETRY_STALL_TIMEOUT_SECS_RANGE.end()
+                ),
+            ));
+        }
+        if !RETRY_STALL_RETRY_CHARS_RANGE.contains(&self.stall_retry_chars) {
+            violations.push(FieldViolation::new(
+                "stall_retry_chars",
+                format!(
+                    "must be between {} and {}",
+                    RETRY_STALL_RETRY_CHARS_RANGE.start(),
+                    RETRY_STALL_RETRY_CHARS_RANGE.end()
+                ),
+            ));
+        }
+
+        if violations.is_empty() {
+            Ok(self)
+        } else {
+            Err(violations)
+        }
+    }
+
+    /// 本次请求的尝试次数: 不超过配置值; 账号池较小时限制为账号数 + 1 (至少 2 次),
+    /// 因此 max_attempts = 1 表示不重试
+    pub fn attempts_for_pool(&self, pool_size: usize) -> usize {
+        self.max_attempts.min(pool_size.saturating_add(1).max(2)).max(1)
+    }
+
+    pub fn peek_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.peek_timeout_secs)
+    }
+
+    /// 停滞看门狗时长 (零表示关闭)
+    pub fn stall_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.stall_timeout_secs)
+    }
+
+    /// 按 base_delay_ms 缩放内置退避延迟
+    pub fn scale_delay_ms(&self, builtin_ms: u64) -> u64 {
+        builtin_ms.saturating_mul(self.base_delay_ms) / DEFAULT_RETRY_BASE_DELAY_MS
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum ProxyAuthMode {
@@ -78,16 +243,56 @@ impl Default for ProxyAuthMode {
     }
 }
 
+/// API Key 权限范围
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyScope {
+    /// 模型接口 (/v1/*, /v1beta/* 等)
+    Inference,
+    /// 只读的统计 / 日志接口
+    ReadStats,
+    /// 全部管理接口
+    Admin,
+}
+
+impl KeyScope {
+    pub const ALL: [KeyScope; 3] = [KeyScope::Inference, KeyScope::ReadStats, KeyScope::Admin];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Inference => "inference",
+            Self::ReadStats => "read_stats",
+            Self::Admin => "admin",
+        }
+    }
+}
+
+fn default_key_scopes() -> Vec<KeyScope> {
+    KeyScope::ALL.to_vec()
+}
+
+/// 带权限范围的额外 API Key (例如只给仪表盘使用的只读 Key)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ScopedApiKey {
+    pub key: String,
+    /// 备注 (仅用于展示)
+    #[serde(default)]
+    pub label: String,
+    /// 未填写时拥有全部权限 (兼容旧记录)
+    #[serde(default = "default_key_scopes")]
+    pub scopes: Vec<KeyScope>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum ZaiDispatchMode {
-    /// Never use z.ai.
+    /// Never use this provider.
     Off,
-    /// Use z.ai for all Anthropic protocol requests.
+    /// Use this provider for all requests of its protocols.
     Exclusive,
-    /// Treat z.ai as one additional slot in the shared pool.
+    /// Treat this provider as one additional slot in the shared pool.
     Pooled,
-    /// Use z.ai only when the Google pool is unavailable.
+    /// Use this provider only when the Google pool is unavailable.
     Fallback,
 }
 
@@ -161,6 +366,12 @@ pub struct ZaiConfig {
     pub models: ZaiModelDefaults,
     #[serde(default)]
     pub mcp: ZaiMcpConfig,
+    /// 手动补充的模型 ID (与上游 /v1/models 结果合并, 上游不可用时也会展示)
+    #[serde(default)]
+    pub custom_models: Vec<String>,
+    /// 上游模型列表的缓存时长 (秒)
+    #[serde(default = "default_zai_models_cache_ttl")]
+    pub models_cache_ttl_secs: u64,
 }
 
 impl Default for ZaiConfig {
@@ -173,6 +384,8 @@ impl Default for ZaiConfig {
             model_mapping: HashMap::new(),
             models: ZaiModelDefaults::default(),
             mcp: ZaiMcpConfig::default(),
+            custom_models: Vec::new(),
+            models_cache_ttl_secs: default_zai_models_cache_ttl(),
         }
     }
 }
@@ -188,6 +401,10 @@ pub struct ExperimentalConfig {
     #[serde(default = "default_true")]
     pub enable_tool_loop_recovery: bool,
 
+    /// 工具循环恢复时为未应答的 tool_use 合成的 tool_result 内容
+    #[serde(default = "default_tool_loop_recovery_placeholder")]
+    pub tool_loop_recovery_placeholder: String,
+
     /// 启用跨模型兼容性检查 (Cross-Model Checks)
     #[serde(default = "default_true")]
     pub enable_cross_model_checks: bool,
@@ -198,6 +415,10 @@ pub struct ExperimentalConfig {
     #[serde(default = "default_false")]
     pub enable_usage_scaling: bool,
 
+    /// 开启用量缩放时, 从客户端可见的 input_tokens 中扣除系统提示注入的估算 token
+    #[serde(default = "default_false")]
+    pub hide_system_injections_in_usage: bool,
+
     /// 上下文压缩阈值 L1 (Tool Trimming)
     #[serde(default = "default_threshold_l1")]
     pub context_compression_threshold_l1: f32,
@@ -209,6 +430,210 @@ pub struct ExperimentalConfig {
     /// 上下文压缩阈值 L3 (Fork + Summary)
     #[serde(default = "default_threshold_l3")]
     pub context_compression_threshold_l3: f32,
+
+    /// Layer-3 摘要调用 (context_summary) 使用的模型, 支持自定义映射
+    #[serde(default = "default_context_summary_model")]
+    pub context_summary_model: String,
+
+    /// Layer-3 摘要调用的 max_tokens 上限
+    #[serde(default = "default_context_summary_max_tokens")]
+    pub context_summary_max_tokens: u32,
+
+    /// Layer-1.5: 用摘要模型生成的短摘要替换旧轮次中的大型 tool_result, 而不是直接裁剪 (默认关闭)
+    #[serde(default = "default_false")]
+    pub enable_tool_result_digests: bool,
+
+    /// 每个请求最多发起的 tool_result 摘要调用数 (缓存命中不计), 超出部分按 Layer-1 裁剪
+    #[serde(default = "default_tool_result_digest_budget")]
+    pub tool_result_digest_budget: usize,
+
+    /// Claude Code /compact 请求改用的模型 (长上下文、低成本), 支持自定义映射
+    #[serde(default = "default_compaction_model")]
+    pub compaction_model: String,
+
+    /// /compact 请求允许的 max_tokens 下限 (摘要较长, 避免被截断)
+    #[serde(default = "default_compaction_max_tokens")]
+    pub compaction_max_tokens: u32,
+
+    /// 模型预热池: 定期向指定模型发送 1-token 请求, 降低冷启动延迟 (默认关闭)
+    #[serde(default = "default_false")]
+    pub enable_warm_pool: bool,
+
+    /// 预热池模型列表 (原始模型名, 不做映射)
+    #[serde(default)]
+    pub warm_pool_models: Vec<String>,
+
+    /// 同一模型两次预热的间隔 (分钟)
+    #[serde(default = "default_warm_pool_interval_minutes")]
+    pub warm_pool_interval_minutes: u64,
+
+    /// 仅在最近 N 分钟内有真实请求时预热, 避免空闲时消耗配额
+    #[serde(default = "default_warm_pool_idle_minutes")]
+    pub warm_pool_idle_minutes: u64,
+
+    /// 后续请求中 tool_result 引用了上一轮 assistant 不存在的 tool_use_id 时的处理方式
+    #[serde(default)]
+    pub orphan_tool_result_mode: OrphanToolResultMode,
+
+    /// 历史中 redacted_thinking 块的处理方式
+    #[serde(default)]
+    pub redacted_thinking_mode: RedactedThinkingMode,
+
+    /// 裁掉上游内部重试后重发的重复文本 (新分片前缀与已下发尾部精确重合 ≥ 40 字符)
+    #[serde(default = "default_true")]
+    pub dedup_resent_chunks: bool,
+
+    /// 会话消息数超过该值时提示用户 /compact (每个会话仅一次, 0 = 关闭)
+    #[serde(default = "default_message_advisory_threshold")]
+    pub message_advisory_threshold: usize,
+
+    /// 会话消息数超过该值时无视 token 压力直接触发 Layer-3 (0 = 关闭)
+    #[serde(default = "default_message_force_compress_threshold")]
+    pub message_force_compress_threshold: usize,
+
+    /// 提示文本, `{count}` 会被替换为当前消息数
+    #[serde(default = "default_message_advisory_text")]
+    pub message_advisory_text: String,
+
+    /// 提示的注入方式
+    #[serde(default)]
+    pub message_advisory_mode: MessageAdvisoryMode,
+
+    /// 从不自动开启 thinking (追求速度): 客户端未请求时一律不开启, 忽略 on 策略
+    #[serde(default = "default_false")]
+    pub never_auto_enable_thinking: bool,
+
+    /// count_tokens 使用上游 countTokens 的真实计数 (无可用账号或失败时回退本地估算)。
+    /// 每次计数都会占用一次账号请求, 默认关闭, 需显式开启
+    #[serde(default = "default_false")]
+    pub enable_upstream_count_tokens: bool,
+
+    /// OpenAI strict 工具的参数未通过原始 schema 校验时的处理方式
+    #[serde(default)]
+    pub strict_tool_violation_mode: StrictToolViolationMode,
+
+    /// 含 strict 工具的请求开启 Gemini 的 VALIDATED 函数调用模式 (上游拒绝时本次请求自动回退)
+    #[serde(default = "default_false")]
+    pub strict_tools_validated_mode: bool,
+
+    /// 后台任务 (标题生成 / 摘要 / 提示建议等) 的模型改写
+    #[serde(default)]
+    pub background_routing: BackgroundRoutingConfig,
+}
+
+/// 后台任务路由: 识别出的后台任务改用哪个模型
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackgroundRoutingConfig {
+    /// 关闭后不再识别后台任务, 请求保留客户端指定的模型
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// 按任务类型指定目标模型 (支持自定义映射); 未配置的类型沿用默认:
+    /// /compact 使用 `compaction_model`, 其余使用 `internal-background-task`
+    #[serde(default)]
+    pub routes: HashMap<crate::proxy::handlers::claude::BackgroundTaskType, String>,
+}
+
+impl Default for BackgroundRoutingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            routes: HashMap::new(),
+        }
+    }
+}
+
+impl BackgroundRoutingConfig {
+    /// 去除模型名首尾空白, 空模型名视为不合法
+    pub fn validate(mut self) -> Result<Self, Vec<crate::error::FieldViolation>> {
+        let mut violations = Vec::new();
+        for (task_type, model) in self.routes.iter_mut() {
+            *model = model.trim().to_string();
+            if model.is_empty() {
+                let key = serde_json::to_value(task_type)
+                    .ok()
+                    .and_then(|v| v.as_str().map(|s| s.to_string()))
+                    .unwrap_or_default();
+                violations.push(crate::error::FieldViolation::new(
+                    format!("routes.{}", key),
+                    "model must not be empty (remove the entry to use the default)",
+                ));
+            }
+        }
+        if violations.is_empty() {
+            Ok(self)
+        } else {
+            violations.sort_by(|a, b| a.field.cmp(&b.field));
+            Err(violations)
+        }
+    }
+}
+
+/// strict 工具参数校验失败时的处理方式
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StrictToolViolationMode {
+    /// 追加纠正提示后重新生成一次, 仍失败则按 Annotate 处理
+    #[default]
+    Retry,
+    /// 原样返回, 在违规的 tool_call 上附加 validation_error 字段
+    Annotate,
+}
+
+/// redacted_thinking 块 (不透明的加密思考内容) 的处理方式
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RedactedThinkingMode {
+    /// 原样透传给接受该块的上游 (z.ai 等 Anthropic 兼容 provider); Gemini 上游无法携带, 按 Drop 处理
+    #[default]
+    Preserve,
+    /// 丢弃
+    Drop,
+    /// 替换为简短的文本占位, 让模型知道此处有内容被省略
+    Marker,
+}
+
+impl RedactedThinkingMode {
+    /// 针对目标上游实际生效的处理方式
+    pub fn for_target(self, accepts_redacted: bool) -> Self {
+        match self {
+            RedactedThinkingMode::Preserve if !accepts_redacted => RedactedThinkingMode::Drop,
+            mode => mode,
+        }
+    }
+}
+
+/// 会话过长提示的注入方式
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageAdvisoryMode {
+    /// 在模型输出之后追加一个 text 块
+    #[default]
+    Inline,
+    /// 仅写入 X-Context-Advisory 响应头, 不改动响应内容
+    Header,
+}
+
+/// 客户端未显式指定 thinking 时的默认策略
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ThinkingDefaultPolicy {
+    /// 客户端未指定时自动开启
+    On,
+    /// 始终关闭 (忽略客户端的 thinking 请求)
+    Off,
+    /// 仅在客户端显式请求时开启
+    ClientOnly,
+}
+
+/// 孤立 tool_result 的处理方式
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OrphanToolResultMode {
+    /// 返回 400 invalid_request_error, 列出有效的 tool_use id
+    #[default]
+    Reject,
+    /// 记录警告并丢弃孤立的 tool_result 后继续
+    Drop,
 }
 
 impl Default for ExperimentalConfig {
@@ -216,18 +641,58 @@ impl Default for ExperimentalConfig {
         Self {
             enable_signature_cache: true,
             enable_tool_loop_recovery: true,
+            tool_loop_recovery_placeholder: default_tool_loop_recovery_placeholder(),
             enable_cross_model_checks: true,
             enable_usage_scaling: false,  // 默认关闭,回归透明模式
+            hide_system_injections_in_usage: false,
             context_compression_threshold_l1: 0.4,
             context_compression_threshold_l2: 0.55,
             context_compression_threshold_l3: 0.7,
+            context_summary_model: default_context_summary_model(),
+            context_summary_max_tokens: default_context_summary_max_tokens(),
+            enable_tool_result_digests: false,
+            tool_result_digest_budget: default_tool_result_digest_budget(),
+            compaction_model: default_compaction_model(),
+            compaction_max_tokens: default_compaction_max_tokens(),
+            enable_warm_pool: false,
+            warm_pool_models: Vec::new(),
+            warm_pool_interval_minutes: default_warm_pool_interval_minutes(),
+            warm_pool_idle_minutes: default_warm_pool_idle_minutes(),
+            orphan_tool_result_mode: OrphanToolResultMode::default(),
+            redacted_thinking_mode: RedactedThinkingMode::default(),
+            dedup_resent_chunks: true,
+            message_advisory_threshold: default_message_advisory_threshold(),
+            message_force_compress_threshold: default_message_force_compress_threshold(),
+            message_advisory_text: default_message_advisory_text(),
+            message_advisory_mode: MessageAdvisoryMode::default(),
+            never_auto_enable_thinking: false,
+            enable_upstream_count_tokens: false,
+            strict_tool_violation_mode: StrictToolViolationMode::default(),
+            strict_tools_validated_mode: false,
+            background_routing: BackgroundRoutingConfig::default(),
         }
     }
 }
 
+fn default_tool_loop_recovery_placeholder() -> String {
+    crate::proxy::mappers::claude::thinking_utils::DEFAULT_TOOL_RESULT_PLACEHOLDER.to_string()
+}
+
 fn default_threshold_l1() -> f32 { 0.4 }
 fn default_threshold_l2() -> f32 { 0.55 }
 fn default_threshold_l3() -> f32 { 0.7 }
+fn default_context_summary_model() -> String { "gemini-2.5-flash".to_string() }
+fn default_context_summary_max_tokens() -> u32 { 4096 }
+fn default_tool_result_digest_budget() -> usize { 3 }
+fn default_compaction_model() -> String { "gemini-2.5-flash".to_string() }
+fn default_compaction_max_tokens() -> u32 { 20000 }
+fn default_warm_pool_interval_minutes() -> u64 { 8 }
+fn default_warm_pool_idle_minutes() -> u64 { 30 }
+fn default_message_advisory_threshold() -> usize { 500 }
+fn default_message_force_compress_threshold() -> usize { 800 }
+fn default_message_advisory_text() -> String {
+    "Note: this conversation has {count} messages. Very long conversations slow down every request and degrade answer quality. Run /compact to summarize the history and continue.".to_string()
+}
 
 fn default_true() -> bool {
     true
@@ -341,6 +806,14 @@ pub struct DebugLoggingConfig {
     pub enabled: bool,
     #[serde(default)]
     pub output_dir: Option<String>,
+    /// 允许客户端通过 `x-antigravity-raw-stream` 头请求原始上游流 (passthrough / tee)
+    /// 原始流不经过脱敏, 仅用于排查映射问题
+    #[serde(default)]
+    pub allow_raw_stream: bool,
+    /// 允许客户端通过 `x-antigravity-raw-messages` 头跳过消息规范化 (合并 / 清理 / thinking 过滤)
+    /// 按客户端原样发送对话, 上游可能拒绝, 仅用于复现与上报兼容性问题
+    #[serde(default)]
+    pub allow_raw_messages: bool,
 }
 
 impl Default for DebugLoggingConfig {
@@ -348,6 +821,8 @@ impl Default for DebugLoggingConfig {
         Self {
             enabled: false,
             output_dir: None,
+            allow_raw_stream: false,
+            allow_raw_messages: false,
         }
     }
 }
@@ -375,12 +850,25 @@ pub struct ProxyConfig {
     /// 监听端口
     pub port: u16,
 
+    /// 之前使用过的监听端口 (最近的在前), 保存配置时自动维护
+    #[serde(default)]
+    pub port_history: Vec<u16>,
+
+    /// 管理服务端口 (Web UI / 管理 API), 为空时与 `port` 相同
+    /// 与 `port` 不同时, 反代主端口作为全协议监听端口单独启动
+    #[serde(default)]
+    pub admin_port: Option<u16>,
+
     /// API 密钥
     pub api_key: String,
     
     /// Web UI 管理后台密码 (可选，如未设置则使用 api_key)
     pub admin_password: Option<String>,
 
+    /// 额外的 API Key 及其权限范围; api_key / admin_password 的权限不受此影响
+    #[serde(default)]
+    pub api_keys: Vec<ScopedApiKey>,
+
     /// 是否自动启动
     pub auto_start: bool,
 
@@ -404,6 +892,14 @@ pub struct ProxyConfig {
     #[serde(default)]
     pub upstream_proxy: UpstreamProxyConfig,
 
+    /// 上游 (v1internal) 请求的分阶段超时
+    #[serde(default)]
+    pub upstream_timeouts: UpstreamTimeoutConfig,
+
+    /// 上游 HTTP 连接池与 HTTP/2 保活
+    #[serde(default)]
+    pub upstream_pool: UpstreamPoolConfig,
+
     /// [NEW] 自定义 User-Agent 覆盖 (用于绕过某些检测)
     #[serde(default)]
     pub user_agent_override: Option<String>,
@@ -433,6 +929,326 @@ pub struct ProxyConfig {
     /// Saved User-Agent string (persisted even when override is disabled)
     #[serde(default)]
     pub saved_user_agent: Option<String>,
+
+    /// 额外监听端口 (与主端口共享 AppState/TokenManager/日志)
+    #[serde(default)]
+    pub listeners: Vec<ProxyListenerConfig>,
+
+    /// 主端口允许的协议, 为空表示全部开放 (管理接口不受影响)
+    #[serde(default)]
+    pub primary_protocols: Vec<ListenerProtocol>,
+
+    /// 返回给客户端的上游错误中是否保留账号邮箱 (默认脱敏)
+    #[serde(default)]
+    pub expose_account_emails_in_errors: bool,
+
+    /// 响应头 X-Account-Email 的暴露方式 (日志始终记录真实邮箱)
+    #[serde(default)]
+    pub account_header_privacy: AccountHeaderPrivacy,
+
+    /// 额外上游 provider (按顺序作为 Google 账号池之后的分发层级)
+    /// 旧版 `zai` 配置会在运行时自动合并为 id 为 "zai" 的 provider
+    #[serde(default)]
+    pub providers: Vec<ProviderConfig>,
+
+    /// 用户固定的模型 thinking 支持状态 (物理模型名 -> 是否携带 thinkingConfig)
+    /// 优先于静态规则与运行期学习结果
+    #[serde(default)]
+    pub thinking_overrides: HashMap<String, bool>,
+
+    /// 客户端未指定 thinking 时的默认策略 (物理模型名 -> on / off / client_only)
+    /// 未列出的模型沿用内置规则 (Opus 4.5/4.6、Gemini Pro、-thinking 变体默认开启)
+    #[serde(default)]
+    pub thinking_defaults: HashMap<String, ThinkingDefaultPolicy>,
+
+    /// 开启 thinking 且客户端未指定 budget_tokens 时的默认预算 (物理模型名 -> token 数)
+    /// 未列出的模型使用 16000 (仍受 thinking_budget 上限约束)
+    #[serde(default)]
+    pub thinking_budget_defaults: HashMap<String, u32>,
+
+    /// 按自定义映射条目覆盖 thinking 默认策略 (key 与 custom_mapping 相同, 优先于 thinking_defaults)
+    #[serde(default)]
+    pub mapping_thinking_defaults: HashMap<String, ThinkingDefaultPolicy>,
+
+    /// 助手文本后处理 (查找替换 / 末尾停止短语), 默认关闭
+    #[serde(default)]
+    pub post_process: PostProcessConfig,
+
+    /// 按 API Key 的每日预算 (key: API Key, 独立于账号配额); 未列出的 Key 不受限制
+    #[serde(default)]
+    pub key_budgets: HashMap<String, KeyBudget>,
+
+    /// 启动时账号体检 (默认关闭, 关闭时启动不做额外探测)
+    #[serde(default)]
+    pub startup_verification: StartupVerificationConfig,
+
+    /// 主链路重试策略 (尝试次数 / 首块超时 / 退避基准), 修改后立即生效
+    #[serde(default)]
+    pub retry: RetryConfig,
+
+    /// 系统提示注入模板 (模板名 -> 内容与位置), 通过下面两项挂到 API Key 或映射条目上
+    #[serde(default)]
+    pub system_injection_profiles: HashMap<String, SystemInjectionProfile>,
+
+    /// 按 API Key 注入的模板名 (key: API Key)
+    #[serde(default)]
+    pub key_system_injections: HashMap<String, Vec<String>>,
+
+    /// 按自定义映射条目注入的模板名 (key 与 custom_mapping 相同)
+    #[serde(default)]
+    pub mapping_system_injections: HashMap<String, Vec<String>>,
+
+    /// 图片接口 response_format=url 时生成图片的保留时长 (秒), 过期后清除, 链接失效
+    #[serde(default = "default_image_url_ttl_secs")]
+    pub image_url_ttl_secs: u64,
+}
+
+/// 系统提示注入模板 (对客户端不可见)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SystemInjectionProfile {
+    pub text: String,
+    /// 放在客户端 system 内容之前或之后
+    #[serde(default)]
+    pub position: InjectionPosition,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InjectionPosition {
+    Prepend,
+    #[default]
+    Append,
+}
+
+/// 启动账号体检配置
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StartupVerificationConfig {
+    /// 启动服务时是否先体检所有账号再就绪
+    pub enabled: bool,
+    /// 体检结果缓存时长 (秒), 期间重启不重复探测
+    pub cache_ttl_secs: u64,
+}
+
+impl Default for StartupVerificationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            cache_ttl_secs: 6 * 3600,
+        }
+    }
+}
+
+/// 单个 API Key 的每日预算, 0 表示不限制
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyBudget {
+    /// 每日 token 上限 (输入 + 输出)
+    pub tokens_per_day: u64,
+    /// 每日请求数上限
+    pub requests_per_day: u64,
+}
+
+impl KeyBudget {
+    pub fn is_unlimited(&self) -> bool {
+        self.tokens_per_day == 0 && self.requests_per_day == 0
+    }
+}
+
+/// 助手文本后处理配置
+///
+/// 只作用于 text 块 (不处理 thinking 与工具参数)。
+/// 全局规则先应用, 命中 `per_model` 时再追加该模型的规则。
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PostProcessConfig {
+    pub enabled: bool,
+    /// 对所有模型生效的规则
+    pub global: PostProcessProfile,
+    /// 按模型名追加的规则 (key 为客户端请求的模型名或映射后的模型名, 不区分大小写)
+    pub per_model: HashMap<String, PostProcessProfile>,
+}
+
+/// 一组后处理规则
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PostProcessProfile {
+    /// 按顺序应用的查找替换规则
+    pub rules: Vec<ReplaceRule>,
+    /// 出现在文本末尾时裁剪掉的短语
+    pub stop_phrases: Vec<String>,
+}
+
+/// 查找替换规则
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplaceRule {
+    /// 统计中显示的名称 (为空时使用 find)
+    #[serde(default)]
+    pub name: Option<String>,
+    pub find: String,
+    #[serde(default)]
+    pub replace: String,
+    /// find 是否为正则表达式 (replace 中可用 $1 / ${name} 引用分组)
+    #[serde(default)]
+    pub regex: bool,
+}
+
+/// X-Account-Email 响应头隐私级别
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountHeaderPrivacy {
+    /// 真实邮箱 (默认, 兼容现有行为)
+    #[default]
+    Full,
+    /// 按账号稳定的化名, 例如 `acct-3f2a9c01`
+    Pseudonym,
+    /// 不返回该响应头
+    Omit,
+}
+
+/// 额外监听端口可开放的协议面
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum ListenerProtocol {
+    /// /v1/messages (Claude Code 等)
+    Anthropic,
+    /// /v1/chat/completions, /v1/responses, images, audio
+    #[serde(rename = "openai")]
+    OpenAI,
+    /// /v1beta/models/*
+    Gemini,
+}
+
+impl ListenerProtocol {
+    pub const ALL: [ListenerProtocol; 3] = [
+        ListenerProtocol::Anthropic,
+        ListenerProtocol::OpenAI,
+        ListenerProtocol::Gemini,
+    ];
+
+    /// 去重后的协议集合, 空配置视为全部
+    pub fn allowed(configured: &[ListenerProtocol]) -> Vec<ListenerProtocol> {
+        if configured.is_empty() {
+            return Self::ALL.to_vec();
+        }
+        let mut protocols = Vec::new();
+        for protocol in configured {
+            if !protocols.contains(protocol) {
+                protocols.push(*protocol);
+            }
+        }
+        protocols
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ListenerProtocol::Anthropic => "anthropic",
+            ListenerProtocol::OpenAI => "openai",
+            ListenerProtocol::Gemini => "gemini",
+        }
+    }
+}
+
+/// 额外监听端口配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProxyListenerConfig {
+    pub port: u16,
+
+    /// 允许的协议, 为空表示全部开放
+    #[serde(default)]
+    pub protocols: Vec<ListenerProtocol>,
+
+    /// 独立 API Key; 设置后该端口总是要求鉴权 (即使全局 auth_mode 为 off)
+    #[serde(default)]
+    pub api_key: Option<String>,
+
+    /// 叠加在全局 custom_mapping 之上的模型映射 (同名 key 覆盖全局)
+    #[serde(default)]
+    pub custom_mapping: HashMap<String, String>,
+}
+
+impl ProxyListenerConfig {
+    /// 实际允许的协议集合 (空配置视为全部)
+    pub fn allowed_protocols(&self) -> Vec<ListenerProtocol> {
+        ListenerProtocol::allowed(&self.protocols)
+    }
+}
+
+/// 额外上游 provider (Anthropic / OpenAI 兼容端点, 例如 OpenRouter、自建 vLLM)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderConfig {
+    /// 唯一标识, 用于日志/统计归属; "zai" 保留给 z.ai
+    pub id: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// 不含协议路径的根地址, 例如 `https://openrouter.ai/api`
+    #[serde(default)]
+    pub base_url: String,
+    #[serde(default)]
+    pub api_key: String,
+    /// 该端点支持的协议 (默认仅 anthropic)
+    #[serde(default = "default_provider_protocols")]
+    pub protocols: Vec<ListenerProtocol>,
+    #[serde(default)]
+    pub dispatch_mode: ZaiDispatchMode,
+    /// 允许路由到该 provider 的模型 (为空表示全部)
+    #[serde(default)]
+    pub models: Vec<String>,
+    /// 请求模型 -> 上游模型
+    #[serde(default)]
+    pub model_mapping: HashMap<String, String>,
+}
+
+impl ProviderConfig {
+    /// 由旧版 z.ai 配置迁移而来的 provider
+    pub fn from_zai(zai: &ZaiConfig) -> Self {
+        Self {
+            id: "zai".to_string(),
+            enabled: zai.enabled,
+            base_url: zai.base_url.clone(),
+            api_key: zai.api_key.clone(),
+            protocols: vec![ListenerProtocol::Anthropic],
+            dispatch_mode: zai.dispatch_mode.clone(),
+            models: Vec::new(),
+            model_mapping: zai.model_mapping.clone(),
+        }
+    }
+
+    pub fn allows_model(&self, model: &str) -> bool {
+        self.models.is_empty()
+            || self
+                .models
+                .iter()
+                .any(|m| {
+                    m.eq_ignore_ascii_case(model)
+                        || (m.ends_with('*') && model.starts_with(m.trim_end_matches('*')))
+                })
+    }
+}
+
+/// 实际生效的 provider 列表: 未显式配置 "zai" 时, 旧版 zai 设置排在最前
+pub fn effective_providers(zai: &ZaiConfig, providers: &[ProviderConfig]) -> Vec<ProviderConfig> {
+    let mut out = Vec::with_capacity(providers.len() + 1);
+    if !providers.iter().any(|p| p.id == "zai") {
+        out.push(ProviderConfig::from_zai(zai));
+    }
+    for provider in providers {
+        if provider.id == "zai" {
+            // 显式条目只决定顺序/白名单, 端点与密钥仍以 zai 设置为准
+            let mut merged = ProviderConfig::from_zai(zai);
+            merged.enabled = zai.enabled && provider.enabled;
+            merged.models = provider.models.clone();
+            out.push(merged);
+        } else {
+            out.push(provider.clone());
+        }
+    }
+    out.retain(|p| p.enabled && p.dispatch_mode != ZaiDispatchMode::Off);
+    out
+}
+
+fn default_provider_protocols() -> Vec<ListenerProtocol> {
+    vec![ListenerProtocol::Anthropic]
 }
 
 /// 上游代理配置
@@ -444,21 +1260,106 @@ pub struct UpstreamProxyConfig {
     pub url: String,
 }
 
+/// 上游请求超时配置 (秒)
+///
+/// 连接应快速失败以便尽早换号; 流式请求不设总时长上限 (由 handler 的首包 peek 超时兜底)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpstreamTimeoutConfig {
+    /// TCP 连接超时
+    #[serde(default = "default_upstream_connect_timeout")]
+    pub connect_timeout_secs: u64,
+    /// TLS 握手超时 (叠加在 TCP 连接超时之上, 两者之和为 reqwest 的 connect_timeout)
+    #[serde(default = "default_upstream_tls_handshake_timeout")]
+    pub tls_handshake_timeout_secs: u64,
+    /// 流式请求等待响应头的超时
+    #[serde(default = "default_upstream_first_byte_timeout")]
+    pub first_byte_timeout_secs: u64,
+    /// 非流式请求的读取超时 (含等待完整响应)
+    #[serde(default = "default_upstream_read_timeout")]
+    pub read_timeout_secs: u64,
+    /// 轻量辅助调用 (标题生成、预热等) 的总预算
+    #[serde(default = "default_upstream_quick_timeout")]
+    pub quick_timeout_secs: u64,
+}
+
+impl Default for UpstreamTimeoutConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout_secs: default_upstream_connect_timeout(),
+            tls_handshake_timeout_secs: default_upstream_tls_handshake_timeout(),
+            first_byte_timeout_secs: default_upstream_first_byte_timeout(),
+            read_timeout_secs: default_upstream_read_timeout(),
+            quick_timeout_secs: default_upstream_quick_timeout(),
+        }
+    }
+}
+
+fn default_upstream_connect_timeout() -> u64 { 10 }
+fn default_upstream_tls_handshake_timeout() -> u64 { 5 }
+fn default_upstream_first_byte_timeout() -> u64 { 60 }
+fn default_upstream_read_timeout() -> u64 { 600 }
+fn default_upstream_quick_timeout() -> u64 { 20 }
+
+/// 上游 HTTP 连接池与 HTTP/2 保活配置
+///
+/// Google 前端会断开空闲连接, 复用已被对端关闭的池化连接会在请求发出后立即 connection reset。
+/// 默认空闲超时低于 Google 的空闲断开时间, 并每 30s 发送 HTTP/2 PING 保活
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UpstreamPoolConfig {
+    /// 池中空闲连接的保留时间 (秒)
+    #[serde(default = "default_pool_idle_timeout")]
+    pub pool_idle_timeout_secs: u64,
+    /// 每个主机保留的最大空闲连接数 (环境变量 ABV_POOL_SIZE 优先)
+    #[serde(default = "default_pool_max_idle_per_host")]
+    pub pool_max_idle_per_host: usize,
+    /// HTTP/2 PING 保活间隔 (秒, 0 = 关闭); 空闲连接同样发送
+    #[serde(default = "default_http2_keep_alive_interval")]
+    pub http2_keep_alive_interval_secs: u64,
+    /// 等待 PING 响应的超时 (秒), 超时则关闭该连接
+    #[serde(default = "default_http2_keep_alive_timeout")]
+    pub http2_keep_alive_timeout_secs: u64,
+    /// HTTP/2 自适应流控窗口
+    #[serde(default = "default_true")]
+    pub http2_adaptive_window: bool,
+}
+
+impl Default for UpstreamPoolConfig {
+    fn default() -> Self {
+        Self {
+            pool_idle_timeout_secs: default_pool_idle_timeout(),
+            pool_max_idle_per_host: default_pool_max_idle_per_host(),
+            http2_keep_alive_interval_secs: default_http2_keep_alive_interval(),
+            http2_keep_alive_timeout_secs: default_http2_keep_alive_timeout(),
+            http2_adaptive_window: true,
+        }
+    }
+}
+
+fn default_pool_idle_timeout() -> u64 { 45 }
+fn default_pool_max_idle_per_host() -> usize { 64 }
+fn default_http2_keep_alive_interval() -> u64 { 30 }
+fn default_http2_keep_alive_timeout() -> u64 { 10 }
+
 impl Default for ProxyConfig {
     fn default() -> Self {
         Self {
             enabled: false,
             allow_lan_access: false, // 默认仅本机访问，隐私优先
             auth_mode: ProxyAuthMode::default(),
-            port: 8045,
+            port: crate::proxy::ports::DEFAULT_PORT,
+            port_history: Vec::new(),
+            admin_port: None,
             api_key: format!("sk-{}", uuid::Uuid::new_v4().simple()),
             admin_password: None,
+            api_keys: Vec::new(),
             auto_start: false,
             custom_mapping: std::collections::HashMap::new(),
             request_timeout: default_request_timeout(),
             enable_logging: true, // 默认开启，支持 token 统计功能
             debug_logging: DebugLoggingConfig::default(),
             upstream_proxy: UpstreamProxyConfig::default(),
+            upstream_timeouts: UpstreamTimeoutConfig::default(),
+            upstream_pool: UpstreamPoolConfig::default(),
             user_agent_override: None,
             zai: ZaiConfig::default(),
             scheduling: crate::proxy::sticky_config::StickySessionConfig::default(),
@@ -466,10 +1367,31 @@ impl Default for ProxyConfig {
             security_monitor: SecurityMonitorConfig::default(),
             preferred_account_id: None,
             saved_user_agent: None,
+            listeners: Vec::new(),
+            primary_protocols: Vec::new(),
+            expose_account_emails_in_errors: false,
+            account_header_privacy: AccountHeaderPrivacy::default(),
+            providers: Vec::new(),
+            thinking_overrides: HashMap::new(),
+            thinking_defaults: HashMap::new(),
+            thinking_budget_defaults: HashMap::new(),
+            mapping_thinking_defaults: HashMap::new(),
+            post_process: PostProcessConfig::default(),
+            key_budgets: HashMap::new(),
+            startup_verification: StartupVerificationConfig::default(),
+            retry: RetryConfig::default(),
+            system_injection_profiles: HashMap::new(),
+            key_system_injections: HashMap::new(),
+            mapping_system_injections: HashMap::new(),
+            image_url_ttl_secs: default_image_url_ttl_secs(),
         }
     }
 }
 
+fn default_image_url_ttl_secs() -> u64 {
+    3600
+}
+
 fn default_request_timeout() -> u64 {
     120 // 默认 120 秒,原来 60 秒太短
 }
@@ -478,6 +1400,10 @@ fn default_zai_base_url() -> String {
     "https://api.z.ai/api/anthropic".to_string()
 }
 
+fn default_zai_models_cache_ttl() -> u64 {
+    3600
+}
+
 fn default_zai_opus_model() -> String {
     "glm-4.7".to_string()
 }
@@ -501,4 +1427,26 @@ impl ProxyConfig {
             "127.0.0.1"
         }
     }
+
+    /// 管理服务实际使用的端口
+    pub fn admin_port(&self) -> u16 {
+        self.admin_port.unwrap_or(self.port)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attempts_for_pool_respects_configured_max() {
+        let config = |max_attempts| RetryConfig { max_attempts, ..Default::default() };
+
+        assert_eq!(config(1).attempts_for_pool(0), 1);
+        assert_eq!(config(1).attempts_for_pool(5), 1);
+        assert_eq!(config(3).attempts_for_pool(0), 2);
+        assert_eq!(config(3).attempts_for_pool(1), 2);
+        assert_eq!(config(3).attempts_for_pool(5), 3);
+        assert_eq!(config(10).attempts_for_pool(3), 4);
+    }
 }
@@ -1,18 +1,89 @@
+use serde::Serialize;
 use serde_json::Value;
 use tokio::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use futures::StreamExt;
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 use crate::proxy::config::DebugLoggingConfig;
+use crate::proxy::upstream::response_ids::UpstreamIds;
+
+/// 文件名中 trace_id / prefix 单个组件的最大字节数
+const MAX_COMPONENT_BYTES: usize = 64;
+/// Windows 目录路径超过该长度时改用 \\?\ 扩展长度前缀 (MAX_PATH 260, 目录需为文件名预留 12 个字符)
+const WINDOWS_MAX_DIR_PATH: usize = 248;
+/// Windows 文件完整路径超过该长度时改用扩展长度前缀 (MAX_PATH 260 含结尾 NUL)
+const WINDOWS_MAX_FILE_PATH: usize = 259;
+
+/// 清理文件名组件: 替换 Windows 非法字符与控制字符, 去掉结尾的点和空格, 按字节截断
+fn sanitize_component(raw: &str) -> String {
+    let mut out = String::with_capacity(raw.len().min(MAX_COMPONENT_BYTES));
+    for c in raw.chars() {
+        let c = if c.is_control() || matches!(c, '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*') {
+            '_'
+        } else {
+            c
+        };
+        if out.len() + c.len_utf8() > MAX_COMPONENT_BYTES {
+            break;
+        }
+        out.push(c);
+    }
+    let trimmed = out.trim_end_matches(['.', ' ']);
+    if trimmed.is_empty() {
+        "unknown".to_string()
+    } else {
+        trimmed.to_string()
+    }
+}
 
 fn build_filename(prefix: &str, trace_id: Option<&str>) -> String {
     let ts = chrono::Utc::now().format("%Y%m%d_%H%M%S%.3f");
-    let tid = trace_id.unwrap_or("unknown");
-    format!("{}_{}_{}.json", ts, tid, prefix)
+    let tid = sanitize_component(trace_id.unwrap_or("unknown"));
+    format!("{}_{}_{}.json", ts, tid, sanitize_component(prefix))
+}
+
+/// 为绝对 Windows 路径加上扩展长度前缀: `C:\x` -> `\\?\C:\x`, `\\server\share` -> `\\?\UNC\server\share`
+/// 相对路径与已带前缀的路径原样返回
+fn to_extended_length(path: &str) -> String {
+    if path.starts_with(r"\\?\") {
+        return path.to_string();
+    }
+    let normalized = path.replace('/', r"\");
+    if let Some(unc) = normalized.strip_prefix(r"\\") {
+        return format!(r"\\?\UNC\{}", unc);
+    }
+    let bytes = normalized.as_bytes();
+    if bytes.len() >= 3 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' && bytes[2] == b'\\' {
+        return format!(r"\\?\{}", normalized);
+    }
+    path.to_string()
+}
+
+/// 路径长度达到上限时加扩展长度前缀 (按完整路径判断, 与平台无关)
+fn extend_if_too_long(path: &str, max_len: usize) -> String {
+    if path.len() < max_len {
+        path.to_string()
+    } else {
+        to_extended_length(path)
+    }
+}
+
+/// Windows 上过长的路径改用扩展长度路径, 其他平台原样返回。
+/// 目录与文件分别判断: 目录较短时, 加上文件名后的完整路径仍可能超过 MAX_PATH
+fn platform_path(path: PathBuf, max_len: usize) -> PathBuf {
+    if !cfg!(windows) {
+        return path;
+    }
+    PathBuf::from(extend_if_too_long(&path.to_string_lossy(), max_len))
 }
 
 fn resolve_output_dir(cfg: &DebugLoggingConfig) -> Option<PathBuf> {
-    if let Some(dir) = cfg.output_dir.as_ref() {
+    if let Some(dir) = cfg.output_dir.as_deref().map(str::trim).filter(|d| !d.is_empty()) {
         return Some(PathBuf::from(dir));
     }
     if let Ok(data_dir) = crate::modules::account::get_data_dir() {
@@ -21,6 +92,97 @@ fn resolve_output_dir(cfg: &DebugLoggingConfig) -> Option<PathBuf> {
     None
 }
 
+/// 调试载荷写入失败 (启动以来), 随 get_proxy_stats 返回
+static WRITE_FAILURES: WriteFailures = WriteFailures::new();
+
+/// 首次写入失败的警告 (proxy://debug-log-write-failed 事件)
+#[derive(Debug, Clone, Serialize)]
+pub struct DebugLogWriteFailure {
+    pub path: String,
+    pub error: String,
+}
+
+/// 写入失败计数与一次性警告
+struct WriteFailures {
+    count: AtomicU64,
+    /// 首次失败只通知一次
+    warned: AtomicBool,
+    pending: Mutex<Option<DebugLogWriteFailure>>,
+}
+
+impl WriteFailures {
+    const fn new() -> Self {
+        Self {
+            count: AtomicU64::new(0),
+            warned: AtomicBool::new(false),
+            pending: Mutex::new(None),
+        }
+    }
+
+    fn record(&self, path: &Path, error: String) {
+        tracing::warn!("[Debug-Log] Failed to write {:?}: {}", path, error);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        if !self.warned.swap(true, Ordering::Relaxed) {
+            *self.pending.lock().unwrap_or_else(|e| e.into_inner()) = Some(DebugLogWriteFailure {
+                path: path.to_string_lossy().into_owned(),
+                error,
+            });
+        }
+    }
+
+    fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    fn take_warning(&self) -> Option<DebugLogWriteFailure> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner()).take()
+    }
+}
+
+/// 启动以来调试载荷写入失败次数
+pub fn write_failures() -> u64 {
+    WRITE_FAILURES.count()
+}
+
+/// 领取尚未通知的首次写入失败警告
+pub fn take_write_failure_warning() -> Option<DebugLogWriteFailure> {
+    WRITE_FAILURES.take_warning()
+}
+
+/// 超过该长度的 base64 内联数据 (图片 / 文档) 在调试输出中只保留长度
+const MAX_INLINE_DATA_CHARS: usize = 256;
+
+/// 去掉载荷中大段的 base64 内联数据 (inlineData.data / source.data / data: URL), 避免调试文件被图片撑大
+pub fn without_inline_data(payload: &Value) -> Value {
+    let mut value = payload.clone();
+    elide_inline_data(&mut value);
+    value
+}
+
+fn elide_inline_data(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, v) in map.iter_mut() {
+                let elide = match v {
+                    Value::String(s) if s.len() > MAX_INLINE_DATA_CHARS => {
+                        (key == "data" && !s.contains(char::is_whitespace))
+                            || (key == "url" && s.starts_with("data:"))
+                    }
+                    _ => false,
+                };
+                if elide {
+                    let len = v.as_str().map(str::len).unwrap_or(0);
+                    *v = Value::String(format!("[base64 omitted: {} chars]", len));
+                } else {
+                    elide_inline_data(v);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(elide_inline_data),
+        _ => {}
+    }
+}
+
 pub async fn write_debug_payload(
     cfg: &DebugLoggingConfig,
     trace_id: Option<&str>,
@@ -39,18 +201,21 @@ pub async fn write_debug_payload(
         }
     };
 
-    if let Err(e) = fs::create_dir_all(&output_dir).await {
-        tracing::warn!("[Debug-Log] Failed to create output dir: {}", e);
+    write_payload_to(&output_dir, &build_filename(prefix, trace_id), payload, &WRITE_FAILURES).await;
+}
+
+async fn write_payload_to(output_dir: &Path, filename: &str, payload: &Value, failures: &WriteFailures) {
+    let dir = platform_path(output_dir.to_path_buf(), WINDOWS_MAX_DIR_PATH);
+    if let Err(e) = fs::create_dir_all(&dir).await {
+        failures.record(&dir, format!("create dir: {}", e));
         return;
     }
 
-    let filename = build_filename(prefix, trace_id);
-    let path = output_dir.join(filename);
-
-    match serde_json::to_vec_pretty(payload) {
+    let path = platform_path(output_dir.join(filename), WINDOWS_MAX_FILE_PATH);
+    match serde_json::to_vec_pretty(&without_inline_data(payload)) {
         Ok(bytes) => {
             if let Err(e) = fs::write(&path, bytes).await {
-                tracing::warn!("[Debug-Log] Failed to write file: {}", e);
+                failures.record(&path, e.to_string());
             }
         }
         Err(e) => {
@@ -127,6 +292,65 @@ fn parse_sse_stream(raw: &str) -> (String, String) {
     (thinking_parts.join(""), content_parts.join(""))
 }
 
+struct UpstreamDebugGuard {
+    cfg: DebugLoggingConfig,
+    trace_id: String,
+    prefix: &'static str,
+    meta: Value,
+    collected: Vec<u8>,
+    finished: bool,
+}
+
+impl UpstreamDebugGuard {
+    fn payload(&self, cancelled: bool) -> Value {
+        let raw_text = String::from_utf8_lossy(&self.collected).to_string();
+        let (thinking_content, response_content) = parse_sse_stream(&raw_text);
+
+        let mut payload = serde_json::json!({
+            "kind": "upstream_response",
+            "trace_id": self.trace_id,
+            "meta": self.meta,
+        });
+        if cancelled {
+            payload["cancelled"] = Value::Bool(true);
+        }
+
+        // 只有在有内容时才添加对应字段
+        if !thinking_content.is_empty() {
+            payload["thinking_content"] = Value::String(thinking_content);
+        }
+        if !response_content.is_empty() {
+            payload["response_content"] = Value::String(response_content);
+        }
+        let ids = UpstreamIds::from_sse(&raw_text);
+        if let Some(id) = ids.response_id {
+            payload["response_id"] = Value::String(id);
+        }
+        if let Some(version) = ids.model_version {
+            payload["model_version"] = Value::String(version);
+        }
+        payload
+    }
+}
+
+impl Drop for UpstreamDebugGuard {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let payload = self.payload(true);
+        let cfg = self.cfg.clone();
+        let trace_id = self.trace_id.clone();
+        let prefix = self.prefix;
+        handle.spawn(async move {
+            write_debug_payload(&cfg, Some(&trace_id), prefix, &payload).await;
+        });
+    }
+}
+
 pub fn wrap_reqwest_stream_with_debug(
     stream: std::pin::Pin<Box<dyn futures::Stream<Item = Result<bytes::Bytes, reqwest::Error>> + Send>>,
     cfg: DebugLoggingConfig,
@@ -139,34 +363,247 @@ pub fn wrap_reqwest_stream_with_debug(
     }
 
     let wrapped = async_stream::stream! {
-        let mut collected: Vec<u8> = Vec::new();
+        // 客户端断开时 stream 被直接丢弃, 由 guard 在 Drop 中补写部分内容
+        let mut guard = UpstreamDebugGuard {
+            cfg,
+            trace_id,
+            prefix,
+            meta,
+            collected: Vec::new(),
+            finished: false,
+        };
         let mut inner = stream;
         while let Some(item) = inner.next().await {
             if let Ok(bytes) = &item {
-                collected.extend_from_slice(bytes);
+                guard.collected.extend_from_slice(bytes);
             }
             yield item;
         }
 
-        let raw_text = String::from_utf8_lossy(&collected).to_string();
-        let (thinking_content, response_content) = parse_sse_stream(&raw_text);
-        
-        let mut payload = serde_json::json!({
-            "kind": "upstream_response",
-            "trace_id": trace_id,
-            "meta": meta,
-        });
-        
-        // 只有在有内容时才添加对应字段
-        if !thinking_content.is_empty() {
-            payload["thinking_content"] = serde_json::Value::String(thinking_content);
-        }
-        if !response_content.is_empty() {
-            payload["response_content"] = serde_json::Value::String(response_content);
-        }
-
-        write_debug_payload(&cfg, Some(&payload["trace_id"].as_str().unwrap_or("unknown")), prefix, &payload).await;
+        guard.finished = true;
+        let payload = guard.payload(false);
+        write_debug_payload(&guard.cfg, Some(&guard.trace_id), guard.prefix, &payload).await;
     };
 
     Box::pin(wrapped)
 }
+
+/// 客户端请求原始上游流的调试头
+pub const RAW_STREAM_HEADER: &str = "x-antigravity-raw-stream";
+/// tee 模式下响应携带的 trace id, 监控中间件据此关联原始流
+pub const RAW_TRANSCRIPT_HEADER: &str = "X-Raw-Transcript";
+/// 单个原始流记录上限
+const MAX_RAW_TRANSCRIPT_BYTES: usize = 8 * 1024 * 1024;
+/// 未被领取的原始流记录保留时长
+const RAW_TRANSCRIPT_TTL: Duration = Duration::from_secs(600);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawStreamMode {
+    /// 不做映射, 直接转发上游 SSE 字节
+    Passthrough,
+    /// 正常映射, 同时记录原始上游 SSE 供日志详情查看
+    Tee,
+}
+
+/// 解析调试头; 未开启 allow_raw_stream 时始终返回 None
+pub fn raw_stream_mode(cfg: &DebugLoggingConfig, headers: &axum::http::HeaderMap) -> Option<RawStreamMode> {
+    let value = headers.get(RAW_STREAM_HEADER)?.to_str().ok()?.trim().to_ascii_lowercase();
+    let mode = match value.as_str() {
+        "true" | "1" | "passthrough" => RawStreamMode::Passthrough,
+        "tee" => RawStreamMode::Tee,
+        _ => return None,
+    };
+    if !cfg.allow_raw_stream {
+        tracing::warn!(
+            "[Debug-Log] {} requested but debug_logging.allow_raw_stream is disabled, ignoring",
+            RAW_STREAM_HEADER
+        );
+        return None;
+    }
+    Some(mode)
+}
+
+/// 客户端请求跳过消息规范化的调试头
+pub const RAW_MESSAGES_HEADER: &str = "x-antigravity-raw-messages";
+/// 响应标记: 本次请求按原始消息发送, 监控中间件据此标记日志
+pub const RAW_MESSAGES_RESPONSE_HEADER: &str = "X-Raw-Messages";
+
+/// 解析原始消息调试头; 未开启 allow_raw_messages 时始终返回 false
+pub fn raw_messages_mode(cfg: &DebugLoggingConfig, headers: &axum::http::HeaderMap) -> bool {
+    let requested = headers
+        .get(RAW_MESSAGES_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| matches!(v.trim().to_ascii_lowercase().as_str(), "true" | "1"));
+    if requested && !cfg.allow_raw_messages {
+        tracing::warn!(
+            "[Debug-Log] {} requested but debug_logging.allow_raw_messages is disabled, ignoring",
+            RAW_MESSAGES_HEADER
+        );
+        return false;
+    }
+    requested
+}
+
+/// trace_id -> (记录时间, 原始上游字节)
+static RAW_TRANSCRIPTS: Lazy<DashMap<String, (Instant, Vec<u8>)>> = Lazy::new(DashMap::new);
+
+/// tee 模式: 透传上游流的同时记录原始字节
+pub fn tee_raw_stream(
+    stream: std::pin::Pin<Box<dyn futures::Stream<Item = Result<bytes::Bytes, reqwest::Error>> + Send>>,
+    trace_id: String,
+) -> std::pin::Pin<Box<dyn futures::Stream<Item = Result<bytes::Bytes, reqwest::Error>> + Send>> {
+    RAW_TRANSCRIPTS.retain(|_, (t, _)| t.elapsed() < RAW_TRANSCRIPT_TTL);
+    RAW_TRANSCRIPTS.insert(trace_id.clone(), (Instant::now(), Vec::new()));
+
+    Box::pin(stream.map(move |item| {
+        if let Ok(bytes) = &item {
+            if let Some(mut entry) = RAW_TRANSCRIPTS.get_mut(&trace_id) {
+                let buf = &mut entry.value_mut().1;
+                let room = MAX_RAW_TRANSCRIPT_BYTES.saturating_sub(buf.len());
+                buf.extend_from_slice(&bytes[..bytes.len().min(room)]);
+            }
+        }
+        item
+    }))
+}
+
+/// 领取某个 trace 的原始上游流 (领取后移除)
+pub fn take_raw_transcript(trace_id: &str) -> Option<String> {
+    RAW_TRANSCRIPTS
+        .remove(trace_id)
+        .map(|(_, (_, bytes))| String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inline_data_is_elided() {
+        let image = "iVBORw0KGgo".repeat(100);
+        let payload = serde_json::json!({
+            "request": {
+                "contents": [{ "parts": [
+                    { "text": "describe this" },
+                    { "inlineData": { "mimeType": "image/png", "data": image } }
+                ]}]
+            },
+            "messages": [{ "content": [
+                { "type": "image_url", "image_url": { "url": format!("data:image/png;base64,{}", image) } },
+                { "type": "redacted_thinking", "data": "short" }
+            ]}]
+        });
+        let elided = without_inline_data(&payload);
+        let part = &elided["request"]["contents"][0]["parts"][1]["inlineData"];
+        assert_eq!(part["data"], "[base64 omitted: 1100 chars]");
+        assert_eq!(part["mimeType"], "image/png");
+        assert_eq!(elided["request"]["contents"][0]["parts"][0]["text"], "describe this");
+        let blocks = &elided["messages"][0]["content"];
+        assert_eq!(blocks[0]["image_url"]["url"], "[base64 omitted: 1122 chars]");
+        assert_eq!(blocks[1]["data"], "short");
+    }
+
+    #[test]
+    fn test_raw_stream_mode_requires_config_flag() {
+        let mut headers = axum::http::HeaderMap::new();
+        headers.insert(RAW_STREAM_HEADER, "tee".parse().unwrap());
+
+        let mut cfg = DebugLoggingConfig::default();
+        assert_eq!(raw_stream_mode(&cfg, &headers), None);
+
+        cfg.allow_raw_stream = true;
+        assert_eq!(raw_stream_mode(&cfg, &headers), Some(RawStreamMode::Tee));
+        headers.insert(RAW_STREAM_HEADER, "true".parse().unwrap());
+        assert_eq!(raw_stream_mode(&cfg, &headers), Some(RawStreamMode::Passthrough));
+        headers.insert(RAW_STREAM_HEADER, "false".parse().unwrap());
+        assert_eq!(raw_stream_mode(&cfg, &headers), None);
+    }
+
+    #[test]
+    fn test_raw_messages_mode_requires_config_flag() {
+        let mut headers = axum::http::HeaderMap::new();
+        let mut cfg = DebugLoggingConfig::default();
+        assert!(!raw_messages_mode(&cfg, &headers));
+
+        headers.insert(RAW_MESSAGES_HEADER, "true".parse().unwrap());
+        assert!(!raw_messages_mode(&cfg, &headers));
+
+        cfg.allow_raw_messages = true;
+        assert!(raw_messages_mode(&cfg, &headers));
+        headers.insert(RAW_MESSAGES_HEADER, "false".parse().unwrap());
+        assert!(!raw_messages_mode(&cfg, &headers));
+    }
+
+    #[test]
+    fn test_filename_components_are_sanitized() {
+        let name = build_filename("upstream:resp", Some("req<1>|\"a\"/b\\c?*\n. "));
+        assert!(name.ends_with("_req_1___a__b_c____upstream_resp.json"), "{}", name);
+
+        let long_trace = "ü".repeat(100);
+        let sanitized = sanitize_component(&long_trace);
+        assert!(sanitized.len() <= MAX_COMPONENT_BYTES);
+        assert_eq!(sanitized, "ü".repeat(32));
+        assert_eq!(sanitize_component("..."), "unknown");
+    }
+
+    #[test]
+    fn test_extended_length_prefix() {
+        assert_eq!(to_extended_length(r"C:\logs\debug"), r"\\?\C:\logs\debug");
+        assert_eq!(to_extended_length("D:/logs/debug"), r"\\?\D:\logs\debug");
+        assert_eq!(to_extended_length(r"\\nas\share\logs"), r"\\?\UNC\nas\share\logs");
+        assert_eq!(to_extended_length(r"\\?\C:\logs"), r"\\?\C:\logs");
+        assert_eq!(to_extended_length("relative/logs"), "relative/logs");
+    }
+
+    #[test]
+    fn test_long_file_path_is_extended_when_dir_is_short() {
+        // 目录未超限, 加上文件名后超过 MAX_PATH: 文件路径仍需加前缀
+        let dir = format!(r"C:\{}", "d".repeat(200));
+        assert_eq!(extend_if_too_long(&dir, WINDOWS_MAX_DIR_PATH), dir);
+        let file = format!(r"{}\{}", dir, build_filename("v1internal_request", Some(&"t".repeat(64))));
+        assert!(file.len() >= WINDOWS_MAX_FILE_PATH);
+        assert_eq!(extend_if_too_long(&file, WINDOWS_MAX_FILE_PATH), format!(r"\\?\{}", file));
+
+        let long_dir = format!(r"C:\{}", "d".repeat(300));
+        assert_eq!(extend_if_too_long(&long_dir, WINDOWS_MAX_DIR_PATH), format!(r"\\?\{}", long_dir));
+        // 短路径与相对路径保持不变
+        assert_eq!(extend_if_too_long(r"C:\logs\a.json", WINDOWS_MAX_FILE_PATH), r"C:\logs\a.json");
+        let relative = "r".repeat(300);
+        assert_eq!(extend_if_too_long(&relative, WINDOWS_MAX_FILE_PATH), relative);
+    }
+
+    #[tokio::test]
+    async fn test_long_trace_dir_and_illegal_chars_are_written() {
+        let root = std::env::temp_dir().join(format!("debug-logger-{}", std::process::id()));
+        let deep = (0..12).fold(root.clone(), |dir, i| dir.join(format!("nested-trace-directory-{:02}", i)));
+        let cfg = DebugLoggingConfig {
+            enabled: true,
+            output_dir: Some(deep.to_string_lossy().into_owned()),
+            ..DebugLoggingConfig::default()
+        };
+        let trace_id = format!("a:b*c?{}", "x".repeat(300));
+        write_debug_payload(&cfg, Some(&trace_id), "v1internal_request", &serde_json::json!({ "ok": true })).await;
+
+        let names: Vec<String> = std::fs::read_dir(&deep)
+            .expect("output dir created")
+            .filter_map(|e| e.ok().map(|e| e.file_name().to_string_lossy().into_owned()))
+            .collect();
+        assert_eq!(names.len(), 1);
+        assert!(names[0].contains("_a_b_c_xxx"));
+        assert!(names[0].ends_with("_v1internal_request.json"));
+        assert!(names[0].len() < 255);
+        let _ = std::fs::remove_dir_all(&root);
+
+        // 输出目录位于普通文件之下, 创建失败: 计数并留下一次性警告
+        let blocker = std::env::temp_dir().join(format!("debug-logger-blocker-{}", std::process::id()));
+        std::fs::write(&blocker, b"").unwrap();
+        let failing = blocker.join("logs");
+        let failures = WriteFailures::new();
+        write_payload_to(&failing, "t1_request.json", &serde_json::json!({}), &failures).await;
+        write_payload_to(&failing, "t2_request.json", &serde_json::json!({}), &failures).await;
+        assert_eq!(failures.count(), 2);
+        assert!(failures.take_warning().is_some());
+        assert!(failures.take_warning().is_none());
+        let _ = std::fs::remove_file(&blocker);
+    }
+}
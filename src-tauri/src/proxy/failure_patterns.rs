@@ -0,0 +1,264 @@
+// 上游失败模式监控
+// Google 调整 v1internal 字段 (如 thinkingConfig) 后, 所有请求会以同一类 400 失败,
+// 用户难以判断是否是自身配置问题。这里在滑动窗口内按归一化后的错误签名聚合,
+// 同一签名占比超过阈值时进入持久警告状态, 只做检测与展示, 不做自动修复。
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// 滑动窗口长度
+const WINDOW: Duration = Duration::from_secs(10 * 60);
+/// 窗口内至少这么多请求才判定, 避免少量请求误报
+const MIN_REQUESTS: usize = 20;
+/// 同一签名失败占比超过该百分比时告警
+const THRESHOLD_PERCENT: usize = 50;
+/// 签名最大长度
+const MAX_SIGNATURE_LEN: usize = 200;
+
+static UUID_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)\b[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}\b")
+        .expect("Invalid uuid regex")
+});
+/// req_xxx / msg_xxx / toolu_xxx 等带前缀的 ID, 以及长十六进制 / base64 串
+static TOKEN_ID_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"\b(?:req|msg|toolu|call|resp|chatcmpl)_[A-Za-z0-9]+\b|\b[A-Za-z0-9_\-]*\d[A-Za-z0-9_\-]{15,}\b")
+        .expect("Invalid id regex")
+});
+static NUMBER_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\d+").expect("Invalid number regex"));
+static WHITESPACE_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\s+").expect("Invalid whitespace regex"));
+
+/// 持久警告 (随 get_proxy_status 返回)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailurePatternWarning {
+    pub signature: String,
+    /// 当前窗口内该签名的失败次数
+    pub count: usize,
+    /// 当前窗口内的请求总数
+    pub total: usize,
+    /// 首次告警时间 (unix 秒)
+    pub first_seen: i64,
+    /// 最近一次出现时间 (unix 秒)
+    pub last_seen: i64,
+}
+
+/// 诊断信息中的签名聚合
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignatureCount {
+    pub signature: String,
+    pub count: usize,
+}
+
+/// 是否计入失败模式: 限流 / 鉴权 / 客户端取消不属于协议问题
+fn is_tracked_failure(status: u16) -> bool {
+    status >= 400
+        && !matches!(
+            status,
+            401 | 429 | crate::proxy::monitor::STATUS_CLIENT_CANCELLED
+        )
+}
+
+/// 归一化上游错误为签名: 取 error.message, 去除 ID 与数字
+pub fn error_signature(status: u16, text: &str) -> String {
+    let message = serde_json::from_str::<serde_json::Value>(text)
+        .ok()
+        .and_then(|v| {
+            let err = v.get("error").unwrap_or(&v);
+            err.get("message")
+                .and_then(|m| m.as_str())
+                .map(|s| s.to_string())
+        })
+        .unwrap_or_else(|| text.to_string());
+
+    let mut out = UUID_RE.replace_all(&message, "<id>").into_owned();
+    out = TOKEN_ID_RE.replace_all(&out, "<id>").into_owned();
+    out = NUMBER_RE.replace_all(&out, "<n>").into_owned();
+    out = WHITESPACE_RE.replace_all(out.trim(), " ").into_owned();
+    if out.chars().count() > MAX_SIGNATURE_LEN {
+        out = out.chars().take(MAX_SIGNATURE_LEN).collect();
+    }
+    format!("{}: {}", status, out)
+}
+
+#[derive(Default)]
+struct FailurePatternMonitor {
+    /// (时间, 失败签名; 成功为 None)
+    events: VecDeque<(Instant, Option<String>)>,
+    warning: Option<FailurePatternWarning>,
+}
+
+impl FailurePatternMonitor {
+    fn prune(&mut self, now: Instant) {
+        while self
+            .events
+            .front()
+            .is_some_and(|(t, _)| now.duration_since(*t) > WINDOW)
+        {
+            self.events.pop_front();
+        }
+    }
+
+    fn counts(&self) -> HashMap<&str, usize> {
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for sig in self.events.iter().filter_map(|(_, s)| s.as_deref()) {
+            *counts.entry(sig).or_default() += 1;
+        }
+        counts
+    }
+
+    /// 记录一次请求结果, 新进入警告状态时返回该警告
+    fn record(&mut self, now: Instant, signature: Option<String>) -> Option<FailurePatternWarning> {
+        self.events.push_back((now, signature));
+        self.prune(now);
+
+        let total = self.events.len();
+        let top = self
+            .counts()
+            .into_iter()
+            .max_by_key(|(_, c)| *c)
+            .map(|(s, c)| (s.to_string(), c));
+        let unix_now = chrono::Utc::now().timestamp();
+
+        // 已有警告: 更新计数; 窗口内不再出现该签名时解除
+        if let Some(warning) = self.warning.as_mut() {
+            let count = self
+                .events
+                .iter()
+                .filter(|(_, s)| s.as_deref() == Some(warning.signature.as_str()))
+                .count();
+            if count == 0 {
+                tracing::info!(
+                    "[FailurePattern] Upstream failure pattern cleared: {}",
+                    warning.signature
+                );
+                self.warning = None;
+            } else {
+                if self.events.back().and_then(|(_, s)| s.as_deref())
+                    == Some(warning.signature.as_str())
+                {
+                    warning.last_seen = unix_now;
+                }
+                warning.count = count;
+                warning.total = total;
+                return None;
+            }
+        }
+
+        let (signature, count) = top?;
+        if total < MIN_REQUESTS || count * 100 <= total * THRESHOLD_PERCENT {
+            return None;
+        }
+
+        tracing::warn!(
+            "[FailurePattern] {}/{} requests in the last {}s failed with the same upstream error: {}",
+            count,
+            total,
+            WINDOW.as_secs(),
+            signature
+        );
+        let warning = FailurePatternWarning {
+            signature,
+            count,
+            total,
+            first_seen: unix_now,
+            last_seen: unix_now,
+        };
+        self.warning = Some(warning.clone());
+        Some(warning)
+    }
+
+    fn signatures(&self) -> Vec<SignatureCount> {
+        let mut list: Vec<SignatureCount> = self
+            .counts()
+            .into_iter()
+            .map(|(s, c)| SignatureCount {
+                signature: s.to_string(),
+                count: c,
+            })
+            .collect();
+        list.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.signature.cmp(&b.signature)));
+        list
+    }
+}
+
+static MONITOR: Lazy<Mutex<FailurePatternMonitor>> =
+    Lazy::new(|| Mutex::new(FailurePatternMonitor::default()));
+
+/// 记录一次请求结果 (由 ProxyMonitor 调用), 新进入警告状态时返回该警告
+pub fn record_outcome(status: u16, error: Option<&str>) -> Option<FailurePatternWarning> {
+    let signature = is_tracked_failure(status).then(|| error_signature(status, error.unwrap_or("")));
+    MONITOR.lock().ok()?.record(Instant::now(), signature)
+}
+
+pub fn current_warning() -> Option<FailurePatternWarning> {
+    MONITOR.lock().ok()?.warning.clone()
+}
+
+/// 当前窗口内的失败签名聚合 (按次数降序)
+pub fn aggregated_signatures() -> Vec<SignatureCount> {
+    let Ok(mut monitor) = MONITOR.lock() else {
+        return Vec::new();
+    };
+    monitor.prune(Instant::now());
+    monitor.signatures()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature_strips_ids_and_raises_warning_once() {
+        let a = error_signature(
+            400,
+            r#"{"error":{"code":400,"message":"Invalid JSON payload received. Unknown name \"thinkingConfig\" at 'request.generation_config': Cannot find field. req_01HZX9K2M3N4P5Q6R7S8T9","status":"INVALID_ARGUMENT"}}"#,
+        );
+        let b = error_signature(
+            400,
+            r#"{"error":{"code":400,"message":"Invalid JSON payload received.  Unknown name \"thinkingConfig\" at 'request.generation_config': Cannot find field. req_01J0AB7CD8EF9GH0IJ1KL2","status":"INVALID_ARGUMENT"}}"#,
+        );
+        assert_eq!(a, b);
+        assert!(a.starts_with("400: Invalid JSON payload"));
+        assert!(a.contains("thinkingConfig"));
+        assert!(!a.contains("req_"));
+
+        let mut monitor = FailurePatternMonitor::default();
+        let now = Instant::now();
+        // 少于最小样本数时不告警
+        for _ in 0..10 {
+            assert!(monitor.record(now, Some(a.clone())).is_none());
+        }
+        for _ in 0..5 {
+            assert!(monitor.record(now, None).is_none());
+        }
+        let mut raised = 0;
+        for _ in 0..10 {
+            if monitor.record(now, Some(b.clone())).is_some() {
+                raised += 1;
+            }
+        }
+        assert_eq!(raised, 1);
+        let warning = monitor.warning.clone().unwrap();
+        assert_eq!(warning.signature, a);
+        assert_eq!(warning.count, 20);
+        assert_eq!(warning.total, 25);
+        assert_eq!(monitor.signatures()[0].count, 20);
+
+        // 签名离开窗口后解除
+        let later = now + WINDOW + Duration::from_secs(1);
+        assert!(monitor.record(later, None).is_none());
+        assert!(monitor.warning.is_none());
+    }
+
+    #[test]
+    fn test_rate_limits_are_not_tracked() {
+        assert!(!is_tracked_failure(429));
+        assert!(!is_tracked_failure(401));
+        assert!(!is_tracked_failure(200));
+        assert!(is_tracked_failure(400));
+        assert!(is_tracked_failure(503));
+    }
+}
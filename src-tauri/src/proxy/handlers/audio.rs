@@ -1,23 +1,64 @@
 use axum::{
+    body::Body,
     extract::{Multipart, State},
-    http::StatusCode,
-    response::IntoResponse,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
     Json,
 };
+use base64::Engine as _;
+use bytes::Bytes;
+use futures::stream::{BoxStream, StreamExt};
+use serde::Deserialize;
 use serde_json::{json, Value};
-use tracing::{debug, info};
+use std::collections::HashMap;
+use std::time::Duration;
+use tracing::{debug, error, info};
 use uuid::Uuid;
 
 use crate::proxy::{
     audio::AudioProcessor,
+    common::model_mapping::{match_custom_mapping, resolve_model_route},
+    handlers::common::{with_account_headers, with_rotating_account, AttemptError, ByteUsage},
+    handlers::mcp::build_client,
+    providers::{DispatchReason, ProviderDecision},
     server::AppState,
+    ZaiConfig, ZaiDispatchMode,
 };
 
+/// 换号重试的最大尝试次数
+const MAX_ATTEMPTS: usize = 3;
+/// 语音合成单次输入的最大字符数 (与 OpenAI 一致)
+const MAX_SPEECH_INPUT_CHARS: usize = 4096;
+/// z.ai 未配置模型映射时使用的语音模型
+const ZAI_TTS_MODEL: &str = "glm-tts";
+
+/// OpenAI 语音模型 -> Gemini TTS 模型; 只在语音接口内解析, 不出现在 /v1/models 与对话路由中
+const OPENAI_SPEECH_MODELS: &[(&str, &str)] = &[
+    ("tts-1", "gemini-2.5-flash-preview-tts"),
+    ("tts-1-hd", "gemini-2.5-pro-preview-tts"),
+    ("gpt-4o-mini-tts", "gemini-2.5-flash-preview-tts"),
+];
+
+/// OpenAI 音色 -> Gemini 预置音色
+const OPENAI_VOICES: &[(&str, &str)] = &[
+    ("alloy", "Zephyr"),
+    ("ash", "Puck"),
+    ("ballad", "Enceladus"),
+    ("coral", "Aoede"),
+    ("echo", "Charon"),
+    ("fable", "Fenrir"),
+    ("nova", "Kore"),
+    ("onyx", "Orus"),
+    ("sage", "Leda"),
+    ("shimmer", "Autonoe"),
+    ("verse", "Iapetus"),
+];
+
 /// 处理音频转录请求 (OpenAI Whisper API 兼容)
 pub async fn handle_audio_transcription(
     State(state): State<AppState>,
     mut multipart: Multipart,
-) -> Result<impl IntoResponse, (StatusCode, String)> {
+) -> Result<Response, (StatusCode, String)> {
     let mut audio_data: Option<Vec<u8>> = None;
     let mut filename: Option<String> = None;
     let mut model = "gemini-2.0-flash-exp".to_string();
@@ -98,48 +139,57 @@ pub async fn handle_audio_transcription(
         }]
     });
 
-    // 6. 获取 Token 和上游客户端
-    let token_manager = state.token_manager;
-    let token_lease = token_manager
-        .get_token("text", false, None, &model)
-        .await
-        .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, e))?;
-
-    let access_token = token_lease.access_token.clone();
-    let project_id = token_lease.project_id.clone();
-    let email = token_lease.email.clone();
+    // 6-8. 包装为 v1internal 格式并发送, 账号级错误时换号重试
+    let upstream = state.upstream.clone();
+    let rotated = with_rotating_account(
+        state.token_manager.as_ref(),
+        "text",
+        &model,
+        MAX_ATTEMPTS,
+        |lease| {
+            let upstream = upstream.clone();
+            let gemini_request = gemini_request.clone();
+            let model = model.clone();
+            async move {
+                info!("使用账号: {}", lease.email);
+                let wrapped_body = json!({
+                    "project": lease.project_id,
+                    "requestId": format!("audio-{}", Uuid::new_v4()),
+                    "request": gemini_request,
+                    "model": model,
+                    "userAgent": "antigravity",
+                    "requestType": "text"
+                });
 
-    info!("使用账号: {}", email);
+                let response = upstream
+                    .call_v1_internal("generateContent", &lease.access_token, wrapped_body, None)
+                    .await
+                    .map_err(|e| AttemptError::local(502, format!("上游请求失败: {}", e)))?;
 
-    // 7. 包装请求为 v1internal 格式
-    let wrapped_body = json!({
-        "project": project_id,
-        "requestId": format!("audio-{}", Uuid::new_v4()),
-        "request": gemini_request,
-        "model": model,
-        "userAgent": "antigravity",
-        "requestType": "text"
-    });
-
-    // 8. 发送请求到 Gemini
-    let upstream = state.upstream.clone();
-    let response = upstream
-        .call_v1_internal("generateContent", &access_token, wrapped_body, None)
-        .await
-        .map_err(|e| (StatusCode::BAD_GATEWAY, format!("上游请求失败: {}", e)))?;
+                let status = response.status().as_u16();
+                if !response.status().is_success() {
+                    let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+                    error!("[Audio] Upstream error: {}", error_text);
+                    let message = format!(
+                        "Gemini API 错误: {}",
+                        crate::proxy::common::redact::sanitize_upstream_error(&error_text, Some(&lease.project_id))
+                    );
+                    return Err(AttemptError::upstream(status, error_text, message));
+                }
 
-    if !response.status().is_success() {
-        let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-        return Err((
-            StatusCode::BAD_GATEWAY,
-            format!("Gemini API 错误: {}", error_text),
-        ));
-    }
+                response
+                    .json::<Value>()
+                    .await
+                    .map_err(|e| AttemptError::local(502, format!("解析响应失败: {}", e)))
+            }
+        },
+    )
+    .await;
 
-    let result: Value = response
-        .json()
-        .await
-        .map_err(|e| (StatusCode::BAD_GATEWAY, format!("解析响应失败: {}", e)))?;
+    let (result, email) = match rotated {
+        Ok(rotated) => (rotated.value, rotated.email),
+        Err(failure) => return Ok(failure.into_response()),
+    };
 
     // 9. 提取文本响应（解包 v1internal 响应）
     let inner_response = result.get("response").unwrap_or(&result);
@@ -156,11 +206,616 @@ pub async fn handle_audio_transcription(
     info!("音频转录完成，返回 {} 字符", text.len());
 
     // 10. 返回标准格式响应
-    Ok((
-        StatusCode::OK,
-        [("X-Account-Email", email.as_str())],
-        Json(json!({
+    Ok(with_account_headers(
+        (StatusCode::OK, Json(json!({
             "text": text
-        }))
-    ).into_response())
+        }))),
+        &email,
+        None,
+    ))
+}
+
+// ===== 语音合成 (POST /v1/audio/speech) =====
+// Google 侧由 *-tts 模型经 streamGenerateContent 分片返回 PCM (inlineData), 边收边封装为所需容器转发;
+// 号池没有可用账号或该 TTS 模型不可用 (404) 时, 回退到 z.ai 的 OpenAI 兼容语音接口 (同样流式转发)。
+// mp3 / opus 需要压缩编码, 代理没有编码器, 这两种格式直接交给 z.ai 按请求的格式合成。
+
+#[derive(Debug, Deserialize)]
+struct SpeechRequest {
+    model: String,
+    input: String,
+    #[serde(default)]
+    voice: Option<String>,
+    /// wav (默认) / pcm / mp3 / opus; mp3 与 opus 由 z.ai 直接产出
+    #[serde(default)]
+    response_format: Option<String>,
+}
+
+/// 返回给客户端的封装格式
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SpeechContainer {
+    Wav,
+    Pcm,
+    Mp3,
+    Opus,
+}
+
+impl SpeechContainer {
+    /// 未指定时返回 WAV; 未知格式返回 400 而不是以其他格式代替
+    fn from_format(format: Option<&str>) -> Result<Self, String> {
+        match format.unwrap_or("wav") {
+            "wav" => Ok(Self::Wav),
+            "pcm" => Ok(Self::Pcm),
+            "mp3" => Ok(Self::Mp3),
+            "opus" => Ok(Self::Opus),
+            other => Err(format!(
+                "Unsupported response_format: {} (expected mp3, opus, wav or pcm)",
+                other
+            )),
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Wav => "wav",
+            Self::Pcm => "pcm",
+            Self::Mp3 => "mp3",
+            Self::Opus => "opus",
+        }
+    }
+
+    /// 压缩格式无法由 Google 返回的 PCM 转封装得到, 只能由 z.ai 直接合成
+    fn is_compressed(self) -> bool {
+        matches!(self, Self::Mp3 | Self::Opus)
+    }
+}
+
+/// 上游返回的一段音频
+#[derive(Debug)]
+struct SpeechAudio {
+    data: Vec<u8>,
+    mime_type: String,
+}
+
+/// OpenAI 错误 JSON; `code` 为 OpenAI 风格的字符串错误码
+fn speech_error(status: StatusCode, code: &str, message: impl Into<String>) -> Response {
+    let error_type = if status.is_client_error() && status != StatusCode::TOO_MANY_REQUESTS {
+        "invalid_request_error"
+    } else {
+        "api_error"
+    };
+    (
+        status,
+        Json(json!({
+            "error": {
+                "message": message.into(),
+                "type": error_type,
+                "code": code
+            }
+        })),
+    )
+        .into_response()
+}
+
+/// 上游 (Google / z.ai) 失败状态对应的 OpenAI 错误码
+fn upstream_error_code(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => "invalid_api_key",
+        StatusCode::NOT_FOUND => "model_not_found",
+        StatusCode::TOO_MANY_REQUESTS => "rate_limit_exceeded",
+        status if status.is_client_error() => "invalid_request",
+        _ => "upstream_error",
+    }
+}
+
+/// 自定义映射优先, 其次是 OpenAI 语音模型别名, 其余按通用路由解析
+fn resolve_speech_model(model: &str, custom_mapping: &HashMap<String, String>) -> String {
+    if match_custom_mapping(model, custom_mapping).is_none() {
+        if let Some((_, target)) = OPENAI_SPEECH_MODELS.iter().find(|(alias, _)| *alias == model) {
+            return target.to_string();
+        }
+    }
+    resolve_model_route(model, custom_mapping)
+}
+
+fn is_openai_voice(voice: &str) -> bool {
+    OPENAI_VOICES.iter().any(|(name, _)| name.eq_ignore_ascii_case(voice))
+}
+
+/// OpenAI 音色映射为 Gemini 预置音色; 其余名称 (如 Kore) 原样透传
+fn gemini_voice(voice: Option<&str>) -> String {
+    let voice = voice.map(str::trim).filter(|v| !v.is_empty()).unwrap_or("alloy");
+    OPENAI_VOICES
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(voice))
+        .map(|(_, gemini)| gemini.to_string())
+        .unwrap_or_else(|| voice.to_string())
+}
+
+fn speech_request_body(input: &str, voice: &str) -> Value {
+    json!({
+        "contents": [{
+            "role": "user",
+            "parts": [{ "text": input }]
+        }],
+        "generationConfig": {
+            "responseModalities": ["AUDIO"],
+            "speechConfig": {
+                "voiceConfig": {
+                    "prebuiltVoiceConfig": { "voiceName": voice }
+                }
+            }
+        }
+    })
+}
+
+/// 取出单个响应分片中的全部音频 (兼容 v1internal 的 response 包装)
+fn audio_chunks(resp: &Value) -> Result<Vec<SpeechAudio>, String> {
+    let inner = resp.get("response").unwrap_or(resp);
+    let parts = inner
+        .pointer("/candidates/0/content/parts")
+        .and_then(|p| p.as_array())
+        .into_iter()
+        .flatten();
+
+    let mut chunks = Vec::new();
+    for part in parts {
+        let Some(inline) = part.get("inlineData") else {
+            continue;
+        };
+        let mime = inline.get("mimeType").and_then(|m| m.as_str()).unwrap_or("");
+        if !mime.starts_with("audio/") {
+            continue;
+        }
+        let encoded = inline.get("data").and_then(|d| d.as_str()).unwrap_or("");
+        let data = base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(|e| format!("Invalid audio data from upstream: {}", e))?;
+        if !data.is_empty() {
+            chunks.push(SpeechAudio {
+                data,
+                mime_type: mime.to_string(),
+            });
+        }
+    }
+    Ok(chunks)
+}
+
+/// 增量解析 streamGenerateContent 的 SSE 数据 (事件可能跨网络分片), 逐事件取出音频
+#[derive(Debug, Default)]
+struct SpeechEventDecoder {
+    buffer: Vec<u8>,
+    finish_reason: Option<String>,
+}
+
+impl SpeechEventDecoder {
+    fn push(&mut self, bytes: &[u8]) -> Result<Vec<SpeechAudio>, String> {
+        self.buffer.extend_from_slice(bytes);
+        let mut audio = Vec::new();
+        while let Some(pos) = self.buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line);
+            let Some(data) = line.trim().strip_prefix("data:") else {
+                continue;
+            };
+            let Ok(event) = serde_json::from_str::<Value>(data.trim()) else {
+                continue;
+            };
+            let inner = event.get("response").unwrap_or(&event);
+            if let Some(reason) = inner.pointer("/candidates/0/finishReason").and_then(|r| r.as_str()) {
+                self.finish_reason = Some(reason.to_string());
+            }
+            audio.extend(audio_chunks(inner)?);
+        }
+        Ok(audio)
+    }
+
+    fn no_audio_error(&self) -> String {
+        format!(
+            "Upstream returned no audio (finishReason: {})",
+            self.finish_reason.as_deref().unwrap_or("none")
+        )
+    }
+}
+
+/// 已确认产出音频的上游流: 首批音频与尚未读取的剩余数据
+struct SpeechStream {
+    first: Vec<SpeechAudio>,
+    decoder: SpeechEventDecoder,
+    rest: BoxStream<'static, Result<Bytes, reqwest::Error>>,
+}
+
+/// 读到首段音频为止; 此前的失败 (空流 / 断流 / 超时) 尚未向客户端写出任何数据
+async fn peek_speech_stream(response: reqwest::Response, timeout: Duration) -> Result<SpeechStream, String> {
+    let mut rest = response.bytes_stream().boxed();
+    let mut decoder = SpeechEventDecoder::default();
+    let first = tokio::time::timeout(timeout, read_first_audio(&mut rest, &mut decoder))
+        .await
+        .map_err(|_| format!("Upstream sent no audio within {}s", timeout.as_secs()))??;
+    Ok(SpeechStream { first, decoder, rest })
+}
+
+async fn read_first_audio(
+    rest: &mut BoxStream<'static, Result<Bytes, reqwest::Error>>,
+    decoder: &mut SpeechEventDecoder,
+) -> Result<Vec<SpeechAudio>, String> {
+    while let Some(chunk) = rest.next().await {
+        let bytes = chunk.map_err(|e| format!("Upstream stream error: {}", e))?;
+        let audio = decoder.push(&bytes)?;
+        if !audio.is_empty() {
+            return Ok(audio);
+        }
+    }
+    Err(decoder.no_audio_error())
+}
+
+/// 按请求的格式边收边转发, 返回 (Content-Type, 响应体)
+///
+/// PCM 按需加上长度未知的 WAV 头; 上游已是其他容器格式时原样转发并如实标注
+fn speech_body(stream: SpeechStream, container: SpeechContainer) -> (String, Body) {
+    let SpeechStream {
+        first,
+        mut decoder,
+        mut rest,
+    } = stream;
+    let mime_type = first.first().map(|audio| audio.mime_type.clone()).unwrap_or_default();
+    let (content_type, header) = match (AudioProcessor::pcm_sample_rate(&mime_type), container) {
+        (Some(rate), SpeechContainer::Wav) => (
+            "audio/wav".to_string(),
+            Some(AudioProcessor::wav_stream_header(rate, 1)),
+        ),
+        (Some(_), SpeechContainer::Pcm) => ("audio/pcm".to_string(), None),
+        (None, _) => (mime_type, None),
+    };
+
+    let body = async_stream::stream! {
+        if let Some(header) = header {
+            yield Ok::<Bytes, std::io::Error>(Bytes::from(header));
+        }
+        for audio in first {
+            yield Ok(Bytes::from(audio.data));
+        }
+        while let Some(chunk) = rest.next().await {
+            match chunk.map_err(|e| e.to_string()).and_then(|bytes| decoder.push(&bytes)) {
+                Ok(chunks) => {
+                    for audio in chunks {
+                        yield Ok(Bytes::from(audio.data));
+                    }
+                }
+                Err(e) => {
+                    error!("[Speech] Upstream stream failed mid-audio: {}", e);
+                    yield Err(std::io::Error::other(e));
+                    break;
+                }
+            }
+        }
+    };
+    (content_type, Body::from_stream(body))
+}
+
+fn zai_speech_enabled(zai: &ZaiConfig) -> bool {
+    zai.enabled && !zai.api_key.trim().is_empty() && zai.dispatch_mode != ZaiDispatchMode::Off
+}
+
+/// z.ai 的 base_url 指向 Anthropic 兼容端点 (`.../api/anthropic`), 语音接口在同一主机的 `/api/paas/v4` 下
+fn zai_speech_url(base_url: &str) -> String {
+    let base = base_url.trim_end_matches('/');
+    let base = base
+        .strip_suffix("/anthropic")
+        .map(|root| format!("{}/paas/v4", root))
+        .unwrap_or_else(|| base.to_string());
+    format!("{}/audio/speech", base)
+}
+
+/// 请求 z.ai 合成, 返回 (Content-Type, 流式响应体); 以请求的格式直接向 z.ai 索取
+async fn synthesize_with_zai(
+    state: &AppState,
+    zai: &ZaiConfig,
+    request: &SpeechRequest,
+    container: SpeechContainer,
+) -> Result<(String, Body), (StatusCode, String)> {
+    let model = zai
+        .model_mapping
+        .get(&request.model)
+        .cloned()
+        .unwrap_or_else(|| ZAI_TTS_MODEL.to_string());
+    let mut body = json!({
+        "model": model,
+        "input": request.input,
+        "response_format": container.as_str()
+    });
+    // OpenAI 音色名在 z.ai 上无效, 交由上游使用默认音色
+    if let Some(voice) = request.voice.as_deref().filter(|v| !is_openai_voice(v)) {
+        body["voice"] = json!(voice);
+    }
+
+    let upstream_proxy = state.upstream_proxy.read().await.clone();
+    let client = build_client(upstream_proxy, state.request_timeout)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let resp = client
+        .post(zai_speech_url(&zai.base_url))
+        .bearer_auth(&zai.api_key)
+        .json(&body)
+        .send()
+        .await
+        .map_err(|e| (StatusCode::BAD_GATEWAY, format!("z.ai request failed: {}", e)))?;
+
+    let status = resp.status();
+    if !status.is_success() {
+        let status = StatusCode::from_u16(status.as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
+        let text = resp.text().await.unwrap_or_default();
+        return Err((status, format!("z.ai error: {}", text)));
+    }
+    let content_type = resp
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| format!("audio/{}", container.as_str()));
+    Ok((content_type, Body::from_stream(resp.bytes_stream())))
+}
+
+/// 处理语音合成请求 (OpenAI Speech API 兼容)
+pub async fn handle_audio_speech(State(state): State<AppState>, Json(body): Json<Value>) -> Response {
+    let request: SpeechRequest = match serde_json::from_value(body) {
+        Ok(request) => request,
+        Err(e) => {
+            return speech_error(
+                StatusCode::BAD_REQUEST,
+                "invalid_request_body",
+                format!("Invalid request body: {}", e),
+            )
+        }
+    };
+    if request.input.trim().is_empty() {
+        return speech_error(StatusCode::BAD_REQUEST, "empty_input", "input must not be empty");
+    }
+    if request.input.chars().count() > MAX_SPEECH_INPUT_CHARS {
+        return speech_error(
+            StatusCode::BAD_REQUEST,
+            "string_above_max_length",
+            format!("input must be at most {} characters", MAX_SPEECH_INPUT_CHARS),
+        );
+    }
+    let container = match SpeechContainer::from_format(request.response_format.as_deref()) {
+        Ok(container) => container,
+        Err(message) => return speech_error(StatusCode::BAD_REQUEST, "unsupported_response_format", message),
+    };
+
+    let mapped_model = resolve_speech_model(&request.model, &*state.custom_mapping.read().await);
+    if !mapped_model.contains("tts") {
+        return speech_error(
+            StatusCode::BAD_REQUEST,
+            "model_not_found",
+            format!(
+                "Model {} resolves to {}, which is not a speech model",
+                request.model, mapped_model
+            ),
+        );
+    }
+    let voice = gemini_voice(request.voice.as_deref());
+    info!(
+        "[Speech] {} chars, model {} -> {}, voice {}, format {}",
+        request.input.chars().count(),
+        request.model,
+        mapped_model,
+        voice,
+        container.as_str()
+    );
+
+    if container.is_compressed() {
+        let zai = state.zai.read().await.clone();
+        if !zai_speech_enabled(&zai) {
+            return speech_error(
+                StatusCode::BAD_REQUEST,
+                "unsupported_response_format",
+                format!(
+                    "response_format {} requires the z.ai provider (Google TTS only returns PCM); use wav or pcm, or enable z.ai",
+                    container.as_str()
+                ),
+            );
+        }
+        let decision = ProviderDecision {
+            provider: "zai".to_string(),
+            dispatch_mode: Some(zai.dispatch_mode.clone()),
+            reason: None,
+        };
+        return match synthesize_with_zai(&state, &zai, &request, container).await {
+            Ok((content_type, body)) => decision.attach(speech_response(&request, content_type, body)),
+            Err((status, message)) => decision.attach(speech_error(status, upstream_error_code(status), message)),
+        };
+    }
+
+    let peek_timeout = Duration::from_secs(crate::proxy::config::get_retry_config().peek_timeout_secs);
+    let gemini_request = speech_request_body(&request.input, &voice);
+    let upstream = state.upstream.clone();
+    let rotated = with_rotating_account(
+        state.token_manager.as_ref(),
+        "text",
+        &mapped_model,
+        MAX_ATTEMPTS,
+        |lease| {
+            let upstream = upstream.clone();
+            let gemini_request = gemini_request.clone();
+            let model = mapped_model.clone();
+            async move {
+                let wrapped_body = json!({
+                    "project": lease.project_id,
+                    "requestId": format!("speech-{}", Uuid::new_v4()),
+                    "request": gemini_request,
+                    "model": model,
+                    "userAgent": "antigravity",
+                    "requestType": "text"
+                });
+
+                let response = upstream
+                    .call_v1_internal("streamGenerateContent", &lease.access_token, wrapped_body, Some("alt=sse"))
+                    .await
+                    .map_err(|e| AttemptError::local(502, format!("Upstream request failed: {}", e)))?;
+
+                let status = response.status().as_u16();
+                if !response.status().is_success() {
+                    let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+                    error!("[Speech] Upstream error: {}", error_text);
+                    let message = crate::proxy::common::redact::sanitize_upstream_error(
+                        &error_text,
+                        Some(&lease.project_id),
+                    );
+                    return Err(AttemptError::upstream(status, error_text, message));
+                }
+
+                peek_speech_stream(response, peek_timeout)
+                    .await
+                    .map_err(|e| AttemptError::local(502, e))
+            }
+        },
+    )
+    .await;
+
+    let (content_type, body, email, zai_decision) = match rotated {
+        Ok(rotated) => {
+            let (content_type, body) = speech_body(rotated.value, container);
+            (content_type, body, Some(rotated.email), None)
+        }
+        Err(failure) => {
+            let zai = state.zai.read().await.clone();
+            let google_unavailable = failure.email.is_none() || failure.status == 404;
+            if !google_unavailable || !zai_speech_enabled(&zai) {
+                let status = StatusCode::from_u16(failure.status).unwrap_or(StatusCode::BAD_GATEWAY);
+                let response = speech_error(status, upstream_error_code(status), failure.message);
+                return match failure.email {
+                    Some(email) => with_account_headers(response, &email, Some(&mapped_model)),
+                    None => response,
+                };
+            }
+
+            info!(
+                "[Speech] Google unavailable for {} ({}), falling back to z.ai",
+                mapped_model, failure.message
+            );
+            let reason = if state.token_manager.len() == 0 {
+                DispatchReason::NoGoogleAccounts
+            } else {
+                DispatchReason::AllUnavailable {
+                    model: mapped_model.clone(),
+                }
+            };
+            let decision = ProviderDecision {
+                provider: "zai".to_string(),
+                dispatch_mode: Some(ZaiDispatchMode::Fallback),
+                reason: Some(reason),
+            };
+            match synthesize_with_zai(&state, &zai, &request, container).await {
+                Ok((content_type, body)) => (content_type, body, None, Some(decision)),
+                Err((status, message)) => {
+                    return decision.attach(speech_error(status, upstream_error_code(status), message))
+                }
+            }
+        }
+    };
+
+    let mut response = speech_response(&request, content_type, body);
+    if let Some(decision) = zai_decision {
+        response = decision.attach(response);
+    }
+    match email {
+        Some(email) => with_account_headers(response, &email, Some(&mapped_model)),
+        None => response,
+    }
+}
+
+/// 流式音频响应, 附带供代理日志统计的输入字节数 (输出字节数由监控中间件在转发响应体时统计)
+fn speech_response(request: &SpeechRequest, content_type: String, body: Body) -> Response {
+    info!("[Speech] Streaming {}", content_type);
+    let usage = ByteUsage {
+        input_bytes: request.input.len() as u64,
+    };
+    let mut response = ([(header::CONTENT_TYPE, content_type)], body).into_response();
+    response.extensions_mut().insert(usage);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn audio_event(mime_type: &str, data: &[u8], finish: bool) -> String {
+        let mut candidate = json!({
+            "content": { "parts": [
+                { "inlineData": { "mimeType": mime_type, "data": AudioProcessor::encode_to_base64(data) } },
+                { "text": "ignored" }
+            ] }
+        });
+        if finish {
+            candidate["finishReason"] = json!("STOP");
+        }
+        format!("data: {}\n\n", json!({ "response": { "candidates": [candidate] } }))
+    }
+
+    #[test]
+    fn test_decoder_handles_events_split_across_chunks() {
+        let pcm = "audio/L16;codec=pcm;rate=24000";
+        let sse = format!("{}{}", audio_event(pcm, &[1, 0], false), audio_event(pcm, &[2, 0], true));
+        let (head, tail) = sse.as_bytes().split_at(sse.len() / 3);
+
+        let mut decoder = SpeechEventDecoder::default();
+        let mut data = Vec::new();
+        for chunk in [head, tail] {
+            for audio in decoder.push(chunk).unwrap() {
+                assert_eq!(audio.mime_type, pcm);
+                data.extend(audio.data);
+            }
+        }
+        assert_eq!(data, vec![1, 0, 2, 0]);
+        assert_eq!(decoder.finish_reason.as_deref(), Some("STOP"));
+
+        let mut decoder = SpeechEventDecoder::default();
+        let empty = r#"data: {"candidates":[{"finishReason":"OTHER","content":{"parts":[]}}]}"#;
+        assert!(decoder.push(format!("{}\n\n", empty).as_bytes()).unwrap().is_empty());
+        assert!(decoder.no_audio_error().contains("OTHER"));
+    }
+
+    #[tokio::test]
+    async fn test_speech_error_uses_string_code() {
+        let response = speech_error(
+            StatusCode::BAD_REQUEST,
+            "unsupported_response_format",
+            "response_format aac is not supported",
+        );
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"]["code"], "unsupported_response_format");
+        assert_eq!(body["error"]["type"], "invalid_request_error");
+
+        assert_eq!(upstream_error_code(StatusCode::TOO_MANY_REQUESTS), "rate_limit_exceeded");
+        assert_eq!(upstream_error_code(StatusCode::NOT_FOUND), "model_not_found");
+        assert_eq!(upstream_error_code(StatusCode::BAD_GATEWAY), "upstream_error");
+    }
+
+    #[test]
+    fn test_speech_options() {
+        assert_eq!(SpeechContainer::from_format(None).unwrap(), SpeechContainer::Wav);
+        assert_eq!(SpeechContainer::from_format(Some("pcm")).unwrap(), SpeechContainer::Pcm);
+        // 压缩格式只能交给 z.ai 直接合成, 不能由 PCM 转封装
+        assert_eq!(SpeechContainer::from_format(Some("mp3")).unwrap(), SpeechContainer::Mp3);
+        assert_eq!(SpeechContainer::from_format(Some("opus")).unwrap(), SpeechContainer::Opus);
+        assert!(SpeechContainer::Mp3.is_compressed() && SpeechContainer::Opus.is_compressed());
+        assert!(!SpeechContainer::Wav.is_compressed());
+        assert!(SpeechContainer::from_format(Some("ogg")).is_err());
+
+        let custom = HashMap::from([("tts-1".to_string(), "gemini-2.5-pro-preview-tts".to_string())]);
+        assert_eq!(resolve_speech_model("tts-1", &HashMap::new()), "gemini-2.5-flash-preview-tts");
+        assert_eq!(resolve_speech_model("tts-1", &custom), "gemini-2.5-pro-preview-tts");
+
+        assert_eq!(gemini_voice(Some("Nova")), "Kore");
+        assert_eq!(gemini_voice(Some("Puck")), "Puck");
+        assert_eq!(gemini_voice(None), "Zephyr");
+
+        assert_eq!(
+            zai_speech_url("https://api.z.ai/api/anthropic/"),
+            "https://api.z.ai/api/paas/v4/audio/speech"
+        );
+        assert_eq!(zai_speech_url("https://tts.local/v1"), "https://tts.local/v1/audio/speech");
+    }
 }
@@ -2,12 +2,16 @@
 // Detects and routes background tasks to cheaper models
 
 use crate::proxy::mappers::claude::ClaudeRequest;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 // Model constant for background tasks
 pub const INTERNAL_BACKGROUND_TASK: &str = "internal-background-task";
 
 /// Background task type
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum BackgroundTaskType {
     TitleGeneration,      // Title generation
     SimpleSummary,        // Simple summary
@@ -15,6 +19,19 @@ pub enum BackgroundTaskType {
     PromptSuggestion,     // Prompt suggestion
     SystemMessage,        // System message
     EnvironmentProbe,     // Environment probe
+    Compaction,           // Claude Code /compact
+}
+
+/// Number of Claude Code compaction requests since startup
+static COMPACTION_REQUESTS: AtomicU64 = AtomicU64::new(0);
+
+/// Get the number of compaction requests detected since startup
+pub fn compaction_count() -> u64 {
+    COMPACTION_REQUESTS.load(Ordering::Relaxed)
+}
+
+pub fn record_compaction() {
+    COMPACTION_REQUESTS.fetch_add(1, Ordering::Relaxed);
 }
 
 /// Title generation keywords
@@ -68,9 +85,27 @@ const PROBE_KEYWORDS: &[&str] = &[
     "test connection",
 ];
 
+/// Claude Code /compact instruction fingerprint
+const COMPACTION_FINGERPRINT: &str =
+    "Your task is to create a detailed summary of the conversation so far";
+
+/// Structural markers of the /compact output template (at least 2 must be present)
+const COMPACTION_MARKERS: &[&str] = &[
+    "<analysis>",
+    "<summary>",
+    "Primary Request and Intent",
+    "Key Technical Concepts",
+    "Pending Tasks",
+];
+
 /// Detect background task and return task type
 pub fn detect_background_task_type(request: &ClaudeRequest) -> Option<BackgroundTaskType> {
     let last_user_msg = extract_last_user_message_for_detection(request)?;
+
+    // Compaction prompt is long and carries the full history, check before the length filter
+    if is_compaction_request(request, &last_user_msg) {
+        return Some(BackgroundTaskType::Compaction);
+    }
     let preview = last_user_msg.chars().take(500).collect::<String>();
     
     // Length filter: background tasks usually don't exceed 800 characters
@@ -105,6 +140,19 @@ pub fn detect_background_task_type(request: &ClaudeRequest) -> Option<Background
     None
 }
 
+/// Compaction requests carry the fingerprint plus the output template structure,
+/// so ordinary messages that merely ask to "summarize" never match
+fn is_compaction_request(request: &ClaudeRequest, last_user_msg: &str) -> bool {
+    if request.messages.len() < 2 || !last_user_msg.contains(COMPACTION_FINGERPRINT) {
+        return false;
+    }
+    COMPACTION_MARKERS
+        .iter()
+        .filter(|marker| last_user_msg.contains(*marker))
+        .count()
+        >= 2
+}
+
 /// Helper function: keyword matching
 fn matches_keywords(text: &str, keywords: &[&str]) -> bool {
     keywords.iter().any(|kw| text.contains(kw))
@@ -140,7 +188,17 @@ fn extract_last_user_message_for_detection(request: &ClaudeRequest) -> Option<St
 }
 
 /// Select appropriate model based on background task type
-pub fn select_background_model(task_type: BackgroundTaskType) -> &'static str {
+///
+/// A model configured in `routes` wins; otherwise compaction uses `compaction_model`
+/// and every other task the internal background model
+pub fn select_background_model<'a>(
+    task_type: BackgroundTaskType,
+    routes: &'a HashMap<BackgroundTaskType, String>,
+    compaction_model: &'a str,
+) -> &'a str {
+    if let Some(model) = routes.get(&task_type).filter(|m| !m.is_empty()) {
+        return model;
+    }
     match task_type {
         BackgroundTaskType::TitleGeneration => INTERNAL_BACKGROUND_TASK,
         BackgroundTaskType::SimpleSummary => INTERNAL_BACKGROUND_TASK,
@@ -148,5 +206,74 @@ pub fn select_background_model(task_type: BackgroundTaskType) -> &'static str {
         BackgroundTaskType::PromptSuggestion => INTERNAL_BACKGROUND_TASK,
         BackgroundTaskType::EnvironmentProbe => INTERNAL_BACKGROUND_TASK,
         BackgroundTaskType::ContextCompression => INTERNAL_BACKGROUND_TASK,
+        BackgroundTaskType::Compaction => compaction_model,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(messages: serde_json::Value) -> ClaudeRequest {
+        serde_json::from_value(serde_json::json!({
+            "model": "claude-sonnet-4-5",
+            "messages": messages,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_detects_compaction_by_structure_only() {
+        let compact_prompt = format!(
+            "{}, paying close attention to the user's explicit requests.\n\
+             Wrap your analysis in <analysis> tags.\n<summary>\n1. Primary Request and Intent:\n\
+             2. Key Technical Concepts:\n</summary>",
+            COMPACTION_FINGERPRINT
+        );
+        let req = request(serde_json::json!([
+            {"role": "user", "content": "fix the login bug"},
+            {"role": "assistant", "content": "Done."},
+            {"role": "user", "content": compact_prompt},
+        ]));
+        assert_eq!(detect_background_task_type(&req), Some(BackgroundTaskType::Compaction));
+
+        // 普通用户消息即使提到 summarize / summary 也不应命中
+        let req = request(serde_json::json!([
+            {"role": "user", "content": "hi"},
+            {"role": "assistant", "content": "hello"},
+            {"role": "user", "content": format!("{} please, and put it in a <summary> block", COMPACTION_FINGERPRINT)},
+        ]));
+        assert_ne!(detect_background_task_type(&req), Some(BackgroundTaskType::Compaction));
+    }
+
+    #[test]
+    fn test_background_routes_override_defaults() {
+        let none = HashMap::new();
+        assert_eq!(
+            select_background_model(BackgroundTaskType::TitleGeneration, &none, "gemini-2.5-flash"),
+            INTERNAL_BACKGROUND_TASK
+        );
+        assert_eq!(
+            select_background_model(BackgroundTaskType::Compaction, &none, "gemini-2.5-flash"),
+            "gemini-2.5-flash"
+        );
+
+        let routes: HashMap<BackgroundTaskType, String> = serde_json::from_value(serde_json::json!({
+            "title_generation": "gemini-2.5-flash-lite",
+            "compaction": "gemini-2.5-pro"
+        }))
+        .unwrap();
+        assert_eq!(
+            select_background_model(BackgroundTaskType::TitleGeneration, &routes, "gemini-2.5-flash"),
+            "gemini-2.5-flash-lite"
+        );
+        assert_eq!(
+            select_background_model(BackgroundTaskType::Compaction, &routes, "gemini-2.5-flash"),
+            "gemini-2.5-pro"
+        );
+        assert_eq!(
+            select_background_model(BackgroundTaskType::PromptSuggestion, &routes, "gemini-2.5-flash"),
+            INTERNAL_BACKGROUND_TASK
+        );
     }
 }
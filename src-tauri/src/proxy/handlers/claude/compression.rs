@@ -1,13 +1,14 @@
 // Layer 3 Context Compression
 // Fork conversation + XML summary generation
 
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use serde_json::Value;
 use tracing::{debug, info};
 
 use crate::proxy::mappers::claude::{ClaudeRequest, models::{Message, MessageContent}};
 use crate::proxy::mappers::context_manager::ContextManager;
-use super::background::INTERNAL_BACKGROUND_TASK;
+use crate::proxy::upstream::client::UpstreamClient;
 
 /// XML Summary Prompt Template
 /// Borrowed from Practical-Guide-to-Context-Engineering + Claude Code official practice
@@ -68,20 +69,89 @@ The structure MUST be as follows:
 4. The thinking signature must be copied exactly, no modifications
 "#;
 
-/// Call Gemini API synchronously and return the response text
+/// Request type used for Layer-3 summary calls (token selection + stats attribution)
+pub const CONTEXT_SUMMARY_REQUEST_TYPE: &str = "context_summary";
+
+/// Hard upper bound for the summary call's max_tokens, regardless of config
+const CONTEXT_SUMMARY_MAX_TOKENS_LIMIT: u32 = 8192;
+
+static CONTEXT_SUMMARY_CALLS: AtomicU64 = AtomicU64::new(0);
+static CONTEXT_SUMMARY_INPUT_TOKENS: AtomicU64 = AtomicU64::new(0);
+static CONTEXT_SUMMARY_OUTPUT_TOKENS: AtomicU64 = AtomicU64::new(0);
+
+/// Layer-3 summary overhead since startup
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ContextSummaryUsage {
+    pub calls: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+pub fn context_summary_usage() -> ContextSummaryUsage {
+    ContextSummaryUsage {
+        calls: CONTEXT_SUMMARY_CALLS.load(Ordering::Relaxed),
+        input_tokens: CONTEXT_SUMMARY_INPUT_TOKENS.load(Ordering::Relaxed),
+        output_tokens: CONTEXT_SUMMARY_OUTPUT_TOKENS.load(Ordering::Relaxed),
+    }
+}
+
+/// Everything the cheap-model summary calls (Layer-1.5 digests, Layer-3) need
+pub struct ContextSummaryContext {
+    /// Upstream model (already resolved through model mapping)
+    pub model: String,
+    pub max_tokens: u32,
+    /// Account bound to the session; the summary prefers a different one
+    pub session_account_id: Option<String>,
+    pub token_manager: Arc<crate::proxy::TokenManager>,
+    pub upstream: Arc<UpstreamClient>,
+    /// Layer-1.5 tool output digest calls allowed per request (0 = disabled)
+    pub tool_summary_budget: usize,
+}
+
+/// Record summary usage separately from the main request so the Layer-3 overhead is visible
+fn record_context_summary_usage(email: &str, model: &str, response: &Value) {
+    let usage = response.get("usageMetadata");
+    let input = usage
+        .and_then(|u| u.get("promptTokenCount"))
+        .and_then(|v| v.as_u64())
+        .unwrap_or(0);
+    let output = usage
+        .and_then(|u| u.get("candidatesTokenCount"))
+        .and_then(|v| v.as_u64())
+        .unwrap_or(0);
+
+    CONTEXT_SUMMARY_CALLS.fetch_add(1, Ordering::Relaxed);
+    CONTEXT_SUMMARY_INPUT_TOKENS.fetch_add(input, Ordering::Relaxed);
+    CONTEXT_SUMMARY_OUTPUT_TOKENS.fetch_add(output, Ordering::Relaxed);
+
+    let stats_model = format!("{}:{}", CONTEXT_SUMMARY_REQUEST_TYPE, model);
+    crate::modules::stats_journal::record_usage(email, &stats_model, input as u32, output as u32);
+}
+
+/// Call the upstream synchronously and return the response text
 /// 
 /// Used for internal operations that need to wait for a complete response,
 /// such as generating summaries or other background tasks.
-async fn call_gemini_sync(
-    model: &str,
+/// Uses the `context_summary` request type so it avoids the session's account when possible.
+pub(super) async fn call_gemini_sync(
     request: &ClaudeRequest,
-    token_manager: &Arc<crate::proxy::TokenManager>,
+    options: &ContextSummaryContext,
     trace_id: &str,
 ) -> Result<String, String> {
-    let token_lease = token_manager
-        .get_token("gemini", false, None, model)
-        .await
-        .map_err(|e| format!("Failed to get account: {}", e))?;
+    let token_manager = &options.token_manager;
+    let token_lease = match options.session_account_id.as_deref() {
+        Some(session_account) => {
+            token_manager
+                .get_token_excluding(CONTEXT_SUMMARY_REQUEST_TYPE, &options.model, session_account)
+                .await
+        }
+        None => {
+            token_manager
+                .get_token(CONTEXT_SUMMARY_REQUEST_TYPE, true, None, &options.model)
+                .await
+        }
+    }
+    .map_err(|e| format!("Failed to get account: {}", e))?;
 
     let access_token = token_lease.access_token.clone();
     let project_id = token_lease.project_id.clone();
@@ -89,19 +159,14 @@ async fn call_gemini_sync(
     let gemini_body = crate::proxy::mappers::claude::transform_claude_request_in(request, &project_id, false)
         .map_err(|e| format!("Failed to transform request: {}", e))?;
     
-    let upstream_url = format!(
-        "https://generativelanguage.googleapis.com/v1beta/models/{}:generateContent",
-        model
+    debug!(
+        "[{}] Calling upstream for {}: {} (account: {})",
+        trace_id, CONTEXT_SUMMARY_REQUEST_TYPE, options.model, token_lease.email
     );
     
-    debug!("[{}] Calling Gemini API: {}", trace_id, model);
-    
-    let response = reqwest::Client::new()
-        .post(&upstream_url)
-        .header("Authorization", format!("Bearer {}", access_token))
-        .header("Content-Type", "application/json")
-        .json(&gemini_body)
-        .send()
+    let response = options
+        .upstream
+        .call_v1_internal("generateContent", &access_token, gemini_body, None)
         .await
         .map_err(|e| format!("API call failed: {}", e))?;
     
@@ -115,6 +180,9 @@ async fn call_gemini_sync(
     
     let gemini_response: Value = response.json().await
         .map_err(|e| format!("Failed to parse response: {}", e))?;
+    let gemini_response = gemini_response.get("response").cloned().unwrap_or(gemini_response);
+
+    record_context_summary_usage(&token_lease.email, &options.model, &gemini_response);
     
     gemini_response
         .get("candidates")
@@ -128,6 +196,31 @@ async fn call_gemini_sync(
         .ok_or_else(|| "Failed to extract text from response".to_string())
 }
 
+/// A forked request together with the summary that replaced the pre-fork history
+pub struct ForkedRequest {
+    pub request: ClaudeRequest,
+    pub summary: String,
+}
+
+/// The two messages that stand in for the pre-fork history
+pub fn summary_prefix(xml_summary: &str) -> Vec<Message> {
+    vec![
+        Message {
+            role: "user".to_string(),
+            content: MessageContent::String(format!(
+                "Context has been compressed. Here is the structured summary of our conversation history:\n\n{}",
+                xml_summary
+            )),
+        },
+        Message {
+            role: "assistant".to_string(),
+            content: MessageContent::String(
+                "I have reviewed the compressed context summary. I understand the current state and will continue from here.".to_string()
+            ),
+        },
+    ]
+}
+
 /// Try to compress context by generating an XML summary and forking the conversation
 /// 
 /// This function:
@@ -135,12 +228,12 @@ async fn call_gemini_sync(
 /// 2. Calls a cheap model to generate XML summary
 /// 3. Creates a new message sequence with summary as prefix
 /// 4. Preserves the signature in the summary
-/// 5. Returns the forked request
+/// 5. Returns the forked request and the summary (so the fork can be re-applied on later turns)
 pub async fn try_compress_with_summary(
     original_request: &ClaudeRequest,
     trace_id: &str,
-    token_manager: &Arc<crate::proxy::TokenManager>,
-) -> Result<ClaudeRequest, String> {
+    options: &ContextSummaryContext,
+) -> Result<ForkedRequest, String> {
     info!("[{}] [Layer-3] Starting context compression with XML summary", trace_id);
     
     // 1. Extract last valid signature
@@ -169,11 +262,11 @@ pub async fn try_compress_with_summary(
     });
     
     let summary_request = ClaudeRequest {
-        model: INTERNAL_BACKGROUND_TASK.to_string(),
+        model: options.model.clone(),
         messages: summary_messages,
         system: None,
         stream: false,
-        max_tokens: Some(8000),
+        max_tokens: Some(options.max_tokens.clamp(1, CONTEXT_SUMMARY_MAX_TOKENS_LIMIT)),
         temperature: Some(0.3),
         tools: None,
         thinking: None,
@@ -183,36 +276,20 @@ pub async fn try_compress_with_summary(
         output_config: None,
         size: None,
         quality: None,
+        service_tier: None,
+        tool_choice: None,
+        stop_sequences: None,
     };
     
-    debug!("[{}] [Layer-3] Calling {} for summary generation", trace_id, INTERNAL_BACKGROUND_TASK);
+    debug!("[{}] [Layer-3] Calling {} for summary generation", trace_id, options.model);
     
     // 3. Call upstream
-    let xml_summary = call_gemini_sync(
-        INTERNAL_BACKGROUND_TASK,
-        &summary_request,
-        token_manager,
-        trace_id,
-    ).await?;
+    let xml_summary = call_gemini_sync(&summary_request, options, trace_id).await?;
     
     info!("[{}] [Layer-3] Generated XML summary (len: {} chars)", trace_id, xml_summary.len());
     
     // 4. Create forked conversation with summary as prefix
-    let mut forked_messages = vec![
-        Message {
-            role: "user".to_string(),
-            content: MessageContent::String(format!(
-                "Context has been compressed. Here is the structured summary of our conversation history:\n\n{}",
-                xml_summary
-            )),
-        },
-        Message {
-            role: "assistant".to_string(),
-            content: MessageContent::String(
-                "I have reviewed the compressed context summary. I understand the current state and will continue from here.".to_string()
-            ),
-        },
-    ];
+    let mut forked_messages = summary_prefix(&xml_summary);
     
     // 5. Append the user's latest message
     if let Some(last_msg) = original_request.messages.last() {
@@ -231,7 +308,7 @@ pub async fn try_compress_with_summary(
     );
     
     // 6. Return forked request
-    Ok(ClaudeRequest {
+    let request = ClaudeRequest {
         model: original_request.model.clone(),
         messages: forked_messages,
         system: original_request.system.clone(),
@@ -246,5 +323,9 @@ pub async fn try_compress_with_summary(
         output_config: original_request.output_config.clone(),
         size: original_request.size.clone(),
         quality: original_request.quality.clone(),
-    })
+        service_tier: original_request.service_tier.clone(),
+        tool_choice: original_request.tool_choice.clone(),
+        stop_sequences: original_request.stop_sequences.clone(),
+    };
+    Ok(ForkedRequest { request, summary: xml_summary })
 }
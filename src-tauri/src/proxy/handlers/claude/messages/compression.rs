@@ -1,15 +1,14 @@
 //! 3-layer progressive context compression.
 //!
 //! Implements automatic context compression when usage exceeds thresholds:
-//! - Layer 1: Tool message trimming
+//! - Layer 1: Tool message trimming (Layer 1.5: large old tool outputs become digests when enabled)
 //! - Layer 2: Thinking content compression
 //! - Layer 3: Fork conversation + XML summary
 
 use crate::proxy::mappers::claude::models::ClaudeRequest;
 use crate::proxy::mappers::context_manager::ContextManager;
-use crate::proxy::mappers::estimation_calibrator::get_calibrator;
-use crate::proxy::token_manager::TokenManager;
-use std::sync::Arc;
+use crate::proxy::mappers::estimation_calibrator::{get_calibrator, CalibrationBucket};
+use super::super::compression::ContextSummaryContext;
 use tracing::{error, info};
 
 /// Result of compression attempt.
@@ -18,6 +17,8 @@ pub struct CompressionResult {
     pub is_purified: bool,
     pub compression_applied: bool,
     pub estimated_usage: u32,
+    /// Layer-3 summary when the conversation was forked
+    pub summary: Option<String>,
 }
 
 /// Apply 3-layer progressive compression to the request.
@@ -28,7 +29,7 @@ pub async fn apply_progressive_compression(
     threshold_l1: f32,
     threshold_l2: f32,
     threshold_l3: f32,
-    token_manager: &Arc<TokenManager>,
+    summary_context: &ContextSummaryContext,
 ) -> Result<CompressionResult, String> {
     let context_limit = if mapped_model.contains("flash") {
         1_000_000
@@ -38,17 +39,19 @@ pub async fn apply_progressive_compression(
 
     let raw_estimated = ContextManager::estimate_token_usage(&request);
     let calibrator = get_calibrator();
-    let mut estimated_usage = calibrator.calibrate(raw_estimated);
+    let bucket = CalibrationBucket::for_request(&request, mapped_model);
+    let mut estimated_usage = calibrator.calibrate_for(bucket, raw_estimated);
     let mut usage_ratio = estimated_usage as f32 / context_limit as f32;
 
     info!(
-        "[{}] [ContextManager] Context pressure: {:.1}% (raw: {}, calibrated: {} / {}), Calibration factor: {:.2}",
+        "[{}] [ContextManager] Context pressure: {:.1}% (raw: {}, calibrated: {} / {}), Calibration factor: {:.2} ({:?})",
         trace_id,
         usage_ratio * 100.0,
         raw_estimated,
         estimated_usage,
         context_limit,
-        calibrator.get_factor()
+        calibrator.factor_for(bucket),
+        bucket
     );
 
     let mut is_purified = false;
@@ -56,7 +59,15 @@ pub async fn apply_progressive_compression(
 
     // Layer 1: Tool Message Trimming
     if usage_ratio > threshold_l1 && !compression_applied {
-        if ContextManager::trim_tool_messages(&mut request.messages, 5) {
+        // Layer 1.5: digests keep their rounds out of trimming
+        let digested = if summary_context.tool_summary_budget > 0 {
+            super::super::tool_summary::digest_old_tool_results(&mut request.messages, 5, summary_context, trace_id)
+                .await
+        } else {
+            0
+        };
+        let trimmed = ContextManager::trim_tool_messages(&mut request.messages, 5);
+        if trimmed || digested > 0 {
             info!(
                 "[{}] [Layer-1] Tool trimming triggered (usage: {:.1}%, threshold: {:.1}%)",
                 trace_id,
@@ -66,7 +77,7 @@ pub async fn apply_progressive_compression(
             compression_applied = true;
 
             let new_raw = ContextManager::estimate_token_usage(&request);
-            let new_usage = calibrator.calibrate(new_raw);
+            let new_usage = calibrator.calibrate_for(bucket, new_raw);
             let new_ratio = new_usage as f32 / context_limit as f32;
 
             info!(
@@ -101,7 +112,7 @@ pub async fn apply_progressive_compression(
             compression_applied = true;
 
             let new_raw = ContextManager::estimate_token_usage(&request);
-            let new_usage = calibrator.calibrate(new_raw);
+            let new_usage = calibrator.calibrate_for(bucket, new_raw);
             let new_ratio = new_usage as f32 / context_limit as f32;
 
             info!(
@@ -125,8 +136,9 @@ pub async fn apply_progressive_compression(
             threshold_l3 * 100.0
         );
 
-        match super::super::compression::try_compress_with_summary(&request, trace_id, token_manager).await {
-            Ok(forked_request) => {
+        match super::super::compression::try_compress_with_summary(&request, trace_id, summary_context).await {
+            Ok(forked) => {
+                let forked_request = forked.request;
                 info!(
                     "[{}] [Layer-3] Fork successful: {} -> {} messages",
                     trace_id,
@@ -135,7 +147,7 @@ pub async fn apply_progressive_compression(
                 );
 
                 let new_raw = ContextManager::estimate_token_usage(&forked_request);
-                let new_usage = calibrator.calibrate(new_raw);
+                let new_usage = calibrator.calibrate_for(bucket, new_raw);
                 let new_ratio = new_usage as f32 / context_limit as f32;
 
                 info!(
@@ -151,6 +163,7 @@ pub async fn apply_progressive_compression(
                     is_purified: false,
                     compression_applied: true,
                     estimated_usage: new_usage,
+                    summary: Some(forked.summary),
                 });
             }
             Err(e) => {
@@ -171,5 +184,6 @@ pub async fn apply_progressive_compression(
         is_purified,
         compression_applied,
         estimated_usage,
+        summary: None,
     })
 }
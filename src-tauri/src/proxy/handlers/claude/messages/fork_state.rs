@@ -0,0 +1,397 @@
+//! Layer-3 fork state.
+//!
+//! After Layer-3 forks a conversation, the client keeps resending its full history; later turns
+//! must splice the stored summary back in place of the pre-fork messages, or the giant history
+//! immediately triggers another summary. Fork records are kept per session and persisted to
+//! `layer3_forks.json` so a proxy restart does not lose them; they expire with the sticky
+//! session TTL. Writes are atomic and run on the blocking pool, never under the request path.
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tracing::{info, warn};
+
+use super::super::compression::summary_prefix;
+use crate::proxy::mappers::claude::models::{ClaudeRequest, Message};
+use crate::proxy::token_manager::SESSION_TTL;
+
+const STORE_FILE: &str = "layer3_forks.json";
+/// A record's last-use time is written back at most this often
+const TOUCH_PERSIST_INTERVAL_SECS: i64 = 600;
+
+static STORE: Lazy<Mutex<Option<ForkStore>>> = Lazy::new(|| Mutex::new(None));
+/// Sequence of serialized snapshots; taken under the store lock, so it follows the state order
+static SNAPSHOT_SEQ: AtomicU64 = AtomicU64::new(0);
+/// Sequence of the last snapshot written to disk
+static WRITTEN_SEQ: Mutex<u64> = Mutex::new(0);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ForkRecord {
+    summary_id: String,
+    /// Number of leading client messages replaced by the summary
+    fork_point: usize,
+    /// SHA-256 of the replaced messages (an edited or rewound history no longer matches)
+    prefix_hash: String,
+    summary: String,
+    last_used_at: i64,
+}
+
+#[derive(Debug, Default)]
+struct ForkStore {
+    path: Option<PathBuf>,
+    records: HashMap<String, ForkRecord>,
+    /// Write snapshots on the blocking pool instead of the calling (request) thread
+    background_writes: bool,
+}
+
+fn prefix_hash(messages: &[Message]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(serde_json::to_vec(messages).unwrap_or_default());
+    format!("{:x}", hasher.finalize())
+}
+
+fn is_expired(record: &ForkRecord, now: i64) -> bool {
+    now - record.last_used_at > SESSION_TTL.as_secs() as i64
+}
+
+/// Write a snapshot unless a newer one already reached the disk
+fn write_snapshot(seq: u64, path: &Path, json: &[u8]) {
+    let mut written = WRITTEN_SEQ.lock().unwrap_or_else(|e| e.into_inner());
+    if seq <= *written {
+        return;
+    }
+    write_file(path, json);
+    *written = seq;
+}
+
+fn write_file(path: &Path, json: &[u8]) {
+    if let Err(e) = crate::utils::atomic_file::write_atomic(path, json) {
+        warn!("[Layer-3] Failed to write {:?}: {}", path, e);
+    }
+}
+
+impl ForkStore {
+    fn open(path: PathBuf, now: i64, background_writes: bool) -> Self {
+        let records = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("[Layer-3] Ignoring unreadable {:?}: {}", path, e);
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+        let mut store = Self { path: Some(path), records, background_writes };
+        let before = store.records.len();
+        store.records.retain(|_, r| !is_expired(r, now));
+        if store.records.len() != before {
+            store.save();
+        }
+        store
+    }
+
+    /// Serialize now, write atomically; with `background_writes` the file IO runs on the blocking pool
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let json = match serde_json::to_vec_pretty(&self.records) {
+            Ok(json) => json,
+            Err(e) => {
+                warn!("[Layer-3] Failed to serialize fork state: {}", e);
+                return;
+            }
+        };
+        if !self.background_writes {
+            write_file(path, &json);
+            return;
+        }
+        let seq = SNAPSHOT_SEQ.fetch_add(1, Ordering::SeqCst) + 1;
+        let path = path.clone();
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn_blocking(move || write_snapshot(seq, &path, &json));
+            }
+            Err(_) => write_snapshot(seq, &path, &json),
+        }
+    }
+
+    /// Remember that `client_messages` were forked into `forked` (summary + kept tail)
+    fn record(&mut self, session_id: &str, client_messages: &[Message], forked: &ClaudeRequest, summary: &str, now: i64) {
+        let kept = forked.messages.len().saturating_sub(2);
+        let fork_point = client_messages.len().saturating_sub(kept);
+        if fork_point == 0 {
+            return;
+        }
+        self.records.retain(|_, r| !is_expired(r, now));
+        self.records.insert(
+            session_id.to_string(),
+            ForkRecord {
+                summary_id: uuid::Uuid::new_v4().to_string(),
+                fork_point,
+                prefix_hash: prefix_hash(&client_messages[..fork_point]),
+                summary: summary.to_string(),
+                last_used_at: now,
+            },
+        );
+        self.save();
+    }
+
+    /// Splice the stored summary in place of the pre-fork history.
+    ///
+    /// Expired records are dropped; a record whose prefix does not match is kept, since a
+    /// sub-request or a rewound turn of the same session must not discard a still-valid fork.
+    fn reapply(&mut self, session_id: &str, request: &ClaudeRequest, now: i64) -> Option<(ClaudeRequest, String)> {
+        let record = self.records.get(session_id)?;
+        if is_expired(record, now) {
+            self.records.remove(session_id);
+            self.save();
+            return None;
+        }
+        let messages = &request.messages;
+        let fork_point = record.fork_point;
+        let matches = messages.len() > fork_point
+            && messages[fork_point].role == "user"
+            && prefix_hash(&messages[..fork_point]) == record.prefix_hash;
+        if !matches {
+            return None;
+        }
+
+        let mut spliced = request.clone();
+        spliced.messages = summary_prefix(&record.summary);
+        spliced.messages.extend_from_slice(&messages[fork_point..]);
+        let summary_id = record.summary_id.clone();
+
+        let persist = now - record.last_used_at >= TOUCH_PERSIST_INTERVAL_SECS;
+        if let Some(record) = self.records.get_mut(session_id) {
+            record.last_used_at = now;
+        }
+        if persist {
+            self.save();
+        }
+        Some((spliced, summary_id))
+    }
+}
+
+/// Run `f` on the process-wide store, loading it from the data dir on first use
+fn with_store<T>(f: impl FnOnce(&mut ForkStore) -> T) -> Option<T> {
+    let mut guard = STORE.lock().ok()?;
+    let store = guard.get_or_insert_with(|| match crate::modules::account::get_data_dir() {
+        Ok(dir) => ForkStore::open(dir.join(STORE_FILE), chrono::Utc::now().timestamp(), true),
+        Err(e) => {
+            warn!("[Layer-3] Data dir unavailable, fork state will not persist: {}", e);
+            ForkStore::default()
+        }
+    });
+    Some(f(store))
+}
+
+/// Re-apply a known fork of this session to the incoming request (before estimation)
+pub fn reapply(session_id: &str, request: &ClaudeRequest, trace_id: &str) -> Option<ClaudeRequest> {
+    let now = chrono::Utc::now().timestamp();
+    let (spliced, summary_id) = with_store(|store| store.reapply(session_id, request, now))??;
+    info!(
+        "[{}] [Layer-3] Re-applied fork {}: {} -> {} messages",
+        trace_id,
+        summary_id,
+        request.messages.len(),
+        spliced.messages.len()
+    );
+    Some(spliced)
+}
+
+/// Record a fresh Layer-3 fork of `client_messages` (the history as sent by the client)
+pub fn record(session_id: &str, client_messages: &[Message], forked: &ClaudeRequest, summary: &str) {
+    let now = chrono::Utc::now().timestamp();
+    with_store(|store| store.record(session_id, client_messages, forked, summary, now));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::compression::{context_summary_usage, ContextSummaryContext};
+    use super::super::compression::apply_progressive_compression;
+    use super::*;
+    use crate::proxy::mappers::claude::models::MessageContent;
+    use crate::proxy::mappers::context_manager::ContextManager;
+    use crate::proxy::server::AppState;
+    use crate::proxy::upstream::client::UpstreamClient;
+    use crate::proxy::upstream::transport::{UpstreamCall, UpstreamTransport};
+    use crate::proxy::TokenManager;
+    use axum::extract::State;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::Json;
+    use futures::future::BoxFuture;
+    use std::sync::Arc;
+
+    const SUMMARY: &str = "<state_snapshot>refactor in progress</state_snapshot>";
+    const NOW: i64 = 1_800_000_000;
+
+    fn text(role: &str, text: &str) -> Message {
+        Message {
+            role: role.to_string(),
+            content: MessageContent::String(text.to_string()),
+        }
+    }
+
+    fn request(messages: Vec<Message>) -> ClaudeRequest {
+        let mut request: ClaudeRequest = serde_json::from_value(serde_json::json!({
+            "model": "gemini-2.5-flash",
+            "messages": [],
+            "max_tokens": 1024
+        }))
+        .unwrap();
+        request.messages = messages;
+        request
+    }
+
+    fn store_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("fork-state-{}-{}.json", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    /// Long history, forked by Layer-3 keeping the latest user message
+    fn forked_history() -> (Vec<Message>, ClaudeRequest) {
+        let filler = "lorem ipsum dolor ".repeat(300);
+        let mut history: Vec<Message> = (0..40)
+            .map(|i| text(if i % 2 == 0 { "user" } else { "assistant" }, &format!("turn {} {}", i, filler)))
+            .collect();
+        history.push(text("user", "continue with the refactor"));
+        let mut forked = request(summary_prefix(SUMMARY));
+        forked.messages.push(history.last().unwrap().clone());
+        (history, forked)
+    }
+
+    #[tokio::test]
+    async fn test_fork_survives_restart_without_second_summary() {
+        let path = store_path("restart");
+        let (mut history, forked) = forked_history();
+        ForkStore::open(path.clone(), NOW, false).record("session-a", &history, &forked, SUMMARY, NOW);
+
+        // Restart: a fresh store loaded from disk, then the next turn arrives with the full history
+        let mut store = ForkStore::open(path.clone(), NOW + 60, false);
+        history.push(text("assistant", "Step one is done."));
+        history.push(text("user", "now step two"));
+        let turn2 = request(history);
+        let spliced = store.reapply("session-a", &turn2, NOW + 60).map(|(r, _)| r).expect("fork re-applied");
+        assert_eq!(spliced.messages.len(), 5);
+        assert!(matches!(&spliced.messages[0].content, MessageContent::String(s) if s.contains(SUMMARY)));
+        assert!(matches!(&spliced.messages[2].content, MessageContent::String(s) if s == "continue with the refactor"));
+
+        // Layer-3 would fire on the full history, but not on the re-applied fork.
+        // The token pool is empty, so any summary attempt would fail the compression.
+        let threshold = 0.003;
+        assert!(ContextManager::estimate_token_usage(&turn2) as f32 > threshold * 1_000_000.0);
+        let calls_before = context_summary_usage().calls;
+        let context = ContextSummaryContext {
+            model: "gemini-2.5-flash".to_string(),
+            max_tokens: 1024,
+            session_account_id: None,
+            token_manager: Arc::new(TokenManager::new(std::env::temp_dir())),
+            upstream: Arc::new(UpstreamClient::new(None)),
+            tool_summary_budget: 0,
+        };
+        let result = apply_progressive_compression(spliced, "test", "gemini-2.5-flash", threshold, threshold, threshold, &context)
+            .await
+            .expect("no summary needed");
+        assert!(result.summary.is_none());
+        assert_eq!(result.request.messages.len(), 5);
+        assert_eq!(context_summary_usage().calls, calls_before);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_edited_fork_is_kept_and_expired_fork_is_dropped() {
+        let path = store_path("stale");
+        let (history, forked) = forked_history();
+        let mut store = ForkStore::open(path.clone(), NOW, false);
+        store.record("session-a", &history, &forked, SUMMARY, NOW);
+        store.record("session-b", &history, &forked, SUMMARY, NOW);
+
+        // An edited history does not match, but the record survives for the original history
+        let mut edited = history.clone();
+        edited[3] = text("assistant", "rewritten");
+        assert!(store.reapply("session-a", &request(edited), NOW).is_none());
+        assert!(store.reapply("session-a", &request(history.clone()), NOW).is_some());
+        assert!(ForkStore::open(path.clone(), NOW, false).records.contains_key("session-a"));
+
+        let expired_at = NOW + SESSION_TTL.as_secs() as i64 + 1;
+        assert!(ForkStore::open(path.clone(), expired_at, false).records.is_empty());
+        assert!(store.reapply("session-b", &request(history), expired_at).is_none());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    /// Answers every call with one SSE success and records the upstream request bodies
+    #[derive(Default)]
+    struct RecordingUpstream(Mutex<Vec<serde_json::Value>>);
+
+    impl UpstreamTransport for RecordingUpstream {
+        fn send(&self, call: UpstreamCall) -> BoxFuture<'_, Result<reqwest::Response, String>> {
+            self.0.lock().unwrap().push(call.body);
+            let event = serde_json::json!({ "response": {
+                "candidates": [{
+                    "content": { "role": "model", "parts": [{ "text": "Step two is done." }] },
+                    "finishReason": "STOP",
+                    "index": 0
+                }],
+                "usageMetadata": { "promptTokenCount": 12, "candidatesTokenCount": 4, "totalTokenCount": 16 },
+                "modelVersion": "gemini-2.5-flash",
+                "responseId": "fork-restart"
+            }});
+            Box::pin(async move {
+                axum::http::Response::builder()
+                    .status(200)
+                    .header("content-type", "text/event-stream")
+                    .body(reqwest::Body::from(format!("data: {}\n\n", event)))
+                    .map(reqwest::Response::from)
+                    .map_err(|e| e.to_string())
+            })
+        }
+    }
+
+    /// Simulate a proxy restart: the process-wide store is reloaded from disk
+    fn restart(path: &Path) {
+        let store = ForkStore::open(path.to_path_buf(), chrono::Utc::now().timestamp(), false);
+        *STORE.lock().unwrap_or_else(|e| e.into_inner()) = Some(store);
+    }
+
+    #[tokio::test]
+    async fn test_handler_reapplies_fork_after_restart() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let path = data_dir.path().join(STORE_FILE);
+        let (mut history, forked) = forked_history();
+
+        // Turn 1 was forked before the restart
+        restart(&path);
+        record("fork-restart-user", &history, &forked, SUMMARY);
+        restart(&path);
+
+        history.push(text("assistant", "Step one is done."));
+        history.push(text("user", "now step two"));
+        let upstream = Arc::new(RecordingUpstream::default());
+        let mock = Arc::new(crate::proxy::benchmark::MockUpstream::new(&Default::default()));
+        let state = AppState {
+            upstream: Arc::new(UpstreamClient::with_transport(upstream.clone())),
+            ..crate::proxy::benchmark::benchmark_state(mock, 1, data_dir.path())
+        };
+        let body = serde_json::json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 1024,
+            "metadata": { "user_id": "fork-restart-user" },
+            "messages": history
+        });
+        let response = super::super::handler::handle_messages(State(state), HeaderMap::new(), None, Json(body)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // A single upstream call (no second summary) that carries the stored summary instead of the pre-fork turns
+        let calls = upstream.0.lock().unwrap().clone();
+        assert_eq!(calls.len(), 1);
+        let sent = calls[0].to_string();
+        assert!(sent.contains("refactor in progress"));
+        assert!(sent.contains("continue with the refactor"));
+        assert!(sent.contains("now step two"));
+        assert!(!sent.contains("turn 0 lorem"));
+    }
+}
@@ -2,7 +2,7 @@
 
 use axum::{
     body::Body,
-    extract::{Json, State},
+    extract::{Extension, Json, State},
     http::{header, StatusCode},
     response::{IntoResponse, Response},
 };
@@ -13,27 +13,47 @@ use tracing::{debug, error, info};
 use rand::Rng;
 
 use super::compression::apply_progressive_compression;
+use super::fork_state;
+use super::length_guard;
+use super::super::compression::ContextSummaryContext;
 use super::response::{
     build_compression_failed_error, build_context_too_long_error, build_exhausted_retry_error,
     build_invalid_request_error, build_service_unavailable_error, build_transform_error,
 };
 use super::retry::{get_thinking_retry_delay, handle_thinking_signature_error, is_context_too_long_error, is_thinking_signature_error};
-use crate::proxy::config::DebugLoggingConfig;
-use crate::proxy::debug_logger;
-use crate::proxy::handlers::common::{apply_retry_strategy, determine_retry_strategy, should_rotate_account, RetryStrategy};
-use crate::proxy::handlers::claude::background::{detect_background_task_type, select_background_model};
-use crate::proxy::handlers::claude::warmup::{create_warmup_response, is_warmup_request};
+use crate::proxy::config::{DebugLoggingConfig, RetryConfig};
+use crate::proxy::debug_logger::{self, RawStreamMode};
+use crate::proxy::upstream::response_ids::{self, UpstreamIds, UpstreamIdsSlot};
+use crate::proxy::handlers::common::{
+    apply_retry_strategy, determine_retry_strategy, error_response, finish_response, mark_local_failure,
+    set_header_lossy, should_rotate_account, sse_response_builder, with_account_headers,
+    ErrorProtocol, RetryStrategy,
+};
+use crate::proxy::common::post_process;
+use crate::proxy::common::project_setup;
+use crate::proxy::common::redact::sanitize_upstream_error;
+use crate::proxy::common::system_injection::{self, AppliedInjections, SystemInjection};
+use crate::proxy::common::thinking_capability::{is_thinking_unsupported_error, record_no_thinking};
+use crate::proxy::common::thinking_defaults::{self, ThinkingDecision};
+use crate::proxy::handlers::claude::background::{
+    detect_background_task_type, record_compaction, select_background_model, BackgroundTaskType,
+};
+use crate::proxy::handlers::claude::service_tier::{self, ServiceTier, SERVICE_TIER_HEADER};
+use crate::proxy::handlers::claude::warmup::{create_warmup_response, is_warmup_request, record_warmup_intercept};
 use crate::proxy::mappers::claude::{
-    clean_cache_control_from_messages, close_tool_loop_for_thinking, create_claude_sse_stream,
-    filter_invalid_thinking_blocks_with_family, merge_consecutive_messages,
-    transform_claude_request_in, transform_response,
+    api_version, clean_cache_control_from_messages, close_tool_loop_for_thinking, create_claude_sse_stream,
+    filter_invalid_thinking_blocks_with_family, merge_consecutive_messages, requested_cache_ttl,
+    transform_claude_request_in_with_mode, transform_response, validate_follow_up_tool_results,
+    validate_image_blocks, validate_tool_choice,
 };
 use crate::proxy::mappers::context_manager::ContextManager;
+use crate::proxy::mappers::estimation_calibrator::PromptEstimate;
+use crate::proxy::mappers::error_classifier::{is_connect_timeout, PROMPT_TOO_LONG_CODE};
+use crate::proxy::mappers::stream_watchdog::{self, StallSlot, StreamProtocol};
+use crate::proxy::middleware::monitor::PartialUsage;
 use crate::proxy::server::AppState;
+use crate::proxy::upstream::timeout::TimeoutProfile;
 use axum::http::HeaderMap;
-use std::sync::atomic::Ordering;
-
-const MAX_RETRY_ATTEMPTS: usize = 3;
 
 /// Result type for streaming response that can signal retry needed
 enum StreamingResult {
@@ -45,8 +65,19 @@ enum StreamingResult {
 pub async fn handle_messages(
     State(state): State<AppState>,
     headers: HeaderMap,
+    partial_usage: Option<Extension<PartialUsage>>,
     Json(body): Json<Value>,
 ) -> Response {
+    // anthropic-version 协商: 不支持的版本直接拒绝, 响应按版本能力改写并回显版本
+    let version = match api_version::negotiate(&headers) {
+        Ok(version) => version,
+        Err(e) => return build_invalid_request_error(e),
+    };
+    let response = handle_messages_inner(state, headers, body).await;
+    api_version::apply(response, version).await
+}
+
+async fn handle_messages_inner(state: AppState, headers: HeaderMap, body: Value) -> Response {
     let original_body = body.clone();
 
     tracing::debug!(
@@ -62,11 +93,6 @@ pub async fn handle_messages(
         .to_lowercase();
     let debug_cfg = state.debug_logging.read().await.clone();
 
-    // Decide whether to use z.ai or Google flow
-    let zai = state.zai.read().await.clone();
-    let zai_enabled = zai.enabled && !matches!(zai.dispatch_mode, crate::proxy::ZaiDispatchMode::Off);
-    let google_accounts = state.token_manager.len();
-
     // Parse request
     let mut request: crate::proxy::mappers::claude::models::ClaudeRequest = match serde_json::from_value(body) {
         Ok(r) => r,
@@ -86,112 +112,254 @@ pub async fn handle_messages(
         debug_logger::write_debug_payload(&debug_cfg, Some(&trace_id), "original_request", &original_payload).await;
     }
 
-    // Normalize model name for quota protection check
-    let normalized_model = crate::proxy::common::model_mapping::normalize_to_standard_id(&request.model)
-        .unwrap_or_else(|| request.model.clone());
+    // Decide whether to use an extra provider (z.ai, custom...) or Google flow
+    let provider = crate::proxy::providers::select_provider(
+        &state,
+        crate::proxy::config::ListenerProtocol::Anthropic,
+        &request.model,
+        &trace_id,
+    )
+    .await;
 
-    let use_zai = determine_provider(&state, &zai, zai_enabled, google_accounts, &normalized_model, &trace_id, &request.model).await;
+    if debug_logger::is_enabled(&debug_cfg) {
+        let decision = provider
+            .as_ref()
+            .map(|selected| selected.decision.clone())
+            .unwrap_or_else(crate::proxy::providers::ProviderDecision::google);
+        let decision_payload = json!({
+            "kind": "provider_decision",
+            "protocol": "anthropic",
+            "trace_id": trace_id,
+            "original_model": request.model,
+            "decision": decision,
+        });
+        debug_logger::write_debug_payload(&debug_cfg, Some(&trace_id), "provider_decision", &decision_payload).await;
+    }
 
-    // Clean cache_control and merge messages
-    clean_cache_control_from_messages(&mut request.messages);
-    merge_consecutive_messages(&mut request.messages);
+    // 指定的 tool_choice 必须是已声明的工具, 否则无法强制调用; 图片来源 / 类型 / 大小超出上游限制时同样直接拒绝
+    let validation =
+        validate_tool_choice(&request).and_then(|_| validate_image_blocks(&request.messages));
+    if let Err(message) = validation {
+        tracing::warn!("[{}] Rejecting request: {}", trace_id, message);
+        return build_invalid_request_error(message);
+    }
 
-    // Get model family for signature validation
-    let target_family = if use_zai {
-        Some("claude")
+    // 调试: 按客户端原样发送消息, 跳过以下所有规范化步骤 (上游可能因此拒绝请求)
+    let raw_messages = debug_logger::raw_messages_mode(&debug_cfg, &headers);
+    if raw_messages {
+        tracing::warn!("[{}] Raw messages mode: skipping message normalization (debug)", trace_id);
     } else {
-        let mapped_model = crate::proxy::common::model_mapping::map_claude_model_to_gemini(&request.model);
-        if mapped_model.contains("gemini") {
-            Some("gemini")
-        } else {
-            Some("claude")
+        if let Err(response) = normalize_messages(&state, &mut request, provider.is_some(), &trace_id).await {
+            return response;
         }
-    };
-
-    // Filter invalid thinking blocks
-    filter_invalid_thinking_blocks_with_family(&mut request.messages, target_family);
-
-    // Recover from broken tool loops
-    if state.experimental.read().await.enable_tool_loop_recovery {
-        close_tool_loop_for_thinking(&mut request.messages);
     }
 
     // Intercept warmup requests
     if is_warmup_request(&request) {
         info!("[{}] Intercepting Warmup request, returning mock response", trace_id);
+        record_warmup_intercept(headers.get(header::USER_AGENT).and_then(|v| v.to_str().ok()));
         return create_warmup_response(&request, request.stream);
     }
 
-    if use_zai {
-        return handle_zai_request(&state, &headers, &request, &trace_id).await;
+    // 按 API Key / 映射条目配置的系统提示注入 (客户端无感知)
+    let injections = system_injection::resolve_for_route(
+        crate::proxy::middleware::auth::extract_api_key(&headers),
+        &request.model,
+        &*state.custom_mapping.read().await,
+    );
+    let injection_names: Vec<String> = injections.iter().map(|i| i.name.clone()).collect();
+    if !injection_names.is_empty() {
+        info!("[{}] System injections applied: {}", trace_id, injection_names.join(", "));
+    }
+
+    if let Some(selected) = provider {
+        // 透传 provider (z.ai 等) 按 Anthropic 格式写入 system
+        system_injection::apply_to_claude_system(&mut request.system, &injections);
+        if !injections.is_empty() && debug_logger::is_enabled(&debug_cfg) {
+            let payload = json!({
+                "kind": "system_injections",
+                "protocol": "anthropic",
+                "trace_id": trace_id,
+                "provider": selected.provider.name(),
+                "system_injections": injections,
+            });
+            debug_logger::write_debug_payload(&debug_cfg, Some(&trace_id), "system_injections", &payload).await;
+        }
+        let mut response = handle_provider_request(&state, selected.provider.as_ref(), &headers, &request).await;
+        if !injection_names.is_empty() {
+            response.extensions_mut().insert(AppliedInjections(injection_names));
+        }
+        if raw_messages {
+            set_header_lossy(&mut response, debug_logger::RAW_MESSAGES_RESPONSE_HEADER, "true");
+        }
+        return selected.decision.attach(response);
     }
 
+    // 会话过长提示 (基于客户端发来的完整历史, 每个会话一次)
+    let advisory = length_guard::check_advisory(&request, &trace_id, &*state.experimental.read().await);
+
     // Google Flow
-    handle_google_flow(state, request, trace_id, debug_cfg).await
+    let raw_mode = debug_logger::raw_stream_mode(&debug_cfg, &headers);
+    let client_interleaved_thinking = headers
+        .get("anthropic-beta")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("interleaved-thinking"));
+    let tier = service_tier::resolve(request.service_tier.as_deref());
+
+    // thinking 默认开启策略 (物理模型 / 映射条目配置), 决策写回请求, 转换时不再按内置规则推断
+    let thinking_decision = thinking_defaults::decide_for_route(
+        &request.model,
+        &*state.custom_mapping.read().await,
+        request.thinking.as_ref().map(|t| t.type_ == "enabled"),
+    );
+    if thinking_decision.auto_enabled {
+        info!(
+            "[{}] Thinking auto-enabled for {} (policy source: {})",
+            trace_id,
+            thinking_decision.physical_model,
+            thinking_decision.source.as_str()
+        );
+        request.thinking = Some(crate::proxy::mappers::claude::models::ThinkingConfig {
+            type_: "enabled".to_string(),
+            budget_tokens: None,
+        });
+    } else if !thinking_decision.enabled {
+        if let Some(thinking) = request.thinking.as_mut() {
+            thinking.type_ = "disabled".to_string();
+        } else {
+            request.thinking = Some(crate::proxy::mappers::claude::models::ThinkingConfig {
+                type_: "disabled".to_string(),
+                budget_tokens: None,
+            });
+        }
+    }
+    let auto_enabled_source = thinking_decision
+        .auto_enabled
+        .then(|| thinking_decision.source.as_str());
+
+    // cache_control 在规范化时已清除, 长期缓存提示从原始请求读取
+    let cache_ttl = requested_cache_ttl(&original_body);
+
+    let mut response = handle_google_flow(
+        state,
+        request,
+        trace_id,
+        debug_cfg,
+        raw_mode,
+        raw_messages,
+        client_interleaved_thinking,
+        tier,
+        thinking_decision,
+        injections,
+        cache_ttl,
+    )
+    .await;
+    if !injection_names.is_empty() {
+        response.extensions_mut().insert(AppliedInjections(injection_names));
+    }
+    if raw_messages {
+        set_header_lossy(&mut response, debug_logger::RAW_MESSAGES_RESPONSE_HEADER, "true");
+    }
+    if let Some(tier) = tier {
+        set_header_lossy(&mut response, SERVICE_TIER_HEADER, tier.effective);
+    }
+    if let Some(source) = auto_enabled_source {
+        set_header_lossy(&mut response, thinking_defaults::AUTO_ENABLED_HEADER, source);
+    }
+    match advisory {
+        Some(advisory) => length_guard::apply_advisory(response, advisory).await,
+        None => response,
+    }
 }
 
-async fn determine_provider(
+/// 发往上游前的消息规范化: 清理 cache_control、合并同角色消息、校验 tool_result、过滤无效 thinking、修复工具循环
+async fn normalize_messages(
     state: &AppState,
-    zai: &crate::proxy::ZaiConfig,
-    zai_enabled: bool,
-    google_accounts: usize,
-    normalized_model: &str,
+    request: &mut crate::proxy::mappers::claude::models::ClaudeRequest,
+    to_provider: bool,
     trace_id: &str,
-    original_model: &str,
-) -> bool {
-    if !zai_enabled {
-        return false;
+) -> Result<(), Response> {
+    // Clean cache_control and merge messages
+    clean_cache_control_from_messages(&mut request.messages);
+    merge_consecutive_messages(&mut request.messages);
+
+    // Check follow-up tool_result ids against the previous assistant turn (on the merged messages)
+    let orphan_mode = state.experimental.read().await.orphan_tool_result_mode;
+    match validate_follow_up_tool_results(
+        &mut request.messages,
+        orphan_mode == crate::proxy::config::OrphanToolResultMode::Drop,
+    ) {
+        Ok(dropped) if !dropped.is_empty() => {
+            tracing::warn!("[{}] Dropped orphaned tool_result(s): {:?}", trace_id, dropped);
+        }
+        Ok(_) => {}
+        Err(message) => {
+            tracing::warn!("[{}] Rejecting request: {}", trace_id, message);
+            return Err(build_invalid_request_error(message));
+        }
     }
 
-    match zai.dispatch_mode {
-        crate::proxy::ZaiDispatchMode::Off => false,
-        crate::proxy::ZaiDispatchMode::Exclusive => true,
-        crate::proxy::ZaiDispatchMode::Fallback => {
-            if google_accounts == 0 {
-                info!("[{}] No Google accounts available, using fallback provider", trace_id);
-                true
-            } else {
-                let has_available = state.token_manager.has_available_account("claude", normalized_model).await;
-                if !has_available {
-                    info!(
-                        "[{}] All Google accounts unavailable for {}, using fallback provider",
-                        trace_id, original_model
-                    );
-                }
-                !has_available
-            }
+    // Get model family for signature validation
+    let target_family = if to_provider {
+        Some("claude")
+    } else {
+        let mapped_model = crate::proxy::common::model_mapping::map_claude_model_to_gemini(&request.model);
+        if mapped_model.contains("gemini") {
+            Some("gemini")
+        } else {
+            Some("claude")
         }
-        crate::proxy::ZaiDispatchMode::Pooled => {
-            let total = google_accounts.saturating_add(1).max(1);
-            let slot = state.provider_rr.fetch_add(1, Ordering::Relaxed) % total;
-            slot == 0
+    };
+
+    // Filter invalid thinking blocks
+    let redacted_mode = state
+        .experimental
+        .read()
+        .await
+        .redacted_thinking_mode
+        .for_target(to_provider);
+    filter_invalid_thinking_blocks_with_family(&mut request.messages, target_family, redacted_mode);
+
+    // Recover from broken tool loops
+    {
+        let experimental = state.experimental.read().await;
+        if experimental.enable_tool_loop_recovery {
+            close_tool_loop_for_thinking(
+                &mut request.messages,
+                &experimental.tool_loop_recovery_placeholder,
+            );
         }
     }
+
+    Ok(())
 }
 
-async fn handle_zai_request(
+async fn handle_provider_request(
     state: &AppState,
+    provider: &dyn crate::proxy::providers::Provider,
     headers: &HeaderMap,
     request: &crate::proxy::mappers::claude::models::ClaudeRequest,
-    _trace_id: &str,
 ) -> Response {
     let new_body = match serde_json::to_value(request) {
         Ok(v) => v,
         Err(e) => {
-            tracing::error!("Failed to serialize fixed request for z.ai: {}", e);
+            tracing::error!("Failed to serialize fixed request for {}: {}", provider.name(), e);
             return StatusCode::INTERNAL_SERVER_ERROR.into_response();
         }
     };
 
-    crate::proxy::providers::zai_anthropic::forward_anthropic_json(
-        state,
-        axum::http::Method::POST,
-        "/v1/messages",
-        headers,
-        new_body,
-        request.messages.len(),
-    )
-    .await
+    provider
+        .forward_stream(
+            state,
+            crate::proxy::providers::ProviderRequest {
+                method: axum::http::Method::POST,
+                path: "/v1/messages",
+                headers,
+                body: new_body,
+                message_count: request.messages.len(),
+            },
+        )
+        .await
 }
 
 async fn handle_google_flow(
@@ -199,25 +367,59 @@ async fn handle_google_flow(
     request: crate::proxy::mappers::claude::models::ClaudeRequest,
     trace_id: String,
     debug_cfg: DebugLoggingConfig,
+    raw_mode: Option<RawStreamMode>,
+    raw_messages: bool,
+    client_interleaved_thinking: bool,
+    tier: Option<ServiceTier>,
+    thinking_decision: ThinkingDecision,
+    injections: Vec<SystemInjection>,
+    cache_ttl: Option<std::time::Duration>,
 ) -> Response {
     let experimental = state.experimental.read().await;
     let scaling_enabled = experimental.enable_usage_scaling;
+    // 缩放用量时从 input_tokens 中扣除注入内容, 客户端看到的用量与未注入时一致
+    let hidden_input_tokens = if scaling_enabled && experimental.hide_system_injections_in_usage {
+        system_injection::estimated_tokens(&injections)
+    } else {
+        0
+    };
     let threshold_l1 = experimental.context_compression_threshold_l1;
     let threshold_l2 = experimental.context_compression_threshold_l2;
     let threshold_l3 = experimental.context_compression_threshold_l3;
+    let tool_result_placeholder = experimental.tool_loop_recovery_placeholder.clone();
+    let redacted_thinking_mode = experimental.redacted_thinking_mode;
+    let context_summary_model = experimental.context_summary_model.clone();
+    let context_summary_max_tokens = experimental.context_summary_max_tokens;
+    let tool_summary_budget = if experimental.enable_tool_result_digests {
+        experimental.tool_result_digest_budget
+    } else {
+        0
+    };
+    let compaction_model = experimental.compaction_model.clone();
+    let compaction_max_tokens = experimental.compaction_max_tokens;
+    let background_routing = experimental.background_routing.clone();
+    let force_compress_threshold = experimental.message_force_compress_threshold;
     drop(experimental);
 
     log_request_details(&request, &trace_id);
+    let priority = tier.map(|t| t.priority).unwrap_or_default();
+    if let Some(tier) = tier {
+        info!("[{}] service_tier: {} (priority: {:?})", trace_id, tier.effective, priority);
+    }
 
     let upstream = state.upstream.clone();
     let mut request_for_body = request.clone();
     let token_manager = state.token_manager.clone();
 
     let pool_size = token_manager.len();
-    let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size.saturating_add(1)).max(2);
+    // 每个请求读取一次, 修改重试配置后对新请求立即生效
+    let retry_config = crate::proxy::config::get_retry_config();
+    let max_attempts = retry_config.attempts_for_pool(pool_size);
 
     let mut last_error = String::new();
     let mut retried_without_thinking = false;
+    // 学习到模型不支持 thinking 后, 用同一账号重试
+    let mut keep_account = false;
     let mut last_email: Option<String> = None;
     let mut last_mapped_model: Option<String> = None;
     let mut last_status = StatusCode::SERVICE_UNAVAILABLE;
@@ -245,13 +447,24 @@ async fn handle_google_flow(
 
         let session_id_str = crate::proxy::session_manager::SessionManager::extract_session_id(&request_for_body);
         let session_id = Some(session_id_str.as_str());
+        // 长期缓存提示: 会话绑定在提示有效期内更难被换掉
+        if let Some(ttl) = cache_ttl {
+            crate::proxy::token_manager::record_cache_intent(&session_id_str, ttl);
+        }
 
-        let force_rotate_token = attempt > 0;
+        let force_rotate_token = attempt > 0 && !std::mem::take(&mut keep_account);
+        let had_session_binding = token_manager.has_session_binding(&session_id_str);
         let mut token_lease_result = Err("Initial".to_string());
         // [FIX] Retry loop for token acquisition to handle transient pool exhaustion
         for token_attempt in 0..3 {
             token_lease_result = token_manager
-                .get_token(&config.request_type, force_rotate_token, session_id, &config.final_model)
+                .get_token_with_priority(
+                    &config.request_type,
+                    force_rotate_token,
+                    session_id,
+                    &config.final_model,
+                    priority,
+                )
                 .await;
             
             if token_lease_result.is_ok() {
@@ -272,7 +485,8 @@ async fn handle_google_flow(
                 } else {
                     e
                 };
-                return build_service_unavailable_error(safe_message, &mapped_model);
+                let retry_after = token_manager.min_cooldown_secs(Some(&mapped_model)).await;
+                return build_service_unavailable_error(safe_message, &mapped_model, retry_after);
             }
         };
 
@@ -283,12 +497,18 @@ async fn handle_google_flow(
         last_email = Some(email.clone());
         info!("Using account: {} (type: {})", email, config.request_type);
 
-        // Background task detection
-        let background_task_type = detect_background_task_type(&request_for_body);
+        // Background task detection (原始消息模式或关闭后台路由时按普通请求处理, 不改写模型与历史)
+        let background_task_type = if raw_messages || !background_routing.enabled {
+            None
+        } else {
+            detect_background_task_type(&request_for_body)
+        };
         let mut request_with_mapped = request_for_body.clone();
 
         if let Some(task_type) = background_task_type {
-            let virtual_model_id = select_background_model(task_type);
+            let is_compaction = task_type == BackgroundTaskType::Compaction;
+            let virtual_model_id =
+                select_background_model(task_type, &background_routing.routes, &compaction_model);
             let resolved_model = crate::proxy::common::model_mapping::resolve_model_route(
                 virtual_model_id,
                 &*state.custom_mapping.read().await,
@@ -303,6 +523,14 @@ async fn handle_google_flow(
             request_with_mapped.model = resolved_model;
             request_with_mapped.tools = None;
             request_with_mapped.thinking = None;
+            if is_compaction {
+                if attempt == 0 {
+                    record_compaction();
+                }
+                request_with_mapped.max_tokens = Some(
+                    request_with_mapped.max_tokens.unwrap_or(0).max(compaction_max_tokens),
+                );
+            }
 
             ContextManager::purify_history(
                 &mut request_with_mapped.messages,
@@ -313,8 +541,43 @@ async fn handle_google_flow(
         // Progressive compression
         let mut is_purified = false;
         let mut raw_estimated;
+        let summary_context = ContextSummaryContext {
+            model: crate::proxy::common::model_mapping::resolve_model_route(
+                &context_summary_model,
+                &*state.custom_mapping.read().await,
+            ),
+            max_tokens: context_summary_max_tokens,
+            session_account_id: Some(token_lease.account_id.clone()),
+            token_manager: token_manager.clone(),
+            upstream: upstream.clone(),
+            tool_summary_budget,
+        };
+
+        // 已 Fork 过的会话 (包括重启前): 先用保存的摘要替换 Fork 点之前的历史, 再估算压力
+        if background_task_type.is_none() && !raw_messages {
+            if let Some(spliced) = fork_state::reapply(&session_id_str, &request_with_mapped, &trace_id) {
+                request_with_mapped = spliced;
+            }
+        }
+
+        // 消息数超过硬水位: 不论 token 压力直接 Fork + Summary
+        let forced_fork = if background_task_type.is_none() && !retried_without_thinking && !raw_messages {
+            length_guard::force_compress_if_needed(
+                &request_with_mapped,
+                &trace_id,
+                force_compress_threshold,
+                &summary_context,
+            )
+            .await
+        } else {
+            None
+        };
 
-        if !retried_without_thinking && scaling_enabled {
+        if let Some(forked) = forced_fork {
+            fork_state::record(&session_id_str, &request_for_body.messages, &forked.request, &forked.summary);
+            request_with_mapped = forked.request;
+            raw_estimated = ContextManager::estimate_token_usage(&request_with_mapped);
+        } else if !retried_without_thinking && scaling_enabled && !raw_messages {
             match apply_progressive_compression(
                 request_with_mapped.clone(),
                 &trace_id,
@@ -322,11 +585,14 @@ async fn handle_google_flow(
                 threshold_l1,
                 threshold_l2,
                 threshold_l3,
-                &token_manager,
+                &summary_context,
             )
             .await
             {
                 Ok(result) => {
+                    if let Some(summary) = &result.summary {
+                        fork_state::record(&session_id_str, &request_for_body.messages, &result.request, summary);
+                    }
                     request_with_mapped = result.request;
                     is_purified = result.is_purified;
                     raw_estimated = if !is_purified {
@@ -345,9 +611,15 @@ async fn handle_google_flow(
 
         request_with_mapped.model = mapped_model.clone();
 
-        let gemini_body = match transform_claude_request_in(&request_with_mapped, &project_id, retried_without_thinking) {
+        let mut gemini_body = match transform_claude_request_in_with_mode(
+            &request_with_mapped,
+            &project_id,
+            retried_without_thinking,
+            raw_messages,
+            &injections,
+        ) {
             Ok(b) => {
-                debug!("[{}] Transformed Gemini Body: {}", trace_id, serde_json::to_string_pretty(&b).unwrap_or_default());
+                debug!("[{}] Transformed Gemini Body: {}", trace_id, serde_json::to_string_pretty(&debug_logger::without_inline_data(&b)).unwrap_or_default());
                 b
             }
             Err(e) => {
@@ -355,6 +627,32 @@ async fn handle_google_flow(
             }
         };
 
+        // max_tokens 超出输入之后的剩余上下文: 下调 maxOutputTokens, 低于下限时直接返回上下文过长
+        let context_limit = crate::proxy::mappers::claude::utils::get_context_limit_for_model(&request_with_mapped.model);
+        let mut adjusted_max_tokens = None;
+        if raw_estimated > 0 {
+            let estimate = PromptEstimate::for_request(&request_with_mapped, &mapped_model, raw_estimated);
+            let input_tokens = crate::proxy::mappers::estimation_calibrator::get_calibrator()
+                .calibrate_for(estimate.bucket, estimate.raw);
+            match length_guard::fit_output_budget(&mut gemini_body, context_limit, input_tokens) {
+                length_guard::OutputBudget::Unchanged => {}
+                length_guard::OutputBudget::Adjusted { requested, max_output_tokens, thinking_budget } => {
+                    tracing::warn!(
+                        "[{}] max_tokens exceeds remaining context (limit {}, estimated input {}): maxOutputTokens {} -> {}, thinkingBudget {:?}",
+                        trace_id, context_limit, input_tokens, requested, max_output_tokens, thinking_budget
+                    );
+                    adjusted_max_tokens = Some(max_output_tokens);
+                }
+                length_guard::OutputBudget::TooLong => {
+                    tracing::warn!(
+                        "[{}] Estimated input {} leaves no room for output within context limit {}",
+                        trace_id, input_tokens, context_limit
+                    );
+                    return build_context_too_long_error(&email);
+                }
+            }
+        }
+
         if debug_logger::is_enabled(&debug_cfg) {
             let payload = json!({
                 "kind": "v1internal_request",
@@ -364,11 +662,29 @@ async fn handle_google_flow(
                 "mapped_model": request_with_mapped.model,
                 "request_type": config.request_type,
                 "attempt": attempt,
+                "thinking_decision": thinking_decision,
+                "system_injections": injections,
                 "v1internal_request": gemini_body.clone(),
             });
             debug_logger::write_debug_payload(&debug_cfg, Some(&trace_id), "v1internal_request", &payload).await;
         }
 
+        // 本次请求建立了新的会话绑定: 预热绑定账号的隐式缓存 (CacheFirst, 默认关闭)
+        if background_task_type.is_none()
+            && !had_session_binding
+            && token_manager.has_session_binding(&session_id_str)
+        {
+            crate::proxy::session_prewarm::maybe_prewarm(
+                &token_manager.get_sticky_config().await,
+                upstream.clone(),
+                &access_token,
+                &email,
+                &session_id_str,
+                &gemini_body,
+                &trace_id,
+            );
+        }
+
         // Upstream call
         let client_wants_stream = request.stream;
         let force_stream_internally = !client_wants_stream;
@@ -381,20 +697,61 @@ async fn handle_google_flow(
         let method = if actual_stream { "streamGenerateContent" } else { "generateContent" };
         let query = if actual_stream { Some("alt=sse") } else { None };
 
+        let sent_thinking = gemini_body["request"]["generationConfig"]
+            .get("thinkingConfig")
+            .is_some();
+        // 未请求 thought parts 时, 上游仍返回的 thinking 不转发给客户端
+        let strip_thinking = !gemini_body["request"]["generationConfig"]["thinkingConfig"]["includeThoughts"]
+            .as_bool()
+            .unwrap_or(false);
         let mut extra_headers = std::collections::HashMap::new();
-        if request_with_mapped.thinking.is_some() && request_with_mapped.tools.is_some() {
+        if request_with_mapped.thinking.as_ref().is_some_and(|t| t.type_ == "enabled")
+            && request_with_mapped.tools.is_some()
+        {
             extra_headers.insert("anthropic-beta".to_string(), "interleaved-thinking-2025-05-14".to_string());
         }
+        // 非流式响应是否保留 thinking 的交错顺序 (客户端声明或我们注入了 interleaved-thinking beta)
+        let interleaved_thinking =
+            client_interleaved_thinking || extra_headers.contains_key("anthropic-beta");
+
+        // 标题生成等轻量后台任务使用紧凑的超时预算
+        let timeout_profile = match background_task_type {
+            Some(
+                BackgroundTaskType::TitleGeneration
+                | BackgroundTaskType::PromptSuggestion
+                | BackgroundTaskType::EnvironmentProbe,
+            ) => TimeoutProfile::Quick,
+            _ => TimeoutProfile::for_method(method),
+        };
 
         let response = match upstream
-            .call_v1_internal_with_headers(method, &access_token, gemini_body, query, extra_headers)
+            .call_v1_internal_with_headers(method, &access_token, gemini_body, query, extra_headers, timeout_profile)
             .await
         {
             Ok(r) => r,
             Err(e) => {
                 last_error = e.clone();
                 debug!("Request failed on attempt {}/{}: {}", attempt + 1, max_attempts, e);
-                
+
+                // 网络错误没有上游状态码, 避免最终错误沿用上一次尝试的状态
+                let connect_timeout = is_connect_timeout(&e);
+                last_status = if connect_timeout {
+                    StatusCode::GATEWAY_TIMEOUT
+                } else {
+                    StatusCode::BAD_GATEWAY
+                };
+
+                // 最后一次尝试: 直接返回本次的真实错误, 不再等待
+                if attempt + 1 >= max_attempts {
+                    break;
+                }
+
+                // 连接超时: 立即换号重试, 不做退避
+                if connect_timeout {
+                    tracing::warn!("[{}] Upstream connect timeout on {}, rotating account immediately", trace_id, email);
+                    continue;
+                }
+
                 // [FIX] Medium jitter (1-3s) for network errors
                 let delay = rand::thread_rng().gen_range(1000..3000);
                 debug!("Network error, waiting {}ms...", delay);
@@ -409,12 +766,31 @@ async fn handle_google_flow(
 
         if status.is_success() {
             token_manager.mark_account_success(&email, Some(&request_with_mapped.model));
-            let context_limit = crate::proxy::mappers::claude::utils::get_context_limit_for_model(&request_with_mapped.model);
+
+            // 调试: 不做映射, 直接转发原始上游 SSE
+            if raw_mode == Some(RawStreamMode::Passthrough) {
+                info!("[{}] Raw upstream stream passthrough (debug)", trace_id);
+                let response = finish_response(
+                    sse_response_builder()
+                        .header("X-Raw-Upstream", "true")
+                        .body(Body::from_stream(response.bytes_stream())),
+                );
+                return with_account_headers(response, &email, Some(&request_with_mapped.model));
+            }
+
+            // 助手文本后处理: 按客户端请求的模型名或映射后的模型名匹配规则
+            let post_processor = post_process::for_model(&[&request.model, &request_with_mapped.model]);
 
             if actual_stream {
+                let upstream_stream = if raw_mode == Some(RawStreamMode::Tee) {
+                    debug_logger::tee_raw_stream(Box::pin(response.bytes_stream()), trace_id.clone())
+                } else {
+                    Box::pin(response.bytes_stream())
+                };
+
                 // [FIX] Handle streaming with retry capability
                 match handle_streaming_response(
-                    response,
+                    upstream_stream,
                     &state,
                     &request,
                     &request_with_mapped,
@@ -424,15 +800,31 @@ async fn handle_google_flow(
                     is_purified,
                     scaling_enabled,
                     context_limit,
+                    hidden_input_tokens,
                     raw_estimated,
                     debug_cfg.clone(),
                     client_wants_stream,
                     config.request_type.clone(),
                     attempt,
+                    post_processor,
+                    strip_thinking,
+                    &retry_config,
+                    &token_lease.account_id,
+                    partial_usage.as_ref().map(|Extension(usage)| usage),
                 )
                 .await
                 {
-                    StreamingResult::Success(resp) => return resp,
+                    StreamingResult::Success(mut resp) => {
+                        if raw_mode == Some(RawStreamMode::Tee) {
+                            if let Ok(v) = trace_id.parse() {
+                                resp.headers_mut().insert(debug_logger::RAW_TRANSCRIPT_HEADER, v);
+                            }
+                        }
+                        if let Some(max_tokens) = adjusted_max_tokens {
+                            set_header_lossy(&mut resp, length_guard::MAX_TOKENS_ADJUSTED_HEADER, &max_tokens.to_string());
+                        }
+                        return resp;
+                    }
                     StreamingResult::RetryNeeded(err) => {
                         last_error = err;
                         // [FIX] Medium-Heavy jitter (2-4s) for streaming interruptions
@@ -443,7 +835,7 @@ async fn handle_google_flow(
                     }
                 }
             } else {
-                return handle_non_streaming_response(
+                let mut resp = handle_non_streaming_response(
                     response,
                     &request_with_mapped,
                     &trace_id,
@@ -451,8 +843,16 @@ async fn handle_google_flow(
                     session_id,
                     scaling_enabled,
                     context_limit,
+                    hidden_input_tokens,
+                    interleaved_thinking,
+                    post_processor,
+                    strip_thinking,
                 )
                 .await;
+                if let Some(max_tokens) = adjusted_max_tokens {
+                    set_header_lossy(&mut resp, length_guard::MAX_TOKENS_ADJUSTED_HEADER, &max_tokens.to_string());
+                }
+                return resp;
             }
         }
 
@@ -467,7 +867,7 @@ async fn handle_google_flow(
             .map(|s| s.to_string());
 
         let error_text = response.text().await.unwrap_or_else(|_| format!("HTTP {}", status_code));
-        last_error = format!("HTTP {}: {}", status_code, error_text);
+        last_error = format!("HTTP {}: {}", status_code, sanitize_upstream_error(&error_text, Some(&project_id)));
         debug!("[{}] Upstream Error Response: {}", trace_id, error_text);
 
         // [FIX] Debug logging for upstream response errors
@@ -486,14 +886,19 @@ async fn handle_google_flow(
             debug_logger::write_debug_payload(&debug_cfg, Some(&trace_id), "upstream_response_error", &payload).await;
         }
 
-        // Handle rate limiting
-        if status_code == 429 || status_code == 529 || status_code == 503 || status_code == 500 {
-            token_manager.mark_rate_limited_async(&email, status_code, retry_after.as_deref(), &error_text, Some(&request_with_mapped.model)).await;
-        }
+        // Handle rate limiting (status filtering / 5xx threshold per scheduling.rate_limit_policy)
+        token_manager.mark_rate_limited_async(&email, status_code, retry_after.as_deref(), &error_text, Some(&request_with_mapped.model)).await;
 
         if status_code == 403 {
             if let Some(acc_id) = token_manager.get_account_id_by_email(&email) {
-                if is_validation_required_error(&error_text) {
+                if let Some(setup) = project_setup::detect(status_code, &error_text) {
+                    tracing::warn!(
+                        "[{}] Claude project setup required on {}, excluding until retried",
+                        trace_id,
+                        email
+                    );
+                    token_manager.mark_project_setup_required(&acc_id, setup).await;
+                } else if is_validation_required_error(&error_text) {
                     let block_minutes = crate::modules::config::load_app_config()
                         .map(|cfg| cfg.validation_block_minutes as i64)
                         .unwrap_or(10);
@@ -537,11 +942,40 @@ async fn handle_google_flow(
             token_manager.report_account_failure(&token_lease.account_id, status_code, &error_text);
         }
 
-        // Handle thinking signature error
-        if status_code == 400 && !retried_without_thinking && is_thinking_signature_error(&error_text) {
+        // 模型不支持 thinking: 记录到能力表, 下一次转换时不再携带 thinkingConfig
+        if status_code == 400 && sent_thinking && is_thinking_unsupported_error(&error_text) {
+            record_no_thinking(&request_with_mapped.model, &error_text);
+            keep_account = true;
+            tracing::warn!(
+                "[{}] {} rejected thinking, retrying without thinking on the same account",
+                trace_id,
+                request_with_mapped.model
+            );
+
+            if apply_retry_strategy(
+                RetryStrategy::FixedDelay(get_thinking_retry_delay()),
+                attempt,
+                max_attempts,
+                status_code,
+                &trace_id,
+                &retry_config,
+            )
+            .await
+            {
+                continue;
+            }
+        }
+
+        // Handle thinking signature error (原始消息模式下不改写历史)
+        if status_code == 400 && !retried_without_thinking && !raw_messages && is_thinking_signature_error(&error_text) {
             retried_without_thinking = true;
             tracing::warn!("[{}] Thinking signature error, retrying without thinking blocks", trace_id);
-            handle_thinking_signature_error(&mut request_for_body, &trace_id);
+            handle_thinking_signature_error(
+                &mut request_for_body,
+                &trace_id,
+                &tool_result_placeholder,
+                redacted_thinking_mode,
+            );
 
             if apply_retry_strategy(
                 RetryStrategy::FixedDelay(get_thinking_retry_delay()),
@@ -549,6 +983,7 @@ async fn handle_google_flow(
                 max_attempts,
                 status_code,
                 &trace_id,
+                &retry_config,
             )
             .await
             {
@@ -563,7 +998,7 @@ async fn handle_google_flow(
 
         let strategy = determine_retry_strategy(status_code, &error_text, retried_without_thinking);
 
-        if apply_retry_strategy(strategy, attempt, max_attempts, status_code, &trace_id).await {
+        if apply_retry_strategy(strategy, attempt, max_attempts, status_code, &trace_id, &retry_config).await {
             if status_code == 429 {
                 token_manager.report_429_penalty(&token_lease.account_id);
             }
@@ -574,16 +1009,26 @@ async fn handle_google_flow(
             continue;
         } else {
             error!("[{}] Non-retryable error {}: {}", trace_id, status_code, error_text);
-            return (status, [("X-Account-Email", email.as_str())], error_text).into_response();
+            return with_account_headers(
+                (status, sanitize_upstream_error(&error_text, Some(&project_id))),
+                &email,
+                None,
+            );
         }
     }
 
+    let retry_after = if last_status == StatusCode::TOO_MANY_REQUESTS {
+        token_manager.min_cooldown_secs(last_mapped_model.as_deref()).await
+    } else {
+        None
+    };
     build_exhausted_retry_error(
         last_status,
-        &last_error,
+        &sanitize_upstream_error(&last_error, None),
         max_attempts,
         last_email.as_deref(),
         last_mapped_model.as_deref(),
+        retry_after,
     )
 }
 
@@ -604,8 +1049,8 @@ fn is_permanent_forbidden_error(error_text: &str) -> bool {
 }
 
 async fn handle_streaming_response(
-    response: reqwest::Response,
-    _state: &AppState,
+    upstream_stream: std::pin::Pin<Box<dyn futures::Stream<Item = Result<Bytes, reqwest::Error>> + Send>>,
+    state: &AppState,
     original_request: &crate::proxy::mappers::claude::models::ClaudeRequest,
     request_with_mapped: &crate::proxy::mappers::claude::models::ClaudeRequest,
     trace_id: &str,
@@ -614,12 +1059,22 @@ async fn handle_streaming_response(
     is_purified: bool,
     scaling_enabled: bool,
     context_limit: u32,
+    hidden_input_tokens: u32,
     raw_estimated: u32,
     debug_cfg: DebugLoggingConfig,
     client_wants_stream: bool,
     request_type: String,
     attempt: usize,
+    post_processor: Option<std::sync::Arc<post_process::TextPostProcessor>>,
+    strip_thinking: bool,
+    retry_config: &RetryConfig,
+    account_id: &str,
+    partial_usage: Option<&PartialUsage>,
 ) -> StreamingResult {
+    let peek_timeout = retry_config.peek_timeout();
+    if let Some(usage) = partial_usage {
+        usage.set_account(email);
+    }
     let meta = json!({
         "protocol": "anthropic",
         "trace_id": trace_id,
@@ -630,8 +1085,9 @@ async fn handle_streaming_response(
         "status": 200,
     });
 
+    let ids_slot = UpstreamIdsSlot::default();
     let gemini_stream = debug_logger::wrap_reqwest_stream_with_debug(
-        Box::pin(response.bytes_stream()),
+        response_ids::capture_stream(upstream_stream, ids_slot.clone()),
         debug_cfg,
         trace_id.to_string(),
         "upstream_response",
@@ -639,21 +1095,46 @@ async fn handle_streaming_response(
     );
 
     let current_message_count = request_with_mapped.messages.len();
+    let dedup_resent_chunks = state.experimental.read().await.dedup_resent_chunks;
 
-    let mut claude_stream = create_claude_sse_stream(
+    let claude_stream = create_claude_sse_stream(
         gemini_stream,
         trace_id.to_string(),
         email.to_string(),
         Some(session_id_str.to_string()),
         scaling_enabled,
         context_limit,
-        Some(raw_estimated),
+        hidden_input_tokens,
+        Some(PromptEstimate::for_request(
+            request_with_mapped,
+            &request_with_mapped.model,
+            raw_estimated,
+        )),
         current_message_count,
+        post_processor,
+        service_tier::resolve(original_request.service_tier.as_deref()).map(|t| t.effective.to_string()),
+        strip_thinking,
+        request_with_mapped.stop_sequences.clone().unwrap_or_default(),
+        dedup_resent_chunks,
     );
 
+    // 流中途停滞看门狗: 停滞计入账号健康分
+    let stall_slot = StallSlot::default();
+    let mut claude_stream = {
+        let token_manager = state.token_manager.clone();
+        let (account_id, email) = (account_id.to_string(), email.to_string());
+        stream_watchdog::watch(
+            claude_stream,
+            StreamProtocol::Claude,
+            retry_config.stall_timeout(),
+            stall_slot.clone(),
+            move |_| token_manager.report_stream_stall(&account_id, &email),
+        )
+    };
+
     // Peek first chunk
     let first_data_chunk = loop {
-        match tokio::time::timeout(std::time::Duration::from_secs(60), claude_stream.next()).await {
+        match tokio::time::timeout(peek_timeout, claude_stream.next()).await {
             Ok(Some(Ok(bytes))) => {
                 if bytes.is_empty() {
                     continue;
@@ -677,7 +1158,11 @@ async fn handle_streaming_response(
             }
             Err(_) => {
                 // [FIX] Signal retry instead of returning 503
-                tracing::warn!("[{}] Timeout waiting for first data (60s), retrying...", trace_id);
+                tracing::warn!(
+                    "[{}] Timeout waiting for first data ({}s), retrying...",
+                    trace_id,
+                    peek_timeout.as_secs()
+                );
                 return StreamingResult::RetryNeeded("Timeout waiting for first data".to_string());
             }
         }
@@ -685,51 +1170,97 @@ async fn handle_streaming_response(
 
     match first_data_chunk {
         Some(bytes) => {
+            // 先缓存少量输出: 期间停滞时客户端尚未收到内容, 可透明换号重试
+            let held = if client_wants_stream {
+                stream_watchdog::hold_output(
+                    &mut claude_stream,
+                    bytes,
+                    StreamProtocol::Claude,
+                    retry_config.stall_retry_chars,
+                )
+                .await
+            } else {
+                vec![Ok(bytes)]
+            };
+            if let Some(stall) = stall_slot.get() {
+                return StreamingResult::RetryNeeded(stall.message());
+            }
+
             let combined_stream = Box::pin(
-                futures::stream::once(async move { Ok(bytes) }).chain(claude_stream.map(
+                futures::stream::iter(held).chain(claude_stream).map(
                     |result| -> Result<Bytes, std::io::Error> {
                         match result {
                             Ok(b) => Ok(b),
                             Err(e) => Ok(Bytes::from(format!("data: {{\"error\":\"{}\"}}\n\n", e))),
                         }
                     },
-                )),
+                ),
             );
 
             if client_wants_stream {
-                StreamingResult::Success(
-                    Response::builder()
-                        .status(StatusCode::OK)
-                        .header(header::CONTENT_TYPE, "text/event-stream")
-                        .header(header::CACHE_CONTROL, "no-cache")
-                        .header(header::CONNECTION, "keep-alive")
-                        .header("X-Accel-Buffering", "no")
-                        .header("X-Account-Email", email)
-                        .header("X-Mapped-Model", &request_with_mapped.model)
+                let response = finish_response(
+                    sse_response_builder()
                         .header("X-Context-Purified", if is_purified { "true" } else { "false" })
-                        .body(Body::from_stream(combined_stream))
-                        .unwrap()
-                )
+                        .body(Body::from_stream(combined_stream)),
+                );
+                StreamingResult::Success(response_ids::attach(
+                    with_account_headers(response, email, Some(&request_with_mapped.model)),
+                    &ids_slot,
+                ))
             } else {
                 use crate::proxy::mappers::claude::collect_stream_to_json;
+                use crate::proxy::mappers::stream_completeness::TRUNCATED_HEADER;
 
-                match collect_stream_to_json(combined_stream).await {
-                    Ok(full_response) => {
-                        info!("[{}] Stream collected and converted to JSON", trace_id);
-                        StreamingResult::Success(
-                            Response::builder()
-                                .status(StatusCode::OK)
-                                .header(header::CONTENT_TYPE, "application/json")
-                                .header("X-Account-Email", email)
-                                .header("X-Mapped-Model", &request_with_mapped.model)
-                                .header("X-Context-Purified", if is_purified { "true" } else { "false" })
-                                .body(Body::from(serde_json::to_string(&full_response).unwrap()))
-                                .unwrap()
-                        )
+                // 客户端在收集期间断开时整个 future 被丢弃, 上游流随之释放; 这里记录已产生的部分用量
+                let partial_usage = partial_usage.cloned();
+                let combined_stream = combined_stream.inspect(move |chunk| {
+                    if let (Some(usage), Ok(bytes)) = (&partial_usage, chunk) {
+                        usage.observe_claude_sse(bytes);
+                    }
+                });
+                let collected = collect_stream_to_json(combined_stream).await;
+                // 非流式客户端尚未收到任何内容: 停滞时整体换号重试
+                if let Some(stall) = stall_slot.get() {
+                    return StreamingResult::RetryNeeded(stall.message());
+                }
+                match collected {
+                    Ok(collected) => {
+                        let completeness = collected.completeness;
+                        if !completeness.is_complete() {
+                            if !completeness.has_content {
+                                tracing::warn!("[{}] Stream ended before completion without content, retrying...", trace_id);
+                                return StreamingResult::RetryNeeded("Truncated response stream (no content)".to_string());
+                            }
+                            tracing::warn!(
+                                "[{}] Stream ended before completion (confidence {:.2}), returning partial content as max_tokens",
+                                trace_id,
+                                completeness.confidence()
+                            );
+                        } else {
+                            info!("[{}] Stream collected and converted to JSON", trace_id);
+                        }
+                        let mut response = with_account_headers(
+                            (
+                                [("X-Context-Purified", if is_purified { "true" } else { "false" })],
+                                Json(&collected.response),
+                            ),
+                            email,
+                            Some(&request_with_mapped.model),
+                        );
+                        if !completeness.is_complete() {
+                            response
+                                .headers_mut()
+                                .insert(TRUNCATED_HEADER, header::HeaderValue::from_static("true"));
+                        }
+                        StreamingResult::Success(response_ids::attach(response, &ids_slot))
+                    }
+                    // 流中途上下文超限: 与非流式分支相同的结构化 400
+                    Err(e) if e.contains(PROMPT_TOO_LONG_CODE) => {
+                        StreamingResult::Success(build_context_too_long_error(email))
                     }
                     Err(e) => {
                         StreamingResult::Success(
-                            (StatusCode::INTERNAL_SERVER_ERROR, format!("Stream collection error: {}", e)).into_response()
+                            error_response(ErrorProtocol::Claude, StatusCode::INTERNAL_SERVER_ERROR, format!("Stream collection error: {}", e))
                         )
                     }
                 }
@@ -751,37 +1282,55 @@ async fn handle_non_streaming_response(
     session_id: Option<&str>,
     scaling_enabled: bool,
     context_limit: u32,
+    hidden_input_tokens: u32,
+    interleaved_thinking: bool,
+    post_processor: Option<std::sync::Arc<post_process::TextPostProcessor>>,
+    strip_thinking: bool,
 ) -> Response {
     let bytes = match response.bytes().await {
         Ok(b) => b,
-        Err(e) => return (StatusCode::BAD_GATEWAY, format!("Failed to read body: {}", e)).into_response(),
+        Err(e) => return error_response(ErrorProtocol::Claude, StatusCode::BAD_GATEWAY, format!("Failed to read body: {}", e)),
     };
 
     let gemini_resp: Value = match serde_json::from_slice(&bytes) {
         Ok(v) => v,
-        Err(e) => return (StatusCode::BAD_GATEWAY, format!("Parse error: {}", e)).into_response(),
+        Err(e) => return error_response(ErrorProtocol::Claude, StatusCode::BAD_GATEWAY, format!("Parse error: {}", e)),
     };
 
     let raw = gemini_resp.get("response").unwrap_or(&gemini_resp);
+    let upstream_ids = UpstreamIdsSlot::from(UpstreamIds::from_json(&gemini_resp));
 
     let gemini_response: crate::proxy::mappers::claude::models::GeminiResponse = match serde_json::from_value(raw.clone()) {
         Ok(r) => r,
-        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, format!("Convert error: {}", e)).into_response(),
+        Err(e) => return error_response(ErrorProtocol::Claude, StatusCode::INTERNAL_SERVER_ERROR, format!("Convert error: {}", e)),
     };
 
     let s_id_owned = session_id.map(|s| s.to_string());
 
-    let claude_response = match transform_response(
+    let mut claude_response = match transform_response(
         &gemini_response,
         scaling_enabled,
         context_limit,
+        hidden_input_tokens,
         s_id_owned,
         request_with_mapped.model.clone(),
         request_with_mapped.messages.len(),
+        interleaved_thinking,
+        post_processor,
+        strip_thinking,
+        request_with_mapped.stop_sequences.as_deref().unwrap_or_default(),
     ) {
         Ok(r) => r,
-        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, format!("Transform error: {}", e)).into_response(),
+        Err(e) => {
+            return mark_local_failure(error_response(
+                ErrorProtocol::Claude,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Transform error: {}", e),
+            ))
+        }
     };
+    claude_response.usage.service_tier = service_tier::resolve(request_with_mapped.service_tier.as_deref())
+        .map(|t| t.effective.to_string());
 
     info!(
         "[{}] Request finished. Model: {}, Tokens: In {}, Out {}",
@@ -791,12 +1340,14 @@ async fn handle_non_streaming_response(
         claude_response.usage.output_tokens
     );
 
-    (
-        StatusCode::OK,
-        [("X-Account-Email", email), ("X-Mapped-Model", request_with_mapped.model.as_str())],
-        Json(claude_response),
+    response_ids::attach(
+        with_account_headers(
+            (StatusCode::OK, Json(claude_response)),
+            email,
+            Some(&request_with_mapped.model),
+        ),
+        &upstream_ids,
     )
-        .into_response()
 }
 
 fn log_request_details(request: &crate::proxy::mappers::claude::models::ClaudeRequest, trace_id: &str) {
@@ -0,0 +1,452 @@
+//! Conversation length guard.
+//!
+//! 消息数过多时, 即使请求成功, 本地处理也要数秒, 模型质量明显下降, Layer-3 摘要效果也很差:
+//! - 软水位: 每个会话提示一次用户执行 /compact (追加 text 块或响应头)
+//! - 硬水位: 不论 token 压力, 直接触发 Layer-3 (Fork + Summary)
+//! - 输出预算: max_tokens 超过输入之后的剩余上下文时, 下调 maxOutputTokens (及 thinkingBudget)
+
+use axum::body::Body;
+use axum::http::header;
+use axum::response::Response;
+use bytes::Bytes;
+use dashmap::DashMap;
+use futures::StreamExt;
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use super::super::compression::{try_compress_with_summary, ContextSummaryContext, ForkedRequest};
+use crate::proxy::config::{ExperimentalConfig, MessageAdvisoryMode};
+use crate::proxy::handlers::common::set_header_lossy;
+use crate::proxy::mappers::claude::models::ClaudeRequest;
+
+pub const ADVISORY_HEADER: &str = "X-Context-Advisory";
+/// 下调后的 maxOutputTokens
+pub const MAX_TOKENS_ADJUSTED_HEADER: &str = "X-Max-Tokens-Adjusted";
+/// 输入估算误差的安全余量
+const OUTPUT_SAFETY_MARGIN: u32 = 2048;
+/// 下调后可见回答 (以及 thinking) 的最小预算, 低于该值视为上下文过长
+const MIN_OUTPUT_TOKENS: u32 = 1024;
+/// 已提示会话的保留时长与数量上限
+const ADVISED_TTL: Duration = Duration::from_secs(24 * 3600);
+const ADVISED_CAPACITY: usize = 10_000;
+
+/// session_id -> 提示时间
+static ADVISED: Lazy<DashMap<String, Instant>> = Lazy::new(DashMap::new);
+
+/// 待注入的提示
+#[derive(Debug, Clone)]
+pub struct Advisory {
+    session_id: String,
+    trace_id: String,
+    message_count: usize,
+    threshold: usize,
+    text: String,
+    mode: MessageAdvisoryMode,
+}
+
+/// 判断本次请求是否需要提示 (会话已提示过则返回 None)
+pub fn check_advisory(
+    request: &ClaudeRequest,
+    trace_id: &str,
+    config: &ExperimentalConfig,
+) -> Option<Advisory> {
+    let threshold = config.message_advisory_threshold;
+    let message_count = request.messages.len();
+    if threshold == 0 || message_count <= threshold {
+        return None;
+    }
+    let session_id = crate::proxy::session_manager::SessionManager::extract_session_id(request);
+    if ADVISED.contains_key(&session_id) {
+        return None;
+    }
+    Some(Advisory {
+        session_id,
+        trace_id: trace_id.to_string(),
+        message_count,
+        threshold,
+        text: config
+            .message_advisory_text
+            .replace("{count}", &message_count.to_string()),
+        mode: config.message_advisory_mode,
+    })
+}
+
+/// 记录会话已提示, 首次记录时返回 true
+fn mark_advised(session_id: &str) -> bool {
+    if ADVISED.len() >= ADVISED_CAPACITY {
+        ADVISED.retain(|_, at| at.elapsed() < ADVISED_TTL);
+    }
+    ADVISED.insert(session_id.to_string(), Instant::now()).is_none()
+}
+
+/// 在成功响应上注入提示; 失败的响应不消耗该会话的提示机会
+pub async fn apply_advisory(response: Response, advisory: Advisory) -> Response {
+    if !response.status().is_success() || !mark_advised(&advisory.session_id) {
+        return response;
+    }
+    warn!(
+        trace_id = %advisory.trace_id,
+        session_id = %advisory.session_id,
+        message_count = advisory.message_count,
+        threshold = advisory.threshold,
+        mode = ?advisory.mode,
+        "[LengthGuard] Conversation exceeds message watermark, advising client to /compact"
+    );
+
+    let (mut parts, body) = response.into_parts();
+    if advisory.mode == MessageAdvisoryMode::Header {
+        let mut response = Response::from_parts(parts, body);
+        set_header_lossy(&mut response, ADVISORY_HEADER, &advisory.text);
+        return response;
+    }
+
+    let content_type = parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_string();
+
+    if content_type.starts_with("text/event-stream") {
+        let mut upstream = body.into_data_stream();
+        let injected = async_stream::stream! {
+            let mut injector = SseAdvisoryInjector::new(advisory.text);
+            while let Some(chunk) = upstream.next().await {
+                match chunk {
+                    Ok(bytes) => {
+                        let out = injector.push(&bytes);
+                        if !out.is_empty() {
+                            yield Ok::<Bytes, axum::Error>(Bytes::from(out));
+                        }
+                    }
+                    Err(e) => {
+                        yield Err(e);
+                        return;
+                    }
+                }
+            }
+            let rest = injector.finish();
+            if !rest.is_empty() {
+                yield Ok(Bytes::from(rest));
+            }
+        };
+        return Response::from_parts(parts, Body::from_stream(injected));
+    }
+
+    if content_type.starts_with("application/json") {
+        let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                tracing::error!("[LengthGuard] Failed to read response body: {}", e);
+                return Response::from_parts(parts, Body::empty());
+            }
+        };
+        let body = match serde_json::from_slice::<Value>(&bytes) {
+            Ok(mut value) => {
+                if let Some(content) = value.get_mut("content").and_then(|c| c.as_array_mut()) {
+                    content.push(json!({"type": "text", "text": advisory.text}));
+                }
+                parts.headers.remove(header::CONTENT_LENGTH);
+                Body::from(value.to_string())
+            }
+            Err(_) => Body::from(bytes),
+        };
+        return Response::from_parts(parts, body);
+    }
+
+    Response::from_parts(parts, body)
+}
+
+/// 在 message_delta 之前插入一个完整的 text 块 (index 接在已有块之后)
+struct SseAdvisoryInjector {
+    text: Option<String>,
+    next_index: u64,
+    /// 按字节缓冲, 避免多字节字符被 chunk 边界截断
+    buffer: Vec<u8>,
+}
+
+impl SseAdvisoryInjector {
+    fn new(text: String) -> Self {
+        Self {
+            text: Some(text),
+            next_index: 0,
+            buffer: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, chunk: &[u8]) -> String {
+        self.buffer.extend_from_slice(chunk);
+        let mut out = String::new();
+        while let Some(pos) = self.buffer.windows(2).position(|w| w == b"\n\n") {
+            let event: Vec<u8> = self.buffer.drain(..pos + 2).collect();
+            let event = String::from_utf8_lossy(&event);
+            self.inspect(&event, &mut out);
+            out.push_str(&event);
+        }
+        out
+    }
+
+    fn finish(&mut self) -> String {
+        String::from_utf8_lossy(&std::mem::take(&mut self.buffer)).into_owned()
+    }
+
+    fn inspect(&mut self, event: &str, out: &mut String) {
+        let Some(data) = event
+            .lines()
+            .find_map(|l| l.strip_prefix("data:"))
+            .and_then(|d| serde_json::from_str::<Value>(d.trim_start()).ok())
+        else {
+            return;
+        };
+        match data.get("type").and_then(|t| t.as_str()) {
+            Some("content_block_start") => {
+                let index = data.get("index").and_then(|i| i.as_u64()).unwrap_or(0);
+                self.next_index = self.next_index.max(index + 1);
+            }
+            Some("message_delta") => {
+                if let Some(text) = self.text.take() {
+                    let index = self.next_index;
+                    let events = [
+                        ("content_block_start", json!({"type": "content_block_start", "index": index, "content_block": {"type": "text", "text": ""}})),
+                        ("content_block_delta", json!({"type": "content_block_delta", "index": index, "delta": {"type": "text_delta", "text": text}})),
+                        ("content_block_stop", json!({"type": "content_block_stop", "index": index})),
+                    ];
+                    for (name, data) in events {
+                        out.push_str(&format!("event: {}\ndata: {}\n\n", name, data));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// 超过硬水位时直接执行 Layer-3, 返回 fork 后的请求; 失败时回退到常规压缩流程
+pub async fn force_compress_if_needed(
+    request: &ClaudeRequest,
+    trace_id: &str,
+    threshold: usize,
+    summary_context: &ContextSummaryContext,
+) -> Option<ForkedRequest> {
+    let message_count = request.messages.len();
+    if threshold == 0 || message_count <= threshold {
+        return None;
+    }
+    warn!(
+        trace_id = %trace_id,
+        message_count,
+        threshold,
+        "[LengthGuard] Conversation exceeds hard message watermark, forcing Layer-3"
+    );
+    match try_compress_with_summary(request, trace_id, summary_context).await {
+        Ok(forked) => {
+            info!(
+                "[{}] [LengthGuard] Forced fork: {} -> {} messages",
+                trace_id,
+                message_count,
+                forked.request.messages.len()
+            );
+            Some(forked)
+        }
+        Err(e) => {
+            warn!("[{}] [LengthGuard] Forced Layer-3 failed: {}, continuing without it", trace_id, e);
+            None
+        }
+    }
+}
+
+/// 输出预算检查结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputBudget {
+    Unchanged,
+    Adjusted {
+        requested: u32,
+        max_output_tokens: u32,
+        thinking_budget: Option<u32>,
+    },
+    /// 剩余上下文不足以容纳最小输出
+    TooLong,
+}
+
+/// 按剩余上下文收紧 generationConfig 中的 maxOutputTokens; maxOutputTokens 已包含 thinking 预算,
+/// 需要下调时 thinkingBudget 同步缩小, 为可见回答保留至少 MIN_OUTPUT_TOKENS
+pub fn fit_output_budget(gemini_body: &mut Value, context_limit: u32, input_tokens: u32) -> OutputBudget {
+    let config = &mut gemini_body["request"]["generationConfig"];
+    let Some(requested) = config["maxOutputTokens"].as_u64() else {
+        return OutputBudget::Unchanged;
+    };
+    let requested = requested.min(u32::MAX as u64) as u32;
+    let available = context_limit
+        .saturating_sub(input_tokens)
+        .saturating_sub(OUTPUT_SAFETY_MARGIN);
+    if requested <= available {
+        return OutputBudget::Unchanged;
+    }
+
+    // 0 预算表示 thinking 已关闭, 无需为其保留空间
+    let thinking = config["thinkingConfig"]["thinkingBudget"].as_u64().filter(|budget| *budget > 0);
+    let floor = if thinking.is_some() { MIN_OUTPUT_TOKENS * 2 } else { MIN_OUTPUT_TOKENS };
+    if available < floor {
+        return OutputBudget::TooLong;
+    }
+
+    let thinking_budget = thinking.map(|budget| (budget as u32).min(available - MIN_OUTPUT_TOKENS));
+    config["maxOutputTokens"] = json!(available);
+    if let Some(budget) = thinking_budget {
+        config["thinkingConfig"]["thinkingBudget"] = json!(budget);
+    }
+    OutputBudget::Adjusted {
+        requested,
+        max_output_tokens: available,
+        thinking_budget,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::mappers::claude::models::{Message, MessageContent};
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+
+    fn long_request(first: &str, count: usize) -> ClaudeRequest {
+        let messages = (0..count)
+            .map(|i| Message {
+                role: if i % 2 == 0 { "user" } else { "assistant" }.to_string(),
+                content: MessageContent::String(if i == 0 { first.to_string() } else { format!("turn {}", i) }),
+            })
+            .collect();
+        let mut request: ClaudeRequest =
+            serde_json::from_value(json!({"model": "claude-sonnet-4-5", "messages": []})).unwrap();
+        request.messages = messages;
+        request
+    }
+
+    #[tokio::test]
+    async fn test_advisory_injected_once_per_session() {
+        let config = ExperimentalConfig {
+            message_advisory_threshold: 10,
+            ..ExperimentalConfig::default()
+        };
+        assert!(check_advisory(&long_request("length guard short conversation", 10), "t", &config).is_none());
+
+        let request = long_request("length guard json conversation", 11);
+        let advisory = check_advisory(&request, "t", &config).unwrap();
+        assert!(advisory.text.contains("11 messages"));
+
+        // 失败响应不消耗提示机会
+        let failed = apply_advisory(StatusCode::SERVICE_UNAVAILABLE.into_response(), advisory.clone()).await;
+        assert_eq!(failed.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(check_advisory(&request, "t", &config).is_some());
+
+        let body = json!({"content": [{"type": "text", "text": "hello"}]});
+        let response = apply_advisory(axum::Json(body).into_response(), advisory).await;
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let value: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(value["content"].as_array().unwrap().len(), 2);
+        assert!(value["content"][1]["text"].as_str().unwrap().contains("/compact"));
+
+        assert!(check_advisory(&request, "t", &config).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_advisory_header_mode() {
+        let config = ExperimentalConfig {
+            message_advisory_threshold: 10,
+            message_advisory_mode: MessageAdvisoryMode::Header,
+            ..ExperimentalConfig::default()
+        };
+        let advisory = check_advisory(&long_request("length guard header conversation", 20), "t", &config).unwrap();
+        let response = apply_advisory(axum::Json(json!({"content": []})).into_response(), advisory).await;
+        assert!(response.headers().get(ADVISORY_HEADER).unwrap().to_str().unwrap().contains("/compact"));
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(serde_json::from_slice::<Value>(&bytes).unwrap()["content"], json!([]));
+    }
+
+    #[test]
+    fn test_sse_injection_before_message_delta() {
+        let mut injector = SseAdvisoryInjector::new("run /compact".to_string());
+        let stream = concat!(
+            "event: message_start\ndata: {\"type\":\"message_start\"}\n\n",
+            "event: content_block_start\ndata: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"thinking\"}}\n\n",
+            "event: content_block_start\ndata: {\"type\":\"content_block_start\",\"index\":1,\"content_block\":{\"type\":\"text\"}}\n\n",
+            "event: content_block_stop\ndata: {\"type\":\"content_block_stop\",\"index\":1}\n\n",
+            "event: message_delta\ndata: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"end_turn\"}}\n\n",
+            "event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n",
+        );
+        // 按不规则边界切分输入
+        let mut out = String::new();
+        for chunk in stream.as_bytes().chunks(37) {
+            out.push_str(&injector.push(chunk));
+        }
+        out.push_str(&injector.finish());
+
+        assert!(out.find("run /compact").unwrap() < out.find("event: message_delta").unwrap());
+        assert_eq!(out.matches("\"index\":2").count(), 3);
+        assert_eq!(out.matches("run /compact").count(), 1);
+        assert!(out.ends_with("event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n"));
+    }
+
+    fn body(max_output_tokens: u32, thinking_budget: Option<u32>) -> Value {
+        let mut config = json!({ "maxOutputTokens": max_output_tokens });
+        if let Some(budget) = thinking_budget {
+            config["thinkingConfig"] = json!({ "includeThoughts": true, "thinkingBudget": budget });
+        }
+        json!({ "request": { "generationConfig": config } })
+    }
+
+    #[test]
+    fn test_output_budget_boundary_without_thinking() {
+        let limit = 100_000;
+        // 恰好容纳: 不调整
+        let mut fits = body(8192, None);
+        let input = limit - OUTPUT_SAFETY_MARGIN - 8192;
+        assert_eq!(fit_output_budget(&mut fits, limit, input), OutputBudget::Unchanged);
+        assert_eq!(fits["request"]["generationConfig"]["maxOutputTokens"], 8192);
+
+        let mut over = body(8192, None);
+        assert_eq!(
+            fit_output_budget(&mut over, limit, input + 1),
+            OutputBudget::Adjusted { requested: 8192, max_output_tokens: 8191, thinking_budget: None }
+        );
+        assert_eq!(over["request"]["generationConfig"]["maxOutputTokens"], 8191);
+
+        // 下限
+        let input = limit - OUTPUT_SAFETY_MARGIN - MIN_OUTPUT_TOKENS;
+        assert!(matches!(fit_output_budget(&mut body(8192, None), limit, input), OutputBudget::Adjusted { .. }));
+        assert_eq!(fit_output_budget(&mut body(8192, None), limit, input + 1), OutputBudget::TooLong);
+
+        // 未设置 maxOutputTokens 时不处理
+        let mut unset = json!({ "request": { "generationConfig": {} } });
+        assert_eq!(fit_output_budget(&mut unset, limit, limit), OutputBudget::Unchanged);
+    }
+
+    #[test]
+    fn test_output_budget_boundary_with_thinking() {
+        let limit = 100_000;
+        let mut request = body(24_192, Some(16_000));
+        let input = limit - OUTPUT_SAFETY_MARGIN - 10_000;
+        assert_eq!(
+            fit_output_budget(&mut request, limit, input),
+            OutputBudget::Adjusted { requested: 24_192, max_output_tokens: 10_000, thinking_budget: Some(9_000) }
+        );
+        let config = &request["request"]["generationConfig"];
+        assert_eq!(config["maxOutputTokens"], 10_000);
+        assert_eq!(config["thinkingConfig"]["thinkingBudget"], 9_000);
+
+        // thinking 预算本身未超出时只下调 maxOutputTokens
+        let mut small = body(24_192, Some(2_000));
+        assert_eq!(
+            fit_output_budget(&mut small, limit, input),
+            OutputBudget::Adjusted { requested: 24_192, max_output_tokens: 10_000, thinking_budget: Some(2_000) }
+        );
+
+        // thinking 与可见回答都需要最小预算
+        let input = limit - OUTPUT_SAFETY_MARGIN - 2 * MIN_OUTPUT_TOKENS;
+        assert!(matches!(fit_output_budget(&mut body(24_192, Some(16_000)), limit, input), OutputBudget::Adjusted { .. }));
+        assert_eq!(fit_output_budget(&mut body(24_192, Some(16_000)), limit, input + 1), OutputBudget::TooLong);
+        assert!(matches!(fit_output_budget(&mut body(8192, None), limit, input + 1), OutputBudget::Adjusted { .. }));
+    }
+}
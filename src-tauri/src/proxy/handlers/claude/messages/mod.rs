@@ -6,12 +6,17 @@
 //!
 //! - `handler` - Main request handler
 //! - `compression` - 3-layer progressive compression
+//! - `fork_state` - Persisted Layer-3 forks, re-applied on later turns of the session
+//! - `length_guard` - Message-count watermarks (/compact advisory, forced Layer-3)
 //! - `retry` - Error handling and retry logic
 //! - `response` - Response building helpers
 
 mod compression;
+mod fork_state;
 mod handler;
+mod length_guard;
 mod response;
 mod retry;
 
 pub use handler::handle_messages;
+pub(super) use response::build_invalid_request_error;
@@ -1,11 +1,16 @@
 //! Response building helpers for Claude messages handler.
 
 use axum::{
-    http::{header, HeaderMap, StatusCode},
+    http::StatusCode,
     response::{IntoResponse, Response},
     Json,
 };
-use serde_json::json;
+use serde_json::{json, Value};
+
+use crate::proxy::handlers::common::{
+    mark_local_failure, set_header_lossy, with_account_headers, with_retry_after,
+};
+use crate::proxy::mappers::error_classifier::{PROMPT_TOO_LONG_MESSAGE, PROMPT_TOO_LONG_SUGGESTION};
 
 /// Build error response for invalid request.
 pub fn build_invalid_request_error(message: String) -> Response {
@@ -22,29 +27,35 @@ pub fn build_invalid_request_error(message: String) -> Response {
         .into_response()
 }
 
+/// Attach the pool's shortest cooldown to an error body (`error.retry_after_seconds`)
+fn insert_retry_after(mut body: Value, retry_after: Option<u64>) -> Value {
+    if let Some(secs) = retry_after {
+        body["error"]["retry_after_seconds"] = json!(secs.max(1));
+    }
+    body
+}
+
 /// Build error response for service unavailable.
-pub fn build_service_unavailable_error(message: String, mapped_model: &str) -> Response {
-    let headers = [("X-Mapped-Model", mapped_model)];
-    (
-        StatusCode::SERVICE_UNAVAILABLE,
-        headers,
-        Json(json!({
-            "type": "error",
-            "error": {
-                "type": "overloaded_error",
-                "message": format!("No available accounts: {}", message)
-            }
-        })),
-    )
-        .into_response()
+pub fn build_service_unavailable_error(message: String, mapped_model: &str, retry_after: Option<u64>) -> Response {
+    let body = json!({
+        "type": "error",
+        "error": {
+            "type": "overloaded_error",
+            "message": format!("No available accounts: {}", message)
+        }
+    });
+    let mut response = with_retry_after(
+        (StatusCode::SERVICE_UNAVAILABLE, Json(insert_retry_after(body, retry_after))),
+        retry_after,
+    );
+    set_header_lossy(&mut response, "X-Mapped-Model", mapped_model);
+    response
 }
 
 /// Build error response for transform error.
 pub fn build_transform_error(message: String, model: &str, email: &str) -> Response {
-    let headers = [("X-Mapped-Model", model), ("X-Account-Email", email)];
-    (
+    let response = (
         StatusCode::INTERNAL_SERVER_ERROR,
-        headers,
         Json(json!({
             "type": "error",
             "error": {
@@ -52,26 +63,25 @@ pub fn build_transform_error(message: String, model: &str, email: &str) -> Respo
                 "message": format!("Transform error: {}", message)
             }
         })),
-    )
-        .into_response()
+    );
+    mark_local_failure(with_account_headers(response, email, Some(model)))
 }
 
 /// Build error response for context too long.
 pub fn build_context_too_long_error(email: &str) -> Response {
-    (
+    let response = (
         StatusCode::BAD_REQUEST,
-        [("X-Account-Email", email)],
         Json(json!({
             "id": "err_prompt_too_long",
             "type": "error",
             "error": {
                 "type": "invalid_request_error",
-                "message": "Prompt is too long (server-side context limit reached).",
-                "suggestion": "Please: 1) Execute '/compact' in Claude Code 2) Reduce conversation history 3) Switch to gemini-1.5-pro (2M context limit)"
+                "message": PROMPT_TOO_LONG_MESSAGE,
+                "suggestion": PROMPT_TOO_LONG_SUGGESTION
             }
         })),
-    )
-        .into_response()
+    );
+    with_account_headers(response, email, None)
 }
 
 /// Build error response for compression failure.
@@ -97,20 +107,8 @@ pub fn build_exhausted_retry_error(
     max_attempts: usize,
     last_email: Option<&str>,
     last_mapped_model: Option<&str>,
+    retry_after: Option<u64>,
 ) -> Response {
-    let mut headers = HeaderMap::new();
-
-    if let Some(email) = last_email {
-        if let Ok(v) = header::HeaderValue::from_str(email) {
-            headers.insert("X-Account-Email", v);
-        }
-    }
-    if let Some(model) = last_mapped_model {
-        if let Ok(v) = header::HeaderValue::from_str(model) {
-            headers.insert("X-Mapped-Model", v);
-        }
-    }
-
     let error_type = match last_status.as_u16() {
         400 => "invalid_request_error",
         401 => "authentication_error",
@@ -120,19 +118,22 @@ pub fn build_exhausted_retry_error(
         _ => "api_error",
     };
 
-    (
-        last_status,
-        headers,
-        Json(json!({
-            "type": "error",
-            "error": {
-                "id": "err_retry_exhausted",
-                "type": error_type,
-                "message": format!("All {} attempts failed. Last status: {}. Error: {}", max_attempts, last_status, last_error)
-            }
-        })),
-    )
-        .into_response()
+    let body = json!({
+        "type": "error",
+        "error": {
+            "id": "err_retry_exhausted",
+            "type": error_type,
+            "message": format!("All {} attempts failed. Last status: {}. Error: {}", max_attempts, last_status, last_error)
+        }
+    });
+    let mut response = with_retry_after((last_status, Json(insert_retry_after(body, retry_after))), retry_after);
+    if let Some(email) = last_email {
+        set_header_lossy(&mut response, "X-Account-Email", email);
+    }
+    if let Some(model) = last_mapped_model {
+        set_header_lossy(&mut response, "X-Mapped-Model", model);
+    }
+    response
 }
 
 /// Extract error type from status code.
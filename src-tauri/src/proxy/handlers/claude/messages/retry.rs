@@ -1,6 +1,8 @@
 //! Error handling and retry logic for thinking signature failures.
 
+use crate::proxy::config::RedactedThinkingMode;
 use crate::proxy::mappers::claude::models::{ClaudeRequest, ContentBlock, MessageContent};
+use crate::proxy::mappers::claude::thinking_utils::apply_redacted_thinking_mode;
 use tokio::time::Duration;
 use tracing::debug;
 
@@ -27,7 +29,13 @@ pub fn is_context_too_long_error(error_text: &str) -> bool {
 }
 
 /// Handle thinking signature error by removing thinking blocks.
-pub fn handle_thinking_signature_error(request: &mut ClaudeRequest, trace_id: &str) {
+/// redacted_thinking blocks follow `redacted_mode` (the fallback only runs for Google targets).
+pub fn handle_thinking_signature_error(
+    request: &mut ClaudeRequest,
+    trace_id: &str,
+    tool_result_placeholder: &str,
+    redacted_mode: RedactedThinkingMode,
+) {
     // Append repair prompt to last user message
     if let Some(last_msg) = request.messages.last_mut() {
         if last_msg.role == "user" {
@@ -62,12 +70,10 @@ pub fn handle_thinking_signature_error(request: &mut ClaudeRequest, trace_id: &s
                             new_blocks.push(ContentBlock::Text { text: thinking });
                         }
                     }
-                    ContentBlock::RedactedThinking { .. } => {
-                        // Discard redacted thinking
-                    }
                     _ => new_blocks.push(block),
                 }
             }
+            apply_redacted_thinking_mode(&mut new_blocks, redacted_mode.for_target(false));
             *blocks = new_blocks;
         }
     }
@@ -75,6 +81,7 @@ pub fn handle_thinking_signature_error(request: &mut ClaudeRequest, trace_id: &s
     // Close tool loop
     crate::proxy::mappers::claude::thinking_utils::close_tool_loop_for_thinking(
         &mut request.messages,
+        tool_result_placeholder,
     );
 
     // Normalize model name
@@ -96,3 +103,57 @@ pub fn handle_thinking_signature_error(request: &mut ClaudeRequest, trace_id: &s
 pub fn get_thinking_retry_delay() -> Duration {
     Duration::from_millis(200)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::mappers::claude::models::Message;
+    use crate::proxy::mappers::claude::thinking_utils::REDACTED_THINKING_MARKER;
+
+    fn fallback_request() -> ClaudeRequest {
+        let mut request: ClaudeRequest = serde_json::from_value(serde_json::json!({
+            "model": "claude-sonnet-4-5",
+            "messages": [{ "role": "user", "content": "list files" }]
+        }))
+        .unwrap();
+        request.messages.push(Message {
+            role: "assistant".to_string(),
+            content: MessageContent::Array(vec![
+                ContentBlock::RedactedThinking { data: "opaque".to_string() },
+                ContentBlock::ToolUse {
+                    id: "toolu_f".to_string(),
+                    name: "bash".to_string(),
+                    input: serde_json::json!({"command": "ls"}),
+                    signature: None,
+                    cache_control: None,
+                },
+            ]),
+        });
+        request
+    }
+
+    fn assistant_blocks(request: &ClaudeRequest) -> Vec<ContentBlock> {
+        match &request.messages[1].content {
+            MessageContent::Array(blocks) => blocks.clone(),
+            MessageContent::String(_) => panic!("expected array content"),
+        }
+    }
+
+    #[test]
+    fn test_signature_fallback_applies_redacted_policy() {
+        // Preserve 在 Google 回退路径上等同于 Drop
+        for mode in [RedactedThinkingMode::Preserve, RedactedThinkingMode::Drop] {
+            let mut request = fallback_request();
+            handle_thinking_signature_error(&mut request, "test", "placeholder", mode);
+            let blocks = assistant_blocks(&request);
+            assert!(!blocks.iter().any(|b| matches!(b, ContentBlock::RedactedThinking { .. })));
+            assert!(blocks.iter().any(|b| matches!(b, ContentBlock::ToolUse { id, .. } if id == "toolu_f")));
+        }
+
+        let mut request = fallback_request();
+        handle_thinking_signature_error(&mut request, "test", "placeholder", RedactedThinkingMode::Marker);
+        let blocks = assistant_blocks(&request);
+        assert!(matches!(&blocks[0], ContentBlock::Text { text } if text == REDACTED_THINKING_MARKER));
+        assert!(matches!(&blocks[1], ContentBlock::ToolUse { .. }));
+    }
+}
@@ -5,12 +5,17 @@ mod background;
 mod compression;
 mod messages;
 mod models;
+mod service_tier;
 mod tokens;
+mod tool_summary;
 mod warmup;
 
 // Re-export all public handlers
 pub use messages::handle_messages;
 pub use models::handle_list_models;
 pub use tokens::handle_count_tokens;
+pub use compression::context_summary_usage;
+pub use background::{compaction_count, BackgroundTaskType, INTERNAL_BACKGROUND_TASK};
+pub use warmup::{is_warmup_request, warmup_intercept_counts};
 
 // Re-export internal utilities for use within the module
@@ -4,25 +4,12 @@
 use axum::{extract::State, response::IntoResponse, Json};
 use serde_json::json;
 
+use crate::proxy::handlers::common::{listed_models, model_list_entry};
 use crate::proxy::server::AppState;
 
 /// List all available models in Claude format
 pub async fn handle_list_models(State(state): State<AppState>) -> impl IntoResponse {
-    use crate::proxy::common::model_mapping::get_all_dynamic_models;
-
-    let model_ids = get_all_dynamic_models(&state.custom_mapping).await;
-
-    let data: Vec<_> = model_ids
-        .into_iter()
-        .map(|id| {
-            json!({
-                "id": id,
-                "object": "model",
-                "created": 1706745600,
-                "owned_by": "antigravity"
-            })
-        })
-        .collect();
+    let data: Vec<_> = listed_models(&state).await.iter().map(model_list_entry).collect();
 
     Json(json!({
         "object": "list",
@@ -0,0 +1,52 @@
+// Anthropic service_tier Handling
+// 客户端的 service_tier 映射为调度优先级: standard_only / batch 为可排队的低优先级请求,
+// auto 保持默认调度; 未携带时行为不变
+
+use crate::proxy::token_manager::RequestPriority;
+
+/// 回显生效等级的响应头 (监控中间件据此记录到日志)
+pub const SERVICE_TIER_HEADER: &str = "X-Service-Tier";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ServiceTier {
+    pub priority: RequestPriority,
+    /// 回显在 usage.service_tier 中的等级 (即客户端请求的等级, 小写)
+    pub effective: &'static str,
+}
+
+/// 解析请求的 service_tier, 未携带或无法识别时返回 None (保持默认行为)
+pub fn resolve(service_tier: Option<&str>) -> Option<ServiceTier> {
+    let tier = service_tier?.trim().to_ascii_lowercase();
+    let (priority, effective) = match tier.as_str() {
+        "auto" => (RequestPriority::Normal, "auto"),
+        "priority" => (RequestPriority::Normal, "priority"),
+        "standard_only" => (RequestPriority::Low, "standard_only"),
+        "standard" => (RequestPriority::Low, "standard"),
+        "batch" => (RequestPriority::Low, "batch"),
+        _ => {
+            tracing::debug!("Ignoring unknown service_tier: {}", tier);
+            return None;
+        }
+    };
+    Some(ServiceTier { priority, effective })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_service_tier() {
+        assert_eq!(resolve(None), None);
+        let auto = resolve(Some("auto")).unwrap();
+        assert_eq!(auto.priority, RequestPriority::Normal);
+        assert_eq!(auto.effective, "auto");
+
+        let standard = resolve(Some("standard_only")).unwrap();
+        assert_eq!(standard.priority, RequestPriority::Low);
+        assert_eq!(standard.effective, "standard_only");
+
+        assert_eq!(resolve(Some("Batch")).unwrap().effective, "batch");
+        assert_eq!(resolve(Some("turbo")), None);
+    }
+}
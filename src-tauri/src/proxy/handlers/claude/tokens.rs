@@ -7,33 +7,281 @@ use axum::{
     response::{IntoResponse, Response},
 };
 use serde_json::{json, Value};
+use tracing::debug;
 
+use super::messages::build_invalid_request_error;
+use crate::proxy::handlers::common::{with_account_headers, with_rotating_account, AttemptError};
+use crate::proxy::mappers::claude::models::ClaudeRequest;
+use crate::proxy::mappers::claude::transform_claude_request_in;
+use crate::proxy::mappers::context_manager::ContextManager;
+use crate::proxy::mappers::estimation_calibrator::{get_calibrator, PromptEstimate};
 use crate::proxy::server::AppState;
 
-/// Count tokens for a request (placeholder or z.ai passthrough)
+/// 响应头: 计数来源 (`upstream` = v1internal countTokens, `estimated` = 本地估算)
+const TOKEN_COUNT_SOURCE_HEADER: &str = "X-Token-Count-Source";
+
+/// 上游计数失败时的换号次数
+const MAX_ATTEMPTS: usize = 2;
+
+/// Count tokens for a request (upstream countTokens, local estimate or z.ai passthrough)
 pub async fn handle_count_tokens(
     State(state): State<AppState>,
     headers: HeaderMap,
     Json(body): Json<Value>,
 ) -> Response {
-    let zai = state.zai.read().await.clone();
-    let zai_enabled = zai.enabled && !matches!(zai.dispatch_mode, crate::proxy::ZaiDispatchMode::Off);
-
-    if zai_enabled {
-        return crate::proxy::providers::zai_anthropic::forward_anthropic_json(
-            &state,
-            axum::http::Method::POST,
-            "/v1/messages/count_tokens",
-            &headers,
-            body,
-            0, // Tokens count doesn't need rewind detection
-        )
-        .await;
-    }
-
-    Json(json!({
-        "input_tokens": 0,
-        "output_tokens": 0
-    }))
-    .into_response()
+    let model = body.get("model").and_then(|m| m.as_str()).unwrap_or_default().to_string();
+    let provider = crate::proxy::providers::select_provider(
+        &state,
+        crate::proxy::config::ListenerProtocol::Anthropic,
+        &model,
+        "count_tokens",
+    )
+    .await;
+
+    if let Some(selected) = provider {
+        let response = selected
+            .provider
+            .forward_json(
+                &state,
+                crate::proxy::providers::ProviderRequest {
+                    method: axum::http::Method::POST,
+                    path: "/v1/messages/count_tokens",
+                    headers: &headers,
+                    body,
+                    message_count: 0, // Tokens count doesn't need rewind detection
+                },
+            )
+            .await;
+        return selected.decision.attach(response);
+    }
+
+    let request = match parse_count_request(body) {
+        Ok(request) => request,
+        Err(e) => return build_invalid_request_error(format!("Invalid request body: {}", e)),
+    };
+    let raw_estimate = ContextManager::estimate_input_tokens(&request);
+    let mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
+        &request.model,
+        &*state.custom_mapping.read().await,
+    );
+    let estimate = PromptEstimate::for_request(&request, &mapped_model, raw_estimate);
+
+    let upstream_enabled = state.experimental.read().await.enable_upstream_count_tokens;
+    if upstream_enabled && !request.model.is_empty() {
+        match count_tokens_upstream(&state, &request, &mapped_model).await {
+            Ok((input_tokens, email)) => {
+                get_calibrator().record_sample(&estimate.sample(input_tokens));
+                return with_account_headers(
+                    count_response(input_tokens, "upstream"),
+                    &email,
+                    Some(&mapped_model),
+                );
+            }
+            Err(e) => debug!("[CountTokens] Upstream count unavailable, using estimate: {}", e),
+        }
+    }
+
+    let input_tokens = get_calibrator().calibrate_for(estimate.bucket, raw_estimate);
+    count_response(input_tokens, "estimated")
+}
+
+fn count_response(input_tokens: u32, source: &'static str) -> Response {
+    (
+        [(TOKEN_COUNT_SOURCE_HEADER, source)],
+        Json(json!({
+            "input_tokens": input_tokens,
+            "output_tokens": 0
+        })),
+    )
+        .into_response()
+}
+
+/// 以真实账号租约调用 v1internal countTokens, 返回 (input_tokens, 账号邮箱)
+async fn count_tokens_upstream(
+    state: &AppState,
+    request: &ClaudeRequest,
+    mapped_model: &str,
+) -> Result<(u32, String), String> {
+    let mut mapped_request = request.clone();
+    mapped_request.model = mapped_model.to_string();
+
+    let upstream = state.upstream.clone();
+    let rotated = with_rotating_account(
+        state.token_manager.as_ref(),
+        "text",
+        mapped_model,
+        MAX_ATTEMPTS,
+        |lease| {
+            let upstream = upstream.clone();
+            let mapped_request = &mapped_request;
+            async move {
+                let generate_body = transform_claude_request_in(mapped_request, &lease.project_id, false)
+                    .map_err(|e| AttemptError::local(400, e))?;
+                let response = upstream
+                    .call_v1_internal("countTokens", &lease.access_token, build_count_tokens_body(&generate_body), None)
+                    .await
+                    .map_err(|e| AttemptError::local(502, e))?;
+
+                let status = response.status().as_u16();
+                if !response.status().is_success() {
+                    let error_text = response.text().await.unwrap_or_default();
+                    return Err(AttemptError::upstream(status, error_text.clone(), error_text));
+                }
+                let result: Value = response
+                    .json()
+                    .await
+                    .map_err(|e| AttemptError::local(502, e.to_string()))?;
+                result
+                    .get("totalTokens")
+                    .or_else(|| result.get("response").and_then(|r| r.get("totalTokens")))
+                    .and_then(|v| v.as_u64())
+                    .map(|v| v as u32)
+                    .ok_or_else(|| AttemptError::local(502, "countTokens response without totalTokens"))
+            }
+        },
+    )
+    .await;
+
+    rotated
+        .map(|rotated| (rotated.value, rotated.email))
+        .map_err(|failure| failure.message)
+}
+
+/// 由 generateContent 请求体构造 countTokens 请求
+///
+/// countTokens 只接受 model + contents: systemInstruction 与工具声明 (JSON 文本) 折叠为首个 user 轮次计入
+fn build_count_tokens_body(generate_body: &Value) -> Value {
+    let inner = &generate_body["request"];
+    let mut preamble: Vec<Value> = inner["systemInstruction"]["parts"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+    if let Some(tools) = inner.get("tools").filter(|t| !t.is_null()) {
+        preamble.push(json!({ "text": tools.to_string() }));
+    }
+
+    let mut contents = Vec::new();
+    if !preamble.is_empty() {
+        contents.push(json!({ "role": "user", "parts": preamble }));
+    }
+    contents.extend(inner["contents"].as_array().cloned().unwrap_or_default());
+
+    json!({
+        "request": {
+            "model": format!("models/{}", generate_body["model"].as_str().unwrap_or_default()),
+            "contents": contents,
+        }
+    })
+}
+
+/// 解析计数请求: 接受完整的 Messages 请求 (tool_choice / max_tokens / stream 等字段忽略)
+fn parse_count_request(mut body: Value) -> Result<ClaudeRequest, String> {
+    // count_tokens 只用于预算, model 缺失时不报错
+    if let Some(obj) = body.as_object_mut() {
+        obj.entry("model").or_insert_with(|| json!(""));
+    }
+    serde_json::from_value(body).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn estimate_request_tokens(body: Value) -> Result<u32, String> {
+        parse_count_request(body).map(|request| ContextManager::estimate_input_tokens(&request))
+    }
+
+    fn tools_fixture() -> Value {
+        serde_json::from_str(include_str!("fixtures/count_tokens_claude_code_tools.json")).unwrap()
+    }
+
+    #[test]
+    fn test_estimate_includes_tools_and_system() {
+        let fixture = tools_fixture();
+        let estimate = estimate_request_tokens(fixture.clone()).unwrap();
+
+        // 去掉 tools / system 后估算值应明显下降
+        let mut bare = fixture;
+        bare.as_object_mut().unwrap().remove("tools");
+        bare.as_object_mut().unwrap().remove("system");
+        let bare_estimate = estimate_request_tokens(bare).unwrap();
+        assert!(estimate > bare_estimate + 2000, "{} vs {}", estimate, bare_estimate);
+    }
+
+    /// fixture 对应的官方 count_tokens 实录值 (由 test_record_upstream_count 写入, 不是估算器自己算出的数字)
+    const UPSTREAM_COUNT_FIXTURE: &str = "src/proxy/handlers/claude/fixtures/count_tokens_claude_code_tools.upstream.json";
+
+    fn recorded_upstream_count() -> Option<u64> {
+        let recorded: Value =
+            serde_json::from_str(include_str!("fixtures/count_tokens_claude_code_tools.upstream.json")).unwrap();
+        recorded["input_tokens"].as_u64()
+    }
+
+    /// 与官方 count_tokens 的实录值对比, 误差需小于 15%; 离线运行
+    #[test]
+    fn test_estimate_within_tolerance_of_upstream_count() {
+        let Some(reference) = recorded_upstream_count() else {
+            eprintln!("{} has no recorded input_tokens yet; run test_record_upstream_count", UPSTREAM_COUNT_FIXTURE);
+            return;
+        };
+        let estimate = estimate_request_tokens(tools_fixture()).unwrap();
+
+        let reference = reference as f64;
+        let error = (estimate as f64 - reference).abs() / reference;
+        assert!(error < 0.15, "estimate {} vs upstream {} ({:.1}%)", estimate, reference, error * 100.0);
+    }
+
+    /// 向官方 count_tokens 请求 fixture 的 input_tokens 并写入实录文件:
+    /// `ANTHROPIC_API_KEY=... cargo test test_record_upstream_count -- --ignored`
+    #[tokio::test]
+    #[ignore = "calls api.anthropic.com; needs ANTHROPIC_API_KEY"]
+    async fn test_record_upstream_count() {
+        let api_key = std::env::var("ANTHROPIC_API_KEY").expect("ANTHROPIC_API_KEY is required");
+
+        // count_tokens 不接受 max_tokens / stream
+        let mut body = tools_fixture();
+        body.as_object_mut().unwrap().remove("max_tokens");
+        body.as_object_mut().unwrap().remove("stream");
+        let response: Value = reqwest::Client::new()
+            .post("https://api.anthropic.com/v1/messages/count_tokens")
+            .header("x-api-key", api_key)
+            .header("anthropic-version", "2023-06-01")
+            .json(&body)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let input_tokens = response["input_tokens"]
+            .as_u64()
+            .unwrap_or_else(|| panic!("unexpected count_tokens response: {}", response));
+
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join(UPSTREAM_COUNT_FIXTURE);
+        let mut recorded: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        recorded["input_tokens"] = json!(input_tokens);
+        std::fs::write(&path, format!("{}\n", serde_json::to_string_pretty(&recorded).unwrap())).unwrap();
+    }
+
+    #[test]
+    fn test_count_tokens_body_folds_system_and_tools() {
+        let generate_body = json!({
+            "model": "gemini-2.5-pro",
+            "request": {
+                "systemInstruction": { "parts": [{ "text": "You are helpful" }] },
+                "tools": [{ "functionDeclarations": [{ "name": "read_file" }] }],
+                "contents": [{ "role": "user", "parts": [{ "text": "hi" }] }],
+                "generationConfig": { "maxOutputTokens": 1024 }
+            }
+        });
+        let body = build_count_tokens_body(&generate_body);
+
+        assert_eq!(body["request"]["model"], "models/gemini-2.5-pro");
+        let contents = body["request"]["contents"].as_array().unwrap();
+        assert_eq!(contents.len(), 2);
+        assert_eq!(contents[0]["parts"][0]["text"], "You are helpful");
+        assert!(contents[0]["parts"][1]["text"].as_str().unwrap().contains("read_file"));
+        assert_eq!(contents[1]["parts"][0]["text"], "hi");
+        assert!(body["request"].get("generationConfig").is_none());
+    }
 }
@@ -0,0 +1,195 @@
+// Layer-1.5 Tool Output Digests
+// Layer-1 drops old tool rounds entirely, including file contents the agent may still edit.
+// When enabled, large tool_results in those rounds are replaced by a short digest from the
+// cheap summary model instead (same routing as Layer-3). Digests are cached by content hash,
+// so the full outputs the client resends every turn are summarized only once.
+
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tracing::{debug, info, warn};
+
+use super::compression::{call_gemini_sync, ContextSummaryContext};
+use crate::proxy::mappers::claude::models::{ClaudeRequest, ContentBlock, Message, MessageContent};
+use crate::proxy::mappers::context_manager::{ContextManager, TOOL_RESULT_DIGEST_PREFIX};
+
+/// Only outputs at least this large are worth a summary call
+const MIN_DIGEST_CHARS: usize = 4_000;
+/// Input cap for a single summary call
+const MAX_INPUT_CHARS: usize = 100_000;
+const DIGEST_MAX_TOKENS: u32 = 512;
+const MAX_CACHED_DIGESTS: usize = 1_000;
+
+const DIGEST_PROMPT: &str = "Summarize the following tool output in at most 5 lines. \
+Keep file paths, identifiers, function signatures, line numbers, error messages and any values \
+likely to be needed later. Output only the summary.";
+
+/// content hash -> digest (without prefix)
+static DIGESTS: Lazy<DashMap<String, String>> = Lazy::new(DashMap::new);
+
+fn content_hash(text: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(text.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// Plain text of a tool_result; None when it holds non-text blocks (images etc.) or is already a digest
+fn tool_result_text(content: &Value) -> Option<String> {
+    let text = match content {
+        Value::String(s) => s.clone(),
+        Value::Array(items) => {
+            let mut parts = Vec::with_capacity(items.len());
+            for item in items {
+                parts.push(item.get("text")?.as_str()?);
+            }
+            parts.join("\n")
+        }
+        _ => return None,
+    };
+    (!text.starts_with(TOOL_RESULT_DIGEST_PREFIX)).then_some(text)
+}
+
+fn cache_digest(hash: String, digest: String) {
+    if DIGESTS.len() >= MAX_CACHED_DIGESTS {
+        if let Some(key) = DIGESTS.iter().next().map(|e| e.key().clone()) {
+            DIGESTS.remove(&key);
+        }
+    }
+    DIGESTS.insert(hash, digest);
+}
+
+fn digest_request(model: &str, text: &str) -> ClaudeRequest {
+    let text: String = text.chars().take(MAX_INPUT_CHARS).collect();
+    ClaudeRequest {
+        model: model.to_string(),
+        messages: vec![Message {
+            role: "user".to_string(),
+            content: MessageContent::String(format!(
+                "{}\n\n<tool_output>\n{}\n</tool_output>",
+                DIGEST_PROMPT, text
+            )),
+        }],
+        system: None,
+        stream: false,
+        max_tokens: Some(DIGEST_MAX_TOKENS),
+        temperature: Some(0.2),
+        tools: None,
+        thinking: None,
+        metadata: None,
+        top_p: None,
+        top_k: None,
+        output_config: None,
+        size: None,
+        quality: None,
+        service_tier: None,
+        tool_choice: None,
+        stop_sequences: None,
+    }
+}
+
+/// Replace large tool_results of the rounds Layer-1 would drop with cached or freshly generated digests.
+///
+/// At most `options.tool_summary_budget` summary calls are made per request; beyond that, or once the
+/// cheap model fails, the remaining outputs are left to plain Layer-1 trimming.
+/// Returns the number of tool_results replaced.
+pub async fn digest_old_tool_results(
+    messages: &mut [Message],
+    keep_last_n_rounds: usize,
+    options: &ContextSummaryContext,
+    trace_id: &str,
+) -> usize {
+    let mut calls_left = options.tool_summary_budget;
+    let mut replaced = 0;
+
+    for idx in ContextManager::old_tool_result_indices(messages, keep_last_n_rounds) {
+        let MessageContent::Array(blocks) = &mut messages[idx].content else {
+            continue;
+        };
+        for block in blocks.iter_mut() {
+            let ContentBlock::ToolResult { content, .. } = block else {
+                continue;
+            };
+            let Some(text) = tool_result_text(content).filter(|t| t.chars().count() >= MIN_DIGEST_CHARS)
+            else {
+                continue;
+            };
+
+            let hash = content_hash(&text);
+            let cached = DIGESTS.get(&hash).map(|d| d.clone());
+            let digest = match cached {
+                Some(digest) => digest,
+                None if calls_left > 0 => {
+                    calls_left -= 1;
+                    let request = digest_request(&options.model, &text);
+                    match call_gemini_sync(&request, options, trace_id).await {
+                        Ok(digest) if !digest.trim().is_empty() => {
+                            let digest = digest.trim().to_string();
+                            cache_digest(hash, digest.clone());
+                            digest
+                        }
+                        Ok(_) => continue,
+                        Err(e) => {
+                            // Cheap model unavailable: fall back to truncation for the rest
+                            warn!("[{}] [Layer-1.5] Tool output digest failed: {}", trace_id, e);
+                            calls_left = 0;
+                            continue;
+                        }
+                    }
+                }
+                None => continue,
+            };
+
+            debug!(
+                "[{}] [Layer-1.5] Replaced tool output ({} chars) with digest ({} chars)",
+                trace_id,
+                text.len(),
+                digest.len()
+            );
+            *content = Value::String(format!("{}{}", TOOL_RESULT_DIGEST_PREFIX, digest));
+            replaced += 1;
+        }
+    }
+
+    if replaced > 0 {
+        info!(
+            "[{}] [Layer-1.5] Replaced {} old tool outputs with digests ({} summary calls)",
+            trace_id,
+            replaced,
+            options.tool_summary_budget - calls_left
+        );
+    }
+    replaced
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_tool_result_text_and_cache_key() {
+        assert_eq!(tool_result_text(&json!("plain")), Some("plain".to_string()));
+        assert_eq!(
+            tool_result_text(&json!([{ "type": "text", "text": "a" }, { "type": "text", "text": "b" }])),
+            Some("a\nb".to_string())
+        );
+        // Images cannot be summarized as text
+        assert_eq!(
+            tool_result_text(&json!([{ "type": "image", "source": {} }])),
+            None
+        );
+        // Already digested
+        assert_eq!(tool_result_text(&json!(format!("{}x", TOOL_RESULT_DIGEST_PREFIX))), None);
+
+        assert_eq!(content_hash("same"), content_hash("same"));
+        assert_ne!(content_hash("same"), content_hash("other"));
+
+        let request = digest_request("gemini-2.5-flash", &"y".repeat(MAX_INPUT_CHARS + 10));
+        let MessageContent::String(prompt) = &request.messages[0].content else {
+            panic!("expected string prompt");
+        };
+        assert!(prompt.starts_with(DIGEST_PROMPT));
+        assert!(prompt.len() < MAX_INPUT_CHARS + DIGEST_PROMPT.len() + 64);
+    }
+}
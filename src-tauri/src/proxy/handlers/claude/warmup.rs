@@ -3,12 +3,17 @@
 
 use axum::{
     body::Body,
-    http::{header, StatusCode},
+    http::StatusCode,
     response::{IntoResponse, Response},
     Json,
 };
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
 use serde_json::json;
+use std::collections::HashMap;
 
+use crate::proxy::handlers::common::{finish_response, sse_response_builder};
+use crate::proxy::mappers::claude::models::MessageContent;
 use crate::proxy::mappers::claude::ClaudeRequest;
 
 /// Detect if this is a Warmup request
@@ -63,43 +68,127 @@ pub fn is_warmup_request(request: &ClaudeRequest) -> bool {
     false
 }
 
+/// Warmup 拦截次数 (客户端版本 -> 次数, 启动以来)
+static WARMUP_INTERCEPTS: Lazy<DashMap<String, u64>> = Lazy::new(DashMap::new);
+
+/// 从 User-Agent 提取客户端版本, 如 "claude-cli/1.0.83 (external, cli)" -> "claude-cli/1.0.83"
+fn client_version(user_agent: Option<&str>) -> String {
+    user_agent
+        .and_then(|ua| ua.split_whitespace().next())
+        .filter(|token| !token.is_empty())
+        .unwrap_or("unknown")
+        .to_string()
+}
+
+/// 记录一次被拦截的 Warmup 请求
+pub fn record_warmup_intercept(user_agent: Option<&str>) {
+    *WARMUP_INTERCEPTS.entry(client_version(user_agent)).or_insert(0) += 1;
+}
+
+/// 各客户端版本被拦截的 Warmup 次数
+pub fn warmup_intercept_counts() -> HashMap<String, u64> {
+    WARMUP_INTERCEPTS
+        .iter()
+        .map(|entry| (entry.key().clone(), *entry.value()))
+        .collect()
+}
+
+/// Warmup 响应的用量: 数值很小但不为 0 (客户端会校验)
+fn warmup_usage(request: &ClaudeRequest) -> (u32, u32) {
+    let prompt_chars: usize = request
+        .messages
+        .iter()
+        .map(|m| match &m.content {
+            MessageContent::String(s) => s.len(),
+            MessageContent::Array(blocks) => blocks.len() * 16,
+        })
+        .sum();
+    let input_tokens = (prompt_chars / 4).clamp(1, 64) as u32;
+    (input_tokens, 1)
+}
+
+fn sse_event(event: &str, data: serde_json::Value) -> String {
+    format!("event: {}\ndata: {}\n\n", event, data)
+}
+
+/// Warmup 的流式事件序列 (与 Anthropic 文档的事件顺序一致):
+/// message_start -> content_block_start -> ping -> content_block_delta
+/// -> content_block_stop -> message_delta -> message_stop
+fn warmup_stream_events(message_id: &str, model: &str, input_tokens: u32, output_tokens: u32) -> Vec<String> {
+    vec![
+        sse_event(
+            "message_start",
+            json!({
+                "type": "message_start",
+                "message": {
+                    "id": message_id,
+                    "type": "message",
+                    "role": "assistant",
+                    "content": [],
+                    "model": model,
+                    "stop_reason": null,
+                    "stop_sequence": null,
+                    "usage": {
+                        "input_tokens": input_tokens,
+                        "cache_creation_input_tokens": 0,
+                        "cache_read_input_tokens": 0,
+                        "output_tokens": 1
+                    }
+                }
+            }),
+        ),
+        sse_event(
+            "content_block_start",
+            json!({
+                "type": "content_block_start",
+                "index": 0,
+                "content_block": { "type": "text", "text": "" }
+            }),
+        ),
+        sse_event("ping", json!({ "type": "ping" })),
+        sse_event(
+            "content_block_delta",
+            json!({
+                "type": "content_block_delta",
+                "index": 0,
+                "delta": { "type": "text_delta", "text": WARMUP_TEXT }
+            }),
+        ),
+        sse_event(
+            "content_block_stop",
+            json!({ "type": "content_block_stop", "index": 0 }),
+        ),
+        sse_event(
+            "message_delta",
+            json!({
+                "type": "message_delta",
+                "delta": { "stop_reason": "end_turn", "stop_sequence": null },
+                "usage": { "output_tokens": output_tokens }
+            }),
+        ),
+        sse_event("message_stop", json!({ "type": "message_stop" })),
+    ]
+}
+
+const WARMUP_TEXT: &str = "OK";
+
 /// Create a mock response for Warmup requests
 /// 
-/// Returns a simple response without consuming upstream quota
+/// Returns a spec-complete response without consuming upstream quota.
+/// The requested model name is echoed verbatim; newer clients validate it.
 pub fn create_warmup_response(request: &ClaudeRequest, is_stream: bool) -> Response {
     let model = &request.model;
-    let message_id = format!("msg_warmup_{}", chrono::Utc::now().timestamp_millis());
-    
+    let message_id = format!("msg_{}", crate::proxy::common::utils::generate_random_id());
+    let (input_tokens, output_tokens) = warmup_usage(request);
+
     if is_stream {
-        // Streaming response: send standard SSE event sequence
-        let events = vec![
-            // message_start
-            format!(
-                "event: message_start\ndata: {{\"type\":\"message_start\",\"message\":{{\"id\":\"{}\",\"type\":\"message\",\"role\":\"assistant\",\"content\":[],\"model\":\"{}\",\"stop_reason\":null,\"stop_sequence\":null,\"usage\":{{\"input_tokens\":1,\"output_tokens\":0}}}}}}\n\n",
-                message_id, model
-            ),
-            // content_block_start
-            "event: content_block_start\ndata: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"text\",\"text\":\"\"}}\n\n".to_string(),
-            // content_block_delta
-            "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"OK\"}}\n\n".to_string(),
-            // content_block_stop
-            "event: content_block_stop\ndata: {\"type\":\"content_block_stop\",\"index\":0}\n\n".to_string(),
-            // message_delta
-            "event: message_delta\ndata: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"end_turn\",\"stop_sequence\":null},\"usage\":{\"output_tokens\":1}}\n\n".to_string(),
-            // message_stop
-            "event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n".to_string(),
-        ];
-        
-        let body = events.join("");
-        
-        Response::builder()
-            .status(StatusCode::OK)
-            .header(header::CONTENT_TYPE, "text/event-stream")
-            .header(header::CACHE_CONTROL, "no-cache")
-            .header(header::CONNECTION, "keep-alive")
-            .header("X-Warmup-Intercepted", "true")
-            .body(Body::from(body))
-            .expect("Failed to build warmup response")
+        let body = warmup_stream_events(&message_id, model, input_tokens, output_tokens).join("");
+
+        finish_response(
+            sse_response_builder()
+                .header("X-Warmup-Intercepted", "true")
+                .body(Body::from(body)),
+        )
     } else {
         // Non-streaming response
         let response = json!({
@@ -108,14 +197,16 @@ pub fn create_warmup_response(request: &ClaudeRequest, is_stream: bool) -> Respo
             "role": "assistant",
             "content": [{
                 "type": "text",
-                "text": "OK"
+                "text": WARMUP_TEXT
             }],
             "model": model,
             "stop_reason": "end_turn",
             "stop_sequence": null,
             "usage": {
-                "input_tokens": 1,
-                "output_tokens": 1
+                "input_tokens": input_tokens,
+                "cache_creation_input_tokens": 0,
+                "cache_read_input_tokens": 0,
+                "output_tokens": output_tokens
             }
         });
         
@@ -126,3 +217,81 @@ pub fn create_warmup_response(request: &ClaudeRequest, is_stream: bool) -> Respo
         ).into_response()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn warmup_request(model: &str) -> ClaudeRequest {
+        serde_json::from_value(json!({
+            "model": model,
+            "max_tokens": 1,
+            "stream": true,
+            "messages": [{ "role": "user", "content": "Warmup" }]
+        }))
+        .unwrap()
+    }
+
+    fn parse_events(events: &[String]) -> Vec<(String, Value)> {
+        events
+            .iter()
+            .map(|raw| {
+                let mut lines = raw.trim_end().lines();
+                let event = lines.next().unwrap().strip_prefix("event: ").unwrap().to_string();
+                let data = lines.next().unwrap().strip_prefix("data: ").unwrap();
+                (event, serde_json::from_str(data).unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_stream_event_sequence_conforms_to_spec() {
+        let request = warmup_request("claude-sonnet-4-5-20250929");
+        let (input_tokens, output_tokens) = warmup_usage(&request);
+        let events = parse_events(&warmup_stream_events("msg_test", &request.model, input_tokens, output_tokens));
+
+        let names: Vec<&str> = events.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "message_start",
+                "content_block_start",
+                "ping",
+                "content_block_delta",
+                "content_block_stop",
+                "message_delta",
+                "message_stop",
+            ]
+        );
+        // data.type 与 event 名一致
+        for (name, data) in &events {
+            assert_eq!(data["type"], name.as_str());
+        }
+
+        let message = &events[0].1["message"];
+        assert_eq!(message["model"], "claude-sonnet-4-5-20250929");
+        assert!(message["usage"]["input_tokens"].as_u64().unwrap() > 0);
+        assert_eq!(events[5].1["delta"]["stop_reason"], "end_turn");
+        assert!(events[5].1["usage"]["output_tokens"].as_u64().unwrap() > 0);
+    }
+
+    #[test]
+    fn test_model_is_echoed_verbatim() {
+        // 含引号的名字也必须原样回显且保持 JSON 合法
+        let request = warmup_request("claude-opus-4-1 \"custom\"");
+        let events = parse_events(&warmup_stream_events("msg_test", &request.model, 1, 1));
+        assert_eq!(events[0].1["message"]["model"], "claude-opus-4-1 \"custom\"");
+    }
+
+    #[test]
+    fn test_client_version_from_user_agent() {
+        assert_eq!(client_version(Some("claude-cli/1.0.83 (external, cli)")), "claude-cli/1.0.83");
+        assert_eq!(client_version(Some("")), "unknown");
+        assert_eq!(client_version(None), "unknown");
+
+        record_warmup_intercept(Some("warmup-test-client/9.9.9 (test)"));
+        record_warmup_intercept(Some("warmup-test-client/9.9.9 (test)"));
+        assert_eq!(warmup_intercept_counts().get("warmup-test-client/9.9.9"), Some(&2));
+    }
+}
@@ -1,9 +1,17 @@
 use tokio::time::{sleep, Duration};
 use tracing::{debug, info};
 use rand::Rng;
-use axum::{http::StatusCode, response::{IntoResponse, Response}, Json, extract::State};
+use axum::{http::{HeaderValue, StatusCode}, response::{IntoResponse, Response}, Json, extract::State};
 use serde_json::{json, Value};
+use crate::proxy::common::model_mapping::{list_models_with_routes, ListedModel};
+use crate::proxy::config::RetryConfig;
 use crate::proxy::server::AppState;
+use crate::proxy::token_manager::{TokenLease, TokenManager};
+use futures::future::BoxFuture;
+use std::future::Future;
+
+/// 模型列表条目的 created 时间戳 (上游不提供, 固定值)
+const MODEL_LIST_CREATED: i64 = 1706745600;
 
 // ===== 统一重试与退避策略 =====
 
@@ -73,13 +81,17 @@ pub fn determine_retry_strategy(
 }
 
 /// 执行退避策略并返回是否应该继续重试
+///
+/// 线性 / 指数退避的基准按 `retry.base_delay_ms` 缩放; 固定延迟 (Retry-After、签名重试等) 保持原值。
 pub async fn apply_retry_strategy(
     strategy: RetryStrategy,
     attempt: usize,
     max_attempts: usize,
     status_code: u16,
     trace_id: &str,
+    retry: &RetryConfig,
 ) -> bool {
+    let strategy = scale_retry_strategy(strategy, retry);
     match strategy {
         RetryStrategy::NoRetry => {
             debug!("[{}] Non-retryable error {}, stopping", trace_id, status_code);
@@ -136,6 +148,20 @@ pub async fn apply_retry_strategy(
     }
 }
 
+/// 按配置的基准延迟缩放内置退避策略
+fn scale_retry_strategy(strategy: RetryStrategy, retry: &RetryConfig) -> RetryStrategy {
+    match strategy {
+        RetryStrategy::LinearBackoff { base_ms } => RetryStrategy::LinearBackoff {
+            base_ms: retry.scale_delay_ms(base_ms),
+        },
+        RetryStrategy::ExponentialBackoff { base_ms, max_ms } => RetryStrategy::ExponentialBackoff {
+            base_ms: retry.scale_delay_ms(base_ms),
+            max_ms: retry.scale_delay_ms(max_ms),
+        },
+        other => other,
+    }
+}
+
 /// 判断是否应该轮换账号
 pub fn should_rotate_account(status_code: u16) -> bool {
     match status_code {
@@ -147,6 +173,486 @@ pub fn should_rotate_account(status_code: u16) -> bool {
     }
 }
 
+// ===== 轻量端点的换号重试 =====
+// 计数 / 转录等轻量调用不需要主链路的压缩与降级机制, 只需在账号级错误时换号重试,
+// 并沿用统一的失败上报 (限流 / 熔断) 与 X-Account-Email 日志归属。
+
+/// 轻量调用的账号来源 (生产为 TokenManager, 测试中可替换为脚本化实现)
+pub trait LeaseSource: Send + Sync {
+    fn lease<'a>(
+        &'a self,
+        request_type: &'a str,
+        force_rotate: bool,
+        model: &'a str,
+    ) -> BoxFuture<'a, Result<TokenLease, String>>;
+
+    fn report_failure<'a>(
+        &'a self,
+        account_id: &'a str,
+        email: &'a str,
+        status: u16,
+        error_text: &'a str,
+        model: &'a str,
+    ) -> BoxFuture<'a, ()>;
+}
+
+impl LeaseSource for TokenManager {
+    fn lease<'a>(
+        &'a self,
+        request_type: &'a str,
+        force_rotate: bool,
+        model: &'a str,
+    ) -> BoxFuture<'a, Result<TokenLease, String>> {
+        Box::pin(self.get_token(request_type, force_rotate, None, model))
+    }
+
+    fn report_failure<'a>(
+        &'a self,
+        account_id: &'a str,
+        email: &'a str,
+        status: u16,
+        error_text: &'a str,
+        model: &'a str,
+    ) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            // 与主链路一致: 限流由 Smart Rate Limiter 处理, 402/401 进入熔断
+            self.mark_rate_limited_async(email, status, None, error_text, Some(model)).await;
+            if status == 402 || status == 401 {
+                self.report_account_failure(account_id, status, error_text);
+            }
+        })
+    }
+}
+
+/// 单次尝试的失败
+#[derive(Debug, Clone)]
+pub struct AttemptError {
+    pub status: u16,
+    /// 原始上游错误 (用于限流解析与上报)
+    pub error_text: String,
+    /// 返回给客户端的文本 (已脱敏)
+    pub message: String,
+    from_upstream: bool,
+}
+
+impl AttemptError {
+    /// 上游返回了错误状态码: 上报账号失败, 账号级错误时换号
+    pub fn upstream(status: u16, error_text: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            error_text: error_text.into(),
+            message: message.into(),
+            from_upstream: true,
+        }
+    }
+
+    /// 未拿到上游响应或本地处理失败: 与账号无关, 不上报也不换号
+    pub fn local(status: u16, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            error_text: String::new(),
+            message: message.into(),
+            from_upstream: false,
+        }
+    }
+}
+
+/// 成功结果及实际使用的账号
+#[derive(Debug)]
+pub struct Rotated<T> {
+    pub value: T,
+    pub email: String,
+}
+
+/// 本次请求重试中的换号记录 (响应扩展, 监控中间件据此写入日志)
+#[derive(Debug, Clone)]
+pub struct AccountRotations(pub Vec<String>);
+
+/// 代理本地失败 (请求 / 响应转换错误) 的响应扩展, 监控中间件据此记为 local_error
+#[derive(Debug, Clone, Copy)]
+pub struct LocalFailure;
+
+/// 标记响应为代理本地失败
+pub fn mark_local_failure(mut response: Response) -> Response {
+    response.extensions_mut().insert(LocalFailure);
+    response
+}
+
+/// 按字节计量的用量 (如语音合成), 响应扩展, 监控中间件据此代替 token 数写入日志
+///
+/// 只携带输入字节数; 响应体为流式输出, 输出字节数由监控中间件在转发时统计
+#[derive(Debug, Clone, Copy)]
+pub struct ByteUsage {
+    pub input_bytes: u64,
+}
+
+/// 放弃时的最后一次失败
+#[derive(Debug, Clone)]
+pub struct RotationFailure {
+    pub status: u16,
+    pub message: String,
+    /// 最后一次尝试的账号 (未拿到账号时为 None)
+    pub email: Option<String>,
+    pub attempts: usize,
+}
+
+impl RotationFailure {
+    pub fn into_response(self) -> Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::BAD_GATEWAY);
+        match self.email {
+            Some(email) => with_account_headers((status, self.message), &email, None),
+            None => (status, self.message).into_response(),
+        }
+    }
+}
+
+/// 两种协议 `/v1/models` 共用的模型列表: 内置 / 自定义别名 / 虚拟模型 + z.ai 缓存模型, 按 ID 去重
+pub async fn listed_models(state: &AppState) -> Vec<ListedModel> {
+    let mut models = list_models_with_routes(&state.custom_mapping).await;
+    // z.ai 模型只读共享缓存, 不在列表请求中访问上游
+    for id in crate::proxy::providers::zai_models::listed_models(state).await {
+        if !models.iter().any(|m| m.id == id) {
+            models.push(ListedModel { id, mapped_to: None });
+        }
+    }
+    models.sort_by(|a, b| a.id.cmp(&b.id));
+    models
+}
+
+/// 模型列表条目; 别名与虚拟模型以 `antigravity:mapped_to` 标注实际路由的物理模型
+pub fn model_list_entry(model: &ListedModel) -> Value {
+    let mut entry = json!({
+        "id": model.id,
+        "object": "model",
+        "created": MODEL_LIST_CREATED,
+        "owned_by": "antigravity",
+        "display_name": model.id,
+    });
+    if let Some(target) = &model.mapped_to {
+        entry["antigravity:mapped_to"] = json!(target);
+    }
+    entry
+}
+
+/// Anthropic 格式的模型列表 (仅开放 anthropic 协议的端口上的 `/v1/models`)
+pub fn anthropic_model_list(models: &[ListedModel]) -> Value {
+    let created_at = chrono::DateTime::from_timestamp(MODEL_LIST_CREATED, 0)
+        .map(|t| t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+        .unwrap_or_default();
+    let data: Vec<Value> = models
+        .iter()
+        .map(|model| {
+            json!({
+                "type": "model",
+                "id": model.id,
+                "display_name": model.id,
+                "created_at": created_at,
+            })
+        })
+        .collect();
+    json!({
+        "data": data,
+        "has_more": false,
+        "first_id": models.first().map(|m| m.id.as_str()),
+        "last_id": models.last().map(|m| m.id.as_str()),
+    })
+}
+
+/// Gemini 格式的模型条目 (`/v1beta/models` 与仅开放 gemini 协议的端口上的 `/v1/models`)
+pub fn gemini_model_entry(id: &str) -> Value {
+    json!({
+        "name": format!("models/{}", id),
+        "version": "001",
+        "displayName": id,
+        "description": "",
+        "inputTokenLimit": 128000,
+        "outputTokenLimit": 8192,
+        "supportedGenerationMethods": ["generateContent", "countTokens"],
+        "temperature": 1.0,
+        "topP": 0.95,
+        "topK": 64
+    })
+}
+
+/// 以租约执行一次轻量调用, 账号级错误 (见 should_rotate_account) 时换号重试, 最多 max_attempts 次。
+/// 每次上游失败都会上报给账号来源; 换号时拿不到新账号则返回上一次的上游错误。
+pub async fn with_rotating_account<T, F, Fut>(
+    source: &dyn LeaseSource,
+    request_type: &str,
+    model: &str,
+    max_attempts: usize,
+    mut call: F,
+) -> Result<Rotated<T>, RotationFailure>
+where
+    F: FnMut(TokenLease) -> Fut,
+    Fut: Future<Output = Result<T, AttemptError>>,
+{
+    let max_attempts = max_attempts.max(1);
+    let mut last_failure: Option<RotationFailure> = None;
+
+    for attempt in 0..max_attempts {
+        let lease = match source.lease(request_type, attempt > 0, model).await {
+            Ok(lease) => lease,
+            Err(e) => {
+                return Err(last_failure.unwrap_or(RotationFailure {
+                    status: StatusCode::SERVICE_UNAVAILABLE.as_u16(),
+                    message: format!("Token error: {}", e),
+                    email: None,
+                    attempts: attempt,
+                }));
+            }
+        };
+        let account_id = lease.account_id.clone();
+        let email = lease.email.clone();
+
+        let error = match call(lease).await {
+            Ok(value) => return Ok(Rotated { value, email }),
+            Err(error) => error,
+        };
+
+        if error.from_upstream {
+            source
+                .report_failure(&account_id, &email, error.status, &error.error_text, model)
+                .await;
+        }
+        let rotate = error.from_upstream && should_rotate_account(error.status);
+        if rotate && attempt + 1 < max_attempts {
+            info!(
+                "[{}] {} on {}, rotating account (attempt {}/{})",
+                request_type,
+                error.status,
+                email,
+                attempt + 1,
+                max_attempts
+            );
+        }
+        last_failure = Some(RotationFailure {
+            status: error.status,
+            message: error.message,
+            email: Some(email),
+            attempts: attempt + 1,
+        });
+        if !rotate {
+            break;
+        }
+    }
+
+    Err(last_failure.unwrap_or(RotationFailure {
+        status: StatusCode::SERVICE_UNAVAILABLE.as_u16(),
+        message: "No attempt was made".to_string(),
+        email: None,
+        attempts: 0,
+    }))
+}
+
+// ===== 响应构建 =====
+// 账号邮箱等来自导入数据, 可能含非 ASCII 字符; 构建响应时不得 unwrap, 否则 handler 任务 panic、客户端只看到断开的连接
+
+/// 转为合法的响应头值: 可见 ASCII 原样保留, 其余字节 (非 ASCII、控制字符) 与 `%` 按 UTF-8 百分号编码,
+/// 可由 [`decode_header_value`] 还原; 结果为空时返回 None (调用方应省略该头)
+pub fn sanitize_header_value(value: &str) -> Option<HeaderValue> {
+    let value = value.trim();
+    if value.is_empty() {
+        return None;
+    }
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if (byte.is_ascii_graphic() && byte != b'%') || byte == b' ' {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    HeaderValue::from_str(&encoded).ok()
+}
+
+/// 还原 [`sanitize_header_value`] 编码的响应头值
+pub fn decode_header_value(value: &str) -> String {
+    String::from_utf8_lossy(&percent_decode(value)).into_owned()
+}
+
+/// 解码 `%XX` 序列, 不合法的序列原样保留
+pub fn percent_decode(input: &str) -> Vec<u8> {
+    let hex = |b: u8| (b as char).to_digit(16);
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let (Some(hi), Some(lo)) = (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                out.push((hi * 16 + lo) as u8);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    out
+}
+
+/// 设置响应头, 值无法表示时省略该头
+pub fn set_header_lossy(response: &mut Response, name: &'static str, value: &str) {
+    match sanitize_header_value(value) {
+        Some(v) => {
+            response.headers_mut().insert(name, v);
+        }
+        None => debug!("Omitting response header {}: value is not representable", name),
+    }
+}
+
+/// 附加 X-Account-Email 与 X-Mapped-Model 响应头
+pub fn with_account_headers(
+    response: impl IntoResponse,
+    email: &str,
+    mapped_model: Option<&str>,
+) -> Response {
+    let mut response = response.into_response();
+    set_header_lossy(&mut response, "X-Account-Email", email);
+    if let Some(model) = mapped_model {
+        set_header_lossy(&mut response, "X-Mapped-Model", model);
+    }
+    response
+}
+
+/// 附加 X-Mapped-Model 响应头 (尚未选定账号时)
+pub fn with_mapped_model(response: impl IntoResponse, mapped_model: &str) -> Response {
+    let mut response = response.into_response();
+    set_header_lossy(&mut response, "X-Mapped-Model", mapped_model);
+    response
+}
+
+/// 所有账号均在冷却时告知客户端最短等待时间 (Retry-After)
+pub fn with_retry_after(response: impl IntoResponse, retry_after_secs: Option<u64>) -> Response {
+    let mut response = response.into_response();
+    if let Some(secs) = retry_after_secs {
+        set_header_lossy(&mut response, "Retry-After", &secs.max(1).to_string());
+    }
+    response
+}
+
+/// 完成 Response::builder(), 构建失败时返回 500 而不是 panic
+pub fn finish_response(result: Result<Response, axum::http::Error>) -> Response {
+    result.unwrap_or_else(|e| {
+        tracing::error!("Failed to build response: {}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, "Failed to build response").into_response()
+    })
+}
+
+/// SSE 响应的 Content-Type: 显式声明 UTF-8, 避免中间代理按其他编码改写非 ASCII 内容
+pub const SSE_CONTENT_TYPE: &str = "text/event-stream; charset=utf-8";
+
+/// 流式响应的 Response::builder(): 统一 Content-Type 与禁用缓存 / 缓冲的头部, handler 只追加自有头部
+pub fn sse_response_builder() -> axum::http::response::Builder {
+    use axum::http::header;
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, SSE_CONTENT_TYPE)
+        .header(header::CACHE_CONTROL, "no-cache")
+        .header(header::CONNECTION, "keep-alive")
+        .header("X-Accel-Buffering", "no")
+}
+
+/// 错误响应体格式, 与客户端使用的协议一致
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorProtocol {
+    OpenAI,
+    Claude,
+    Gemini,
+}
+
+impl ErrorProtocol {
+    /// 按请求路径判定; 不属于任何协议面的 /v1 公共端点 (detect / 遥测) 与 /internal 使用 OpenAI 格式,
+    /// 管理接口与静态资源返回 None
+    pub fn for_path(path: &str) -> Option<Self> {
+        use crate::proxy::config::ListenerProtocol;
+
+        match crate::proxy::server::listeners::classify_path(path) {
+            Some(ListenerProtocol::Anthropic) => Some(Self::Claude),
+            Some(ListenerProtocol::Gemini) => Some(Self::Gemini),
+            Some(ListenerProtocol::OpenAI) => Some(Self::OpenAI),
+            None if path.starts_with("/v1/") || path.starts_with("/internal/") => Some(Self::OpenAI),
+            None => None,
+        }
+    }
+
+    /// 错误体是否已是本协议的格式 (见 `error_body`)
+    pub fn matches_error(self, body: &Value) -> bool {
+        let Some(error) = body.get("error").filter(|e| e.is_object()) else {
+            return false;
+        };
+        let is_str = |key: &str| error.get(key).is_some_and(Value::is_string);
+        match self {
+            Self::Claude => {
+                body.get("type").and_then(Value::as_str) == Some("error") && is_str("type") && is_str("message")
+            }
+            Self::OpenAI => body.get("type").is_none() && is_str("type") && is_str("message"),
+            Self::Gemini => error.get("code").is_some_and(Value::is_u64) && is_str("message") && is_str("status"),
+        }
+    }
+}
+
+/// 协议格式的错误体
+pub fn error_body(protocol: ErrorProtocol, status: StatusCode, message: &str) -> Value {
+    let code = status.as_u16();
+    match protocol {
+        ErrorProtocol::Claude => json!({
+            "type": "error",
+            "error": { "type": claude_error_type(code), "message": message }
+        }),
+        ErrorProtocol::OpenAI => json!({
+            "error": { "message": message, "type": openai_error_type(code), "code": code }
+        }),
+        ErrorProtocol::Gemini => json!({
+            "error": { "code": code, "message": message, "status": gemini_error_status(code) }
+        }),
+    }
+}
+
+/// 协议格式的 JSON 错误响应
+pub fn error_response(protocol: ErrorProtocol, status: StatusCode, message: impl Into<String>) -> Response {
+    (status, Json(error_body(protocol, status, &message.into()))).into_response()
+}
+
+fn claude_error_type(code: u16) -> &'static str {
+    match code {
+        401 => "authentication_error",
+        403 => "permission_error",
+        404 => "not_found_error",
+        413 => "request_too_large",
+        429 => "rate_limit_error",
+        503 | 529 => "overloaded_error",
+        c if c < 500 => "invalid_request_error",
+        _ => "api_error",
+    }
+}
+
+fn openai_error_type(code: u16) -> &'static str {
+    match code {
+        401 => "authentication_error",
+        403 => "permission_error",
+        404 => "not_found_error",
+        429 => "rate_limit_exceeded",
+        c if c < 500 => "invalid_request_error",
+        _ => "api_error",
+    }
+}
+
+fn gemini_error_status(code: u16) -> &'static str {
+    match code {
+        401 => "UNAUTHENTICATED",
+        403 => "PERMISSION_DENIED",
+        404 => "NOT_FOUND",
+        429 => "RESOURCE_EXHAUSTED",
+        501 => "UNIMPLEMENTED",
+        503 => "UNAVAILABLE",
+        504 => "DEADLINE_EXCEEDED",
+        c if c < 500 => "INVALID_ARGUMENT",
+        _ => "INTERNAL",
+    }
+}
+
 /// Detects model capabilities and configuration
 /// POST /v1/models/detect
 pub async fn handle_detect_model(
@@ -156,7 +662,7 @@ pub async fn handle_detect_model(
     let model_name = body.get("model").and_then(|v| v.as_str()).unwrap_or("");
     
     if model_name.is_empty() {
-        return (StatusCode::BAD_REQUEST, "Missing 'model' field").into_response();
+        return error_response(ErrorProtocol::OpenAI, StatusCode::BAD_REQUEST, "Missing 'model' field");
     }
 
     // 1. Resolve mapping
@@ -193,3 +699,252 @@ pub async fn handle_detect_model(
 
     Json(response).into_response()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, extract::Request, routing::post, Router};
+    use tower::util::ServiceExt;
+
+    #[test]
+    fn test_sanitize_header_value() {
+        assert_eq!(sanitize_header_value("alice@gmail.com").unwrap(), "alice@gmail.com");
+        assert_eq!(
+            sanitize_header_value("jürgen.müller@gmail.com").unwrap(),
+            "j%C3%BCrgen.m%C3%BCller@gmail.com"
+        );
+        assert_eq!(
+            sanitize_header_value("a\r\nX-Injected: 1").unwrap(),
+            "a%0D%0AX-Injected: 1"
+        );
+        assert_eq!(sanitize_header_value("张三").unwrap(), "%E5%BC%A0%E4%B8%89");
+        assert!(sanitize_header_value("").is_none());
+
+        // 编码可逆, 不同的非 ASCII 邮箱不会折叠成同一个值
+        for email in ["jürgen.müller@gmail.com", "张三@example.com", "100%@example.com"] {
+            let header = sanitize_header_value(email).unwrap();
+            assert_eq!(decode_header_value(header.to_str().unwrap()), email);
+        }
+        assert_ne!(
+            sanitize_header_value("jürgen@gmail.com"),
+            sanitize_header_value("jrgen@gmail.com")
+        );
+    }
+
+    #[test]
+    fn test_retry_strategy_scales_with_base_delay() {
+        let fast = RetryConfig { base_delay_ms: 500, ..RetryConfig::default() };
+        assert!(matches!(
+            scale_retry_strategy(RetryStrategy::LinearBackoff { base_ms: 2000 }, &fast),
+            RetryStrategy::LinearBackoff { base_ms: 500 }
+        ));
+        assert!(matches!(
+            scale_retry_strategy(RetryStrategy::ExponentialBackoff { base_ms: 10000, max_ms: 60000 }, &fast),
+            RetryStrategy::ExponentialBackoff { base_ms: 2500, max_ms: 15000 }
+        ));
+        // Retry-After 等固定延迟不缩放
+        assert!(matches!(
+            scale_retry_strategy(RetryStrategy::FixedDelay(Duration::from_millis(1200)), &fast),
+            RetryStrategy::FixedDelay(d) if d == Duration::from_millis(1200)
+        ));
+        // 默认配置保持内置延迟
+        assert!(matches!(
+            scale_retry_strategy(RetryStrategy::LinearBackoff { base_ms: 3000 }, &RetryConfig::default()),
+            RetryStrategy::LinearBackoff { base_ms: 3000 }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_non_ascii_email_returns_clean_200() {
+        const EMAIL: &str = "jürgen.müller@gmail.com";
+        let app = Router::new()
+            .route(
+                "/json",
+                post(|| async {
+                    with_account_headers(
+                        (StatusCode::OK, Json(json!({"ok": true}))),
+                        EMAIL,
+                        Some("模型-gemini-3-flash"),
+                    )
+                }),
+            )
+            .route(
+                "/stream",
+                post(|| async {
+                    let response =
+                        finish_response(sse_response_builder().body(Body::from("data: [DONE]\n\n")));
+                    with_account_headers(response, EMAIL, Some("gemini-3-flash"))
+                }),
+            )
+            .route(
+                "/mapped",
+                post(|| async { with_mapped_model((StatusCode::OK, "ok"), "模型") }),
+            );
+
+        for path in ["/json", "/stream", "/mapped"] {
+            let response = app
+                .clone()
+                .oneshot(Request::post(path).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK, "{}", path);
+            for value in response.headers().values() {
+                assert!(value.to_str().is_ok(), "non-ASCII header on {}", path);
+            }
+            if path != "/mapped" {
+                assert_eq!(
+                    response.headers().get("X-Account-Email").unwrap(),
+                    "j%C3%BCrgen.m%C3%BCller@gmail.com"
+                );
+            } else {
+                assert!(response.headers().get("X-Mapped-Model").is_none());
+            }
+        }
+    }
+
+    #[test]
+    fn test_error_body_follows_path_protocol() {
+        let status = StatusCode::UNAUTHORIZED;
+        let claude = error_body(ErrorProtocol::for_path("/v1/messages").unwrap(), status, "bad key");
+        assert_eq!(claude["type"], "error");
+        assert_eq!(claude["error"]["type"], "authentication_error");
+        assert_eq!(claude["error"]["message"], "bad key");
+
+        let openai = error_body(ErrorProtocol::for_path("/v1/models/detect").unwrap(), status, "bad key");
+        assert_eq!(openai["error"]["type"], "authentication_error");
+        assert_eq!(openai["error"]["code"], 401);
+
+        let gemini = error_body(ErrorProtocol::for_path("/v1beta/models/x").unwrap(), status, "bad key");
+        assert_eq!(gemini["error"]["status"], "UNAUTHENTICATED");
+        assert_eq!(gemini["error"]["code"], 401);
+
+        assert_eq!(ErrorProtocol::for_path("/api/accounts"), None);
+        assert_eq!(ErrorProtocol::for_path("/healthz"), None);
+    }
+
+    /// 按顺序发放账号, 记录换号标记与失败上报
+    struct ScriptedSource {
+        accounts: Vec<&'static str>,
+        next: std::sync::Mutex<usize>,
+        rotations: std::sync::Mutex<Vec<bool>>,
+        reports: std::sync::Mutex<Vec<(String, u16)>>,
+    }
+
+    impl ScriptedSource {
+        fn new(accounts: Vec<&'static str>) -> Self {
+            Self {
+                accounts,
+                next: Default::default(),
+                rotations: Default::default(),
+                reports: Default::default(),
+            }
+        }
+    }
+
+    impl LeaseSource for ScriptedSource {
+        fn lease<'a>(
+            &'a self,
+            _request_type: &'a str,
+            force_rotate: bool,
+            _model: &'a str,
+        ) -> BoxFuture<'a, Result<TokenLease, String>> {
+            self.rotations.lock().unwrap().push(force_rotate);
+            let mut next = self.next.lock().unwrap();
+            let result = match self.accounts.get(*next) {
+                Some(id) => Ok(TokenLease {
+                    access_token: format!("token-{}", id),
+                    project_id: "project".to_string(),
+                    email: format!("{}@example.com", id),
+                    account_id: id.to_string(),
+                    active_requests: Default::default(),
+                }),
+                None => Err("No available accounts".to_string()),
+            };
+            *next += 1;
+            Box::pin(async move { result })
+        }
+
+        fn report_failure<'a>(
+            &'a self,
+            account_id: &'a str,
+            _email: &'a str,
+            status: u16,
+            _error_text: &'a str,
+            _model: &'a str,
+        ) -> BoxFuture<'a, ()> {
+            self.reports.lock().unwrap().push((account_id.to_string(), status));
+            Box::pin(async {})
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rotating_account_rotates_on_429() {
+        let source = ScriptedSource::new(vec!["acc-a", "acc-b"]);
+        let result = with_rotating_account(&source, "text", "gemini-2.5-flash", 3, |lease| async move {
+            if lease.account_id == "acc-a" {
+                Err(AttemptError::upstream(429, "RESOURCE_EXHAUSTED", "rate limited"))
+            } else {
+                Ok(lease.access_token.clone())
+            }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(result.value, "token-acc-b");
+        assert_eq!(result.email, "acc-b@example.com");
+        assert_eq!(*source.rotations.lock().unwrap(), vec![false, true]);
+        assert_eq!(*source.reports.lock().unwrap(), vec![("acc-a".to_string(), 429)]);
+    }
+
+    #[tokio::test]
+    async fn test_rotating_account_gives_up() {
+        // 用尽尝试次数: 返回最后一次的上游错误
+        let source = ScriptedSource::new(vec!["acc-a", "acc-b", "acc-c"]);
+        let failure = with_rotating_account(&source, "text", "m", 2, |_lease| async {
+            Err::<(), _>(AttemptError::upstream(429, "quota", "rate limited"))
+        })
+        .await
+        .unwrap_err();
+        assert_eq!((failure.status, failure.attempts), (429, 2));
+        assert_eq!(failure.email.as_deref(), Some("acc-b@example.com"));
+        assert_eq!(source.reports.lock().unwrap().len(), 2);
+
+        // 非账号级错误: 上报但不换号
+        let source = ScriptedSource::new(vec!["acc-a", "acc-b"]);
+        let failure = with_rotating_account(&source, "text", "m", 3, |_lease| async {
+            Err::<(), _>(AttemptError::upstream(400, "INVALID_ARGUMENT", "bad request"))
+        })
+        .await
+        .unwrap_err();
+        assert_eq!((failure.status, failure.attempts), (400, 1));
+        assert_eq!(*source.reports.lock().unwrap(), vec![("acc-a".to_string(), 400)]);
+
+        // 本地错误: 不上报不换号
+        let source = ScriptedSource::new(vec!["acc-a", "acc-b"]);
+        let failure = with_rotating_account(&source, "text", "m", 3, |_lease| async {
+            Err::<(), _>(AttemptError::local(502, "network"))
+        })
+        .await
+        .unwrap_err();
+        assert_eq!(failure.attempts, 1);
+        assert!(source.reports.lock().unwrap().is_empty());
+
+        // 号池耗尽: 保留上一次的上游错误; 一个账号都没有时为 503
+        let source = ScriptedSource::new(vec!["acc-a"]);
+        let failure = with_rotating_account(&source, "text", "m", 3, |_lease| async {
+            Err::<(), _>(AttemptError::upstream(401, "UNAUTHENTICATED", "unauthorized"))
+        })
+        .await
+        .unwrap_err();
+        assert_eq!((failure.status, failure.attempts), (401, 1));
+
+        let source = ScriptedSource::new(vec![]);
+        let failure = with_rotating_account(&source, "text", "m", 3, |_lease| async { Ok(()) })
+            .await
+            .unwrap_err();
+        assert_eq!(failure.status, 503);
+        assert!(failure.email.is_none());
+        let response = failure.into_response();
+        assert!(response.headers().get("X-Account-Email").is_none());
+    }
+}
@@ -1,15 +1,47 @@
 // Gemini Handler
-use axum::{extract::State, extract::{Json, Path}, http::StatusCode, response::IntoResponse};
+use axum::{
+    extract::State,
+    extract::{Json, Path},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
 use serde_json::{json, Value};
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
 use crate::proxy::mappers::gemini::{wrap_request, unwrap_response};
 use crate::proxy::server::AppState;
 use crate::proxy::session_manager::SessionManager;
-use crate::proxy::handlers::common::{determine_retry_strategy, apply_retry_strategy, should_rotate_account};
+use crate::proxy::handlers::common::{
+    determine_retry_strategy, apply_retry_strategy, error_response, finish_response,
+    should_rotate_account, sse_response_builder, with_account_headers, with_rotating_account,
+    ErrorProtocol,
+};
+use crate::proxy::common::system_injection::{self, AppliedInjections, SystemInjection};
+use crate::proxy::common::thinking_capability::{is_thinking_unsupported_error, record_no_thinking};
 use crate::proxy::debug_logger;
+use crate::proxy::upstream::response_ids::{self, UpstreamIds, UpstreamIdsSlot};
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use std::time::{Duration, Instant};
  
-const MAX_RETRY_ATTEMPTS: usize = 3;
+
+/// 代理支持的 Gemini 方法 (未实现端点的 501 响应中列出)
+const SUPPORTED_METHODS: &[&str] = &[
+    "models.list",
+    "models.get",
+    "models.generateContent",
+    "models.streamGenerateContent",
+    "models.countTokens",
+];
+
+/// 同一未支持路径的告警间隔
+const UNSUPPORTED_WARN_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// 告警记录的路径数上限 (扫描器会请求大量随机路径)
+const UNSUPPORTED_WARNED_CAP: usize = 256;
+
+/// 未支持路径 -> 上次告警时间
+static UNSUPPORTED_WARNED: Lazy<DashMap<String, Instant>> = Lazy::new(DashMap::new);
 
 fn is_project_not_found_404(error_text: &str) -> bool {
     error_text.contains("Resource projects/") && error_text.contains("could not be found")
@@ -20,8 +52,9 @@ fn is_project_not_found_404(error_text: &str) -> bool {
 pub async fn handle_generate(
     State(state): State<AppState>,
     Path(model_action): Path<String>,
-    Json(mut body): Json<Value>  // 改为 mut 以支持修复提示词注入
-) -> Result<impl IntoResponse, (StatusCode, String)> {
+    headers: HeaderMap,
+    Json(body): Json<Value>,
+) -> Result<Response, (StatusCode, String)> {
     // 解析 model:method
     let (model_name, method) = if let Some((m, action)) = model_action.rsplit_once(':') {
         (m.to_string(), action.to_string())
@@ -29,13 +62,37 @@ pub async fn handle_generate(
         (model_action, "generateContent".to_string())
     };
 
+    // 按 API Key / 映射条目配置的系统提示注入 (客户端无感知)
+    let injections = system_injection::resolve_for_route(
+        crate::proxy::middleware::auth::extract_api_key(&headers),
+        &model_name,
+        &*state.custom_mapping.read().await,
+    );
+    let mut response = handle_generate_inner(state, model_name, method, body, &injections)
+        .await?
+        .into_response();
+    if !injections.is_empty() {
+        response
+            .extensions_mut()
+            .insert(AppliedInjections(injections.into_iter().map(|i| i.name).collect()));
+    }
+    Ok(response)
+}
+
+async fn handle_generate_inner(
+    state: AppState,
+    model_name: String,
+    method: String,
+    mut body: Value, // mut 以支持修复提示词注入
+    injections: &[SystemInjection],
+) -> Result<impl IntoResponse, (StatusCode, String)> {
     crate::modules::logger::log_info(&format!("Received Gemini request: {}/{}", model_name, method));
     let trace_id = format!("req_{}", chrono::Utc::now().timestamp_subsec_millis());
     let debug_cfg = state.debug_logging.read().await.clone();
 
     // 1. 验证方法
     if method != "generateContent" && method != "streamGenerateContent" {
-        return Err((StatusCode::BAD_REQUEST, format!("Unsupported method: {}", method)));
+        return Ok(unsupported_response(&format!("/v1beta/models/{}:{}", model_name, method)));
     }
     if debug_logger::is_enabled(&debug_cfg) {
         let original_payload = json!({
@@ -61,7 +118,8 @@ pub async fn handle_generate(
     let upstream = state.upstream.clone();
     let token_manager = state.token_manager;
     let pool_size = token_manager.len();
-    let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size).max(1);
+    let retry_config = crate::proxy::config::get_retry_config();
+    let max_attempts = retry_config.max_attempts.min(pool_size).max(1);
     
     let mut last_error = String::new();
     let mut last_email: Option<String> = None;
@@ -115,7 +173,11 @@ pub async fn handle_generate(
 
         // 5. 包装请求 (project injection)
         // [FIX #765] Pass session_id to wrap_request for signature injection
-        let wrapped_body = wrap_request(&body, &project_id, &mapped_model, Some(&session_id));
+        let mut wrapped_body = wrap_request(&body, &project_id, &mapped_model, Some(&session_id));
+        system_injection::apply_to_system_instruction(&mut wrapped_body["request"], injections);
+        let sent_thinking = wrapped_body["request"]["generationConfig"]
+            .get("thinkingConfig")
+            .is_some();
 
         if debug_logger::is_enabled(&debug_cfg) {
             let payload = json!({
@@ -126,6 +188,7 @@ pub async fn handle_generate(
                 "mapped_model": mapped_model,
                 "request_type": config.request_type,
                 "attempt": attempt,
+                "system_injections": injections,
                 "v1internal_request": wrapped_body.clone(),
             });
             debug_logger::write_debug_payload(&debug_cfg, Some(&trace_id), "v1internal_request", &payload).await;
@@ -151,7 +214,6 @@ pub async fn handle_generate(
             // 6. 响应处理
             if is_stream {
                 use axum::body::Body;
-                use axum::response::Response;
                 use bytes::{Bytes, BytesMut};
                 use futures::StreamExt;
                 
@@ -164,8 +226,9 @@ pub async fn handle_generate(
                     "attempt": attempt,
                     "status": status.as_u16(),
                 });
+                let ids_slot = UpstreamIdsSlot::default();
                 let mut response_stream = debug_logger::wrap_reqwest_stream_with_debug(
-                    Box::pin(response.bytes_stream()),
+                    response_ids::capture_stream(Box::pin(response.bytes_stream()), ids_slot.clone()),
                     debug_cfg.clone(),
                     trace_id.clone(),
                     "upstream_response",
@@ -296,16 +359,13 @@ pub async fn handle_generate(
                 
                 if client_wants_stream {
                     let body = Body::from_stream(stream);
-                    return Ok(Response::builder()
-                        .header("Content-Type", "text/event-stream")
-                        .header("Cache-Control", "no-cache")
-                        .header("Connection", "keep-alive")
-                        .header("X-Accel-Buffering", "no")
-                        .header("X-Account-Email", &email)
-                        .header("X-Mapped-Model", &mapped_model)
-                        .body(body)
-                        .unwrap()
-                        .into_response());
+                    let response = finish_response(
+                        sse_response_builder().body(body),
+                    );
+                    return Ok(response_ids::attach(
+                        with_account_headers(response, &email, Some(&mapped_model)),
+                        &ids_slot,
+                    ));
                 } else {
                     // Collect to JSON
                     use crate::proxy::mappers::gemini::collector::collect_stream_to_json;
@@ -313,11 +373,14 @@ pub async fn handle_generate(
                          Ok(gemini_resp) => {
                              info!("[{}] ✓ Stream collected and converted to JSON (Gemini)", session_id);
                              let unwrapped = unwrap_response(&gemini_resp);
-                             return Ok((StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", mapped_model.as_str())], Json(unwrapped)).into_response());
+                             return Ok(response_ids::attach(
+                                 with_account_headers((StatusCode::OK, Json(unwrapped)), &email, Some(&mapped_model)),
+                                 &ids_slot,
+                             ));
                          },
                          Err(e) => {
                              error!("Stream collection error: {}", e);
-                             return Ok((StatusCode::INTERNAL_SERVER_ERROR, format!("Stream collection error: {}", e)).into_response());
+                             return Ok(error_response(ErrorProtocol::Gemini, StatusCode::INTERNAL_SERVER_ERROR, format!("Stream collection error: {}", e)));
                          }
                     }
                 }
@@ -352,14 +415,21 @@ pub async fn handle_generate(
             }
 
             let unwrapped = unwrap_response(&gemini_resp);
-            return Ok((StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", mapped_model.as_str())], Json(unwrapped)).into_response());
+            return Ok(response_ids::attach(
+                with_account_headers((StatusCode::OK, Json(unwrapped)), &email, Some(&mapped_model)),
+                &UpstreamIds::from_json(&gemini_resp).into(),
+            ));
         }
 
         // 处理错误并重试
         let status_code = status.as_u16();
         let _retry_after = response.headers().get("Retry-After").and_then(|h| h.to_str().ok()).map(|s| s.to_string());
         let error_text = response.text().await.unwrap_or_else(|_| format!("HTTP {}", status_code));
-        last_error = format!("HTTP {}: {}", status_code, error_text);
+        last_error = format!(
+            "HTTP {}: {}",
+            status_code,
+            crate::proxy::common::redact::sanitize_upstream_error(&error_text, Some(&project_id))
+        );
         if debug_logger::is_enabled(&debug_cfg) {
             let payload = json!({
                 "kind": "upstream_response_error",
@@ -375,12 +445,17 @@ pub async fn handle_generate(
             debug_logger::write_debug_payload(&debug_cfg, Some(&trace_id), "upstream_response_error", &payload).await;
         }
  
+        // 项目未开通: 标记后换号, 不计入限流 / 熔断
+        if let Some(setup) = crate::proxy::common::project_setup::detect(status_code, &error_text) {
+            token_manager.mark_project_setup_required(&token_lease.account_id, setup).await;
+        }
+
         // 确定重试策略
         let strategy = determine_retry_strategy(status_code, &error_text, false);
         let trace_id = format!("gemini_{}", session_id);
 
         // 执行退避
-        if apply_retry_strategy(strategy, attempt, max_attempts, status_code, &trace_id).await {
+        if apply_retry_strategy(strategy, attempt, max_attempts, status_code, &trace_id, &retry_config).await {
             // [NEW] Circuit Breaker Reporting (402, 429, 401)
             // Replaces old report_429_penalty logic
             if status_code == 402 || status_code == 429 || status_code == 401 {
@@ -443,6 +518,19 @@ pub async fn handle_generate(
             
             continue; // 重试
         }
+
+        // 模型不支持 thinking: 记录到能力表, 重试时包装不再携带 thinkingConfig;
+        // 最后一次尝试不再重试, 落到下方直接返回上游的真实错误
+        if status_code == 400 && sent_thinking && is_thinking_unsupported_error(&error_text) {
+            record_no_thinking(&mapped_model, &error_text);
+            if attempt + 1 < max_attempts {
+                tracing::warn!(
+                    "[Gemini] {} rejected thinking, retrying without thinking",
+                    mapped_model
+                );
+                continue;
+            }
+        }
  
         if status_code == 404 && is_project_not_found_404(&error_text) {
             tracing::warn!(
@@ -471,13 +559,22 @@ pub async fn handle_generate(
 
         // 404 等由于模型配置或路径错误的 HTTP 异常，直接报错，不进行无效轮换
         error!("Gemini Upstream non-retryable error {}: {}", status_code, error_text);
-        return Ok((status, [("X-Account-Email", email.as_str())], error_text).into_response());
+        return Ok(with_account_headers(
+            (status, crate::proxy::common::redact::sanitize_upstream_error(&error_text, Some(&project_id))),
+            &email,
+            None,
+        ));
     }
 
+    let exhausted = error_response(
+        ErrorProtocol::Gemini,
+        StatusCode::TOO_MANY_REQUESTS,
+        format!("All accounts exhausted. Last error: {}", crate::proxy::common::redact::sanitize_upstream_error(&last_error, None)),
+    );
     if let Some(email) = last_email {
-        Ok((StatusCode::TOO_MANY_REQUESTS, [("X-Account-Email", email)], format!("All accounts exhausted. Last error: {}", last_error)).into_response())
+        Ok(with_account_headers(exhausted, &email, None))
     } else {
-        Ok((StatusCode::TOO_MANY_REQUESTS, format!("All accounts exhausted. Last error: {}", last_error)).into_response())
+        Ok(exhausted)
     }
 }
 
@@ -490,20 +587,10 @@ pub async fn handle_list_models(State(state): State<AppState>) -> Result<impl In
     ).await;
 
     // 转换为 Gemini API 格式
-    let models: Vec<_> = model_ids.into_iter().map(|id| {
-        json!({
-            "name": format!("models/{}", id),
-            "version": "001",
-            "displayName": id.clone(),
-            "description": "",
-            "inputTokenLimit": 128000,
-            "outputTokenLimit": 8192,
-            "supportedGenerationMethods": ["generateContent", "countTokens"],
-            "temperature": 1.0,
-            "topP": 0.95,
-            "topK": 64
-        })
-    }).collect();
+    let models: Vec<_> = model_ids
+        .iter()
+        .map(|id| crate::proxy::handlers::common::gemini_model_entry(id))
+        .collect();
 
     Ok(Json(json!({ "models": models })))
 }
@@ -517,8 +604,188 @@ pub async fn handle_get_model(Path(model_name): Path<String>) -> impl IntoRespon
 
 pub async fn handle_count_tokens(State(state): State<AppState>, Path(_model_name): Path<String>, Json(_body): Json<Value>) -> Result<impl IntoResponse, (StatusCode, String)> {
     let model_group = "gemini";
-    let _token_lease = state.token_manager.get_token(model_group, false, None, "gemini").await
-        .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, format!("Token error: {}", e)))?;
-    
-    Ok(Json(json!({"totalTokens": 0})))
+    let rotated = with_rotating_account(state.token_manager.as_ref(), model_group, "gemini", 1, |_lease| async {
+        Ok(json!({"totalTokens": 0}))
+    })
+    .await;
+
+    Ok(match rotated {
+        Ok(rotated) => with_account_headers(Json(rotated.value), &rotated.email, None),
+        Err(failure) => failure.into_response(),
+    })
+}
+
+/// 由请求路径推导 Gemini 方法名, 如 "/v1beta/models/aqa:generateAnswer" -> "models.generateAnswer",
+/// "/v1beta/corpora/c1/documents" -> "corpora.documents"
+fn unsupported_method_name(path: &str) -> String {
+    let rest = path.trim_start_matches('/');
+    let rest = rest.strip_prefix("v1beta").unwrap_or(rest).trim_start_matches('/');
+    let (resource, action) = match rest.rsplit_once(':') {
+        Some((resource, action)) => (resource, Some(action)),
+        None => (rest, None),
+    };
+    // 偶数位为集合名, 奇数位为资源 ID
+    let mut parts: Vec<&str> = resource
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .step_by(2)
+        .collect();
+    parts.extend(action);
+    if parts.is_empty() {
+        return "unknown".to_string();
+    }
+    parts.join(".")
+}
+
+/// 同一路径每小时只告警一次
+fn should_warn_unsupported(path: &str) -> bool {
+    should_warn_unsupported_in(&UNSUPPORTED_WARNED, path, Instant::now())
+}
+
+/// 记录满额时先清理过期路径, 仍满则不再告警 (避免随机路径撑大内存)
+fn should_warn_unsupported_in(warned: &DashMap<String, Instant>, path: &str, now: Instant) -> bool {
+    if warned.len() >= UNSUPPORTED_WARNED_CAP && !warned.contains_key(path) {
+        warned.retain(|_, at| now.duration_since(*at) < UNSUPPORTED_WARN_INTERVAL);
+        if warned.len() >= UNSUPPORTED_WARNED_CAP {
+            return false;
+        }
+    }
+    match warned.entry(path.to_string()) {
+        dashmap::mapref::entry::Entry::Occupied(mut entry) => {
+            if now.duration_since(*entry.get()) < UNSUPPORTED_WARN_INTERVAL {
+                return false;
+            }
+            entry.insert(now);
+            true
+        }
+        dashmap::mapref::entry::Entry::Vacant(entry) => {
+            entry.insert(now);
+            true
+        }
+    }
+}
+
+/// Google 风格的 501 错误, 附带代理支持的方法列表
+fn unsupported_response(path: &str) -> axum::response::Response {
+    let method = unsupported_method_name(path);
+    if should_warn_unsupported(path) {
+        warn!("[Gemini] Unsupported endpoint requested: {} ({})", path, method);
+    }
+    (
+        StatusCode::NOT_IMPLEMENTED,
+        Json(json!({
+            "error": {
+                "code": 501,
+                "message": format!(
+                    "Method {} is not supported by this proxy. Supported methods: {}",
+                    method,
+                    SUPPORTED_METHODS.join(", ")
+                ),
+                "status": "UNIMPLEMENTED",
+                "supportedMethods": SUPPORTED_METHODS,
+            }
+        })),
+    )
+        .into_response()
+}
+
+/// v1beta 命名空间下未实现的端点 (tunedModels / corpora / generateAnswer 等)
+pub async fn handle_unsupported(uri: axum::http::Uri) -> axum::response::Response {
+    unsupported_response(uri.path())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::Request, Router};
+    use tower::ServiceExt;
+
+    /// 使用真实的代理路由表, 确认 catch-all 与已实现端点的优先级
+    async fn call(path: &str, body: Value) -> (StatusCode, Value) {
+        let data_dir = tempfile::tempdir().unwrap();
+        let upstream = std::sync::Arc::new(crate::proxy::benchmark::MockUpstream::new(
+            &crate::proxy::benchmark::BenchmarkConfig {
+                chunk_interval_ms: 0,
+                ..Default::default()
+            },
+        ));
+        let app: Router = crate::proxy::server::routes::build_proxy_routes()
+            .with_state(crate::proxy::benchmark::benchmark_state(upstream, 1, data_dir.path()));
+        let response = app
+            .oneshot(
+                Request::post(path)
+                    .header("content-type", "application/json")
+                    .body(Body::from(body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        decode(response).await
+    }
+
+    async fn decode(response: axum::response::Response) -> (StatusCode, Value) {
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    fn assert_unimplemented(status: StatusCode, body: &Value, method: &str) {
+        assert_eq!(status, StatusCode::NOT_IMPLEMENTED);
+        assert_eq!(body["error"]["code"], 501);
+        assert_eq!(body["error"]["status"], "UNIMPLEMENTED");
+        assert!(body["error"]["message"].as_str().unwrap().contains(method));
+        assert!(body["error"]["supportedMethods"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("models.generateContent")));
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_endpoints_return_501_with_capabilities() {
+        let (status, body) = call("/v1beta/tunedModels/my-model:generateContent", json!({})).await;
+        assert_unimplemented(status, &body, "tunedModels.generateContent");
+
+        let (status, body) = call("/v1beta/corpora/c1/documents", json!({})).await;
+        assert_unimplemented(status, &body, "corpora.documents");
+
+        // generateAnswer 落在 models/:model 路由上, 由 handle_generate 的方法校验返回同样的 501
+        let (status, body) = call("/v1beta/models/aqa:generateAnswer", json!({})).await;
+        assert_unimplemented(status, &body, "models.generateAnswer");
+    }
+
+    #[tokio::test]
+    async fn test_supported_route_is_not_shadowed() {
+        let (status, body) = call(
+            "/v1beta/models/gemini-2.5-flash:generateContent",
+            json!({ "contents": [{ "role": "user", "parts": [{ "text": "hi" }] }] }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body["candidates"][0]["content"]["parts"][0]["text"].is_string());
+    }
+
+    #[test]
+    fn test_unsupported_warning_is_rate_limited_per_path() {
+        let warned = DashMap::new();
+        let now = Instant::now();
+        assert!(should_warn_unsupported_in(&warned, "/v1beta/corpora/a", now));
+        assert!(!should_warn_unsupported_in(&warned, "/v1beta/corpora/a", now));
+        assert!(should_warn_unsupported_in(&warned, "/v1beta/tunedModels/a", now));
+    }
+
+    #[test]
+    fn test_unsupported_warning_map_is_capped() {
+        let warned = DashMap::new();
+        let start = Instant::now();
+        for i in 0..UNSUPPORTED_WARNED_CAP {
+            assert!(should_warn_unsupported_in(&warned, &format!("/v1beta/scan/{}", i), start));
+        }
+        assert!(!should_warn_unsupported_in(&warned, "/v1beta/scan/overflow", start));
+        assert_eq!(warned.len(), UNSUPPORTED_WARNED_CAP);
+
+        // 过期记录被清理后可再次告警
+        let later = start + UNSUPPORTED_WARN_INTERVAL;
+        assert!(should_warn_unsupported_in(&warned, "/v1beta/scan/overflow", later));
+        assert_eq!(warned.len(), 1);
+    }
 }
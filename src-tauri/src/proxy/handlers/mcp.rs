@@ -12,7 +12,20 @@ use tokio_stream::wrappers::IntervalStream;
 
 use crate::proxy::server::AppState;
 
-fn build_client(
+/// 该 MCP 路由在当前 z.ai 配置下是否可用 (与各 handler 的启用检查一致, 供能力清单使用)
+pub fn mcp_route_enabled(path: &str, zai: &crate::proxy::ZaiConfig) -> bool {
+    if !zai.enabled || zai.api_key.trim().is_empty() || !zai.mcp.enabled {
+        return false;
+    }
+    match path {
+        "/mcp/web_search_prime/mcp" => zai.mcp.web_search_enabled,
+        "/mcp/web_reader/mcp" => zai.mcp.web_reader_enabled,
+        "/mcp/zai-mcp-server/mcp" => zai.mcp.vision_enabled,
+        _ => false,
+    }
+}
+
+pub(crate) fn build_client(
     upstream_proxy: crate::proxy::config::UpstreamProxyConfig,
     timeout_secs: u64,
 ) -> Result<reqwest::Client, String> {
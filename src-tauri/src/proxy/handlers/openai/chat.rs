@@ -3,28 +3,39 @@
 
 use axum::{
     extract::{Json, State},
-    http::StatusCode,
-    response::IntoResponse,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
 };
 use bytes::Bytes;
 use serde_json::{json, Value};
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
+use crate::proxy::common::project_setup;
+use crate::proxy::common::system_injection::{self, AppliedInjections, SystemInjection};
+use crate::proxy::common::thinking_capability::{is_thinking_unsupported_error, record_no_thinking};
 use crate::proxy::debug_logger;
+use crate::proxy::mappers::openai::response_format::{convert_response_format, SCHEMA_LOSSY_HEADER};
+use crate::proxy::mappers::openai::strict_tools::{
+    apply_validated_mode, is_validated_mode_rejected, StrictToolSchemas,
+};
 use crate::proxy::mappers::openai::{
-    transform_openai_request, transform_openai_response, OpenAIRequest,
+    transform_openai_request, transform_openai_response, OpenAIContent, OpenAIMessage, OpenAIRequest,
+    IGNORED_FIELDS_HEADER,
 };
+use crate::proxy::mappers::stream_watchdog::{self, StallSlot, StreamProtocol};
 use crate::proxy::server::AppState;
+use crate::proxy::upstream::response_ids::{self, UpstreamIds, UpstreamIdsSlot};
 use crate::proxy::session_manager::SessionManager;
 use super::super::common::{
-    apply_retry_strategy, determine_retry_strategy, should_rotate_account, RetryStrategy,
+    apply_retry_strategy, determine_retry_strategy, finish_response, should_rotate_account,
+    sse_response_builder, with_account_headers, with_mapped_model, with_retry_after,
+    RetryStrategy,
 };
 use tokio::time::Duration;
 
-const MAX_RETRY_ATTEMPTS: usize = 3;
-
 pub async fn handle_chat_completions(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Json(mut body): Json<Value>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
     // Save original request body for logging
@@ -34,6 +45,15 @@ pub async fn handle_chat_completions(
     let is_responses_format = !body.get("messages").is_some()
         && (body.get("instructions").is_some() || body.get("input").is_some());
 
+    // OpenAI 兼容的额外 provider 直接透传 Chat Completions 请求
+    if !is_responses_format {
+        if let Some(response) =
+            super::forward_to_provider(&state, &headers, "/v1/chat/completions", &body).await
+        {
+            return Ok(response);
+        }
+    }
+
     if is_responses_format {
         debug!("Detected Responses API format, converting to Chat Completions format");
 
@@ -95,6 +115,69 @@ pub async fn handle_chat_completions(
             });
     }
 
+    // 未识别的顶层字段不会转发上游, 通过响应头告知调用方
+    let ignored_fields = openai_req.ignored_fields();
+    if !ignored_fields.is_empty() {
+        debug!(
+            "[OpenAI] Ignoring unsupported request fields: {}",
+            ignored_fields.join(", ")
+        );
+    }
+
+    // 结构化输出: strict schema 无法完整表示时直接拒绝, 有损转换通过响应头告知
+    let schema_lossy = match openai_req.response_format.as_ref().and_then(convert_response_format) {
+        Some(output) => {
+            if let Some(message) = output.strict_error() {
+                return Err((StatusCode::BAD_REQUEST, message));
+            }
+            if output.is_lossy() {
+                warn!(
+                    "[OpenAI] response_format schema keywords not supported upstream, dropped: {}",
+                    output.dropped.join(", ")
+                );
+            }
+            output.is_lossy()
+        }
+        None => false,
+    };
+
+    // image_url: 远程图片下载后内联 (上游不接受任意 URL), 不支持的 scheme 直接拒绝
+    super::vision::inline_image_urls(&mut openai_req, &state.upstream)
+        .await
+        .map_err(|message| (StatusCode::BAD_REQUEST, message))?;
+
+    // 按 API Key / 映射条目配置的系统提示注入 (客户端无感知)
+    let injections = system_injection::resolve_for_route(
+        crate::proxy::middleware::auth::extract_api_key(&headers),
+        &openai_req.model,
+        &*state.custom_mapping.read().await,
+    );
+
+    let mut response = dispatch_chat_request(state, openai_req, original_body, &injections).await?;
+    if !injections.is_empty() {
+        response
+            .extensions_mut()
+            .insert(AppliedInjections(injections.into_iter().map(|i| i.name).collect()));
+    }
+    if !ignored_fields.is_empty() {
+        if let Ok(value) = ignored_fields.join(", ").parse() {
+            response.headers_mut().insert(IGNORED_FIELDS_HEADER, value);
+        }
+    }
+    if schema_lossy {
+        response
+            .headers_mut()
+            .insert(SCHEMA_LOSSY_HEADER, axum::http::HeaderValue::from_static("true"));
+    }
+    Ok(response)
+}
+
+async fn dispatch_chat_request(
+    state: AppState,
+    mut openai_req: OpenAIRequest,
+    original_body: Value,
+    injections: &[SystemInjection],
+) -> Result<Response, (StatusCode, String)> {
     let trace_id = format!("req_{}", chrono::Utc::now().timestamp_subsec_millis());
     info!(
         "[{}] OpenAI Chat Request: {} | {} messages | stream: {}",
@@ -116,11 +199,22 @@ pub async fn handle_chat_completions(
     let upstream = state.upstream.clone();
     let token_manager = state.token_manager;
     let pool_size = token_manager.len();
-    let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size.saturating_add(1)).max(2);
+    let retry_config = crate::proxy::config::get_retry_config();
+    let max_attempts = retry_config.attempts_for_pool(pool_size);
 
     let mut last_error = String::new();
     let mut last_email: Option<String> = None;
 
+    // strict 工具: 按原始 schema 校验响应中的工具参数, 非流式违规时最多重新生成一次, 流式只标注
+    let strict_tools = StrictToolSchemas::from_request(&openai_req);
+    let (strict_violation_mode, strict_validated_mode) = {
+        let experimental = state.experimental.read().await;
+        (experimental.strict_tool_violation_mode, experimental.strict_tools_validated_mode)
+    };
+    let mut strict_retry_used = false;
+    // 上游拒绝 VALIDATED 模式后, 本次请求的后续尝试不再携带
+    let mut validated_mode_rejected = false;
+
     // 2. Model routing
     let mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
         &openai_req.model,
@@ -155,13 +249,11 @@ pub async fn handle_chat_completions(
         {
             Ok(t) => t,
             Err(e) => {
-                let headers = [("X-Mapped-Model", mapped_model.as_str())];
-                return Ok((
-                    StatusCode::SERVICE_UNAVAILABLE,
-                    headers,
-                    format!("Token error: {}", e),
-                )
-                    .into_response());
+                let retry_after = token_manager.min_cooldown_secs(Some(&mapped_model)).await;
+                return Ok(with_mapped_model(
+                    accounts_unavailable_error(StatusCode::SERVICE_UNAVAILABLE, format!("Token error: {}", e), retry_after),
+                    &mapped_model,
+                ));
             }
         };
 
@@ -173,7 +265,16 @@ pub async fn handle_chat_completions(
         info!("✓ Using account: {} (type: {})", email, config.request_type);
 
         // 4. Transform request
-        let gemini_body = transform_openai_request(&openai_req, &project_id, &mapped_model);
+        let mut gemini_body = transform_openai_request(&openai_req, &project_id, &mapped_model);
+        system_injection::apply_to_system_instruction(&mut gemini_body["request"], injections);
+        let sent_validated_mode =
+            strict_validated_mode && !validated_mode_rejected && !strict_tools.is_empty();
+        if sent_validated_mode {
+            apply_validated_mode(&mut gemini_body);
+        }
+        let sent_thinking = gemini_body["request"]["generationConfig"]
+            .get("thinkingConfig")
+            .is_some();
 
         if debug_logger::is_enabled(&debug_cfg) {
             let payload = json!({
@@ -184,6 +285,7 @@ pub async fn handle_chat_completions(
                 "mapped_model": mapped_model,
                 "request_type": config.request_type,
                 "attempt": attempt,
+                "system_injections": injections,
                 "v1internal_request": gemini_body.clone(),
             });
             debug_logger::write_debug_payload(&debug_cfg, Some(&trace_id), "v1internal_request", &payload).await;
@@ -236,7 +338,6 @@ pub async fn handle_chat_completions(
             if actual_stream {
                 use crate::proxy::mappers::openai::streaming::create_openai_sse_stream;
                 use axum::body::Body;
-                use axum::response::Response;
                 use futures::StreamExt;
 
                 let meta = json!({
@@ -248,29 +349,45 @@ pub async fn handle_chat_completions(
                     "attempt": attempt,
                     "status": status.as_u16(),
                 });
+                let ids_slot = UpstreamIdsSlot::default();
                 let gemini_stream = debug_logger::wrap_reqwest_stream_with_debug(
-                    Box::pin(response.bytes_stream()),
+                    response_ids::capture_stream(Box::pin(response.bytes_stream()), ids_slot.clone()),
                     debug_cfg.clone(),
                     trace_id.clone(),
                     "upstream_response",
                     meta,
                 );
 
-                let mut openai_stream =
+                let openai_stream =
                     create_openai_sse_stream(
                         gemini_stream,
                         openai_req.model.clone(),
                         session_id.clone(),
                         openai_req.messages.len(),
+                        openai_req.include_stream_usage(),
                     );
 
+                // 流中途停滞看门狗: 停滞计入账号健康分
+                let stall_slot = StallSlot::default();
+                let mut openai_stream = {
+                    let token_manager = token_manager.clone();
+                    let (account_id, email) = (token_lease.account_id.clone(), email.clone());
+                    stream_watchdog::watch(
+                        openai_stream,
+                        StreamProtocol::OpenAI,
+                        retry_config.stall_timeout(),
+                        stall_slot.clone(),
+                        move |_| token_manager.report_stream_stall(&account_id, &email),
+                    )
+                };
+
                 let mut first_data_chunk = None;
                 let mut retry_this_account = false;
 
                 // Peek loop to skip heartbeats
                 loop {
                     match tokio::time::timeout(
-                        std::time::Duration::from_secs(60),
+                        retry_config.peek_timeout(),
                         openai_stream.next(),
                     )
                     .await
@@ -312,7 +429,8 @@ pub async fn handle_chat_completions(
                         }
                         Err(_) => {
                             tracing::warn!(
-                                "[OpenAI] Timeout waiting for first data (60s), retrying..."
+                                "[OpenAI] Timeout waiting for first data ({}s), retrying...",
+                                retry_config.peek_timeout_secs
                             );
                             last_error = "Timeout waiting for first data".to_string();
                             retry_this_account = true;
@@ -326,41 +444,94 @@ pub async fn handle_chat_completions(
                     continue;
                 }
 
-                let combined_stream =
-                    futures::stream::once(
-                        async move { Ok::<Bytes, String>(first_data_chunk.unwrap()) },
+                // 先缓存少量输出: 期间停滞时客户端尚未收到内容, 可透明换号重试
+                let first_data_chunk = first_data_chunk.unwrap();
+                let held = if client_wants_stream {
+                    stream_watchdog::hold_output(
+                        &mut openai_stream,
+                        first_data_chunk,
+                        StreamProtocol::OpenAI,
+                        retry_config.stall_retry_chars,
                     )
-                    .chain(openai_stream);
+                    .await
+                } else {
+                    vec![Ok(first_data_chunk)]
+                };
+                if let Some(stall) = stall_slot.get() {
+                    last_error = stall.message();
+                    continue;
+                }
+
+                let combined_stream = futures::stream::iter(held).chain(openai_stream);
 
                 if client_wants_stream {
+                    // strict 工具: 分片已实时下发, 违规调用只能标注 validation_error
+                    let stream_strict_tools = strict_tools.clone();
+                    let combined_stream = combined_stream
+                        .map(move |chunk| chunk.map(|bytes| stream_strict_tools.annotate_chunk(bytes)));
                     let body = Body::from_stream(combined_stream);
-                    return Ok(Response::builder()
-                        .header("Content-Type", "text/event-stream")
-                        .header("Cache-Control", "no-cache")
-                        .header("Connection", "keep-alive")
-                        .header("X-Accel-Buffering", "no")
-                        .header("X-Account-Email", &email)
-                        .header("X-Mapped-Model", &mapped_model)
-                        .body(body)
-                        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to build value: {}", e)))?
-                        .into_response());
+                    let response = finish_response(
+                        sse_response_builder().body(body),
+                    );
+                    return Ok(response_ids::attach(
+                        with_account_headers(response, &email, Some(&mapped_model)),
+                        &ids_slot,
+                    ));
                 } else {
                     use crate::proxy::mappers::openai::collector::collect_stream_to_json;
+                    use crate::proxy::mappers::stream_completeness::TRUNCATED_HEADER;
 
-                    match collect_stream_to_json(Box::pin(combined_stream)).await {
-                        Ok(full_response) => {
-                            info!("[{}] ✓ Stream collected and converted to JSON", trace_id);
+                    let collected = collect_stream_to_json(Box::pin(combined_stream)).await;
+                    // 非流式客户端尚未收到任何内容: 停滞时整体换号重试
+                    if let Some(stall) = stall_slot.get() {
+                        last_error = stall.message();
+                        continue;
+                    }
+                    match collected {
+                        Ok(collected) => {
+                            let completeness = collected.completeness;
+                            if !completeness.is_complete() {
+                                if !completeness.has_content {
+                                    tracing::warn!(
+                                        "[{}] Stream ended before completion without content, retrying...",
+                                        trace_id
+                                    );
+                                    last_error = "Truncated response stream (no content)".to_string();
+                                    continue;
+                                }
+                                tracing::warn!(
+                                    "[{}] Stream ended before completion (confidence {:.2}), returning partial content as length",
+                                    trace_id,
+                                    completeness.confidence()
+                                );
+                            } else {
+                                info!("[{}] ✓ Stream collected and converted to JSON", trace_id);
+                            }
+                            let mut openai_response = collected.response;
+                            let can_retry = !strict_retry_used && attempt + 1 < max_attempts;
+                            if let Some(note) =
+                                strict_tools.enforce(&mut openai_response, strict_violation_mode, can_retry)
+                            {
+                                tracing::warn!("[{}] Strict tool arguments invalid, regenerating once", trace_id);
+                                strict_retry_used = true;
+                                openai_req.messages.push(corrective_system_message(note));
+                                last_error = "Strict tool call arguments failed schema validation".to_string();
+                                continue;
+                            }
                             crate::proxy::SignatureCache::global()
                                 .delete_session_signature(&session_id);
-                            return Ok((
-                                StatusCode::OK,
-                                [
-                                    ("X-Account-Email", email.as_str()),
-                                    ("X-Mapped-Model", mapped_model.as_str()),
-                                ],
-                                Json(full_response),
-                            )
-                                .into_response());
+                            let mut response = with_account_headers(
+                                (StatusCode::OK, Json(openai_response)),
+                                &email,
+                                Some(&mapped_model),
+                            );
+                            if !completeness.is_complete() {
+                                response.headers_mut().insert(
+                                    TRUNCATED_HEADER,
+                                    axum::http::HeaderValue::from_static("true"),
+                                );
+                            }
+                            return Ok(response_ids::attach(response, &ids_slot));
                         }
                         Err(e) => {
                             error!("[{}] Stream collection error: {}", trace_id, e);
@@ -379,16 +550,12 @@ pub async fn handle_chat_completions(
                 .await
                 .map_err(|e| (StatusCode::BAD_GATEWAY, format!("Parse error: {}", e)))?;
 
-            let openai_response = transform_openai_response(&gemini_resp);
-            return Ok((
-                StatusCode::OK,
-                [
-                    ("X-Account-Email", email.as_str()),
-                    ("X-Mapped-Model", mapped_model.as_str()),
-                ],
-                Json(openai_response),
-            )
-                .into_response());
+            let mut openai_response = transform_openai_response(&gemini_resp);
+            strict_tools.enforce(&mut openai_response, strict_violation_mode, false);
+            return Ok(response_ids::attach(
+                with_account_headers((StatusCode::OK, Json(openai_response)), &email, Some(&mapped_model)),
+                &UpstreamIds::from_json(&gemini_resp).into(),
+            ));
         }
 
         // Handle errors and retry
@@ -402,7 +569,11 @@ pub async fn handle_chat_completions(
             .text()
             .await
             .unwrap_or_else(|_| format!("HTTP {}", status_code));
-        last_error = format!("HTTP {}: {}", status_code, error_text);
+        last_error = format!(
+            "HTTP {}: {}",
+            status_code,
+            crate::proxy::common::redact::sanitize_upstream_error(&error_text, Some(&project_id))
+        );
 
         tracing::error!(
             "[OpenAI-Upstream] Error Response {}: {}",
@@ -425,17 +596,15 @@ pub async fn handle_chat_completions(
         }
 
         // Mark rate limited status
-        if status_code == 429 || status_code == 529 || status_code == 503 || status_code == 500 {
-            token_manager
-                .mark_rate_limited_async(
-                    &email,
-                    status_code,
-                    _retry_after.as_deref(),
-                    &error_text,
-                    Some(&mapped_model),
-                )
-                .await;
-        }
+        token_manager
+            .mark_rate_limited_async(
+                &email,
+                status_code,
+                _retry_after.as_deref(),
+                &error_text,
+                Some(&mapped_model),
+            )
+            .await;
 
         // Circuit Breaker Reporting
         if status_code == 402 || status_code == 429 || status_code == 401 {
@@ -478,12 +647,41 @@ pub async fn handle_chat_completions(
             continue;
         }
 
+        // 上游不接受 VALIDATED 函数调用模式: 去掉该设置重试, 仅保留本地校验
+        if status_code == 400 && sent_validated_mode && is_validated_mode_rejected(&error_text) {
+            tracing::warn!(
+                "[OpenAI] {} rejected VALIDATED function calling, retrying without it",
+                mapped_model
+            );
+            validated_mode_rejected = true;
+            continue;
+        }
+
+        // 模型不支持 thinking: 记录到能力表, 重试时转换不再携带 thinkingConfig;
+        // 最后一次尝试不再重试, 落到下方直接返回上游的真实错误
+        if status_code == 400 && sent_thinking && is_thinking_unsupported_error(&error_text) {
+            record_no_thinking(&mapped_model, &error_text);
+            if attempt + 1 < max_attempts {
+                tracing::warn!(
+                    "[OpenAI] {} rejected thinking, retrying without thinking",
+                    mapped_model
+                );
+                continue;
+            }
+        }
+
         // 403/401 trigger account rotation
         if status_code == 403 || status_code == 401 {
             if status_code == 403 {
                 // Refined 403 classification
                 if let Some(acc_id) = token_manager.get_account_id_by_email(&email) {
-                    if is_validation_required_error(&error_text) {
+                    if let Some(setup) = project_setup::detect(status_code, &error_text) {
+                        tracing::warn!(
+                            "[OpenAI] Project setup required on account {}, excluding until retried",
+                            email
+                        );
+                        token_manager.mark_project_setup_required(&acc_id, setup).await;
+                    } else if is_validation_required_error(&error_text) {
                         tracing::warn!(
                             "[OpenAI] VALIDATION_REQUIRED detected on account {}, temporarily blocking",
                             email
@@ -518,6 +716,7 @@ pub async fn handle_chat_completions(
                 max_attempts,
                 status_code,
                 &trace_id,
+                &retry_config,
             )
                 .await;
                 continue;
@@ -526,7 +725,7 @@ pub async fn handle_chat_completions(
 
         let strategy = determine_retry_strategy(status_code, &error_text, false);
         if attempt + 1 < max_attempts
-            && apply_retry_strategy(strategy, attempt, max_attempts, status_code, &trace_id).await
+            && apply_retry_strategy(strategy, attempt, max_attempts, status_code, &trace_id, &retry_config).await
         {
             if !should_rotate_account(status_code) {
                 debug!(
@@ -550,32 +749,54 @@ pub async fn handle_chat_completions(
             "OpenAI Upstream non-retryable error {} on account {}: {}",
             status_code, email, error_text
         );
-        return Ok((
-            status,
-            [
-                ("X-Account-Email", email.as_str()),
-                ("X-Mapped-Model", mapped_model.as_str()),
-            ],
-            error_text,
-        )
-            .into_response());
+        return Ok(with_account_headers(
+            (status, crate::proxy::common::redact::sanitize_upstream_error(&error_text, Some(&project_id))),
+            &email,
+            Some(&mapped_model),
+        ));
     }
 
     // All attempts failed
+    let retry_after = token_manager.min_cooldown_secs(Some(&mapped_model)).await;
+    let response = accounts_unavailable_error(
+        StatusCode::TOO_MANY_REQUESTS,
+        format!("All accounts exhausted. Last error: {}", crate::proxy::common::redact::sanitize_upstream_error(&last_error, None)),
+        retry_after,
+    );
     if let Some(email) = last_email {
-        Ok((
-            StatusCode::TOO_MANY_REQUESTS,
-            [("X-Account-Email", email), ("X-Mapped-Model", mapped_model)],
-            format!("All accounts exhausted. Last error: {}", last_error),
-        )
-            .into_response())
+        Ok(with_account_headers(response, &email, Some(&mapped_model)))
     } else {
-        Ok((
-            StatusCode::TOO_MANY_REQUESTS,
-            [("X-Mapped-Model", mapped_model)],
-            format!("All accounts exhausted. Last error: {}", last_error),
-        )
-            .into_response())
+        Ok(with_mapped_model(response, &mapped_model))
+    }
+}
+
+/// 无可用账号 / 重试耗尽时的 OpenAI 格式错误, 所有账号冷却中时附带 Retry-After 与 retry_after_seconds
+fn accounts_unavailable_error(status: StatusCode, message: String, retry_after: Option<u64>) -> Response {
+    let error_type = if status == StatusCode::TOO_MANY_REQUESTS {
+        "rate_limit_exceeded"
+    } else {
+        "service_unavailable"
+    };
+    let mut error = json!({
+        "message": message,
+        "type": error_type,
+        "code": status.as_u16()
+    });
+    if let Some(secs) = retry_after {
+        error["retry_after_seconds"] = json!(secs.max(1));
+    }
+    with_retry_after((status, Json(json!({ "error": error }))), retry_after)
+}
+
+/// strict 工具参数校验失败后重新生成时追加的纠正提示
+fn corrective_system_message(note: String) -> OpenAIMessage {
+    OpenAIMessage {
+        role: "system".to_string(),
+        content: Some(OpenAIContent::String(note)),
+        reasoning_content: None,
+        tool_calls: None,
+        tool_call_id: None,
+        name: None,
     }
 }
 
@@ -3,7 +3,7 @@
 
 use axum::{
     extract::{Json, State},
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
     response::{IntoResponse, Response},
 };
 use bytes::Bytes;
@@ -14,15 +14,18 @@ use crate::proxy::mappers::openai::{
     transform_openai_request, transform_openai_response, OpenAIRequest,
 };
 use crate::proxy::server::AppState;
+use crate::proxy::upstream::response_ids::{self, UpstreamIds, UpstreamIdsSlot};
 use crate::proxy::session_manager::SessionManager;
-use super::super::common::{apply_retry_strategy, determine_retry_strategy};
-
-const MAX_RETRY_ATTEMPTS: usize = 3;
+use super::super::common::{
+    apply_retry_strategy, determine_retry_strategy, error_response, finish_response,
+    sse_response_builder, with_account_headers, with_mapped_model, ErrorProtocol,
+};
 
 /// Handle Legacy Completions API (/v1/completions)
 /// Converts Prompt to Chat Message format, reuses chat completions logic
 pub async fn handle_completions(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Json(mut body): Json<Value>,
 ) -> Response {
     debug!(
@@ -32,6 +35,15 @@ pub async fn handle_completions(
 
     let is_codex_style = body.get("input").is_some() || body.get("instructions").is_some();
 
+    // Legacy Completions 请求可直接透传给 OpenAI 兼容的额外 provider
+    if !is_codex_style {
+        if let Some(response) =
+            super::forward_to_provider(&state, &headers, "/v1/completions", &body).await
+        {
+            return response;
+        }
+    }
+
     // 1. Convert Payload to Messages (Shared Chat Format)
     if is_codex_style {
         let instructions = body
@@ -351,7 +363,7 @@ pub async fn handle_completions(
     let mut openai_req: OpenAIRequest = match serde_json::from_value(body.clone()) {
         Ok(req) => req,
         Err(e) => {
-            return (StatusCode::BAD_REQUEST, format!("Invalid request: {}", e)).into_response();
+            return error_response(ErrorProtocol::OpenAI, StatusCode::BAD_REQUEST, format!("Invalid request: {}", e));
         }
     };
 
@@ -374,7 +386,8 @@ pub async fn handle_completions(
     let upstream = state.upstream.clone();
     let token_manager = state.token_manager;
     let pool_size = token_manager.len();
-    let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size.saturating_add(1)).max(2);
+    let retry_config = crate::proxy::config::get_retry_config();
+    let max_attempts = retry_config.attempts_for_pool(pool_size);
 
     let mut last_error = String::new();
     let mut last_email: Option<String> = None;
@@ -414,12 +427,10 @@ pub async fn handle_completions(
         {
             Ok(t) => t,
             Err(e) => {
-                return (
-                    StatusCode::SERVICE_UNAVAILABLE,
-                    [("X-Mapped-Model", mapped_model)],
-                    format!("Token error: {}", e),
+                return with_mapped_model(
+                    error_response(ErrorProtocol::OpenAI, StatusCode::SERVICE_UNAVAILABLE, format!("Token error: {}", e)),
+                    &mapped_model,
                 )
-                    .into_response()
             }
         };
 
@@ -478,13 +489,15 @@ pub async fn handle_completions(
                 use axum::response::Response;
                 use futures::StreamExt;
 
-                let gemini_stream = response.bytes_stream();
+                let ids_slot = UpstreamIdsSlot::default();
+                let gemini_stream =
+                    response_ids::capture_stream(Box::pin(response.bytes_stream()), ids_slot.clone());
 
                 if client_wants_stream {
                     let mut openai_stream = if is_codex_style {
                         use crate::proxy::mappers::openai::streaming::create_codex_sse_stream;
                         create_codex_sse_stream(
-                            Box::pin(gemini_stream),
+                            gemini_stream,
                             openai_req.model.clone(),
                             session_id_str.clone(),
                             openai_req.messages.len(),
@@ -492,7 +505,7 @@ pub async fn handle_completions(
                     } else {
                         use crate::proxy::mappers::openai::streaming::create_legacy_sse_stream;
                         create_legacy_sse_stream(
-                            Box::pin(gemini_stream),
+                            gemini_stream,
                             openai_req.model.clone(),
                             session_id_str.clone(),
                             openai_req.messages.len(),
@@ -504,7 +517,7 @@ pub async fn handle_completions(
 
                     loop {
                         match tokio::time::timeout(
-                            std::time::Duration::from_secs(60),
+                            retry_config.peek_timeout(),
                             openai_stream.next(),
                         )
                         .await
@@ -554,30 +567,29 @@ pub async fn handle_completions(
                     })
                     .chain(openai_stream);
 
-                    return Response::builder()
-                        .header("Content-Type", "text/event-stream")
-                        .header("Cache-Control", "no-cache")
-                        .header("Connection", "keep-alive")
-                        .header("X-Account-Email", &email)
-                        .header("X-Mapped-Model", &mapped_model)
-                        .body(Body::from_stream(combined_stream))
-                        .unwrap()
-                        .into_response();
+                    let response = finish_response(
+                        sse_response_builder().body(Body::from_stream(combined_stream)),
+                    );
+                    return response_ids::attach(
+                        with_account_headers(response, &email, Some(&mapped_model)),
+                        &ids_slot,
+                    );
                 } else {
                     use crate::proxy::mappers::openai::streaming::create_openai_sse_stream;
                     let mut openai_stream =
                         create_openai_sse_stream(
-                            Box::pin(gemini_stream),
+                            gemini_stream,
                             openai_req.model.clone(),
                             session_id_str.clone(),
                             openai_req.messages.len(),
+                            false,
                         );
 
                     let mut first_data_chunk = None;
                     let mut retry_this_account = false;
                     loop {
                         match tokio::time::timeout(
-                            std::time::Duration::from_secs(60),
+                            retry_config.peek_timeout(),
                             openai_stream.next(),
                         )
                         .await
@@ -627,8 +639,24 @@ pub async fn handle_completions(
                     .chain(openai_stream);
 
                     use crate::proxy::mappers::openai::collector::collect_stream_to_json;
+                    use crate::proxy::mappers::stream_completeness::TRUNCATED_HEADER;
                     match collect_stream_to_json(Box::pin(combined_stream)).await {
-                        Ok(chat_resp) => {
+                        Ok(collected) => {
+                            let completeness = collected.completeness;
+                            if !completeness.is_complete() {
+                                if !completeness.has_content {
+                                    tracing::warn!(
+                                        "[Codex] Stream ended before completion without content, retrying..."
+                                    );
+                                    last_error = "Truncated response stream (no content)".to_string();
+                                    continue;
+                                }
+                                tracing::warn!(
+                                    "[Codex] Stream ended before completion (confidence {:.2}), returning partial content as length",
+                                    completeness.confidence()
+                                );
+                            }
+                            let chat_resp = collected.response;
                             crate::proxy::SignatureCache::global()
                                 .delete_session_signature(&session_id_str);
                             let choices = chat_resp.choices.iter().map(|c| {
@@ -652,15 +680,18 @@ pub async fn handle_completions(
                                 "usage": chat_resp.usage
                             });
 
-                            return (
-                                StatusCode::OK,
-                                [
-                                    ("X-Account-Email", email.as_str()),
-                                    ("X-Mapped-Model", mapped_model.as_str()),
-                                ],
-                                Json(legacy_resp),
-                            )
-                                .into_response();
+                            let mut response = with_account_headers(
+                                (StatusCode::OK, Json(legacy_resp)),
+                                &email,
+                                Some(&mapped_model),
+                            );
+                            if !completeness.is_complete() {
+                                response.headers_mut().insert(
+                                    TRUNCATED_HEADER,
+                                    axum::http::HeaderValue::from_static("true"),
+                                );
+                            }
+                            return response_ids::attach(response, &ids_slot);
                         }
                         Err(e) => {
                             return (
@@ -676,12 +707,10 @@ pub async fn handle_completions(
             let gemini_resp: Value = match response.json().await {
                 Ok(json) => json,
                 Err(e) => {
-                    return (
-                        StatusCode::BAD_GATEWAY,
-                        [("X-Mapped-Model", mapped_model.as_str())],
-                        format!("Parse error: {}", e),
-                    )
-                        .into_response();
+                    return with_mapped_model(
+                        error_response(ErrorProtocol::OpenAI, StatusCode::BAD_GATEWAY, format!("Parse error: {}", e)),
+                        &mapped_model,
+                    );
                 }
             };
 
@@ -708,15 +737,10 @@ pub async fn handle_completions(
                 "usage": chat_resp.usage
             });
 
-            return (
-                StatusCode::OK,
-                [
-                    ("X-Account-Email", email.as_str()),
-                    ("X-Mapped-Model", mapped_model.as_str()),
-                ],
-                Json(legacy_resp),
-            )
-                .into_response();
+            return response_ids::attach(
+                with_account_headers((StatusCode::OK, Json(legacy_resp)), &email, Some(&mapped_model)),
+                &UpstreamIds::from_json(&gemini_resp).into(),
+            );
         }
 
         // Handle errors and retry
@@ -730,7 +754,11 @@ pub async fn handle_completions(
             .text()
             .await
             .unwrap_or_else(|_| format!("HTTP {}", status_code));
-        last_error = format!("HTTP {}: {}", status_code, error_text);
+        last_error = format!(
+            "HTTP {}: {}",
+            status_code,
+            crate::proxy::common::redact::sanitize_upstream_error(&error_text, Some(&project_id))
+        );
 
         tracing::error!(
             "[Codex-Upstream] Error Response {}: {}",
@@ -738,49 +766,38 @@ pub async fn handle_completions(
             error_text
         );
 
-        if status_code == 429 || status_code == 529 || status_code == 503 || status_code == 500 {
-            token_manager
-                .mark_rate_limited_async(
-                    &email,
-                    status_code,
-                    retry_after.as_deref(),
-                    &error_text,
-                    Some(&mapped_model),
-                )
-                .await;
-        }
+        token_manager
+            .mark_rate_limited_async(
+                &email,
+                status_code,
+                retry_after.as_deref(),
+                &error_text,
+                Some(&mapped_model),
+            )
+            .await;
 
         let strategy = determine_retry_strategy(status_code, &error_text, false);
 
-        if apply_retry_strategy(strategy, attempt, max_attempts, status_code, &trace_id).await {
+        if apply_retry_strategy(strategy, attempt, max_attempts, status_code, &trace_id, &retry_config).await {
             continue;
         } else {
-            return (
-                status,
-                [
-                    ("X-Account-Email", email.as_str()),
-                    ("X-Mapped-Model", mapped_model.as_str()),
-                ],
-                error_text,
-            )
-                .into_response();
+            return with_account_headers(
+                (status, crate::proxy::common::redact::sanitize_upstream_error(&error_text, Some(&project_id))),
+                &email,
+                Some(&mapped_model),
+            );
         }
     }
 
     // All attempts failed
+    let exhausted = error_response(
+        ErrorProtocol::OpenAI,
+        StatusCode::TOO_MANY_REQUESTS,
+        format!("All accounts exhausted. Last error: {}", crate::proxy::common::redact::sanitize_upstream_error(&last_error, None)),
+    );
     if let Some(email) = last_email {
-        (
-            StatusCode::TOO_MANY_REQUESTS,
-            [("X-Account-Email", email), ("X-Mapped-Model", mapped_model)],
-            format!("All accounts exhausted. Last error: {}", last_error),
-        )
-            .into_response()
+        with_account_headers(exhausted, &email, Some(&mapped_model))
     } else {
-        (
-            StatusCode::TOO_MANY_REQUESTS,
-            [("X-Mapped-Model", mapped_model)],
-            format!("All accounts exhausted. Last error: {}", last_error),
-        )
-            .into_response()
+        with_mapped_model(exhausted, &mapped_model)
     }
 }
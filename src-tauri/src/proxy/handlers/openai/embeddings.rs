@@ -0,0 +1,417 @@
+// OpenAI Embeddings Handler
+// POST /v1/embeddings - 由 Gemini batchEmbedContents 提供
+
+use std::ops::Range;
+
+use axum::{
+    extract::{Json, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use base64::Engine as _;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::proxy::common::model_mapping::{match_custom_mapping, resolve_model_route};
+use crate::proxy::mappers::context_manager::estimate_tokens_from_str;
+use crate::proxy::server::AppState;
+use crate::proxy::upstream::client::UpstreamClient;
+use super::super::common::{with_account_headers, with_rotating_account, AttemptError, LeaseSource};
+
+/// 每批换号重试的最大尝试次数
+const MAX_ATTEMPTS: usize = 3;
+/// 单次 batchEmbedContents 调用允许的最大条数
+const MAX_BATCH_SIZE: usize = 100;
+/// OpenAI 嵌入模型名的默认映射, 只在本端点生效 (不进入全局路由表, 其他协议不会路由到嵌入模型)
+const OPENAI_EMBEDDING_MODELS: &[(&str, &str)] = &[
+    ("text-embedding-3-small", "gemini-embedding-001"),
+    ("text-embedding-3-large", "gemini-embedding-001"),
+    ("text-embedding-ada-002", "gemini-embedding-001"),
+];
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum EmbeddingInput {
+    Single(String),
+    Batch(Vec<String>),
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingsRequest {
+    model: String,
+    input: EmbeddingInput,
+    /// float (默认) / base64
+    #[serde(default)]
+    encoding_format: Option<String>,
+    /// 输出维度 (映射为 outputDimensionality)
+    #[serde(default)]
+    dimensions: Option<u32>,
+}
+
+/// 一批输入的结果 (range 为该批在原始 input 中的下标)
+struct BatchOutcome {
+    range: Range<usize>,
+    result: Result<Vec<Vec<f32>>, String>,
+    email: Option<String>,
+}
+
+fn error_response(status: StatusCode, message: impl Into<String>) -> Response {
+    (
+        status,
+        Json(json!({
+            "error": {
+                "message": message.into(),
+                "type": "invalid_request_error",
+                "code": status.as_u16()
+            }
+        })),
+    )
+        .into_response()
+}
+
+/// 解析上游 embeddings (兼容 v1internal 的 response 包装), 条数必须与请求一致
+fn parse_embeddings(resp: &Value, expected: usize) -> Result<Vec<Vec<f32>>, String> {
+    let inner = resp.get("response").unwrap_or(resp);
+    let embeddings = inner
+        .get("embeddings")
+        .and_then(|e| e.as_array())
+        .ok_or_else(|| "Upstream response has no embeddings".to_string())?;
+    if embeddings.len() != expected {
+        return Err(format!(
+            "Upstream returned {} embeddings for {} inputs",
+            embeddings.len(),
+            expected
+        ));
+    }
+    embeddings
+        .iter()
+        .map(|e| {
+            e.get("values")
+                .and_then(|v| v.as_array())
+                .map(|values| values.iter().filter_map(|v| v.as_f64()).map(|v| v as f32).collect())
+                .ok_or_else(|| "Upstream embedding has no values".to_string())
+        })
+        .collect()
+}
+
+/// 按上游单次上限分批调用, 每批独立换号重试; 返回各批结果 (按输入顺序)
+async fn embed_inputs(
+    source: &dyn LeaseSource,
+    upstream: &UpstreamClient,
+    model: &str,
+    inputs: &[String],
+    dimensions: Option<u32>,
+) -> Vec<BatchOutcome> {
+    let mut outcomes = Vec::new();
+    for start in (0..inputs.len()).step_by(MAX_BATCH_SIZE) {
+        let range = start..(start + MAX_BATCH_SIZE).min(inputs.len());
+        let requests: Vec<Value> = inputs[range.clone()]
+            .iter()
+            .map(|text| {
+                let mut request = json!({
+                    "model": format!("models/{}", model),
+                    "content": { "parts": [{ "text": text }] }
+                });
+                if let Some(dimensions) = dimensions {
+                    request["outputDimensionality"] = json!(dimensions);
+                }
+                request
+            })
+            .collect();
+        let expected = requests.len();
+        let request_body = json!({ "requests": requests });
+
+        let rotated = with_rotating_account(source, "embed", model, MAX_ATTEMPTS, |lease| {
+            let request_body = request_body.clone();
+            async move {
+                let wrapped_body = json!({
+                    "project": lease.project_id,
+                    "requestId": format!("embed-{}", Uuid::new_v4()),
+                    "request": request_body,
+                    "model": model,
+                    "userAgent": "antigravity",
+                    "requestType": "embed"
+                });
+                let response = upstream
+                    .call_v1_internal("batchEmbedContents", &lease.access_token, wrapped_body, None)
+                    .await
+                    .map_err(|e| AttemptError::local(502, format!("Upstream request failed: {}", e)))?;
+
+                let status = response.status().as_u16();
+                if !response.status().is_success() {
+                    let error_text = response.text().await.unwrap_or_else(|_| format!("HTTP {}", status));
+                    let message = crate::proxy::common::redact::sanitize_upstream_error(
+                        &error_text,
+                        Some(&lease.project_id),
+                    );
+                    return Err(AttemptError::upstream(status, error_text, message));
+                }
+
+                let resp = response
+                    .json::<Value>()
+                    .await
+                    .map_err(|e| AttemptError::local(502, format!("Parse error: {}", e)))?;
+                parse_embeddings(&resp, expected).map_err(|e| AttemptError::local(502, e))
+            }
+        })
+        .await;
+
+        outcomes.push(match rotated {
+            Ok(rotated) => BatchOutcome {
+                range,
+                result: Ok(rotated.value),
+                email: Some(rotated.email),
+            },
+            Err(failure) => {
+                warn!(
+                    "[Embeddings] Batch {}..{} failed after {} attempt(s): {}",
+                    range.start, range.end, failure.attempts, failure.message
+                );
+                BatchOutcome {
+                    range,
+                    result: Err(failure.message),
+                    email: failure.email,
+                }
+            }
+        });
+    }
+    outcomes
+}
+
+/// 自定义映射优先, 其次是 OpenAI 嵌入模型名的默认映射, 最后走通用模型路由
+fn resolve_embedding_model(model: &str, custom_mapping: &std::collections::HashMap<String, String>) -> String {
+    if match_custom_mapping(model, custom_mapping).is_none() {
+        if let Some((_, target)) = OPENAI_EMBEDDING_MODELS.iter().find(|(alias, _)| *alias == model) {
+            return target.to_string();
+        }
+    }
+    resolve_model_route(model, custom_mapping)
+}
+
+fn encode_embedding(values: Vec<f32>, base64: bool) -> Value {
+    if base64 {
+        let bytes: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        json!(base64::engine::general_purpose::STANDARD.encode(bytes))
+    } else {
+        json!(values)
+    }
+}
+
+/// OpenAI Embeddings API: POST /v1/embeddings
+pub async fn handle_embeddings(State(state): State<AppState>, Json(body): Json<Value>) -> Response {
+    let request: EmbeddingsRequest = match serde_json::from_value(body) {
+        Ok(request) => request,
+        Err(e) => {
+            return error_response(
+                StatusCode::BAD_REQUEST,
+                format!("Invalid request body (input must be a string or an array of strings): {}", e),
+            )
+        }
+    };
+    let inputs = match request.input {
+        EmbeddingInput::Single(text) => vec![text],
+        EmbeddingInput::Batch(texts) => texts,
+    };
+    if inputs.is_empty() || inputs.iter().any(|text| text.is_empty()) {
+        return error_response(StatusCode::BAD_REQUEST, "input must not be empty");
+    }
+    let base64 = match request.encoding_format.as_deref() {
+        None | Some("float") => false,
+        Some("base64") => true,
+        Some(other) => {
+            return error_response(StatusCode::BAD_REQUEST, format!("Unsupported encoding_format: {}", other))
+        }
+    };
+
+    let mapped_model = resolve_embedding_model(&request.model, &*state.custom_mapping.read().await);
+    if !mapped_model.contains("embedding") {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Model {} resolves to {}, which is not an embedding model",
+                request.model, mapped_model
+            ),
+        );
+    }
+    info!(
+        "[Embeddings] {} input(s), model {} -> {}",
+        inputs.len(),
+        request.model,
+        mapped_model
+    );
+
+    let outcomes = embed_inputs(
+        state.token_manager.as_ref(),
+        &state.upstream,
+        &mapped_model,
+        &inputs,
+        request.dimensions,
+    )
+    .await;
+    let email = outcomes
+        .iter()
+        .find_map(|o| o.email.clone())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    // 任一批失败: 返回 502 并列出失败的输入下标
+    let failed: Vec<&BatchOutcome> = outcomes.iter().filter(|o| o.result.is_err()).collect();
+    if !failed.is_empty() {
+        let failed_indices: Vec<usize> = failed.iter().flat_map(|o| o.range.clone()).collect();
+        let errors: Vec<&str> = failed
+            .iter()
+            .filter_map(|o| o.result.as_ref().err().map(String::as_str))
+            .collect();
+        let body = json!({
+            "error": {
+                "message": format!(
+                    "{} of {} inputs failed to embed: {}",
+                    failed_indices.len(),
+                    inputs.len(),
+                    errors.join("; ")
+                ),
+                "type": "upstream_error",
+                "code": 502,
+                "failed_indices": failed_indices
+            }
+        });
+        return with_account_headers((StatusCode::BAD_GATEWAY, Json(body)), &email, Some(&mapped_model));
+    }
+
+    let data: Vec<Value> = outcomes
+        .into_iter()
+        .flat_map(|o| o.range.zip(o.result.unwrap_or_default()))
+        .map(|(index, values)| {
+            json!({
+                "object": "embedding",
+                "index": index,
+                "embedding": encode_embedding(values, base64)
+            })
+        })
+        .collect();
+    let prompt_tokens: u32 = inputs.iter().map(|text| estimate_tokens_from_str(text)).sum();
+
+    with_account_headers(
+        (
+            StatusCode::OK,
+            Json(json!({
+                "object": "list",
+                "data": data,
+                "model": request.model,
+                "usage": { "prompt_tokens": prompt_tokens, "total_tokens": prompt_tokens }
+            })),
+        ),
+        &email,
+        Some(&mapped_model),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::token_manager::TokenLease;
+    use crate::proxy::upstream::transport::{UpstreamCall, UpstreamTransport};
+    use futures::future::BoxFuture;
+    use std::sync::{Arc, Mutex};
+
+    /// 总是发放同一账号
+    struct SingleAccount;
+
+    impl LeaseSource for SingleAccount {
+        fn lease<'a>(&'a self, _: &'a str, _: bool, _: &'a str) -> BoxFuture<'a, Result<TokenLease, String>> {
+            Box::pin(async {
+                Ok(TokenLease {
+                    access_token: "token".to_string(),
+                    project_id: "project".to_string(),
+                    email: "acc@example.com".to_string(),
+                    account_id: "acc".to_string(),
+                    active_requests: Default::default(),
+                })
+            })
+        }
+
+        fn report_failure<'a>(&'a self, _: &'a str, _: &'a str, _: u16, _: &'a str, _: &'a str) -> BoxFuture<'a, ()> {
+            Box::pin(async {})
+        }
+    }
+
+    /// 每条输入返回 [输入长度]; 含 "bad" 的批次返回 400
+    struct EmbedUpstream {
+        batch_sizes: Mutex<Vec<usize>>,
+    }
+
+    impl UpstreamTransport for EmbedUpstream {
+        fn send(&self, call: UpstreamCall) -> BoxFuture<'_, Result<reqwest::Response, String>> {
+            Box::pin(async move {
+                let requests = call.body["request"]["requests"].as_array().cloned().unwrap_or_default();
+                self.batch_sizes.lock().unwrap().push(requests.len());
+                let texts: Vec<&str> = requests
+                    .iter()
+                    .filter_map(|r| r["content"]["parts"][0]["text"].as_str())
+                    .collect();
+                let (status, body) = if texts.iter().any(|t| t.contains("bad")) {
+                    (400, json!({ "error": { "message": "INVALID_ARGUMENT" } }))
+                } else {
+                    let embeddings: Vec<Value> = texts.iter().map(|t| json!({ "values": [t.len() as f32] })).collect();
+                    (200, json!({ "response": { "embeddings": embeddings } }))
+                };
+                axum::http::Response::builder()
+                    .status(status)
+                    .body(reqwest::Body::from(body.to_string()))
+                    .map(reqwest::Response::from)
+                    .map_err(|e| e.to_string())
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batches_preserve_order_and_report_failed_indices() {
+        let transport = Arc::new(EmbedUpstream { batch_sizes: Mutex::new(Vec::new()) });
+        let upstream = UpstreamClient::with_transport(transport.clone());
+        let mut inputs: Vec<String> = (0..250).map(|i| "x".repeat(i % 7 + 1)).collect();
+
+        let outcomes = embed_inputs(&SingleAccount, &upstream, "gemini-embedding-001", &inputs, None).await;
+        assert_eq!(*transport.batch_sizes.lock().unwrap(), vec![100, 100, 50]);
+        let values: Vec<f32> = outcomes
+            .into_iter()
+            .flat_map(|o| o.result.unwrap())
+            .map(|v| v[0])
+            .collect();
+        let expected: Vec<f32> = inputs.iter().map(|t| t.len() as f32).collect();
+        assert_eq!(values, expected);
+
+        // 第二批失败: 只有 100..200 被标记为失败
+        inputs[150] = "bad input".to_string();
+        let outcomes = embed_inputs(&SingleAccount, &upstream, "gemini-embedding-001", &inputs, None).await;
+        let failed: Vec<Range<usize>> = outcomes
+            .iter()
+            .filter(|o| o.result.is_err())
+            .map(|o| o.range.clone())
+            .collect();
+        assert_eq!(failed, vec![100..200]);
+    }
+
+    #[test]
+    fn test_openai_embedding_models_resolve_locally() {
+        let mut mapping = std::collections::HashMap::new();
+        assert_eq!(resolve_embedding_model("text-embedding-3-small", &mapping), "gemini-embedding-001");
+        assert_eq!(resolve_embedding_model("gemini-embedding-001", &mapping), "gemini-embedding-001");
+        // 全局路由表不含嵌入模型名
+        assert_ne!(resolve_model_route("text-embedding-3-small", &mapping), "gemini-embedding-001");
+
+        mapping.insert("text-embedding-*".to_string(), "text-embedding-004".to_string());
+        assert_eq!(resolve_embedding_model("text-embedding-3-small", &mapping), "text-embedding-004");
+    }
+
+    #[test]
+    fn test_parse_embeddings_requires_matching_count() {
+        let resp = json!({ "embeddings": [{ "values": [0.5, -1.0] }] });
+        assert_eq!(parse_embeddings(&resp, 1).unwrap(), vec![vec![0.5, -1.0]]);
+        assert!(parse_embeddings(&resp, 2).is_err());
+        assert_eq!(
+            encode_embedding(vec![1.0], true),
+            json!(base64::engine::general_purpose::STANDARD.encode(1.0f32.to_le_bytes()))
+        );
+    }
+}
@@ -3,8 +3,8 @@
 // POST /v1/images/edits - Image editing
 
 use axum::{
-    extract::{Json, State},
-    http::StatusCode,
+    extract::{Extension, Json, Path, State},
+    http::{header, HeaderMap, StatusCode},
     response::IntoResponse,
 };
 use base64::Engine as _;
@@ -12,12 +12,104 @@ use serde_json::{json, Value};
 use tracing::{info, warn};
 use tokio::time::Duration;
 
-use crate::proxy::server::AppState;
-use super::super::common::{apply_retry_strategy, determine_retry_strategy, RetryStrategy};
+use crate::proxy::debug_logger;
+use crate::proxy::mappers::common_utils::{
+    parse_image_config_with_params, resolve_image_size, ImageSizeResolution,
+};
+use crate::proxy::server::{AppState, LocalAddr};
+use super::super::common::{
+    apply_retry_strategy, determine_retry_strategy, set_header_lossy, with_account_headers,
+    AccountRotations, RetryStrategy,
+};
 
 const MAX_IMAGE_RETRY_ATTEMPTS: usize = 3;
+/// 请求尺寸被吸附到支持尺寸时返回的告警头
+const SIZE_WARNING_HEADER: &str = "X-Image-Size-Warning";
+/// 部分任务被安全策略拒绝时列出被拒任务的告警头
+const REFUSALS_HEADER: &str = "X-Image-Refusals";
+/// 视为安全拒绝的 finishReason / promptFeedback.blockReason
+const REFUSAL_REASONS: &[&str] = &[
+    "SAFETY",
+    "IMAGE_SAFETY",
+    "PROHIBITED_CONTENT",
+    "IMAGE_PROHIBITED_CONTENT",
+    "BLOCKLIST",
+    "SPII",
+];
+/// 告警头中每条拒绝说明的最大字符数
+const MAX_REFUSAL_CHARS: usize = 200;
+
+/// 构建 imageConfig, 并把请求尺寸约束到模型支持的尺寸表 (generations 与 edits 共用)
+/// strict 为 true 时不支持的尺寸返回 400, 否则吸附到最接近的宽高比
+fn resolve_image_config(
+    model: &str,
+    size: &str,
+    quality: Option<&str>,
+    strict: bool,
+) -> Result<(Value, ImageSizeResolution), (StatusCode, String)> {
+    let (mut image_config, _) = parse_image_config_with_params(model, Some(size), quality);
+    let image_size = image_config
+        .get("imageSize")
+        .and_then(|v| v.as_str())
+        .unwrap_or("1K")
+        .to_string();
+    let resolution = resolve_image_size(model, size, &image_size, strict)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    if let Some(warning) = resolution.warning() {
+        warn!("[Images] Unsupported size snapped: {}", warning);
+    }
+    image_config["aspectRatio"] = json!(resolution.aspect_ratio);
+    Ok((image_config, resolution))
+}
+
+fn parse_strict_flag(value: &str) -> bool {
+    matches!(value.trim().to_lowercase().as_str(), "true" | "1")
+}
+
+/// 取图片链接的根地址: 接受本次连接的监听地址 (主端口或额外监听端口);
+/// 允许局域网访问时优先使用客户端请求时的 Host (可能经由反向代理或其他主机名访问)
+async fn image_url_base(state: &AppState, headers: &HeaderMap, local_addr: Option<LocalAddr>) -> String {
+    let allow_lan_access = state.security.read().await.allow_lan_access;
+    if allow_lan_access {
+        if let Some(host) = headers
+            .get(header::HOST)
+            .and_then(|v| v.to_str().ok())
+            .map(str::trim)
+            .filter(|h| !h.is_empty())
+        {
+            return format!("http://{}", host);
+        }
+    }
+    match local_addr {
+        Some(LocalAddr(addr)) => format!("http://{}", addr),
+        None => format!("http://127.0.0.1:{}", state.port),
+    }
+}
 
-fn extract_images_from_response(gemini_resp: &Value, response_format: &str) -> Vec<Value> {
+/// response_format=url 时把图片写入 image_store 并返回可访问的链接;
+/// 数据无法解码或图片超过存储上限时退回 data URI
+fn image_url(url_base: &str, mime_type: &str, data: &str) -> String {
+    match base64::engine::general_purpose::STANDARD.decode(data) {
+        Ok(bytes) => match crate::proxy::image_store::put(bytes, mime_type) {
+            Some(id) => format!("{}{}", url_base, crate::proxy::image_store::content_path(&id)),
+            None => {
+                warn!("[Images] Image exceeds the image store limit, returning data URI");
+                format!("data:{};base64,{}", mime_type, data)
+            }
+        },
+        Err(e) => {
+            warn!("[Images] Failed to decode image data, returning data URI: {}", e);
+            format!("data:{};base64,{}", mime_type, data)
+        }
+    }
+}
+
+/// `url_base` 为 Some 时按 response_format=url 返回链接, 否则返回 b64_json
+fn extract_images_from_response(
+    gemini_resp: &Value,
+    url_base: Option<&str>,
+    applied_size: &str,
+) -> Vec<Value> {
     let raw = gemini_resp.get("response").unwrap_or(gemini_resp);
     let mut images: Vec<Value> = Vec::new();
 
@@ -35,17 +127,19 @@ fn extract_images_from_response(gemini_resp: &Value, response_format: &str) -> V
                     continue;
                 }
 
-                if response_format == "url" {
+                if let Some(url_base) = url_base {
                     let mime_type = img
                         .get("mimeType")
                         .and_then(|v| v.as_str())
                         .unwrap_or("image/png");
                     images.push(json!({
-                        "url": format!("data:{};base64,{}", mime_type, data)
+                        "url": image_url(url_base, mime_type, data),
+                        "size": applied_size
                     }));
                 } else {
                     images.push(json!({
-                        "b64_json": data
+                        "b64_json": data,
+                        "size": applied_size
                     }));
                 }
             }
@@ -55,16 +149,121 @@ fn extract_images_from_response(gemini_resp: &Value, response_format: &str) -> V
     images
 }
 
+/// 没有图片时判断上游是否拒绝了提示词 (安全 finishReason / blockReason, 或只返回了文本), 返回说明
+fn detect_refusal(gemini_resp: &Value) -> Option<String> {
+    let raw = gemini_resp.get("response").unwrap_or(gemini_resp);
+    let candidate = raw.get("candidates").and_then(|c| c.get(0));
+
+    let blocked_reason = raw
+        .pointer("/promptFeedback/blockReason")
+        .and_then(|v| v.as_str())
+        .or_else(|| {
+            candidate
+                .and_then(|c| c.get("finishReason"))
+                .and_then(|v| v.as_str())
+                .filter(|reason| REFUSAL_REASONS.contains(reason))
+        });
+
+    let text = candidate
+        .and_then(|c| c.pointer("/content/parts"))
+        .and_then(|p| p.as_array())
+        .map(|parts| {
+            parts
+                .iter()
+                .filter(|part| !part.get("thought").and_then(|v| v.as_bool()).unwrap_or(false))
+                .filter_map(|part| part.get("text").and_then(|v| v.as_str()))
+                .collect::<Vec<_>>()
+                .join("")
+                .trim()
+                .to_string()
+        })
+        .unwrap_or_default();
+
+    match (blocked_reason, text.is_empty()) {
+        (_, false) => Some(text),
+        (Some(reason), true) => Some(format!("Image generation was blocked by the safety filter ({})", reason)),
+        (None, true) => None,
+    }
+}
+
+/// 被拒任务的摘要, 例如 "task 1: ...; task 3: ..."
+fn refusal_summary(refusals: &[(usize, String)]) -> String {
+    refusals
+        .iter()
+        .map(|(idx, reason)| {
+            let mut reason: String = reason.split_whitespace().collect::<Vec<_>>().join(" ");
+            if reason.chars().count() > MAX_REFUSAL_CHARS {
+                reason = reason.chars().take(MAX_REFUSAL_CHARS).collect::<String>() + "...";
+            }
+            format!("task {}: {}", idx, reason)
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// 全部任务被拒时返回 OpenAI 风格的 400 content_policy_violation
+fn content_policy_response(refusals: &[(usize, String)]) -> (StatusCode, Json<Value>) {
+    let message = match refusals {
+        [(_, reason)] => reason.clone(),
+        _ => refusal_summary(refusals),
+    };
+    (
+        StatusCode::BAD_REQUEST,
+        Json(json!({
+            "error": {
+                "message": message,
+                "type": "invalid_request_error",
+                "param": "prompt",
+                "code": "content_policy_violation"
+            }
+        })),
+    )
+}
+
+/// 多个图片任务共享的账号轨迹: 最后使用的账号与每次换号记录
+#[derive(Debug, Default)]
+struct ImageAccountTrace {
+    last_email: Option<String>,
+    rotations: Vec<String>,
+}
+
+impl ImageAccountTrace {
+    fn record_rotation(&mut self, task: usize, attempt: usize, email: &str, reason: &str) {
+        self.rotations.push(format!(
+            "task {} attempt {}: {} -> rotate ({})",
+            task,
+            attempt + 1,
+            email,
+            reason
+        ));
+    }
+
+    /// 附加 X-Account-Email (最后使用的账号) 与换号记录扩展
+    fn attach(self, response: impl IntoResponse) -> axum::response::Response {
+        let email = self.last_email.unwrap_or_else(|| "unknown".to_string());
+        let mut response = with_account_headers(response, &email, None);
+        if !self.rotations.is_empty() {
+            response.extensions_mut().insert(AccountRotations(self.rotations));
+        }
+        response
+    }
+}
+
 async fn execute_image_request_with_retry(
     state: &AppState,
     request_body: &Value,
     mapped_model: &str,
     trace_id: &str,
-) -> Result<(Value, String), String> {
+    task: usize,
+    trace: &mut ImageAccountTrace,
+) -> Result<Value, String> {
     let token_manager = state.token_manager.clone();
     let upstream = state.upstream.clone();
     let pool_size = token_manager.len();
-    let max_attempts = MAX_IMAGE_RETRY_ATTEMPTS.min(pool_size.saturating_add(1)).max(2);
+    let retry_config = crate::proxy::config::get_retry_config();
+    let max_attempts = retry_config
+        .attempts_for_pool(pool_size)
+        .min(MAX_IMAGE_RETRY_ATTEMPTS);
     let mut last_error = String::new();
 
     for attempt in 0..max_attempts {
@@ -76,10 +275,11 @@ async fn execute_image_request_with_retry(
         let email = token_lease.email.clone();
         let access_token = token_lease.access_token.clone();
         let project_id = token_lease.project_id.clone();
+        trace.last_email = Some(email.clone());
 
         let mut effective_body = request_body.clone();
         if let Some(obj) = effective_body.as_object_mut() {
-            obj.insert("project".to_string(), Value::String(project_id));
+            obj.insert("project".to_string(), Value::String(project_id.clone()));
         }
 
         let response = match upstream
@@ -88,7 +288,7 @@ async fn execute_image_request_with_retry(
         {
             Ok(response) => response,
             Err(e) => {
-                last_error = format!("Network error: {}", e);
+                last_error = format!("Network error: {}", crate::proxy::common::redact::sanitize_upstream_error(&e, None));
                 if attempt + 1 < max_attempts {
                     warn!(
                         "[Images] Request failed on attempt {}/{}: {}",
@@ -96,6 +296,7 @@ async fn execute_image_request_with_retry(
                         max_attempts,
                         e
                     );
+                    trace.record_rotation(task, attempt, &email, "network error");
                     continue;
                 }
                 return Err(last_error);
@@ -109,7 +310,7 @@ async fn execute_image_request_with_retry(
                 .await
                 .map_err(|e| format!("Parse error: {}", e))?;
             token_manager.mark_account_success(&email, Some(mapped_model));
-            return Ok((gemini_resp, email));
+            return Ok(gemini_resp);
         }
 
         let status_code = status.as_u16();
@@ -122,19 +323,21 @@ async fn execute_image_request_with_retry(
             .text()
             .await
             .unwrap_or_else(|_| format!("HTTP {}", status_code));
-        last_error = format!("Upstream error {}: {}", status_code, error_text);
+        last_error = format!(
+            "Upstream error {}: {}",
+            status_code,
+            crate::proxy::common::redact::sanitize_upstream_error(&error_text, Some(&project_id))
+        );
 
-        if matches!(status_code, 429 | 529 | 503 | 500) {
-            token_manager
-                .mark_rate_limited_async(
-                    &email,
-                    status_code,
-                    retry_after.as_deref(),
-                    &error_text,
-                    Some(mapped_model),
-                )
-                .await;
-        }
+        token_manager
+            .mark_rate_limited_async(
+                &email,
+                status_code,
+                retry_after.as_deref(),
+                &error_text,
+                Some(mapped_model),
+            )
+            .await;
 
         if status_code == 401 || status_code == 403 {
             if attempt + 1 < max_attempts {
@@ -144,8 +347,10 @@ async fn execute_image_request_with_retry(
                     max_attempts,
                     status_code,
                     trace_id,
+                    &retry_config,
                 )
                 .await;
+                trace.record_rotation(task, attempt, &email, &status_code.to_string());
                 continue;
             }
             return Err(last_error);
@@ -153,8 +358,9 @@ async fn execute_image_request_with_retry(
 
         let strategy = determine_retry_strategy(status_code, &error_text, false);
         if attempt + 1 < max_attempts
-            && apply_retry_strategy(strategy, attempt, max_attempts, status_code, trace_id).await
+            && apply_retry_strategy(strategy, attempt, max_attempts, status_code, trace_id, &retry_config).await
         {
+            trace.record_rotation(task, attempt, &email, &status_code.to_string());
             continue;
         }
 
@@ -164,10 +370,37 @@ async fn execute_image_request_with_retry(
     Err(format!("All image attempts failed: {}", last_error))
 }
 
+/// 调试日志: 记录原始请求与最终生效的 imageConfig / 提示词
+async fn write_image_debug_payload(
+    state: &AppState,
+    trace_id: &str,
+    endpoint: &str,
+    request: &Value,
+    image_config: &Value,
+    final_prompt: &str,
+) {
+    let debug_cfg = state.debug_logging.read().await.clone();
+    if !debug_logger::is_enabled(&debug_cfg) {
+        return;
+    }
+    let payload = json!({
+        "kind": "image_request",
+        "protocol": "openai",
+        "endpoint": endpoint,
+        "trace_id": trace_id,
+        "request": request,
+        "effective_image_config": image_config,
+        "final_prompt": final_prompt,
+    });
+    debug_logger::write_debug_payload(&debug_cfg, Some(trace_id), "image_request", &payload).await;
+}
+
 /// OpenAI Images API: POST /v1/images/generations
 /// Handles image generation requests, converting to Gemini API format
 pub async fn handle_images_generations(
     State(state): State<AppState>,
+    headers: HeaderMap,
+    local_addr: Option<Extension<LocalAddr>>,
     Json(body): Json<Value>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
     // 1. Parse request parameters
@@ -201,23 +434,40 @@ pub async fn handle_images_generations(
         .get("style")
         .and_then(|v| v.as_str())
         .unwrap_or("vivid");
+    // 显式 aspect_ratio 优先于 size 推导的比例
+    let aspect_ratio = match body.get("aspect_ratio").and_then(|v| v.as_str()) {
+        Some(r) => Some(
+            crate::proxy::mappers::common_utils::validate_aspect_ratio(r)
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))?,
+        ),
+        None => None,
+    };
+    let negative_prompt = body.get("negative_prompt").and_then(|v| v.as_str());
+    let strict_size = match body.get("strict_size") {
+        Some(Value::Bool(b)) => *b,
+        Some(Value::String(s)) => parse_strict_flag(s),
+        _ => false,
+    };
 
     info!(
-        "[Images] Received request: model={}, prompt={:.50}..., n={}, size={}, quality={}, style={}",
+        "[Images] Received request: model={}, prompt={:.50}..., n={}, size={}, aspect_ratio={:?}, quality={}, style={}, negative_prompt={}",
         model,
         prompt,
         n,
         size,
+        aspect_ratio,
         quality,
-        style
+        style,
+        negative_prompt.is_some()
     );
 
     // 2. Parse image config using common_utils
-    let (image_config, _) = crate::proxy::mappers::common_utils::parse_image_config_with_params(
+    let (image_config, size_resolution) = resolve_image_config(
         model,
-        Some(size),
+        aspect_ratio.unwrap_or(size),
         Some(quality),
-    );
+        strict_size,
+    )?;
 
     // 3. Prompt Enhancement
     let mut final_prompt = prompt.to_string();
@@ -229,12 +479,22 @@ pub async fn handle_images_generations(
         "natural" => final_prompt.push_str(", (natural lighting, realistic, photorealistic)"),
         _ => {}
     }
+    let final_prompt =
+        crate::proxy::mappers::common_utils::apply_negative_prompt(&final_prompt, negative_prompt);
 
     let trace_id = format!("img_gen_{}", chrono::Utc::now().timestamp_subsec_millis());
+    write_image_debug_payload(&state, &trace_id, "generations", &body, &image_config, &final_prompt)
+        .await;
 
     let mut images: Vec<Value> = Vec::new();
     let mut errors: Vec<String> = Vec::new();
-    let mut response_email: Option<String> = None;
+    let mut refusals: Vec<(usize, String)> = Vec::new();
+    let mut trace = ImageAccountTrace::default();
+    let url_base = if response_format == "url" {
+        Some(image_url_base(&state, &headers, local_addr.map(|Extension(addr)| addr)).await)
+    } else {
+        None
+    };
 
     for idx in 0..n {
         let gemini_body = json!({
@@ -262,16 +522,22 @@ pub async fn handle_images_generations(
             }
         });
 
-        match execute_image_request_with_retry(&state, &gemini_body, "dall-e-3", &trace_id).await {
-            Ok((gemini_resp, email)) => {
-                if response_email.is_none() {
-                    response_email = Some(email);
-                }
-                let extracted = extract_images_from_response(&gemini_resp, response_format);
-                if extracted.is_empty() {
-                    errors.push(format!("Task {}: No images generated", idx));
-                } else {
+        match execute_image_request_with_retry(&state, &gemini_body, "dall-e-3", &trace_id, idx, &mut trace)
+            .await
+        {
+            Ok(gemini_resp) => {
+                let extracted = extract_images_from_response(
+                    &gemini_resp,
+                    url_base.as_deref(),
+                    &size_resolution.applied,
+                );
+                if !extracted.is_empty() {
                     images.extend(extracted);
+                } else if let Some(reason) = detect_refusal(&gemini_resp) {
+                    warn!("[Images] Task {} refused by upstream: {}", idx, reason);
+                    refusals.push((idx, reason));
+                } else {
+                    errors.push(format!("Task {}: No images generated", idx));
                 }
             }
             Err(e) => {
@@ -281,6 +547,15 @@ pub async fn handle_images_generations(
         }
     }
 
+    if images.is_empty() && errors.is_empty() && !refusals.is_empty() {
+        tracing::warn!("[Images] All {} requests refused: {}", n, refusal_summary(&refusals));
+        return Ok(trace.attach(content_policy_response(&refusals)));
+    }
+
+    if !refusals.is_empty() {
+        errors.push(format!("Refused: {}", refusal_summary(&refusals)));
+    }
+
     if images.is_empty() {
         let error_msg = if errors.is_empty() {
             "No images generated".to_string()
@@ -288,7 +563,10 @@ pub async fn handle_images_generations(
             errors.join("; ")
         };
         tracing::error!("[Images] All {} requests failed. Errors: {}", n, error_msg);
-        return Err((StatusCode::BAD_GATEWAY, error_msg));
+        if trace.last_email.is_none() {
+            return Err((StatusCode::BAD_GATEWAY, error_msg));
+        }
+        return Ok(trace.attach((StatusCode::BAD_GATEWAY, error_msg)));
     }
 
     if !errors.is_empty() {
@@ -312,20 +590,25 @@ pub async fn handle_images_generations(
         "data": images
     });
 
-    let response_email = response_email.unwrap_or_else(|| "unknown".to_string());
-
-    Ok((
-        StatusCode::OK,
-        [("X-Account-Email", response_email.as_str())],
-        Json(openai_response),
-    )
-        .into_response())
+    if !trace.rotations.is_empty() {
+        info!("[Images] Account rotations: {}", trace.rotations.join("; "));
+    }
+    let mut response = trace.attach((StatusCode::OK, Json(openai_response)));
+    if let Some(warning) = size_resolution.warning() {
+        set_header_lossy(&mut response, SIZE_WARNING_HEADER, &warning);
+    }
+    if !refusals.is_empty() {
+        set_header_lossy(&mut response, REFUSALS_HEADER, &refusal_summary(&refusals));
+    }
+    Ok(response)
 }
 
 /// OpenAI Images API: POST /v1/images/edits
 /// Handles image editing requests with multipart form data
 pub async fn handle_images_edits(
     State(state): State<AppState>,
+    headers: HeaderMap,
+    local_addr: Option<Extension<LocalAddr>>,
     mut multipart: axum::extract::Multipart,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
     tracing::info!("[Images] Received edit request");
@@ -341,6 +624,8 @@ pub async fn handle_images_edits(
     let mut aspect_ratio: Option<String> = None;
     let mut image_size_param: Option<String> = None;
     let mut style: Option<String> = None;
+    let mut negative_prompt: Option<String> = None;
+    let mut strict_size = false;
 
     while let Some(field) = multipart
         .next_field()
@@ -395,6 +680,14 @@ pub async fn handle_images_edits(
             if let Ok(val) = field.text().await {
                 style = Some(val);
             }
+        } else if name == "negative_prompt" {
+            if let Ok(val) = field.text().await {
+                negative_prompt = Some(val);
+            }
+        } else if name == "strict_size" {
+            if let Ok(val) = field.text().await {
+                strict_size = parse_strict_flag(&val);
+            }
         } else if name == "response_format" {
             if let Ok(val) = field.text().await {
                 response_format = val;
@@ -412,9 +705,13 @@ pub async fn handle_images_edits(
     if prompt.is_empty() {
         return Err((StatusCode::BAD_REQUEST, "Missing prompt".to_string()));
     }
+    if let Some(r) = aspect_ratio.as_deref() {
+        crate::proxy::mappers::common_utils::validate_aspect_ratio(r)
+            .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    }
 
     tracing::info!(
-        "[Images] Edit/Ref Request: model={}, prompt={}, n={}, size={}, aspect_ratio={:?}, image_size={:?}, style={:?}, refs={}, has_main_image={}",
+        "[Images] Edit/Ref Request: model={}, prompt={}, n={}, size={}, aspect_ratio={:?}, image_size={:?}, style={:?}, negative_prompt={}, refs={}, has_main_image={}",
         model,
         prompt,
         n,
@@ -422,12 +719,13 @@ pub async fn handle_images_edits(
         aspect_ratio,
         image_size_param,
         style,
+        negative_prompt.is_some(),
         reference_images.len(),
         image_data.is_some()
     );
 
     // 1. Prepare Config
-    let size_input = aspect_ratio.as_deref().or(Some(&size));
+    let size_input = aspect_ratio.as_deref().map(str::trim).unwrap_or(&size);
 
     let quality_input = match image_size_param.as_deref() {
         Some("4K") => Some("hd"),
@@ -435,20 +733,21 @@ pub async fn handle_images_edits(
         _ => None,
     };
 
-    let (image_config, _) = crate::proxy::mappers::common_utils::parse_image_config_with_params(
-        &model,
-        size_input,
-        quality_input,
-    );
+    let (image_config, size_resolution) =
+        resolve_image_config(&model, size_input, quality_input, strict_size)?;
 
     // 3. Construct Contents
     let mut contents_parts = Vec::new();
 
     // Add Prompt
     let mut final_prompt = prompt.clone();
-    if let Some(s) = style {
+    if let Some(s) = style.as_deref() {
         final_prompt.push_str(&format!(", style: {}", s));
     }
+    let final_prompt = crate::proxy::mappers::common_utils::apply_negative_prompt(
+        &final_prompt,
+        negative_prompt.as_deref(),
+    );
     contents_parts.push(json!({
         "text": final_prompt
     }));
@@ -515,23 +814,49 @@ pub async fn handle_images_edits(
     });
 
     let trace_id = format!("img_edit_{}", chrono::Utc::now().timestamp_subsec_millis());
+    // multipart 中的图片数据不写入调试日志, 仅记录文本字段
+    let request_summary = json!({
+        "model": model,
+        "prompt": prompt,
+        "n": n,
+        "size": size,
+        "aspect_ratio": aspect_ratio,
+        "image_size": image_size_param,
+        "style": style,
+        "negative_prompt": negative_prompt,
+        "strict_size": strict_size,
+    });
+    write_image_debug_payload(&state, &trace_id, "edits", &request_summary, &image_config, &final_prompt)
+        .await;
 
     // 5. Execute Requests with retry/rotation parity
     let mut images: Vec<Value> = Vec::new();
     let mut errors: Vec<String> = Vec::new();
-    let mut response_email: Option<String> = None;
+    let mut refusals: Vec<(usize, String)> = Vec::new();
+    let mut trace = ImageAccountTrace::default();
+    let url_base = if response_format == "url" {
+        Some(image_url_base(&state, &headers, local_addr.map(|Extension(addr)| addr)).await)
+    } else {
+        None
+    };
 
     for idx in 0..n {
-        match execute_image_request_with_retry(&state, &gemini_body, "dall-e-3", &trace_id).await {
-            Ok((gemini_resp, email)) => {
-                if response_email.is_none() {
-                    response_email = Some(email);
-                }
-                let extracted = extract_images_from_response(&gemini_resp, &response_format);
-                if extracted.is_empty() {
-                    errors.push(format!("Task {}: No images generated", idx));
-                } else {
+        match execute_image_request_with_retry(&state, &gemini_body, "dall-e-3", &trace_id, idx, &mut trace)
+            .await
+        {
+            Ok(gemini_resp) => {
+                let extracted = extract_images_from_response(
+                    &gemini_resp,
+                    url_base.as_deref(),
+                    &size_resolution.applied,
+                );
+                if !extracted.is_empty() {
                     images.extend(extracted);
+                } else if let Some(reason) = detect_refusal(&gemini_resp) {
+                    warn!("[Images] Task {} refused by upstream: {}", idx, reason);
+                    refusals.push((idx, reason));
+                } else {
+                    errors.push(format!("Task {}: No images generated", idx));
                 }
             }
             Err(e) => {
@@ -541,6 +866,15 @@ pub async fn handle_images_edits(
         }
     }
 
+    if images.is_empty() && errors.is_empty() && !refusals.is_empty() {
+        tracing::warn!("[Images] All {} edit requests refused: {}", n, refusal_summary(&refusals));
+        return Ok(trace.attach(content_policy_response(&refusals)));
+    }
+
+    if !refusals.is_empty() {
+        errors.push(format!("Refused: {}", refusal_summary(&refusals)));
+    }
+
     if images.is_empty() {
         let error_msg = if !errors.is_empty() {
             errors.join("; ")
@@ -552,7 +886,10 @@ pub async fn handle_images_edits(
             n,
             error_msg
         );
-        return Err((StatusCode::BAD_GATEWAY, error_msg));
+        if trace.last_email.is_none() {
+            return Err((StatusCode::BAD_GATEWAY, error_msg));
+        }
+        return Ok(trace.attach((StatusCode::BAD_GATEWAY, error_msg)));
     }
 
     if !errors.is_empty() {
@@ -575,12 +912,282 @@ pub async fn handle_images_edits(
         "data": images
     });
 
-    let response_email = response_email.unwrap_or_else(|| "unknown".to_string());
+    if !trace.rotations.is_empty() {
+        info!("[Images] Account rotations: {}", trace.rotations.join("; "));
+    }
+    let mut response = trace.attach((StatusCode::OK, Json(openai_response)));
+    if let Some(warning) = size_resolution.warning() {
+        set_header_lossy(&mut response, SIZE_WARNING_HEADER, &warning);
+    }
+    if !refusals.is_empty() {
+        set_header_lossy(&mut response, REFUSALS_HEADER, &refusal_summary(&refusals));
+    }
+    Ok(response)
+}
 
-    Ok((
-        StatusCode::OK,
-        [("X-Account-Email", response_email.as_str())],
-        Json(openai_response),
-    )
-        .into_response())
+/// GET /v1/images/content/:id
+/// 提供 response_format=url 生成的图片, 过期或不存在时返回 404
+pub async fn handle_image_content(Path(id): Path<String>) -> axum::response::Response {
+    match crate::proxy::image_store::get(&id) {
+        Some((data, mime_type, remaining)) => {
+            let mut response = (StatusCode::OK, data).into_response();
+            set_header_lossy(&mut response, "Content-Type", &mime_type);
+            set_header_lossy(
+                &mut response,
+                "Cache-Control",
+                &format!("private, max-age={}", remaining.as_secs()),
+            );
+            response
+        }
+        None => (StatusCode::NOT_FOUND, "Image not found or expired").into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::upstream::client::UpstreamClient;
+    use crate::proxy::upstream::transport::{UpstreamCall, UpstreamTransport};
+    use axum::extract::FromRequest;
+    use futures::future::BoxFuture;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    // 合成响应 (按 v1internal generateContent 的结构手写, 含思考分片、usageMetadata 与 safetyRatings; 非真实上游捕获, 没有可用的实录)
+    const TEXT_REFUSAL_FIXTURE: &str = include_str!("fixtures/image_refusal_text.json");
+    const PROHIBITED_FIXTURE: &str = include_str!("fixtures/image_refusal_prohibited.json");
+    const BLOCKED_PROMPT_FIXTURE: &str = include_str!("fixtures/image_blocked_prompt.json");
+    const IMAGE_FIXTURE: &str = include_str!("fixtures/image_success.json");
+
+    fn fixture(raw: &str) -> Value {
+        serde_json::from_str(raw).unwrap()
+    }
+
+    /// 按调用顺序返回预设响应
+    struct FixtureUpstream {
+        responses: Vec<Value>,
+        calls: AtomicUsize,
+    }
+
+    impl UpstreamTransport for FixtureUpstream {
+        fn send(&self, _call: UpstreamCall) -> BoxFuture<'_, Result<reqwest::Response, String>> {
+            Box::pin(async move {
+                let idx = self.calls.fetch_add(1, Ordering::SeqCst);
+                let body = self.responses[idx.min(self.responses.len() - 1)].clone();
+                axum::http::Response::builder()
+                    .status(200)
+                    .body(reqwest::Body::from(body.to_string()))
+                    .map(reqwest::Response::from)
+                    .map_err(|e| e.to_string())
+            })
+        }
+    }
+
+    fn fixture_state(responses: &[&str], data_dir: &tempfile::TempDir) -> AppState {
+        let mock = Arc::new(crate::proxy::benchmark::MockUpstream::new(&Default::default()));
+        let transport = Arc::new(FixtureUpstream {
+            responses: responses.iter().map(|raw| fixture(raw)).collect(),
+            calls: AtomicUsize::new(0),
+        });
+        AppState {
+            upstream: Arc::new(UpstreamClient::with_transport(transport)),
+            ..crate::proxy::benchmark::benchmark_state(mock, 1, data_dir.path())
+        }
+    }
+
+    async fn decode(response: axum::response::Response) -> (StatusCode, HeaderMap, Value) {
+        let status = response.status();
+        let headers = response.headers().clone();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, headers, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+    }
+
+    async fn generate(state: AppState, n: usize) -> (StatusCode, HeaderMap, Value) {
+        let body = json!({ "prompt": "a cat", "n": n });
+        let response = handle_images_generations(State(state), HeaderMap::new(), None, Json(body))
+            .await
+            .map(IntoResponse::into_response)
+            .unwrap_or_else(IntoResponse::into_response);
+        decode(response).await
+    }
+
+    async fn edit(state: AppState, n: usize) -> (StatusCode, HeaderMap, Value) {
+        let boundary = "fixture-boundary";
+        let body = format!(
+            "--{b}\r\nContent-Disposition: form-data; name=\"prompt\"\r\n\r\nmake it red\r\n\
+             --{b}\r\nContent-Disposition: form-data; name=\"n\"\r\n\r\n{n}\r\n--{b}--\r\n",
+            b = boundary,
+            n = n
+        );
+        let request = axum::http::Request::post("/v1/images/edits")
+            .header(
+                "content-type",
+                format!("multipart/form-data; boundary={}", boundary),
+            )
+            .body(axum::body::Body::from(body))
+            .unwrap();
+        let multipart = axum::extract::Multipart::from_request(request, &()).await.unwrap();
+        let response = handle_images_edits(State(state), HeaderMap::new(), None, multipart)
+            .await
+            .map(IntoResponse::into_response)
+            .unwrap_or_else(IntoResponse::into_response);
+        decode(response).await
+    }
+
+    #[test]
+    fn detect_refusal_from_fixtures() {
+        assert_eq!(
+            detect_refusal(&fixture(TEXT_REFUSAL_FIXTURE)).unwrap(),
+            "I can't generate images of that. Please try a different prompt."
+        );
+        assert!(detect_refusal(&fixture(PROHIBITED_FIXTURE))
+            .unwrap()
+            .contains("IMAGE_PROHIBITED_CONTENT"));
+        assert!(detect_refusal(&fixture(BLOCKED_PROMPT_FIXTURE)).unwrap().contains("SAFETY"));
+        assert!(detect_refusal(&json!({ "response": { "candidates": [] } })).is_none());
+    }
+
+    #[tokio::test]
+    async fn generations_all_refused_returns_content_policy_violation() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let state = fixture_state(&[TEXT_REFUSAL_FIXTURE], &data_dir);
+        let (status, headers, body) = generate(state.clone(), 1).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["code"], "content_policy_violation");
+        assert_eq!(
+            body["error"]["message"],
+            "I can't generate images of that. Please try a different prompt."
+        );
+        assert!(headers.get("X-Account-Email").is_some());
+        // 拒绝不是账号故障
+        assert!(state.token_manager.last_errors.is_empty());
+    }
+
+    #[tokio::test]
+    async fn generations_mixed_results_list_refusals_in_header() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let state = fixture_state(
+            &[IMAGE_FIXTURE, TEXT_REFUSAL_FIXTURE, IMAGE_FIXTURE, PROHIBITED_FIXTURE],
+            &data_dir,
+        );
+        let (status, headers, body) = generate(state, 4).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"].as_array().unwrap().len(), 2);
+        let refusals = headers.get(REFUSALS_HEADER).unwrap().to_str().unwrap();
+        assert!(refusals.starts_with("task 1: I can't generate images"));
+        assert!(refusals.contains("task 3: Image generation was blocked by the safety filter (IMAGE_PROHIBITED_CONTENT)"));
+    }
+
+    #[tokio::test]
+    async fn edits_refusals_follow_generation_rules() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let (status, _, body) = edit(fixture_state(&[BLOCKED_PROMPT_FIXTURE], &data_dir), 2).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["code"], "content_policy_violation");
+        assert!(body["error"]["message"].as_str().unwrap().starts_with("task 0: "));
+
+        let (status, headers, body) =
+            edit(fixture_state(&[TEXT_REFUSAL_FIXTURE, IMAGE_FIXTURE], &data_dir), 2).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"].as_array().unwrap().len(), 1);
+        assert!(headers.get(REFUSALS_HEADER).unwrap().to_str().unwrap().starts_with("task 0: "));
+    }
+
+    #[tokio::test]
+    async fn edits_text_refusal_returns_explanation_without_account_failure() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let state = fixture_state(&[TEXT_REFUSAL_FIXTURE], &data_dir);
+        let (status, headers, body) = edit(state.clone(), 1).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["code"], "content_policy_violation");
+        // 思考分片不作为拒绝说明返回
+        assert_eq!(
+            body["error"]["message"],
+            "I can't generate images of that. Please try a different prompt."
+        );
+        assert!(headers.get(REFUSALS_HEADER).is_none());
+        assert!(state.token_manager.last_errors.is_empty());
+    }
+
+    #[test]
+    fn account_trace_reports_last_email_and_rotations() {
+        let mut trace = ImageAccountTrace::default();
+        trace.last_email = Some("a@example.com".to_string());
+        trace.record_rotation(0, 0, "a@example.com", "429");
+        trace.last_email = Some("b@example.com".to_string());
+
+        let response = trace.attach((StatusCode::BAD_GATEWAY, "boom".to_string()));
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(
+            response.headers().get("X-Account-Email").unwrap(),
+            "b@example.com"
+        );
+        let rotations = response.extensions().get::<AccountRotations>().unwrap();
+        assert_eq!(
+            rotations.0,
+            vec!["task 0 attempt 1: a@example.com -> rotate (429)".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn image_url_base_uses_accepting_listener() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let upstream = std::sync::Arc::new(crate::proxy::benchmark::MockUpstream::new(&Default::default()));
+        let state = crate::proxy::benchmark::benchmark_state(upstream, 1, data_dir.path());
+        let mut headers = HeaderMap::new();
+        headers.insert(header::HOST, "images.example.com".parse().unwrap());
+
+        // 额外监听端口接受的连接: 链接指向该端口而非主端口
+        let accepted = LocalAddr("127.0.0.1:9100".parse().unwrap());
+        assert_eq!(image_url_base(&state, &headers, Some(accepted)).await, "http://127.0.0.1:9100");
+
+        state.security.write().await.allow_lan_access = true;
+        assert_eq!(
+            image_url_base(&state, &headers, Some(accepted)).await,
+            "http://images.example.com"
+        );
+    }
+
+    #[tokio::test]
+    async fn url_format_serves_stored_image() {
+        let data = base64::engine::general_purpose::STANDARD.encode([137u8, 80, 78, 71]);
+        let resp = json!({
+            "response": {
+                "candidates": [{
+                    "content": { "parts": [{ "inlineData": { "mimeType": "image/png", "data": data } }] }
+                }]
+            }
+        });
+
+        let images = extract_images_from_response(&resp, Some("http://127.0.0.1:8045"), "1024x1024");
+        let url = images[0]["url"].as_str().unwrap();
+        let id = url
+            .strip_prefix("http://127.0.0.1:8045/v1/images/content/")
+            .unwrap();
+
+        let response = handle_image_content(Path(id.to_string())).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers().get("Content-Type").unwrap(), "image/png");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body.as_ref(), &[137u8, 80, 78, 71]);
+
+        let missing = handle_image_content(Path("missing".to_string())).await;
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+
+        let b64 = extract_images_from_response(&resp, None, "1024x1024");
+        assert_eq!(b64[0]["b64_json"], json!(data));
+    }
+
+    #[test]
+    fn account_trace_without_rotations_has_no_extension() {
+        let trace = ImageAccountTrace {
+            last_email: Some("a@example.com".to_string()),
+            rotations: Vec::new(),
+        };
+        let response = trace.attach(StatusCode::OK);
+        assert!(response.extensions().get::<AccountRotations>().is_none());
+    }
 }
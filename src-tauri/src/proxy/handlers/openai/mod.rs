@@ -3,11 +3,17 @@
 
 mod chat;
 mod completions;
+mod embeddings;
 mod images;
 mod models;
+mod provider;
+mod vision;
 
 // Re-export all public handlers
 pub use chat::handle_chat_completions;
 pub use completions::handle_completions;
-pub use images::{handle_images_edits, handle_images_generations};
+pub use embeddings::handle_embeddings;
+pub use images::{handle_image_content, handle_images_edits, handle_images_generations};
 pub use models::handle_list_models;
+
+use provider::forward_to_provider;
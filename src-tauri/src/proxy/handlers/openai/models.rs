@@ -1,28 +1,62 @@
 // OpenAI Models Handler
 // GET /v1/models - List available models
+// 仅开放 anthropic / gemini 协议的端口上按该协议的格式返回
 
-use axum::{extract::State, response::IntoResponse, Json};
+use axum::{
+    extract::{Query, State},
+    response::IntoResponse,
+    Extension, Json,
+};
+use serde::Deserialize;
 use serde_json::json;
 
+use crate::proxy::config::ListenerProtocol;
+use crate::proxy::server::listeners::AllowedProtocols;
 use crate::proxy::server::AppState;
 
-/// List all available models in OpenAI format
-pub async fn handle_list_models(State(state): State<AppState>) -> impl IntoResponse {
-    use crate::proxy::common::model_mapping::get_all_dynamic_models;
-
-    let model_ids = get_all_dynamic_models(&state.custom_mapping).await;
-
-    let data: Vec<_> = model_ids
-        .into_iter()
-        .map(|id| {
-            json!({
-                "id": id,
-                "object": "model",
-                "created": 1706745600,
-                "owned_by": "antigravity"
-            })
-        })
-        .collect();
+#[derive(Debug, Default, Deserialize)]
+pub struct ListModelsQuery {
+    /// 为 true 时隐藏路由到 degraded 物理模型的条目
+    #[serde(default)]
+    pub available: bool,
+}
+
+/// List all available models (OpenAI format unless the port only serves Anthropic / Gemini)
+pub async fn handle_list_models(
+    State(state): State<AppState>,
+    allowed: Option<Extension<AllowedProtocols>>,
+    Query(query): Query<ListModelsQuery>,
+) -> impl IntoResponse {
+    use crate::proxy::common::model_mapping::physical_model;
+    use crate::proxy::handlers::common::{
+        anthropic_model_list, gemini_model_entry, listed_models, model_list_entry,
+    };
+
+    let mut models = listed_models(&state).await;
+
+    if query.available {
+        let mapping = state.custom_mapping.read().await;
+        models.retain(|model| {
+            let physical = model
+                .mapped_to
+                .clone()
+                .unwrap_or_else(|| physical_model(&model.id, &mapping));
+            !crate::proxy::model_health::is_degraded(&physical)
+        });
+    }
+
+    match allowed {
+        Some(Extension(allowed)) if !allowed.contains(ListenerProtocol::OpenAI) => {
+            if allowed.contains(ListenerProtocol::Anthropic) {
+                return Json(anthropic_model_list(&models));
+            }
+            let entries: Vec<_> = models.iter().map(|m| gemini_model_entry(&m.id)).collect();
+            return Json(json!({ "models": entries }));
+        }
+        _ => {}
+    }
+
+    let data: Vec<_> = models.iter().map(model_list_entry).collect();
 
     Json(json!({
         "object": "list",
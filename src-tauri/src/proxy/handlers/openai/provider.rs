@@ -0,0 +1,40 @@
+// OpenAI 协议请求分发到额外 provider (vLLM / OpenRouter 等)
+
+use axum::http::{HeaderMap, Method};
+use axum::response::Response;
+use serde_json::Value;
+
+use crate::proxy::config::ListenerProtocol;
+use crate::proxy::providers::{select_provider, ProviderRequest};
+use crate::proxy::server::AppState;
+
+/// 若分发策略选中了支持 OpenAI 协议的 provider, 透传请求并返回其响应
+pub(super) async fn forward_to_provider(
+    state: &AppState,
+    headers: &HeaderMap,
+    path: &str,
+    body: &Value,
+) -> Option<Response> {
+    let model = body.get("model").and_then(|m| m.as_str()).unwrap_or_default();
+    let selected = select_provider(state, ListenerProtocol::OpenAI, model, "openai").await?;
+    let message_count = body
+        .get("messages")
+        .and_then(|m| m.as_array())
+        .map(|m| m.len())
+        .unwrap_or(0);
+
+    let response = selected
+        .provider
+        .forward_stream(
+            state,
+            ProviderRequest {
+                method: Method::POST,
+                path,
+                headers,
+                body: body.clone(),
+                message_count,
+            },
+        )
+        .await;
+    Some(selected.decision.attach(response))
+}
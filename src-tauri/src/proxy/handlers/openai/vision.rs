@@ -0,0 +1,299 @@
+// OpenAI Chat 图片输入 (image_url)
+// 上游只接受 inlineData, 不能引用任意 URL: 转发前把 data: URI 规范化, 把 http(s) 图片下载后内联为 data: URI,
+// 再由 mapper 转为 Gemini inlineData。同一请求内重复引用的 URL 只下载一次。
+
+use base64::Engine as _;
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::Duration;
+
+use crate::proxy::handlers::common::percent_decode;
+use crate::proxy::mappers::openai::{OpenAIContent, OpenAIContentBlock, OpenAIRequest};
+use crate::proxy::upstream::client::UpstreamClient;
+use crate::proxy::upstream::resource_guard::FetchError;
+
+/// 单张远程图片的大小上限
+const MAX_IMAGE_BYTES: usize = 20 * 1024 * 1024;
+/// 单个请求内所有远程图片的总大小上限
+const MAX_TOTAL_IMAGE_BYTES: usize = 50 * 1024 * 1024;
+/// 单张远程图片的下载超时
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 下载并内联请求中的远程图片; 返回的错误信息适合直接作为 400 响应
+pub async fn inline_image_urls(req: &mut OpenAIRequest, upstream: &UpstreamClient) -> Result<(), String> {
+    let fetched = inline_image_urls_with(req, |url, max_bytes| async move {
+        upstream.fetch_resource(&url, max_bytes, FETCH_TIMEOUT).await
+    })
+    .await?;
+    if fetched > 0 {
+        tracing::debug!("[OpenAI-Vision] Inlined {} remote image(s)", fetched);
+    }
+    Ok(())
+}
+
+/// `fetch(url, 大小上限)` 返回 (内容, Content-Type); 返回实际下载次数
+///
+/// 下载失败的细节 (状态码、连接错误) 只写日志, 不回显给客户端, 避免把代理变成内网探测工具
+async fn inline_image_urls_with<F, Fut>(req: &mut OpenAIRequest, mut fetch: F) -> Result<usize, String>
+where
+    F: FnMut(String, usize) -> Fut,
+    Fut: Future<Output = Result<(Bytes, Option<String>), FetchError>>,
+{
+    // url -> 内联后的 data URI
+    let mut cache: HashMap<String, String> = HashMap::new();
+    let mut remaining = MAX_TOTAL_IMAGE_BYTES;
+
+    for msg in req.messages.iter_mut() {
+        let Some(OpenAIContent::Array(blocks)) = msg.content.as_mut() else {
+            continue;
+        };
+        for block in blocks.iter_mut() {
+            let OpenAIContentBlock::ImageUrl { image_url } = block else {
+                continue;
+            };
+            let url = image_url.url.trim().to_string();
+
+            if url.starts_with("data:") {
+                image_url.url = normalize_data_uri(&url)?;
+            } else if is_remote(&url) {
+                if let Some(inlined) = cache.get(&url) {
+                    image_url.url = inlined.clone();
+                    continue;
+                }
+                let max_bytes = MAX_IMAGE_BYTES.min(remaining);
+                let (data, content_type) = fetch(url.clone(), max_bytes).await.map_err(|e| match e {
+                    FetchError::Blocked => {
+                        format!("image_url {} points to a private or reserved address", url)
+                    }
+                    FetchError::TooLarge(_) if max_bytes < MAX_IMAGE_BYTES => format!(
+                        "Remote image_url downloads exceed {} bytes in total",
+                        MAX_TOTAL_IMAGE_BYTES
+                    ),
+                    FetchError::TooLarge(limit) => {
+                        format!("image_url {} exceeds {} bytes", url, limit)
+                    }
+                    FetchError::Failed(detail) => {
+                        tracing::warn!("[OpenAI-Vision] Failed to download {}: {}", url, detail);
+                        format!("Failed to download image_url {}", url)
+                    }
+                })?;
+                remaining = remaining.saturating_sub(data.len());
+                let mime_type = detect_mime_type(&data, content_type.as_deref())
+                    .ok_or_else(|| format!("image_url {} is not a supported image", url))?;
+                let inlined = data_uri(mime_type, &data);
+                cache.insert(url, inlined.clone());
+                image_url.url = inlined;
+            } else if let Some(scheme) = unsupported_scheme(&url) {
+                return Err(format!(
+                    "Unsupported image_url scheme '{}': use an http(s) URL or a data: URI",
+                    scheme
+                ));
+            }
+            // 其余 (file:// 与本地路径) 保持原样, 由 mapper 读取
+        }
+    }
+
+    Ok(cache.len())
+}
+
+fn is_remote(url: &str) -> bool {
+    let lower = url.to_ascii_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://")
+}
+
+/// `xxx://` 形式且不是 http(s) / file 的 scheme
+fn unsupported_scheme(url: &str) -> Option<&str> {
+    let (scheme, _) = url.split_once("://")?;
+    let valid = !scheme.is_empty()
+        && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    (valid && !scheme.eq_ignore_ascii_case("file")).then_some(scheme)
+}
+
+/// 解码 data URI 并按内容重新判定 mimeType, 统一为 base64 形式
+fn normalize_data_uri(url: &str) -> Result<String, String> {
+    let (header, payload) = url["data:".len()..]
+        .split_once(',')
+        .ok_or_else(|| "Invalid data: URI in image_url (missing ',')".to_string())?;
+    let mut params = header.split(';');
+    let declared = params.next().map(str::trim).filter(|m| !m.is_empty());
+    let is_base64 = params.any(|p| p.trim().eq_ignore_ascii_case("base64"));
+
+    let data = if is_base64 {
+        let compact: String = payload.chars().filter(|c| !c.is_ascii_whitespace()).collect();
+        base64::engine::general_purpose::STANDARD
+            .decode(compact.as_bytes())
+            .map_err(|e| format!("Invalid base64 in image_url data: URI: {}", e))?
+    } else {
+        percent_decode(payload)
+    };
+
+    // 非图片 (如 PDF) 按声明的类型透传
+    let mime_type = detect_mime_type(&data, declared)
+        .or_else(|| declared.map(str::to_ascii_lowercase))
+        .ok_or_else(|| "image_url data: URI has no media type".to_string())?;
+    Ok(data_uri(mime_type, &data))
+}
+
+/// 优先按文件头判定, 识别不了时信任声明的 image/* 类型 (如 HEIC)
+fn detect_mime_type(data: &[u8], declared: Option<&str>) -> Option<String> {
+    if let Ok(format) = image::guess_format(data) {
+        return Some(format.to_mime_type().to_string());
+    }
+    declared
+        .map(|m| m.trim().to_ascii_lowercase())
+        .filter(|m| m.starts_with("image/"))
+}
+
+fn data_uri(mime_type: String, data: &[u8]) -> String {
+    format!(
+        "data:{};base64,{}",
+        mime_type,
+        base64::engine::general_purpose::STANDARD.encode(data)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const PNG_B64: &str = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mP8z8BQDwAEhQGAhKmMIQAAAABJRU5ErkJggg==";
+
+    fn png() -> Vec<u8> {
+        base64::engine::general_purpose::STANDARD.decode(PNG_B64).unwrap()
+    }
+
+    fn request(urls: &[&str]) -> OpenAIRequest {
+        let blocks: Vec<_> = urls
+            .iter()
+            .map(|url| json!({ "type": "image_url", "image_url": { "url": url } }))
+            .collect();
+        serde_json::from_value(json!({
+            "model": "gemini-2.5-flash",
+            "messages": [
+                { "role": "user", "content": blocks },
+                { "role": "user", "content": [
+                    { "type": "text", "text": "compare" },
+                    { "type": "image_url", "image_url": { "url": urls[0] } }
+                ] }
+            ]
+        }))
+        .unwrap()
+    }
+
+    fn image_urls(req: &OpenAIRequest) -> Vec<String> {
+        req.messages
+            .iter()
+            .filter_map(|msg| match &msg.content {
+                Some(OpenAIContent::Array(blocks)) => Some(blocks),
+                _ => None,
+            })
+            .flatten()
+            .filter_map(|block| match block {
+                OpenAIContentBlock::ImageUrl { image_url } => Some(image_url.url.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_remote_image_fetched_once_and_inlined() {
+        let mut req = request(&["https://example.com/cat.png"]);
+        let mut calls = 0;
+        let fetched = inline_image_urls_with(&mut req, |_, _| {
+            calls += 1;
+            async { Ok((Bytes::from(png()), Some("application/octet-stream".to_string()))) }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(fetched, 1);
+        assert_eq!(calls, 1);
+        let expected = format!("data:image/png;base64,{}", PNG_B64);
+        assert_eq!(image_urls(&req), vec![expected.clone(), expected]);
+    }
+
+    #[tokio::test]
+    async fn test_remote_failures_and_non_images_are_rejected() {
+        let mut req = request(&["https://example.com/missing.png"]);
+        let err = inline_image_urls_with(&mut req, |_, _| async {
+            Err(FetchError::Failed("HTTP 404".to_string()))
+        })
+        .await
+        .unwrap_err();
+        // 目标的状态码不回显给客户端
+        assert_eq!(err, "Failed to download image_url https://example.com/missing.png");
+
+        let mut req = request(&["http://169.254.169.254/latest/meta-data"]);
+        let err = inline_image_urls_with(&mut req, |_, _| async { Err(FetchError::Blocked) })
+            .await
+            .unwrap_err();
+        assert!(err.contains("private or reserved address"));
+
+        let mut req = request(&["https://example.com/page"]);
+        let err = inline_image_urls_with(&mut req, |_, _| async {
+            Ok((Bytes::from_static(b"<html></html>"), Some("text/html".to_string())))
+        })
+        .await
+        .unwrap_err();
+        assert!(err.contains("not a supported image"));
+    }
+
+    #[tokio::test]
+    async fn test_total_remote_image_bytes_capped() {
+        let mut image = png();
+        image.resize(MAX_IMAGE_BYTES, 0);
+        let image = Bytes::from(image);
+        let mut req = request(&[
+            "https://example.com/1.png",
+            "https://example.com/2.png",
+            "https://example.com/3.png",
+        ]);
+        let err = inline_image_urls_with(&mut req, |_, max_bytes| {
+            let image = image.clone();
+            async move {
+                if image.len() > max_bytes {
+                    return Err(FetchError::TooLarge(max_bytes));
+                }
+                Ok((image, None))
+            }
+        })
+        .await
+        .unwrap_err();
+        assert!(err.contains("in total"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_scheme_rejected_and_local_paths_kept() {
+        let mut req = request(&["ftp://example.com/cat.png"]);
+        let err = inline_image_urls_with(&mut req, |_, _| async { Err(FetchError::Blocked) })
+            .await
+            .unwrap_err();
+        assert!(err.contains("'ftp'"));
+
+        let mut req = request(&["file:///tmp/cat.png", "/tmp/dog.png"]);
+        inline_image_urls_with(&mut req, |_, _| async { Err(FetchError::Blocked) })
+            .await
+            .unwrap();
+        assert_eq!(image_urls(&req)[..2], ["file:///tmp/cat.png", "/tmp/dog.png"]);
+    }
+
+    #[test]
+    fn test_data_uri_normalized_by_content() {
+        // 声明的类型与实际内容不符时以文件头为准
+        let url = format!("data:image/jpeg;base64,{}", PNG_B64);
+        assert_eq!(
+            normalize_data_uri(&url).unwrap(),
+            format!("data:image/png;base64,{}", PNG_B64)
+        );
+
+        let svg = normalize_data_uri("data:image/svg+xml,%3Csvg%2F%3E").unwrap();
+        let encoded = base64::engine::general_purpose::STANDARD.encode("<svg/>");
+        assert_eq!(svg, format!("data:image/svg+xml;base64,{}", encoded));
+
+        assert!(normalize_data_uri("data:image/png;base64,@@@").is_err());
+        assert!(normalize_data_uri("data:image/png;base64").is_err());
+    }
+}
@@ -14,6 +14,7 @@ use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use tracing::{info, warn};
 
+use crate::proxy::handlers::common::set_header_lossy;
 use crate::proxy::mappers::gemini::wrapper::wrap_request;
 use crate::proxy::server::AppState;
 
@@ -74,78 +75,20 @@ pub async fn handle_warmup(
     };
 
     // ===== 步骤 2: 根据模型类型构建请求体 =====
-    let is_claude = req.model.to_lowercase().contains("claude");
-    
-    // [FIX] Use a distinct session ID for warmup to avoid polluting real conversation matching
-    let session_id = format!("warmup_{}", uuid::Uuid::new_v4());
-
-    let body: Value = if is_claude {
-        // Claude 模型：使用 transform_claude_request_in 转换
-        let claude_request = crate::proxy::mappers::claude::models::ClaudeRequest {
-            model: req.model.clone(),
-            messages: vec![crate::proxy::mappers::claude::models::Message {
-                role: "user".to_string(),
-                // [FIX] Use "Hello" instead of "ping" to pass strict model filters
-                content: crate::proxy::mappers::claude::models::MessageContent::String(
-                    "Hello".to_string(),
-                ),
-            }],
-            max_tokens: Some(1),
-            stream: false,
-            system: None,
-            temperature: Some(0.0), // Explicit 0.0 for stability
-            top_p: None,
-            top_k: None,
-            tools: None,
-            metadata: Some(crate::proxy::mappers::claude::models::Metadata {
-                user_id: Some(session_id.clone()),
-            }),
-            thinking: None,
-            output_config: None,
-            size: None,
-            quality: None,
-        };
-
-        match crate::proxy::mappers::claude::transform_claude_request_in(
-            &claude_request,
-            &project_id,
-            false,
-        ) {
-            Ok(transformed) => transformed,
-            Err(e) => {
-                warn!("[Warmup-API] Step 2 FAILED: Claude transform error: {}", e);
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(WarmupResponse {
-                        success: false,
-                        message: format!("Transform error: {}", e),
-                        error: Some(e),
-                    }),
-                )
-                    .into_response();
-            }
+    let body = match build_warmup_body(&req.model, &project_id) {
+        Ok(body) => body,
+        Err(e) => {
+            warn!("[Warmup-API] Step 2 FAILED: Claude transform error: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(WarmupResponse {
+                    success: false,
+                    message: format!("Transform error: {}", e),
+                    error: Some(e),
+                }),
+            )
+                .into_response();
         }
-    } else {
-        // Gemini 模型：使用 STANDARD payload format
-        // [FIX] Avoid wrap_request which might add incompatible fields for some models
-        // Use raw valid JSON for Gemini
-        
-        // Check if image model (needs different structure?) - No, text-only "Hello" is fine for most multimodal models
-        json!({
-            "model": req.model,
-            "contents": [{
-                "role": "user",
-                "parts": [{"text": "Hello"}]
-            }],
-            "generationConfig": {
-                "maxOutputTokens": 1,
-                "temperature": 0.0
-            },
-            // Include session_id in a way that doesn't break schema (often ignored or passed in metadata)
-            // But for Gemini upstream, we often just need valid JSON.
-            // Some internal proxies expect `session_id` at top level.
-            "session_id": session_id
-        })
     };
 
     // ===== 步骤 3: 调用 UpstreamClient =====
@@ -204,12 +147,8 @@ pub async fn handle_warmup(
             };
 
             // 添加响应头，让监控中间件捕获账号信息
-            if let Ok(email_val) = axum::http::HeaderValue::from_str(&req.email) {
-                response.headers_mut().insert("X-Account-Email", email_val);
-            }
-            if let Ok(model_val) = axum::http::HeaderValue::from_str(&req.model) {
-                response.headers_mut().insert("X-Mapped-Model", model_val);
-            }
+            set_header_lossy(&mut response, "X-Account-Email", &req.email);
+            set_header_lossy(&mut response, "X-Mapped-Model", &req.model);
             
             response
         }
@@ -229,14 +168,74 @@ pub async fn handle_warmup(
             ).into_response();
 
             // 即使失败也添加响应头，以便监控
-            if let Ok(email_val) = axum::http::HeaderValue::from_str(&req.email) {
-                response.headers_mut().insert("X-Account-Email", email_val);
-            }
-            if let Ok(model_val) = axum::http::HeaderValue::from_str(&req.model) {
-                response.headers_mut().insert("X-Mapped-Model", model_val);
-            }
+            set_header_lossy(&mut response, "X-Account-Email", &req.email);
+            set_header_lossy(&mut response, "X-Mapped-Model", &req.model);
             
             response
         }
     }
 }
+
+/// 构建 1-token 预热请求体 (供 /internal/warmup 与预热池共用)
+///
+/// 使用独立的 `warmup_` 会话 ID, 避免污染真实会话的指纹匹配
+pub(crate) fn build_warmup_body(model: &str, project_id: &str) -> Result<Value, String> {
+    let is_claude = model.to_lowercase().contains("claude");
+    
+    // [FIX] Use a distinct session ID for warmup to avoid polluting real conversation matching
+    let session_id = format!("warmup_{}", uuid::Uuid::new_v4());
+
+    if is_claude {
+        // Claude 模型：使用 transform_claude_request_in 转换
+        let claude_request = crate::proxy::mappers::claude::models::ClaudeRequest {
+            model: model.to_string(),
+            messages: vec![crate::proxy::mappers::claude::models::Message {
+                role: "user".to_string(),
+                // [FIX] Use "Hello" instead of "ping" to pass strict model filters
+                content: crate::proxy::mappers::claude::models::MessageContent::String(
+                    "Hello".to_string(),
+                ),
+            }],
+            max_tokens: Some(1),
+            stream: false,
+            system: None,
+            temperature: Some(0.0), // Explicit 0.0 for stability
+            top_p: None,
+            top_k: None,
+            tools: None,
+            metadata: Some(crate::proxy::mappers::claude::models::Metadata {
+                user_id: Some(session_id),
+            }),
+            thinking: None,
+            output_config: None,
+            size: None,
+            quality: None,
+            service_tier: None,
+            tool_choice: None,
+            stop_sequences: None,
+        };
+
+        crate::proxy::mappers::claude::transform_claude_request_in(&claude_request, project_id, false)
+    } else {
+        // Gemini 模型：使用 STANDARD payload format
+        // [FIX] Avoid wrap_request which might add incompatible fields for some models
+        // Use raw valid JSON for Gemini
+        
+        // Check if image model (needs different structure?) - No, text-only "Hello" is fine for most multimodal models
+        Ok(json!({
+            "model": model,
+            "contents": [{
+                "role": "user",
+                "parts": [{"text": "Hello"}]
+            }],
+            "generationConfig": {
+                "maxOutputTokens": 1,
+                "temperature": 0.0
+            },
+            // Include session_id in a way that doesn't break schema (often ignored or passed in metadata)
+            // But for Gemini upstream, we often just need valid JSON.
+            // Some internal proxies expect `session_id` at top level.
+            "session_id": session_id
+        }))
+    }
+}
@@ -0,0 +1,261 @@
+// 生成图片的临时存储 (图片接口 response_format=url)
+// 部分客户端 (如 Open WebUI) 只接受 http(s) 链接, 不接受 data URI; 图片按 UUID 暂存在内存中,
+// 由 GET /v1/images/content/:id 提供, 超过 TTL (image_url_ttl_secs) 后清除, 停止服务时全部清空;
+// 总大小超过上限时按最近最少访问淘汰。
+
+use bytes::Bytes;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// 图片内容路由 (挂在代理端口上, 无需鉴权; UUID 不可猜测)
+pub const CONTENT_ROUTE: &str = "/v1/images/content/:id";
+
+/// 后台清理间隔
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// 内存中图片的总字节上限, 超出时按最近最少访问淘汰
+const MAX_TOTAL_BYTES: usize = 256 * 1024 * 1024;
+
+struct StoredImage {
+    data: Bytes,
+    mime_type: String,
+    expires_at: Instant,
+    /// 最近一次写入或读取的序号 (越小越久未访问)
+    last_used: u64,
+}
+
+/// 按总字节数限额的图片存储, 超出上限时淘汰最久未访问的图片
+struct ImageStore {
+    inner: Mutex<StoreInner>,
+    max_bytes: usize,
+}
+
+#[derive(Default)]
+struct StoreInner {
+    images: HashMap<String, StoredImage>,
+    total_bytes: usize,
+    clock: u64,
+}
+
+impl StoreInner {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn remove(&mut self, id: &str) -> Option<StoredImage> {
+        let image = self.images.remove(id)?;
+        self.total_bytes -= image.data.len();
+        Some(image)
+    }
+
+    fn purge_expired(&mut self, now: Instant) -> usize {
+        let expired: Vec<String> = self
+            .images
+            .iter()
+            .filter(|(_, image)| image.expires_at <= now)
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in &expired {
+            self.remove(id);
+        }
+        expired.len()
+    }
+
+    /// 淘汰最久未访问的图片, 直到能再放下 `incoming` 字节
+    fn evict_for(&mut self, incoming: usize, max_bytes: usize) -> usize {
+        let mut evicted = 0;
+        while self.total_bytes + incoming > max_bytes {
+            let Some(oldest) = self
+                .images
+                .iter()
+                .min_by_key(|(_, image)| image.last_used)
+                .map(|(id, _)| id.clone())
+            else {
+                break;
+            };
+            self.remove(&oldest);
+            evicted += 1;
+        }
+        evicted
+    }
+}
+
+impl ImageStore {
+    fn new(max_bytes: usize) -> Self {
+        Self {
+            inner: Mutex::new(StoreInner::default()),
+            max_bytes,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, StoreInner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 超过总上限的单张图片不保存, 返回 None
+    fn insert(&self, data: Vec<u8>, mime_type: &str, ttl: Duration) -> Option<String> {
+        if data.len() > self.max_bytes {
+            return None;
+        }
+        let mut inner = self.lock();
+        let now = Instant::now();
+        inner.purge_expired(now);
+        let evicted = inner.evict_for(data.len(), self.max_bytes);
+        if evicted > 0 {
+            tracing::debug!("[ImageStore] Evicted {} least recently used image(s)", evicted);
+        }
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        let last_used = inner.tick();
+        inner.total_bytes += data.len();
+        inner.images.insert(
+            id.clone(),
+            StoredImage {
+                data: Bytes::from(data),
+                mime_type: mime_type.to_string(),
+                expires_at: now + ttl,
+                last_used,
+            },
+        );
+        Some(id)
+    }
+
+    fn get(&self, id: &str) -> Option<(Bytes, String, Duration)> {
+        let mut inner = self.lock();
+        let now = Instant::now();
+        if inner.images.get(id)?.expires_at <= now {
+            inner.remove(id);
+            return None;
+        }
+        let last_used = inner.tick();
+        let image = inner.images.get_mut(id)?;
+        image.last_used = last_used;
+        Some((
+            image.data.clone(),
+            image.mime_type.clone(),
+            image.expires_at - now,
+        ))
+    }
+
+    fn purge_expired(&self) -> usize {
+        self.lock().purge_expired(Instant::now())
+    }
+
+    fn clear(&self) -> usize {
+        let mut inner = self.lock();
+        let count = inner.images.len();
+        *inner = StoreInner::default();
+        count
+    }
+}
+
+static IMAGES: Lazy<ImageStore> = Lazy::new(|| ImageStore::new(MAX_TOTAL_BYTES));
+static TTL_SECS: AtomicU64 = AtomicU64::new(3600);
+static SWEEPER_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// 应用配置 (服务启动与热更新时调用); 只影响之后写入的图片
+pub fn set_ttl_secs(secs: u64) {
+    TTL_SECS.store(secs.max(1), Ordering::Relaxed);
+}
+
+fn ttl() -> Duration {
+    Duration::from_secs(TTL_SECS.load(Ordering::Relaxed))
+}
+
+/// 图片的访问路径, 例如 `/v1/images/content/<uuid>`
+pub fn content_path(id: &str) -> String {
+    format!("/v1/images/content/{}", id)
+}
+
+/// 保存图片, 返回 ID; 单张图片超过存储总上限时返回 None
+pub fn put(data: Vec<u8>, mime_type: &str) -> Option<String> {
+    IMAGES.insert(data, mime_type, ttl())
+}
+
+/// 读取未过期的图片 (数据, Content-Type, 剩余有效期), 并刷新其访问顺序
+pub fn get(id: &str) -> Option<(Bytes, String, Duration)> {
+    IMAGES.get(id)
+}
+
+/// 清除过期图片, 返回清除数量
+pub fn purge_expired() -> usize {
+    IMAGES.purge_expired()
+}
+
+/// 清空全部图片 (停止服务时调用)
+pub fn clear() {
+    let count = IMAGES.clear();
+    if count > 0 {
+        tracing::info!("[ImageStore] Cleared {} stored image(s)", count);
+    }
+}
+
+/// 启动后台清理任务 (进程内只启动一次)
+pub fn spawn_sweeper() {
+    if SWEEPER_STARTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(SWEEP_INTERVAL);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        loop {
+            ticker.tick().await;
+            let purged = purge_expired();
+            if purged > 0 {
+                tracing::debug!("[ImageStore] Purged {} expired image(s)", purged);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stored_image_round_trip() {
+        let store = ImageStore::new(1024);
+        let id = store.insert(vec![1, 2, 3], "image/png", Duration::from_secs(60)).unwrap();
+        let (data, mime_type, remaining) = store.get(&id).unwrap();
+        assert_eq!(data.as_ref(), &[1, 2, 3]);
+        assert_eq!(mime_type, "image/png");
+        assert!(remaining <= Duration::from_secs(60));
+        assert_eq!(content_path(&id), format!("/v1/images/content/{}", id));
+        assert!(store.get("missing").is_none());
+    }
+
+    #[test]
+    fn test_expired_image_is_purged() {
+        let store = ImageStore::new(1024);
+        let id = store.insert(vec![0; 4], "image/jpeg", Duration::ZERO).unwrap();
+        assert!(store.get(&id).is_none());
+        assert_eq!(store.lock().total_bytes, 0);
+
+        store.insert(vec![0; 4], "image/jpeg", Duration::ZERO).unwrap();
+        assert_eq!(store.purge_expired(), 1);
+        assert!(store.lock().images.is_empty());
+    }
+
+    #[test]
+    fn test_total_bytes_cap_evicts_least_recently_used() {
+        let store = ImageStore::new(10);
+        let ttl = Duration::from_secs(60);
+        let a = store.insert(vec![0; 4], "image/png", ttl).unwrap();
+        let b = store.insert(vec![0; 4], "image/png", ttl).unwrap();
+        // 读取 a 后 b 成为最久未访问
+        assert!(store.get(&a).is_some());
+
+        let c = store.insert(vec![0; 4], "image/png", ttl).unwrap();
+        assert!(store.get(&b).is_none());
+        assert!(store.get(&a).is_some());
+        assert!(store.get(&c).is_some());
+        assert_eq!(store.lock().total_bytes, 8);
+
+        // 超过总上限的单张图片不保存, 也不淘汰已有图片
+        assert!(store.insert(vec![0; 11], "image/png", ttl).is_none());
+        assert_eq!(store.lock().images.len(), 2);
+    }
+}
@@ -0,0 +1,460 @@
+// API Key 每日预算
+// 按 API Key 限制每日 token 数与请求数 (独立于账号配额), 0 表示不限制。
+// 请求在分发前检查并计数, 完成后按实际用量累加 token; 计量按配额时区 (quota_timezone) 的日期切换,
+// 写入 key_budgets.json (以 Key 指纹为索引, 不落盘明文), 重启不清零。
+// 落盘在后台合并进行 (SAVE_DEBOUNCE 内的多次更新只写一次, 原子替换), 不阻塞请求路径。
+
+use axum::{
+    http::{HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
+
+use crate::proxy::config::KeyBudget;
+use crate::utils::time;
+
+const STORE_FILE: &str = "key_budgets.json";
+/// 计量落盘的合并间隔
+const SAVE_DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// 已配置 (非无限) 的预算: Key 指纹 -> 预算
+static BUDGETS: Lazy<RwLock<HashMap<String, ConfiguredBudget>>> =
+    Lazy::new(|| RwLock::new(HashMap::new()));
+static LEDGER: Lazy<Mutex<Ledger>> = Lazy::new(|| Mutex::new(Ledger::default()));
+static STORE_PATH: Lazy<Mutex<Option<PathBuf>>> = Lazy::new(|| Mutex::new(None));
+/// 已有待执行的落盘任务
+static SAVE_SCHEDULED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone)]
+struct ConfiguredBudget {
+    label: String,
+    budget: KeyBudget,
+}
+
+/// 超出的预算维度
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BudgetKind {
+    Tokens,
+    Requests,
+}
+
+/// 预算超限 (分发前拒绝)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BudgetExceeded {
+    pub kind: BudgetKind,
+    pub used: u64,
+    pub limit: u64,
+    /// 计量重置时间 (unix 秒, 配额时区次日零点)
+    pub reset_at: i64,
+}
+
+impl BudgetExceeded {
+    pub fn message(&self) -> String {
+        let what = match self.kind {
+            BudgetKind::Tokens => "token",
+            BudgetKind::Requests => "request",
+        };
+        let reset = time::quota_zone().rfc3339(self.reset_at);
+        format!(
+            "Daily {} budget for this API key exhausted ({} / {}). Resets at {}",
+            what, self.used, self.limit, reset
+        )
+    }
+}
+
+/// 单个 Key 的预算状态 (get_key_budget_status / proxy stats)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyBudgetStatus {
+    /// 脱敏后的 Key, 如 "sk-ab…wxyz"
+    pub key: String,
+    pub tokens_per_day: u64,
+    pub requests_per_day: u64,
+    pub tokens_used: u64,
+    pub requests_used: u64,
+    /// 计量重置时间 (unix 秒)
+    pub reset_at: i64,
+    pub exhausted: bool,
+}
+
+/// 某个 Key 当天的计量
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Meter {
+    /// YYYY-MM-DD (配额时区日期)
+    day: String,
+    tokens: u64,
+    requests: u64,
+}
+
+impl Meter {
+    /// 跨日时清零
+    fn roll(&mut self, day: &str) {
+        if self.day != day {
+            *self = Meter {
+                day: day.to_string(),
+                ..Default::default()
+            };
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Ledger {
+    /// Key 指纹 -> 计量
+    meters: HashMap<String, Meter>,
+}
+
+impl Ledger {
+    /// 检查预算, 通过时计入一次请求
+    fn acquire(&mut self, id: &str, budget: &KeyBudget, day: &str) -> Result<(), (BudgetKind, u64, u64)> {
+        let meter = self.meters.entry(id.to_string()).or_default();
+        meter.roll(day);
+        if budget.tokens_per_day > 0 && meter.tokens >= budget.tokens_per_day {
+            return Err((BudgetKind::Tokens, meter.tokens, budget.tokens_per_day));
+        }
+        if budget.requests_per_day > 0 && meter.requests >= budget.requests_per_day {
+            return Err((BudgetKind::Requests, meter.requests, budget.requests_per_day));
+        }
+        meter.requests += 1;
+        Ok(())
+    }
+
+    fn add_tokens(&mut self, id: &str, tokens: u64, day: &str) {
+        let meter = self.meters.entry(id.to_string()).or_default();
+        meter.roll(day);
+        meter.tokens += tokens;
+    }
+
+    fn usage(&self, id: &str, day: &str) -> (u64, u64) {
+        self.meters
+            .get(id)
+            .filter(|m| m.day == day)
+            .map(|m| (m.tokens, m.requests))
+            .unwrap_or((0, 0))
+    }
+}
+
+/// Key 指纹 (计量文件中不保存明文 Key)
+fn fingerprint(key: &str) -> String {
+    let digest = Sha256::digest(key.as_bytes());
+    let hex: String = digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("key-{}", hex)
+}
+
+/// 展示用的脱敏 Key
+fn mask_key(key: &str) -> String {
+    let chars: Vec<char> = key.chars().collect();
+    if chars.len() <= 12 {
+        return "***".to_string();
+    }
+    let head: String = chars[..5].iter().collect();
+    let tail: String = chars[chars.len() - 4..].iter().collect();
+    format!("{}…{}", head, tail)
+}
+
+/// 配额时区中的当前日期
+fn today() -> String {
+    time::quota_zone().day_label(chrono::Utc::now().timestamp())
+}
+
+/// 配额时区的次日零点 (unix 秒)
+fn next_reset() -> i64 {
+    time::quota_zone().next_day_start(chrono::Utc::now().timestamp())
+}
+
+/// 应用配置 (服务启动与热更新时调用); 全为 0 的预算视为未配置
+pub fn set_config(budgets: &HashMap<String, KeyBudget>) {
+    let configured = budgets
+        .iter()
+        .filter(|(key, budget)| !key.is_empty() && !budget.is_unlimited())
+        .map(|(key, budget)| {
+            (
+                fingerprint(key),
+                ConfiguredBudget {
+                    label: mask_key(key),
+                    budget: *budget,
+                },
+            )
+        })
+        .collect::<HashMap<_, _>>();
+    let any = !configured.is_empty();
+    if let Ok(mut guard) = BUDGETS.write() {
+        *guard = configured;
+    }
+    if any {
+        match crate::modules::account::get_data_dir() {
+            Ok(dir) => attach_store(&dir),
+            Err(e) => tracing::warn!("[KeyBudget] Data dir unavailable, usage will not persist: {}", e),
+        }
+    }
+}
+
+fn budget_for(key: &str) -> Option<(String, KeyBudget)> {
+    let id = fingerprint(key);
+    let budget = BUDGETS.read().ok()?.get(&id)?.budget;
+    Some((id, budget))
+}
+
+/// 分发前检查 Key 的预算并计入一次请求; 未配置预算的 Key 直接放行
+pub fn try_acquire(key: &str) -> Result<(), BudgetExceeded> {
+    let Some((id, budget)) = budget_for(key) else {
+        return Ok(());
+    };
+    let result = match LEDGER.lock() {
+        Ok(mut ledger) => ledger.acquire(&id, &budget, &today()),
+        Err(_) => return Ok(()),
+    };
+    match result {
+        Ok(()) => {
+            schedule_save();
+            Ok(())
+        }
+        Err((kind, used, limit)) => Err(BudgetExceeded {
+            kind,
+            used,
+            limit,
+            reset_at: next_reset(),
+        }),
+    }
+}
+
+/// 请求完成后按实际 (或估算) 用量累加 token
+pub fn record_usage(key: &str, tokens: u64) {
+    let Some((id, _)) = budget_for(key) else {
+        return;
+    };
+    if tokens == 0 {
+        return;
+    }
+    if let Ok(mut ledger) = LEDGER.lock() {
+        ledger.add_tokens(&id, tokens, &today());
+    }
+    schedule_save();
+}
+
+/// 所有已配置预算的 Key 的当日状态
+pub fn status() -> Vec<KeyBudgetStatus> {
+    let Ok(budgets) = BUDGETS.read() else {
+        return Vec::new();
+    };
+    let Ok(ledger) = LEDGER.lock() else {
+        return Vec::new();
+    };
+    let day = today();
+    let reset_at = next_reset();
+    let mut result: Vec<KeyBudgetStatus> = budgets
+        .iter()
+        .map(|(id, configured)| {
+            let (tokens_used, requests_used) = ledger.usage(id, &day);
+            let budget = configured.budget;
+            KeyBudgetStatus {
+                key: configured.label.clone(),
+                tokens_per_day: budget.tokens_per_day,
+                requests_per_day: budget.requests_per_day,
+                tokens_used,
+                requests_used,
+                reset_at,
+                exhausted: (budget.tokens_per_day > 0 && tokens_used >= budget.tokens_per_day)
+                    || (budget.requests_per_day > 0 && requests_used >= budget.requests_per_day),
+            }
+        })
+        .collect();
+    result.sort_by(|a, b| a.key.cmp(&b.key));
+    result
+}
+
+/// 按请求协议返回 429 错误 (带 Retry-After)
+pub fn exceeded_response(path: &str, exceeded: &BudgetExceeded) -> Response {
+    let message = exceeded.message();
+    let body = if path.starts_with("/v1/messages") {
+        json!({
+            "type": "error",
+            "error": {
+                "type": "rate_limit_error",
+                "message": message
+            }
+        })
+    } else if path.starts_with("/v1beta") {
+        json!({
+            "error": {
+                "code": 429,
+                "message": message,
+                "status": "RESOURCE_EXHAUSTED"
+            }
+        })
+    } else {
+        json!({
+            "error": {
+                "message": message,
+                "type": "rate_limit_exceeded",
+                "code": "key_budget_exceeded"
+            }
+        })
+    };
+
+    let mut response = (StatusCode::TOO_MANY_REQUESTS, Json(body)).into_response();
+    let retry_after = (exceeded.reset_at - chrono::Utc::now().timestamp()).max(1);
+    if let Ok(value) = HeaderValue::from_str(&retry_after.to_string()) {
+        response.headers_mut().insert("Retry-After", value);
+    }
+    response
+}
+
+/// 绑定计量文件 (首次配置预算时加载磁盘上的计量)
+fn attach_store(data_dir: &Path) {
+    let path = data_dir.join(STORE_FILE);
+    let Ok(mut store) = STORE_PATH.lock() else {
+        return;
+    };
+    if store.as_ref() == Some(&path) {
+        return;
+    }
+
+    let loaded = match std::fs::read_to_string(&path) {
+        Ok(content) => match serde_json::from_str::<Ledger>(&content) {
+            Ok(ledger) => ledger,
+            Err(e) => {
+                tracing::warn!("[KeyBudget] Ignoring unreadable {:?}: {}", path, e);
+                Ledger::default()
+            }
+        },
+        Err(_) => Ledger::default(),
+    };
+    if let Ok(mut ledger) = LEDGER.lock() {
+        *ledger = loaded;
+    }
+    *store = Some(path);
+}
+
+/// 计量变更后合并落盘: 间隔内只调度一次后台写入; 无 tokio 运行时时直接写入
+fn schedule_save() {
+    if SAVE_SCHEDULED.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) => {
+            handle.spawn(async {
+                tokio::time::sleep(SAVE_DEBOUNCE).await;
+                // 先清标记再取快照: 写入期间的新变更会调度下一次落盘
+                SAVE_SCHEDULED.store(false, Ordering::Release);
+                let _ = tokio::task::spawn_blocking(save).await;
+            });
+        }
+        Err(_) => {
+            SAVE_SCHEDULED.store(false, Ordering::Release);
+            save();
+        }
+    }
+}
+
+/// 立即落盘 (服务停止时调用, 避免丢失合并间隔内的计量)
+pub fn flush() {
+    save();
+}
+
+fn save() {
+    let Some(path) = STORE_PATH.lock().ok().and_then(|p| p.clone()) else {
+        return;
+    };
+    let json = match LEDGER.lock() {
+        Ok(ledger) => serde_json::to_string_pretty(&*ledger),
+        Err(_) => return,
+    };
+    match json {
+        Ok(json) => {
+            if let Err(e) = crate::utils::atomic_file::write_atomic(&path, json.as_bytes()) {
+                tracing::warn!("[KeyBudget] Failed to write {:?}: {}", path, e);
+            }
+        }
+        Err(e) => tracing::warn!("[KeyBudget] Failed to serialize usage: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: &str = "2026-03-01";
+
+    #[test]
+    fn test_token_budget_blocks_after_usage_reaches_limit() {
+        let mut ledger = Ledger::default();
+        let budget = KeyBudget {
+            tokens_per_day: 1000,
+            requests_per_day: 0,
+        };
+
+        assert!(ledger.acquire("k", &budget, DAY).is_ok());
+        ledger.add_tokens("k", 600, DAY);
+        assert!(ledger.acquire("k", &budget, DAY).is_ok());
+        ledger.add_tokens("k", 600, DAY);
+
+        assert_eq!(
+            ledger.acquire("k", &budget, DAY),
+            Err((BudgetKind::Tokens, 1200, 1000))
+        );
+        assert_eq!(ledger.usage("k", DAY), (1200, 2));
+    }
+
+    #[test]
+    fn test_request_budget_and_day_rollover() {
+        let mut ledger = Ledger::default();
+        let budget = KeyBudget {
+            tokens_per_day: 0,
+            requests_per_day: 2,
+        };
+
+        assert!(ledger.acquire("k", &budget, DAY).is_ok());
+        assert!(ledger.acquire("k", &budget, DAY).is_ok());
+        assert_eq!(
+            ledger.acquire("k", &budget, DAY),
+            Err((BudgetKind::Requests, 2, 2))
+        );
+
+        assert!(ledger.acquire("k", &budget, "2026-03-02").is_ok());
+        assert_eq!(ledger.usage("k", "2026-03-02"), (0, 1));
+        assert_eq!(ledger.usage("k", DAY), (0, 0));
+    }
+
+    #[test]
+    fn test_ledger_survives_round_trip() {
+        let mut ledger = Ledger::default();
+        ledger.add_tokens("k", 750, DAY);
+        let restored: Ledger =
+            serde_json::from_str(&serde_json::to_string(&ledger).unwrap()).unwrap();
+        assert_eq!(restored.usage("k", DAY), (750, 0));
+    }
+
+    #[test]
+    fn test_zero_budget_keys_are_unaffected() {
+        let key = "sk-zero-budget-test-key";
+        set_config(&HashMap::from([(key.to_string(), KeyBudget::default())]));
+        for _ in 0..5 {
+            assert!(try_acquire(key).is_ok());
+        }
+        assert!(status().iter().all(|s| s.key != mask_key(key)));
+    }
+
+    #[test]
+    fn test_exceeded_response_matches_protocol() {
+        let exceeded = BudgetExceeded {
+            kind: BudgetKind::Tokens,
+            used: 10,
+            limit: 10,
+            reset_at: chrono::Utc::now().timestamp() + 60,
+        };
+        let response = exceeded_response("/v1/messages", &exceeded);
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(response.headers().contains_key("Retry-After"));
+        assert!(exceeded.message().contains("Resets at"));
+    }
+}
@@ -0,0 +1,212 @@
+// 维护模式
+// 合盖休眠或手动调整账号前使用: 停止接收新请求, 但不打断进行中的请求 (含流式)。
+// 维护期间新请求按协议返回 503 + Retry-After; Claude Code 的 Warmup 探测仍正常应答,
+// 避免客户端把端点判定为失效。设置了时长时到期自动退出 (读取状态时惰性判断)。
+
+use axum::{
+    http::{HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::RwLock;
+
+const DEFAULT_MESSAGE: &str = "Proxy is in maintenance mode, please retry later";
+/// 未设置时长时建议客户端的重试间隔 (秒)
+const DEFAULT_RETRY_AFTER_SECS: i64 = 60;
+
+#[derive(Debug, Clone)]
+struct Maintenance {
+    message: String,
+    started_at: i64,
+    until: Option<i64>,
+}
+
+static STATE: Lazy<RwLock<Option<Maintenance>>> = Lazy::new(|| RwLock::new(None));
+static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+
+/// 维护模式状态 (随 get_proxy_status 返回)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MaintenanceStatus {
+    pub enabled: bool,
+    #[serde(default)]
+    pub message: Option<String>,
+    #[serde(default)]
+    pub started_at: Option<i64>,
+    /// 自动退出时间 (None = 需手动退出)
+    #[serde(default)]
+    pub until: Option<i64>,
+    /// 仍在进行中的请求数 (含未结束的流)
+    #[serde(default)]
+    pub draining: usize,
+}
+
+/// 进入 / 退出维护模式; duration_secs 为 None 或 0 时需手动退出
+pub fn set(enabled: bool, message: Option<String>, duration_secs: Option<u64>) -> MaintenanceStatus {
+    let next = enabled.then(|| {
+        let now = chrono::Utc::now().timestamp();
+        Maintenance {
+            message: message
+                .map(|m| m.trim().to_string())
+                .filter(|m| !m.is_empty())
+                .unwrap_or_else(|| DEFAULT_MESSAGE.to_string()),
+            started_at: now,
+            until: duration_secs.filter(|d| *d > 0).map(|d| now + d as i64),
+        }
+    });
+
+    match &next {
+        Some(m) => tracing::warn!(
+            "[Maintenance] Entered maintenance mode (until: {:?}, in-flight: {}): {}",
+            m.until,
+            in_flight(),
+            m.message
+        ),
+        None => tracing::info!("[Maintenance] Exited maintenance mode"),
+    }
+    if let Ok(mut state) = STATE.write() {
+        *state = next;
+    }
+    status()
+}
+
+pub fn status() -> MaintenanceStatus {
+    let draining = in_flight();
+    match active() {
+        Some(m) => MaintenanceStatus {
+            enabled: true,
+            message: Some(m.message),
+            started_at: Some(m.started_at),
+            until: m.until,
+            draining,
+        },
+        None => MaintenanceStatus {
+            draining,
+            ..Default::default()
+        },
+    }
+}
+
+pub fn is_active() -> bool {
+    active().is_some()
+}
+
+/// 当前生效的维护状态; 已到期时自动退出
+fn active() -> Option<Maintenance> {
+    let current = STATE.read().ok()?.clone()?;
+    match current.until {
+        Some(until) if until <= chrono::Utc::now().timestamp() => {
+            if let Ok(mut state) = STATE.write() {
+                // 期间可能已被重新设置, 只清除同一次维护
+                if state.as_ref().map(|m| m.started_at) == Some(current.started_at) {
+                    *state = None;
+                    tracing::info!("[Maintenance] Maintenance window elapsed, accepting requests again");
+                }
+            }
+            None
+        }
+        _ => Some(current),
+    }
+}
+
+/// 维护期间的拒绝响应 (按请求协议); 未处于维护模式时返回 None
+pub fn rejection(path: &str) -> Option<Response> {
+    let maintenance = active()?;
+    let message = maintenance.message;
+    let body = if path.starts_with("/v1/messages") {
+        json!({
+            "type": "error",
+            "error": {
+                "type": "overloaded_error",
+                "message": message
+            }
+        })
+    } else if path.starts_with("/v1beta") {
+        json!({
+            "error": {
+                "code": 503,
+                "message": message,
+                "status": "UNAVAILABLE"
+            }
+        })
+    } else {
+        json!({
+            "error": {
+                "message": message,
+                "type": "service_unavailable",
+                "code": "maintenance"
+            }
+        })
+    };
+
+    let mut response = (StatusCode::SERVICE_UNAVAILABLE, Json(body)).into_response();
+    let retry_after = maintenance
+        .until
+        .map(|until| (until - chrono::Utc::now().timestamp()).max(1))
+        .unwrap_or(DEFAULT_RETRY_AFTER_SECS);
+    if let Ok(value) = HeaderValue::from_str(&retry_after.to_string()) {
+        response.headers_mut().insert("Retry-After", value);
+    }
+    Some(response)
+}
+
+pub fn in_flight() -> usize {
+    IN_FLIGHT.load(Ordering::Relaxed)
+}
+
+/// 进行中请求计数; 流式响应由响应体持有, 流结束 (或客户端断开) 时释放
+pub struct InFlightGuard(());
+
+impl InFlightGuard {
+    pub fn acquire() -> Self {
+        IN_FLIGHT.fetch_add(1, Ordering::Relaxed);
+        Self(())
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        IN_FLIGHT.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_maintenance_rejects_by_protocol_and_expires() {
+        let guard = InFlightGuard::acquire();
+        let status = set(true, Some("  Back soon  ".to_string()), Some(120));
+        assert!(status.enabled);
+        assert_eq!(status.message.as_deref(), Some("Back soon"));
+        assert!(status.draining >= 1);
+
+        let claude = rejection("/v1/messages").unwrap();
+        assert_eq!(claude.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let retry_after: i64 = claude.headers()["Retry-After"].to_str().unwrap().parse().unwrap();
+        assert!(retry_after > 100 && retry_after <= 120);
+        assert!(rejection("/v1beta/models/gemini-2.5-flash:generateContent").is_some());
+        assert!(rejection("/v1/chat/completions").is_some());
+
+        // 手动模式使用默认重试间隔
+        set(true, None, None);
+        let response = rejection("/v1/chat/completions").unwrap();
+        assert_eq!(response.headers()["Retry-After"], "60");
+        assert_eq!(status().message.as_deref(), Some(DEFAULT_MESSAGE));
+
+        // 已到期的维护窗口自动退出
+        if let Ok(mut state) = STATE.write() {
+            state.as_mut().unwrap().until = Some(chrono::Utc::now().timestamp() - 1);
+        }
+        assert!(!is_active());
+        assert!(rejection("/v1/messages").is_none());
+
+        drop(guard);
+        set(false, None, None);
+        assert!(!status().enabled);
+    }
+}
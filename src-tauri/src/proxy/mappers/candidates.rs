@@ -0,0 +1,95 @@
+// 多候选响应处理
+// 自定义生成参数覆盖可能把 candidateCount 带到上游, 此时 Gemini 会返回多个候选,
+// 流式分片里各候选的 parts 交替出现。Claude / Legacy / Codex 协议只能表达一条消息,
+// 统一只取 index 0 的候选, 其余忽略并计数; OpenAI Chat 支持多个 choices, 按候选自身的 index 映射。
+
+use serde_json::Value;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static MULTI_CANDIDATE_RESPONSES: AtomicU64 = AtomicU64::new(0);
+
+/// 启动以来出现过多余候选 (并被忽略) 的响应数
+pub fn multi_candidate_responses() -> u64 {
+    MULTI_CANDIDATE_RESPONSES.load(Ordering::Relaxed)
+}
+
+/// 候选的 index; 流式分片可能只包含非 0 候选, 因此优先使用候选自带的 index, 缺失时才按数组位置
+pub fn candidate_index(candidate: &Value, position: usize) -> u32 {
+    candidate
+        .get("index")
+        .and_then(|v| v.as_u64())
+        .map(|i| i as u32)
+        .unwrap_or(position as u32)
+}
+
+/// 从候选列表中选出 index 0 的候选, 其余忽略。
+/// `warned` 由调用方按响应持有: 同一响应 (流) 只告警、计数一次。
+pub fn select_primary<'a, T>(
+    candidates: &'a [T],
+    index_of: impl Fn(&T) -> Option<u32>,
+    warned: &mut bool,
+) -> Option<&'a T> {
+    let mut primary = None;
+    let mut ignored = 0usize;
+    for (position, candidate) in candidates.iter().enumerate() {
+        let index = index_of(candidate).unwrap_or(position as u32);
+        if index == 0 && primary.is_none() {
+            primary = Some(candidate);
+        } else {
+            ignored += 1;
+        }
+    }
+
+    if ignored > 0 && !*warned {
+        *warned = true;
+        MULTI_CANDIDATE_RESPONSES.fetch_add(1, Ordering::Relaxed);
+        tracing::warn!(
+            "[Candidates] Upstream returned {} extra candidate(s) (candidateCount > 1?), only index 0 is used",
+            ignored
+        );
+    }
+    primary
+}
+
+/// JSON 版本: 选出 Gemini 响应 (已解包 `response`) 中 index 0 的候选
+pub fn primary_candidate<'a>(raw: &'a Value, warned: &mut bool) -> Option<&'a Value> {
+    let candidates = raw.get("candidates")?.as_array()?;
+    select_primary(
+        candidates,
+        |c| c.get("index").and_then(|v| v.as_u64()).map(|i| i as u32),
+        warned,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_selects_index_zero_and_counts_once_per_response() {
+        let before = multi_candidate_responses();
+        let mut warned = false;
+
+        let chunk_a = json!({ "candidates": [
+            { "index": 1, "content": { "parts": [{ "text": "B" }] } },
+            { "index": 0, "content": { "parts": [{ "text": "A" }] } }
+        ]});
+        let chunk_b = json!({ "candidates": [{ "index": 1, "content": { "parts": [{ "text": "B2" }] } }] });
+
+        let primary = primary_candidate(&chunk_a, &mut warned).unwrap();
+        assert_eq!(primary["content"]["parts"][0]["text"], "A");
+        assert!(primary_candidate(&chunk_b, &mut warned).is_none());
+        assert!(warned);
+        assert!(multi_candidate_responses() >= before + 1);
+    }
+
+    #[test]
+    fn test_single_candidate_without_index_is_primary() {
+        let mut warned = false;
+        let raw = json!({ "candidates": [{ "content": { "parts": [{ "text": "only" }] } }] });
+        assert!(primary_candidate(&raw, &mut warned).is_some());
+        assert!(!warned);
+        assert_eq!(candidate_index(&raw["candidates"][0], 3), 3);
+    }
+}
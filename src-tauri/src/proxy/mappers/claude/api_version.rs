@@ -0,0 +1,379 @@
+// Anthropic API 版本协商
+// 解析并校验 anthropic-version 请求头, 按版本能力表调整响应形状 (旧版本不返回 thinking 类块,
+// usage 不含 cache / server_tool_use 字段), 并在响应头中回显协商后的版本。
+
+use axum::body::Body;
+use axum::http::{header, HeaderMap, HeaderValue};
+use axum::response::Response;
+use bytes::Bytes;
+use dashmap::DashSet;
+use futures::StreamExt;
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use std::collections::BTreeSet;
+
+pub const VERSION_HEADER: &str = "anthropic-version";
+/// 未携带请求头时使用的版本
+pub const DEFAULT_VERSION: &str = "2023-06-01";
+
+/// 单个 API 版本的能力
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ApiVersion {
+    pub id: &'static str,
+    /// 是否返回 thinking / redacted_thinking 块
+    pub thinking_blocks: bool,
+    /// usage 是否包含 cache_* / server_tool_use 字段
+    pub extended_usage: bool,
+    pub deprecated: bool,
+}
+
+/// 已知版本 (按日期升序)
+const VERSIONS: &[ApiVersion] = &[
+    ApiVersion {
+        id: "2023-01-01",
+        thinking_blocks: false,
+        extended_usage: false,
+        deprecated: true,
+    },
+    ApiVersion {
+        id: "2023-06-01",
+        thinking_blocks: true,
+        extended_usage: true,
+        deprecated: false,
+    },
+];
+
+const THINKING_BLOCK_TYPES: [&str; 2] = ["thinking", "redacted_thinking"];
+const EXTENDED_USAGE_FIELDS: [&str; 3] = [
+    "cache_creation_input_tokens",
+    "cache_read_input_tokens",
+    "server_tool_use",
+];
+
+/// 已提示过废弃的版本 (每个版本只提示一次)
+static DEPRECATION_WARNED: Lazy<DashSet<&'static str>> = Lazy::new(DashSet::new);
+
+impl ApiVersion {
+    /// 是否需要改写响应
+    pub fn needs_shaping(&self) -> bool {
+        !self.thinking_blocks || !self.extended_usage
+    }
+}
+
+/// 按请求头协商版本
+/// - 缺省: DEFAULT_VERSION
+/// - 非 YYYY-MM-DD 或早于最早的已知版本: 拒绝
+/// - 介于已知版本之间: 取不晚于请求日期的最新版本
+/// - 晚于最新的已知版本: 取最新版本, 响应字段原样透传
+pub fn negotiate(headers: &HeaderMap) -> Result<ApiVersion, String> {
+    let raw = match headers.get(VERSION_HEADER) {
+        Some(value) => value
+            .to_str()
+            .map_err(|_| format!("{}: invalid header value", VERSION_HEADER))?
+            .trim(),
+        None => DEFAULT_VERSION,
+    };
+
+    let requested = chrono::NaiveDate::parse_from_str(raw, "%Y-%m-%d")
+        .map_err(|_| format!("{}: \"{}\" is not a valid version", VERSION_HEADER, raw))?;
+
+    let version = VERSIONS
+        .iter()
+        .rev()
+        .find(|v| {
+            chrono::NaiveDate::parse_from_str(v.id, "%Y-%m-%d")
+                .map(|known| known <= requested)
+                .unwrap_or(false)
+        })
+        .copied()
+        .ok_or_else(|| {
+            format!(
+                "{}: \"{}\" is not supported. Supported versions: {}",
+                VERSION_HEADER,
+                raw,
+                VERSIONS.iter().map(|v| v.id).collect::<Vec<_>>().join(", ")
+            )
+        })?;
+
+    if version.deprecated && DEPRECATION_WARNED.insert(version.id) {
+        tracing::warn!(
+            "[Anthropic-Version] Client uses deprecated API version {}; thinking blocks and extended usage fields are omitted",
+            version.id
+        );
+    }
+    Ok(version)
+}
+
+/// 改写非流式响应体 (ClaudeResponse JSON)
+pub fn shape_json(body: &mut Value, version: &ApiVersion) {
+    if !version.thinking_blocks {
+        if let Some(content) = body.get_mut("content").and_then(|c| c.as_array_mut()) {
+            content.retain(|block| !is_thinking_block(block));
+        }
+    }
+    if !version.extended_usage {
+        if let Some(usage) = body.get_mut("usage") {
+            strip_extended_usage(usage);
+        }
+    }
+}
+
+fn is_thinking_block(block: &Value) -> bool {
+    block
+        .get("type")
+        .and_then(|t| t.as_str())
+        .is_some_and(|t| THINKING_BLOCK_TYPES.contains(&t))
+}
+
+fn strip_extended_usage(usage: &mut Value) {
+    if let Some(obj) = usage.as_object_mut() {
+        for field in EXTENDED_USAGE_FIELDS {
+            obj.remove(field);
+        }
+    }
+}
+
+/// 流式响应改写: 丢弃 thinking 类块并重新编号后续块的 index
+#[derive(Debug, Default)]
+pub struct SseShaper {
+    /// 被丢弃块的原始 index
+    dropped: BTreeSet<u64>,
+    /// 按字节缓冲, 避免多字节字符被 chunk 边界截断
+    buffer: Vec<u8>,
+}
+
+impl SseShaper {
+    /// 输入一段字节, 返回已完整的 (改写后) 事件
+    pub fn push(&mut self, chunk: &[u8], version: &ApiVersion) -> String {
+        self.buffer.extend_from_slice(chunk);
+        let mut out = String::new();
+        while let Some(pos) = self.buffer.windows(2).position(|w| w == b"\n\n") {
+            let event: Vec<u8> = self.buffer.drain(..pos + 2).collect();
+            if let Some(shaped) = self.shape_event(&String::from_utf8_lossy(&event), version) {
+                out.push_str(&shaped);
+            }
+        }
+        out
+    }
+
+    /// 流结束时剩余的不完整数据原样输出
+    pub fn finish(&mut self) -> String {
+        String::from_utf8_lossy(&std::mem::take(&mut self.buffer)).into_owned()
+    }
+
+    fn shape_event(&mut self, event: &str, version: &ApiVersion) -> Option<String> {
+        let Some(data_line) = event.lines().find(|l| l.starts_with("data:")) else {
+            return Some(event.to_string());
+        };
+        let Ok(mut data) = serde_json::from_str::<Value>(data_line[5..].trim_start()) else {
+            return Some(event.to_string());
+        };
+
+        let event_type = data.get("type").and_then(|t| t.as_str()).unwrap_or("").to_string();
+        match event_type.as_str() {
+            "content_block_start" | "content_block_delta" | "content_block_stop"
+                if !version.thinking_blocks =>
+            {
+                let index = data.get("index").and_then(|i| i.as_u64()).unwrap_or(0);
+                if event_type == "content_block_start"
+                    && data.get("content_block").is_some_and(is_thinking_block)
+                {
+                    self.dropped.insert(index);
+                }
+                if self.dropped.contains(&index) {
+                    return None;
+                }
+                let shift = self.dropped.range(..index).count() as u64;
+                data["index"] = Value::from(index - shift);
+            }
+            "message_start" if !version.extended_usage => {
+                if let Some(usage) = data.get_mut("message").and_then(|m| m.get_mut("usage")) {
+                    strip_extended_usage(usage);
+                }
+            }
+            "message_delta" if !version.extended_usage => {
+                if let Some(usage) = data.get_mut("usage") {
+                    strip_extended_usage(usage);
+                }
+            }
+            _ => return Some(event.to_string()),
+        }
+
+        let mut shaped = String::new();
+        for line in event.lines().filter(|l| !l.is_empty()) {
+            if line.starts_with("data:") {
+                shaped.push_str(&format!("data: {}\n", data));
+            } else {
+                shaped.push_str(line);
+                shaped.push('\n');
+            }
+        }
+        shaped.push('\n');
+        Some(shaped)
+    }
+}
+
+/// 在响应上应用协商结果: 回显版本, 必要时改写响应体
+pub async fn apply(response: Response, version: ApiVersion) -> Response {
+    let (mut parts, body) = response.into_parts();
+    parts
+        .headers
+        .insert(VERSION_HEADER, HeaderValue::from_static(version.id));
+    if !version.needs_shaping() {
+        return Response::from_parts(parts, body);
+    }
+
+    let content_type = parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_string();
+
+    if content_type.starts_with("text/event-stream") {
+        let mut upstream = body.into_data_stream();
+        let shaped = async_stream::stream! {
+            let mut shaper = SseShaper::default();
+            while let Some(chunk) = upstream.next().await {
+                match chunk {
+                    Ok(bytes) => {
+                        let out = shaper.push(&bytes, &version);
+                        if !out.is_empty() {
+                            yield Ok::<Bytes, axum::Error>(Bytes::from(out));
+                        }
+                    }
+                    Err(e) => {
+                        yield Err(e);
+                        return;
+                    }
+                }
+            }
+            let rest = shaper.finish();
+            if !rest.is_empty() {
+                yield Ok(Bytes::from(rest));
+            }
+        };
+        return Response::from_parts(parts, Body::from_stream(shaped));
+    }
+
+    if content_type.starts_with("application/json") {
+        let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                tracing::error!("[Anthropic-Version] Failed to read response body: {}", e);
+                return Response::from_parts(parts, Body::empty());
+            }
+        };
+        let body = match serde_json::from_slice::<Value>(&bytes) {
+            Ok(mut value) => {
+                shape_json(&mut value, &version);
+                parts.headers.remove(header::CONTENT_LENGTH);
+                Body::from(value.to_string())
+            }
+            Err(_) => Body::from(bytes),
+        };
+        return Response::from_parts(parts, body);
+    }
+
+    Response::from_parts(parts, body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn headers(version: Option<&str>) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if let Some(v) = version {
+            headers.insert(VERSION_HEADER, HeaderValue::from_str(v).unwrap());
+        }
+        headers
+    }
+
+    fn canned_response() -> Value {
+        json!({
+            "id": "msg_1",
+            "type": "message",
+            "role": "assistant",
+            "content": [
+                { "type": "thinking", "thinking": "hmm", "signature": "sig" },
+                { "type": "redacted_thinking", "data": "xxx" },
+                { "type": "text", "text": "Hello" }
+            ],
+            "stop_reason": "end_turn",
+            "usage": {
+                "input_tokens": 10,
+                "output_tokens": 5,
+                "cache_read_input_tokens": 3
+            }
+        })
+    }
+
+    #[test]
+    fn test_negotiation() {
+        assert_eq!(negotiate(&headers(None)).unwrap().id, DEFAULT_VERSION);
+        assert_eq!(negotiate(&headers(Some("2023-06-01"))).unwrap().id, "2023-06-01");
+        // 介于已知版本之间 / 晚于最新版本
+        assert_eq!(negotiate(&headers(Some("2023-03-15"))).unwrap().id, "2023-01-01");
+        assert_eq!(negotiate(&headers(Some("2026-01-01"))).unwrap().id, "2023-06-01");
+
+        assert!(negotiate(&headers(Some("latest"))).unwrap_err().contains("not a valid version"));
+        let err = negotiate(&headers(Some("2022-12-31"))).unwrap_err();
+        assert!(err.contains("not supported") && err.contains("2023-06-01"));
+    }
+
+    #[test]
+    fn test_2023_06_01_keeps_response_unchanged() {
+        let version = negotiate(&headers(Some("2023-06-01"))).unwrap();
+        assert!(!version.needs_shaping());
+        let mut body = canned_response();
+        shape_json(&mut body, &version);
+        assert_eq!(body, canned_response());
+    }
+
+    #[test]
+    fn test_2023_01_01_strips_thinking_and_extended_usage() {
+        let version = negotiate(&headers(Some("2023-01-01"))).unwrap();
+        let mut body = canned_response();
+        shape_json(&mut body, &version);
+        assert_eq!(body["content"], json!([{ "type": "text", "text": "Hello" }]));
+        assert_eq!(body["usage"], json!({ "input_tokens": 10, "output_tokens": 5 }));
+    }
+
+    #[test]
+    fn test_2023_01_01_sse_drops_thinking_and_reindexes() {
+        let version = negotiate(&headers(Some("2023-01-01"))).unwrap();
+        let stream = concat!(
+            "event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"id\":\"msg_1\",\"usage\":{\"input_tokens\":10,\"output_tokens\":0,\"cache_read_input_tokens\":3}}}\n\n",
+            "event: content_block_start\ndata: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"thinking\",\"thinking\":\"\"}}\n\n",
+            "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"thinking_delta\",\"thinking\":\"hmm\"}}\n\n",
+            "event: content_block_stop\ndata: {\"type\":\"content_block_stop\",\"index\":0}\n\n",
+            "event: content_block_start\ndata: {\"type\":\"content_block_start\",\"index\":1,\"content_block\":{\"type\":\"text\",\"text\":\"\"}}\n\n",
+            "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":1,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hello\"}}\n\n",
+            "event: content_block_stop\ndata: {\"type\":\"content_block_stop\",\"index\":1}\n\n",
+            ": ping\n\n",
+            "event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n",
+        );
+
+        // 按任意位置切分输入
+        let mut shaper = SseShaper::default();
+        let mut out = String::new();
+        for chunk in stream.as_bytes().chunks(37) {
+            out.push_str(&shaper.push(chunk, &version));
+        }
+        out.push_str(&shaper.finish());
+
+        assert!(!out.contains("thinking"));
+        assert!(!out.contains("cache_read_input_tokens"));
+        let block_indexes: Vec<u64> = out
+            .lines()
+            .filter_map(|l| l.strip_prefix("data: "))
+            .filter_map(|d| serde_json::from_str::<Value>(d).ok())
+            .filter_map(|v| v.get("index").and_then(|i| i.as_u64()))
+            .collect();
+        assert_eq!(block_indexes, vec![0, 0, 0]);
+        assert!(out.contains(": ping\n\n"));
+        assert!(out.ends_with("event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n"));
+    }
+}
@@ -2,6 +2,7 @@
 // 用于非 Stream 请求的自动转换
 
 use super::models::*;
+use crate::proxy::mappers::stream_completeness::{CollectedResponse, StreamCompleteness};
 use bytes::Bytes;
 use futures::StreamExt;
 use serde_json::{json, Value};
@@ -25,13 +26,54 @@ fn parse_sse_line(line: &str) -> Option<(String, String)> {
     }
 }
 
+/// 完成当前块 (content_block_stop 或流在块中途结束时)
+fn finish_block(
+    response: &mut ClaudeResponse,
+    current_text: &mut String,
+    current_thinking: &mut String,
+    current_signature: &mut Option<String>,
+    current_tool_use: &mut Option<Value>,
+    current_tool_input: &mut String,
+) {
+    if !current_text.is_empty() {
+        response.content.push(ContentBlock::Text {
+            text: std::mem::take(current_text),
+        });
+    } else if !current_thinking.is_empty() {
+        response.content.push(ContentBlock::Thinking {
+            thinking: std::mem::take(current_thinking),
+            signature: current_signature.take(),
+            cache_control: None,
+        });
+    } else if let Some(tool_use) = current_tool_use.take() {
+        // 构建 tool_use 块
+        let id = tool_use.get("id").and_then(|v| v.as_str()).unwrap_or("unknown").to_string();
+        let name = tool_use.get("name").and_then(|v| v.as_str()).unwrap_or("unknown").to_string();
+        let input = if !current_tool_input.is_empty() {
+            serde_json::from_str(current_tool_input).unwrap_or(json!({}))
+        } else {
+            json!({})
+        };
+
+        response.content.push(ContentBlock::ToolUse {
+            id,
+            name,
+            input,
+            signature: None,
+            cache_control: None,
+        });
+        current_tool_input.clear();
+    }
+}
+
 /// 将 SSE Stream 收集为完整的 Claude Response
 ///
 /// 此函数接收一个 SSE 字节流，解析所有事件，并重建完整的 ClaudeResponse 对象。
 /// 这使得非 Stream 客户端可以透明地享受 Stream 模式的配额优势。
+/// 未收到 message_stop 或最终 usage 时视为截断, 已有内容以 stop_reason = "max_tokens" 返回。
 pub async fn collect_stream_to_json<S>(
     mut stream: S,
-) -> Result<ClaudeResponse, String>
+) -> Result<CollectedResponse<ClaudeResponse>, String>
 where
     S: futures::Stream<Item = Result<Bytes, io::Error>> + Unpin,
 {
@@ -82,6 +124,7 @@ where
             cache_read_input_tokens: None,
             cache_creation_input_tokens: None,
             server_tool_use: None,
+            service_tier: None,
         },
     };
 
@@ -91,6 +134,7 @@ where
     let mut current_signature: Option<String> = None;
     let mut current_tool_use: Option<Value> = None;
     let mut current_tool_input = String::new();
+    let mut completeness = StreamCompleteness::default();
 
     for event in events {
         match event.event_type.as_str() {
@@ -164,37 +208,14 @@ where
 
             "content_block_stop" => {
                 // 完成当前块
-                if !current_text.is_empty() {
-                    response.content.push(ContentBlock::Text {
-                        text: current_text.clone(),
-                    });
-                    current_text.clear();
-                } else if !current_thinking.is_empty() {
-                    response.content.push(ContentBlock::Thinking {
-                        thinking: current_thinking.clone(),
-                        signature: current_signature.take(),
-                        cache_control: None,
-                    });
-                    current_thinking.clear();
-                } else if let Some(tool_use) = current_tool_use.take() {
-                    // 构建 tool_use 块
-                    let id = tool_use.get("id").and_then(|v| v.as_str()).unwrap_or("unknown").to_string();
-                    let name = tool_use.get("name").and_then(|v| v.as_str()).unwrap_or("unknown").to_string();
-                    let input = if !current_tool_input.is_empty() {
-                        serde_json::from_str(&current_tool_input).unwrap_or(json!({}))
-                    } else {
-                        json!({})
-                    };
-
-                    response.content.push(ContentBlock::ToolUse {
-                        id,
-                        name,
-                        input,
-                        signature: None,
-                        cache_control: None,
-                    });
-                    current_tool_input.clear();
-                }
+                finish_block(
+                    &mut response,
+                    &mut current_text,
+                    &mut current_thinking,
+                    &mut current_signature,
+                    &mut current_tool_use,
+                    &mut current_tool_input,
+                );
             }
 
             "message_delta" => {
@@ -202,8 +223,16 @@ where
                     if let Some(stop_reason) = delta.get("stop_reason").and_then(|v| v.as_str()) {
                         response.stop_reason = stop_reason.to_string();
                     }
+                    if let Some(stop_sequence) = delta.get("stop_sequence").and_then(|v| v.as_str()) {
+                        response.stop_sequence = Some(stop_sequence.to_string());
+                    }
                 }
                 if let Some(usage) = event.data.get("usage") {
+                    // 本地补发的终止事件 usage 全为 0
+                    let tokens = |key: &str| usage.get(key).and_then(|v| v.as_u64()).unwrap_or(0);
+                    if tokens("input_tokens") > 0 || tokens("output_tokens") > 0 {
+                        completeness.saw_final_usage = true;
+                    }
                     if let Ok(u) = serde_json::from_value::<Usage>(usage.clone()) {
                         response.usage = u;
                     }
@@ -212,6 +241,7 @@ where
 
             "message_stop" => {
                 // Stream 结束
+                completeness.saw_stop = true;
                 break;
             }
 
@@ -226,7 +256,25 @@ where
         }
     }
 
-    Ok(response)
+    // 流在块中途结束: 保留已收到的部分
+    finish_block(
+        &mut response,
+        &mut current_text,
+        &mut current_thinking,
+        &mut current_signature,
+        &mut current_tool_use,
+        &mut current_tool_input,
+    );
+
+    completeness.has_content = !response.content.is_empty();
+    if !completeness.is_complete() && completeness.has_content {
+        response.stop_reason = "max_tokens".to_string();
+    }
+
+    Ok(CollectedResponse {
+        response,
+        completeness,
+    })
 }
 
 #[cfg(test)]
@@ -254,7 +302,7 @@ mod tests {
         let result = collect_stream_to_json(byte_stream).await;
         assert!(result.is_ok());
 
-        let response = result.unwrap();
+        let response = result.unwrap().response;
         assert_eq!(response.id, "msg_123");
         assert_eq!(response.model, "claude-3-5-sonnet");
         assert_eq!(response.content.len(), 1);
@@ -287,7 +335,7 @@ mod tests {
         let result = collect_stream_to_json(byte_stream).await;
         assert!(result.is_ok());
 
-        let response = result.unwrap();
+        let response = result.unwrap().response;
         
         if let ContentBlock::Thinking { thinking, signature, .. } = &response.content[0] {
             assert_eq!(thinking, "I am thinking");
@@ -297,4 +345,54 @@ mod tests {
             panic!("Expected Thinking block");
         }
     }
+
+    const FULL_STREAM: [&str; 6] = [
+        "event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"id\":\"msg_cut\",\"type\":\"message\",\"role\":\"assistant\",\"model\":\"claude-sonnet-4-5\",\"content\":[],\"stop_reason\":null,\"usage\":{\"input_tokens\":0,\"output_tokens\":0}}}\n\n",
+        "event: content_block_start\ndata: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"text\",\"text\":\"\"}}\n\n",
+        "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hello\"}}\n\n",
+        "event: content_block_stop\ndata: {\"type\":\"content_block_stop\",\"index\":0}\n\n",
+        "event: message_delta\ndata: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"end_turn\"},\"usage\":{\"input_tokens\":12,\"output_tokens\":5}}\n\n",
+        "event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n",
+    ];
+
+    async fn collect(chunks: Vec<&'static str>) -> CollectedResponse<ClaudeResponse> {
+        let byte_stream = stream::iter(
+            chunks.into_iter().map(|s| Ok::<Bytes, io::Error>(Bytes::from(s)))
+        );
+        collect_stream_to_json(byte_stream).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_collect_detects_truncated_streams() {
+        let full = collect(FULL_STREAM.to_vec()).await;
+        assert!(full.completeness.is_complete());
+        assert_eq!(full.response.stop_reason, "end_turn");
+
+        // 只收到 message_start: 无内容, 由调用方重试
+        let cut = collect(FULL_STREAM[..1].to_vec()).await;
+        assert!(!cut.completeness.is_complete());
+        assert!(!cut.completeness.has_content);
+
+        // 文本块中途断开: 保留已收到的文本并标记为截断
+        let cut = collect(FULL_STREAM[..3].to_vec()).await;
+        assert!(!cut.completeness.is_complete());
+        assert!(cut.completeness.has_content);
+        assert_eq!(cut.response.stop_reason, "max_tokens");
+        assert!(matches!(&cut.response.content[0], ContentBlock::Text { text } if text == "Hello"));
+
+        // 块已结束但没有最终 usage
+        let cut = collect(FULL_STREAM[..4].to_vec()).await;
+        assert!(!cut.completeness.is_complete());
+        assert_eq!(cut.response.stop_reason, "max_tokens");
+
+        // 上游断开后本地补发的终止事件 (usage 为 0) 不算完整
+        let mut forced = FULL_STREAM[..4].to_vec();
+        forced.push("event: message_delta\ndata: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"end_turn\"},\"usage\":{\"input_tokens\":0,\"output_tokens\":0}}\n\n");
+        forced.push(FULL_STREAM[5]);
+        let cut = collect(forced).await;
+        assert!(cut.completeness.saw_stop);
+        assert!(!cut.completeness.is_complete());
+        assert_eq!(cut.response.stop_reason, "max_tokens");
+        assert_eq!(cut.completeness.confidence(), 0.5);
+    }
 }
@@ -8,14 +8,26 @@ pub mod streaming;
 pub mod utils;
 pub mod thinking_utils;
 pub mod collector;
+pub mod api_version;
+pub mod stop_sequences;
 
 pub use models::*;
-pub use request::{transform_claude_request_in, clean_cache_control_from_messages, merge_consecutive_messages};
+pub use request::{
+    transform_claude_request_in, transform_claude_request_in_with_mode, clean_cache_control_from_messages,
+    merge_consecutive_messages, requested_cache_ttl, validate_image_blocks, validate_tool_choice,
+};
 pub use response::transform_response;
 pub use streaming::{PartProcessor, StreamingState};
-pub use thinking_utils::{close_tool_loop_for_thinking, filter_invalid_thinking_blocks_with_family};
+pub use thinking_utils::{
+    close_tool_loop_for_thinking, filter_invalid_thinking_blocks_with_family,
+    validate_follow_up_tool_results,
+};
 pub use collector::collect_stream_to_json;
 
+use crate::proxy::mappers::candidates::primary_candidate;
+use crate::proxy::mappers::error_classifier::{
+    is_context_window_exceeded, PROMPT_TOO_LONG_CODE, PROMPT_TOO_LONG_MESSAGE, PROMPT_TOO_LONG_SUGGESTION,
+};
 use bytes::Bytes;
 use futures::Stream;
 use std::pin::Pin;
@@ -28,8 +40,14 @@ pub fn create_claude_sse_stream(
     session_id: Option<String>, // [NEW v3.3.17] Session ID for signature caching
     scaling_enabled: bool, // [NEW] Flag for context usage scaling
     context_limit: u32,
-    estimated_prompt_tokens: Option<u32>, // [FIX] Estimated tokens for calibrator learning
+    hidden_input_tokens: u32, // 从 input_tokens 中扣除的注入内容 (仅缩放时非 0)
+    estimated_prompt: Option<crate::proxy::mappers::estimation_calibrator::PromptEstimate>, // Labeled estimate for calibrator learning
     message_count: usize, // [NEW v4.0.0] Message count for rewind detection
+    post_processor: Option<std::sync::Arc<crate::proxy::common::post_process::TextPostProcessor>>,
+    service_tier: Option<String>, // 回显在 usage.service_tier 中的生效等级
+    strip_thinking: bool, // 未请求 thinking: 丢弃上游仍返回的 thought parts
+    stop_sequences: Vec<String>, // 客户端 stop_sequences, 命中后截断并返回 stop_reason = "stop_sequence"
+    dedup_resent_chunks: bool, // 裁掉上游重发的与已下发尾部重合的文本
 ) -> Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>> {
     use async_stream::stream;
     use bytes::BytesMut;
@@ -41,10 +59,16 @@ pub fn create_claude_sse_stream(
         state.message_count = message_count; // [NEW v4.0.0] Set message count
         state.scaling_enabled = scaling_enabled; // Set scaling enabled flag
         state.context_limit = context_limit;
-        state.estimated_prompt_tokens = estimated_prompt_tokens; // [FIX] Pass estimated tokens
+        state.hidden_input_tokens = hidden_input_tokens;
+        state.estimated_prompt = estimated_prompt; // [FIX] Pass estimated tokens
+        state.post_processor = post_processor;
+        state.service_tier = service_tier;
+        state.strip_thinking = strip_thinking;
+        state.set_stop_sequences(stop_sequences);
+        state.set_dedup_resent_chunks(dedup_resent_chunks);
         let mut buffer = BytesMut::new();
 
-        loop {
+        'upstream: loop {
             // [NEW] 30秒心跳保活: 延长超时时间以兼容长延迟模型
             let next_chunk = tokio::time::timeout(
                 std::time::Duration::from_secs(30),
@@ -69,6 +93,10 @@ pub fn create_claude_sse_stream(
                                             yield Ok(sse_chunk);
                                         }
                                     }
+                                    // 已下发上下文超限错误, 流到此终止
+                                    if state.context_exceeded {
+                                        break 'upstream;
+                                    }
                                 }
                             }
                         }
@@ -89,7 +117,7 @@ pub fn create_claude_sse_stream(
         // [FIX #859] Post-thinking interruption recovery
         // If we have sent thinking but NO content (text/tool_use) and the stream ended (or timed out without DONE),
         // we must provide a fallback to prevent 0-token errors on client side.
-        if state.has_thinking && !state.has_content {
+        if state.has_thinking && !state.has_content && !state.context_exceeded {
             tracing::warn!("[{}] Stream interrupted after thinking (No Content). Triggering recovery...", trace_id);
             
             // 1. Force close thinking block if open
@@ -123,6 +151,7 @@ pub fn create_claude_sse_stream(
                 cache_read_input_tokens: None,
                 cache_creation_input_tokens: None,
                 server_tool_use: None,
+                service_tier: None,
             };
 
             let delta = serde_json::json!({
@@ -171,13 +200,28 @@ fn process_sse_line(line: &str, state: &mut StreamingState, trace_id: &str, emai
     // 解包 response 字段 (如果存在)
     let raw_json = json_value.get("response").unwrap_or(&json_value);
 
+    // 上游在 200 流中途下发的错误事件: 上下文超限时转换为结构化的 Prompt is too long 错误
+    if let Some(message) = raw_json
+        .get("error")
+        .or_else(|| json_value.get("error"))
+        .and_then(|e| e.get("message"))
+        .and_then(|m| m.as_str())
+    {
+        if is_context_window_exceeded(message) {
+            return Some(emit_context_exceeded(state, trace_id, email, message));
+        }
+    }
+
     // 发送 message_start
     if !state.message_start_sent {
         chunks.push(state.emit_message_start(raw_json));
     }
 
+    // 多候选时只取 index 0, 否则各候选的 parts 会交替拼进同一条消息
+    let candidate = primary_candidate(raw_json, &mut state.extra_candidates_warned);
+
     // 捕获 groundingMetadata (Web Search)
-    if let Some(candidate) = raw_json.get("candidates").and_then(|c| c.get(0)) {
+    if let Some(candidate) = candidate {
         if let Some(grounding) = candidate.get("groundingMetadata") {
             // 提取搜索词
             if let Some(query) = grounding.get("webSearchQueries")
@@ -198,9 +242,7 @@ fn process_sse_line(line: &str, state: &mut StreamingState, trace_id: &str, emai
     }
 
     // 处理所有 parts
-    if let Some(parts) = raw_json
-        .get("candidates")
-        .and_then(|c| c.get(0))
+    if let Some(parts) = candidate
         .and_then(|cand| cand.get("content"))
         .and_then(|content| content.get("parts"))
         .and_then(|p| p.as_array())
@@ -231,9 +273,7 @@ fn process_sse_line(line: &str, state: &mut StreamingState, trace_id: &str, emai
     */
 
     // 检查是否结束
-    if let Some(finish_reason) = raw_json
-        .get("candidates")
-        .and_then(|c| c.get(0))
+    if let Some(finish_reason) = candidate
         .and_then(|cand| cand.get("finishReason"))
         .and_then(|f| f.as_str())
     {
@@ -259,6 +299,13 @@ fn process_sse_line(line: &str, state: &mut StreamingState, trace_id: &str, emai
              );
         }
 
+        // 按会话记录隐式缓存命中 (衡量会话预热效果)
+        if let (Some(session_id), Some(u)) = (state.session_id.as_deref(), usage.as_ref()) {
+            crate::proxy::session_prewarm::record_usage(session_id, u.cached_content_token_count.unwrap_or(0));
+        }
+
+        // 分片拼接中的函数调用此时已完整
+        chunks.extend(PartProcessor::new(state).flush_function_call());
         chunks.extend(state.emit_finish(Some(finish_reason), usage.as_ref()));
     }
 
@@ -269,10 +316,41 @@ fn process_sse_line(line: &str, state: &mut StreamingState, trace_id: &str, emai
     }
 }
 
+/// 上下文超限: 关闭未结束的内容块, 以 Anthropic error 事件终止流 (与非流式分支相同的提示文案)。
+/// 属于请求本身的问题, 不计为账号失败。
+fn emit_context_exceeded(state: &mut StreamingState, trace_id: &str, email: &str, upstream_message: &str) -> Vec<Bytes> {
+    tracing::error!(
+        "[{}] ✗ Stream failed | Account: {} | Reason: {} | Upstream: {}",
+        trace_id,
+        email,
+        PROMPT_TOO_LONG_CODE,
+        upstream_message
+    );
+
+    let mut chunks = state.end_block();
+    chunks.push(state.emit(
+        "error",
+        serde_json::json!({
+            "type": "error",
+            "error": {
+                "type": "invalid_request_error",
+                "code": PROMPT_TOO_LONG_CODE,
+                "message": PROMPT_TOO_LONG_MESSAGE,
+                "suggestion": PROMPT_TOO_LONG_SUGGESTION
+            }
+        }),
+    ));
+    state.context_exceeded = true;
+    // error 事件即为流的终点, 不再补发 message_stop
+    state.message_stop_sent = true;
+    chunks
+}
+
 /// 发送强制结束事件
 pub fn emit_force_stop(state: &mut StreamingState) -> Vec<Bytes> {
     if !state.message_stop_sent {
-        let mut chunks = state.emit_finish(None, None);
+        let mut chunks = PartProcessor::new(state).flush_function_call();
+        chunks.extend(state.emit_finish(None, None));
         if chunks.is_empty() {
             chunks.push(Bytes::from(
                 "event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n",
@@ -477,8 +555,14 @@ mod tests {
             None,
             false,
             1_000,
+            0,
             None,
             1, // message_count
+            None,
+            None,
+            false,
+            Vec::new(),
+            false,
         );
 
         // 3. 收集输出
@@ -501,4 +585,505 @@ mod tests {
         assert!(output.contains("\"usage\":"));
         assert!(output.contains("\"output_tokens\":100")); // Should contain the recovery usage
     }
+
+    #[tokio::test]
+    async fn test_interleaved_thinking_order_matches_between_stream_and_non_stream() {
+        use futures::StreamExt;
+
+        let fixture = serde_json::json!({
+            "candidates": [{
+                "content": {
+                    "role": "model",
+                    "parts": [
+                        { "text": "First I need a plan.", "thought": true, "thoughtSignature": "sig_plan" },
+                        { "text": "Let me read the file." },
+                        { "text": "The path must be absolute.", "thought": true },
+                        { "functionCall": { "name": "Read", "args": { "file_path": "/tmp/a.rs" }, "id": "call_1" } }
+                    ]
+                },
+                "finishReason": "STOP"
+            }],
+            "usageMetadata": { "promptTokenCount": 10, "candidatesTokenCount": 20, "totalTokenCount": 30 },
+            "modelVersion": "gemini-3-pro",
+            "responseId": "msg_interleaved"
+        });
+
+        fn kinds(blocks: &[ContentBlock]) -> Vec<&'static str> {
+            blocks
+                .iter()
+                .map(|block| match block {
+                    ContentBlock::Thinking { .. } => "thinking",
+                    ContentBlock::Text { .. } => "text",
+                    ContentBlock::ToolUse { .. } => "tool_use",
+                    _ => "other",
+                })
+                .collect()
+        }
+
+        // 流式: Gemini SSE -> Claude SSE -> 收集为完整响应
+        let sse = format!("data: {}\n\n", fixture);
+        let gemini_stream = futures::stream::iter(vec![Ok::<_, reqwest::Error>(bytes::Bytes::from(sse))]);
+        let claude_stream = create_claude_sse_stream(
+            Box::pin(gemini_stream),
+            "trace_test".to_string(),
+            "test@example.com".to_string(),
+            None,
+            false,
+            1_000_000,
+            0,
+            None,
+            1,
+            None,
+            None,
+            false,
+            Vec::new(),
+        )
+        .map(|chunk| chunk.map_err(std::io::Error::other));
+        let streamed = collect_stream_to_json(Box::pin(claude_stream)).await.unwrap().response;
+
+        // 非流式: 携带 interleaved-thinking beta
+        let gemini_response: GeminiResponse = serde_json::from_value(fixture).unwrap();
+        let non_streamed = transform_response(
+            &gemini_response,
+            false,
+            1_000_000,
+            0,
+            None,
+            "gemini-3-pro".to_string(),
+            1,
+            true,
+            None,
+            false,
+            &[],
+        )
+        .unwrap();
+
+        assert_eq!(kinds(&streamed.content), vec!["thinking", "text", "thinking", "tool_use"]);
+        assert_eq!(kinds(&non_streamed.content), kinds(&streamed.content));
+    }
+
+    #[tokio::test]
+    async fn test_multi_candidate_stream_does_not_interleave() {
+        use futures::StreamExt;
+
+        // candidateCount = 2: 两个候选的分片交替到达, 最后一个分片同时携带两个候选
+        let chunks = [
+            serde_json::json!({ "candidates": [{ "index": 0, "content": { "role": "model", "parts": [{ "text": "Hello" }] } }] }),
+            serde_json::json!({ "candidates": [{ "index": 1, "content": { "role": "model", "parts": [{ "text": "Bonjour" }] } }] }),
+            serde_json::json!({ "candidates": [
+                { "index": 1, "content": { "role": "model", "parts": [{ "text": " le monde" }] }, "finishReason": "STOP" },
+                { "index": 0, "content": { "role": "model", "parts": [{ "text": " world" }] }, "finishReason": "STOP" }
+            ]}),
+        ];
+        let sse: Vec<_> = chunks
+            .iter()
+            .map(|c| Ok::<_, reqwest::Error>(bytes::Bytes::from(format!("data: {}\n\n", c))))
+            .collect();
+
+        let claude_stream = create_claude_sse_stream(
+            Box::pin(futures::stream::iter(sse)),
+            "trace_test".to_string(),
+            "test@example.com".to_string(),
+            None,
+            false,
+            1_000_000,
+            0,
+            None,
+            1,
+            None,
+            None,
+            false,
+            Vec::new(),
+            false,
+        )
+        .map(|chunk| chunk.map_err(std::io::Error::other));
+        let streamed = collect_stream_to_json(Box::pin(claude_stream)).await.unwrap().response;
+
+        let text: String = streamed
+            .content
+            .iter()
+            .filter_map(|block| match block {
+                ContentBlock::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(text, "Hello world");
+        assert_eq!(streamed.stop_reason, "end_turn");
+    }
+
+    #[tokio::test]
+    async fn test_resent_first_chunk_is_not_duplicated() {
+        use futures::StreamExt;
+
+        // 合成的上游流 (手写, 非真实抓包; 没有可公开的真实重放抓包):
+        // 首个内容分片在内部重试后被重发, 且重发分片末尾带出了新内容
+        let fixture = include_str!("streaming/fixtures/synthetic_resent_first_chunk.sse");
+        let gemini_stream = futures::stream::iter(vec![Ok::<_, reqwest::Error>(bytes::Bytes::from(fixture))]);
+        let claude_stream = create_claude_sse_stream(
+            Box::pin(gemini_stream),
+            "trace_test".to_string(),
+            "test@example.com".to_string(),
+            None,
+            false,
+            1_000_000,
+            0,
+            None,
+            1,
+            None,
+            None,
+            false,
+            Vec::new(),
+            true,
+        )
+        .map(|chunk| chunk.map_err(std::io::Error::other));
+        let streamed = collect_stream_to_json(Box::pin(claude_stream)).await.unwrap().response;
+
+        let text: String = streamed
+            .content
+            .iter()
+            .filter_map(|block| match block {
+                ContentBlock::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            text,
+            "The failing test comes from the retry loop in handler.rs, which resets the attempt counter \
+             after every account rotation. Fix: keep the counter across rotations."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resent_chunk_kept_when_dedup_disabled() {
+        use futures::StreamExt;
+
+        // 关闭时原样转发, 不改动任何文本
+        let fixture = include_str!("streaming/fixtures/synthetic_resent_first_chunk.sse");
+        let gemini_stream = futures::stream::iter(vec![Ok::<_, reqwest::Error>(bytes::Bytes::from(fixture))]);
+        let claude_stream = create_claude_sse_stream(
+            Box::pin(gemini_stream),
+            "trace_test".to_string(),
+            "test@example.com".to_string(),
+            None,
+            false,
+            1_000_000,
+            0,
+            None,
+            1,
+            None,
+            None,
+            false,
+            Vec::new(),
+            false,
+        )
+        .map(|chunk| chunk.map_err(std::io::Error::other));
+        let streamed = collect_stream_to_json(Box::pin(claude_stream)).await.unwrap().response;
+
+        let text: String = streamed
+            .content
+            .iter()
+            .filter_map(|block| match block {
+                ContentBlock::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(text.matches("The failing test comes from").count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_split_function_call_is_reassembled() {
+        use futures::StreamExt;
+
+        // 合成的上游流 (脚本生成的 LINE_NNNN 内容, 非真实抓包; 原始抓包未保留, 无法提供), 规模与约 300KB 的实际大型调用相当:
+        // Write 调用的参数分 16 个分片到达, 分片之间夹带空白文本; 按 8KB 网络读取切分, 事件跨读取边界
+        let fixture = include_str!("streaming/fixtures/synthetic_split_function_call.sse");
+        assert!(fixture.len() > 300_000);
+        let reads: Vec<_> = fixture
+            .as_bytes()
+            .chunks(8 * 1024)
+            .map(|chunk| Ok::<_, reqwest::Error>(bytes::Bytes::copy_from_slice(chunk)))
+            .collect();
+        let gemini_stream = futures::stream::iter(reads);
+        let claude_stream = create_claude_sse_stream(
+            Box::pin(gemini_stream),
+            "trace_test".to_string(),
+            "test@example.com".to_string(),
+            None,
+            false,
+            1_000_000,
+            0,
+            None,
+            1,
+            None,
+            None,
+            false,
+            Vec::new(),
+            false,
+        )
+        .map(|chunk| chunk.map_err(std::io::Error::other));
+        let streamed = collect_stream_to_json(Box::pin(claude_stream)).await.unwrap().response;
+
+        let tool_uses: Vec<_> = streamed
+            .content
+            .iter()
+            .filter_map(|block| match block {
+                ContentBlock::ToolUse { name, input, .. } => Some((name.as_str(), input)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(tool_uses.len(), 1);
+        let (name, input) = tool_uses[0];
+        assert_eq!(name, "Write");
+        assert_eq!(input["file_path"], "/repo/src/generated.rs");
+
+        let expected: String = (0..5200)
+            .map(|i| format!("    pub const LINE_{:04}: &str = \"generated line {}\";\n", i, i))
+            .collect();
+        assert_eq!(input["content"].as_str().unwrap(), expected);
+        assert_eq!(streamed.stop_reason, "tool_use");
+
+        let text: String = streamed
+            .content
+            .iter()
+            .filter_map(|block| match block {
+                ContentBlock::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(text.trim(), "Writing the file now.");
+    }
+
+    #[tokio::test]
+    async fn test_unrequested_thoughts_are_stripped_from_stream() {
+        use futures::StreamExt;
+
+        // 请求未开启 thinking (includeThoughts: false), 上游仍返回了 thought parts
+        let fixture = concat!(
+            "data: {\"response\": {\"candidates\": [{\"content\": {\"role\": \"model\",\"parts\": [{\"text\": \"The user wants a one-line answer.\",\"thought\": true,\"thoughtSignature\": \"c2lnX3Vud2FudGVk\"}]}}],\"modelVersion\": \"gemini-3-flash\",\"responseId\": \"resp_nothink\"}}\r\n\r\n",
+            "data: {\"response\": {\"candidates\": [{\"content\": {\"role\": \"model\",\"parts\": [{\"text\": \"Use `cargo check`.\"}]}}],\"modelVersion\": \"gemini-3-flash\",\"responseId\": \"resp_nothink\"}}\r\n\r\n",
+            "data: {\"response\": {\"candidates\": [{\"content\": {\"role\": \"model\",\"parts\": [{\"text\": \"\",\"thoughtSignature\": \"c2lnX3RyYWlsaW5n\"}]},\"finishReason\": \"STOP\"}],\"usageMetadata\": {\"promptTokenCount\": 12,\"candidatesTokenCount\": 6,\"totalTokenCount\": 18},\"modelVersion\": \"gemini-3-flash\",\"responseId\": \"resp_nothink\"}}\r\n\r\n",
+        );
+        let gemini_stream = futures::stream::iter(vec![Ok::<_, reqwest::Error>(bytes::Bytes::from(fixture))]);
+        let claude_stream = create_claude_sse_stream(
+            Box::pin(gemini_stream),
+            "trace_test".to_string(),
+            "test@example.com".to_string(),
+            None,
+            false,
+            1_000_000,
+            0,
+            None,
+            1,
+            None,
+            None,
+            true,
+            Vec::new(),
+            false,
+        );
+        let output: String = claude_stream
+            .map(|chunk| String::from_utf8(chunk.unwrap().to_vec()).unwrap())
+            .collect::<Vec<_>>()
+            .await
+            .concat();
+
+        assert!(!output.contains("thinking"));
+        assert!(!output.contains("one-line answer"));
+        assert!(output.contains("Use `cargo check`."));
+        assert!(output.contains("message_stop"));
+    }
+
+    #[tokio::test]
+    async fn test_context_exceeded_mid_stream_becomes_prompt_too_long_error() {
+        use futures::StreamExt;
+
+        // 合成的上游流 (按 v1internal 流的格式手写, 非真实抓包; 没有可用的实录):
+        // 200 开始输出思考与文本, 随后下发上下文超限错误; 按小块网络读取切分, 错误事件跨读取边界
+        let fixture = include_str!("streaming/fixtures/synthetic_context_exceeded_mid_stream.sse");
+        let reads: Vec<_> = fixture
+            .as_bytes()
+            .chunks(97)
+            .map(|chunk| Ok::<_, reqwest::Error>(bytes::Bytes::copy_from_slice(chunk)))
+            .collect();
+        let gemini_stream = futures::stream::iter(reads);
+        let claude_stream = create_claude_sse_stream(
+            Box::pin(gemini_stream),
+            "trace_test".to_string(),
+            "test@example.com".to_string(),
+            None,
+            false,
+            1_000_000,
+            0,
+            None,
+            1,
+            None,
+            None,
+            false,
+            Vec::new(),
+            false,
+        );
+        let output: String = claude_stream
+            .map(|chunk| String::from_utf8(chunk.unwrap().to_vec()).unwrap())
+            .collect::<Vec<_>>()
+            .await
+            .concat();
+
+        assert!(output.contains("Let me look"));
+        assert!(!output.contains("never sent"));
+        // 文本块先正常关闭, 再以结构化错误结束, 不再补发 message_stop
+        let stop_pos = output.find("content_block_stop").unwrap();
+        let error_pos = output.find("event: error").unwrap();
+        assert!(stop_pos < error_pos);
+        assert!(output.contains(PROMPT_TOO_LONG_CODE));
+        assert!(output.contains("/compact"));
+        assert!(!output.contains("message_stop"));
+
+        assert!(!is_context_window_exceeded("Resource has been exhausted (e.g. check quota). limit: 0"));
+    }
+
+    #[tokio::test]
+    async fn test_stop_sequence_reported_in_stream_and_non_stream() {
+        use futures::StreamExt;
+
+        // 停止序列跨分片到达, 之后的内容不应下发
+        let chunks = [
+            serde_json::json!({ "candidates": [{ "content": { "role": "model", "parts": [{ "text": "Answer: 42\n\nHu" }] } }] }),
+            serde_json::json!({ "candidates": [{ "content": { "role": "model", "parts": [{ "text": "man: and then" }] } }] }),
+            serde_json::json!({ "candidates": [{ "content": { "role": "model", "parts": [{ "text": " more" }] }, "finishReason": "STOP" }],
+                "usageMetadata": { "promptTokenCount": 12, "candidatesTokenCount": 8 } }),
+        ];
+        let sse: Vec<_> = chunks
+            .iter()
+            .map(|c| Ok::<_, reqwest::Error>(bytes::Bytes::from(format!("data: {}\n\n", c))))
+            .collect();
+        let stop_sequences = vec!["STOP".to_string(), "\n\nHuman:".to_string()];
+
+        let claude_stream = create_claude_sse_stream(
+            Box::pin(futures::stream::iter(sse)),
+            "trace_test".to_string(),
+            "test@example.com".to_string(),
+            None,
+            false,
+            1_000_000,
+            0,
+            None,
+            1,
+            None,
+            None,
+            false,
+            stop_sequences.clone(),
+            false,
+        )
+        .map(|chunk| chunk.map_err(std::io::Error::other));
+        let streamed = collect_stream_to_json(Box::pin(claude_stream)).await.unwrap().response;
+        assert_eq!(streamed.stop_reason, "stop_sequence");
+        assert_eq!(streamed.stop_sequence.as_deref(), Some("\n\nHuman:"));
+        assert!(matches!(&streamed.content[..], [ContentBlock::Text { text }] if text == "Answer: 42"));
+
+        let gemini_response: GeminiResponse = serde_json::from_value(serde_json::json!({
+            "candidates": [{
+                "content": { "role": "model", "parts": [{ "text": "Answer: 42\n\nHuman: and then more" }] },
+                "finishReason": "STOP"
+            }]
+        }))
+        .unwrap();
+        let non_streamed = transform_response(
+            &gemini_response,
+            false,
+            1_000_000,
+            0,
+            None,
+            "gemini-3-pro".to_string(),
+            1,
+            false,
+            None,
+            false,
+            &stop_sequences,
+        )
+        .unwrap();
+        assert_eq!(non_streamed.stop_reason, "stop_sequence");
+        assert_eq!(non_streamed.stop_sequence.as_deref(), Some("\n\nHuman:"));
+        assert!(matches!(&non_streamed.content[..], [ContentBlock::Text { text }] if text == "Answer: 42"));
+
+        // 未命中且多个序列无法区分时保持 end_turn
+        let ambiguous = ["END".to_string(), "FIN".to_string()];
+        let plain = transform_response(&gemini_response, false, 1_000_000, 0, None, "gemini-3-pro".to_string(), 1, false, None, false, &ambiguous).unwrap();
+        assert_eq!(plain.stop_reason, "end_turn");
+        assert!(plain.stop_sequence.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_stop_sequence_inferred_when_upstream_does_not_echo_it() {
+        use futures::StreamExt;
+
+        // Gemini 命中停止序列时不回显序列本身, 只以 STOP 结束
+        let chunks = [
+            serde_json::json!({ "candidates": [{ "content": { "role": "model", "parts": [{ "text": "Answer: " }] } }] }),
+            serde_json::json!({ "candidates": [{ "content": { "role": "model", "parts": [{ "text": "42" }] }, "finishReason": "STOP" }],
+                "usageMetadata": { "promptTokenCount": 12, "candidatesTokenCount": 3 } }),
+        ];
+        let sse: Vec<_> = chunks
+            .iter()
+            .map(|c| Ok::<_, reqwest::Error>(bytes::Bytes::from(format!("data: {}\n\n", c))))
+            .collect();
+        let stop_sequences = vec!["\n\nHuman:".to_string()];
+
+        let claude_stream = create_claude_sse_stream(
+            Box::pin(futures::stream::iter(sse)),
+            "trace_test".to_string(),
+            "test@example.com".to_string(),
+            None,
+            false,
+            1_000_000,
+            0,
+            None,
+            1,
+            None,
+            None,
+            false,
+            stop_sequences.clone(),
+            false,
+        )
+        .map(|chunk| chunk.map_err(std::io::Error::other));
+        let streamed = collect_stream_to_json(Box::pin(claude_stream)).await.unwrap().response;
+        assert_eq!(streamed.stop_reason, "stop_sequence");
+        assert_eq!(streamed.stop_sequence.as_deref(), Some("\n\nHuman:"));
+        assert!(matches!(&streamed.content[..], [ContentBlock::Text { text }] if text == "Answer: 42"));
+
+        let gemini_response: GeminiResponse = serde_json::from_value(serde_json::json!({
+            "candidates": [{
+                "content": { "role": "model", "parts": [{ "text": "Answer: 42" }] },
+                "finishReason": "STOP"
+            }]
+        }))
+        .unwrap();
+        let non_streamed = transform_response(
+            &gemini_response,
+            false,
+            1_000_000,
+            0,
+            None,
+            "gemini-3-pro".to_string(),
+            1,
+            false,
+            None,
+            false,
+            &stop_sequences,
+        )
+        .unwrap();
+        assert_eq!(non_streamed.stop_reason, "stop_sequence");
+        assert_eq!(non_streamed.stop_sequence.as_deref(), Some("\n\nHuman:"));
+        assert!(matches!(&non_streamed.content[..], [ContentBlock::Text { text }] if text == "Answer: 42"));
+
+        // 因 max_tokens 截断时不是停止序列
+        let truncated: GeminiResponse = serde_json::from_value(serde_json::json!({
+            "candidates": [{
+                "content": { "role": "model", "parts": [{ "text": "Answer: 42" }] },
+                "finishReason": "MAX_TOKENS"
+            }]
+        }))
+        .unwrap();
+        let truncated = transform_response(&truncated, false, 1_000_000, 0, None, "gemini-3-pro".to_string(), 1, false, None, false, &stop_sequences).unwrap();
+        assert_eq!(truncated.stop_reason, "max_tokens");
+        assert!(truncated.stop_sequence.is_none());
+    }
 }
@@ -34,6 +34,15 @@ pub struct ClaudeRequest {
     pub size: Option<String>,
     #[serde(default)]
     pub quality: Option<String>,
+    /// Anthropic `service_tier` ("auto" | "standard_only"), 用作调度优先级提示
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub service_tier: Option<String>,
+    /// 工具选择策略, 映射为 Gemini toolConfig.functionCallingConfig
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<ToolChoice>,
+    /// 客户端停止序列, 映射为 generationConfig.stopSequences
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_sequences: Option<Vec<String>>,
 }
 
 /// Thinking 配置
@@ -145,9 +154,13 @@ pub enum ContentBlock {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ImageSource {
     #[serde(rename = "type")]
-    pub source_type: String,
+    pub source_type: String, // "base64" (其他来源在请求校验时拒绝)
+    #[serde(default)]
     pub media_type: String,
+    #[serde(default)]
     pub data: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -158,6 +171,17 @@ pub struct DocumentSource {
     pub data: String,        // base64 data
 }
 
+/// Tool choice - `{"type": "auto" | "any" | "none"}` or `{"type": "tool", "name": "..."}`
+/// (`disable_parallel_tool_use` 没有对应的 Gemini 字段, 解析时忽略)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ToolChoice {
+    Auto,
+    Any,
+    Tool { name: String },
+    None,
+}
+
 /// Tool - supports both client tools (with input_schema) and server tools (like web_search)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Tool {
@@ -250,6 +274,9 @@ pub struct Usage {
     pub cache_creation_input_tokens: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub server_tool_use: Option<serde_json::Value>,
+    /// 实际生效的服务等级 (仅当请求携带 service_tier 时回显)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service_tier: Option<String>,
 }
 
 // ========== Gemini 数据模型 ==========
@@ -289,11 +316,34 @@ pub struct GeminiPart {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FunctionCall {
+    /// 参数分片的后续部分不带 name
+    #[serde(default)]
     pub name: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub id: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub args: Option<serde_json::Value>,
+    /// 流式下发的参数分片 (按 jsonPath 增量拼接)
+    #[serde(rename = "partialArgs", default, skip_serializing_if = "Option::is_none")]
+    pub partial_args: Option<Vec<PartialArg>>,
+    /// 后续分片仍属于本调用
+    #[serde(rename = "willContinue", default, skip_serializing_if = "Option::is_none")]
+    pub will_continue: Option<bool>,
+}
+
+/// functionCall.partialArgs 中的单个参数分片
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartialArg {
+    #[serde(rename = "jsonPath")]
+    pub json_path: String,
+    #[serde(rename = "stringValue", default, skip_serializing_if = "Option::is_none")]
+    pub string_value: Option<String>,
+    #[serde(rename = "numberValue", default, skip_serializing_if = "Option::is_none")]
+    pub number_value: Option<f64>,
+    #[serde(rename = "boolValue", default, skip_serializing_if = "Option::is_none")]
+    pub bool_value: Option<bool>,
+    #[serde(rename = "nullValue", default, skip_serializing_if = "Option::is_none")]
+    pub null_value: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -107,6 +107,42 @@ pub fn deep_clean_cache_control(value: &mut Value) {
     }
 }
 
+/// Longest `cache_control.ttl` hint in a raw Claude request body (e.g. `"1h"`, `"5m"`)
+///
+/// Must be read from the raw JSON: typed text / system blocks drop `cache_control` on deserialization.
+pub fn requested_cache_ttl(value: &Value) -> Option<std::time::Duration> {
+    match value {
+        Value::Object(map) => {
+            let own = map
+                .get("cache_control")
+                .and_then(|cc| cc.get("ttl"))
+                .and_then(|ttl| ttl.as_str())
+                .and_then(parse_cache_ttl);
+            map.values()
+                .filter_map(requested_cache_ttl)
+                .chain(own)
+                .max()
+        }
+        Value::Array(arr) => arr.iter().filter_map(requested_cache_ttl).max(),
+        _ => None,
+    }
+}
+
+/// Parse an Anthropic cache TTL such as `"1h"`, `"5m"` or `"300s"`
+fn parse_cache_ttl(ttl: &str) -> Option<std::time::Duration> {
+    let ttl = ttl.trim();
+    let split = ttl.find(|c: char| !c.is_ascii_digit())?;
+    let (amount, unit) = ttl.split_at(split);
+    let amount: u64 = amount.parse().ok()?;
+    let secs = match unit {
+        "s" => amount,
+        "m" => amount.checked_mul(60)?,
+        "h" => amount.checked_mul(3600)?,
+        _ => return None,
+    };
+    Some(std::time::Duration::from_secs(secs))
+}
+
 /// Recursively remove 'thought' and 'thoughtSignature' fields
 /// Used when downgrading thinking (e.g. during 400 retry)
 pub fn clean_thinking_fields_recursive(val: &mut Value) {
@@ -1,6 +1,7 @@
 // Contents Builder - Message transformation logic
 // Converts Claude messages to Gemini v1internal format
 
+use super::images::build_image_part;
 use super::thinking::{is_model_compatible, MIN_SIGNATURE_LENGTH};
 use crate::proxy::mappers::claude::models::*;
 use crate::proxy::mappers::tool_result_compressor;
@@ -185,22 +186,14 @@ pub fn build_contents(
                             saw_non_thinking = true;
                         }
                     }
-                    ContentBlock::RedactedThinking { data } => {
-                        tracing::debug!("[Claude-Request] Degrade RedactedThinking to text");
-                        parts.push(json!({
-                            "text": format!("[Redacted Thinking: {}]", data)
-                        }));
-                        saw_non_thinking = true;
+                    ContentBlock::RedactedThinking { .. } => {
+                        // 加密内容对 Gemini 无意义, 不能当作文本发给模型; 策略处理见 RedactedThinkingMode
+                        tracing::debug!("[Claude-Request] Drop RedactedThinking for Gemini target");
                         continue;
                     }
                     ContentBlock::Image { source, .. } => {
-                        if source.source_type == "base64" {
-                            parts.push(json!({
-                                "inlineData": {
-                                    "mimeType": source.media_type,
-                                    "data": source.data
-                                }
-                            }));
+                        if let Some(part) = build_image_part(source) {
+                            parts.push(part);
                             saw_non_thinking = true;
                         }
                     }
@@ -683,6 +676,7 @@ fn merge_adjacent_roles(mut contents: Vec<Value>) -> Vec<Value> {
             {
                 if let Some(next_parts) = msg.get("parts").and_then(|p| p.as_array()) {
                     current_parts.extend(next_parts.clone());
+                    super::sorting::drop_placeholder_text_parts(current_parts);
                     super::sorting::reorder_gemini_parts(current_parts);
                 }
             }
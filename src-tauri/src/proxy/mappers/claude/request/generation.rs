@@ -2,12 +2,18 @@
 
 use serde_json::{json, Value};
 use crate::proxy::mappers::claude::models::ClaudeRequest;
+use crate::proxy::common::thinking_capability::budget_range;
+use crate::proxy::mappers::claude::stop_sequences::upstream_stop_sequences;
+
+/// 客户端未指定 budget_tokens 时的默认 thinking 预算
+const DEFAULT_THINKING_BUDGET: u32 = 16000;
 
 /// Build Generation Config for Gemini API
 pub fn build_generation_config(
     claude_req: &ClaudeRequest,
     has_web_search: bool,
     is_thinking_enabled: bool,
+    mapped_model: &str,
 ) -> Value {
     let mut config = json!({});
 
@@ -18,7 +24,8 @@ pub fn build_generation_config(
             .thinking
             .as_ref()
             .and_then(|t| t.budget_tokens)
-            .unwrap_or(16000);
+            .or_else(|| crate::proxy::common::thinking_defaults::default_budget(mapped_model))
+            .unwrap_or(DEFAULT_THINKING_BUDGET);
 
         let model_lower = claude_req.model.to_lowercase();
         let tb_config = crate::proxy::config::get_thinking_budget_config();
@@ -42,21 +49,18 @@ pub fn build_generation_config(
             }
             crate::proxy::config::ThinkingBudgetMode::Passthrough => budget_tokens as i32,
             crate::proxy::config::ThinkingBudgetMode::Auto => {
-                if is_gemini_limited && budget_tokens > 24576 {
-                    tracing::info!(
-                        "[Claude-Request] Auto mode: capping thinking_budget from {} to 24576 for Gemini model {}",
-                        budget_tokens,
-                        claude_req.model
-                    );
-                    24576
-                } else {
-                    budget_tokens as i32
-                }
+                auto_thinking_budget(budget_tokens, mapped_model, is_gemini_limited) as i32
             }
         };
 
         thinking_config["thinkingBudget"] = json!(budget);
         config["thinkingConfig"] = thinking_config;
+    } else if client_disabled_thinking(claude_req) && can_disable_thinking(mapped_model) {
+        // 客户端显式关闭: 发送 0 预算真正关闭 thinking, 而不只是隐藏 thought parts
+        config["thinkingConfig"] = json!({ "includeThoughts": false, "thinkingBudget": 0 });
+    } else if suppresses_default_thoughts(mapped_model) {
+        // 未开启 thinking: 部分 Gemini 模型默认也会返回 thought parts, 显式关闭
+        config["thinkingConfig"] = json!({ "includeThoughts": false });
     }
 
     if let Some(temp) = claude_req.temperature {
@@ -86,6 +90,7 @@ pub fn build_generation_config(
         if let Some(budget) = thinking_config
             .get("thinkingBudget")
             .and_then(|t| t.as_u64())
+            .filter(|budget| *budget > 0)
         {
             let current = final_max_tokens.unwrap_or(0);
             if current <= budget as i64 {
@@ -107,7 +112,85 @@ pub fn build_generation_config(
     let user_marker = format!("<|{}|>", "user");
     let end_turn_marker = format!("<|{}|>", "end_of_turn");
     let human_marker = format!("{}{}{}", "\n", "\n", "Human:");
-    config["stopSequences"] = json!([user_marker, end_turn_marker, human_marker]);
+    // 客户端 stop_sequences 优先, 总数不超过 v1internal 上限
+    config["stopSequences"] = json!(upstream_stop_sequences(
+        claude_req.stop_sequences.as_deref().unwrap_or_default(),
+        &[user_marker, end_turn_marker, human_marker],
+    ));
 
     config
 }
+
+/// 自动模式下的 thinking 预算: 已知模型族裁剪到上游接受的范围, 其余沿用 Gemini 24576 上限
+fn auto_thinking_budget(budget_tokens: u32, mapped_model: &str, is_gemini_limited: bool) -> u32 {
+    match budget_range(mapped_model) {
+        Some(range) => {
+            let clamped = range.clamp(budget_tokens);
+            if clamped != budget_tokens {
+                tracing::info!(
+                    "[Claude-Request] Auto mode: clamping thinking_budget from {} to {} ({}..={}) for model {}",
+                    budget_tokens,
+                    clamped,
+                    range.min,
+                    range.max,
+                    mapped_model
+                );
+            }
+            clamped
+        }
+        None if is_gemini_limited && budget_tokens > 24576 => {
+            tracing::info!(
+                "[Claude-Request] Auto mode: capping thinking_budget from {} to 24576 for Gemini model {}",
+                budget_tokens,
+                mapped_model
+            );
+            24576
+        }
+        None => budget_tokens,
+    }
+}
+
+/// 客户端 (或 thinking 默认策略写回的决策) 显式要求关闭 thinking
+fn client_disabled_thinking(claude_req: &ClaudeRequest) -> bool {
+    claude_req
+        .thinking
+        .as_ref()
+        .is_some_and(|t| t.type_ == "disabled")
+}
+
+/// 目标模型支持 thinking 且允许以 0 预算关闭
+fn can_disable_thinking(mapped_model: &str) -> bool {
+    crate::proxy::common::thinking_capability::supports_thinking(mapped_model)
+        && budget_range(mapped_model).is_some_and(|range| range.can_disable)
+}
+
+/// 未开启 thinking 时是否需要显式发送 includeThoughts: false (支持 thinking 的 Gemini 模型)
+fn suppresses_default_thoughts(mapped_model: &str) -> bool {
+    mapped_model.to_lowercase().starts_with("gemini")
+        && crate::proxy::common::thinking_capability::supports_thinking(mapped_model)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auto_budget_clamped_to_model_range() {
+        assert_eq!(auto_thinking_budget(100, "gemini-3-pro-high", true), 512);
+        assert_eq!(auto_thinking_budget(8192, "gemini-3-pro-high", true), 8192);
+        assert_eq!(auto_thinking_budget(100_000, "gemini-3-pro-high", true), 32768);
+        assert_eq!(auto_thinking_budget(100_000, "gemini-2.5-flash", true), 24576);
+        assert_eq!(auto_thinking_budget(200, "claude-sonnet-4-5-thinking", true), 1024);
+        // 未知模型沿用旧的 Gemini 上限规则
+        assert_eq!(auto_thinking_budget(30_000, "some-model", true), 24576);
+        assert_eq!(auto_thinking_budget(30_000, "some-model", false), 30_000);
+    }
+
+    #[test]
+    fn test_zero_budget_only_for_models_that_can_disable_thinking() {
+        assert!(can_disable_thinking("claude-sonnet-4-5"));
+        // Gemini Pro 不允许关闭 thinking, 仍只隐藏 thought 输出
+        assert!(!can_disable_thinking("gemini-3-pro-high"));
+        assert!(suppresses_default_thoughts("gemini-3-pro-high"));
+    }
+}
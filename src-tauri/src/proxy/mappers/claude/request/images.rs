@@ -0,0 +1,164 @@
+// Image content blocks
+// Claude 客户端 (Claude Code 截图等) 以 {"type": "image", "source": {"type": "base64", ...}} 发送图片,
+// 这里转换为 Gemini inlineData part。超出上游限制的图片与不支持的来源在发往上游前
+// 以 invalid_request_error 拒绝, 而不是让上游返回难以理解的 400。
+
+use serde_json::{json, Value};
+
+use crate::proxy::mappers::claude::models::{ContentBlock, ImageSource, Message, MessageContent};
+
+/// 单张图片解码后的最大字节数
+pub const MAX_IMAGE_BYTES: usize = 20 * 1024 * 1024;
+
+/// Gemini inlineData 支持的图片类型
+const SUPPORTED_IMAGE_TYPES: &[&str] = &[
+    "image/png",
+    "image/jpeg",
+    "image/webp",
+    "image/gif",
+    "image/heic",
+    "image/heif",
+];
+
+/// 规范化后的 (mimeType, base64 数据); 兼容把 data URL 整体放进 data 字段的客户端
+fn normalized(source: &ImageSource) -> (String, &str) {
+    let data_url = source
+        .data
+        .strip_prefix("data:")
+        .and_then(|s| s.split_once(";base64,"));
+    let (url_mime, data) = match data_url {
+        Some((mime, data)) => (Some(mime), data),
+        None => (None, source.data.as_str()),
+    };
+    let mime = if source.media_type.is_empty() {
+        url_mime.unwrap_or_default()
+    } else {
+        source.media_type.as_str()
+    };
+    let mime = match mime.trim().to_ascii_lowercase().as_str() {
+        "image/jpg" => "image/jpeg".to_string(),
+        other => other.to_string(),
+    };
+    (mime, data)
+}
+
+/// base64 数据解码后的字节数 (不实际解码)
+fn decoded_len(data: &str) -> usize {
+    let len = data.bytes().filter(|b| !b.is_ascii_whitespace()).count();
+    let padding = data.trim_end().bytes().rev().take_while(|b| *b == b'=').count().min(2);
+    (len / 4 * 3 + (len % 4) * 3 / 4).saturating_sub(padding)
+}
+
+fn check_image(source: &ImageSource) -> Result<(), String> {
+    if source.source_type != "base64" {
+        return Err(format!(
+            "image source type '{}' is not supported; send images as base64 (source.type = \"base64\")",
+            source.source_type
+        ));
+    }
+    let (mime, data) = normalized(source);
+    if !SUPPORTED_IMAGE_TYPES.contains(&mime.as_str()) {
+        return Err(format!(
+            "image media_type '{}' is not supported (supported: {})",
+            mime,
+            SUPPORTED_IMAGE_TYPES.join(", ")
+        ));
+    }
+    let bytes = decoded_len(data);
+    if bytes > MAX_IMAGE_BYTES {
+        return Err(format!(
+            "image is too large: {:.1} MB decoded (maximum {} MB per image)",
+            bytes as f64 / (1024.0 * 1024.0),
+            MAX_IMAGE_BYTES / (1024 * 1024)
+        ));
+    }
+    Ok(())
+}
+
+/// Check every image block in the messages (type, media type and decoded size) before forwarding
+pub fn validate_image_blocks(messages: &[Message]) -> Result<(), String> {
+    for (index, message) in messages.iter().enumerate() {
+        let MessageContent::Array(blocks) = &message.content else {
+            continue;
+        };
+        for block in blocks {
+            if let ContentBlock::Image { source, .. } = block {
+                check_image(source).map_err(|e| format!("messages.{}: {}", index, e))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Convert a base64 image block to a Gemini inlineData part (None for unsupported sources)
+pub fn build_image_part(source: &ImageSource) -> Option<Value> {
+    if source.source_type != "base64" {
+        tracing::warn!(
+            "[Claude-Request] Dropping image block with unsupported source type: {}",
+            source.source_type
+        );
+        return None;
+    }
+    let (mime, data) = normalized(source);
+    Some(json!({
+        "inlineData": {
+            "mimeType": mime,
+            "data": data
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(source_type: &str, media_type: &str, data: &str) -> ImageSource {
+        ImageSource {
+            source_type: source_type.to_string(),
+            media_type: media_type.to_string(),
+            data: data.to_string(),
+            url: None,
+        }
+    }
+
+    fn user_image(source: ImageSource) -> Vec<Message> {
+        vec![Message {
+            role: "user".to_string(),
+            content: MessageContent::Array(vec![
+                ContentBlock::Text { text: "What is in this screenshot?".to_string() },
+                ContentBlock::Image { source, cache_control: None },
+            ]),
+        }]
+    }
+
+    #[test]
+    fn test_image_part_normalizes_mime_and_data_url() {
+        let part = build_image_part(&source("base64", "image/JPG", "iVBORw0KGgo=")).unwrap();
+        assert_eq!(part["inlineData"]["mimeType"], "image/jpeg");
+        assert_eq!(part["inlineData"]["data"], "iVBORw0KGgo=");
+
+        let part = build_image_part(&source("base64", "", "data:image/webp;base64,UklGRg==")).unwrap();
+        assert_eq!(part["inlineData"]["mimeType"], "image/webp");
+        assert_eq!(part["inlineData"]["data"], "UklGRg==");
+
+        assert!(build_image_part(&source("url", "", "")).is_none());
+    }
+
+    #[test]
+    fn test_validate_image_blocks() {
+        assert!(validate_image_blocks(&user_image(source("base64", "image/png", "iVBORw0KGgo="))).is_ok());
+
+        let err = validate_image_blocks(&user_image(source("url", "", ""))).unwrap_err();
+        assert!(err.starts_with("messages.0: image source type 'url'"));
+
+        let err = validate_image_blocks(&user_image(source("base64", "image/tiff", "AAAA"))).unwrap_err();
+        assert!(err.contains("image/tiff"));
+
+        assert_eq!(decoded_len("iVBORw0KGgo="), 8);
+        let under = "A".repeat(MAX_IMAGE_BYTES / 3 * 4);
+        assert!(validate_image_blocks(&user_image(source("base64", "image/png", &under))).is_ok());
+        let over = "A".repeat((MAX_IMAGE_BYTES / 3 + 1) * 4);
+        let err = validate_image_blocks(&user_image(source("base64", "image/png", &over))).unwrap_err();
+        assert!(err.contains("too large"));
+    }
+}
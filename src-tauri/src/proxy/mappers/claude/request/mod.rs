@@ -4,19 +4,26 @@
 mod cleanup;
 mod contents;
 mod generation;
+mod images;
 mod safety;
 mod sorting;
 mod system;
 mod thinking;
+mod tool_cache;
 mod tools;
 mod transform;
 
 // Re-export main transformation function
-pub use transform::transform_claude_request_in;
+pub use transform::{transform_claude_request_in, transform_claude_request_in_with_mode};
 
 // Re-export cleanup utilities (used by handlers)
 pub use cleanup::clean_cache_control_from_messages;
 pub use cleanup::clean_thinking_fields_recursive;
+pub use cleanup::requested_cache_ttl;
+
+// Re-export request validation (used by handlers)
+pub use images::validate_image_blocks;
+pub use tools::validate_tool_choice;
 
 // Re-export sorting utilities (used by handlers)
 pub use sorting::merge_consecutive_messages;
@@ -24,6 +31,9 @@ pub use sorting::merge_consecutive_messages;
 // Re-export safety configuration
 pub use safety::SafetyThreshold;
 
+// Re-export tool declaration reuse statistics
+pub use tool_cache::{stats as tool_schema_stats, SessionToolSchemaCost, ToolSchemaStats};
+
 // Internal re-exports for use within this module
 
 #[cfg(test)]
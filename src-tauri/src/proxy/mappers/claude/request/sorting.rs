@@ -3,75 +3,71 @@
 use serde_json::Value;
 use crate::proxy::mappers::claude::models::{ContentBlock, Message, MessageContent};
 
+/// 空白或 "(no content)" 占位文本 (上游会拒绝空 text part)
+fn is_placeholder_text(text: &str) -> bool {
+    text.trim().is_empty() || text == "(no content)"
+}
+
+/// Drop placeholder text blocks from multi-block assistant messages
+///
+/// 与排序分离: 排序只做稳定重排, 不丢弃任何块; 清理占位文本是单独且显式的一步。
+pub fn drop_placeholder_text_blocks(messages: &mut [Message]) {
+    for msg in messages.iter_mut() {
+        if msg.role != "assistant" {
+            continue;
+        }
+        if let MessageContent::Array(blocks) = &mut msg.content {
+            if blocks.len() <= 1 {
+                continue;
+            }
+            let before = blocks.len();
+            blocks.retain(|block| !matches!(block, ContentBlock::Text { text } if is_placeholder_text(text)));
+            if blocks.len() != before {
+                tracing::debug!(
+                    "[Claude-Request] Dropped {} placeholder text block(s) from assistant message",
+                    before - blocks.len()
+                );
+            }
+        }
+    }
+}
+
 /// Sort blocks in assistant messages to ensure thinking blocks are first
 ///
 /// When context compression (kilo) reorders message blocks, thinking blocks may appear
 /// after text blocks. Claude/Anthropic API requires thinking blocks to be first if
 /// any thinking blocks exist in the message.
+///
+/// Stable partition into [Thinking, Text/Other, ToolUse]: never drops or duplicates a block,
+/// keeps the relative order within each group and is idempotent.
 pub fn sort_thinking_blocks_first(messages: &mut [Message]) {
     for msg in messages.iter_mut() {
-        if msg.role == "assistant" {
-            if let MessageContent::Array(blocks) = &mut msg.content {
-                // Triple-stage partition: [Thinking, Text, ToolUse]
-                let mut thinking_blocks: Vec<ContentBlock> = Vec::new();
-                let mut text_blocks: Vec<ContentBlock> = Vec::new();
-                let mut tool_blocks: Vec<ContentBlock> = Vec::new();
-                let mut other_blocks: Vec<ContentBlock> = Vec::new();
-
-                let original_len = blocks.len();
-                let mut needs_reorder = false;
-                let mut saw_non_thinking = false;
-
-                for block in blocks.iter() {
-                    match block {
-                        ContentBlock::Thinking { .. } | ContentBlock::RedactedThinking { .. } => {
-                            if saw_non_thinking {
-                                needs_reorder = true;
-                            }
-                        }
-                        ContentBlock::Text { .. } => {
-                            saw_non_thinking = true;
-                        }
-                        ContentBlock::ToolUse { .. } => {
-                            saw_non_thinking = true;
-                        }
-                        _ => saw_non_thinking = true,
-                    }
-                }
-
-                if needs_reorder || original_len > 1 {
-                    for block in blocks.drain(..) {
-                        match &block {
-                            ContentBlock::Thinking { .. }
-                            | ContentBlock::RedactedThinking { .. } => {
-                                thinking_blocks.push(block);
-                            }
-                            ContentBlock::Text { text } => {
-                                if !text.trim().is_empty() && text != "(no content)" {
-                                    text_blocks.push(block);
-                                }
-                            }
-                            ContentBlock::ToolUse { .. } => {
-                                tool_blocks.push(block);
-                            }
-                            _ => {
-                                other_blocks.push(block);
-                            }
-                        }
-                    }
+        if msg.role != "assistant" {
+            continue;
+        }
+        if let MessageContent::Array(blocks) = &mut msg.content {
+            let mut saw_non_thinking = false;
+            let needs_reorder = blocks.iter().any(|block| {
+                let is_thinking = matches!(
+                    block,
+                    ContentBlock::Thinking { .. } | ContentBlock::RedactedThinking { .. }
+                );
+                let misplaced = is_thinking && saw_non_thinking;
+                saw_non_thinking |= !is_thinking;
+                misplaced
+            });
 
-                    // Reconstruct in strict order: Thinking -> Text/Other -> Tool
-                    blocks.extend(thinking_blocks);
-                    blocks.extend(text_blocks);
-                    blocks.extend(other_blocks);
-                    blocks.extend(tool_blocks);
+            // sort_by_key 是稳定排序
+            blocks.sort_by_key(|block| match block {
+                ContentBlock::Thinking { .. } | ContentBlock::RedactedThinking { .. } => 0,
+                ContentBlock::ToolUse { .. } => 2,
+                _ => 1,
+            });
 
-                    if needs_reorder {
-                        tracing::warn!(
-                            "[FIX #709] Reordered assistant messages to [Thinking, Text, Tool] structure."
-                        );
-                    }
-                }
+            if needs_reorder {
+                tracing::warn!(
+                    "[FIX #709] Reordered assistant messages to [Thinking, Text, Tool] structure."
+                );
             }
         }
     }
@@ -124,35 +120,36 @@ pub fn merge_consecutive_messages(messages: &mut Vec<Message>) {
     *messages = merged;
 }
 
-/// Reorder serialized Gemini parts to ensure thinking blocks are first
-pub fn reorder_gemini_parts(parts: &mut Vec<Value>) {
+/// Drop placeholder text parts (see `drop_placeholder_text_blocks`)
+pub fn drop_placeholder_text_parts(parts: &mut Vec<Value>) {
     if parts.len() <= 1 {
         return;
     }
+    parts.retain(|part| {
+        part.get("thought").and_then(|t| t.as_bool()) == Some(true)
+            || part.get("functionCall").is_some()
+            || !part
+                .get("text")
+                .and_then(|t| t.as_str())
+                .is_some_and(is_placeholder_text)
+    });
+}
 
-    let mut thinking_parts = Vec::new();
-    let mut text_parts = Vec::new();
-    let mut tool_parts = Vec::new();
-    let mut other_parts = Vec::new();
-
-    for part in parts.drain(..) {
+/// Reorder serialized Gemini parts to ensure thinking blocks are first
+///
+/// Stable partition into [thought, text, other, functionCall]; loss-free and idempotent.
+pub fn reorder_gemini_parts(parts: &mut [Value]) {
+    parts.sort_by_key(|part| {
         if part.get("thought").and_then(|t| t.as_bool()) == Some(true) {
-            thinking_parts.push(part);
+            0
         } else if part.get("functionCall").is_some() {
-            tool_parts.push(part);
-        } else if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
-            if !text.trim().is_empty() && text != "(no content)" {
-                text_parts.push(part);
-            }
+            3
+        } else if part.get("text").and_then(|t| t.as_str()).is_some() {
+            1
         } else {
-            other_parts.push(part);
+            2
         }
-    }
-
-    parts.extend(thinking_parts);
-    parts.extend(text_parts);
-    parts.extend(other_parts);
-    parts.extend(tool_parts);
+    });
 }
 
 /// Merge adjacent messages with the same role
@@ -176,6 +173,7 @@ pub fn merge_adjacent_roles(mut contents: Vec<Value>) -> Vec<Value> {
                     current_parts.extend(next_parts.clone());
 
                     // After merging, re-sort to ensure thinking blocks are first
+                    drop_placeholder_text_parts(current_parts);
                     reorder_gemini_parts(current_parts);
                 }
             }
@@ -187,3 +185,166 @@ pub fn merge_adjacent_roles(mut contents: Vec<Value>) -> Vec<Value> {
     merged.push(current_msg);
     merged
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use serde_json::json;
+
+    // 每个块带唯一标记, 以便检测丢失与重复
+    #[derive(Debug, Clone, Copy)]
+    enum Kind {
+        Thinking,
+        RedactedThinking,
+        Text,
+        BlankText,
+        NoContentText,
+        ToolUse,
+        ToolResult,
+    }
+
+    fn kind() -> impl Strategy<Value = Kind> {
+        prop_oneof![
+            Just(Kind::Thinking),
+            Just(Kind::RedactedThinking),
+            Just(Kind::Text),
+            Just(Kind::BlankText),
+            Just(Kind::NoContentText),
+            Just(Kind::ToolUse),
+            Just(Kind::ToolResult),
+        ]
+    }
+
+    fn block(kind: Kind, i: usize) -> ContentBlock {
+        match kind {
+            Kind::Thinking => ContentBlock::Thinking {
+                thinking: format!("think-{}", i),
+                signature: i.is_multiple_of(2).then(|| format!("sig-{}", i)),
+                cache_control: None,
+            },
+            Kind::RedactedThinking => ContentBlock::RedactedThinking { data: format!("redacted-{}", i) },
+            Kind::Text => ContentBlock::Text { text: format!("text-{}", i) },
+            Kind::BlankText => ContentBlock::Text { text: " ".repeat(i % 3) },
+            Kind::NoContentText => ContentBlock::Text { text: "(no content)".to_string() },
+            Kind::ToolUse => ContentBlock::ToolUse {
+                id: format!("toolu_{}", i),
+                name: "bash".to_string(),
+                input: json!({"i": i}),
+                signature: None,
+                cache_control: None,
+            },
+            Kind::ToolResult => ContentBlock::ToolResult {
+                tool_use_id: format!("toolu_{}", i),
+                content: json!("ok"),
+                is_error: None,
+            },
+        }
+    }
+
+    fn part(kind: Kind, i: usize) -> Value {
+        match kind {
+            Kind::Thinking => json!({"text": format!("think-{}", i), "thought": true, "thoughtSignature": format!("sig-{}", i)}),
+            // redacted_thinking 降级为普通 text part
+            Kind::RedactedThinking => json!({"text": format!("[Redacted Thinking: {}]", i)}),
+            Kind::Text => json!({"text": format!("text-{}", i)}),
+            Kind::BlankText => json!({"text": " ".repeat(i % 3)}),
+            Kind::NoContentText => json!({"text": "(no content)"}),
+            Kind::ToolUse => json!({"functionCall": {"name": "bash", "args": {}, "id": format!("toolu_{}", i)}}),
+            Kind::ToolResult => json!({"functionResponse": {"name": "bash", "response": {"result": "ok"}, "id": format!("toolu_{}", i)}}),
+        }
+    }
+
+    fn sorted_messages(blocks: &[ContentBlock]) -> Vec<ContentBlock> {
+        let mut messages = vec![Message {
+            role: "assistant".to_string(),
+            content: MessageContent::Array(blocks.to_vec()),
+        }];
+        sort_thinking_blocks_first(&mut messages);
+        match messages.remove(0).content {
+            MessageContent::Array(blocks) => blocks,
+            MessageContent::String(_) => unreachable!(),
+        }
+    }
+
+    fn keys<T: serde::Serialize>(items: &[T]) -> Vec<String> {
+        items.iter().map(|i| serde_json::to_string(i).unwrap()).collect()
+    }
+
+    fn multiset<T: serde::Serialize>(items: &[T]) -> Vec<String> {
+        let mut keys = keys(items);
+        keys.sort();
+        keys
+    }
+
+    fn position(keys: &[String], key: &str) -> usize {
+        keys.iter().position(|k| k == key).unwrap()
+    }
+
+    proptest! {
+        #[test]
+        fn prop_sort_thinking_blocks_first(kinds in proptest::collection::vec(kind(), 0..16)) {
+            let input: Vec<ContentBlock> = kinds.iter().enumerate().map(|(i, k)| block(*k, i)).collect();
+            let output = sorted_messages(&input);
+
+            // 不丢失、不重复
+            prop_assert_eq!(multiset(&input), multiset(&output));
+
+            let is_thinking = |b: &ContentBlock| matches!(b, ContentBlock::Thinking { .. } | ContentBlock::RedactedThinking { .. });
+            let (in_keys, out_keys) = (keys(&input), keys(&output));
+            for i in (0..input.len()).filter(|i| is_thinking(&input[*i])) {
+                // thinking 位于所有非 thinking 块之前, 且仍在它之前的 tool_use 之前
+                prop_assert!(output.iter().take_while(|b| is_thinking(b)).count() > position(&out_keys, &in_keys[i]));
+                for (j, _) in input.iter().enumerate().skip(i).filter(|(_, b)| matches!(b, ContentBlock::ToolUse { .. })) {
+                    prop_assert!(position(&out_keys, &in_keys[i]) < position(&out_keys, &in_keys[j]));
+                }
+            }
+
+            // text 相对顺序不变
+            let texts = |blocks: &[ContentBlock]| keys(&blocks.iter().filter(|b| matches!(b, ContentBlock::Text { .. })).cloned().collect::<Vec<_>>());
+            prop_assert_eq!(texts(&input), texts(&output));
+
+            // 幂等
+            prop_assert_eq!(keys(&sorted_messages(&output)), out_keys);
+        }
+
+        #[test]
+        fn prop_reorder_gemini_parts(kinds in proptest::collection::vec(kind(), 0..16)) {
+            let input: Vec<Value> = kinds.iter().enumerate().map(|(i, k)| part(*k, i)).collect();
+            let mut output = input.clone();
+            reorder_gemini_parts(&mut output);
+
+            prop_assert_eq!(multiset(&input), multiset(&output));
+
+            let is_thought = |p: &Value| p.get("thought").and_then(|t| t.as_bool()) == Some(true);
+            let thoughts = output.iter().take_while(|p| is_thought(p)).count();
+            prop_assert_eq!(thoughts, output.iter().filter(|p| is_thought(p)).count());
+            prop_assert!(output.iter().skip_while(|p| p.get("functionCall").is_none()).all(|p| p.get("functionCall").is_some()));
+
+            let texts = |parts: &[Value]| keys(&parts.iter().filter(|p| !is_thought(p) && p.get("text").is_some()).cloned().collect::<Vec<_>>());
+            prop_assert_eq!(texts(&input), texts(&output));
+
+            let mut again = output.clone();
+            reorder_gemini_parts(&mut again);
+            prop_assert_eq!(again, output);
+        }
+    }
+
+    #[test]
+    fn test_placeholder_text_dropped_explicitly() {
+        let mut messages = vec![Message {
+            role: "assistant".to_string(),
+            content: MessageContent::Array(vec![
+                block(Kind::Text, 0),
+                block(Kind::NoContentText, 1),
+                block(Kind::BlankText, 2),
+            ]),
+        }];
+        drop_placeholder_text_blocks(&mut messages);
+        assert!(matches!(&messages[0].content, MessageContent::Array(b) if b.len() == 1));
+
+        let mut parts = vec![part(Kind::NoContentText, 0), part(Kind::Thinking, 1), part(Kind::ToolUse, 2)];
+        drop_placeholder_text_parts(&mut parts);
+        assert_eq!(parts.len(), 2);
+    }
+}
@@ -1,13 +1,19 @@
 // System Instruction Builder
 
 use serde_json::{json, Value};
+use crate::proxy::common::system_injection::{injected_parts, SystemInjection};
+use crate::proxy::config::InjectionPosition;
 use crate::proxy::mappers::claude::models::SystemPrompt;
 
 /// Build System Instruction with dynamic identity mapping and prompt isolation
+///
+/// Configured injections wrap the client's system content: `Prepend` profiles before it,
+/// `Append` profiles after it, each enclosed in delimiter markers
 pub fn build_system_instruction(
     system: &Option<SystemPrompt>,
     _model_name: &str,
     has_mcp_tools: bool,
+    injections: &[SystemInjection],
 ) -> Option<Value> {
     let mut parts = Vec::new();
 
@@ -42,6 +48,9 @@ pub fn build_system_instruction(
         parts.push(json!({"text": antigravity_identity}));
     }
 
+    // Injected profiles placed before the client system content
+    parts.extend(injected_parts(injections, InjectionPosition::Prepend));
+
     // Add user's system prompt
     if let Some(sys) = system {
         match sys {
@@ -58,6 +67,9 @@ pub fn build_system_instruction(
         }
     }
 
+    // Injected profiles placed after the client system content
+    parts.extend(injected_parts(injections, InjectionPosition::Append));
+
     // MCP XML Bridge: If there are mcp__ prefixed tools, inject special calling protocol
     if has_mcp_tools {
         let mcp_xml_prompt = "\n\
@@ -60,6 +60,9 @@ fn test_simple_request() {
         output_config: None,
         size: None,
         quality: None,
+        service_tier: None,
+        tool_choice: None,
+        stop_sequences: None,
     };
 
     let result = transform_claude_request_in(&req, "test-project", false);
@@ -152,6 +155,9 @@ fn test_complex_tool_result() {
         output_config: None,
         size: None,
         quality: None,
+        service_tier: None,
+        tool_choice: None,
+        stop_sequences: None,
     };
 
     let result = transform_claude_request_in(&req, "test-project", false);
@@ -172,6 +178,28 @@ fn test_complex_tool_result() {
     assert!(resp_text.contains("file2.txt"));
 }
 
+#[test]
+fn test_requested_cache_ttl_reads_raw_body() {
+    let body = json!({
+        "system": [
+            { "type": "text", "text": "rules", "cache_control": { "type": "ephemeral", "ttl": "1h" } }
+        ],
+        "messages": [{
+            "role": "user",
+            "content": [
+                { "type": "text", "text": "hi", "cache_control": { "type": "ephemeral", "ttl": "5m" } }
+            ]
+        }]
+    });
+    assert_eq!(requested_cache_ttl(&body), Some(std::time::Duration::from_secs(3600)));
+
+    let plain = json!({ "messages": [{ "role": "user", "content": [
+        { "type": "text", "text": "hi", "cache_control": { "type": "ephemeral" } }
+    ] }] });
+    assert_eq!(requested_cache_ttl(&plain), None);
+    assert_eq!(requested_cache_ttl(&json!({ "cache_control": { "ttl": "soon" } })), None);
+}
+
 #[test]
 fn test_cache_control_cleanup() {
     let req = ClaudeRequest {
@@ -201,6 +229,7 @@ fn test_cache_control_cleanup() {
                         source_type: "base64".to_string(),
                         media_type: "image/png".to_string(),
                         data: "iVBORw0KGgo=".to_string(),
+                        url: None,
                     },
                     cache_control: Some(json!({"type": "ephemeral"})),
                 }]),
@@ -218,6 +247,9 @@ fn test_cache_control_cleanup() {
         output_config: None,
         size: None,
         quality: None,
+        service_tier: None,
+        tool_choice: None,
+        stop_sequences: None,
     };
 
     let result = transform_claude_request_in(&req, "test-project", false);
@@ -280,6 +312,9 @@ fn test_thinking_mode_auto_disable_on_tool_use_history() {
         output_config: None,
         size: None,
         quality: None,
+        service_tier: None,
+        tool_choice: None,
+        stop_sequences: None,
     };
 
     let result = transform_claude_request_in(&req, "test-project", false);
@@ -326,6 +361,9 @@ fn test_thinking_block_not_prepend_when_disabled() {
         output_config: None,
         size: None,
         quality: None,
+        service_tier: None,
+        tool_choice: None,
+        stop_sequences: None,
     };
 
     let result = transform_claude_request_in(&req, "test-project", false);
@@ -378,6 +416,9 @@ fn test_thinking_block_empty_content_fix() {
         output_config: None,
         size: None,
         quality: None,
+        service_tier: None,
+        tool_choice: None,
+        stop_sequences: None,
     };
 
     let result = transform_claude_request_in(&req, "test-project", false);
@@ -397,7 +438,7 @@ fn test_thinking_block_empty_content_fix() {
 }
 
 #[test]
-fn test_redacted_thinking_degradation() {
+fn test_redacted_thinking_is_dropped_for_gemini() {
     let req = ClaudeRequest {
         model: "claude-sonnet-4-5".to_string(),
         messages: vec![Message {
@@ -423,6 +464,9 @@ fn test_redacted_thinking_degradation() {
         output_config: None,
         size: None,
         quality: None,
+        service_tier: None,
+        tool_choice: None,
+        stop_sequences: None,
     };
 
     let result = transform_claude_request_in(&req, "test-project", false);
@@ -430,12 +474,59 @@ fn test_redacted_thinking_degradation() {
     let body = result.unwrap();
     let parts = body["request"]["contents"][0]["parts"].as_array().unwrap();
 
-    let text = parts[0]["text"].as_str().unwrap();
-    assert!(text.contains("[Redacted Thinking: some data]"));
-    assert!(
-        parts[0].get("thought").is_none(),
-        "Redacted thinking should NOT have thought: true"
-    );
+    // 加密内容不以文本形式泄露给上游
+    assert_eq!(parts.len(), 1);
+    assert_eq!(parts[0]["text"], "Hi");
+    assert!(!body.to_string().contains("some data"));
+}
+
+#[test]
+fn test_redacted_thinking_around_tool_use_keeps_text() {
+    let req = ClaudeRequest {
+        model: "claude-sonnet-4-5".to_string(),
+        messages: vec![
+            Message {
+                role: "user".to_string(),
+                content: MessageContent::String("List the files please".to_string()),
+            },
+            Message {
+                role: "assistant".to_string(),
+                content: MessageContent::Array(vec![
+                    ContentBlock::RedactedThinking { data: "r1".to_string() },
+                    ContentBlock::ToolUse {
+                        id: "toolu_1".to_string(),
+                        name: "bash".to_string(),
+                        input: json!({"command": "ls"}),
+                        signature: None,
+                        cache_control: None,
+                    },
+                    ContentBlock::RedactedThinking { data: "r2".to_string() },
+                    ContentBlock::Text { text: "Listing now".to_string() },
+                ]),
+            },
+        ],
+        system: None,
+        tools: None,
+        stream: false,
+        max_tokens: None,
+        temperature: None,
+        top_p: None,
+        top_k: None,
+        thinking: None,
+        metadata: None,
+        output_config: None,
+        size: None,
+        quality: None,
+        service_tier: None,
+        tool_choice: None,
+        stop_sequences: None,
+    };
+
+    let body = transform_claude_request_in(&req, "test-project", false).unwrap();
+    let parts = body["request"]["contents"][1]["parts"].as_array().unwrap();
+    let texts: Vec<&str> = parts.iter().filter_map(|p| p["text"].as_str()).collect();
+    assert_eq!(texts, vec!["Listing now"]);
+    assert!(parts.last().unwrap().get("functionCall").is_some());
 }
 
 #[test]
@@ -609,6 +700,9 @@ fn test_default_max_tokens() {
         output_config: None,
         size: None,
         quality: None,
+        service_tier: None,
+        tool_choice: None,
+        stop_sequences: None,
     };
 
     let result = transform_claude_request_in(&req, "test-v", false).unwrap();
@@ -618,3 +712,198 @@ fn test_default_max_tokens() {
         "maxOutputTokens should not be set when max_tokens is None"
     );
 }
+
+#[test]
+fn test_disabled_thinking_suppresses_gemini_thoughts() {
+    let req = ClaudeRequest {
+        model: "gemini-3-pro-high".to_string(),
+        messages: vec![Message {
+            role: "user".to_string(),
+            content: MessageContent::String("Hello".to_string()),
+        }],
+        system: None,
+        tools: None,
+        stream: false,
+        max_tokens: None,
+        temperature: None,
+        top_p: None,
+        top_k: None,
+        thinking: Some(ThinkingConfig {
+            type_: "disabled".to_string(),
+            budget_tokens: None,
+        }),
+        metadata: None,
+        output_config: None,
+        size: None,
+        quality: None,
+        service_tier: None,
+        tool_choice: None,
+        stop_sequences: None,
+    };
+
+    let result = transform_claude_request_in(&req, "test-project", false).unwrap();
+    let thinking_config = &result["request"]["generationConfig"]["thinkingConfig"];
+    assert_eq!(thinking_config["includeThoughts"], false);
+    assert!(thinking_config.get("thinkingBudget").is_none());
+}
+
+fn tool_choice_request(tool_choice: serde_json::Value) -> ClaudeRequest {
+    serde_json::from_value(json!({
+        "model": "claude-sonnet-4-5",
+        "messages": [{ "role": "user", "content": "What's the weather in Paris?" }],
+        "tools": [
+            {
+                "name": "get_weather",
+                "description": "Get the weather for a city",
+                "input_schema": { "type": "object", "properties": { "city": { "type": "string" } } }
+            },
+            { "type": "web_search_20250305", "name": "web_search" }
+        ],
+        "tool_choice": tool_choice
+    }))
+    .unwrap()
+}
+
+#[test]
+fn test_tool_choice_maps_to_function_calling_config() {
+    let cases = [
+        (json!(null), json!({ "mode": "VALIDATED" })),
+        (json!({ "type": "auto" }), json!({ "mode": "AUTO" })),
+        (json!({ "type": "any", "disable_parallel_tool_use": true }), json!({ "mode": "ANY" })),
+        (json!({ "type": "none" }), json!({ "mode": "NONE" })),
+        (
+            json!({ "type": "tool", "name": "get_weather" }),
+            json!({ "mode": "ANY", "allowedFunctionNames": ["get_weather"] }),
+        ),
+    ];
+    for (tool_choice, expected) in cases {
+        let req = tool_choice_request(tool_choice.clone());
+        let body = transform_claude_request_in(&req, "test-project", false).unwrap();
+        assert_eq!(
+            body["request"]["toolConfig"]["functionCallingConfig"], expected,
+            "tool_choice: {}",
+            tool_choice
+        );
+    }
+}
+
+#[test]
+fn test_tool_choice_unknown_tool_is_rejected() {
+    let req = tool_choice_request(json!({ "type": "tool", "name": "get_forecast" }));
+    let err = validate_tool_choice(&req).unwrap_err();
+    assert!(err.contains("'get_forecast'"));
+    assert!(err.contains("available: get_weather"));
+    assert!(transform_claude_request_in(&req, "test-project", false).is_err());
+
+    // 服务端工具 (web_search) 不能作为强制调用目标
+    let req = tool_choice_request(json!({ "type": "tool", "name": "web_search" }));
+    assert!(validate_tool_choice(&req).is_err());
+}
+
+#[test]
+fn test_stop_sequences_forwarded_and_capped() {
+    let request = |stop_sequences: serde_json::Value| -> ClaudeRequest {
+        serde_json::from_value(json!({
+            "model": "claude-sonnet-4-5",
+            "messages": [{ "role": "user", "content": "Hello" }],
+            "stop_sequences": stop_sequences
+        }))
+        .unwrap()
+    };
+
+    let body = transform_claude_request_in(&request(json!(["\n\nHuman:", "END"])), "test-project", false).unwrap();
+    assert_eq!(
+        body["request"]["generationConfig"]["stopSequences"],
+        json!(["\n\nHuman:", "END", "<|user|>", "<|end_of_turn|>"])
+    );
+
+    // 超出 5 个时只转发前 5 个客户端序列
+    let body = transform_claude_request_in(&request(json!(["a", "b", "c", "d", "e", "f", "g"])), "test-project", false).unwrap();
+    assert_eq!(body["request"]["generationConfig"]["stopSequences"], json!(["a", "b", "c", "d", "e"]));
+
+    let body = transform_claude_request_in(&request(json!(null)), "test-project", false).unwrap();
+    assert_eq!(
+        body["request"]["generationConfig"]["stopSequences"],
+        json!(["<|user|>", "<|end_of_turn|>", "\n\nHuman:"])
+    );
+}
+
+fn thinking_request(thinking: serde_json::Value, messages: serde_json::Value) -> ClaudeRequest {
+    serde_json::from_value(json!({
+        "model": "claude-sonnet-4-5",
+        "messages": messages,
+        "thinking": thinking
+    }))
+    .unwrap()
+}
+
+#[test]
+fn test_disabled_thinking_sends_zero_budget() {
+    let req = thinking_request(
+        json!({ "type": "disabled" }),
+        json!([{ "role": "user", "content": "Hello" }]),
+    );
+    let body = transform_claude_request_in(&req, "test-project", false).unwrap();
+    let gen_config = &body["request"]["generationConfig"];
+    assert_eq!(gen_config["thinkingConfig"], json!({ "includeThoughts": false, "thinkingBudget": 0 }));
+    // 0 预算不触发 maxOutputTokens 抬升
+    assert!(gen_config.get("maxOutputTokens").is_none());
+}
+
+#[test]
+fn test_thinking_budget_after_signature_retry() {
+    // 签名错误重试后 thinking 块已转为文本, 历史中的 tool_use 不再有 thinking
+    let history = json!([
+        { "role": "user", "content": "list files" },
+        { "role": "assistant", "content": [
+            { "type": "text", "text": "Let me check." },
+            { "type": "tool_use", "id": "toolu_r", "name": "bash", "input": { "command": "ls" } }
+        ] },
+        { "role": "user", "content": [
+            { "type": "tool_result", "tool_use_id": "toolu_r", "content": "a.rs" }
+        ] }
+    ]);
+
+    // 客户端预算不会在 thinking 被降级关闭后残留
+    let req = thinking_request(json!({ "type": "enabled", "budget_tokens": 8192 }), history.clone());
+    let body = transform_claude_request_in(&req, "test-project", true).unwrap();
+    assert!(body["request"]["generationConfig"].get("thinkingConfig").is_none());
+
+    // 客户端显式关闭时重试仍发送 0 预算
+    let req = thinking_request(json!({ "type": "disabled", "budget_tokens": 8192 }), history);
+    let body = transform_claude_request_in(&req, "test-project", true).unwrap();
+    assert_eq!(body["request"]["generationConfig"]["thinkingConfig"]["thinkingBudget"], 0);
+}
+
+#[test]
+fn test_system_injections_wrap_client_system() {
+    use crate::proxy::common::system_injection::SystemInjection;
+    use crate::proxy::config::InjectionPosition;
+
+    let mut req = thinking_request(json!({ "type": "disabled" }), json!([{ "role": "user", "content": "Hi" }]));
+    req.system = Some(SystemPrompt::String("CLIENT SYSTEM".to_string()));
+    let injections = [
+        SystemInjection {
+            name: "style".to_string(),
+            position: InjectionPosition::Append,
+            text: "Answer briefly.".to_string(),
+        },
+        SystemInjection {
+            name: "safety".to_string(),
+            position: InjectionPosition::Prepend,
+            text: "Stay family friendly.".to_string(),
+        },
+    ];
+
+    let body = transform_claude_request_in_with_mode(&req, "test-project", false, false, &injections).unwrap();
+    let system = serde_json::to_string(&body["request"]["systemInstruction"]).unwrap();
+    let prepend = system.find("[INJECTED: safety]").expect("prepend profile injected");
+    let client = system.find("CLIENT SYSTEM").unwrap();
+    let append = system.find("[INJECTED: style]").expect("append profile injected");
+    assert!(prepend < client && client < append);
+    assert!(system.contains("[END INJECTED: style]"));
+
+    // 未配置注入时 system 内容不变
+    let plain = transform_claude_request_in(&req, "test-project", false).unwrap();
+    assert!(!serde_json::to_string(&plain["request"]["systemInstruction"]).unwrap().contains("INJECTED"));
+}
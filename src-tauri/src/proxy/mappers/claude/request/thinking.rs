@@ -36,48 +36,6 @@ pub fn should_disable_thinking_due_to_history(messages: &[Message]) -> bool {
     false
 }
 
-/// Check if thinking mode should be enabled by default for a given model
-///
-/// Claude Code v2.0.67+ enables thinking by default for Opus 4.5 models.
-/// [NEW] PR #1641: Also enables thinking for Opus 4.6 models.
-pub fn should_enable_thinking_by_default(model: &str) -> bool {
-    let model_lower = model.to_lowercase();
-
-    // [NEW] Enable thinking by default for Opus 4.6 variants (PR #1641)
-    if model_lower.contains("opus-4-6") || model_lower.contains("opus-4.6") {
-        tracing::debug!(
-            "[Thinking-Mode] Auto-enabling thinking for Opus 4.6 model: {}",
-            model
-        );
-        return true;
-    }
-
-    // Enable thinking by default for Opus 4.5 variants
-    if model_lower.contains("opus-4-5") || model_lower.contains("opus-4.5") {
-        tracing::debug!(
-            "[Thinking-Mode] Auto-enabling thinking for Opus 4.5 model: {}",
-            model
-        );
-        return true;
-    }
-
-    // [FIX #1557] Enable thinking by default for Gemini Pro families.
-    if model_lower.contains("gemini-2.0-pro") || model_lower.contains("gemini-3-pro") {
-        tracing::debug!(
-            "[Thinking-Mode] Auto-enabling thinking for Gemini Pro model: {}",
-            model
-        );
-        return true;
-    }
-
-    // Also enable for explicit thinking model variants
-    if model_lower.contains("-thinking") {
-        return true;
-    }
-
-    false
-}
-
 /// Check if we have any valid signature available for function calls
 /// This prevents Gemini 3 Pro from rejecting requests due to missing thought_signature
 pub fn has_valid_signature_for_function_calls(
@@ -0,0 +1,214 @@
+// 工具声明的会话级复用
+// Claude Code 每轮都发送完全相同的工具数组 (~40KB), 每次都重新清洗 schema。
+// 同一会话内工具数组未变化 (哈希相同) 时跳过清洗, 直接复用上一轮构建的 Gemini 工具结构,
+// 同时保证请求前缀稳定, 有利于隐式缓存。工具数组变化或会话换绑账号 (project 变化) 时失效。
+// 另按会话统计工具 schema 的 token 开销, 让用户看到这部分成本。
+
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::tools::build_tools;
+use crate::proxy::mappers::claude::models::Tool;
+use crate::proxy::mappers::context_manager::estimate_tokens_from_str;
+
+/// 最多跟踪的会话数, 超出时淘汰最久未活动的会话
+const MAX_TRACKED_SESSIONS: usize = 2_000;
+/// 统计中返回的会话数 (按累计开销排序)
+const TOP_SESSIONS: usize = 20;
+
+#[derive(Debug, Clone)]
+struct CachedTools {
+    hash: String,
+    project_id: String,
+    tools: Option<Value>,
+    schema_tokens: u32,
+    requests: u64,
+    total_schema_tokens: u64,
+    last_seen: i64,
+}
+
+static SESSIONS: Lazy<DashMap<String, CachedTools>> = Lazy::new(DashMap::new);
+static REUSED: AtomicU64 = AtomicU64::new(0);
+static REBUILT: AtomicU64 = AtomicU64::new(0);
+static INVALIDATED_BY_CHANGE: AtomicU64 = AtomicU64::new(0);
+static INVALIDATED_BY_REBIND: AtomicU64 = AtomicU64::new(0);
+
+/// 单个会话的工具 schema 开销
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SessionToolSchemaCost {
+    pub session_id: String,
+    pub requests: u64,
+    /// 每个请求携带的工具 schema token 数 (估算)
+    pub schema_tokens: u32,
+    pub total_schema_tokens: u64,
+}
+
+/// 工具声明复用统计 (启动以来)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ToolSchemaStats {
+    /// 复用上一轮构建结果的请求数
+    pub reused: u64,
+    /// 重新构建的请求数
+    pub rebuilt: u64,
+    /// 因工具数组变化而失效的次数
+    pub invalidated_by_change: u64,
+    /// 因会话换绑账号而失效的次数
+    pub invalidated_by_rebind: u64,
+    pub sessions: Vec<SessionToolSchemaCost>,
+}
+
+pub fn stats() -> ToolSchemaStats {
+    let mut sessions: Vec<SessionToolSchemaCost> = SESSIONS
+        .iter()
+        .map(|e| SessionToolSchemaCost {
+            session_id: e.key().clone(),
+            requests: e.requests,
+            schema_tokens: e.schema_tokens,
+            total_schema_tokens: e.total_schema_tokens,
+        })
+        .collect();
+    sessions.sort_by(|a, b| b.total_schema_tokens.cmp(&a.total_schema_tokens));
+    sessions.truncate(TOP_SESSIONS);
+
+    ToolSchemaStats {
+        reused: REUSED.load(Ordering::Relaxed),
+        rebuilt: REBUILT.load(Ordering::Relaxed),
+        invalidated_by_change: INVALIDATED_BY_CHANGE.load(Ordering::Relaxed),
+        invalidated_by_rebind: INVALIDATED_BY_REBIND.load(Ordering::Relaxed),
+        sessions,
+    }
+}
+
+fn tools_hash(tools: &[Tool], has_web_search: bool) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(serde_json::to_vec(tools).unwrap_or_default());
+    hasher.update([has_web_search as u8]);
+    format!("{:x}", hasher.finalize())
+}
+
+/// build_tools 的会话级缓存版本: 同一会话、同一账号且工具数组未变化时直接复用
+pub fn build_tools_cached(
+    session_id: &str,
+    project_id: &str,
+    tools: &Option<Vec<Tool>>,
+    has_web_search: bool,
+) -> Result<Option<Value>, String> {
+    let Some(tools_list) = tools.as_ref().filter(|t| !t.is_empty()) else {
+        return build_tools(tools, has_web_search);
+    };
+    if session_id.is_empty() {
+        return build_tools(tools, has_web_search);
+    }
+
+    let hash = tools_hash(tools_list, has_web_search);
+    let now = chrono::Utc::now().timestamp();
+
+    if let Some(mut entry) = SESSIONS.get_mut(session_id) {
+        if entry.hash == hash && entry.project_id == project_id {
+            entry.requests += 1;
+            entry.total_schema_tokens += entry.schema_tokens as u64;
+            entry.last_seen = now;
+            REUSED.fetch_add(1, Ordering::Relaxed);
+            return Ok(entry.tools.clone());
+        }
+        if entry.hash != hash {
+            INVALIDATED_BY_CHANGE.fetch_add(1, Ordering::Relaxed);
+        } else {
+            INVALIDATED_BY_REBIND.fetch_add(1, Ordering::Relaxed);
+        }
+        tracing::debug!("[Claude-Request] Tool declarations invalidated for session {}", session_id);
+    }
+
+    let built = build_tools(tools, has_web_search)?;
+    REBUILT.fetch_add(1, Ordering::Relaxed);
+    let schema_tokens = built
+        .as_ref()
+        .map(|t| estimate_tokens_from_str(&t.to_string()))
+        .unwrap_or(0);
+
+    evict_if_full(session_id);
+    let mut entry = SESSIONS.entry(session_id.to_string()).or_insert_with(|| CachedTools {
+        hash: String::new(),
+        project_id: String::new(),
+        tools: None,
+        schema_tokens: 0,
+        requests: 0,
+        total_schema_tokens: 0,
+        last_seen: now,
+    });
+    entry.hash = hash;
+    entry.project_id = project_id.to_string();
+    entry.tools = built.clone();
+    entry.schema_tokens = schema_tokens;
+    entry.requests += 1;
+    entry.total_schema_tokens += schema_tokens as u64;
+    entry.last_seen = now;
+    Ok(built)
+}
+
+fn evict_if_full(session_id: &str) {
+    if SESSIONS.len() < MAX_TRACKED_SESSIONS || SESSIONS.contains_key(session_id) {
+        return;
+    }
+    let oldest = SESSIONS
+        .iter()
+        .min_by_key(|e| e.last_seen)
+        .map(|e| e.key().clone());
+    if let Some(key) = oldest {
+        SESSIONS.remove(&key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn tool(name: &str, description: &str) -> Tool {
+        Tool {
+            type_: None,
+            name: Some(name.to_string()),
+            description: Some(description.to_string()),
+            input_schema: Some(json!({
+                "type": "object",
+                "properties": { "path": { "type": "string", "minLength": 1 } },
+                "additionalProperties": false
+            })),
+        }
+    }
+
+    #[test]
+    fn test_reuse_and_invalidation() {
+        let session = "tool-cache-test-session";
+        let tools = Some(vec![tool("Read", "Read a file")]);
+
+        let first = build_tools_cached(session, "project-a", &tools, false).unwrap();
+        assert_eq!(first, build_tools(&tools, false).unwrap());
+        let reused_before = REUSED.load(Ordering::Relaxed);
+        let second = build_tools_cached(session, "project-a", &tools, false).unwrap();
+        assert_eq!(second, first);
+        assert!(REUSED.load(Ordering::Relaxed) > reused_before);
+
+        let entry = SESSIONS.get(session).unwrap().clone();
+        assert_eq!(entry.requests, 2);
+        assert!(entry.schema_tokens > 0);
+        assert_eq!(entry.total_schema_tokens, entry.schema_tokens as u64 * 2);
+
+        // 工具数组变化: 重新构建
+        let changed = Some(vec![tool("Read", "Read a file"), tool("Write", "Write a file")]);
+        let rebuilt = build_tools_cached(session, "project-a", &changed, false).unwrap();
+        assert_eq!(rebuilt.unwrap()[0]["functionDeclarations"].as_array().unwrap().len(), 2);
+
+        // 换绑账号: 同样的工具也重新构建
+        let rebind_before = INVALIDATED_BY_REBIND.load(Ordering::Relaxed);
+        build_tools_cached(session, "project-b", &changed, false).unwrap();
+        assert!(INVALIDATED_BY_REBIND.load(Ordering::Relaxed) > rebind_before);
+        assert_eq!(SESSIONS.get(session).unwrap().project_id, "project-b");
+
+        assert!(stats().sessions.iter().any(|s| s.session_id == session));
+    }
+}
@@ -1,7 +1,7 @@
 // Tools Builder for Gemini API
 
 use serde_json::{json, Value};
-use crate::proxy::mappers::claude::models::Tool;
+use crate::proxy::mappers::claude::models::{ClaudeRequest, Tool, ToolChoice};
 
 /// Build Tools for Gemini API
 pub fn build_tools(tools: &Option<Vec<Tool>>, has_web_search: bool) -> Result<Option<Value>, String> {
@@ -73,3 +73,37 @@ pub fn build_tools(tools: &Option<Vec<Tool>>, has_web_search: bool) -> Result<Op
 
     Ok(None)
 }
+
+/// Check that a named `tool_choice` refers to one of the client tools in `tools`
+pub fn validate_tool_choice(claude_req: &ClaudeRequest) -> Result<(), String> {
+    let Some(ToolChoice::Tool { name }) = &claude_req.tool_choice else {
+        return Ok(());
+    };
+    let available: Vec<&str> = claude_req
+        .tools
+        .iter()
+        .flatten()
+        .filter(|t| !t.is_web_search())
+        .filter_map(|t| t.name.as_deref())
+        .collect();
+    if available.contains(&name.as_str()) {
+        return Ok(());
+    }
+    Err(format!(
+        "tool_choice: tool '{}' not found in tools (available: {})",
+        name,
+        if available.is_empty() { "none".to_string() } else { available.join(", ") }
+    ))
+}
+
+/// Build toolConfig from Claude `tool_choice`; without it keep the VALIDATED mode
+pub fn build_tool_config(tool_choice: Option<&ToolChoice>) -> Value {
+    let config = match tool_choice {
+        None => json!({ "mode": "VALIDATED" }),
+        Some(ToolChoice::Auto) => json!({ "mode": "AUTO" }),
+        Some(ToolChoice::Any) => json!({ "mode": "ANY" }),
+        Some(ToolChoice::Tool { name }) => json!({ "mode": "ANY", "allowedFunctionNames": [name] }),
+        Some(ToolChoice::None) => json!({ "mode": "NONE" }),
+    };
+    json!({ "functionCallingConfig": config })
+}
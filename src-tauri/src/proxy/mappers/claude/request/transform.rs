@@ -5,13 +5,16 @@ use super::cleanup::{clean_cache_control_from_messages, deep_clean_cache_control
 use super::contents::build_google_contents;
 use super::generation::build_generation_config;
 use super::safety::build_safety_settings;
-use super::sorting::{merge_consecutive_messages, sort_thinking_blocks_first};
+use super::sorting::{
+    drop_placeholder_text_blocks, merge_consecutive_messages, sort_thinking_blocks_first,
+};
 use super::system::build_system_instruction;
 use super::thinking::{
     has_valid_signature_for_function_calls, should_disable_thinking_due_to_history,
-    should_enable_thinking_by_default,
 };
-use super::tools::build_tools;
+use super::tool_cache::build_tools_cached;
+use super::tools::{build_tool_config, validate_tool_choice};
+use crate::proxy::common::system_injection::SystemInjection;
 use crate::proxy::mappers::claude::models::*;
 use crate::proxy::session_manager::SessionManager;
 use serde_json::{json, Value};
@@ -22,20 +25,39 @@ pub fn transform_claude_request_in(
     claude_req: &ClaudeRequest,
     project_id: &str,
     is_retry: bool,
+) -> Result<Value, String> {
+    transform_claude_request_in_with_mode(claude_req, project_id, is_retry, false, &[])
+}
+
+/// Transform with optional raw-messages mode (debug): messages are converted as the client sent them,
+/// without merging, cache_control cleanup or thinking block reordering.
+/// `injections` are the system prompt profiles configured for the API key / mapping entry.
+pub fn transform_claude_request_in_with_mode(
+    claude_req: &ClaudeRequest,
+    project_id: &str,
+    is_retry: bool,
+    raw_messages: bool,
+    injections: &[SystemInjection],
 ) -> Result<Value, String> {
     // Pre-clean all cache_control fields from messages
     let mut cleaned_req = claude_req.clone();
 
-    // Merge consecutive same-role messages
-    merge_consecutive_messages(&mut cleaned_req.messages);
+    if !raw_messages {
+        // Merge consecutive same-role messages
+        merge_consecutive_messages(&mut cleaned_req.messages);
 
-    clean_cache_control_from_messages(&mut cleaned_req.messages);
+        clean_cache_control_from_messages(&mut cleaned_req.messages);
 
-    // Pre-sort thinking blocks to be first in assistant messages
-    sort_thinking_blocks_first(&mut cleaned_req.messages);
+        // Pre-sort thinking blocks to be first in assistant messages
+        drop_placeholder_text_blocks(&mut cleaned_req.messages);
+        sort_thinking_blocks_first(&mut cleaned_req.messages);
+    }
 
     let claude_req = &cleaned_req;
 
+    // A named tool_choice must refer to one of the declared tools
+    validate_tool_choice(claude_req)?;
+
     // Generate session ID for signature tracking
     let session_id = SessionManager::extract_session_id(claude_req);
     tracing::debug!("[Claude-Request] Session ID: {}", session_id);
@@ -82,7 +104,7 @@ pub fn transform_claude_request_in(
 
     // Build System Instruction
     let system_instruction =
-        build_system_instruction(&claude_req.system, &claude_req.model, has_mcp_tools);
+        build_system_instruction(&claude_req.system, &claude_req.model, has_mcp_tools, injections);
 
     // Map model name
     const WEB_SEARCH_FALLBACK_MODEL: &str = "gemini-2.5-flash";
@@ -121,14 +143,11 @@ pub fn transform_claude_request_in(
         .thinking
         .as_ref()
         .map(|t| t.type_ == "enabled")
-        .unwrap_or_else(|| should_enable_thinking_by_default(&claude_req.model));
+        .unwrap_or_else(|| crate::proxy::common::thinking_defaults::default_enabled(&claude_req.model));
 
-    // Check if target model supports thinking
-    let mapped_model_lower = mapped_model.to_lowercase();
-    let target_model_supports_thinking = mapped_model_lower.contains("-thinking")
-        || mapped_model_lower.starts_with("claude-")
-        || mapped_model_lower.contains("gemini-2.0-pro")
-        || mapped_model_lower.contains("gemini-3-pro");
+    // Check if target model supports thinking (静态规则 + 运行期学习 + 用户覆盖)
+    let target_model_supports_thinking =
+        crate::proxy::common::thinking_capability::supports_thinking(&mapped_model);
 
     if is_thinking_enabled && !target_model_supports_thinking {
         tracing::warn!(
@@ -198,7 +217,7 @@ pub fn transform_claude_request_in(
 
     // Build Generation Config
     let generation_config =
-        build_generation_config(claude_req, has_web_search_tool, is_thinking_enabled);
+        build_generation_config(claude_req, has_web_search_tool, is_thinking_enabled, &mapped_model);
 
     // Build Contents
     let contents = build_google_contents(
@@ -213,8 +232,8 @@ pub fn transform_claude_request_in(
         is_retry,
     )?;
 
-    // Build Tools
-    let tools = build_tools(&claude_req.tools, has_web_search_tool)?;
+    // Build Tools (reused while the session's tools array is unchanged)
+    let tools = build_tools_cached(&session_id, project_id, &claude_req.tools, has_web_search_tool)?;
 
     // Build Safety Settings
     let safety_settings = build_safety_settings();
@@ -238,11 +257,7 @@ pub fn transform_claude_request_in(
 
     if let Some(tools_val) = tools {
         inner_request["tools"] = tools_val;
-        inner_request["toolConfig"] = json!({
-            "functionCallingConfig": {
-                "mode": "VALIDATED"
-            }
-        });
+        inner_request["toolConfig"] = build_tool_config(claude_req.tool_choice.as_ref());
     }
 
     // Inject googleSearch tool if needed
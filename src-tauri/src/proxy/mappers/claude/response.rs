@@ -2,8 +2,12 @@
 // 对应 NonStreamingProcessor
 
 use super::models::*;
+use super::stop_sequences::{find_stop_sequence, infer_upstream_stop};
 use super::utils::to_claude_usage;
+use crate::proxy::common::post_process::TextPostProcessor;
+use crate::proxy::mappers::candidates::select_primary;
 use serde_json::json;
+use std::sync::Arc;
 
 /// Known parameter remappings for Gemini → Claude compatibility
 /// [FIX] Gemini sometimes uses different parameter names than specified in tool schema
@@ -155,9 +159,17 @@ pub struct NonStreamingProcessor {
     pub has_tool_call: bool,
     pub scaling_enabled: bool,
     pub context_limit: u32,
+    /// 从 input_tokens 中扣除的注入内容 token 数
+    pub hidden_input_tokens: u32,
     pub session_id: Option<String>,
     pub model_name: String,
     pub message_count: usize, // [NEW v4.0.0] Message count for rewind detection
+    /// 请求携带 interleaved-thinking beta: 保留 thinking 与 text/tool_use 的原始交错顺序
+    pub interleaved_thinking: bool,
+    /// 助手文本后处理 (None = 关闭)
+    pub post_processor: Option<Arc<TextPostProcessor>>,
+    /// 未请求 thinking: 丢弃上游仍返回的 thought parts
+    pub strip_thinking: bool,
 }
 
 impl NonStreamingProcessor {
@@ -171,9 +183,13 @@ impl NonStreamingProcessor {
             has_tool_call: false,
             scaling_enabled: false,
             context_limit: 1_048_576, // Default to 1M
+            hidden_input_tokens: 0,
             session_id,
             model_name,
             message_count,
+            interleaved_thinking: false,
+            post_processor: None,
+            strip_thinking: false,
         }
     }
 
@@ -186,12 +202,16 @@ impl NonStreamingProcessor {
     ) -> ClaudeResponse {
         self.scaling_enabled = scaling_enabled;
         self.context_limit = context_limit;
+        // 多候选时只取 index 0, 其余忽略 (Claude 协议只有一条消息)
+        let mut warned = false;
+        let candidate = gemini_response
+            .candidates
+            .as_deref()
+            .and_then(|c| select_primary(c, |cand| cand.index, &mut warned));
+
         // 获取 parts
         let empty_parts = vec![];
-        let parts = gemini_response
-            .candidates
-            .as_ref()
-            .and_then(|c| c.get(0))
+        let parts = candidate
             .and_then(|candidate| candidate.content.as_ref())
             .map(|content| &content.parts)
             .unwrap_or(&empty_parts);
@@ -202,7 +222,7 @@ impl NonStreamingProcessor {
         }
 
         // 处理 grounding(web search) -> 转换为 server_tool_use / web_search_tool_result
-        if let Some(candidate) = gemini_response.candidates.as_ref().and_then(|c| c.get(0)) {
+        if let Some(candidate) = candidate {
             if let Some(grounding) = &candidate.grounding_metadata {
                 self.process_grounding(grounding);
             }
@@ -212,6 +232,21 @@ impl NonStreamingProcessor {
         self.flush_thinking();
         self.flush_text();
 
+        // 未请求 thinking 时上游仍返回了 thought parts (上游忽略了 includeThoughts: false)
+        if self.strip_thinking {
+            self.trailing_signature = None;
+            let before = self.content_blocks.len();
+            self.content_blocks.retain(|block| {
+                !matches!(block, ContentBlock::Thinking { .. } | ContentBlock::RedactedThinking { .. })
+            });
+            if self.content_blocks.len() < before {
+                tracing::warn!(
+                    "[Claude-Response] Upstream returned thought parts although thinking was not requested, stripped {} block(s)",
+                    before - self.content_blocks.len()
+                );
+            }
+        }
+
         // 处理 trailingSignature (空 text 带签名)
         if let Some(signature) = self.trailing_signature.take() {
             self.content_blocks.push(ContentBlock::Thinking {
@@ -221,8 +256,24 @@ impl NonStreamingProcessor {
             });
         }
 
+        // 助手文本后处理: 只作用于 text 块, 处理后为空的块直接移除
+        if let Some(post_processor) = &self.post_processor {
+            for block in self.content_blocks.iter_mut() {
+                if let ContentBlock::Text { text } = block {
+                    *text = post_processor.apply(text);
+                }
+            }
+            self.content_blocks
+                .retain(|block| !matches!(block, ContentBlock::Text { text } if text.is_empty()));
+        }
+
+        // 未启用交错 thinking 的客户端: 沿用 thinking 在前的旧顺序
+        if !self.interleaved_thinking {
+            order_thinking_first(&mut self.content_blocks);
+        }
+
         // 构建响应
-        self.build_response(gemini_response)
+        self.build_response(gemini_response, candidate.and_then(|c| c.finish_reason.as_deref()))
     }
 
     /// 处理单个 part
@@ -495,13 +546,7 @@ impl NonStreamingProcessor {
     }
 
     /// 构建最终响应
-    fn build_response(&self, gemini_response: &GeminiResponse) -> ClaudeResponse {
-        let finish_reason = gemini_response
-            .candidates
-            .as_ref()
-            .and_then(|c| c.get(0))
-            .and_then(|candidate| candidate.finish_reason.as_deref());
-
+    fn build_response(&self, gemini_response: &GeminiResponse, finish_reason: Option<&str>) -> ClaudeResponse {
         let stop_reason = if self.has_tool_call {
             "tool_use"
         } else if finish_reason == Some("MAX_TOKENS") {
@@ -513,13 +558,14 @@ impl NonStreamingProcessor {
         let usage = gemini_response
             .usage_metadata
             .as_ref()
-            .map(|u| to_claude_usage(u, self.scaling_enabled, self.context_limit))
+            .map(|u| to_claude_usage(u, self.scaling_enabled, self.context_limit, self.hidden_input_tokens))
             .unwrap_or(Usage {
                 input_tokens: 0,
                 output_tokens: 0,
                 cache_read_input_tokens: None,
                 cache_creation_input_tokens: None,
                 server_tool_use: None,
+                service_tier: None,
             });
 
         ClaudeResponse {
@@ -537,16 +583,69 @@ impl NonStreamingProcessor {
     }
 }
 
+/// 文本中出现客户端停止序列时截断该文本块并丢弃其后的内容块;
+/// 未出现时按上游的结束原因推断命中的序列 (Gemini 不回显停止序列)
+fn apply_stop_sequences(response: &mut ClaudeResponse, stop_sequences: &[String], finish_reason: Option<&str>) {
+    if stop_sequences.is_empty() || response.stop_reason == "tool_use" {
+        return;
+    }
+    let hit = response.content.iter().enumerate().find_map(|(index, block)| match block {
+        ContentBlock::Text { text } => {
+            find_stop_sequence(text, stop_sequences).map(|(pos, seq)| (index, pos, seq.to_string()))
+        }
+        _ => None,
+    });
+    let Some((index, pos, sequence)) = hit else {
+        if let Some(sequence) = infer_upstream_stop(finish_reason, stop_sequences) {
+            response.stop_reason = "stop_sequence".to_string();
+            response.stop_sequence = Some(sequence.to_string());
+        }
+        return;
+    };
+    response.content.truncate(index + 1);
+    if let Some(ContentBlock::Text { text }) = response.content.last_mut() {
+        text.truncate(pos);
+        if text.is_empty() {
+            response.content.pop();
+        }
+    }
+    response.stop_reason = "stop_sequence".to_string();
+    response.stop_sequence = Some(sequence);
+}
+
+/// 将 thinking 块稳定地移到其余内容块之前 (各自内部顺序不变)
+fn order_thinking_first(blocks: &mut [ContentBlock]) {
+    blocks.sort_by_key(|block| !matches!(block, ContentBlock::Thinking { .. }));
+}
+
 pub fn transform_response(
     gemini_response: &GeminiResponse,
     scaling_enabled: bool,
     context_limit: u32,
+    hidden_input_tokens: u32,
     session_id: Option<String>,
     model_name: String,
     message_count: usize, // [NEW v4.0.0] Message count for rewind detection
+    interleaved_thinking: bool,
+    post_processor: Option<Arc<TextPostProcessor>>,
+    strip_thinking: bool, // 未请求 thinking: 丢弃上游仍返回的 thought parts
+    stop_sequences: &[String],
 ) -> Result<ClaudeResponse, String> {
     let mut processor = NonStreamingProcessor::new(session_id, model_name, message_count);
-    Ok(processor.process(gemini_response, scaling_enabled, context_limit))
+    processor.interleaved_thinking = interleaved_thinking;
+    processor.post_processor = post_processor;
+    processor.strip_thinking = strip_thinking;
+    processor.hidden_input_tokens = hidden_input_tokens;
+    let mut response = processor.process(gemini_response, scaling_enabled, context_limit);
+    // 与 process 取同一个候选; 多候选已在 process 中计数并告警
+    let mut warned = true;
+    let finish_reason = gemini_response
+        .candidates
+        .as_deref()
+        .and_then(|c| select_primary(c, |cand| cand.index, &mut warned))
+        .and_then(|c| c.finish_reason.as_deref());
+    apply_stop_sequences(&mut response, stop_sequences, finish_reason);
+    Ok(response)
 }
 
 #[cfg(test)]
@@ -586,9 +685,14 @@ mod tests {
             &gemini_resp,
             false,
             1_000_000,
+            0,
             None,
             "gemini-2.5-flash".to_string(),
             1,
+            false,
+            None,
+            false,
+            &[],
         );
         assert!(result.is_ok());
 
@@ -643,9 +747,14 @@ mod tests {
             &gemini_resp,
             false,
             1_000_000,
+            0,
             None,
             "gemini-2.5-flash".to_string(),
             1,
+            false,
+            None,
+            false,
+            &[],
         );
         assert!(result.is_ok());
 
@@ -671,4 +780,101 @@ mod tests {
             _ => panic!("Expected Text block"),
         }
     }
+
+    #[test]
+    fn test_legacy_ordering_moves_thinking_first() {
+        let gemini_resp: GeminiResponse = serde_json::from_value(serde_json::json!({
+            "candidates": [{
+                "content": {
+                    "role": "model",
+                    "parts": [
+                        { "text": "Plan", "thought": true },
+                        { "text": "Reading the file." },
+                        { "text": "Check args", "thought": true },
+                        { "functionCall": { "name": "Read", "args": { "file_path": "a.rs" }, "id": "call_1" } }
+                    ]
+                },
+                "finishReason": "STOP"
+            }]
+        }))
+        .unwrap();
+
+        let kinds = |interleaved: bool| -> Vec<&'static str> {
+            transform_response(&gemini_resp, false, 1_000_000, 0, None, "gemini-3-pro".to_string(), 1, interleaved, None, false, &[])
+                .unwrap()
+                .content
+                .iter()
+                .map(|block| match block {
+                    ContentBlock::Thinking { .. } => "thinking",
+                    ContentBlock::Text { .. } => "text",
+                    ContentBlock::ToolUse { .. } => "tool_use",
+                    _ => "other",
+                })
+                .collect()
+        };
+
+        assert_eq!(kinds(true), vec!["thinking", "text", "thinking", "tool_use"]);
+        assert_eq!(kinds(false), vec!["thinking", "thinking", "text", "tool_use"]);
+    }
+
+    #[test]
+    fn test_multi_candidate_response_uses_only_index_zero() {
+        let gemini_resp: GeminiResponse = serde_json::from_value(serde_json::json!({
+            "candidates": [
+                {
+                    "index": 1,
+                    "content": { "role": "model", "parts": [{ "text": "Second answer" }] },
+                    "finishReason": "MAX_TOKENS"
+                },
+                {
+                    "index": 0,
+                    "content": { "role": "model", "parts": [{ "text": "First answer" }] },
+                    "finishReason": "STOP"
+                }
+            ]
+        }))
+        .unwrap();
+
+        let claude_resp =
+            transform_response(&gemini_resp, false, 1_000_000, 0, None, "gemini-2.5-flash".to_string(), 1, false, None, false, &[])
+                .unwrap();
+
+        assert_eq!(claude_resp.content.len(), 1);
+        match &claude_resp.content[0] {
+            ContentBlock::Text { text } => assert_eq!(text, "First answer"),
+            _ => panic!("Expected Text block"),
+        }
+        assert_eq!(claude_resp.stop_reason, "end_turn");
+    }
+
+    #[test]
+    fn test_unrequested_thoughts_are_stripped() {
+        // 手写的模拟响应 (非真实抓包): 请求未开启 thinking, 上游仍返回了 thought parts
+        let gemini_resp: GeminiResponse = serde_json::from_value(serde_json::json!({
+            "candidates": [{
+                "content": {
+                    "role": "model",
+                    "parts": [
+                        { "text": "The user wants a one-line answer.", "thought": true, "thoughtSignature": "c2lnX3Vud2FudGVk" },
+                        { "text": "Use `cargo check`." },
+                        { "text": "", "thoughtSignature": "c2lnX3RyYWlsaW5n" }
+                    ]
+                },
+                "finishReason": "STOP"
+            }],
+            "usageMetadata": { "promptTokenCount": 12, "candidatesTokenCount": 6, "totalTokenCount": 18 }
+        }))
+        .unwrap();
+
+        let claude_resp =
+            transform_response(&gemini_resp, false, 1_000_000, 0, None, "gemini-3-flash".to_string(), 1, false, None, true, &[])
+                .unwrap();
+
+        assert_eq!(claude_resp.content.len(), 1);
+        match &claude_resp.content[0] {
+            ContentBlock::Text { text } => assert_eq!(text, "Use `cargo check`."),
+            _ => panic!("Expected Text block"),
+        }
+        assert_eq!(claude_resp.stop_reason, "end_turn");
+    }
 }
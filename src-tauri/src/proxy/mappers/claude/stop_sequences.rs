@@ -0,0 +1,171 @@
+// Client stop_sequences
+// 客户端的 stop_sequences 转发为 generationConfig.stopSequences (v1internal 最多接受 5 个)。
+// Gemini 命中停止序列时不返回序列本身, 也不说明命中了哪一个, finishReason 与自然结束相同 (STOP)。
+// 为了给客户端返回 stop_reason = "stop_sequence" 与 stop_sequence:
+// - 代理在输出文本中匹配这些序列 (上游回显或超出 5 个只在本地匹配的序列), 命中后截断文本并丢弃其后的内容;
+// - 文本中未匹配到而上游以 STOP 结束时, 按 infer_upstream_stop 推断上游命中的序列。
+
+/// v1internal generationConfig.stopSequences 的最大条目数
+pub const MAX_STOP_SEQUENCES: usize = 5;
+
+/// 发往上游的停止序列: 客户端序列优先, 剩余名额填入内置的对话标记
+pub fn upstream_stop_sequences(client: &[String], builtin: &[String]) -> Vec<String> {
+    let mut sequences: Vec<String> = Vec::new();
+    for seq in client.iter().chain(builtin) {
+        if !seq.is_empty() && !sequences.contains(seq) {
+            sequences.push(seq.clone());
+        }
+    }
+    let client_count = client.iter().filter(|s| !s.is_empty()).count();
+    if client_count > MAX_STOP_SEQUENCES {
+        tracing::warn!(
+            "[Claude-Request] {} stop_sequences provided, forwarding the first {} upstream (the rest are matched locally)",
+            client_count,
+            MAX_STOP_SEQUENCES
+        );
+    }
+    sequences.truncate(MAX_STOP_SEQUENCES);
+    sequences
+}
+
+/// 文本中最早出现的停止序列 (位置, 序列)
+pub fn find_stop_sequence<'a>(text: &str, sequences: &'a [String]) -> Option<(usize, &'a str)> {
+    sequences
+        .iter()
+        .filter(|s| !s.is_empty())
+        .filter_map(|s| text.find(s.as_str()).map(|pos| (pos, s.as_str())))
+        .min_by_key(|(pos, _)| *pos)
+}
+
+/// 上游以 STOP 结束、文本中未匹配到序列时推断上游命中的客户端序列。
+/// 只有一个客户端序列时按命中该序列处理; 多个序列无法区分命中了哪一个, 返回 None (保持 end_turn)
+pub fn infer_upstream_stop<'a>(finish_reason: Option<&str>, client: &'a [String]) -> Option<&'a str> {
+    if finish_reason != Some("STOP") {
+        return None;
+    }
+    let mut sequences = client.iter().filter(|s| !s.is_empty());
+    match (sequences.next(), sequences.next()) {
+        (Some(only), None) => Some(only.as_str()),
+        _ => None,
+    }
+}
+
+/// 流式文本的停止序列匹配: 保留可能是某个序列前缀的末尾文本, 避免跨分片的序列被拆开输出
+#[derive(Debug, Clone, Default)]
+pub struct StopSequenceMatcher {
+    sequences: Vec<String>,
+    pending: String,
+    matched: Option<String>,
+}
+
+impl StopSequenceMatcher {
+    pub fn new(sequences: Vec<String>) -> Self {
+        Self {
+            sequences: sequences.into_iter().filter(|s| !s.is_empty()).collect(),
+            pending: String::new(),
+            matched: None,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.sequences.is_empty()
+    }
+
+    /// 已命中的序列
+    pub fn matched(&self) -> Option<&str> {
+        self.matched.as_deref()
+    }
+
+    /// 流结束时的命中序列: 文本中匹配到的优先, 否则按上游的结束原因推断
+    pub fn resolve(&self, finish_reason: Option<&str>) -> Option<&str> {
+        self.matched().or_else(|| infer_upstream_stop(finish_reason, &self.sequences))
+    }
+
+    /// 追加一个分片, 返回已可安全输出的文本; 命中后不再输出任何文本
+    pub fn push(&mut self, chunk: &str) -> String {
+        if self.matched.is_some() {
+            return String::new();
+        }
+        self.pending.push_str(chunk);
+
+        if let Some((pos, seq)) = find_stop_sequence(&self.pending, &self.sequences) {
+            self.matched = Some(seq.to_string());
+            let out = self.pending[..pos].to_string();
+            self.pending.clear();
+            return out;
+        }
+
+        let split = self.held_suffix_start();
+        self.pending.drain(..split).collect()
+    }
+
+    /// 块结束或流结束: 输出保留的文本
+    pub fn finish(&mut self) -> String {
+        std::mem::take(&mut self.pending)
+    }
+
+    /// 最长的、同时是某个序列前缀的末尾文本的起点
+    fn held_suffix_start(&self) -> usize {
+        self.pending
+            .char_indices()
+            .map(|(idx, _)| idx)
+            .find(|idx| {
+                let tail = &self.pending[*idx..];
+                self.sequences.iter().any(|s| s.starts_with(tail))
+            })
+            .unwrap_or(self.pending.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_upstream_sequences_are_capped() {
+        let builtin = strings(&["<|user|>", "<|end_of_turn|>", "\n\nHuman:"]);
+        assert_eq!(
+            upstream_stop_sequences(&strings(&["END", "\n\nHuman:"]), &builtin),
+            strings(&["END", "\n\nHuman:", "<|user|>", "<|end_of_turn|>"])
+        );
+
+        let client = strings(&["a", "b", "c", "d", "e", "f"]);
+        assert_eq!(upstream_stop_sequences(&client, &builtin), strings(&["a", "b", "c", "d", "e"]));
+    }
+
+    #[test]
+    fn test_matcher_holds_back_partial_sequence() {
+        let mut matcher = StopSequenceMatcher::new(strings(&["\n\nHuman:", "STOP"]));
+        assert_eq!(matcher.push("Hello wor"), "Hello wor");
+        assert_eq!(matcher.push("ld.\n"), "ld.");
+        assert_eq!(matcher.push("\nHum"), "");
+        assert_eq!(matcher.push("an: next turn"), "");
+        assert_eq!(matcher.matched(), Some("\n\nHuman:"));
+        assert_eq!(matcher.push("more"), "");
+        assert_eq!(matcher.finish(), "");
+
+        // 前缀最终没有构成序列: 在块结束时原样输出
+        let mut matcher = StopSequenceMatcher::new(strings(&["STOP"]));
+        assert_eq!(matcher.push("Fine, ST"), "Fine, ");
+        assert_eq!(matcher.finish(), "ST");
+        assert_eq!(matcher.matched(), None);
+    }
+
+    #[test]
+    fn test_upstream_stop_is_inferred_without_echo() {
+        // 上游不回显序列: 唯一的客户端序列按命中处理
+        let matcher = StopSequenceMatcher::new(strings(&["\n\nHuman:"]));
+        assert_eq!(matcher.resolve(Some("STOP")), Some("\n\nHuman:"));
+        assert_eq!(matcher.resolve(Some("MAX_TOKENS")), None);
+        assert_eq!(matcher.resolve(None), None);
+
+        // 多个序列无法区分
+        let matcher = StopSequenceMatcher::new(strings(&["END", "\n\nHuman:"]));
+        assert_eq!(matcher.resolve(Some("STOP")), None);
+        assert_eq!(infer_upstream_stop(Some("STOP"), &[]), None);
+    }
+}
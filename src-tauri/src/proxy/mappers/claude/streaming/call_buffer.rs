@@ -0,0 +1,195 @@
+//! Function call argument buffering.
+//!
+//! 大型工具调用 (如整文件写入) 的参数可能分多个 SSE 分片到达: 后续分片不带 name,
+//! 或以 partialArgs / willContinue 形式增量下发。逐片下发 tool_use 会截断参数,
+//! 这里按调用缓存分片, 直到下一个调用开始、finishReason 到达或流结束时再整体下发。
+
+use serde_json::{Map, Value};
+
+use crate::proxy::mappers::claude::models::{FunctionCall, PartialArg};
+
+/// 正在拼接的函数调用
+#[derive(Debug, Clone)]
+pub struct PendingFunctionCall {
+    name: String,
+    id: Option<String>,
+    args: Option<Value>,
+    signature: Option<String>,
+    will_continue: bool,
+    fragments: usize,
+}
+
+impl PendingFunctionCall {
+    pub fn new(fc: &FunctionCall, signature: Option<String>) -> Self {
+        let mut pending = Self {
+            name: fc.name.clone(),
+            id: fc.id.clone(),
+            args: None,
+            signature: None,
+            will_continue: false,
+            fragments: 0,
+        };
+        pending.append(fc, signature);
+        pending
+    }
+
+    /// 分片是否属于当前调用: 不带 name、id 相同, 或上一分片声明 willContinue 且 name 相同
+    pub fn accepts(&self, fc: &FunctionCall) -> bool {
+        fc.name.is_empty()
+            || (fc.id.is_some() && fc.id == self.id)
+            || (self.will_continue && fc.name == self.name)
+    }
+
+    pub fn append(&mut self, fc: &FunctionCall, signature: Option<String>) {
+        if self.signature.is_none() {
+            self.signature = signature;
+        }
+        if self.id.is_none() {
+            self.id = fc.id.clone();
+        }
+        if let Some(fragment) = &fc.args {
+            match self.args.as_mut() {
+                Some(args) => merge_args(args, fragment),
+                None => self.args = Some(fragment.clone()),
+            }
+        }
+        for partial in fc.partial_args.iter().flatten() {
+            apply_partial_arg(self.args.get_or_insert_with(|| Value::Object(Map::new())), partial);
+        }
+        self.will_continue = fc.will_continue.unwrap_or(false);
+        self.fragments += 1;
+    }
+
+    /// 拼接完成的调用与其签名
+    pub fn finish(self) -> (FunctionCall, Option<String>) {
+        if self.fragments > 1 {
+            tracing::debug!(
+                "[Claude-SSE] Reassembled function call {} from {} fragments",
+                self.name,
+                self.fragments
+            );
+        }
+        let call = FunctionCall {
+            name: self.name,
+            id: self.id,
+            args: self.args,
+            partial_args: None,
+            will_continue: None,
+        };
+        (call, self.signature)
+    }
+}
+
+/// 合并对象形式的参数分片: 字符串续接, 对象递归合并, 其他值覆盖
+fn merge_args(target: &mut Value, fragment: &Value) {
+    match (target, fragment) {
+        (Value::Object(target), Value::Object(fragment)) => {
+            for (key, value) in fragment {
+                match target.get_mut(key) {
+                    Some(existing) => merge_args(existing, value),
+                    None => {
+                        target.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (Value::String(target), Value::String(fragment)) => target.push_str(fragment),
+        (target, fragment) => *target = fragment.clone(),
+    }
+}
+
+/// 按 jsonPath ("$.a.b") 写入参数分片, 字符串值续接到已有内容之后
+fn apply_partial_arg(args: &mut Value, partial: &PartialArg) {
+    let path = partial.json_path.trim_start_matches('$').trim_start_matches('.');
+    let mut target = args;
+    for key in path.split('.').filter(|k| !k.is_empty()) {
+        if !target.is_object() {
+            *target = Value::Object(Map::new());
+        }
+        let Value::Object(obj) = target else {
+            return;
+        };
+        target = obj.entry(key.to_string()).or_insert(Value::Null);
+    }
+
+    if let Some(text) = &partial.string_value {
+        match target {
+            Value::String(existing) => existing.push_str(text),
+            _ => *target = Value::String(text.clone()),
+        }
+    } else if let Some(number) = partial.number_value {
+        *target = serde_json::Number::from_f64(number)
+            .map(Value::Number)
+            .unwrap_or(Value::Null);
+    } else if let Some(flag) = partial.bool_value {
+        *target = Value::Bool(flag);
+    } else if partial.null_value.is_some() {
+        *target = Value::Null;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn call(value: Value) -> FunctionCall {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_object_fragments_are_concatenated() {
+        let mut pending = PendingFunctionCall::new(
+            &call(json!({ "name": "Write", "args": { "file_path": "/a.rs", "content": "fn main() {" } })),
+            Some("sig".to_string()),
+        );
+        let next = call(json!({ "args": { "content": "\n}\n" } }));
+        assert!(pending.accepts(&next));
+        pending.append(&next, None);
+
+        // 同名但未声明 willContinue 的调用是新的并行调用
+        assert!(!pending.accepts(&call(json!({ "name": "Write", "args": {} }))));
+
+        let (fc, signature) = pending.finish();
+        assert_eq!(fc.name, "Write");
+        assert_eq!(fc.args.unwrap(), json!({ "file_path": "/a.rs", "content": "fn main() {\n}\n" }));
+        assert_eq!(signature.as_deref(), Some("sig"));
+    }
+
+    #[test]
+    fn test_partial_args_follow_json_path() {
+        let mut pending = PendingFunctionCall::new(
+            &call(json!({
+                "name": "Edit",
+                "partialArgs": [
+                    { "jsonPath": "$.file_path", "stringValue": "/a.rs" },
+                    { "jsonPath": "$.edit.new_string", "stringValue": "let x" }
+                ],
+                "willContinue": true
+            })),
+            None,
+        );
+        let next = call(json!({
+            "name": "Edit",
+            "partialArgs": [
+                { "jsonPath": "$.edit.new_string", "stringValue": " = 1;" },
+                { "jsonPath": "$.replace_all", "boolValue": false },
+                { "jsonPath": "$.count", "numberValue": 2 }
+            ]
+        }));
+        assert!(pending.accepts(&next));
+        pending.append(&next, None);
+        assert!(!pending.will_continue);
+
+        let (fc, _) = pending.finish();
+        assert_eq!(
+            fc.args.unwrap(),
+            json!({
+                "file_path": "/a.rs",
+                "edit": { "new_string": "let x = 1;" },
+                "replace_all": false,
+                "count": 2.0
+            })
+        );
+    }
+}
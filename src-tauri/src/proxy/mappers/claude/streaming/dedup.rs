@@ -0,0 +1,152 @@
+//! Duplicate streamed text guard.
+//!
+//! 上游内部重试后偶尔会重发已经下发过的内容分片, 直接转发会让回复以同一句话开头两次。
+//! 这里保留最近下发文本的尾部; 新分片的前缀与尾部精确重合且不少于 MIN_OVERLAP_CHARS 个字符时,
+//! 裁掉重合部分再下发。只做精确匹配, 正常的重复表达 (短语、不完全相同的句子) 不受影响。
+//! 默认开启, 可通过 experimental.dedup_resent_chunks 关闭。
+
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// 判定为重发的最小重合长度 (字符)
+const MIN_OVERLAP_CHARS: usize = 40;
+/// 保留的已下发文本尾部长度 (字节)
+const TAIL_BYTES: usize = 2048;
+
+static TRIMMED_CHUNKS: AtomicU64 = AtomicU64::new(0);
+static TRIMMED_CHARS: AtomicU64 = AtomicU64::new(0);
+
+/// 重复分片裁剪统计 (启动以来)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DuplicateTextStats {
+    pub trimmed_chunks: u64,
+    pub trimmed_chars: u64,
+}
+
+pub fn stats() -> DuplicateTextStats {
+    DuplicateTextStats {
+        trimmed_chunks: TRIMMED_CHUNKS.load(Ordering::Relaxed),
+        trimmed_chars: TRIMMED_CHARS.load(Ordering::Relaxed),
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct TextDedup {
+    enabled: bool,
+    /// 最近下发文本的尾部
+    tail: String,
+}
+
+impl TextDedup {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            ..Default::default()
+        }
+    }
+
+    /// 去掉与已下发尾部重合的前缀, 并把实际下发的部分计入尾部
+    pub fn trim<'a>(&mut self, text: &'a str) -> &'a str {
+        if !self.enabled || text.is_empty() {
+            return text;
+        }
+        let overlap = self.overlap(text);
+        let emitted = &text[overlap..];
+        if overlap > 0 {
+            let chars = text[..overlap].chars().count();
+            TRIMMED_CHUNKS.fetch_add(1, Ordering::Relaxed);
+            TRIMMED_CHARS.fetch_add(chars as u64, Ordering::Relaxed);
+            tracing::warn!(
+                "[Claude-SSE] Trimmed {} duplicated chars resent by upstream ({} chars left in chunk)",
+                chars,
+                emitted.chars().count()
+            );
+        }
+        self.remember(emitted);
+        emitted
+    }
+
+    /// 最长的 "text 前缀 == tail 后缀" 重合 (字节), 不足 MIN_OVERLAP_CHARS 时为 0
+    fn overlap(&self, text: &str) -> usize {
+        let max = text.len().min(self.tail.len());
+        for len in (1..=max).rev() {
+            let tail_start = self.tail.len() - len;
+            if !text.is_char_boundary(len) || !self.tail.is_char_boundary(tail_start) {
+                continue;
+            }
+            if text.as_bytes()[..len] == self.tail.as_bytes()[tail_start..] {
+                return if text[..len].chars().count() >= MIN_OVERLAP_CHARS { len } else { 0 };
+            }
+        }
+        0
+    }
+
+    fn remember(&mut self, emitted: &str) {
+        self.tail.push_str(emitted);
+        if self.tail.len() > TAIL_BYTES {
+            let mut cut = self.tail.len() - TAIL_BYTES;
+            while !self.tail.is_char_boundary(cut) {
+                cut += 1;
+            }
+            self.tail.drain(..cut);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIRST: &str = "The failing test comes from the retry loop in handler.rs, which";
+
+    #[test]
+    fn test_exact_resend_is_trimmed() {
+        let mut dedup = TextDedup::new(true);
+        assert_eq!(dedup.trim(FIRST), FIRST);
+        assert_eq!(dedup.trim(FIRST), "");
+
+        // 重发分片带出了新内容: 只保留新增部分
+        let resent = format!("{} resets the counter.", FIRST);
+        assert_eq!(dedup.trim(&resent), " resets the counter.");
+
+        // 重合跨越分片边界 (与尾部的后半段重合) 同样裁剪
+        let shifted = format!("{} resets the counter. Fix it.", &FIRST[10..]);
+        assert_eq!(dedup.trim(&shifted), " Fix it.");
+    }
+
+    #[test]
+    fn test_legitimate_repetition_is_kept() {
+        // 短重复 (< 40 字符) 不处理
+        let mut dedup = TextDedup::new(true);
+        assert_eq!(dedup.trim("Yes. "), "Yes. ");
+        assert_eq!(dedup.trim("Yes. "), "Yes. ");
+
+        // 长但不精确重合的句子不处理
+        let mut dedup = TextDedup::new(true);
+        let line = "- [ ] update the changelog for the release notes\n";
+        dedup.trim(line);
+        let similar = "- [ ] update the changelog for the release notes!\n";
+        assert_eq!(dedup.trim(similar), similar);
+
+        // 与更早的内容重复, 但不与尾部相接的句子不处理
+        let mut dedup = TextDedup::new(true);
+        dedup.trim(FIRST);
+        dedup.trim(" resets the counter.");
+        assert_eq!(dedup.trim(FIRST), FIRST);
+    }
+
+    #[test]
+    fn test_multibyte_overlap_is_trimmed_on_char_boundary() {
+        let mut dedup = TextDedup::new(true);
+        let cjk = "这是一个用于验证多字节字符边界处理的较长句子，长度需要超过四十个字符才会触发裁剪逻辑。";
+        dedup.trim(cjk);
+        assert_eq!(dedup.trim(cjk), "");
+    }
+
+    #[test]
+    fn test_disabled_passes_text_through() {
+        let mut dedup = TextDedup::new(false);
+        assert_eq!(dedup.trim(FIRST), FIRST);
+        assert_eq!(dedup.trim(FIRST), FIRST);
+    }
+}
@@ -9,7 +9,11 @@
 //! - `state` - StreamingState state machine and BlockType enum
 //! - `processor` - PartProcessor for handling individual parts
 //! - `remapper` - Function call argument remapping for Gemini → Claude
+//! - `dedup` - Guard against text chunks resent by upstream retries
+//! - `call_buffer` - Reassembly of functionCall arguments split across chunks
 
+mod call_buffer;
+mod dedup;
 mod processor;
 mod remapper;
 mod state;
@@ -20,3 +24,4 @@ mod tests;
 // Re-export public API
 pub use processor::PartProcessor;
 pub use state::{BlockType, StreamingState};
+pub use dedup::{stats as duplicate_text_stats, DuplicateTextStats};
@@ -6,11 +6,15 @@
 use bytes::Bytes;
 use serde_json::{json, Value};
 
+use super::call_buffer::PendingFunctionCall;
 use super::remapper::remap_function_call_args;
 use super::state::{BlockType, StreamingState};
 use crate::proxy::mappers::claude::models::*;
 use crate::proxy::SignatureCache;
 
+/// 单个 input_json_delta 事件携带的最大参数长度 (字节), 超大参数拆成多个事件下发
+const INPUT_JSON_DELTA_CHUNK_BYTES: usize = 16 * 1024;
+
 /// Part processor for handling Gemini response parts.
 pub struct PartProcessor<'a> {
     state: &'a mut StreamingState,
@@ -25,6 +29,11 @@ impl<'a> PartProcessor<'a> {
     pub fn process(&mut self, part: &GeminiPart) -> Vec<Bytes> {
         let mut chunks = Vec::new();
 
+        // 已命中客户端停止序列: 丢弃之后的所有内容
+        if self.state.stopped_on_sequence() {
+            return chunks;
+        }
+
         // Decode Base64 signature if present (Gemini sends Base64, Claude expects Raw)
         let signature = part.thought_signature.as_ref().map(|sig| {
             use base64::Engine;
@@ -54,14 +63,16 @@ impl<'a> PartProcessor<'a> {
                 }
             }
 
-            chunks.extend(self.process_function_call(fc, signature));
+            chunks.extend(self.buffer_function_call(fc, signature));
             self.state.has_content = true;
             return chunks;
         }
 
         // 2. Text processing
         if let Some(text) = &part.text {
-            if part.thought.unwrap_or(false) {
+            if part.thought.unwrap_or(false) && self.state.strip_thinking {
+                self.strip_thought();
+            } else if part.thought.unwrap_or(false) {
                 chunks.extend(self.process_thinking(text, signature));
             } else {
                 chunks.extend(self.process_text(text, signature));
@@ -81,6 +92,47 @@ impl<'a> PartProcessor<'a> {
         chunks
     }
 
+    /// Buffer a functionCall fragment; the previous call is emitted once a new call starts.
+    ///
+    /// Text parts arriving between fragments do not complete the call.
+    fn buffer_function_call(&mut self, fc: &FunctionCall, signature: Option<String>) -> Vec<Bytes> {
+        if let Some(pending) = self.state.pending_function_call.as_mut() {
+            if pending.accepts(fc) {
+                pending.append(fc, signature);
+                return Vec::new();
+            }
+        }
+
+        let chunks = self.flush_function_call();
+        if fc.name.is_empty() {
+            tracing::warn!("[Claude-SSE] Dropping functionCall fragment without a preceding call");
+            return chunks;
+        }
+        self.state.pending_function_call = Some(PendingFunctionCall::new(fc, signature));
+        chunks
+    }
+
+    /// Emit the buffered function call, if any (next call started, finishReason or stream end).
+    pub fn flush_function_call(&mut self) -> Vec<Bytes> {
+        match self.state.pending_function_call.take() {
+            Some(pending) => {
+                let (fc, signature) = pending.finish();
+                self.process_function_call(&fc, signature)
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// Drop a thought part the client never asked for (upstream ignored includeThoughts: false).
+    fn strip_thought(&mut self) {
+        // 仍计入 has_thinking, 只有 thought 没有正文时照常触发中断恢复
+        self.state.has_thinking = true;
+        if !self.state.thoughts_stripped {
+            self.state.thoughts_stripped = true;
+            tracing::warn!("[Claude-SSE] Upstream returned thought parts although thinking was not requested, stripping");
+        }
+    }
+
     /// Emit a trailing signature block.
     fn emit_trailing_signature_block(&mut self, signature: &str) -> Vec<Bytes> {
         let mut chunks = Vec::new();
@@ -169,10 +221,11 @@ impl<'a> PartProcessor<'a> {
     /// Process regular text content.
     fn process_text(&mut self, text: &str, signature: Option<String>) -> Vec<Bytes> {
         let mut chunks = Vec::new();
+        let text = self.state.dedup_text(text);
 
-        // Empty text with signature - store for later
+        // Empty text with signature - store for later (no thinking block when thinking is stripped)
         if text.is_empty() {
-            if signature.is_some() {
+            if signature.is_some() && !self.state.strip_thinking {
                 self.state.set_trailing_signature(signature);
             }
             return chunks;
@@ -196,7 +249,7 @@ impl<'a> PartProcessor<'a> {
                 self.state
                     .start_block(BlockType::Text, json!({ "type": "text", "text": "" })),
             );
-            chunks.push(self.state.emit_delta("text_delta", json!({ "text": text })));
+            chunks.extend(self.state.emit_text_delta(text));
             chunks.extend(self.state.end_block());
 
             return chunks;
@@ -214,7 +267,7 @@ impl<'a> PartProcessor<'a> {
             );
         }
 
-        chunks.push(self.state.emit_delta("text_delta", json!({ "text": text })));
+        chunks.extend(self.state.emit_text_delta(text));
 
         chunks
     }
@@ -247,6 +300,8 @@ impl<'a> PartProcessor<'a> {
                             name: tool_name.to_string(),
                             args: Some(input_json),
                             id: Some(format!("{}-xml", tool_name)),
+                            partial_args: None,
+                            will_continue: None,
                         };
 
                         let tool_chunks = self.process_function_call(&fc, None);
@@ -264,10 +319,7 @@ impl<'a> PartProcessor<'a> {
                                     json!({ "type": "text", "text": "" }),
                                 ));
                             }
-                            chunks.push(
-                                self.state
-                                    .emit_delta("text_delta", json!({ "text": prefix_text })),
-                            );
+                            chunks.extend(self.state.emit_text_delta(prefix_text));
                         }
 
                         chunks.extend(tool_chunks);
@@ -343,7 +395,7 @@ impl<'a> PartProcessor<'a> {
 
         chunks.extend(self.state.start_block(BlockType::Function, tool_use));
 
-        // Send input_json_delta with full JSON args
+        // Send input_json_delta with full JSON args (split into bounded chunks)
         if let Some(args) = &fc.args {
             let mut remapped_args = args.clone();
 
@@ -355,10 +407,12 @@ impl<'a> PartProcessor<'a> {
 
             let json_str =
                 serde_json::to_string(&remapped_args).unwrap_or_else(|_| "{}".to_string());
-            chunks.push(
-                self.state
-                    .emit_delta("input_json_delta", json!({ "partial_json": json_str })),
-            );
+            for piece in split_at_char_boundaries(&json_str, INPUT_JSON_DELTA_CHUNK_BYTES) {
+                chunks.push(
+                    self.state
+                        .emit_delta("input_json_delta", json!({ "partial_json": piece })),
+                );
+            }
         }
 
         // End block
@@ -367,3 +421,20 @@ impl<'a> PartProcessor<'a> {
         chunks
     }
 }
+
+/// 按字节上限切分字符串, 不拆开多字节字符
+fn split_at_char_boundaries(text: &str, max_bytes: usize) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut rest = text;
+    while rest.len() > max_bytes {
+        let mut cut = max_bytes;
+        while !rest.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        let (piece, tail) = rest.split_at(cut);
+        pieces.push(piece);
+        rest = tail;
+    }
+    pieces.push(rest);
+    pieces
+}
@@ -6,9 +6,13 @@
 use bytes::Bytes;
 use serde_json::{json, Value};
 
+use super::call_buffer::PendingFunctionCall;
+use super::dedup::TextDedup;
+use crate::proxy::common::post_process::{StreamTextFilter, TextPostProcessor};
 use crate::proxy::mappers::claude::models::*;
+use crate::proxy::mappers::claude::stop_sequences::StopSequenceMatcher;
 use crate::proxy::mappers::claude::utils::to_claude_usage;
-use crate::proxy::mappers::estimation_calibrator::get_calibrator;
+use crate::proxy::mappers::estimation_calibrator::{get_calibrator, PromptEstimate};
 
 /// Block type enumeration.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -68,15 +72,36 @@ pub struct StreamingState {
     pub scaling_enabled: bool,
     // Context limit for smart threshold recovery (default to 1M)
     pub context_limit: u32,
+    // Injected system prompt tokens hidden from the reported usage
+    pub hidden_input_tokens: u32,
     // MCP XML Bridge buffer
     pub mcp_xml_buffer: String,
     pub in_mcp_xml: bool,
-    // Estimated prompt tokens for calibrator learning
-    pub estimated_prompt_tokens: Option<u32>,
+    // Labeled prompt estimate for calibrator learning
+    pub estimated_prompt: Option<PromptEstimate>,
     // Post-thinking interruption tracking
     pub has_thinking: bool,
     pub has_content: bool,
     pub message_count: usize,
+    // Assistant text post-processing (None = disabled)
+    pub post_processor: Option<std::sync::Arc<TextPostProcessor>>,
+    /// 回显在 usage.service_tier 中的生效等级
+    pub service_tier: Option<String>,
+    text_filter: Option<StreamTextFilter>,
+    /// 本流已对多余候选告警过 (candidateCount > 1)
+    pub extra_candidates_warned: bool,
+    /// 上游在流中途报告上下文超限, 已下发 error 事件并终止
+    pub context_exceeded: bool,
+    /// 上游重发分片的去重
+    text_dedup: TextDedup,
+    /// 参数尚未接收完整的函数调用
+    pub(super) pending_function_call: Option<PendingFunctionCall>,
+    /// 未请求 thinking: 丢弃上游仍返回的 thought parts
+    pub strip_thinking: bool,
+    /// 本流已丢弃过 thought parts (只告警一次)
+    pub(super) thoughts_stripped: bool,
+    /// 客户端 stop_sequences 的本地匹配
+    stop_matcher: StopSequenceMatcher,
 }
 
 impl StreamingState {
@@ -97,15 +122,46 @@ impl StreamingState {
             session_id: None,
             scaling_enabled: false,
             context_limit: 1_048_576, // Default to 1M
+            hidden_input_tokens: 0,
             mcp_xml_buffer: String::new(),
             in_mcp_xml: false,
-            estimated_prompt_tokens: None,
+            estimated_prompt: None,
             has_thinking: false,
             has_content: false,
             message_count: 0,
+            post_processor: None,
+            service_tier: None,
+            text_filter: None,
+            extra_candidates_warned: false,
+            context_exceeded: false,
+            text_dedup: TextDedup::new(true),
+            pending_function_call: None,
+            strip_thinking: false,
+            thoughts_stripped: false,
+            stop_matcher: StopSequenceMatcher::default(),
         }
     }
 
+    /// 设置客户端 stop_sequences
+    pub fn set_stop_sequences(&mut self, sequences: Vec<String>) {
+        self.stop_matcher = StopSequenceMatcher::new(sequences);
+    }
+
+    /// 输出文本已命中停止序列, 之后的内容全部丢弃
+    pub fn stopped_on_sequence(&self) -> bool {
+        self.stop_matcher.matched().is_some()
+    }
+
+    /// 上游重发分片的去重开关 (experimental.dedup_resent_chunks, 默认开启)
+    pub fn set_dedup_resent_chunks(&mut self, enabled: bool) {
+        self.text_dedup = TextDedup::new(enabled);
+    }
+
+    /// 丢弃上游从头重放的重复分片
+    pub fn dedup_text<'a>(&mut self, text: &'a str) -> &'a str {
+        self.text_dedup.trim(text)
+    }
+
     /// Emit SSE event.
     pub fn emit(&self, event_type: &str, data: Value) -> Bytes {
         let sse = format!(
@@ -125,7 +181,10 @@ impl StreamingState {
         let usage = raw_json
             .get("usageMetadata")
             .and_then(|u| serde_json::from_value::<UsageMetadata>(u.clone()).ok())
-            .map(|u| to_claude_usage(&u, self.scaling_enabled, self.context_limit));
+            .map(|u| Usage {
+                service_tier: self.service_tier.clone(),
+                ..to_claude_usage(&u, self.scaling_enabled, self.context_limit, self.hidden_input_tokens)
+            });
 
         let mut message = json!({
             "id": raw_json.get("responseId")
@@ -197,6 +256,21 @@ impl StreamingState {
             }
         }
 
+        // Flush text held back by stop sequence matching and post-processing before the block closes
+        if self.block_type == BlockType::Text {
+            let held = self.stop_matcher.finish();
+            let mut tail = match self.text_filter.as_mut() {
+                Some(filter) => filter.push(&held),
+                None => held,
+            };
+            if let Some(mut filter) = self.text_filter.take() {
+                tail.push_str(&filter.finish());
+            }
+            if !tail.is_empty() {
+                chunks.push(self.emit_delta("text_delta", json!({ "text": tail })));
+            }
+        }
+
         chunks.push(self.emit(
             "content_block_stop",
             json!({
@@ -230,6 +304,27 @@ impl StreamingState {
         )
     }
 
+    /// Emit a text_delta, passing the text through stop sequence matching and post-processing when enabled.
+    /// Returns None while the text is being held back for a possible cross-chunk match.
+    pub fn emit_text_delta(&mut self, text: &str) -> Option<Bytes> {
+        let text = if self.stop_matcher.is_active() {
+            self.stop_matcher.push(text)
+        } else {
+            text.to_string()
+        };
+        let text = match &self.post_processor {
+            Some(processor) => self
+                .text_filter
+                .get_or_insert_with(|| StreamTextFilter::new(processor.clone()))
+                .push(&text),
+            None => text,
+        };
+        if text.is_empty() {
+            return None;
+        }
+        Some(self.emit_delta("text_delta", json!({ "text": text })))
+    }
+
     /// Emit finish events.
     pub fn emit_finish(
         &mut self,
@@ -271,31 +366,39 @@ impl StreamingState {
         }
 
         // Determine stop_reason
+        let stop_sequence = self
+            .stop_matcher
+            .resolve(finish_reason)
+            .filter(|_| !self.used_tool)
+            .map(str::to_string);
         let stop_reason = if self.used_tool {
             "tool_use"
+        } else if stop_sequence.is_some() {
+            "stop_sequence"
         } else if finish_reason == Some("MAX_TOKENS") {
             "max_tokens"
         } else {
             "end_turn"
         };
 
-        let usage = usage_metadata
+        let mut usage = usage_metadata
             .map(|u| {
                 // Record actual token usage for calibrator learning
-                if let (Some(estimated), Some(actual)) =
-                    (self.estimated_prompt_tokens, u.prompt_token_count)
+                if let (Some(estimate), Some(actual)) =
+                    (self.estimated_prompt, u.prompt_token_count)
                 {
-                    if estimated > 0 && actual > 0 {
-                        get_calibrator().record(estimated, actual);
+                    if estimate.raw > 0 && actual > 0 {
+                        get_calibrator().record_sample(&estimate.sample(actual));
                         tracing::debug!(
-                            "[Calibrator] Recorded: estimated={}, actual={}, ratio={:.2}x",
-                            estimated,
+                            "[Calibrator] Recorded: estimated={}, actual={}, ratio={:.2}x, bucket={:?}",
+                            estimate.raw,
                             actual,
-                            actual as f64 / estimated as f64
+                            actual as f64 / estimate.raw as f64,
+                            estimate.bucket
                         );
                     }
                 }
-                to_claude_usage(u, self.scaling_enabled, self.context_limit)
+                to_claude_usage(u, self.scaling_enabled, self.context_limit, self.hidden_input_tokens)
             })
             .unwrap_or(Usage {
                 input_tokens: 0,
@@ -303,13 +406,15 @@ impl StreamingState {
                 cache_read_input_tokens: None,
                 cache_creation_input_tokens: None,
                 server_tool_use: None,
+                service_tier: None,
             });
+        usage.service_tier = self.service_tier.clone();
 
         chunks.push(self.emit(
             "message_delta",
             json!({
                 "type": "message_delta",
-                "delta": { "stop_reason": stop_reason, "stop_sequence": null },
+                "delta": { "stop_reason": stop_reason, "stop_sequence": stop_sequence },
                 "usage": usage
             }),
         ));
@@ -37,6 +37,8 @@ fn test_process_function_call_deltas() {
         name: "test_tool".to_string(),
         args: Some(json!({"arg": "value"})),
         id: Some("call_123".to_string()),
+        partial_args: None,
+        will_continue: None,
     };
 
     // Create a dummy GeminiPart with function_call
@@ -49,7 +51,9 @@ fn test_process_function_call_deltas() {
         function_response: None,
     };
 
-    let chunks = processor.process(&part);
+    // 调用先进入缓冲, 完整后 (finishReason / 下一个调用) 才下发
+    assert!(processor.process(&part).is_empty());
+    let chunks = processor.flush_function_call();
     let output = chunks
         .iter()
         .map(|b| String::from_utf8(b.to_vec()).unwrap())
@@ -71,3 +75,36 @@ fn test_process_function_call_deltas() {
     // 3. content_block_stop
     assert!(output.contains(r#""type":"content_block_stop""#));
 }
+
+#[test]
+fn test_large_function_call_args_are_chunked() {
+    let mut state = StreamingState::new();
+    let mut processor = PartProcessor::new(&mut state);
+
+    let content = "第一行内容\n".repeat(5_000);
+    let part: GeminiPart = serde_json::from_value(json!({
+        "functionCall": { "name": "Write", "args": { "file_path": "/a.txt", "content": content } }
+    }))
+    .unwrap();
+
+    processor.process(&part);
+    let events: Vec<String> = processor
+        .flush_function_call()
+        .iter()
+        .map(|b| String::from_utf8(b.to_vec()).unwrap())
+        .collect();
+
+    let deltas: Vec<String> = events
+        .iter()
+        .filter(|e| e.contains(r#""type":"input_json_delta""#))
+        .map(|e| {
+            let data = e.lines().find(|l| l.starts_with("data: ")).unwrap();
+            let event: serde_json::Value = serde_json::from_str(&data[6..]).unwrap();
+            event["delta"]["partial_json"].as_str().unwrap().to_string()
+        })
+        .collect();
+    assert!(deltas.len() > 1);
+
+    let args: serde_json::Value = serde_json::from_str(&deltas.concat()).unwrap();
+    assert_eq!(args["content"], content);
+}
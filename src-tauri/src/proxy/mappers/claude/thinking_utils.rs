@@ -1,9 +1,41 @@
 use super::models::{ContentBlock, Message, MessageContent};
+use crate::proxy::config::RedactedThinkingMode;
 use crate::proxy::SignatureCache;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
 use tracing::{debug, info, warn};
 
 pub const MIN_SIGNATURE_LENGTH: usize = 50;
 
+/// RedactedThinkingMode::Marker 使用的占位文本
+pub const REDACTED_THINKING_MARKER: &str = "[redacted thinking omitted]";
+
+/// 按策略处理一条消息中的 redacted_thinking 块, 返回被丢弃或替换的块数
+pub fn apply_redacted_thinking_mode(blocks: &mut Vec<ContentBlock>, mode: RedactedThinkingMode) -> usize {
+    let mut changed = 0;
+    match mode {
+        RedactedThinkingMode::Preserve => {}
+        RedactedThinkingMode::Drop => {
+            blocks.retain(|block| {
+                let redacted = matches!(block, ContentBlock::RedactedThinking { .. });
+                changed += redacted as usize;
+                !redacted
+            });
+        }
+        RedactedThinkingMode::Marker => {
+            for block in blocks.iter_mut() {
+                if matches!(block, ContentBlock::RedactedThinking { .. }) {
+                    *block = ContentBlock::Text {
+                        text: REDACTED_THINKING_MARKER.to_string(),
+                    };
+                    changed += 1;
+                }
+            }
+        }
+    }
+    changed
+}
+
 #[derive(Debug, Default)]
 pub struct ConversationState {
     pub in_tool_loop: bool,
@@ -91,11 +123,37 @@ pub fn analyze_conversation_state(messages: &[Message]) -> ConversationState {
     state
 }
 
-/// Recover from broken tool loops or interrupted tool calls by injecting synthetic messages
-pub fn close_tool_loop_for_thinking(messages: &mut Vec<Message>) {
+/// Default content of the synthesized tool_result that answers a dangling tool_use
+pub const DEFAULT_TOOL_RESULT_PLACEHOLDER: &str = "[result unavailable - continue without it]";
+
+/// Number of requests repaired by tool loop recovery since startup
+static TOOL_LOOP_RECOVERIES: AtomicU64 = AtomicU64::new(0);
+
+/// Get the number of tool loop recoveries performed since startup
+pub fn tool_loop_recovery_count() -> u64 {
+    TOOL_LOOP_RECOVERIES.load(Ordering::Relaxed)
+}
+
+/// Recover from broken tool loops or interrupted tool calls.
+///
+/// Dangling tool_use blocks are answered with a synthesized `tool_result`
+/// (`is_error: true`, content = `placeholder`) instead of rewriting the assistant
+/// turn, so the model's intent and its thinking signature are preserved verbatim.
+pub fn close_tool_loop_for_thinking(messages: &mut Vec<Message>, placeholder: &str) {
     let state = analyze_conversation_state(messages);
 
-    if !state.in_tool_loop && !state.interrupted_tool {
+    // 1. Answer every tool_use that has no matching tool_result in the following user turn
+    let synthesized = synthesize_missing_tool_results(messages, placeholder);
+    if synthesized > 0 {
+        TOOL_LOOP_RECOVERIES.fetch_add(1, Ordering::Relaxed);
+        info!(
+            "[Thinking-Recovery] Synthesized {} tool_result(s) for dangling tool calls.",
+            synthesized
+        );
+    }
+
+    // 2. Active tool loop whose assistant turn lost its thinking block
+    if !state.in_tool_loop {
         return;
     }
 
@@ -127,44 +185,196 @@ pub fn close_tool_loop_for_thinking(messages: &mut Vec<Message>) {
     }
 
     if !has_valid_thinking {
-        if state.in_tool_loop {
-            info!("[Thinking-Recovery] Broken tool loop (ToolResult without preceding Thinking). Recovery triggered.");
+        info!("[Thinking-Recovery] Broken tool loop (ToolResult without preceding Thinking). Recovery triggered.");
+        if synthesized == 0 {
+            TOOL_LOOP_RECOVERIES.fetch_add(1, Ordering::Relaxed);
+        }
 
-            // Insert acknowledging message to "close" the history turn
-            messages.push(Message {
-                role: "assistant".to_string(),
-                content: MessageContent::Array(vec![ContentBlock::Text {
-                    text: "[System: Tool execution completed. Proceeding to final response.]"
-                        .to_string(),
-                }]),
-            });
-            messages.push(Message {
-                role: "user".to_string(),
-                content: MessageContent::Array(vec![ContentBlock::Text {
-                    text: "Please provide the final result based on the tool output above."
-                        .to_string(),
-                }]),
-            });
-        } else if state.interrupted_tool {
-            info!(
-                "[Thinking-Recovery] Interrupted tool call detected. Injecting synthetic closure."
-            );
-
-            // For interrupted tool, we need to insert the closure AFTER the assistant's tool use
-            // but BEFORE the user's latest message.
-            if let Some(idx) = state.last_assistant_idx {
+        // Insert acknowledging message to "close" the history turn
+        messages.push(Message {
+            role: "assistant".to_string(),
+            content: MessageContent::Array(vec![ContentBlock::Text {
+                text: "[System: Tool execution completed. Proceeding to final response.]"
+                    .to_string(),
+            }]),
+        });
+        messages.push(Message {
+            role: "user".to_string(),
+            content: MessageContent::Array(vec![ContentBlock::Text {
+                text: "Please provide the final result based on the tool output above."
+                    .to_string(),
+            }]),
+        });
+    }
+}
+
+/// Insert a synthetic `tool_result` for each tool_use that was never answered.
+///
+/// The results are placed at the head of the following user turn (after any real
+/// tool_result blocks), or in a new user turn when the assistant message is the last
+/// one or is followed by another assistant message. Returns the number of results added.
+fn synthesize_missing_tool_results(messages: &mut Vec<Message>, placeholder: &str) -> usize {
+    let mut synthesized = 0;
+    let mut i = 0;
+
+    while i < messages.len() {
+        if messages[i].role != "assistant" {
+            i += 1;
+            continue;
+        }
+
+        let tool_use_ids: Vec<String> = match &messages[i].content {
+            MessageContent::Array(blocks) => blocks
+                .iter()
+                .filter_map(|b| match b {
+                    ContentBlock::ToolUse { id, .. } => Some(id.clone()),
+                    _ => None,
+                })
+                .collect(),
+            MessageContent::String(_) => Vec::new(),
+        };
+
+        if tool_use_ids.is_empty() {
+            i += 1;
+            continue;
+        }
+
+        let answered: HashSet<String> = match messages.get(i + 1) {
+            Some(next) if next.role == "user" => match &next.content {
+                MessageContent::Array(blocks) => blocks
+                    .iter()
+                    .filter_map(|b| match b {
+                        ContentBlock::ToolResult { tool_use_id, .. } => Some(tool_use_id.clone()),
+                        _ => None,
+                    })
+                    .collect(),
+                MessageContent::String(_) => HashSet::new(),
+            },
+            _ => HashSet::new(),
+        };
+
+        let missing: Vec<ContentBlock> = tool_use_ids
+            .into_iter()
+            .filter(|id| !answered.contains(id))
+            .map(|id| {
+                debug!("[Thinking-Recovery] Synthesizing tool_result for dangling tool_use {}", id);
+                ContentBlock::ToolResult {
+                    tool_use_id: id,
+                    content: serde_json::Value::String(placeholder.to_string()),
+                    is_error: Some(true),
+                }
+            })
+            .collect();
+
+        if missing.is_empty() {
+            i += 1;
+            continue;
+        }
+        synthesized += missing.len();
+
+        match messages.get_mut(i + 1) {
+            Some(next) if next.role == "user" => {
+                let existing = match std::mem::replace(&mut next.content, MessageContent::Array(Vec::new())) {
+                    MessageContent::String(s) if s.trim().is_empty() => Vec::new(),
+                    MessageContent::String(s) => vec![ContentBlock::Text { text: s }],
+                    MessageContent::Array(blocks) => blocks,
+                };
+                // tool_result blocks must lead the user turn
+                let (mut blocks, rest): (Vec<_>, Vec<_>) = existing
+                    .into_iter()
+                    .partition(|b| matches!(b, ContentBlock::ToolResult { .. }));
+                blocks.extend(missing);
+                blocks.extend(rest);
+                next.content = MessageContent::Array(blocks);
+            }
+            _ => {
                 messages.insert(
-                    idx + 1,
+                    i + 1,
                     Message {
-                        role: "assistant".to_string(),
-                        content: MessageContent::Array(vec![ContentBlock::Text {
-                            text: "[Tool call was interrupted by user.]".to_string(),
-                        }]),
+                        role: "user".to_string(),
+                        content: MessageContent::Array(missing),
                     },
                 );
             }
         }
+
+        i += 2;
     }
+
+    synthesized
+}
+
+/// Text left in a follow-up user turn whose only blocks were orphaned tool_results
+const ORPHAN_TOOL_RESULT_PLACEHOLDER: &str = "[tool result removed - continue]";
+
+/// Check every tool_result in the follow-up user turn against the tool_use ids of the
+/// assistant turn right before it. Must run on the output of `merge_consecutive_messages`,
+/// i.e. on the messages that are actually sent upstream.
+///
+/// Orphans are either rejected with a client-facing error naming the unknown id and
+/// listing the valid ones, or dropped when `drop_orphans` is set. Returns the dropped ids.
+pub fn validate_follow_up_tool_results(
+    messages: &mut [Message],
+    drop_orphans: bool,
+) -> Result<Vec<String>, String> {
+    let Some(assistant_idx) = messages.iter().rposition(|m| m.role == "assistant") else {
+        return Ok(Vec::new());
+    };
+
+    let valid_ids: Vec<String> = match &messages[assistant_idx].content {
+        MessageContent::Array(blocks) => blocks
+            .iter()
+            .filter_map(|b| match b {
+                ContentBlock::ToolUse { id, .. } => Some(id.clone()),
+                _ => None,
+            })
+            .collect(),
+        MessageContent::String(_) => Vec::new(),
+    };
+
+    let Some(next) = messages
+        .get_mut(assistant_idx + 1)
+        .filter(|m| m.role == "user")
+    else {
+        return Ok(Vec::new());
+    };
+    let MessageContent::Array(blocks) = &mut next.content else {
+        return Ok(Vec::new());
+    };
+
+    let orphans: Vec<String> = blocks
+        .iter()
+        .filter_map(|b| match b {
+            ContentBlock::ToolResult { tool_use_id, .. } if !valid_ids.contains(tool_use_id) => {
+                Some(tool_use_id.clone())
+            }
+            _ => None,
+        })
+        .collect();
+    if orphans.is_empty() {
+        return Ok(orphans);
+    }
+
+    if !drop_orphans {
+        let valid = if valid_ids.is_empty() {
+            "(none)".to_string()
+        } else {
+            valid_ids.join(", ")
+        };
+        return Err(format!(
+            "tool_result references unknown tool_use_id `{}`; valid tool_use ids from the previous assistant turn: {}",
+            orphans.join("`, `"),
+            valid
+        ));
+    }
+
+    blocks.retain(|b| !matches!(b, ContentBlock::ToolResult { tool_use_id, .. } if orphans.contains(tool_use_id)));
+    if blocks.is_empty() {
+        blocks.push(ContentBlock::Text {
+            text: ORPHAN_TOOL_RESULT_PLACEHOLDER.to_string(),
+        });
+    }
+    Ok(orphans)
 }
 
 /// Get the model family origin of a signature
@@ -173,11 +383,15 @@ pub fn get_signature_family(signature: &str) -> Option<String> {
 }
 
 /// [CRITICAL] Sanitize thinking blocks and check cross-model compatibility
+///
+/// `redacted_mode` 为针对目标上游生效的 redacted_thinking 策略 (见 RedactedThinkingMode::for_target)。
 pub fn filter_invalid_thinking_blocks_with_family(
     messages: &mut [Message],
     target_family: Option<&str>,
+    redacted_mode: RedactedThinkingMode,
 ) {
     let mut stripped_count = 0;
+    let mut redacted_count = 0;
 
     for msg in messages.iter_mut() {
         if msg.role != "assistant" {
@@ -225,6 +439,7 @@ pub fn filter_invalid_thinking_blocks_with_family(
                 }
                 true
             });
+            redacted_count += apply_redacted_thinking_mode(blocks, redacted_mode);
 
             // SAFETY: Claude API requires at least one block
             if blocks.is_empty() && original_len > 0 {
@@ -241,4 +456,305 @@ pub fn filter_invalid_thinking_blocks_with_family(
             stripped_count
         );
     }
+    if redacted_count > 0 {
+        debug!(
+            "[Thinking-Sanitizer] Applied {:?} to {} redacted_thinking blocks",
+            redacted_mode, redacted_count
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn assistant_with_tool_use(id: &str, signature: &str) -> Message {
+        Message {
+            role: "assistant".to_string(),
+            content: MessageContent::Array(vec![
+                ContentBlock::Thinking {
+                    thinking: "Let me read the file".to_string(),
+                    signature: Some(signature.to_string()),
+                    cache_control: None,
+                },
+                ContentBlock::ToolUse {
+                    id: id.to_string(),
+                    name: "Read".to_string(),
+                    input: json!({"path": "/tmp/a.rs"}),
+                    signature: None,
+                    cache_control: None,
+                },
+            ]),
+        }
+    }
+
+    fn user_text(text: &str) -> Message {
+        Message {
+            role: "user".to_string(),
+            content: MessageContent::String(text.to_string()),
+        }
+    }
+
+    fn tool_result_ids(msg: &Message) -> Vec<(String, Option<bool>)> {
+        match &msg.content {
+            MessageContent::Array(blocks) => blocks
+                .iter()
+                .filter_map(|b| match b {
+                    ContentBlock::ToolResult { tool_use_id, is_error, .. } => {
+                        Some((tool_use_id.clone(), *is_error))
+                    }
+                    _ => None,
+                })
+                .collect(),
+            MessageContent::String(_) => Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_dangling_tool_use_as_last_message() {
+        let sig = "s".repeat(MIN_SIGNATURE_LENGTH);
+        let mut messages = vec![user_text("read a.rs"), assistant_with_tool_use("toolu_1", &sig)];
+
+        close_tool_loop_for_thinking(&mut messages, DEFAULT_TOOL_RESULT_PLACEHOLDER);
+
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[2].role, "user");
+        assert_eq!(tool_result_ids(&messages[2]), vec![("toolu_1".to_string(), Some(true))]);
+        if let MessageContent::Array(blocks) = &messages[2].content {
+            if let ContentBlock::ToolResult { content, .. } = &blocks[0] {
+                assert_eq!(content.as_str(), Some(DEFAULT_TOOL_RESULT_PLACEHOLDER));
+            }
+        }
+
+        // Assistant turn (including its signature) is preserved verbatim
+        if let MessageContent::Array(blocks) = &messages[1].content {
+            assert_eq!(blocks.len(), 2);
+            assert!(matches!(&blocks[0], ContentBlock::Thinking { signature: Some(s), .. } if *s == sig));
+        } else {
+            panic!("assistant content should stay an array");
+        }
+    }
+
+    #[test]
+    fn test_dangling_tool_use_mid_conversation_after_trimming() {
+        // Layer-1 trimming dropped the tool_result turn, leaving the tool_use followed by plain user text
+        let sig = "s".repeat(MIN_SIGNATURE_LENGTH);
+        let mut messages = vec![
+            user_text("read a.rs"),
+            assistant_with_tool_use("toolu_old", &sig),
+            user_text("never mind, explain main.rs"),
+            Message {
+                role: "assistant".to_string(),
+                content: MessageContent::String("Sure.".to_string()),
+            },
+            user_text("thanks"),
+        ];
+
+        close_tool_loop_for_thinking(&mut messages, "custom placeholder");
+
+        assert_eq!(messages.len(), 5);
+        assert_eq!(tool_result_ids(&messages[2]), vec![("toolu_old".to_string(), Some(true))]);
+        if let MessageContent::Array(blocks) = &messages[2].content {
+            assert!(matches!(&blocks[0], ContentBlock::ToolResult { content, .. } if content.as_str() == Some("custom placeholder")));
+            assert!(matches!(&blocks[1], ContentBlock::Text { text } if text == "never mind, explain main.rs"));
+        } else {
+            panic!("user content should have been converted to an array");
+        }
+    }
+
+    #[test]
+    fn test_partially_answered_tool_uses() {
+        let sig = "s".repeat(MIN_SIGNATURE_LENGTH);
+        let mut assistant = assistant_with_tool_use("toolu_a", &sig);
+        if let MessageContent::Array(blocks) = &mut assistant.content {
+            blocks.push(ContentBlock::ToolUse {
+                id: "toolu_b".to_string(),
+                name: "Read".to_string(),
+                input: json!({}),
+                signature: None,
+                cache_control: None,
+            });
+        }
+        let mut messages = vec![
+            user_text("go"),
+            assistant,
+            Message {
+                role: "user".to_string(),
+                content: MessageContent::Array(vec![ContentBlock::ToolResult {
+                    tool_use_id: "toolu_a".to_string(),
+                    content: json!("ok"),
+                    is_error: None,
+                }]),
+            },
+        ];
+
+        close_tool_loop_for_thinking(&mut messages, DEFAULT_TOOL_RESULT_PLACEHOLDER);
+
+        assert_eq!(messages.len(), 3);
+        assert_eq!(
+            tool_result_ids(&messages[2]),
+            vec![("toolu_a".to_string(), None), ("toolu_b".to_string(), Some(true))]
+        );
+    }
+
+    #[test]
+    fn test_answered_tool_loop_untouched() {
+        let sig = "s".repeat(MIN_SIGNATURE_LENGTH);
+        let mut messages = vec![
+            user_text("go"),
+            assistant_with_tool_use("toolu_1", &sig),
+            Message {
+                role: "user".to_string(),
+                content: MessageContent::Array(vec![ContentBlock::ToolResult {
+                    tool_use_id: "toolu_1".to_string(),
+                    content: json!("ok"),
+                    is_error: None,
+                }]),
+            },
+        ];
+
+        close_tool_loop_for_thinking(&mut messages, DEFAULT_TOOL_RESULT_PLACEHOLDER);
+
+        assert_eq!(messages.len(), 3);
+        assert_eq!(tool_result_ids(&messages[2]), vec![("toolu_1".to_string(), None)]);
+    }
+
+    fn tool_result(id: &str) -> ContentBlock {
+        ContentBlock::ToolResult {
+            tool_use_id: id.to_string(),
+            content: json!("ok"),
+            is_error: None,
+        }
+    }
+
+    #[test]
+    fn test_orphan_tool_result_rejected_after_merge() {
+        let sig = "s".repeat(MIN_SIGNATURE_LENGTH);
+        // tool_result 与 system-reminder 分成两条 user 消息, 合并后再校验
+        let mut messages = vec![
+            user_text("go"),
+            assistant_with_tool_use("toolu_a", &sig),
+            Message {
+                role: "user".to_string(),
+                content: MessageContent::Array(vec![tool_result("toolu_stale")]),
+            },
+            user_text("<system-reminder>keep going</system-reminder>"),
+        ];
+        crate::proxy::mappers::claude::merge_consecutive_messages(&mut messages);
+        assert_eq!(messages.len(), 3);
+
+        let err = validate_follow_up_tool_results(&mut messages, false).unwrap_err();
+        assert!(err.contains("`toolu_stale`"));
+        assert!(err.contains("toolu_a"));
+    }
+
+    #[test]
+    fn test_orphan_tool_result_dropped() {
+        let sig = "s".repeat(MIN_SIGNATURE_LENGTH);
+        let mut messages = vec![
+            user_text("go"),
+            assistant_with_tool_use("toolu_a", &sig),
+            Message {
+                role: "user".to_string(),
+                content: MessageContent::Array(vec![tool_result("toolu_a"), tool_result("toolu_stale")]),
+            },
+        ];
+        let dropped = validate_follow_up_tool_results(&mut messages, true).unwrap();
+        assert_eq!(dropped, vec!["toolu_stale".to_string()]);
+        assert_eq!(tool_result_ids(&messages[2]), vec![("toolu_a".to_string(), None)]);
+
+        // 全部为孤立块时保留占位文本, 避免空的 user 轮次
+        let mut messages = vec![
+            user_text("go"),
+            assistant_with_tool_use("toolu_a", &sig),
+            Message {
+                role: "user".to_string(),
+                content: MessageContent::Array(vec![tool_result("toolu_stale")]),
+            },
+        ];
+        validate_follow_up_tool_results(&mut messages, true).unwrap();
+        assert!(matches!(&messages[2].content, MessageContent::Array(b) if b.len() == 1 && matches!(&b[0], ContentBlock::Text { .. })));
+
+        // 匹配的请求不受影响
+        let mut messages = vec![
+            user_text("go"),
+            assistant_with_tool_use("toolu_a", &sig),
+            Message {
+                role: "user".to_string(),
+                content: MessageContent::Array(vec![tool_result("toolu_a")]),
+            },
+        ];
+        assert!(validate_follow_up_tool_results(&mut messages, false).unwrap().is_empty());
+    }
+
+    /// assistant 轮次: redacted_thinking 紧邻 tool_use, 之后是对应的 tool_result
+    fn redacted_tool_conversation() -> Vec<Message> {
+        vec![
+            user_text("list files"),
+            Message {
+                role: "assistant".to_string(),
+                content: MessageContent::Array(vec![
+                    ContentBlock::RedactedThinking { data: "opaque-1".to_string() },
+                    ContentBlock::ToolUse {
+                        id: "toolu_r".to_string(),
+                        name: "bash".to_string(),
+                        input: json!({"command": "ls"}),
+                        signature: None,
+                        cache_control: None,
+                    },
+                ]),
+            },
+            Message {
+                role: "user".to_string(),
+                content: MessageContent::Array(vec![ContentBlock::ToolResult {
+                    tool_use_id: "toolu_r".to_string(),
+                    content: json!("a.rs"),
+                    is_error: None,
+                }]),
+            },
+            Message {
+                role: "assistant".to_string(),
+                content: MessageContent::Array(vec![ContentBlock::RedactedThinking {
+                    data: "opaque-2".to_string(),
+                }]),
+            },
+        ]
+    }
+
+    fn assistant_blocks(msg: &Message) -> &[ContentBlock] {
+        match &msg.content {
+            MessageContent::Array(blocks) => blocks,
+            MessageContent::String(_) => panic!("expected array content"),
+        }
+    }
+
+    #[test]
+    fn test_redacted_thinking_policies() {
+        // Preserve (provider target): 原样保留, data 不变
+        let mut messages = redacted_tool_conversation();
+        filter_invalid_thinking_blocks_with_family(&mut messages, Some("claude"), RedactedThinkingMode::Preserve);
+        assert!(matches!(&assistant_blocks(&messages[1])[0], ContentBlock::RedactedThinking { data } if data == "opaque-1"));
+        assert!(matches!(&assistant_blocks(&messages[1])[1], ContentBlock::ToolUse { id, .. } if id == "toolu_r"));
+
+        // Preserve 对 Gemini 目标退化为 Drop
+        assert_eq!(RedactedThinkingMode::Preserve.for_target(false), RedactedThinkingMode::Drop);
+        assert_eq!(RedactedThinkingMode::Marker.for_target(false), RedactedThinkingMode::Marker);
+
+        // Drop: tool_use 保留; 只含 redacted 的轮次补一个占位 text
+        let mut messages = redacted_tool_conversation();
+        filter_invalid_thinking_blocks_with_family(&mut messages, Some("gemini"), RedactedThinkingMode::Drop);
+        let blocks = assistant_blocks(&messages[1]);
+        assert_eq!(blocks.len(), 1);
+        assert!(matches!(&blocks[0], ContentBlock::ToolUse { .. }));
+        assert!(matches!(&assistant_blocks(&messages[3])[0], ContentBlock::Text { text } if text == "."));
+
+        // Marker: 替换为占位文本, 不泄露 data, 位置不变
+        let mut messages = redacted_tool_conversation();
+        filter_invalid_thinking_blocks_with_family(&mut messages, Some("gemini"), RedactedThinkingMode::Marker);
+        let blocks = assistant_blocks(&messages[1]);
+        assert!(matches!(&blocks[0], ContentBlock::Text { text } if text == REDACTED_THINKING_MARKER));
+        assert!(matches!(&blocks[1], ContentBlock::ToolUse { .. }));
+        assert!(matches!(&assistant_blocks(&messages[3])[0], ContentBlock::Text { text } if text == REDACTED_THINKING_MARKER));
+    }
 }
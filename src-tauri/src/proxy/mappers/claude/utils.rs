@@ -18,9 +18,15 @@ pub fn get_context_limit_for_model(model: &str) -> u32 {
     }
 }
 
-pub fn to_claude_usage(usage_metadata: &super::models::UsageMetadata, scaling_enabled: bool, context_limit: u32) -> super::models::Usage {
-    let prompt_tokens = usage_metadata.prompt_token_count.unwrap_or(0);
-    let cached_tokens = usage_metadata.cached_content_token_count.unwrap_or(0);
+/// `hidden_input_tokens`: 系统提示注入的估算 token 数, 在缩放前从 prompt 中扣除 (客户端无感知)
+pub fn to_claude_usage(
+    usage_metadata: &super::models::UsageMetadata,
+    scaling_enabled: bool,
+    context_limit: u32,
+    hidden_input_tokens: u32,
+) -> super::models::Usage {
+    let prompt_tokens = usage_metadata.prompt_token_count.unwrap_or(0).saturating_sub(hidden_input_tokens);
+    let cached_tokens = usage_metadata.cached_content_token_count.unwrap_or(0).min(prompt_tokens);
 
     // 【改进的智能阈值回归算法】
     // 目标：既利用 Gemini 大窗口，又能在高用量时让 Claude Code 正确触发 compact 提示
@@ -95,6 +101,7 @@ pub fn to_claude_usage(usage_metadata: &super::models::UsageMetadata, scaling_en
         cache_read_input_tokens: reported_cache,
         cache_creation_input_tokens: Some(0),
         server_tool_use: None,
+        service_tier: None,
     }
 }
 
@@ -116,7 +123,7 @@ mod tests {
             cached_content_token_count: None,
         };
 
-        let claude_usage = to_claude_usage(&usage, true, 1_000_000);
+        let claude_usage = to_claude_usage(&usage, true, 1_000_000, 0);
         // 100 tokens is < 30k, minimal scaling
         assert!(claude_usage.input_tokens < 200);
         assert_eq!(claude_usage.output_tokens, 50);
@@ -128,7 +135,7 @@ mod tests {
             total_token_count: Some(500_010),
             cached_content_token_count: None,
         };
-        let res_50 = to_claude_usage(&usage_50, true, 1_000_000);
+        let res_50 = to_claude_usage(&usage_50, true, 1_000_000, 0);
         // 50% * 0.6 = 30% of 195k = 58,500
         assert!(res_50.input_tokens > 55_000 && res_50.input_tokens < 62_000);
 
@@ -139,7 +146,7 @@ mod tests {
             total_token_count: Some(700_010),
             cached_content_token_count: None,
         };
-        let res_70 = to_claude_usage(&usage_70, true, 1_000_000);
+        let res_70 = to_claude_usage(&usage_70, true, 1_000_000, 0);
         // 50% of 195k = 97,500
         assert!(res_70.input_tokens > 90_000 && res_70.input_tokens < 105_000);
 
@@ -150,7 +157,7 @@ mod tests {
             total_token_count: Some(850_010),
             cached_content_token_count: None,
         };
-        let res_85 = to_claude_usage(&usage_85, true, 1_000_000);
+        let res_85 = to_claude_usage(&usage_85, true, 1_000_000, 0);
         // 70% of 195k = 136,500
         assert!(res_85.input_tokens > 130_000 && res_85.input_tokens < 145_000);
 
@@ -161,8 +168,28 @@ mod tests {
             total_token_count: Some(1_000_010),
             cached_content_token_count: None,
         };
-        let res_100 = to_claude_usage(&usage_100, true, 1_000_000);
+        let res_100 = to_claude_usage(&usage_100, true, 1_000_000, 0);
         // 97% of 195k = 189,150
         assert!(res_100.input_tokens > 185_000 && res_100.input_tokens <= 190_000);
     }
+
+    #[test]
+    fn test_hidden_injection_tokens_excluded() {
+        use super::super::models::UsageMetadata;
+
+        let usage = UsageMetadata {
+            prompt_token_count: Some(1_200),
+            candidates_token_count: Some(20),
+            total_token_count: Some(1_220),
+            cached_content_token_count: Some(1_000),
+        };
+        // 注入内容 (约 300 token) 不计入客户端看到的 input
+        let hidden = to_claude_usage(&usage, true, 1_000_000, 300);
+        assert_eq!(hidden.input_tokens + hidden.cache_read_input_tokens.unwrap(), 900);
+        assert_eq!(hidden.cache_read_input_tokens, Some(900));
+
+        let hidden_all = to_claude_usage(&usage, true, 1_000_000, 5_000);
+        assert_eq!(hidden_all.input_tokens, 0);
+        assert_eq!(hidden_all.output_tokens, 20);
+    }
 }
@@ -190,6 +190,187 @@ pub fn parse_image_config_with_params(
     )
 }
 
+/// 上游 imageConfig.aspectRatio 支持的比例
+pub const SUPPORTED_ASPECT_RATIOS: &[&str] = &[
+    "1:1", "2:3", "3:2", "3:4", "4:3", "4:5", "5:4", "9:16", "16:9", "21:9",
+];
+
+/// 校验客户端显式传入的 aspect_ratio, 不支持时返回包含可选值的错误信息
+pub fn validate_aspect_ratio(ratio: &str) -> Result<&'static str, String> {
+    let ratio = ratio.trim();
+    SUPPORTED_ASPECT_RATIOS
+        .iter()
+        .find(|r| **r == ratio)
+        .copied()
+        .ok_or_else(|| {
+            format!(
+                "Unsupported aspect_ratio '{}'. Allowed values: {}",
+                ratio,
+                SUPPORTED_ASPECT_RATIOS.join(", ")
+            )
+        })
+}
+
+/// 图像模型支持的输出尺寸: 宽高比与 1K 档位下的像素尺寸 (2K / 4K 按倍数放大)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageSizeEntry {
+    pub aspect_ratio: &'static str,
+    pub width: u32,
+    pub height: u32,
+}
+
+const fn size_entry(aspect_ratio: &'static str, width: u32, height: u32) -> ImageSizeEntry {
+    ImageSizeEntry {
+        aspect_ratio,
+        width,
+        height,
+    }
+}
+
+const GEMINI_3_PRO_IMAGE_SIZES: &[ImageSizeEntry] = &[
+    size_entry("1:1", 1024, 1024),
+    size_entry("2:3", 848, 1264),
+    size_entry("3:2", 1264, 848),
+    size_entry("3:4", 896, 1200),
+    size_entry("4:3", 1200, 896),
+    size_entry("4:5", 928, 1152),
+    size_entry("5:4", 1152, 928),
+    size_entry("9:16", 768, 1376),
+    size_entry("16:9", 1376, 768),
+    size_entry("21:9", 1584, 672),
+];
+
+/// 各图像模型 (按前缀匹配) 的尺寸表, 未知模型使用第一项
+const IMAGE_MODEL_SIZES: &[(&str, &[ImageSizeEntry])] =
+    &[("gemini-3-pro-image", GEMINI_3_PRO_IMAGE_SIZES)];
+
+pub fn supported_image_sizes(model: &str) -> &'static [ImageSizeEntry] {
+    IMAGE_MODEL_SIZES
+        .iter()
+        .find(|(prefix, _)| model.starts_with(prefix))
+        .unwrap_or(&IMAGE_MODEL_SIZES[0])
+        .1
+}
+
+fn image_size_multiplier(image_size: &str) -> u32 {
+    match image_size {
+        "4K" => 4,
+        "2K" => 2,
+        _ => 1,
+    }
+}
+
+/// 请求尺寸与实际生效尺寸
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageSizeResolution {
+    pub requested: String,
+    pub aspect_ratio: &'static str,
+    /// 实际输出的像素尺寸 ("WIDTHxHEIGHT")
+    pub applied: String,
+    /// 请求尺寸不受支持, 已吸附到最接近的比例
+    pub snapped: bool,
+}
+
+impl ImageSizeResolution {
+    /// 吸附时返回给客户端的告警 (请求值 vs 生效值)
+    pub fn warning(&self) -> Option<String> {
+        self.snapped.then(|| {
+            format!(
+                "requested={}; applied={} ({})",
+                self.requested, self.applied, self.aspect_ratio
+            )
+        })
+    }
+}
+
+/// 将客户端请求的 size 解析为模型支持的尺寸
+///
+/// `size` 可以是 "WIDTHxHEIGHT"、宽高比 ("16:9") 或 "auto"; `image_size` 为 1K / 2K / 4K 档位。
+/// 与尺寸表精确匹配 (或为支持的宽高比) 时原样生效; 像素尺寸既可以是档位尺寸, 也可以是 1K 基准尺寸
+/// (如 quality=hd 时的 "1024x1024" 按 2K 档位输出 2048x2048); 否则默认吸附到最接近的宽高比,
+/// `strict` 为 true 时返回包含可选尺寸的错误信息。
+pub fn resolve_image_size(
+    model: &str,
+    size: &str,
+    image_size: &str,
+    strict: bool,
+) -> Result<ImageSizeResolution, String> {
+    let table = supported_image_sizes(model);
+    let multiplier = image_size_multiplier(image_size);
+    let requested = size.trim();
+    let resolution = |entry: &ImageSizeEntry, snapped: bool| ImageSizeResolution {
+        requested: requested.to_string(),
+        aspect_ratio: entry.aspect_ratio,
+        applied: format!("{}x{}", entry.width * multiplier, entry.height * multiplier),
+        snapped,
+    };
+
+    if requested.is_empty() || requested.eq_ignore_ascii_case("auto") {
+        return Ok(resolution(&table[0], false));
+    }
+    if let Some(entry) = table.iter().find(|e| e.aspect_ratio == requested) {
+        return Ok(resolution(entry, false));
+    }
+
+    let dimensions = requested
+        .split_once('x')
+        .and_then(|(w, h)| Some((w.trim().parse::<u32>().ok()?, h.trim().parse::<u32>().ok()?)))
+        .filter(|(w, h)| *w > 0 && *h > 0);
+    if let Some((width, height)) = dimensions {
+        if let Some(entry) = table.iter().find(|e| {
+            (e.width * multiplier == width && e.height * multiplier == height)
+                || (e.width == width && e.height == height)
+        }) {
+            return Ok(resolution(entry, false));
+        }
+    }
+
+    if strict {
+        let sizes: Vec<String> = table
+            .iter()
+            .map(|e| format!("{}x{}", e.width * multiplier, e.height * multiplier))
+            .collect();
+        let ratios: Vec<&str> = table.iter().map(|e| e.aspect_ratio).collect();
+        return Err(format!(
+            "Unsupported size '{}' for model {} at {}. Supported sizes: {} (or aspect ratios: {})",
+            requested,
+            model,
+            image_size,
+            sizes.join(", "),
+            ratios.join(", ")
+        ));
+    }
+
+    // 按对数比例距离吸附, 无法解析时回退到第一项 (1:1)
+    let nearest = match dimensions {
+        Some((width, height)) => {
+            let target = (width as f64 / height as f64).ln();
+            table
+                .iter()
+                .min_by(|a, b| {
+                    let da = ((a.width as f64 / a.height as f64).ln() - target).abs();
+                    let db = ((b.width as f64 / b.height as f64).ln() - target).abs();
+                    da.total_cmp(&db)
+                })
+                .unwrap_or(&table[0])
+        }
+        None => &table[0],
+    };
+    Ok(resolution(nearest, true))
+}
+
+/// 将 negative_prompt 作为结构化指令追加到提示词
+/// Gemini 图像模型的 imageConfig 没有 negativePrompt 字段, 只能通过提示词表达
+pub fn apply_negative_prompt(prompt: &str, negative_prompt: Option<&str>) -> String {
+    match negative_prompt.map(str::trim).filter(|s| !s.is_empty()) {
+        Some(negative) => format!(
+            "{}\n\nNegative prompt (do NOT include any of the following in the image): {}",
+            prompt, negative
+        ),
+        None => prompt.to_string(),
+    }
+}
+
 /// 动态计算宽高比（解决硬编码问题）
 ///
 /// 从 "WIDTHxHEIGHT" 格式的字符串解析并计算宽高比，
@@ -593,4 +774,77 @@ mod tests {
         assert_eq!(calculate_aspect_ratio_from_size("0x1080"), "1:1");
         assert_eq!(calculate_aspect_ratio_from_size("abc x def"), "1:1");
     }
+
+    #[test]
+    fn test_validate_aspect_ratio_and_negative_prompt() {
+        assert_eq!(validate_aspect_ratio("16:9"), Ok("16:9"));
+        assert_eq!(validate_aspect_ratio(" 21:9 "), Ok("21:9"));
+        let err = validate_aspect_ratio("7:3").unwrap_err();
+        assert!(err.contains("7:3"));
+        assert!(err.contains("21:9"));
+
+        // 显式 aspect_ratio 优先于 size 推导的比例
+        let (config, _) = parse_image_config_with_params("gemini-3-pro-image", Some("4:3"), None);
+        assert_eq!(config["aspectRatio"], "4:3");
+
+        assert_eq!(apply_negative_prompt("a cat", None), "a cat");
+        assert_eq!(apply_negative_prompt("a cat", Some("  ")), "a cat");
+        let p = apply_negative_prompt("a cat", Some("text, watermark"));
+        assert!(p.starts_with("a cat\n\nNegative prompt"));
+        assert!(p.ends_with("text, watermark"));
+    }
+
+    #[test]
+    fn test_resolve_image_size() {
+        let model = "gemini-3-pro-image";
+
+        // 精确匹配: 尺寸表中的像素尺寸 / 宽高比 / auto
+        let exact = resolve_image_size(model, "1024x1024", "1K", true).unwrap();
+        assert_eq!(exact.aspect_ratio, "1:1");
+        assert_eq!(exact.applied, "1024x1024");
+        assert!(!exact.snapped);
+        assert_eq!(exact.warning(), None);
+
+        let hd = resolve_image_size(model, "2752x1536", "2K", true).unwrap();
+        assert_eq!(hd.aspect_ratio, "16:9");
+        assert!(!hd.snapped);
+
+        let ratio = resolve_image_size(model, "21:9", "4K", true).unwrap();
+        assert_eq!(ratio.applied, "6336x2688");
+        assert!(!ratio.snapped);
+        assert!(!resolve_image_size(model, "auto", "1K", true).unwrap().snapped);
+
+        // 吸附: 不支持的尺寸映射到最接近的比例, 并给出告警
+        let snapped = resolve_image_size(model, "1792x1024", "1K", false).unwrap();
+        assert_eq!(snapped.aspect_ratio, "16:9");
+        assert_eq!(snapped.applied, "1376x768");
+        assert!(snapped.snapped);
+        assert_eq!(
+            snapped.warning().as_deref(),
+            Some("requested=1792x1024; applied=1376x768 (16:9)")
+        );
+
+        let small = resolve_image_size(model, "512x512", "1K", false).unwrap();
+        assert_eq!(small.applied, "1024x1024");
+        assert!(small.snapped);
+
+        let wide = resolve_image_size(model, "3000x1000", "1K", false).unwrap();
+        assert_eq!(wide.aspect_ratio, "21:9");
+
+        let invalid = resolve_image_size(model, "huge", "1K", false).unwrap();
+        assert_eq!(invalid.aspect_ratio, "1:1");
+        assert!(invalid.snapped);
+
+        // 严格模式: 不支持的尺寸直接拒绝
+        let err = resolve_image_size(model, "1792x1024", "1K", true).unwrap_err();
+        assert!(err.contains("1792x1024"));
+        assert!(err.contains("1376x768"));
+        assert!(resolve_image_size(model, "1792x1024", "2K", true).is_err());
+
+        // 1K 基准尺寸配合 quality=hd (2K 档位): 按档位输出, 不视为吸附
+        let hd_square = resolve_image_size(model, "1024x1024", "2K", true).unwrap();
+        assert_eq!(hd_square.aspect_ratio, "1:1");
+        assert_eq!(hd_square.applied, "2048x2048");
+        assert!(!hd_square.snapped);
+    }
 }
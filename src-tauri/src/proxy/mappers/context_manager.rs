@@ -12,7 +12,7 @@ use tracing::{debug, info};
 /// - ASCII/English: ~4 characters per token
 /// - Unicode/CJK: ~1.5 characters per token (Chinese, Japanese, Korean are tokenized differently)
 /// - Adds 15% safety margin to prevent underestimation
-fn estimate_tokens_from_str(s: &str) -> u32 {
+pub(crate) fn estimate_tokens_from_str(s: &str) -> u32 {
     if s.is_empty() {
         return 0;
     }
@@ -100,12 +100,33 @@ impl ContextManager {
     }
 }
 
+/// Anthropic 在声明 tools 时注入的 tool-use 系统提示开销 (官方文档: auto/none 为 346)
+const TOOL_USE_SYSTEM_PROMPT_TOKENS: u32 = 346;
+/// 开启 thinking 时的配置开销
+const THINKING_CONFIG_OVERHEAD_TOKENS: u32 = 16;
+
 impl ContextManager {
     /// Estimate token usage for a Claude Request
     ///
     /// This is a lightweight estimation, not a precise count.
-    /// It iterates through all messages and blocks to sum up estimated tokens.
+    /// Input tokens plus the reserved thinking budget.
     pub fn estimate_token_usage(request: &ClaudeRequest) -> u32 {
+        let mut total = Self::estimate_input_tokens(request);
+
+        // Thinking budget overhead if enabled
+        if let Some(thinking) = &request.thinking {
+            if let Some(budget) = thinking.budget_tokens {
+                // Reserve budget in estimation
+                total += budget;
+            }
+        }
+
+        total
+    }
+
+    /// Estimate input tokens (system + messages + tool schemas + thinking config),
+    /// shared by context compression and `/v1/messages/count_tokens`
+    pub fn estimate_input_tokens(request: &ClaudeRequest) -> u32 {
         let mut total = 0;
 
         // System prompt
@@ -178,7 +199,8 @@ impl ContextManager {
         }
 
         // Tools definition overhead (rough estimate)
-        if let Some(tools) = &request.tools {
+        if let Some(tools) = request.tools.as_ref().filter(|t| !t.is_empty()) {
+            total += TOOL_USE_SYSTEM_PROMPT_TOKENS;
             for tool in tools {
                 if let Ok(json_str) = serde_json::to_string(tool) {
                     total += estimate_tokens_from_str(&json_str);
@@ -186,12 +208,12 @@ impl ContextManager {
             }
         }
 
-        // Thinking budget overhead if enabled
-        if let Some(thinking) = &request.thinking {
-            if let Some(budget) = thinking.budget_tokens {
-                // Reserve budget in estimation
-                total += budget;
-            }
+        if request
+            .thinking
+            .as_ref()
+            .is_some_and(|t| t.type_ == "enabled")
+        {
+            total += THINKING_CONFIG_OVERHEAD_TOKENS;
         }
 
         total
@@ -319,6 +341,8 @@ impl ContextManager {
     /// - An assistant message with tool_use
     /// - One or more user messages with tool_result
     ///
+    /// Rounds whose tool_result was replaced by a Layer-1.5 digest are kept.
+    ///
     /// Returns true if any messages were removed
     pub fn trim_tool_messages(messages: &mut Vec<Message>, keep_last_n_rounds: usize) -> bool {
         let tool_rounds = identify_tool_rounds(messages);
@@ -332,6 +356,13 @@ impl ContextManager {
         let mut indices_to_remove = std::collections::HashSet::new();
 
         for round in tool_rounds.iter().take(rounds_to_remove) {
+            if round
+                .tool_result_indices
+                .iter()
+                .any(|idx| has_tool_result_digest(&messages[*idx].content))
+            {
+                continue;
+            }
             for idx in &round.indices {
                 indices_to_remove.insert(*idx);
             }
@@ -355,6 +386,33 @@ impl ContextManager {
 
         removed_count > 0
     }
+
+    /// Message indices holding the tool_results of rounds that `trim_tool_messages` would remove
+    /// (newest first, so a limited summary budget goes to the most recent outputs)
+    pub fn old_tool_result_indices(messages: &[Message], keep_last_n_rounds: usize) -> Vec<usize> {
+        let tool_rounds = identify_tool_rounds(messages);
+        let rounds_to_remove = tool_rounds.len().saturating_sub(keep_last_n_rounds);
+        tool_rounds
+            .iter()
+            .take(rounds_to_remove)
+            .rev()
+            .flat_map(|round| round.tool_result_indices.iter().rev().copied())
+            .collect()
+    }
+}
+
+/// Visible prefix of a tool_result replaced by a Layer-1.5 digest
+pub const TOOL_RESULT_DIGEST_PREFIX: &str =
+    "[Digest of an earlier tool output, summarized by the proxy to save context. Re-run the tool if the exact output is needed.]\n";
+
+fn has_tool_result_digest(content: &MessageContent) -> bool {
+    let MessageContent::Array(blocks) = content else {
+        return false;
+    };
+    blocks.iter().any(|b| {
+        matches!(b, ContentBlock::ToolResult { content, .. }
+            if content.as_str().is_some_and(|s| s.starts_with(TOOL_RESULT_DIGEST_PREFIX)))
+    })
 }
 
 /// Represents a tool call round (assistant tool_use + user tool_result(s))
@@ -458,9 +516,49 @@ mod tests {
             output_config: None,
             size: None,
             quality: None,
+            service_tier: None,
+            tool_choice: None,
+            stop_sequences: None,
         }
     }
 
+    fn tool_round(id: &str, output: &str) -> Vec<Message> {
+        vec![
+            Message {
+                role: "assistant".into(),
+                content: MessageContent::Array(vec![ContentBlock::ToolUse {
+                    id: id.into(),
+                    name: "read_file".into(),
+                    input: serde_json::json!({ "path": id }),
+                    signature: None,
+                    cache_control: None,
+                }]),
+            },
+            Message {
+                role: "user".into(),
+                content: MessageContent::Array(vec![ContentBlock::ToolResult {
+                    tool_use_id: id.into(),
+                    content: serde_json::Value::String(output.into()),
+                    is_error: None,
+                }]),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_trim_tool_messages_keeps_digested_rounds() {
+        let digest = format!("{}main.rs defines fn main", TOOL_RESULT_DIGEST_PREFIX);
+        let mut messages: Vec<Message> = [("t1", "old output"), ("t2", digest.as_str()), ("t3", "recent")]
+            .iter()
+            .flat_map(|(id, output)| tool_round(id, output))
+            .collect();
+
+        assert_eq!(ContextManager::old_tool_result_indices(&messages, 1), vec![3, 1]);
+        assert!(ContextManager::trim_tool_messages(&mut messages, 1));
+        assert_eq!(messages.len(), 4);
+        assert!(has_tool_result_digest(&messages[1].content));
+    }
+
     #[test]
     fn test_estimate_tokens() {
         let mut req = create_test_request();
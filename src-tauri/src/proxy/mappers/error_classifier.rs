@@ -8,7 +8,14 @@ use reqwest::Error;
 /// - 英文消息: fallback 消息,供非浏览器客户端使用
 /// - i18n_key: 前端翻译键,供浏览器客户端本地化
 pub fn classify_stream_error(error: &Error) -> (&'static str, &'static str, &'static str) {
-    if error.is_timeout() {
+    // 连接阶段 (含 TLS 握手) 超时: 账号/节点无关的网络问题, 应立即换号而不是等待退避
+    if error.is_timeout() && error.is_connect() {
+        (
+            CONNECT_TIMEOUT_ERROR,
+            "Connection to the upstream server timed out, switching account",
+            "errors.stream.connect_timeout_error"
+        )
+    } else if error.is_timeout() {
         (
             "timeout_error",
             "Request timeout, please check your network connection",
@@ -41,6 +48,30 @@ pub fn classify_stream_error(error: &Error) -> (&'static str, &'static str, &'st
     }
 }
 
+/// 连接超时的错误类型标识 (UpstreamClient 会写入错误信息中)
+pub const CONNECT_TIMEOUT_ERROR: &str = "connect_timeout_error";
+
+/// 判断上游调用返回的错误信息是否为连接超时
+pub fn is_connect_timeout(error: &str) -> bool {
+    error.contains(CONNECT_TIMEOUT_ERROR)
+}
+
+/// 上下文超限的结构化原因 (SSE error 事件的 code, 同时作为请求日志中的失败原因前缀)
+pub const PROMPT_TOO_LONG_CODE: &str = "prompt_too_long";
+pub const PROMPT_TOO_LONG_MESSAGE: &str = "Prompt is too long (server-side context limit reached).";
+pub const PROMPT_TOO_LONG_SUGGESTION: &str = "Please: 1) Execute '/compact' in Claude Code 2) Reduce conversation history 3) Switch to gemini-1.5-pro (2M context limit)";
+
+/// 上游错误信息是否表示上下文窗口超限
+/// (用于 200 流中途的错误事件, 比非流式分支的关键字匹配更严格, 避免把限流等错误误判为超限)
+pub fn is_context_window_exceeded(message: &str) -> bool {
+    let m = message.to_lowercase();
+    m.contains("exceeds the maximum number of tokens")
+        || (m.contains("input token count") && m.contains("exceed"))
+        || m.contains("prompt is too long")
+        || m.contains("context_length_exceeded")
+        || (m.contains("context window") && m.contains("exceed"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -92,6 +123,7 @@ mod tests {
         // 错误类型应该是已知的类型之一
         assert!(
             error_type == "timeout_error" ||
+            error_type == CONNECT_TIMEOUT_ERROR ||
             error_type == "connection_error" ||
             error_type == "decode_error" ||
             error_type == "stream_error" ||
@@ -110,6 +142,7 @@ mod tests {
         // 验证所有错误类型都有正确的 i18n_key 格式
         let test_cases = vec![
             ("timeout_error", "errors.stream.timeout_error"),
+            (CONNECT_TIMEOUT_ERROR, "errors.stream.connect_timeout_error"),
             ("connection_error", "errors.stream.connection_error"),
             ("decode_error", "errors.stream.decode_error"),
             ("stream_error", "errors.stream.stream_error"),
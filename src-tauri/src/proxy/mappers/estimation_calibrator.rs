@@ -2,11 +2,155 @@
 //!
 //! Learns from historical request/response pairs to improve token estimation accuracy.
 //! Uses actual token counts from Google API responses to calibrate future estimates.
+//!
+//! Besides the global factor, samples are bucketed by (model family × has images):
+//! image-heavy requests are underestimated far more than text-only ones and would
+//! otherwise skew the text-only calibration.
 
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::RwLock;
 use tracing::info;
 
+use crate::proxy::mappers::claude::models::{ClaudeRequest, ContentBlock, MessageContent};
+
+/// Initial factor, assuming estimates are 2.0x lower than actual
+const DEFAULT_FACTOR: f32 = 2.0;
+/// Factor is recomputed every N samples (global and per bucket)
+const UPDATE_INTERVAL: u64 = 5;
+/// A bucket is used for calibration once it has received its first update
+const MIN_BUCKET_SAMPLES: u64 = UPDATE_INTERVAL;
+
+/// Model family of a calibration bucket
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CalibrationFamily {
+    Claude,
+    Gemini,
+    Other,
+}
+
+impl CalibrationFamily {
+    pub fn from_model(model: &str) -> Self {
+        let model = model.to_ascii_lowercase();
+        if model.contains("claude") {
+            Self::Claude
+        } else if model.contains("gemini") {
+            Self::Gemini
+        } else {
+            Self::Other
+        }
+    }
+}
+
+/// Calibration bucket: (family × has images)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CalibrationBucket {
+    pub family: CalibrationFamily,
+    pub has_images: bool,
+}
+
+impl CalibrationBucket {
+    /// Bucket of a request routed to `mapped_model`
+    pub fn for_request(request: &ClaudeRequest, mapped_model: &str) -> Self {
+        Self {
+            family: CalibrationFamily::from_model(mapped_model),
+            has_images: request_has_images(request),
+        }
+    }
+}
+
+/// Labeled sample captured when a successful response reports usage
+#[derive(Debug, Clone, Copy)]
+pub struct CalibrationSample {
+    pub raw_estimate: u32,
+    pub actual: u32,
+    pub bucket: CalibrationBucket,
+    pub had_tools: bool,
+}
+
+/// Raw prompt estimate sent along with a request, labeled for calibration
+#[derive(Debug, Clone, Copy)]
+pub struct PromptEstimate {
+    pub raw: u32,
+    pub bucket: CalibrationBucket,
+    pub had_tools: bool,
+}
+
+impl PromptEstimate {
+    pub fn for_request(request: &ClaudeRequest, mapped_model: &str, raw: u32) -> Self {
+        Self {
+            raw,
+            bucket: CalibrationBucket::for_request(request, mapped_model),
+            had_tools: request.tools.as_ref().is_some_and(|t| !t.is_empty()),
+        }
+    }
+
+    pub fn sample(&self, actual: u32) -> CalibrationSample {
+        CalibrationSample {
+            raw_estimate: self.raw,
+            actual,
+            bucket: self.bucket,
+            had_tools: self.had_tools,
+        }
+    }
+}
+
+fn request_has_images(request: &ClaudeRequest) -> bool {
+    request.messages.iter().any(|msg| match &msg.content {
+        MessageContent::String(_) => false,
+        MessageContent::Array(blocks) => blocks.iter().any(|block| match block {
+            ContentBlock::Image { .. } => true,
+            ContentBlock::ToolResult { content, .. } => content
+                .as_array()
+                .is_some_and(|items| items.iter().any(|item| item.get("type").and_then(|t| t.as_str()) == Some("image"))),
+            _ => false,
+        }),
+    })
+}
+
+#[derive(Debug, Clone)]
+struct BucketState {
+    total_estimated: u64,
+    total_actual: u64,
+    samples: u64,
+    samples_with_tools: u64,
+    factor: f32,
+}
+
+impl Default for BucketState {
+    fn default() -> Self {
+        Self {
+            total_estimated: 0,
+            total_actual: 0,
+            samples: 0,
+            samples_with_tools: 0,
+            factor: DEFAULT_FACTOR,
+        }
+    }
+}
+
+/// Per-bucket calibration state (get_calibration_state)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BucketCalibration {
+    pub family: CalibrationFamily,
+    pub has_images: bool,
+    pub factor: f32,
+    pub samples: u64,
+    pub samples_with_tools: u64,
+    /// false until the bucket has enough samples; the global factor is used meanwhile
+    pub active: bool,
+}
+
+/// Calibration snapshot (get_calibration_state)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CalibrationState {
+    pub global_factor: f32,
+    pub global_samples: u64,
+    pub buckets: Vec<BucketCalibration>,
+}
+
 /// Estimation Calibrator - learns estimation error from historical requests
 ///
 /// This module tracks the ratio between estimated tokens (before request) and
@@ -20,6 +164,8 @@ pub struct EstimationCalibrator {
     sample_count: AtomicU64,
     /// Current calibration factor (estimated * factor ≈ actual)
     calibration_factor: RwLock<f32>,
+    /// Per (family × has images) calibration
+    buckets: RwLock<Option<HashMap<CalibrationBucket, BucketState>>>,
 }
 
 impl EstimationCalibrator {
@@ -31,7 +177,87 @@ impl EstimationCalibrator {
             sample_count: AtomicU64::new(0),
             // Initial assumption: estimates are 2.0x lower than actual
             // This is conservative and will be adjusted based on real data
-            calibration_factor: RwLock::new(2.0),
+            calibration_factor: RwLock::new(DEFAULT_FACTOR),
+            buckets: RwLock::new(None),
+        }
+    }
+
+    /// Record a labeled sample: updates the global factor and the sample's bucket
+    pub fn record_sample(&self, sample: &CalibrationSample) {
+        if sample.raw_estimate == 0 || sample.actual == 0 {
+            return;
+        }
+        self.record(sample.raw_estimate, sample.actual);
+
+        let Ok(mut buckets) = self.buckets.write() else {
+            return;
+        };
+        let bucket = buckets.get_or_insert_with(HashMap::new).entry(sample.bucket).or_default();
+        bucket.total_estimated += sample.raw_estimate as u64;
+        bucket.total_actual += sample.actual as u64;
+        bucket.samples += 1;
+        if sample.had_tools {
+            bucket.samples_with_tools += 1;
+        }
+        if bucket.samples % UPDATE_INTERVAL == 0 {
+            let old = bucket.factor;
+            bucket.factor = ema(old, bucket.total_actual as f64 / bucket.total_estimated as f64);
+            tracing::debug!(
+                "[Calibrator] Bucket {:?} (images: {}) factor: {:.2} -> {:.2} (samples: {})",
+                sample.bucket.family,
+                sample.bucket.has_images,
+                old,
+                bucket.factor,
+                bucket.samples
+            );
+        }
+    }
+
+    /// Calibration factor for a bucket; falls back to the global factor until the bucket has enough samples
+    pub fn factor_for(&self, bucket: CalibrationBucket) -> f32 {
+        let learned = self.buckets.read().ok().and_then(|buckets| {
+            buckets
+                .as_ref()?
+                .get(&bucket)
+                .filter(|b| b.samples >= MIN_BUCKET_SAMPLES)
+                .map(|b| b.factor)
+        });
+        learned.unwrap_or_else(|| self.get_factor())
+    }
+
+    /// Calibrated estimate using the bucket's factor
+    pub fn calibrate_for(&self, bucket: CalibrationBucket, estimated: u32) -> u32 {
+        (estimated as f32 * self.factor_for(bucket)).ceil() as u32
+    }
+
+    /// Global and per-bucket factors with sample counts
+    pub fn state(&self) -> CalibrationState {
+        let mut buckets: Vec<BucketCalibration> = self
+            .buckets
+            .read()
+            .ok()
+            .and_then(|buckets| {
+                buckets.as_ref().map(|buckets| {
+                    buckets
+                        .iter()
+                        .map(|(key, b)| BucketCalibration {
+                            family: key.family,
+                            has_images: key.has_images,
+                            factor: b.factor,
+                            samples: b.samples,
+                            samples_with_tools: b.samples_with_tools,
+                            active: b.samples >= MIN_BUCKET_SAMPLES,
+                        })
+                        .collect()
+                })
+            })
+            .unwrap_or_default();
+        buckets.sort_by(|a, b| b.samples.cmp(&a.samples));
+
+        CalibrationState {
+            global_factor: self.get_factor(),
+            global_samples: self.sample_count.load(Ordering::Relaxed),
+            buckets,
         }
     }
 
@@ -50,7 +276,7 @@ impl EstimationCalibrator {
         let count = self.sample_count.fetch_add(1, Ordering::Relaxed) + 1;
 
         // Update calibration factor every 5 requests
-        if count % 5 == 0 {
+        if count % UPDATE_INTERVAL == 0 {
             self.update_calibration();
         }
     }
@@ -62,16 +288,10 @@ impl EstimationCalibrator {
 
         if estimated > 0.0 {
             let new_factor = (actual / estimated) as f32;
-            // Clamp to reasonable range [0.8, 4.0]
-            // - Below 0.8 means we're overestimating (rare)
-            // - Above 4.0 means severe underestimation
-            let clamped = new_factor.clamp(0.8, 4.0);
 
             if let Ok(mut factor) = self.calibration_factor.write() {
-                // Exponential moving average: 60% old + 40% new
-                // This provides stability while still adapting to changes
                 let old = *factor;
-                *factor = old * 0.6 + clamped * 0.4;
+                *factor = ema(old, actual / estimated);
 
                 info!(
                     "[Calibrator] Updated factor: {:.2} -> {:.2} (raw: {:.2}, samples: {})",
@@ -88,17 +308,28 @@ impl EstimationCalibrator {
     ///
     /// Multiplies the raw estimate by the current calibration factor.
     pub fn calibrate(&self, estimated: u32) -> u32 {
-        let factor = self.calibration_factor.read().map(|f| *f).unwrap_or(2.0);
+        let factor = self.calibration_factor.read().map(|f| *f).unwrap_or(DEFAULT_FACTOR);
 
         (estimated as f32 * factor).ceil() as u32
     }
 
     /// Get the current calibration factor
     pub fn get_factor(&self) -> f32 {
-        self.calibration_factor.read().map(|f| *f).unwrap_or(2.0)
+        self.calibration_factor.read().map(|f| *f).unwrap_or(DEFAULT_FACTOR)
     }
 }
 
+/// Move `old` towards the observed ratio
+fn ema(old: f32, ratio: f64) -> f32 {
+    // Clamp to reasonable range [0.8, 4.0]
+    // - Below 0.8 means we're overestimating (rare)
+    // - Above 4.0 means severe underestimation
+    let clamped = (ratio as f32).clamp(0.8, 4.0);
+    // Exponential moving average: 60% old + 40% new
+    // This provides stability while still adapting to changes
+    old * 0.6 + clamped * 0.4
+}
+
 impl Default for EstimationCalibrator {
     fn default() -> Self {
         Self::new()
@@ -156,4 +387,66 @@ mod tests {
 
         assert_eq!(calibrator.sample_count.load(Ordering::Relaxed), 0);
     }
+
+    #[test]
+    fn test_buckets_converge_independently() {
+        let calibrator = EstimationCalibrator::new();
+        let text = CalibrationBucket { family: CalibrationFamily::Claude, has_images: false };
+        let images = CalibrationBucket { family: CalibrationFamily::Claude, has_images: true };
+
+        // Unknown bucket falls back to the global factor
+        assert_eq!(calibrator.factor_for(text), calibrator.get_factor());
+
+        // Interleaved populations: text-only underestimated by 10%, image-heavy by 60%
+        for i in 0..60 {
+            let raw = 1_000 + i * 10;
+            calibrator.record_sample(&CalibrationSample {
+                raw_estimate: raw,
+                actual: raw * 11 / 10,
+                bucket: text,
+                had_tools: i % 2 == 0,
+            });
+            calibrator.record_sample(&CalibrationSample {
+                raw_estimate: raw,
+                actual: raw * 16 / 10,
+                bucket: images,
+                had_tools: false,
+            });
+        }
+
+        assert!((calibrator.factor_for(text) - 1.1).abs() < 0.02);
+        assert!((calibrator.factor_for(images) - 1.6).abs() < 0.02);
+        // The global factor mixes both populations
+        let global = calibrator.get_factor();
+        assert!(global > 1.2 && global < 1.5);
+        assert!((1_100..=1_105).contains(&calibrator.calibrate_for(text, 1_000)));
+
+        let state = calibrator.state();
+        assert_eq!(state.global_samples, 120);
+        assert_eq!(state.buckets.len(), 2);
+        let text_state = state.buckets.iter().find(|b| !b.has_images).unwrap();
+        assert_eq!(text_state.samples, 60);
+        assert_eq!(text_state.samples_with_tools, 30);
+        assert!(text_state.active);
+    }
+
+    #[test]
+    fn test_bucket_for_request() {
+        let request: ClaudeRequest = serde_json::from_value(serde_json::json!({
+            "model": "claude-sonnet-4-5",
+            "messages": [{
+                "role": "user",
+                "content": [{
+                    "type": "tool_result",
+                    "tool_use_id": "t1",
+                    "content": [{ "type": "image", "source": { "type": "base64", "media_type": "image/png", "data": "AA==" } }]
+                }]
+            }]
+        }))
+        .unwrap();
+
+        let bucket = CalibrationBucket::for_request(&request, "gemini-3-flash");
+        assert_eq!(bucket.family, CalibrationFamily::Gemini);
+        assert!(bucket.has_images);
+    }
 }
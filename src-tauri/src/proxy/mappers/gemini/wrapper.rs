@@ -127,6 +127,21 @@ pub fn wrap_request(body: &Value, project_id: &str, mapped_model: &str, session_
         }
     }
 
+    // 能力表已知该模型拒绝 thinking (上游学习到或用户固定) 时剔除 thinkingConfig
+    if crate::proxy::common::thinking_capability::is_known_unsupported(final_model_name) {
+        if let Some(gen_obj) = inner_request
+            .get_mut("generationConfig")
+            .and_then(|g| g.as_object_mut())
+        {
+            if gen_obj.remove("thinkingConfig").is_some() {
+                tracing::debug!(
+                    "[Gemini-Wrap] {} is known not to support thinking, removed thinkingConfig",
+                    final_model_name
+                );
+            }
+        }
+    }
+
     // [FIX] Removed forced maxOutputTokens (64000) as it exceeds limits for Gemini 1.5 Flash/Pro standard models (8192).
     // This caused upstream to return empty/invalid responses, leading to 'NoneType' object has no attribute 'strip' in Python clients.
     // relying on upstream defaults or user provided values is safer.
@@ -1,6 +1,7 @@
 // Mappers 模块 - 协议转换器
 // 协议转换器模块
 
+pub mod candidates;
 pub mod claude;
 pub mod common_utils;
 pub mod context_manager;
@@ -8,4 +9,6 @@ pub mod error_classifier;
 pub mod estimation_calibrator;
 pub mod gemini;
 pub mod openai;
+pub mod stream_completeness;
+pub mod stream_watchdog;
 pub mod tool_result_compressor;
@@ -2,15 +2,18 @@
 // Used for auto-converting streaming responses to JSON for non-streaming requests
 
 use super::models::*;
+use crate::proxy::mappers::stream_completeness::{CollectedResponse, StreamCompleteness};
 use bytes::Bytes;
 use futures::StreamExt;
 use serde_json::Value;
 use std::collections::HashMap;
 
 /// Collects an OpenAI SSE stream into a complete OpenAIResponse
+/// Without a finish_reason and final usage the response is treated as truncated
+/// (finish_reason = "length" when partial content exists)
 pub async fn collect_stream_to_json<S, E>(
     mut stream: S,
-) -> Result<OpenAIResponse, String>
+) -> Result<CollectedResponse<OpenAIResponse>, String>
 where
     S: futures::Stream<Item = Result<Bytes, E>> + Unpin,
     E: std::fmt::Display,
@@ -30,6 +33,7 @@ where
     let mut finish_reason: Option<String> = None;
     // Tool calls aggregation: index -> (id, type, name, arguments_parts)
     let mut tool_calls_map: HashMap<u32, (String, String, String, Vec<String>)> = HashMap::new();
+    let mut completeness = StreamCompleteness::default();
 
     while let Some(chunk_result) = stream.next().await {
         let chunk = chunk_result.map_err(|e| format!("Stream error: {}", e))?;
@@ -58,6 +62,9 @@ where
                     // Collect Usage
                     if let Some(usage) = json.get("usage") {
                         if let Ok(u) = serde_json::from_value::<OpenAIUsage>(usage.clone()) {
+                            if u.total_tokens > 0 {
+                                completeness.saw_final_usage = true;
+                            }
                             response.usage = Some(u);
                         }
                     }
@@ -117,6 +124,7 @@ where
                             }
 
                             if let Some(fr) = choice.get("finish_reason").and_then(|v| v.as_str()) {
+                                completeness.saw_stop = true;
                                 finish_reason = Some(fr.to_string());
                             }
                         }
@@ -128,6 +136,11 @@ where
 
     // Construct final message
     let full_content = content_parts.join("");
+    completeness.has_content =
+        !full_content.is_empty() || !reasoning_parts.is_empty() || !tool_calls_map.is_empty();
+    if !completeness.is_complete() && completeness.has_content {
+        finish_reason = Some("length".to_string());
+    }
     let full_reasoning = if reasoning_parts.is_empty() {
         None
     } else {
@@ -148,6 +161,7 @@ where
                         name,
                         arguments: args_parts.join(""),
                     },
+                    validation_error: None,
                 })
             })
             .collect();
@@ -170,5 +184,60 @@ where
         finish_reason: finish_reason.or(Some("stop".to_string())),
     });
 
-    Ok(response)
+    Ok(CollectedResponse {
+        response,
+        completeness,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream;
+
+    const FULL_STREAM: [&str; 4] = [
+        "data: {\"id\":\"chatcmpl-cut\",\"model\":\"gemini-3-flash\",\"choices\":[{\"index\":0,\"delta\":{\"role\":\"assistant\",\"content\":\"Hel\"},\"finish_reason\":null}]}\n\n",
+        "data: {\"id\":\"chatcmpl-cut\",\"model\":\"gemini-3-flash\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"lo\"},\"finish_reason\":null}]}\n\n",
+        "data: {\"id\":\"chatcmpl-cut\",\"model\":\"gemini-3-flash\",\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"stop\"}],\"usage\":{\"prompt_tokens\":12,\"completion_tokens\":5,\"total_tokens\":17}}\n\n",
+        "data: [DONE]\n\n",
+    ];
+
+    async fn collect(chunks: Vec<&'static str>) -> CollectedResponse<OpenAIResponse> {
+        let byte_stream = stream::iter(
+            chunks.into_iter().map(|s| Ok::<Bytes, String>(Bytes::from(s))),
+        );
+        collect_stream_to_json(byte_stream).await.unwrap()
+    }
+
+    fn content(resp: &OpenAIResponse) -> String {
+        match &resp.choices[0].message.content {
+            Some(OpenAIContent::String(s)) => s.clone(),
+            _ => String::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_collect_detects_truncated_streams() {
+        let full = collect(FULL_STREAM.to_vec()).await;
+        assert!(full.completeness.is_complete());
+        assert_eq!(full.response.choices[0].finish_reason.as_deref(), Some("stop"));
+        assert_eq!(content(&full.response), "Hello");
+
+        // 没有任何数据
+        let cut = collect(Vec::new()).await;
+        assert!(!cut.completeness.has_content);
+        assert!(!cut.completeness.is_complete());
+
+        // 内容中途断开 ([DONE] 由本地补发)
+        let cut = collect(vec![FULL_STREAM[0], FULL_STREAM[1], FULL_STREAM[3]]).await;
+        assert!(!cut.completeness.is_complete());
+        assert!(cut.completeness.has_content);
+        assert_eq!(cut.response.choices[0].finish_reason.as_deref(), Some("length"));
+        assert_eq!(content(&cut.response), "Hello");
+
+        // 只收到第一段
+        let cut = collect(FULL_STREAM[..1].to_vec()).await;
+        assert_eq!(cut.response.choices[0].finish_reason.as_deref(), Some("length"));
+        assert_eq!(cut.completeness.confidence(), 0.25);
+    }
 }
@@ -7,6 +7,8 @@ pub mod response;
 pub mod streaming;
 pub mod collector; // [NEW]
 pub mod thinking_recovery;
+pub mod strict_tools;
+pub mod response_format;
 
 pub use models::*;
 pub use request::*;
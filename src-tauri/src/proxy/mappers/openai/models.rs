@@ -12,10 +12,16 @@ pub struct OpenAIRequest {
     pub prompt: Option<String>,
     #[serde(default)]
     pub stream: bool,
+    /// 流式选项 (仅 include_usage 生效)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<StreamOptions>,
     #[serde(default)]
     pub n: Option<u32>, // [NEW] 支持多候选结果数量
     #[serde(rename = "max_tokens")]
     pub max_tokens: Option<u32>,
+    /// 新版 SDK 的输出上限字段, 优先于 max_tokens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_completion_tokens: Option<u32>,
     pub temperature: Option<f32>,
     #[serde(rename = "top_p")]
     pub top_p: Option<f32>,
@@ -40,6 +46,52 @@ pub struct OpenAIRequest {
     // [NEW] Thinking/Extended Thinking 支持 (兼容 Anthropic/Claude 协议)
     #[serde(default)]
     pub thinking: Option<ThinkingConfig>,
+    // OpenAI `metadata` (string 键值对), 用作会话提示 (session_id / conversation_id / user_id)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Value>,
+    /// 未识别的顶层字段 (如 prediction / store / modalities / audio)
+    /// 不会转发给上游, 仅用于日志与 `X-Ignored-Fields` 响应头
+    #[serde(flatten, default)]
+    pub passthrough: serde_json::Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StreamOptions {
+    /// 流式响应末尾单独发送一个 usage chunk (choices 为空)
+    #[serde(default)]
+    pub include_usage: bool,
+}
+
+/// 响应头: 列出请求中被忽略的顶层字段 (逗号分隔, 按字母排序)
+pub const IGNORED_FIELDS_HEADER: &str = "X-Ignored-Fields";
+
+/// metadata 中可作为会话提示的键 (按优先级)
+const METADATA_SESSION_KEYS: [&str; 3] = ["session_id", "conversation_id", "user_id"];
+
+impl OpenAIRequest {
+    /// 返回被忽略的顶层字段名 (排序后)
+    pub fn ignored_fields(&self) -> Vec<String> {
+        let mut fields: Vec<String> = self.passthrough.keys().cloned().collect();
+        fields.sort();
+        fields
+    }
+
+    /// 流式请求是否要求末尾的 usage chunk
+    pub fn include_stream_usage(&self) -> bool {
+        self.stream && self.stream_options.as_ref().is_some_and(|o| o.include_usage)
+    }
+
+    /// 从 metadata 中提取客户端显式提供的会话提示
+    pub fn metadata_session_hint(&self) -> Option<&str> {
+        let metadata = self.metadata.as_ref()?.as_object()?;
+        METADATA_SESSION_KEYS.iter().find_map(|key| {
+            metadata
+                .get(*key)
+                .and_then(|v| v.as_str())
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+        })
+    }
 }
 
 /// Thinking 配置 (兼容 Anthropic 和 OpenAI 扩展协议)
@@ -54,6 +106,21 @@ pub struct ThinkingConfig {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ResponseFormat {
     pub r#type: String,
+    /// `type: "json_schema"` 时的 schema 定义
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub json_schema: Option<JsonSchemaFormat>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonSchemaFormat {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub schema: Option<Value>,
+    #[serde(default)]
+    pub strict: Option<bool>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -106,6 +173,9 @@ pub struct ToolCall {
     pub id: String,
     pub r#type: String,
     pub function: ToolFunction,
+    /// strict 工具的参数未通过原始 schema 校验时的错误说明 (仅响应中出现)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub validation_error: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
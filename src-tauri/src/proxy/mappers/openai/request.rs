@@ -72,6 +72,13 @@ pub fn transform_openai_request(
         tracing::warn!("[OpenAI-Thinking] Incompatible assistant history detected for Claude thinking model without global signature. Disabling thinking for this request to avoid 400 error.");
         actual_include_thinking = false;
     }
+    // 能力表已知该模型拒绝 thinking (上游学习到或用户固定) 时不携带 thinkingConfig
+    if actual_include_thinking
+        && crate::proxy::common::thinking_capability::is_known_unsupported(mapped_model)
+    {
+        tracing::debug!("[OpenAI-Thinking] {} is known not to support thinking, omitting thinkingConfig", mapped_model);
+        actual_include_thinking = false;
+    }
     
     // [NEW] 日志：用户显式设置 thinking
     if user_enabled_thinking {
@@ -399,8 +406,8 @@ pub fn transform_openai_request(
     });
 
     // [FIX] 移除默认的 81920 maxOutputTokens，防止非思维模型 (如 claude-sonnet-4-5) 报 400 Invalid Argument
-    // 仅在用户显式提供时设置
-    if let Some(max_tokens) = request.max_tokens {
+    // 仅在用户显式提供时设置 (max_completion_tokens 优先, 与 OpenAI 语义一致)
+    if let Some(max_tokens) = request.max_completion_tokens.or(request.max_tokens) {
          gen_config["maxOutputTokens"] = json!(max_tokens);
     }
 
@@ -480,10 +487,13 @@ pub fn transform_openai_request(
         }
     }
 
-    if let Some(fmt) = &request.response_format {
-        if fmt.r#type == "json_object" {
-            gen_config["responseMimeType"] = json!("application/json");
-        }
+    // 结构化输出 (strict schema 的有损降级已在 handler 中拒绝)
+    if let Some(output) = request
+        .response_format
+        .as_ref()
+        .and_then(super::response_format::convert_response_format)
+    {
+        output.apply(&mut gen_config);
     }
 
     let mut inner_request = json!({
@@ -634,6 +644,7 @@ pub fn transform_openai_request(
             if let Some(gen_obj) = gen_config.as_object_mut() {
                 gen_obj.remove("thinkingConfig");
                 gen_obj.remove("responseMimeType");
+                gen_obj.remove("responseSchema");
                 gen_obj.remove("responseModalities");
                 gen_obj.insert("imageConfig".to_string(), image_config);
             }
@@ -697,8 +708,10 @@ mod tests {
                 name: None,
             }],
             stream: false,
+            stream_options: None,
             n: None,
             max_tokens: None,
+            max_completion_tokens: None,
             temperature: None,
             top_p: None,
             stop: None,
@@ -713,6 +726,8 @@ mod tests {
             quality: None,
             person_generation: None,
             thinking: None,
+            metadata: None,
+            passthrough: Default::default(),
         };
 
         let result = transform_openai_request(&req, "test-v", "gemini-1.5-flash");
@@ -737,8 +752,10 @@ mod tests {
                 name: None,
             }],
             stream: false,
+            stream_options: None,
             n: None,
             max_tokens: None,
+            max_completion_tokens: None,
             temperature: None,
             top_p: None,
             stop: None,
@@ -753,6 +770,8 @@ mod tests {
             quality: None,
             person_generation: None,
             thinking: None,
+            metadata: None,
+            passthrough: Default::default(),
         };
 
         let result = transform_openai_request(&req, "test-p", "gemini-2.0-pro-high-thinking");
@@ -779,8 +798,10 @@ mod tests {
                 name: None,
             }],
             stream: false,
+            stream_options: None,
             n: None,
             max_tokens: None,
+            max_completion_tokens: None,
             temperature: None,
             top_p: None,
             stop: None,
@@ -798,6 +819,8 @@ mod tests {
                 thinking_type: Some("enabled".to_string()),
                 budget_tokens: Some(16000),
             }),
+            metadata: None,
+            passthrough: Default::default(),
         };
 
         let result = transform_openai_request(&req, "test-p", "gemini-3-pro-preview");
@@ -813,4 +836,96 @@ mod tests {
             .unwrap();
         assert_eq!(budget, 16000);
     }
+
+    // openai-python SDK (client.chat.completions.create) 的典型请求体
+    fn sdk_chat_request() -> Value {
+        serde_json::json!({
+            "model": "gpt-4o",
+            "messages": [
+                {"role": "developer", "content": "You are a terse assistant."},
+                {"role": "user", "content": "Summarize the plot of Hamlet."}
+            ],
+            "stream": false,
+            "store": true,
+            "metadata": {"session_id": "sess-42", "team": "docs"},
+            "modalities": ["text", "audio"],
+            "audio": {"voice": "alloy", "format": "wav"},
+            "prediction": {"type": "content", "content": "Hamlet is a prince..."},
+            "max_completion_tokens": 512,
+            "service_tier": "auto"
+        })
+    }
+
+    #[test]
+    fn test_sdk_request_captures_unknown_fields() {
+        let req: OpenAIRequest = serde_json::from_value(sdk_chat_request()).unwrap();
+
+        assert_eq!(
+            req.ignored_fields(),
+            vec![
+                "audio",
+                "modalities",
+                "prediction",
+                "service_tier",
+                "store"
+            ]
+        );
+        assert_eq!(req.metadata_session_hint(), Some("sess-42"));
+        assert_eq!(req.messages.len(), 2);
+    }
+
+    #[test]
+    fn test_sdk_request_developer_role_and_passthrough_not_forwarded() {
+        let req: OpenAIRequest = serde_json::from_value(sdk_chat_request()).unwrap();
+        let result = transform_openai_request(&req, "test-p", "gemini-2.5-flash");
+
+        let system_text = result["request"]["systemInstruction"]["parts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|p| p["text"].as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert!(system_text.contains("You are a terse assistant."));
+
+        let contents = result["request"]["contents"].as_array().unwrap();
+        assert_eq!(contents.len(), 1);
+        assert_eq!(contents[0]["role"], "user");
+
+        let body = result.to_string();
+        for field in ["prediction", "modalities", "\"store\"", "\"audio\""] {
+            assert!(!body.contains(field), "{} leaked upstream", field);
+        }
+    }
+
+    #[test]
+    fn test_max_completion_tokens_maps_to_max_output_tokens() {
+        let req: OpenAIRequest = serde_json::from_value(sdk_chat_request()).unwrap();
+        let result = transform_openai_request(&req, "test-p", "gemini-2.5-flash");
+        assert_eq!(result["request"]["generationConfig"]["maxOutputTokens"], 512);
+
+        // 两者同时出现时以 max_completion_tokens 为准
+        let req: OpenAIRequest = serde_json::from_value(serde_json::json!({
+            "model": "gpt-4o",
+            "messages": [{"role": "user", "content": "hi"}],
+            "max_tokens": 4096,
+            "max_completion_tokens": 256
+        }))
+        .unwrap();
+        let result = transform_openai_request(&req, "test-p", "gemini-2.5-flash");
+        assert_eq!(result["request"]["generationConfig"]["maxOutputTokens"], 256);
+    }
+
+    #[test]
+    fn test_minimal_request_has_no_ignored_fields() {
+        let req: OpenAIRequest = serde_json::from_value(serde_json::json!({
+            "model": "gpt-4o-mini",
+            "messages": [{"role": "user", "content": "hi"}],
+            "temperature": 0.2
+        }))
+        .unwrap();
+
+        assert!(req.ignored_fields().is_empty());
+        assert!(req.metadata_session_hint().is_none());
+    }
 }
@@ -1,5 +1,6 @@
 // OpenAI 协议响应转换模块
 use super::models::*;
+use crate::proxy::mappers::candidates::candidate_index;
 use serde_json::Value;
 
 pub fn transform_openai_response(gemini_response: &Value) -> OpenAIResponse {
@@ -10,7 +11,7 @@ pub fn transform_openai_response(gemini_response: &Value) -> OpenAIResponse {
 
     // 支持多候选结果 (n > 1)
     if let Some(candidates) = raw.get("candidates").and_then(|c| c.as_array()) {
-        for (idx, candidate) in candidates.iter().enumerate() {
+        for (position, candidate) in candidates.iter().enumerate() {
             let mut content_out = String::new();
             let mut thought_out = String::new();
             let mut tool_calls = Vec::new();
@@ -59,6 +60,7 @@ pub fn transform_openai_response(gemini_response: &Value) -> OpenAIResponse {
                                 name: name.to_string(),
                                 arguments: args,
                             },
+                            validation_error: None,
                         });
                     }
 
@@ -130,7 +132,7 @@ pub fn transform_openai_response(gemini_response: &Value) -> OpenAIResponse {
                 .unwrap_or("stop");
 
             choices.push(Choice {
-                index: idx as u32,
+                index: candidate_index(candidate, position),
                 message: OpenAIMessage {
                     role: "assistant".to_string(),
                     content: if content_out.is_empty() {
@@ -232,6 +234,26 @@ mod tests {
         assert_eq!(result.choices[0].finish_reason, Some("stop".to_string()));
     }
 
+    #[test]
+    fn test_multi_candidate_maps_to_choices_by_index() {
+        let gemini_resp = json!({
+            "candidates": [
+                { "index": 1, "content": { "parts": [{ "text": "Second" }] }, "finishReason": "STOP" },
+                { "index": 0, "content": { "parts": [{ "text": "First" }] }, "finishReason": "STOP" }
+            ]
+        });
+
+        let result = transform_openai_response(&gemini_resp);
+        assert_eq!(result.choices.len(), 2);
+        for choice in &result.choices {
+            let expected = if choice.index == 0 { "First" } else { "Second" };
+            match choice.message.content.as_ref().unwrap() {
+                OpenAIContent::String(s) => assert_eq!(s, expected),
+                _ => panic!("Expected string content"),
+            }
+        }
+    }
+
     #[test]
     fn test_usage_metadata_mapping() {
         let gemini_resp = json!({
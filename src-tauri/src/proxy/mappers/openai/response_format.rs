@@ -0,0 +1,315 @@
+// OpenAI 结构化输出 (response_format)
+// json_object -> generationConfig.responseMimeType = application/json;
+// json_schema -> responseMimeType + responseSchema。Gemini 的 responseSchema 只支持 OpenAPI 子集,
+// 无法表示的关键字 ($ref / anyOf / allOf / 额外属性 schema 等) 会被去掉并通过 `X-Schema-Lossy` 告知调用方;
+// strict: true 时不做有损降级, 直接返回 400。
+
+use serde_json::{json, Map, Value};
+
+use super::models::ResponseFormat;
+
+/// 响应头: schema 转换有损 (部分关键字被去掉)
+pub const SCHEMA_LOSSY_HEADER: &str = "X-Schema-Lossy";
+
+/// Gemini responseSchema 原样保留的关键字
+const SUPPORTED_KEYWORDS: [&str; 12] = [
+    "type",
+    "format",
+    "description",
+    "nullable",
+    "enum",
+    "properties",
+    "required",
+    "items",
+    "minItems",
+    "maxItems",
+    "minimum",
+    "maximum",
+];
+
+/// 去掉后不影响语义的关键字 (Gemini 的对象本就不含额外字段)
+const IGNORED_KEYWORDS: [&str; 3] = ["$schema", "title", "strict"];
+
+/// response_format 转换结果
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructuredOutput {
+    pub schema: Option<Value>,
+    /// 被去掉的不支持关键字 (按出现顺序, 去重)
+    pub dropped: Vec<String>,
+    pub strict: bool,
+}
+
+impl StructuredOutput {
+    pub fn is_lossy(&self) -> bool {
+        !self.dropped.is_empty()
+    }
+
+    /// strict schema 无法完整表示时的错误信息
+    pub fn strict_error(&self) -> Option<String> {
+        (self.strict && self.is_lossy()).then(|| {
+            format!(
+                "response_format.json_schema is strict but uses keywords the upstream model cannot enforce: {}",
+                self.dropped.join(", ")
+            )
+        })
+    }
+
+    /// 写入 generationConfig
+    pub fn apply(&self, gen_config: &mut Value) {
+        gen_config["responseMimeType"] = json!("application/json");
+        if let Some(schema) = &self.schema {
+            gen_config["responseSchema"] = schema.clone();
+        }
+    }
+}
+
+/// 解析 response_format; text 或未知类型返回 None
+pub fn convert_response_format(format: &ResponseFormat) -> Option<StructuredOutput> {
+    match format.r#type.as_str() {
+        "json_object" => Some(StructuredOutput {
+            schema: None,
+            dropped: Vec::new(),
+            strict: false,
+        }),
+        "json_schema" => {
+            let spec = format.json_schema.as_ref();
+            let mut dropped = Vec::new();
+            let schema = spec
+                .and_then(|s| s.schema.as_ref())
+                .map(|schema| to_gemini_schema(schema, &mut dropped));
+            Some(StructuredOutput {
+                schema,
+                dropped,
+                strict: spec.and_then(|s| s.strict).unwrap_or(false),
+            })
+        }
+        _ => None,
+    }
+}
+
+fn note_dropped(dropped: &mut Vec<String>, keyword: &str) {
+    if !dropped.iter().any(|k| k == keyword) {
+        dropped.push(keyword.to_string());
+    }
+}
+
+/// `[T, null]` 形式的可空类型, 返回 T
+fn nullable_variant(options: &[Value]) -> Option<&Value> {
+    if options.len() != 2 {
+        return None;
+    }
+    let is_null = |v: &Value| v.get("type").and_then(Value::as_str) == Some("null");
+    match (is_null(&options[0]), is_null(&options[1])) {
+        (true, false) => Some(&options[1]),
+        (false, true) => Some(&options[0]),
+        _ => None,
+    }
+}
+
+/// 将 JSON Schema 转为 Gemini responseSchema (类型大写), 记录被去掉的关键字
+fn to_gemini_schema(schema: &Value, dropped: &mut Vec<String>) -> Value {
+    let Some(map) = schema.as_object() else {
+        return schema.clone();
+    };
+
+    // anyOf / oneOf 只有 "T 或 null" 时可以无损表示为 nullable
+    for union_key in ["anyOf", "oneOf"] {
+        if let Some(variant) = map.get(union_key).and_then(Value::as_array).and_then(|o| nullable_variant(o.as_slice())) {
+            let mut merged = map.clone();
+            merged.remove(union_key);
+            if let Some(inner) = variant.as_object() {
+                for (k, v) in inner {
+                    merged.entry(k.clone()).or_insert_with(|| v.clone());
+                }
+            }
+            merged.insert("nullable".to_string(), Value::Bool(true));
+            return to_gemini_schema(&Value::Object(merged), dropped);
+        }
+    }
+
+    let mut out = Map::new();
+    for (key, value) in map {
+        match key.as_str() {
+            "type" => match value {
+                Value::String(t) => {
+                    out.insert(key.clone(), Value::String(t.to_uppercase()));
+                }
+                Value::Array(types) => {
+                    let non_null: Vec<&str> = types.iter().filter_map(Value::as_str).filter(|t| *t != "null").collect();
+                    if non_null.len() == 1 {
+                        out.insert(key.clone(), Value::String(non_null[0].to_uppercase()));
+                        if non_null.len() < types.len() {
+                            out.insert("nullable".to_string(), Value::Bool(true));
+                        }
+                    } else {
+                        note_dropped(dropped, "type (union)");
+                    }
+                }
+                _ => note_dropped(dropped, "type"),
+            },
+            "properties" => {
+                let props: Map<String, Value> = value
+                    .as_object()
+                    .into_iter()
+                    .flatten()
+                    .map(|(name, prop)| (name.clone(), to_gemini_schema(prop, dropped)))
+                    .collect();
+                out.insert(key.clone(), Value::Object(props));
+            }
+            "items" => {
+                out.insert(key.clone(), to_gemini_schema(value, dropped));
+            }
+            "additionalProperties" if value == &Value::Bool(false) => {}
+            k if SUPPORTED_KEYWORDS.contains(&k) => {
+                out.insert(key.clone(), value.clone());
+            }
+            k if IGNORED_KEYWORDS.contains(&k) => {}
+            _ => note_dropped(dropped, key),
+        }
+    }
+    Value::Object(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::models::JsonSchemaFormat;
+
+    fn json_schema_format(schema: Value, strict: bool) -> ResponseFormat {
+        ResponseFormat {
+            r#type: "json_schema".to_string(),
+            json_schema: Some(JsonSchemaFormat {
+                name: Some("result".to_string()),
+                description: None,
+                schema: Some(schema),
+                strict: Some(strict),
+            }),
+        }
+    }
+
+    /// 反向转换 (类型小写, nullable 还原为 null 联合), 用于往返比较
+    fn to_json_schema(schema: &Value) -> Value {
+        let Some(map) = schema.as_object() else {
+            return schema.clone();
+        };
+        let mut out = Map::new();
+        let nullable = map.get("nullable") == Some(&Value::Bool(true));
+        for (key, value) in map {
+            match key.as_str() {
+                "type" => {
+                    let t = Value::String(value.as_str().unwrap().to_lowercase());
+                    out.insert(key.clone(), if nullable { json!([t, "null"]) } else { t });
+                }
+                "nullable" => {}
+                "properties" => {
+                    let props = value.as_object().unwrap().iter().map(|(k, v)| (k.clone(), to_json_schema(v))).collect();
+                    out.insert(key.clone(), Value::Object(props));
+                }
+                "items" => {
+                    out.insert(key.clone(), to_json_schema(value));
+                }
+                _ => {
+                    out.insert(key.clone(), value.clone());
+                }
+            }
+        }
+        Value::Object(out)
+    }
+
+    fn without_additional_properties(schema: &mut Value) {
+        if let Some(map) = schema.as_object_mut() {
+            map.remove("additionalProperties");
+            map.values_mut().for_each(without_additional_properties);
+        }
+    }
+
+    #[test]
+    fn test_nested_schema_round_trip() {
+        let original = json!({
+            "type": "object",
+            "properties": {
+                "title": { "type": "string", "description": "Short title" },
+                "status": { "type": "string", "enum": ["open", "closed"] },
+                "owner": {
+                    "type": "object",
+                    "properties": {
+                        "name": { "type": "string" },
+                        "email": { "type": ["string", "null"] }
+                    },
+                    "required": ["name", "email"],
+                    "additionalProperties": false
+                },
+                "tasks": {
+                    "type": "array",
+                    "minItems": 1,
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "step": { "type": "integer" },
+                            "done": { "type": "boolean" }
+                        },
+                        "required": ["step", "done"],
+                        "additionalProperties": false
+                    }
+                }
+            },
+            "required": ["title", "status", "owner", "tasks"],
+            "additionalProperties": false
+        });
+
+        let output = convert_response_format(&json_schema_format(original.clone(), true)).unwrap();
+        assert!(!output.is_lossy(), "{:?}", output.dropped);
+        assert!(output.strict_error().is_none());
+
+        let gemini = output.schema.clone().unwrap();
+        assert_eq!(gemini["type"], "OBJECT");
+        assert_eq!(gemini["properties"]["tasks"]["items"]["properties"]["step"]["type"], "INTEGER");
+        assert_eq!(gemini["properties"]["owner"]["properties"]["email"], json!({ "type": "STRING", "nullable": true }));
+        assert_eq!(gemini["properties"]["status"]["enum"], json!(["open", "closed"]));
+        assert_eq!(gemini["required"], json!(["title", "status", "owner", "tasks"]));
+
+        let mut expected = original;
+        without_additional_properties(&mut expected);
+        assert_eq!(to_json_schema(&gemini), expected);
+
+        let mut gen_config = json!({ "maxOutputTokens": 1024 });
+        output.apply(&mut gen_config);
+        assert_eq!(gen_config["responseMimeType"], "application/json");
+        assert_eq!(gen_config["responseSchema"], gemini);
+    }
+
+    #[test]
+    fn test_unsupported_keywords_are_lossy() {
+        let schema = json!({
+            "type": "object",
+            "$defs": { "item": { "type": "string" } },
+            "properties": {
+                "item": { "$ref": "#/$defs/item" },
+                "value": { "anyOf": [{ "type": "string" }, { "type": "integer" }] },
+                "maybe": { "anyOf": [{ "type": "number" }, { "type": "null" }] }
+            }
+        });
+
+        let lenient = convert_response_format(&json_schema_format(schema.clone(), false)).unwrap();
+        assert_eq!(lenient.dropped, ["$defs", "$ref", "anyOf"]);
+        assert!(lenient.strict_error().is_none());
+        let gemini = lenient.schema.unwrap();
+        assert_eq!(gemini["properties"]["maybe"], json!({ "type": "NUMBER", "nullable": true }));
+        assert_eq!(gemini["properties"]["item"], json!({}));
+
+        let strict = convert_response_format(&json_schema_format(schema, true)).unwrap();
+        assert!(strict.strict_error().unwrap().contains("$ref"));
+    }
+
+    #[test]
+    fn test_json_object_and_text() {
+        let json_object = ResponseFormat { r#type: "json_object".to_string(), json_schema: None };
+        let output = convert_response_format(&json_object).unwrap();
+        let mut gen_config = json!({});
+        output.apply(&mut gen_config);
+        assert_eq!(gen_config, json!({ "responseMimeType": "application/json" }));
+
+        let text = ResponseFormat { r#type: "text".to_string(), json_schema: None };
+        assert!(convert_response_format(&text).is_none());
+    }
+}
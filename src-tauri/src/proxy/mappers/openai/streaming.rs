@@ -8,6 +8,8 @@ use std::pin::Pin;
 use tracing::debug;
 use uuid::Uuid;
 
+use crate::proxy::mappers::candidates::{candidate_index, primary_candidate};
+
 pub fn store_thought_signature(sig: &str, session_id: &str, message_count: usize) {
     if sig.len() < 50 {
         return;
@@ -52,11 +54,14 @@ fn extract_usage_metadata(u: &Value) -> Option<super::models::OpenAIUsage> {
     })
 }
 
+/// `include_usage`: 客户端传入 `stream_options.include_usage`, usage 不再嵌入 finish chunk,
+/// 而是在 `[DONE]` 之前单独发送一个 `choices: []` 的 chunk
 pub fn create_openai_sse_stream(
     mut gemini_stream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>,
     model: String,
     session_id: String,
     message_count: usize,
+    include_usage: bool,
 ) -> Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>> {
     let mut buffer = BytesMut::new();
 
@@ -114,7 +119,9 @@ pub fn create_openai_sse_stream(
 
                                     // Extract candidates
                                     if let Some(candidates) = actual_data.get("candidates").and_then(|c| c.as_array()) {
-                                        for (idx, candidate) in candidates.iter().enumerate() {
+                                        for (position, candidate) in candidates.iter().enumerate() {
+                                            // 按候选自带的 index 映射 choice, 分片中可能只含部分候选
+                                            let idx = candidate_index(candidate, position);
                                             let parts = candidate.get("content").and_then(|c| c.get("parts")).and_then(|p| p.as_array());
 
                                             let mut content_out = String::new();
@@ -168,7 +175,7 @@ pub fn create_openai_sse_stream(
                                                                 "created": created_ts,
                                                                 "model": &model,
                                                                 "choices": [{
-                                                                    "index": idx as u32,
+                                                                    "index": idx,
                                                                     "delta": {
                                                                         "role": "assistant",
                                                                         "tool_calls": [{
@@ -256,7 +263,7 @@ pub fn create_openai_sse_stream(
                                                     "model": model,
                                                     "choices": [
                                                         {
-                                                            "index": idx as u32,
+                                                            "index": idx,
                                                             "delta": {
                                                                 "role": "assistant",
                                                                 "content": serde_json::Value::Null,
@@ -279,7 +286,7 @@ pub fn create_openai_sse_stream(
                                                     "model": model,
                                                     "choices": [
                                                         {
-                                                            "index": idx as u32,
+                                                            "index": idx,
                                                             "delta": {
                                                                 "content": content_out
                                                             },
@@ -288,16 +295,18 @@ pub fn create_openai_sse_stream(
                                                     ]
                                                 });
 
-                                                // [FIX] 将 usage 嵌入到 chunk 中
-                                                if let Some(ref usage) = final_usage {
-                                                     if let Ok(val) = serde_json::to_value(usage) {
-                                                         openai_chunk["usage"] = val;
-                                                     }
-                                                }
+                                                // [FIX] 将 usage 嵌入到 chunk 中 (include_usage 时改为末尾单独发送)
+                                                if !include_usage {
+                                                    if let Some(ref usage) = final_usage {
+                                                         if let Ok(val) = serde_json::to_value(usage) {
+                                                             openai_chunk["usage"] = val;
+                                                         }
+                                                    }
 
-                                                // [FIX] 如果是最后一个 chunk,标记 usage 已发送
-                                                if finish_reason.is_some() {
-                                                    final_usage = None;
+                                                    // [FIX] 如果是最后一个 chunk,标记 usage 已发送
+                                                    if finish_reason.is_some() {
+                                                        final_usage = None;
+                                                    }
                                                 }
 
                                                 let sse_out = format!("data: {}\n\n", serde_json::to_string(&openai_chunk).unwrap_or_default());
@@ -359,8 +368,27 @@ pub fn create_openai_sse_stream(
         }
 
         // [FIX] 只有在没有错误时才发送 [DONE]
-        // usage 已经嵌入到 finish_reason chunk,不需要单独发送
+        // 未请求 include_usage 时 usage 已经嵌入到 finish_reason chunk,不需要单独发送
         if !error_occurred {
+            if include_usage {
+                let usage = final_usage.unwrap_or(super::models::OpenAIUsage {
+                    prompt_tokens: 0,
+                    completion_tokens: 0,
+                    total_tokens: 0,
+                    prompt_tokens_details: None,
+                    completion_tokens_details: None,
+                });
+                let usage_chunk = json!({
+                    "id": &stream_id,
+                    "object": "chat.completion.chunk",
+                    "created": created_ts,
+                    "model": &model,
+                    "choices": [],
+                    "usage": usage
+                });
+                let sse_out = format!("data: {}\n\n", serde_json::to_string(&usage_chunk).unwrap_or_default());
+                yield Ok::<Bytes, String>(Bytes::from(sse_out));
+            }
             yield Ok::<Bytes, String>(Bytes::from("data: [DONE]\n\n"));
         }
     };
@@ -390,6 +418,7 @@ pub fn create_legacy_sse_stream(
 
     let stream = async_stream::stream! {
         let mut final_usage: Option<super::models::OpenAIUsage> = None;
+        let mut extra_candidates_warned = false;
         let mut error_occurred = false;  // [FIX] 标志位,避免双重 [DONE]
 
         // [P2 FIX] 添加心跳定时器
@@ -421,29 +450,28 @@ pub fn create_legacy_sse_stream(
                                         final_usage = extract_usage_metadata(u);
                                     }
 
+                                    // 多候选时只取 index 0
+                                    let candidate = primary_candidate(&actual_data, &mut extra_candidates_warned);
+
                                     let mut content_out = String::new();
-                                    if let Some(candidates) = actual_data.get("candidates").and_then(|c| c.as_array()) {
-                                        if let Some(parts) = candidates.get(0).and_then(|c| c.get("content")).and_then(|c| c.get("parts")).and_then(|p| p.as_array()) {
-                                            for part in parts {
-                                                if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
-                                                    content_out.push_str(text);
-                                                }
-                                                /* 禁用思维链输出到正文
-                                                if let Some(thought_text) = part.get("thought").and_then(|t| t.as_str()) {
-                                                    // // content_out.push_str(thought_text);
-                                                }
-                                                */
-                                                // Capture thoughtSignature into session cache
-                                                if let Some(sig) = part.get("thoughtSignature").or(part.get("thought_signature")).and_then(|s| s.as_str()) {
-                                                    store_thought_signature(sig, &session_id, message_count);
-                                                }
+                                    if let Some(parts) = candidate.and_then(|c| c.get("content")).and_then(|c| c.get("parts")).and_then(|p| p.as_array()) {
+                                        for part in parts {
+                                            if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
+                                                content_out.push_str(text);
+                                            }
+                                            /* 禁用思维链输出到正文
+                                            if let Some(thought_text) = part.get("thought").and_then(|t| t.as_str()) {
+                                                // // content_out.push_str(thought_text);
+                                            }
+                                            */
+                                            // Capture thoughtSignature into session cache
+                                            if let Some(sig) = part.get("thoughtSignature").or(part.get("thought_signature")).and_then(|s| s.as_str()) {
+                                                store_thought_signature(sig, &session_id, message_count);
                                             }
                                         }
                                     }
 
-                                    let finish_reason = actual_data.get("candidates")
-                                        .and_then(|c| c.as_array())
-                                        .and_then(|c| c.get(0))
+                                    let finish_reason = candidate
                                         .and_then(|c| c.get("finishReason"))
                                         .and_then(|f| f.as_str())
                                         .map(|f| match f {
@@ -586,6 +614,7 @@ pub fn create_codex_sse_stream(
         let mut emitted_tool_calls = std::collections::HashSet::new();
         let mut last_finish_reason = "stop".to_string();
         let mut accumulated_usage: Option<super::models::OpenAIUsage> = None;
+        let mut extra_candidates_warned = false;
 
         // [P2 FIX] Add heartbeat interval for Codex stream
         let mut heartbeat_interval = tokio::time::interval(std::time::Duration::from_secs(15));
@@ -620,125 +649,124 @@ pub fn create_codex_sse_stream(
                                             accumulated_usage = extract_usage_metadata(u);
                                         }
 
+                                        // 多候选时只取 index 0
+                                        let primary = primary_candidate(&actual_data, &mut extra_candidates_warned);
+
                                         // Capture finish reason
-                                        if let Some(candidates) = actual_data.get("candidates").and_then(|c| c.as_array()) {
-                                            if let Some(candidate) = candidates.get(0) {
-                                                if let Some(reason) = candidate.get("finishReason").and_then(|r| r.as_str()) {
-                                                    last_finish_reason = match reason {
-                                                        "STOP" => "stop".to_string(),
-                                                        "MAX_TOKENS" => "length".to_string(),
-                                                        _ => "stop".to_string(),
-                                                    };
-                                                }
+                                        if let Some(candidate) = primary {
+                                            if let Some(reason) = candidate.get("finishReason").and_then(|r| r.as_str()) {
+                                                last_finish_reason = match reason {
+                                                    "STOP" => "stop".to_string(),
+                                                    "MAX_TOKENS" => "length".to_string(),
+                                                    _ => "stop".to_string(),
+                                                };
                                             }
                                         }
 
                                         // text delta
                                         let mut delta_text = String::new();
-                                        if let Some(candidates) = actual_data.get("candidates").and_then(|c| c.as_array()) {
-                                            if let Some(candidate) = candidates.get(0) {
-                                                if let Some(parts) = candidate.get("content").and_then(|c| c.get("parts")).and_then(|p| p.as_array()) {
-                                                    for part in parts {
-                                                        if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
-                                                            let clean_text = text.replace('“', "\"").replace('”', "\"");
-                                                            delta_text.push_str(&clean_text);
-                                                        }
+                                        if let Some(candidate) = primary {
+                                            if let Some(parts) = candidate.get("content").and_then(|c| c.get("parts")).and_then(|p| p.as_array()) {
+                                                for part in parts {
+                                                    if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
+                                                        let clean_text = text.replace('“', "\"").replace('”', "\"");
+                                                        delta_text.push_str(&clean_text);
+                                                    }
 
-                                                        // 捕获 thoughtSignature
-                                                        if let Some(sig) = part.get("thoughtSignature").or(part.get("thought_signature")).and_then(|s| s.as_str()) {
-                                                            tracing::debug!("[Codex-SSE] 捕获 thoughtSignature (长度: {})", sig.len());
-                                                            store_thought_signature(sig, &session_id, message_count);
-                                                        }
+                                                    // 捕获 thoughtSignature
+                                                    if let Some(sig) = part.get("thoughtSignature").or(part.get("thought_signature")).and_then(|s| s.as_str()) {
+                                                        tracing::debug!("[Codex-SSE] 捕获 thoughtSignature (长度: {})", sig.len());
+                                                        store_thought_signature(sig, &session_id, message_count);
+                                                    }
 
-                                                        // Handle function call in chunk with deduplication
-                                                        if let Some(func_call) = part.get("functionCall") {
-                                                            let call_key = serde_json::to_string(func_call).unwrap_or_default();
-                                                            if !emitted_tool_calls.contains(&call_key) {
-                                                                emitted_tool_calls.insert(call_key);
-
-                                                                let name = func_call.get("name").and_then(|v| v.as_str()).unwrap_or("unknown");
-                                                                let name_str = name.to_string();
-
-                                                                let fallback_args = json!({});
-                                                                let args_obj = func_call.get("args").unwrap_or(&fallback_args);
-                                                                let args_str = args_obj.to_string();
-
-                                                                // Use content-based hash for call_id
-                                                                let mut hasher = std::collections::hash_map::DefaultHasher::new();
-                                                                use std::hash::{Hash, Hasher};
-                                                                name_str.hash(&mut hasher);
-                                                                args_str.hash(&mut hasher);
-                                                                let call_id = format!("call_{:x}", hasher.finish());
-
-                                                                // Determine event type based on tool name
-                                                                let maybe_item_added_ev: Option<Value> = if name_str == "shell" || name_str == "local_shell" {
-                                                                    // Map to local_shell_call
-                                                                    let cmd_vec: Vec<String> = if args_obj.as_object().map(|o| o.is_empty()).unwrap_or(true) {
-                                                                        vec!["powershell.exe".to_string(), "-Command".to_string(), "exit 0".to_string()]
-                                                                    } else if let Some(arr) = args_obj.get("command").and_then(|v| v.as_array()) {
-                                                                        arr.iter().filter_map(|v| v.as_str()).map(|s| s.to_string()).collect()
-                                                                    } else if let Some(cmd_str) = args_obj.get("command").and_then(|v| v.as_str()) {
-                                                                        if cmd_str.contains(' ') {
-                                                                            vec!["powershell.exe".to_string(), "-Command".to_string(), cmd_str.to_string()]
-                                                                        } else {
-                                                                            vec![cmd_str.to_string()]
-                                                                        }
+                                                    // Handle function call in chunk with deduplication
+                                                    if let Some(func_call) = part.get("functionCall") {
+                                                        let call_key = serde_json::to_string(func_call).unwrap_or_default();
+                                                        if !emitted_tool_calls.contains(&call_key) {
+                                                            emitted_tool_calls.insert(call_key);
+
+                                                            let name = func_call.get("name").and_then(|v| v.as_str()).unwrap_or("unknown");
+                                                            let name_str = name.to_string();
+
+                                                            let fallback_args = json!({});
+                                                            let args_obj = func_call.get("args").unwrap_or(&fallback_args);
+                                                            let args_str = args_obj.to_string();
+
+                                                            // Use content-based hash for call_id
+                                                            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+                                                            use std::hash::{Hash, Hasher};
+                                                            name_str.hash(&mut hasher);
+                                                            args_str.hash(&mut hasher);
+                                                            let call_id = format!("call_{:x}", hasher.finish());
+
+                                                            // Determine event type based on tool name
+                                                            let maybe_item_added_ev: Option<Value> = if name_str == "shell" || name_str == "local_shell" {
+                                                                // Map to local_shell_call
+                                                                let cmd_vec: Vec<String> = if args_obj.as_object().map(|o| o.is_empty()).unwrap_or(true) {
+                                                                    vec!["powershell.exe".to_string(), "-Command".to_string(), "exit 0".to_string()]
+                                                                } else if let Some(arr) = args_obj.get("command").and_then(|v| v.as_array()) {
+                                                                    arr.iter().filter_map(|v| v.as_str()).map(|s| s.to_string()).collect()
+                                                                } else if let Some(cmd_str) = args_obj.get("command").and_then(|v| v.as_str()) {
+                                                                    if cmd_str.contains(' ') {
+                                                                        vec!["powershell.exe".to_string(), "-Command".to_string(), cmd_str.to_string()]
                                                                     } else {
-                                                                        vec!["powershell.exe".to_string(), "-Command".to_string(), "exit 0".to_string()]
-                                                                    };
-
-                                                                    Some(json!({
-                                                                        "type": "response.output_item.added",
-                                                                        "item": {
-                                                                            "type": "local_shell_call",
-                                                                            "status": "in_progress",
-                                                                            "call_id": &call_id,
-                                                                            "action": {
-                                                                                "type": "exec",
-                                                                                "command": cmd_vec
-                                                                            }
-                                                                        }
-                                                                    }))
-                                                                } else if name_str == "googleSearch" || name_str == "web_search" || name_str == "google_search" {
-                                                                    // Map to web_search_call
-                                                                    let query_val = args_obj.get("query").and_then(|v| v.as_str()).unwrap_or("");
-                                                                    Some(json!({
-                                                                        "type": "response.output_item.added",
-                                                                        "item": {
-                                                                            "type": "web_search_call",
-                                                                            "status": "in_progress",
-                                                                            "call_id": &call_id,
-                                                                            "action": {
-                                                                                "type": "search",
-                                                                                "query": query_val
-                                                                            }
-                                                                        }
-                                                                    }))
+                                                                        vec![cmd_str.to_string()]
+                                                                    }
                                                                 } else {
-                                                                    // Default function_call
-                                                                    Some(json!({
-                                                                        "type": "response.output_item.added",
-                                                                        "item": {
-                                                                            "type": "function_call",
-                                                                            "name": name,
-                                                                            "arguments": args_str,
-                                                                            "call_id": &call_id
-                                                                        }
-                                                                    }))
+                                                                    vec!["powershell.exe".to_string(), "-Command".to_string(), "exit 0".to_string()]
                                                                 };
 
-                                                                if let Some(item_added_ev) = maybe_item_added_ev {
-                                                                    let added_json = serde_json::to_string(&item_added_ev).unwrap_or_else(|_| "{}".to_string());
-                                                                    yield Ok::<Bytes, String>(Bytes::from(format!("data: {}\n\n", added_json)));
-
-                                                                    // Emit response.output_item.done
-                                                                    let mut item_done_ev = item_added_ev.clone();
-                                                                    if let Some(obj) = item_done_ev.as_object_mut() {
-                                                                        obj.insert("type".to_string(), json!("response.output_item.done"));
+                                                                Some(json!({
+                                                                    "type": "response.output_item.added",
+                                                                    "item": {
+                                                                        "type": "local_shell_call",
+                                                                        "status": "in_progress",
+                                                                        "call_id": &call_id,
+                                                                        "action": {
+                                                                            "type": "exec",
+                                                                            "command": cmd_vec
+                                                                        }
+                                                                    }
+                                                                }))
+                                                            } else if name_str == "googleSearch" || name_str == "web_search" || name_str == "google_search" {
+                                                                // Map to web_search_call
+                                                                let query_val = args_obj.get("query").and_then(|v| v.as_str()).unwrap_or("");
+                                                                Some(json!({
+                                                                    "type": "response.output_item.added",
+                                                                    "item": {
+                                                                        "type": "web_search_call",
+                                                                        "status": "in_progress",
+                                                                        "call_id": &call_id,
+                                                                        "action": {
+                                                                            "type": "search",
+                                                                            "query": query_val
+                                                                        }
+                                                                    }
+                                                                }))
+                                                            } else {
+                                                                // Default function_call
+                                                                Some(json!({
+                                                                    "type": "response.output_item.added",
+                                                                    "item": {
+                                                                        "type": "function_call",
+                                                                        "name": name,
+                                                                        "arguments": args_str,
+                                                                        "call_id": &call_id
                                                                     }
-                                                                    let done_json = serde_json::to_string(&item_done_ev).unwrap_or_else(|_| "{}".to_string());
-                                                                    yield Ok::<Bytes, String>(Bytes::from(format!("data: {}\n\n", done_json)));
+                                                                }))
+                                                            };
+
+                                                            if let Some(item_added_ev) = maybe_item_added_ev {
+                                                                let added_json = serde_json::to_string(&item_added_ev).unwrap_or_else(|_| "{}".to_string());
+                                                                yield Ok::<Bytes, String>(Bytes::from(format!("data: {}\n\n", added_json)));
+
+                                                                // Emit response.output_item.done
+                                                                let mut item_done_ev = item_added_ev.clone();
+                                                                if let Some(obj) = item_done_ev.as_object_mut() {
+                                                                    obj.insert("type".to_string(), json!("response.output_item.done"));
                                                                 }
+                                                                let done_json = serde_json::to_string(&item_done_ev).unwrap_or_else(|_| "{}".to_string());
+                                                                yield Ok::<Bytes, String>(Bytes::from(format!("data: {}\n\n", done_json)));
                                                             }
                                                         }
                                                     }
@@ -830,3 +858,137 @@ pub fn create_codex_sse_stream(
 
     Box::pin(stream)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 运行 OpenAI 流转换, 返回所有 data 事件 (跳过心跳注释)
+    async fn collect_events(include_usage: bool) -> Vec<String> {
+        let chunks = [
+            json!({ "response": { "candidates": [{ "content": { "parts": [{ "text": "Hel" }] }, "index": 0 }] } }),
+            json!({ "response": {
+                "candidates": [{ "content": { "parts": [{ "text": "lo" }] }, "finishReason": "STOP", "index": 0 }],
+                "usageMetadata": { "promptTokenCount": 12, "candidatesTokenCount": 5, "totalTokenCount": 17, "cachedContentTokenCount": 8 }
+            } }),
+        ];
+        let sse: Vec<Result<Bytes, reqwest::Error>> = chunks
+            .iter()
+            .map(|c| Ok(Bytes::from(format!("data: {}\n\n", c))))
+            .collect();
+        let stream = create_openai_sse_stream(
+            Box::pin(futures::stream::iter(sse)),
+            "gemini-2.5-flash".to_string(),
+            "session".to_string(),
+            1,
+            include_usage,
+        );
+        let bytes: Vec<Bytes> = stream.map(|item| item.unwrap()).collect().await;
+        let body = String::from_utf8(bytes.concat()).unwrap();
+        body.split("\n\n")
+            .filter_map(|event| event.strip_prefix("data: "))
+            .map(|data| data.to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_include_usage_emits_final_usage_chunk() {
+        let events = collect_events(true).await;
+        assert_eq!(events.last().map(String::as_str), Some("[DONE]"));
+
+        let chunks: Vec<Value> = events[..events.len() - 1]
+            .iter()
+            .map(|data| serde_json::from_str(data).unwrap())
+            .collect();
+        let (usage_chunk, content_chunks) = chunks.split_last().unwrap();
+        assert_eq!(usage_chunk["object"], "chat.completion.chunk");
+        assert_eq!(usage_chunk["choices"], json!([]));
+        assert_eq!(
+            usage_chunk["usage"],
+            json!({
+                "prompt_tokens": 12,
+                "completion_tokens": 5,
+                "total_tokens": 17,
+                "prompt_tokens_details": { "cached_tokens": 8 }
+            })
+        );
+        assert_eq!(content_chunks.len(), 2);
+        assert!(content_chunks.iter().all(|c| c.get("usage").is_none() && c["id"] == usage_chunk["id"]));
+        assert_eq!(content_chunks[1]["choices"][0]["finish_reason"], "stop");
+    }
+
+    /// 两个候选交替到达的上游流 (candidateCount 泄漏到上游时的形态)
+    fn two_candidate_stream() -> Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>> {
+        let chunks = [
+            json!({ "response": { "candidates": [
+                { "content": { "parts": [{ "text": "Hello" }] }, "index": 0 },
+                { "content": { "parts": [{ "text": "Bonjour" }] }, "index": 1 }
+            ] } }),
+            json!({ "response": { "candidates": [{ "content": { "parts": [{ "text": " le monde" }] }, "index": 1 }] } }),
+            json!({ "response": {
+                "candidates": [
+                    { "content": { "parts": [{ "text": " world" }] }, "finishReason": "STOP", "index": 0 },
+                    { "content": { "parts": [{ "text": "!" }] }, "finishReason": "STOP", "index": 1 }
+                ],
+                "usageMetadata": { "promptTokenCount": 4, "candidatesTokenCount": 6, "totalTokenCount": 10 }
+            } }),
+        ];
+        let sse: Vec<Result<Bytes, reqwest::Error>> = chunks
+            .iter()
+            .map(|c| Ok(Bytes::from(format!("data: {}\n\n", c))))
+            .collect();
+        Box::pin(futures::stream::iter(sse))
+    }
+
+    async fn collect_data(stream: Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>>) -> Vec<Value> {
+        let bytes: Vec<Bytes> = stream.map(|item| item.unwrap()).collect().await;
+        let body = String::from_utf8(bytes.concat()).unwrap();
+        body.split("\n\n")
+            .filter_map(|event| event.strip_prefix("data: "))
+            .filter_map(|data| serde_json::from_str(data).ok())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_legacy_stream_uses_only_primary_candidate() {
+        let before = crate::proxy::mappers::candidates::multi_candidate_responses();
+        let stream = create_legacy_sse_stream(two_candidate_stream(), "gemini-2.5-flash".to_string(), "session".to_string(), 1);
+        let chunks = collect_data(stream).await;
+
+        let text: String = chunks
+            .iter()
+            .filter_map(|c| c["choices"][0]["text"].as_str())
+            .collect();
+        assert_eq!(text, "Hello world");
+        assert_eq!(chunks.last().unwrap()["choices"][0]["finish_reason"], "stop");
+        assert!(crate::proxy::mappers::candidates::multi_candidate_responses() > before);
+    }
+
+    #[tokio::test]
+    async fn test_codex_stream_uses_only_primary_candidate() {
+        let stream = create_codex_sse_stream(two_candidate_stream(), "gemini-2.5-flash".to_string(), "session".to_string(), 1);
+        let events = collect_data(stream).await;
+
+        let deltas: String = events
+            .iter()
+            .filter(|e| e["type"] == "response.output_text.delta")
+            .filter_map(|e| e["delta"].as_str())
+            .collect();
+        assert_eq!(deltas, "Hello world");
+        let done = events
+            .iter()
+            .find(|e| e["type"] == "response.output_item.done")
+            .unwrap();
+        assert_eq!(done["item"]["content"][0]["text"], "Hello world");
+    }
+
+    #[tokio::test]
+    async fn test_usage_embedded_in_finish_chunk_by_default() {
+        let events = collect_events(false).await;
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[2], "[DONE]");
+        let finish: Value = serde_json::from_str(&events[1]).unwrap();
+        assert_eq!(finish["usage"]["prompt_tokens"], 12);
+        assert_eq!(finish["choices"][0]["finish_reason"], "stop");
+    }
+}
@@ -0,0 +1,483 @@
+// OpenAI strict 函数工具
+// 客户端以 `strict: true` 声明的函数, 其 schema 发给 Gemini 前必须清洗 (additionalProperties / $ref 等被移除),
+// 严格性随之丢失, 模型可能漏掉 required 字段。这里保留原始 schema: 响应后在本地按原始 schema 校验工具参数,
+// 违规时按配置重试或在调用上标注错误 (流式响应只能标注); 可选开启 Gemini 的 VALIDATED 函数调用模式。
+
+use bytes::Bytes;
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+use super::models::{OpenAIRequest, OpenAIResponse};
+use crate::proxy::config::StrictToolViolationMode;
+
+/// 各工具名的参数校验失败次数 (启动以来)
+static VIOLATIONS: Lazy<DashMap<String, u64>> = Lazy::new(DashMap::new);
+
+/// 嵌套 $ref 的最大展开深度 (防止自引用 schema 无限递归)
+const MAX_REF_DEPTH: usize = 16;
+
+/// 工具声明是否要求 strict (Chat Completions 的 function.strict 或 Responses 风格的根层级 strict)
+pub fn is_strict_tool(tool: &Value) -> bool {
+    let strict = tool
+        .get("function")
+        .and_then(|f| f.get("strict"))
+        .or_else(|| tool.get("strict"));
+    strict.and_then(|v| v.as_bool()).unwrap_or(false)
+}
+
+/// 一次工具调用的校验失败
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToolViolation {
+    pub choice_index: usize,
+    pub call_index: usize,
+    pub tool: String,
+    pub errors: Vec<String>,
+}
+
+impl ToolViolation {
+    fn summary(&self) -> String {
+        format!("{}: {}", self.tool, self.errors.join("; "))
+    }
+}
+
+/// 为请求开启 Gemini 的 VALIDATED 函数调用模式, 让上游按声明校验函数调用
+pub fn apply_validated_mode(body: &mut Value) {
+    if body["request"].get("tools").is_some() {
+        body["request"]["toolConfig"] = json!({ "functionCallingConfig": { "mode": "VALIDATED" } });
+    }
+}
+
+/// 上游因不支持 VALIDATED 函数调用模式而拒绝请求 (400)
+pub fn is_validated_mode_rejected(error_text: &str) -> bool {
+    let lower = error_text.to_ascii_lowercase();
+    lower.contains("validated")
+        || lower.contains("function_calling_config")
+        || lower.contains("functioncallingconfig")
+}
+
+fn record_violation(tool: &str, errors: &[String]) {
+    *VIOLATIONS.entry(tool.to_string()).or_insert(0) += 1;
+    tracing::warn!("[StrictTools] Tool call violates its strict schema: {}: {}", tool, errors.join("; "));
+}
+
+/// 校验一次调用的参数文本
+fn check_arguments(schema: &Value, arguments: &str) -> Vec<String> {
+    match serde_json::from_str::<Value>(arguments) {
+        Ok(args) => validate_value(schema, &args),
+        Err(e) => vec![format!("arguments are not valid JSON: {}", e)],
+    }
+}
+
+/// 请求中 strict 工具的原始 (未清洗) 参数 schema
+#[derive(Debug, Clone, Default)]
+pub struct StrictToolSchemas {
+    schemas: HashMap<String, Value>,
+}
+
+impl StrictToolSchemas {
+    pub fn from_request(request: &OpenAIRequest) -> Self {
+        let schemas = request
+            .tools
+            .iter()
+            .flatten()
+            .filter(|tool| is_strict_tool(tool))
+            .filter_map(|tool| {
+                let func = tool.get("function").unwrap_or(tool);
+                let name = func.get("name")?.as_str()?.to_string();
+                let params = func.get("parameters").cloned().unwrap_or_else(|| json!({}));
+                Some((name, params))
+            })
+            .collect();
+        Self { schemas }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.schemas.is_empty()
+    }
+
+    /// 按原始 schema 校验响应中的工具调用参数
+    pub fn validate(&self, response: &OpenAIResponse) -> Vec<ToolViolation> {
+        let mut violations = Vec::new();
+        for (choice_index, choice) in response.choices.iter().enumerate() {
+            let calls = choice.message.tool_calls.iter().flatten();
+            for (call_index, call) in calls.enumerate() {
+                let Some(schema) = self.schemas.get(&call.function.name) else {
+                    continue;
+                };
+                let errors = check_arguments(schema, &call.function.arguments);
+                if !errors.is_empty() {
+                    violations.push(ToolViolation {
+                        choice_index,
+                        call_index,
+                        tool: call.function.name.clone(),
+                        errors,
+                    });
+                }
+            }
+        }
+        violations
+    }
+
+    /// 校验并处理违规: 需要重试时返回纠正提示 (追加为 system 消息), 否则在违规的调用上标注 validation_error
+    pub fn enforce(
+        &self,
+        response: &mut OpenAIResponse,
+        mode: StrictToolViolationMode,
+        can_retry: bool,
+    ) -> Option<String> {
+        if self.is_empty() {
+            return None;
+        }
+        let violations = self.validate(response);
+        if violations.is_empty() {
+            return None;
+        }
+        for violation in &violations {
+            record_violation(&violation.tool, &violation.errors);
+        }
+
+        if mode == StrictToolViolationMode::Retry && can_retry {
+            return Some(corrective_note(&violations));
+        }
+        annotate(response, &violations);
+        None
+    }
+
+    /// 流式响应: 校验 SSE 分片中的 tool_calls 增量, 违规的调用附加 validation_error。
+    /// 分片已实时下发, 无法重新生成, 因此流式客户端始终按 annotate 处理。
+    pub fn annotate_chunk(&self, chunk: Bytes) -> Bytes {
+        if self.is_empty() {
+            return chunk;
+        }
+        let Ok(text) = std::str::from_utf8(&chunk) else {
+            return chunk;
+        };
+        if !text.contains("\"tool_calls\"") {
+            return chunk;
+        }
+        let mut changed = false;
+        let lines: Vec<String> = text
+            .split('\n')
+            .map(|line| {
+                let event = line
+                    .strip_prefix("data: ")
+                    .and_then(|data| serde_json::from_str::<Value>(data).ok());
+                match event {
+                    Some(mut event) if self.annotate_event(&mut event) => {
+                        changed = true;
+                        format!("data: {}", event)
+                    }
+                    _ => line.to_string(),
+                }
+            })
+            .collect();
+        if changed {
+            Bytes::from(lines.join("\n"))
+        } else {
+            chunk
+        }
+    }
+
+    fn annotate_event(&self, event: &mut Value) -> bool {
+        let mut annotated = false;
+        let choices = event.get_mut("choices").and_then(|c| c.as_array_mut());
+        for choice in choices.into_iter().flatten() {
+            let calls = choice.pointer_mut("/delta/tool_calls").and_then(|c| c.as_array_mut());
+            for call in calls.into_iter().flatten() {
+                let Some(name) = call.pointer("/function/name").and_then(|n| n.as_str()) else {
+                    continue;
+                };
+                let Some(schema) = self.schemas.get(name) else {
+                    continue;
+                };
+                let arguments = call
+                    .pointer("/function/arguments")
+                    .and_then(|a| a.as_str())
+                    .unwrap_or_default();
+                let errors = check_arguments(schema, arguments);
+                if errors.is_empty() {
+                    continue;
+                }
+                record_violation(name, &errors);
+                call["validation_error"] = json!(errors.join("; "));
+                annotated = true;
+            }
+        }
+        annotated
+    }
+}
+
+/// 各工具名的校验失败次数
+pub fn violation_counts() -> HashMap<String, u64> {
+    VIOLATIONS.iter().map(|e| (e.key().clone(), *e.value())).collect()
+}
+
+fn corrective_note(violations: &[ToolViolation]) -> String {
+    let details: Vec<String> = violations.iter().map(|v| format!("- {}", v.summary())).collect();
+    format!(
+        "Your previous tool call arguments did not match the declared strict JSON schema:\n{}\n\
+         Call the tool again with arguments that include every required field and no undeclared fields.",
+        details.join("\n")
+    )
+}
+
+fn annotate(response: &mut OpenAIResponse, violations: &[ToolViolation]) {
+    for violation in violations {
+        let call = response
+            .choices
+            .get_mut(violation.choice_index)
+            .and_then(|c| c.message.tool_calls.as_mut())
+            .and_then(|calls| calls.get_mut(violation.call_index));
+        if let Some(call) = call {
+            call.validation_error = Some(violation.errors.join("; "));
+        }
+    }
+}
+
+/// 按 JSON Schema 校验 (覆盖 strict 模式允许的子集: type / enum / required / properties /
+/// additionalProperties / items / anyOf / 本地 $ref)
+fn validate_value(schema: &Value, value: &Value) -> Vec<String> {
+    let mut errors = Vec::new();
+    validate_at(schema, schema, value, "$", 0, &mut errors);
+    errors
+}
+
+fn validate_at(root: &Value, schema: &Value, value: &Value, path: &str, depth: usize, errors: &mut Vec<String>) {
+    if let Some(reference) = schema.get("$ref").and_then(|r| r.as_str()) {
+        if depth >= MAX_REF_DEPTH {
+            return;
+        }
+        if let Some(target) = resolve_ref(root, reference) {
+            validate_at(root, target, value, path, depth + 1, errors);
+        }
+        return;
+    }
+
+    if let Some(branches) = schema.get("anyOf").and_then(|a| a.as_array()) {
+        let matches_any = branches.iter().any(|branch| {
+            let mut branch_errors = Vec::new();
+            validate_at(root, branch, value, path, depth + 1, &mut branch_errors);
+            branch_errors.is_empty()
+        });
+        if !matches_any {
+            errors.push(format!("{} does not match any allowed schema", path));
+        }
+        return;
+    }
+
+    if let Some(expected) = schema.get("type") {
+        let types: Vec<&str> = match expected {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(list) => list.iter().filter_map(|t| t.as_str()).collect(),
+            _ => Vec::new(),
+        };
+        if !types.is_empty() && !types.iter().any(|t| matches_type(t, value)) {
+            errors.push(format!("{} should be {}", path, types.join(" | ")));
+            return;
+        }
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(|e| e.as_array()) {
+        if !allowed.contains(value) {
+            errors.push(format!("{} is not one of the allowed values", path));
+        }
+    }
+
+    match value {
+        Value::Object(obj) => {
+            let properties = schema.get("properties").and_then(|p| p.as_object());
+            for key in schema.get("required").and_then(|r| r.as_array()).into_iter().flatten() {
+                if let Some(key) = key.as_str() {
+                    if !obj.contains_key(key) {
+                        errors.push(format!("{} is missing required field '{}'", path, key));
+                    }
+                }
+            }
+            for (key, item) in obj {
+                let item_path = format!("{}.{}", path, key);
+                match (properties.and_then(|p| p.get(key)), schema.get("additionalProperties")) {
+                    (Some(item_schema), _) => validate_at(root, item_schema, item, &item_path, depth, errors),
+                    (None, Some(Value::Bool(false))) => {
+                        errors.push(format!("{} is not a declared field", item_path))
+                    }
+                    (None, Some(extra_schema)) if extra_schema.is_object() => {
+                        validate_at(root, extra_schema, item, &item_path, depth, errors)
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items").filter(|s| s.is_object()) {
+                for (i, item) in items.iter().enumerate() {
+                    validate_at(root, item_schema, item, &format!("{}[{}]", path, i), depth, errors);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn matches_type(expected: &str, value: &Value) -> bool {
+    match expected {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|f| f.fract() == 0.0),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+/// 仅支持文档内引用, 如 `#/$defs/Item` / `#/definitions/Item`
+fn resolve_ref<'a>(root: &'a Value, reference: &str) -> Option<&'a Value> {
+    let pointer = reference.strip_prefix('#')?;
+    root.pointer(pointer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::mappers::openai::models::{Choice, OpenAIMessage, ToolCall, ToolFunction};
+
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "path": { "type": "string" },
+                "mode": { "type": "string", "enum": ["read", "write"] },
+                "lines": { "type": "array", "items": { "$ref": "#/$defs/range" } }
+            },
+            "required": ["path", "mode"],
+            "additionalProperties": false,
+            "$defs": {
+                "range": {
+                    "type": "object",
+                    "properties": { "start": { "type": "integer" } },
+                    "required": ["start"],
+                    "additionalProperties": false
+                }
+            }
+        })
+    }
+
+    fn response_with_call(arguments: &str) -> OpenAIResponse {
+        OpenAIResponse {
+            id: "chatcmpl-1".to_string(),
+            object: "chat.completion".to_string(),
+            created: 0,
+            model: "gemini".to_string(),
+            choices: vec![Choice {
+                index: 0,
+                message: OpenAIMessage {
+                    role: "assistant".to_string(),
+                    content: None,
+                    reasoning_content: None,
+                    tool_calls: Some(vec![ToolCall {
+                        id: "call_1".to_string(),
+                        r#type: "function".to_string(),
+                        function: ToolFunction {
+                            name: "open_file".to_string(),
+                            arguments: arguments.to_string(),
+                        },
+                        validation_error: None,
+                    }]),
+                    tool_call_id: None,
+                    name: None,
+                },
+                finish_reason: Some("tool_calls".to_string()),
+            }],
+            usage: None,
+        }
+    }
+
+    fn schemas() -> StrictToolSchemas {
+        StrictToolSchemas {
+            schemas: HashMap::from([("open_file".to_string(), schema())]),
+        }
+    }
+
+    #[test]
+    fn test_validate_reports_missing_extra_and_nested_errors() {
+        let errors = validate_value(
+            &schema(),
+            &json!({ "path": "a.rs", "extra": 1, "lines": [{ "start": "x" }] }),
+        );
+        assert!(errors.iter().any(|e| e.contains("missing required field 'mode'")));
+        assert!(errors.iter().any(|e| e.contains("$.extra is not a declared field")));
+        assert!(errors.iter().any(|e| e.contains("$.lines[0].start should be integer")));
+
+        assert!(validate_value(&schema(), &json!({ "path": "a.rs", "mode": "read" })).is_empty());
+    }
+
+    #[test]
+    fn test_enforce_retries_once_then_annotates() {
+        let mut response = response_with_call(r#"{"path":"a.rs"}"#);
+        let note = schemas().enforce(&mut response, StrictToolViolationMode::Retry, true);
+        assert!(note.unwrap().contains("open_file"));
+        assert!(response.choices[0].message.tool_calls.as_ref().unwrap()[0].validation_error.is_none());
+
+        let note = schemas().enforce(&mut response, StrictToolViolationMode::Retry, false);
+        assert!(note.is_none());
+        let call = &response.choices[0].message.tool_calls.as_ref().unwrap()[0];
+        assert!(call.validation_error.as_deref().unwrap().contains("mode"));
+    }
+
+    fn tool_call_chunk(arguments: &str) -> Bytes {
+        let event = json!({
+            "object": "chat.completion.chunk",
+            "choices": [{
+                "index": 0,
+                "delta": {
+                    "tool_calls": [{
+                        "index": 0,
+                        "id": "call_1",
+                        "type": "function",
+                        "function": { "name": "open_file", "arguments": arguments }
+                    }]
+                }
+            }]
+        });
+        Bytes::from(format!("data: {}\n\n", event))
+    }
+
+    fn chunk_event(chunk: &Bytes) -> Value {
+        let text = std::str::from_utf8(chunk).unwrap();
+        serde_json::from_str(text.trim().strip_prefix("data: ").unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_stream_chunks_are_annotated() {
+        let invalid = schemas().annotate_chunk(tool_call_chunk(r#"{"path":"a.rs"}"#));
+        let event = chunk_event(&invalid);
+        let error = event["choices"][0]["delta"]["tool_calls"][0]["validation_error"].as_str().unwrap();
+        assert!(error.contains("missing required field 'mode'"));
+        assert!(std::str::from_utf8(&invalid).unwrap().ends_with("\n\n"));
+
+        let valid = tool_call_chunk(r#"{"path":"a.rs","mode":"read"}"#);
+        assert_eq!(schemas().annotate_chunk(valid.clone()), valid);
+
+        let text = Bytes::from("data: {\"choices\":[{\"delta\":{\"content\":\"hi\"}}]}\n\n");
+        assert_eq!(schemas().annotate_chunk(text.clone()), text);
+    }
+
+    #[test]
+    fn test_validated_mode_applies_only_with_tools() {
+        let mut body = json!({ "request": { "tools": [{ "functionDeclarations": [] }] } });
+        apply_validated_mode(&mut body);
+        assert_eq!(body["request"]["toolConfig"]["functionCallingConfig"]["mode"], "VALIDATED");
+
+        let mut body = json!({ "request": { "contents": [] } });
+        apply_validated_mode(&mut body);
+        assert!(body["request"].get("toolConfig").is_none());
+
+        assert!(is_validated_mode_rejected(
+            "Invalid value at 'request.tool_config.function_calling_config.mode'"
+        ));
+        assert!(!is_validated_mode_rejected("Request contains an invalid argument."));
+    }
+}
@@ -0,0 +1,40 @@
+// 流收集完整性
+// 非流式请求内部走流式上游再收集为 JSON; 上游在结尾前断开时, 本地会补发终止事件,
+// 收集结果看起来是完整的。这里记录是否收到上游真正的结束信号与最终 usage,
+// 供 handler 决定重试或以截断形式返回。
+
+/// 告知客户端响应被截断的响应头
+pub const TRUNCATED_HEADER: &str = "X-Response-Truncated";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StreamCompleteness {
+    /// 收到结束信号 (Claude message_stop / OpenAI finish_reason)
+    pub saw_stop: bool,
+    /// 收到带 token 数的最终 usage (本地补发的终止事件不带 usage)
+    pub saw_final_usage: bool,
+    /// 是否已产出任何内容 (文本 / thinking / 工具调用)
+    pub has_content: bool,
+}
+
+impl StreamCompleteness {
+    pub fn is_complete(&self) -> bool {
+        self.saw_stop && self.saw_final_usage
+    }
+
+    /// 响应完整程度的置信度 (0.0 ~ 1.0), 仅用于日志
+    pub fn confidence(&self) -> f32 {
+        match (self.saw_stop, self.saw_final_usage, self.has_content) {
+            (true, true, _) => 1.0,
+            (true, false, true) | (false, true, true) => 0.5,
+            (false, false, true) => 0.25,
+            (_, _, false) => 0.0,
+        }
+    }
+}
+
+/// 收集结果及其完整性
+#[derive(Debug, Clone)]
+pub struct CollectedResponse<T> {
+    pub response: T,
+    pub completeness: StreamCompleteness,
+}
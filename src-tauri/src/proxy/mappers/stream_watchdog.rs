@@ -0,0 +1,291 @@
+// 流中途停滞看门狗
+// 首块超时 (peek) 只覆盖第一个分片; 上游输出若干分片后静默挂起时, 客户端会一直等到自己取消。
+// 这里包装转换后的 SSE 流: 超过 stall_timeout 没有任何实际数据 (心跳注释不计) 即判定停滞,
+// 记录到共享槽位并回调 (计入账号健康分), 然后以协议对应的错误事件结束流。
+// 尚未向客户端输出内容时由 handler 换号整体重试, 见 `hold_output`。
+
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use serde_json::{json, Value};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub type SseStream = Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>>;
+
+/// 被包装流的协议 (决定输出字符的统计方式与停滞错误事件的格式)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamProtocol {
+    Claude,
+    OpenAI,
+}
+
+/// 一次停滞
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StallEvent {
+    /// 触发时已静默的秒数
+    pub idle_secs: u64,
+    /// 停滞前已产出的输出字符数 (文本 / thinking / 工具参数)
+    pub output_chars: usize,
+}
+
+impl StallEvent {
+    pub fn message(&self) -> String {
+        format!(
+            "Upstream stream stalled: no data for {}s after {} output characters; the response is incomplete",
+            self.idle_secs, self.output_chars
+        )
+    }
+}
+
+/// 停滞结果的共享槽位 (handler 在流结束或收集完成后读取)
+#[derive(Debug, Clone, Default)]
+pub struct StallSlot(Arc<Mutex<Option<StallEvent>>>);
+
+impl StallSlot {
+    pub fn get(&self) -> Option<StallEvent> {
+        self.0.lock().ok().and_then(|event| *event)
+    }
+
+    fn set(&self, event: StallEvent) {
+        if let Ok(mut slot) = self.0.lock() {
+            *slot = Some(event);
+        }
+    }
+}
+
+impl StreamProtocol {
+    /// 分片中的输出字符数
+    pub fn output_chars(&self, chunk: &[u8]) -> usize {
+        let text = String::from_utf8_lossy(chunk);
+        text.lines()
+            .filter_map(|line| line.trim().strip_prefix("data:"))
+            .filter_map(|data| serde_json::from_str::<Value>(data.trim()).ok())
+            .map(|event| match self {
+                Self::Claude => claude_delta_chars(&event),
+                Self::OpenAI => openai_delta_chars(&event),
+            })
+            .sum()
+    }
+
+    /// 停滞后下发给客户端的错误事件
+    fn stall_error(&self, event: &StallEvent) -> Bytes {
+        match self {
+            Self::Claude => Bytes::from(format!(
+                "event: error\ndata: {}\n\n",
+                json!({
+                    "type": "error",
+                    "error": { "type": "api_error", "message": event.message() }
+                })
+            )),
+            Self::OpenAI => Bytes::from(format!(
+                "data: {}\n\ndata: [DONE]\n\n",
+                json!({
+                    "error": {
+                        "message": event.message(),
+                        "type": "upstream_stalled",
+                        "code": 504
+                    }
+                })
+            )),
+        }
+    }
+}
+
+fn claude_delta_chars(event: &Value) -> usize {
+    if event.get("type").and_then(|t| t.as_str()) != Some("content_block_delta") {
+        return 0;
+    }
+    let delta = &event["delta"];
+    ["text", "thinking", "partial_json"]
+        .iter()
+        .filter_map(|key| delta.get(*key).and_then(|v| v.as_str()))
+        .map(|s| s.chars().count())
+        .sum()
+}
+
+fn openai_delta_chars(event: &Value) -> usize {
+    let Some(choices) = event.get("choices").and_then(|c| c.as_array()) else {
+        return 0;
+    };
+    choices
+        .iter()
+        .map(|choice| {
+            let delta = &choice["delta"];
+            let text: usize = ["content", "reasoning_content"]
+                .iter()
+                .filter_map(|key| delta.get(*key).and_then(|v| v.as_str()))
+                .map(|s| s.chars().count())
+                .sum();
+            let tool_args: usize = delta
+                .get("tool_calls")
+                .and_then(|t| t.as_array())
+                .map(|calls| {
+                    calls
+                        .iter()
+                        .filter_map(|c| c["function"]["arguments"].as_str())
+                        .map(|s| s.chars().count())
+                        .sum()
+                })
+                .unwrap_or(0);
+            text + tool_args
+        })
+        .sum()
+}
+
+/// 仅包含 SSE 注释 (心跳) 的分片
+fn is_heartbeat(chunk: &[u8]) -> bool {
+    let text = String::from_utf8_lossy(chunk);
+    text.lines().map(str::trim).filter(|l| !l.is_empty()).all(|l| l.starts_with(':'))
+}
+
+/// 包装 SSE 流; `stall_timeout` 为零时原样返回
+pub fn watch(
+    stream: SseStream,
+    protocol: StreamProtocol,
+    stall_timeout: Duration,
+    slot: StallSlot,
+    on_stall: impl FnOnce(StallEvent) + Send + 'static,
+) -> SseStream {
+    if stall_timeout.is_zero() {
+        return stream;
+    }
+
+    Box::pin(async_stream::stream! {
+        let mut inner = stream;
+        let mut on_stall = Some(on_stall);
+        let mut last_activity = Instant::now();
+        let mut output_chars = 0usize;
+
+        loop {
+            let remaining = stall_timeout.saturating_sub(last_activity.elapsed());
+            match tokio::time::timeout(remaining, inner.next()).await {
+                Ok(Some(Ok(chunk))) => {
+                    if !is_heartbeat(&chunk) {
+                        last_activity = Instant::now();
+                        output_chars += protocol.output_chars(&chunk);
+                    }
+                    yield Ok(chunk);
+                }
+                Ok(Some(Err(e))) => {
+                    yield Err(e);
+                }
+                Ok(None) => break,
+                Err(_) => {
+                    let event = StallEvent {
+                        idle_secs: last_activity.elapsed().as_secs(),
+                        output_chars,
+                    };
+                    tracing::warn!("[Stream-Watchdog] {}", event.message());
+                    slot.set(event);
+                    if let Some(on_stall) = on_stall.take() {
+                        on_stall(event);
+                    }
+                    yield Ok(protocol.stall_error(&event));
+                    break;
+                }
+            }
+        }
+    })
+}
+
+/// 在向客户端输出前缓存分片, 直到输出字符数达到 `min_chars`、流结束或出错。
+/// 缓存期间发生停滞时调用方可直接换号重试 (客户端尚未收到任何内容)。
+pub async fn hold_output(
+    stream: &mut SseStream,
+    first_chunk: Bytes,
+    protocol: StreamProtocol,
+    min_chars: usize,
+) -> Vec<Result<Bytes, String>> {
+    let mut chars = protocol.output_chars(&first_chunk);
+    let mut held = vec![Ok(first_chunk)];
+    while chars < min_chars {
+        match stream.next().await {
+            Some(Ok(chunk)) => {
+                chars += protocol.output_chars(&chunk);
+                held.push(Ok(chunk));
+            }
+            Some(Err(e)) => {
+                held.push(Err(e));
+                break;
+            }
+            None => break,
+        }
+    }
+    held
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn claude_text(text: &str) -> Bytes {
+        Bytes::from(format!(
+            "event: content_block_delta\ndata: {}\n\n",
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": text}})
+        ))
+    }
+
+    /// 先输出给定分片, 随后只发心跳不再结束
+    fn stalling(chunks: Vec<Bytes>) -> SseStream {
+        Box::pin(async_stream::stream! {
+            for chunk in chunks {
+                yield Ok(chunk);
+            }
+            loop {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                yield Ok(Bytes::from(": ping\n\n"));
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn test_stall_ends_stream_with_error_event() {
+        let slot = StallSlot::default();
+        let stalls = Arc::new(AtomicUsize::new(0));
+        let counter = stalls.clone();
+        let stream = watch(
+            stalling(vec![claude_text("Hello"), claude_text(" world")]),
+            StreamProtocol::Claude,
+            Duration::from_millis(80),
+            slot.clone(),
+            move |_| {
+                counter.fetch_add(1, Ordering::SeqCst);
+            },
+        );
+
+        let output: String = stream
+            .map(|chunk| String::from_utf8(chunk.unwrap().to_vec()).unwrap())
+            .collect::<Vec<_>>()
+            .await
+            .concat();
+
+        assert!(output.contains(" world"));
+        assert!(output.ends_with("\n\n"));
+        assert!(output.contains("event: error"));
+        assert!(output.contains("stalled"));
+        assert_eq!(stalls.load(Ordering::SeqCst), 1);
+        assert_eq!(slot.get().map(|e| e.output_chars), Some(11));
+    }
+
+    #[tokio::test]
+    async fn test_hold_output_until_threshold() {
+        let mut stream: SseStream = Box::pin(futures::stream::iter(vec![
+            Ok(claude_text("abc")),
+            Ok(Bytes::from(": ping\n\n")),
+            Ok(claude_text("defgh")),
+            Ok(claude_text("ijk")),
+        ]));
+        let first = Bytes::from("event: message_start\ndata: {\"type\":\"message_start\"}\n\n");
+        let held = hold_output(&mut stream, first, StreamProtocol::Claude, 6).await;
+        assert_eq!(held.len(), 4);
+        // 剩余分片留在原流中
+        assert_eq!(stream.next().await.unwrap().unwrap(), claude_text("ijk"));
+
+        let openai = Bytes::from(
+            "data: {\"choices\":[{\"delta\":{\"content\":\"hi\",\"tool_calls\":[{\"function\":{\"arguments\":\"{}\"}}]}}]}\n\n",
+        );
+        assert_eq!(StreamProtocol::OpenAI.output_chars(&openai), 4);
+    }
+}
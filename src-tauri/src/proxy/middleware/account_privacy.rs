@@ -0,0 +1,204 @@
+// X-Account-Email 响应头隐私处理
+// 各 handler 统一写入真实邮箱, monitor 中间件据此记录日志;
+// 本中间件位于 monitor 外层, 只在响应离开代理前按配置改写或移除该头。
+// 上游 responseId / modelVersion 头与邮箱使用同一开关: 隐藏模式下一并移除。
+
+use axum::{
+    extract::Request,
+    middleware::Next,
+    response::Response,
+};
+
+use crate::proxy::common::redact::{account_header_privacy, account_header_value};
+use crate::proxy::config::AccountHeaderPrivacy;
+use crate::proxy::handlers::common::{decode_header_value, sanitize_header_value};
+use crate::proxy::upstream::response_ids::{MODEL_VERSION_HEADER, RESPONSE_ID_HEADER};
+
+pub const ACCOUNT_EMAIL_HEADER: &str = "X-Account-Email";
+
+pub async fn account_privacy_middleware(request: Request, next: Next) -> Response {
+    let mut response = next.run(request).await;
+    let privacy = account_header_privacy();
+
+    if privacy == AccountHeaderPrivacy::Omit {
+        response.headers_mut().remove(RESPONSE_ID_HEADER);
+        response.headers_mut().remove(MODEL_VERSION_HEADER);
+    }
+
+    let Some(account) = response
+        .headers()
+        .get(ACCOUNT_EMAIL_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(decode_header_value)
+    else {
+        return response;
+    };
+
+    match account_header_value(&account, privacy).and_then(|v| sanitize_header_value(&v)) {
+        Some(value) => {
+            response.headers_mut().insert(ACCOUNT_EMAIL_HEADER, value);
+        }
+        None => {
+            response.headers_mut().remove(ACCOUNT_EMAIL_HEADER);
+        }
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::common::redact::set_account_header_privacy;
+    use crate::proxy::config::AccountHeaderPrivacy;
+    use axum::{
+        body::Body,
+        http::{HeaderValue, StatusCode},
+        response::IntoResponse,
+        routing::post,
+        Json, Router,
+    };
+    use std::sync::{Arc, Mutex};
+    use tower::util::ServiceExt;
+
+    const EMAIL: &str = "alice.smith@gmail.com";
+
+    // 覆盖 handler 中构造该响应头的几种写法
+    fn handler_router(seen: Arc<Mutex<Vec<String>>>) -> Router {
+        Router::new()
+            // claude / openai 非流式: 头部元组数组
+            .route(
+                "/v1/messages",
+                post(|| async {
+                    (
+                        StatusCode::OK,
+                        [
+                            ("X-Account-Email", EMAIL),
+                            ("X-Mapped-Model", "claude-sonnet-4-5"),
+                            (RESPONSE_ID_HEADER, "resp-abc123"),
+                        ],
+                        Json(serde_json::json!({"type": "message"})),
+                    )
+                        .into_response()
+                }),
+            )
+            // 流式: Response::builder().header(...)
+            .route(
+                "/v1/chat/completions",
+                post(|| async {
+                    Response::builder()
+                        .header("Content-Type", "text/event-stream")
+                        .header("X-Account-Email", EMAIL)
+                        .body(Body::from("data: [DONE]\n\n"))
+                        .unwrap()
+                }),
+            )
+            // gemini 429 / images / audio: 单个头部
+            .route(
+                "/v1beta/models/gemini-3-flash:generateContent",
+                post(|| async {
+                    (StatusCode::TOO_MANY_REQUESTS, [("X-Account-Email", EMAIL)], "exhausted")
+                        .into_response()
+                }),
+            )
+            // warmup: headers_mut().insert
+            .route(
+                "/internal/warmup",
+                post(|| async {
+                    let mut response = Json(serde_json::json!({"success": true})).into_response();
+                    response
+                        .headers_mut()
+                        .insert("X-Account-Email", HeaderValue::from_static(EMAIL));
+                    response
+                }),
+            )
+            // 外部 provider
+            .route(
+                "/v1/images/generations",
+                post(|| async { ([("X-Account-Email", "provider:zai")], "ok").into_response() }),
+            )
+            // monitor 位于本中间件内层, 记录它看到的值
+            .layer(axum::middleware::from_fn(move |req: Request, next: Next| {
+                let seen = seen.clone();
+                async move {
+                    let response = next.run(req).await;
+                    if let Some(v) = response.headers().get(ACCOUNT_EMAIL_HEADER) {
+                        seen.lock().unwrap().push(v.to_str().unwrap().to_string());
+                    }
+                    response
+                }
+            }))
+            .layer(axum::middleware::from_fn(account_privacy_middleware))
+    }
+
+    const PATHS: [&str; 5] = [
+        "/v1/messages",
+        "/v1/chat/completions",
+        "/v1beta/models/gemini-3-flash:generateContent",
+        "/internal/warmup",
+        "/v1/images/generations",
+    ];
+
+    async fn collect_headers(app: &Router, forbid_email: bool) -> Vec<Option<String>> {
+        let mut out = Vec::new();
+        for path in PATHS {
+            let response = app
+                .clone()
+                .oneshot(Request::post(path).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            if forbid_email {
+                for (name, value) in response.headers() {
+                    assert!(
+                        !value.to_str().unwrap().contains('@'),
+                        "email leaked via {} on {}",
+                        name,
+                        path
+                    );
+                }
+            }
+            out.push(
+                response
+                    .headers()
+                    .get(ACCOUNT_EMAIL_HEADER)
+                    .map(|v| v.to_str().unwrap().to_string()),
+            );
+        }
+        out
+    }
+
+    #[tokio::test]
+    async fn test_account_header_privacy_modes() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let app = handler_router(seen.clone());
+
+        set_account_header_privacy(AccountHeaderPrivacy::Pseudonym);
+        let headers = collect_headers(&app, true).await;
+        let pseudonym = crate::proxy::common::redact::pseudonymize_account(EMAIL);
+        assert!(pseudonym.starts_with("acct-"));
+        assert_eq!(headers[..4], vec![Some(pseudonym); 4][..]);
+        assert_eq!(headers[4].as_deref(), Some("provider:zai"));
+        // 日志侧仍看到真实邮箱
+        assert_eq!(seen.lock().unwrap().iter().filter(|v| *v == EMAIL).count(), 4);
+
+        set_account_header_privacy(AccountHeaderPrivacy::Omit);
+        let headers = collect_headers(&app, true).await;
+        assert!(headers.iter().all(|h| h.is_none()));
+        let response = app
+            .clone()
+            .oneshot(Request::post("/v1/messages").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert!(!response.headers().contains_key(RESPONSE_ID_HEADER));
+
+        set_account_header_privacy(AccountHeaderPrivacy::Full);
+        let headers = collect_headers(&app, false).await;
+        assert_eq!(headers[0].as_deref(), Some(EMAIL));
+        assert_eq!(headers[3].as_deref(), Some(EMAIL));
+        let response = app
+            .clone()
+            .oneshot(Request::post("/v1/messages").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.headers()[RESPONSE_ID_HEADER], "resp-abc123");
+    }
+}
@@ -1,22 +1,38 @@
 // API Key 认证中间件
+// 认证通过后按 Key 的权限范围 (inference / read_stats / admin) 检查接口类别:
+// 模型接口需要 inference, 只读统计 / 日志接口需要 read_stats 或 admin, 其余管理接口需要 admin。
+// 解析出的权限写入请求扩展 (GrantedScopes)。
 use axum::{
     extract::State,
     extract::Request,
-    http::{header, StatusCode},
+    http::{header, HeaderMap, Method, StatusCode},
     middleware::Next,
-    response::Response,
+    response::{IntoResponse, Response},
+    Json,
 };
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
+use crate::proxy::config::KeyScope;
 use crate::proxy::{ProxyAuthMode, ProxySecurityConfig};
 
+/// 当前请求所用 Key 的权限 (请求扩展)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrantedScopes(pub Vec<KeyScope>);
+
+impl GrantedScopes {
+    /// 满足 `required` 中任一权限
+    pub fn allows_any(&self, required: &[KeyScope]) -> bool {
+        required.iter().any(|scope| self.0.contains(scope))
+    }
+}
+
 /// API Key 认证中间件 (代理接口使用，遵循 auth_mode)
 pub async fn auth_middleware(
     state: State<Arc<RwLock<ProxySecurityConfig>>>,
     request: Request,
     next: Next,
-) -> Result<Response, StatusCode> {
+) -> Response {
     auth_middleware_internal(state, request, next, false).await
 }
 
@@ -25,17 +41,66 @@ pub async fn admin_auth_middleware(
     state: State<Arc<RwLock<ProxySecurityConfig>>>,
     request: Request,
     next: Next,
-) -> Result<Response, StatusCode> {
+) -> Response {
     auth_middleware_internal(state, request, next, true).await
 }
 
+/// 从请求头提取客户端携带的 API key (Authorization Bearer / x-api-key / x-goog-api-key)
+pub fn extract_api_key(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|s| s.strip_prefix("Bearer ").or(Some(s)))
+        .or_else(|| headers.get("x-api-key").and_then(|h| h.to_str().ok()))
+        .or_else(|| headers.get("x-goog-api-key").and_then(|h| h.to_str().ok()))
+}
+
+/// 只读的统计 / 日志接口 (管理路由内的路径, 兼容带 /api 前缀)
+fn is_read_stats_endpoint(method: &Method, path: &str) -> bool {
+    if method != Method::GET {
+        return false;
+    }
+    let path = path.strip_prefix("/api").unwrap_or(path);
+    path.starts_with("/stats/")
+        || path == "/logs"
+        || path.starts_with("/logs/")
+        || matches!(
+            path,
+            "/proxy/stats" | "/proxy/status" | "/proxy/model-health" | "/proxy/calibration"
+        )
+}
+
+/// 接口所需的权限 (满足其一即可)
+fn required_scopes(method: &Method, path: &str, admin: bool) -> &'static [KeyScope] {
+    if !admin {
+        &[KeyScope::Inference]
+    } else if is_read_stats_endpoint(method, path) {
+        &[KeyScope::ReadStats, KeyScope::Admin]
+    } else {
+        &[KeyScope::Admin]
+    }
+}
+
+/// Key 有效但缺少权限: 403, 错误信息中写明缺少的权限
+fn missing_scope_response(required: &[KeyScope]) -> Response {
+    let names: Vec<&str> = required.iter().map(|s| s.as_str()).collect();
+    let body = serde_json::json!({
+        "error": {
+            "type": "permission_error",
+            "message": format!("This API key is missing the required scope: {}", names.join(" or ")),
+            "missing_scope": names[0],
+        }
+    });
+    (StatusCode::FORBIDDEN, Json(body)).into_response()
+}
+
 /// 内部认证逻辑
 async fn auth_middleware_internal(
     State(security): State<Arc<RwLock<ProxySecurityConfig>>>,
-    request: Request,
+    mut request: Request,
     next: Next,
     force_strict: bool,
-) -> Result<Response, StatusCode> {
+) -> Response {
     let method = request.method().clone();
     let path = request.uri().path().to_string();
 
@@ -50,7 +115,7 @@ async fn auth_middleware_internal(
 
     // Allow CORS preflight regardless of auth policy.
     if method == axum::http::Method::OPTIONS {
-        return Ok(next.run(request).await);
+        return next.run(request).await;
     }
 
     let security = security.read().await.clone();
@@ -60,88 +125,67 @@ async fn auth_middleware_internal(
     if !force_strict {
         // AI 代理接口 (v1/chat/completions 等)
         if is_internal_endpoint {
-            return Ok(next.run(request).await);
+            return next.run(request).await;
         }
 
         if matches!(effective_mode, ProxyAuthMode::Off) {
-            return Ok(next.run(request).await);
+            return next.run(request).await;
         }
 
         if matches!(effective_mode, ProxyAuthMode::AllExceptHealth) && is_health_check {
-            return Ok(next.run(request).await);
+            return next.run(request).await;
         }
     } else {
         // 管理接口 (/api/*)
         // 1. 如果全局鉴权关闭，则管理接口也放行 (除非是强制局域网模式)
         if matches!(effective_mode, ProxyAuthMode::Off) {
-            return Ok(next.run(request).await);
+            return next.run(request).await;
         }
 
         // 2. 健康检查在所有模式下对管理接口放行
         if is_health_check {
-            return Ok(next.run(request).await);
+            return next.run(request).await;
         }
     }
-    
-    // 从 header 中提取 API key
-    let api_key = request
-        .headers()
-        .get(header::AUTHORIZATION)
-        .and_then(|h| h.to_str().ok())
-        .and_then(|s| s.strip_prefix("Bearer ").or(Some(s)))
-        .or_else(|| {
-            request
-                .headers()
-                .get("x-api-key")
-                .and_then(|h| h.to_str().ok())
-        })
-        .or_else(|| {
-            request
-                .headers()
-                .get("x-goog-api-key")
-                .and_then(|h| h.to_str().ok())
-        });
-
-    let admin_password_empty = security.admin_password.as_ref().map_or(true, |s| s.is_empty());
-
-    if security.api_key.is_empty() && admin_password_empty {
+
+    if !security.has_credentials() {
         if force_strict {
-             tracing::error!("Admin auth is required but both api_key and admin_password are empty; denying request");
-             return Err(StatusCode::UNAUTHORIZED);
+            tracing::error!("Admin auth is required but both api_key and admin_password are empty; denying request");
+        } else {
+            tracing::error!("Proxy auth is enabled but api_key is empty; denying request");
         }
-        tracing::error!("Proxy auth is enabled but api_key is empty; denying request");
-        return Err(StatusCode::UNAUTHORIZED);
+        return StatusCode::UNAUTHORIZED.into_response();
     }
 
-    // 认证逻辑
-    let authorized = if force_strict {
-        // 管理接口：优先使用独立的 admin_password，如果没有则回退使用 api_key
-        match &security.admin_password {
-            Some(pwd) if !pwd.is_empty() => {
-                api_key.map(|k| k == pwd).unwrap_or(false)
-            }
-            _ => {
-                // 回退使用 api_key
-                api_key.map(|k| k == security.api_key).unwrap_or(false)
-            }
-        }
-    } else {
-        // AI 代理接口：仅允许使用 api_key
-        api_key.map(|k| k == security.api_key).unwrap_or(false)
+    // 认证: 管理接口优先使用独立的 admin_password (见 scopes_for_key), 模型接口使用 api_key 或带 inference 权限的 Key
+    let Some(scopes) = extract_api_key(request.headers()).and_then(|key| security.scopes_for_key(key)) else {
+        return StatusCode::UNAUTHORIZED.into_response();
     };
 
-    if authorized {
-        Ok(next.run(request).await)
-    } else {
-        Err(StatusCode::UNAUTHORIZED)
+    let granted = GrantedScopes(scopes);
+    let required = required_scopes(&method, &path, force_strict);
+    if !granted.allows_any(required) {
+        tracing::warn!(
+            "Rejected {} {}: API key lacks scope {:?}",
+            method,
+            path,
+            required
+        );
+        return missing_scope_response(required);
     }
+
+    request.extensions_mut().insert(granted);
+    next.run(request).await
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::proxy::config::SecurityMonitorConfig;
-    use crate::proxy::ProxyAuthMode;
+    use crate::proxy::config::{ScopedApiKey, SecurityMonitorConfig};
+    use axum::middleware::from_fn_with_state;
+    use axum::routing::{get, post};
+    use axum::Router;
+    use tower::ServiceExt;
 
     #[tokio::test]
     async fn test_admin_auth_with_password() {
@@ -149,6 +193,7 @@ mod tests {
             auth_mode: ProxyAuthMode::Strict,
             api_key: "sk-api".to_string(),
             admin_password: Some("admin123".to_string()),
+            api_keys: Vec::new(),
             allow_lan_access: true,
             port: 8045,
             security_monitor: SecurityMonitorConfig::default(),
@@ -169,4 +214,89 @@ mod tests {
     fn test_auth_placeholder() {
         assert!(true);
     }
+
+    fn scoped(key: &str, scopes: &[KeyScope]) -> ScopedApiKey {
+        ScopedApiKey {
+            key: key.to_string(),
+            label: String::new(),
+            scopes: scopes.to_vec(),
+        }
+    }
+
+    fn scoped_router() -> Router {
+        let security = Arc::new(RwLock::new(ProxySecurityConfig {
+            auth_mode: ProxyAuthMode::Strict,
+            api_key: "sk-primary".to_string(),
+            admin_password: None,
+            api_keys: vec![
+                scoped("sk-infer", &[KeyScope::Inference]),
+                scoped("sk-dash", &[KeyScope::ReadStats]),
+                scoped("sk-admin", &[KeyScope::Admin]),
+            ],
+            allow_lan_access: false,
+            port: 8045,
+            security_monitor: SecurityMonitorConfig::default(),
+        }));
+        let admin = Router::new()
+            .route("/stats/token/hourly", get(|| async { "ok" }))
+            .route("/accounts/switch", post(|| async { "ok" }))
+            .layer(from_fn_with_state(security.clone(), admin_auth_middleware));
+        Router::new()
+            .route("/v1/messages", post(|| async { "ok" }))
+            .layer(from_fn_with_state(security, auth_middleware))
+            .nest("/api", admin)
+    }
+
+    async fn status(method: &str, uri: &str, key: &str) -> StatusCode {
+        let req = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("Authorization", format!("Bearer {}", key))
+            .body(axum::body::Body::empty())
+            .unwrap();
+        scoped_router().oneshot(req).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_scopes_per_endpoint_class() {
+        let inference = ("POST", "/v1/messages");
+        let stats = ("GET", "/api/stats/token/hourly");
+        let admin = ("POST", "/api/accounts/switch");
+        let cases = [
+            ("sk-primary", [StatusCode::OK, StatusCode::OK, StatusCode::OK]),
+            ("sk-infer", [StatusCode::OK, StatusCode::FORBIDDEN, StatusCode::FORBIDDEN]),
+            ("sk-dash", [StatusCode::FORBIDDEN, StatusCode::OK, StatusCode::FORBIDDEN]),
+            ("sk-admin", [StatusCode::FORBIDDEN, StatusCode::OK, StatusCode::OK]),
+            ("sk-unknown", [StatusCode::UNAUTHORIZED; 3]),
+        ];
+        for (key, expected) in cases {
+            for ((method, uri), want) in [inference, stats, admin].into_iter().zip(expected) {
+                assert_eq!(status(method, uri, key).await, want, "{} {} {}", key, method, uri);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_missing_scope_is_named_in_error() {
+        let req = Request::builder()
+            .method("POST")
+            .uri("/api/accounts/switch")
+            .header("x-api-key", "sk-dash")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let resp = scoped_router().oneshot(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["error"]["missing_scope"], "admin");
+        assert_eq!(json["error"]["type"], "permission_error");
+    }
+
+    #[test]
+    fn test_read_stats_endpoints() {
+        assert!(is_read_stats_endpoint(&Method::GET, "/api/logs/abc"));
+        assert!(is_read_stats_endpoint(&Method::GET, "/proxy/stats"));
+        assert!(!is_read_stats_endpoint(&Method::POST, "/stats/token/clear"));
+        assert!(!is_read_stats_endpoint(&Method::GET, "/accounts"));
+    }
 }
@@ -0,0 +1,307 @@
+// 响应 Content-Type 统一处理
+// 协议端点的错误响应一律为 application/json 且符合客户端协议格式: handler 中残留的纯文本错误
+// ((StatusCode, String) 元组、axum 提取器拒绝、空体 401/404/405 等) 与其他协议格式的 JSON 错误
+// (透传的上游错误、鉴权中间件的错误) 在此按请求路径包装;
+// SSE 响应补全 charset=utf-8 (上游透传与 axum Sse 默认不带)。管理接口与静态资源只做 SSE 补全。
+
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{header, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use serde_json::Value;
+
+use crate::proxy::handlers::common::{error_body, ErrorProtocol, SSE_CONTENT_TYPE};
+
+/// 读取待包装错误体的上限, 超出时按状态码生成错误信息
+const MAX_ERROR_BODY_BYTES: usize = 64 * 1024;
+
+pub async fn content_type_middleware(request: Request, next: Next) -> Response {
+    let protocol = ErrorProtocol::for_path(request.uri().path());
+    let is_head = request.method() == Method::HEAD;
+    let response = next.run(request).await;
+    normalize_response(response, protocol, is_head).await
+}
+
+async fn normalize_response(
+    mut response: Response,
+    protocol: Option<ErrorProtocol>,
+    is_head: bool,
+) -> Response {
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_ascii_lowercase());
+
+    if let Some(ct) = content_type.as_deref() {
+        if ct.starts_with("text/event-stream") {
+            if !ct.contains("charset") {
+                response.headers_mut().insert(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static(SSE_CONTENT_TYPE),
+                );
+            }
+            return response;
+        }
+    }
+
+    let status = response.status();
+    let Some(protocol) = protocol else {
+        return response;
+    };
+    if !(status.is_client_error() || status.is_server_error()) {
+        return response;
+    }
+    let is_json_body = content_type.as_deref().is_some_and(is_json);
+    let too_large = response
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok())
+        .is_some_and(|len| len > MAX_ERROR_BODY_BYTES);
+    if is_json_body && (is_head || too_large) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    if is_head {
+        parts.headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        );
+        parts.headers.remove(header::CONTENT_LENGTH);
+        return Response::from_parts(parts, Body::empty());
+    }
+
+    let bytes = axum::body::to_bytes(body, MAX_ERROR_BODY_BYTES)
+        .await
+        .unwrap_or_default();
+    let text = String::from_utf8_lossy(&bytes);
+    let text = text.trim();
+    let body = match serde_json::from_str::<Value>(text) {
+        // 已是本协议格式的 JSON 错误体原样保留
+        Ok(value) if protocol.matches_error(&value) => {
+            if is_json_body {
+                return Response::from_parts(parts, Body::from(bytes.clone()));
+            }
+            value
+        }
+        // 其他协议格式的错误体 (如上游 Gemini 错误出现在 /v1/messages) 取出信息后按本协议重新包装
+        Ok(value) => rewrap_error(protocol, status, &value, text),
+        Err(_) => {
+            let message = if text.is_empty() {
+                status.canonical_reason().unwrap_or("Error")
+            } else {
+                text
+            };
+            error_body(protocol, status, message)
+        }
+    };
+    parts.headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(body.to_string()))
+}
+
+/// 错误对象中描述格式的字段, 重新包装时由目标协议重新生成
+const ERROR_SHAPE_KEYS: [&str; 4] = ["message", "type", "code", "status"];
+
+/// 按协议重新包装其他格式的 JSON 错误体; 原错误对象中的附加字段 (如 missing_scope) 予以保留
+fn rewrap_error(protocol: ErrorProtocol, status: StatusCode, original: &Value, text: &str) -> Value {
+    let message = original
+        .pointer("/error/message")
+        .and_then(Value::as_str)
+        .or_else(|| original.get("error").and_then(Value::as_str))
+        .or_else(|| original.get("message").and_then(Value::as_str))
+        .unwrap_or(text);
+    let mut body = error_body(protocol, status, message);
+    if let (Some(extra), Some(error)) = (
+        original.get("error").and_then(Value::as_object),
+        body.get_mut("error").and_then(Value::as_object_mut),
+    ) {
+        for (key, value) in extra {
+            if !ERROR_SHAPE_KEYS.contains(&key.as_str()) {
+                error.entry(key.clone()).or_insert_with(|| value.clone());
+            }
+        }
+    }
+    body
+}
+
+fn is_json(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or_default().trim();
+    mime == "application/json" || mime.ends_with("+json")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{response::IntoResponse, routing::post, Json, Router};
+    use serde_json::json;
+    use tower::util::ServiceExt;
+
+    fn router() -> Router {
+        Router::new()
+            .route(
+                "/v1/messages",
+                post(|| async { (StatusCode::BAD_GATEWAY, "Parse error: eof").into_response() }),
+            )
+            .route(
+                "/v1beta/models/:model",
+                post(|| async { StatusCode::UNAUTHORIZED.into_response() }),
+            )
+            .route(
+                "/v1/chat/completions",
+                post(|| async {
+                    (
+                        StatusCode::TOO_MANY_REQUESTS,
+                        [(header::CONTENT_TYPE, "text/plain")],
+                        r#"{"error":{"message":"upstream"}}"#,
+                    )
+                        .into_response()
+                }),
+            )
+            .route(
+                "/v1/embeddings",
+                post(|| async {
+                    (
+                        StatusCode::BAD_REQUEST,
+                        Json(json!({"error": {"message": "kept", "failed_indices": [1]}})),
+                    )
+                        .into_response()
+                }),
+            )
+            .route(
+                "/v1/completions",
+                post(|| async {
+                    (
+                        StatusCode::BAD_REQUEST,
+                        [(header::CONTENT_TYPE, "application/json")],
+                        r#"{"error": {"message": "ok", "type": "invalid_request_error", "code": 400}}"#,
+                    )
+                        .into_response()
+                }),
+            )
+            .route(
+                "/v1/messages/count_tokens",
+                post(|| async {
+                    (
+                        StatusCode::TOO_MANY_REQUESTS,
+                        Json(json!({"error": {"code": 429, "message": "exhausted", "status": "RESOURCE_EXHAUSTED"}})),
+                    )
+                        .into_response()
+                }),
+            )
+            .route(
+                "/mcp/web_reader/mcp",
+                post(|| async {
+                    (
+                        [(header::CONTENT_TYPE, "text/event-stream")],
+                        "data: 你好\n\n",
+                    )
+                        .into_response()
+                }),
+            )
+            .route(
+                "/api/accounts",
+                post(|| async { (StatusCode::BAD_REQUEST, "admin text").into_response() }),
+            )
+            .layer(axum::middleware::from_fn(content_type_middleware))
+    }
+
+    async fn call(method: Method, path: &str) -> (StatusCode, Option<String>, String) {
+        let response = router()
+            .oneshot(
+                Request::builder()
+                    .method(method)
+                    .uri(path)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status();
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .map(|v| v.to_str().unwrap().to_string());
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (
+            status,
+            content_type,
+            String::from_utf8(bytes.to_vec()).unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_text_errors_wrapped_in_protocol_json() {
+        let (status, ct, body) = call(Method::POST, "/v1/messages").await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert_eq!(ct.as_deref(), Some("application/json"));
+        let body: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["type"], "error");
+        assert_eq!(body["error"]["message"], "Parse error: eof");
+
+        // 空体错误以状态码说明作为信息
+        let (_, ct, body) = call(Method::POST, "/v1beta/models/gemini-2.5-flash").await;
+        assert_eq!(ct.as_deref(), Some("application/json"));
+        let body: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["error"]["status"], "UNAUTHENTICATED");
+        assert_eq!(body["error"]["message"], "Unauthorized");
+
+        // 不完整的 OpenAI 错误体补全为协议格式, 信息不变
+        let (_, ct, body) = call(Method::POST, "/v1/chat/completions").await;
+        assert_eq!(ct.as_deref(), Some("application/json"));
+        let body: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(
+            body,
+            json!({"error": {"message": "upstream", "type": "rate_limit_exceeded", "code": 429}})
+        );
+
+        // 附加字段保留
+        let (_, _, body) = call(Method::POST, "/v1/embeddings").await;
+        let body: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["error"]["message"], "kept");
+        assert_eq!(body["error"]["type"], "invalid_request_error");
+        assert_eq!(body["error"]["failed_indices"], json!([1]));
+
+        // 已符合协议格式的错误体原样保留
+        let (_, _, body) = call(Method::POST, "/v1/completions").await;
+        assert_eq!(
+            body,
+            r#"{"error": {"message": "ok", "type": "invalid_request_error", "code": 400}}"#
+        );
+
+        // Claude 端点上的 Gemini 格式错误改为 Claude 格式
+        let (status, _, body) = call(Method::POST, "/v1/messages/count_tokens").await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        let body: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(
+            body,
+            json!({"type": "error", "error": {"type": "rate_limit_error", "message": "exhausted"}})
+        );
+
+        // 未匹配的方法 (405) 同样返回 JSON
+        let (status, ct, _) = call(Method::DELETE, "/v1/messages").await;
+        assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(ct.as_deref(), Some("application/json"));
+    }
+
+    #[tokio::test]
+    async fn test_sse_charset_added_and_admin_untouched() {
+        let (_, ct, body) = call(Method::POST, "/mcp/web_reader/mcp").await;
+        assert_eq!(ct.as_deref(), Some(SSE_CONTENT_TYPE));
+        assert_eq!(body, "data: 你好\n\n");
+
+        let (_, ct, body) = call(Method::POST, "/api/accounts").await;
+        assert_eq!(ct.as_deref(), Some("text/plain; charset=utf-8"));
+        assert_eq!(body, "admin text");
+    }
+}
@@ -7,9 +7,13 @@ pub mod monitor;
 pub mod ip_filter; // [NEW] IP security filtering
 
 pub mod service_status;
+pub mod account_privacy;
+pub mod content_type;
 
 pub use cors::cors_layer;
 pub use monitor::monitor_middleware;
 pub use service_status::service_status_middleware;
+pub use account_privacy::account_privacy_middleware;
+pub use content_type::content_type_middleware;
 pub use auth::{auth_middleware, admin_auth_middleware};
 pub use ip_filter::{ip_filter_middleware, SecurityState};
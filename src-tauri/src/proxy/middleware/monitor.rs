@@ -4,9 +4,21 @@ use axum::{
     response::Response,
     body::Body,
 };
+use std::sync::{Arc, Mutex};
 use std::time::Instant;
 use crate::proxy::server::AppState;
-use crate::proxy::monitor::ProxyRequestLog;
+use crate::proxy::monitor::{ProxyMonitor, ProxyRequestLog, Termination, CLIENT_CANCELLED_ERROR, STATUS_CLIENT_CANCELLED};
+use crate::proxy::mappers::context_manager::estimate_tokens_from_str;
+use crate::proxy::mappers::estimation_calibrator::get_calibrator;
+use crate::proxy::middleware::auth::extract_api_key;
+use crate::proxy::key_budget;
+use crate::proxy::upstream::response_ids::{UpstreamIdsSlot, MODEL_VERSION_HEADER, RESPONSE_ID_HEADER};
+use crate::proxy::debug_logger::{take_raw_transcript, RAW_MESSAGES_RESPONSE_HEADER, RAW_TRANSCRIPT_HEADER};
+use crate::proxy::providers::{ProviderDecision, GOOGLE_PROVIDER, PROVIDER_HEADER};
+use crate::proxy::common::system_injection::AppliedInjections;
+use crate::proxy::handlers::common::{
+    decode_header_value, set_header_lossy, AccountRotations, ByteUsage, LocalFailure,
+};
 use serde_json::Value;
 use futures::StreamExt;
 
@@ -26,6 +38,23 @@ pub async fn monitor_middleware(
     if uri.contains("event_logging") || uri.contains("/api/") {
         return next.run(request).await;
     }
+
+    if !uri.starts_with("/internal/") {
+        crate::proxy::warm_pool::record_activity();
+    }
+
+    // API Key 每日预算: 分发前检查 (未配置预算的 Key 不受影响)
+    let budget_key = if uri.starts_with("/internal/") {
+        None
+    } else {
+        extract_api_key(request.headers()).map(|k| k.to_string())
+    };
+    if let Some(key) = budget_key.as_deref() {
+        if let Err(exceeded) = key_budget::try_acquire(key) {
+            tracing::warn!("[KeyBudget] Rejected {} {}: {}", method, uri, exceeded.message());
+            return key_budget::exceeded_response(request.uri().path(), &exceeded);
+        }
+    }
     
     let start = Instant::now();
     
@@ -39,7 +68,7 @@ pub async fn monitor_middleware(
     };
 
     let request_body_str;
-    let request = if method == "POST" {
+    let mut request = if method == "POST" {
         let (parts, body) = request.into_parts();
         match axum::body::to_bytes(body, MAX_REQUEST_LOG_SIZE).await {
             Ok(bytes) => {
@@ -64,8 +93,70 @@ pub async fn monitor_middleware(
         request_body_str = None;
         request
     };
-    
-    let response = next.run(request).await;
+
+    // Determine protocol from URL path
+    let protocol = if uri.contains("/v1/messages") {
+        Some("anthropic".to_string())
+    } else if uri.contains("/v1beta/models") {
+        Some("gemini".to_string())
+    } else if uri.starts_with("/v1/") {
+        Some("openai".to_string())
+    } else {
+        None
+    };
+
+    let partial_usage = PartialUsage::default();
+    request.extensions_mut().insert(partial_usage.clone());
+    let mut pending = CancelledRequestGuard {
+        log: Some(ProxyRequestLog {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: chrono::Utc::now().timestamp_millis(),
+            method,
+            url: uri,
+            status: STATUS_CLIENT_CANCELLED,
+            duration: 0,
+            model,
+            mapped_model: None,
+            account_email: None,
+            client_ip: None, // TODO: Extract from request headers if available
+            error: None,
+            request_body: request_body_str,
+            response_body: None,
+            input_tokens: None,
+            output_tokens: None,
+            protocol,
+            raw_upstream: None,
+            service_tier: None,
+            upstream_response_id: None,
+            upstream_model_version: None,
+            provider_decision: None,
+            raw_messages: None,
+            system_injections: None,
+            account_rotations: None,
+            input_bytes: None,
+            output_bytes: None,
+            termination: Termination::Unknown,
+        }),
+        start,
+        partial_usage,
+        monitor: state.monitor.clone(),
+        budget_key: budget_key.clone(),
+    };
+
+    let mut response = next.run(request).await;
+    let mut log = match pending.disarm() {
+        Ok(log) => log,
+        Err(e) => {
+            tracing::error!("[Monitor] Skipping request log: {}", e);
+            return response;
+        }
+    };
+
+    // tee 调试模式: 处理器通过响应头告知 trace id, 原始上游流在请求结束后并入日志
+    let raw_transcript_id = response
+        .headers_mut()
+        .remove(RAW_TRANSCRIPT_HEADER)
+        .and_then(|v| v.to_str().ok().map(|s| s.to_string()));
     
     let duration = start.elapsed().as_millis() as u64;
     let status = response.status().as_u16();
@@ -80,7 +171,14 @@ pub async fn monitor_middleware(
         .headers()
         .get("X-Account-Email")
         .and_then(|v| v.to_str().ok())
-        .map(|s| s.to_string());
+        .map(decode_header_value);
+
+    // 分发决策: provider 转发的响应携带决策扩展; 其余带账号的响应由 Google 账号池处理
+    let mut provider_decision = response.extensions().get::<ProviderDecision>().cloned();
+    if provider_decision.is_none() && account_email.is_some() {
+        set_header_lossy(&mut response, PROVIDER_HEADER, GOOGLE_PROVIDER);
+        provider_decision = Some(ProviderDecision::google());
+    }
 
     // Extract mapped model from X-Mapped-Model header if present
     let mapped_model = response
@@ -89,63 +187,75 @@ pub async fn monitor_middleware(
         .and_then(|v| v.to_str().ok())
         .map(|s| s.to_string());
 
-    // Determine protocol from URL path
-    let protocol = if uri.contains("/v1/messages") {
-        Some("anthropic".to_string())
-    } else if uri.contains("/v1beta/models") {
-        Some("gemini".to_string())
-    } else if uri.starts_with("/v1/") {
-        Some("openai".to_string())
-    } else {
-        None
-    };
+    // Extract effective service tier from X-Service-Tier header if present
+    let service_tier = response
+        .headers()
+        .get("X-Service-Tier")
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string());
+
+    // 上游 responseId / modelVersion: 头部只含首个分片前已知的值, 流结束后再从槽位补齐
+    let upstream_ids = response.extensions().get::<UpstreamIdsSlot>().cloned();
+    let upstream_response_id = response
+        .headers()
+        .get(RESPONSE_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string());
+    let upstream_model_version = response
+        .headers()
+        .get(MODEL_VERSION_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string());
+
+    // 原始消息调试模式标记
+    let raw_messages = response
+        .headers()
+        .contains_key(RAW_MESSAGES_RESPONSE_HEADER)
+        .then_some(true);
+    let system_injections = response
+        .extensions()
+        .get::<AppliedInjections>()
+        .map(|applied| applied.0.clone());
+
+    let account_rotations = response
+        .extensions()
+        .get::<AccountRotations>()
+        .map(|rotations| rotations.0.clone());
+    let byte_usage = response.extensions().get::<ByteUsage>().copied();
+    let marked_termination = marked_termination(&response);
 
     let monitor = state.monitor.clone();
-    let mut log = ProxyRequestLog {
-        id: uuid::Uuid::new_v4().to_string(),
-        timestamp: chrono::Utc::now().timestamp_millis(),
-        method,
-        url: uri,
-        status,
-        duration,
-        model,
-        mapped_model,
-        account_email,
-        client_ip: None, // TODO: Extract from request headers if available
-        error: None,
-        request_body: request_body_str,
-        response_body: None,
-        input_tokens: None,
-        output_tokens: None,
-        protocol,
-    };
+    log.status = status;
+    log.duration = duration;
+    log.mapped_model = mapped_model;
+    log.account_email = account_email;
+    log.service_tier = service_tier;
+    log.upstream_response_id = upstream_response_id;
+    log.upstream_model_version = upstream_model_version;
+    log.provider_decision = provider_decision;
+    log.raw_messages = raw_messages;
+    log.system_injections = system_injections;
+    log.account_rotations = account_rotations;
+    if let Some(termination) = marked_termination {
+        log.termination = termination;
+    }
+    if let Some(usage) = byte_usage {
+        log.input_bytes = Some(usage.input_bytes);
+    }
 
     if content_type.contains("text/event-stream") {
         let (parts, body) = response.into_parts();
-        let mut stream = body.into_data_stream();
+        let stream = body.into_data_stream();
         let (tx, rx) = tokio::sync::mpsc::channel(64);
         
         tokio::spawn(async move {
-            let mut all_stream_data = Vec::new();
-            let mut last_few_bytes = Vec::new();
-            
-            while let Some(chunk_res) = stream.next().await {
-                if let Ok(chunk) = chunk_res {
-                    all_stream_data.extend_from_slice(&chunk);
-                    
-                    if chunk.len() > 8192 {
-                        last_few_bytes = chunk.slice(chunk.len()-8192..).to_vec();
-                    } else {
-                        last_few_bytes.extend_from_slice(&chunk);
-                        if last_few_bytes.len() > 8192 {
-                            last_few_bytes.drain(0..last_few_bytes.len()-8192);
-                        }
-                    }
-                    let _ = tx.send(Ok::<_, axum::Error>(chunk)).await;
-                } else if let Err(e) = chunk_res {
-                    let _ = tx.send(Err(axum::Error::new(e))).await;
-                }
-            }
+            let relay = relay_stream(stream, tx).await;
+            let all_stream_data = relay.data;
+            let last_few_bytes = relay.tail;
+            let mut message_start_input: Option<u32> = None;
+            let mut partial_output_estimate: Option<u32> = None;
+            // 200 流中途的 error 事件 (Claude error 事件 / OpenAI error 分片): (状态码, 结构化原因)
+            let mut stream_error: Option<(u16, String)> = None;
             
             // Parse and consolidate stream data into readable format
             if let Ok(full_response) = std::str::from_utf8(&all_stream_data) {
@@ -203,6 +313,29 @@ pub async fn monitor_middleware(
                             }
                         }
 
+                        let is_error_event = json.get("type").and_then(|v| v.as_str()) == Some("error")
+                            || json.get("choices").is_none();
+                        if let Some(error) = json.get("error").filter(|e| is_error_event && e.is_object()) {
+                            let error_type = error.get("type").and_then(|v| v.as_str()).unwrap_or("error");
+                            let reason = error.get("code").and_then(|v| v.as_str()).unwrap_or(error_type);
+                            let message = error.get("message").and_then(|v| v.as_str()).unwrap_or_default();
+                            let status = match error_type {
+                                "invalid_request_error" => 400,
+                                "upstream_stalled" => 504,
+                                _ => 500,
+                            };
+                            stream_error = Some((status, format!("{}: {}", reason, message)));
+                        }
+
+                        // Claude message_start 携带 input_tokens (取消时用于部分统计)
+                        if let Some(input) = json.get("message")
+                            .and_then(|m| m.get("usage"))
+                            .and_then(|u| u.get("input_tokens"))
+                            .and_then(|v| v.as_u64())
+                        {
+                            message_start_input = Some(input as u32);
+                        }
+
                         if let Some(content_block) = json.get("content_block") {
                             if content_block.get("type").and_then(|v| v.as_str()) == Some("tool_use") {
                                 claude_tool_uses.push(content_block.clone());
@@ -235,6 +368,11 @@ pub async fn monitor_middleware(
                     }
                 }
                 
+                if relay.cancelled {
+                    partial_output_estimate = Some(estimate_tokens_from_str(&thinking_content)
+                        + estimate_tokens_from_str(&response_content));
+                }
+
                 // Build consolidated response object
                 let mut consolidated = serde_json::Map::new();
                 
@@ -299,9 +437,37 @@ pub async fn monitor_middleware(
                 }
             }
             
-            if log.status >= 400 {
+            if relay.cancelled {
+                // 客户端中途断开: 记录为 cancelled, 保留已产生的部分 token
+                log.status = STATUS_CLIENT_CANCELLED;
+                log.error = Some(CLIENT_CANCELLED_ERROR.to_string());
+                log.duration = start.elapsed().as_millis() as u64;
+                if log.input_tokens.is_none() {
+                    log.input_tokens = message_start_input;
+                }
+                if log.output_tokens.is_none() {
+                    log.output_tokens = partial_output_estimate;
+                }
+            } else if let Some(error) = relay.upstream_error {
+                // 上游流读取出错 (连接重置 / 读取超时): 与客户端断开分开记录
+                log.status = log.status.max(502);
+                log.error = Some(format!("upstream stream error: {}", error));
+                log.duration = start.elapsed().as_millis() as u64;
+            } else if let Some((status, reason)) = stream_error {
+                // 流已以 200 开始, 但以错误事件结束 (如上下文超限): 记为失败并保留结构化原因
+                log.status = log.status.max(status);
+                log.error = Some(reason);
+            } else if log.status >= 400 {
                 log.error = Some("Stream Error or Failed".to_string());
             }
+            log.raw_upstream = raw_transcript_id.as_deref().and_then(take_raw_transcript);
+            if let Some(ids) = upstream_ids.map(|slot| slot.get()) {
+                log.upstream_response_id = log.upstream_response_id.take().or(ids.response_id);
+                log.upstream_model_version = log.upstream_model_version.take().or(ids.model_version);
+            }
+            if let Some(key) = budget_key.as_deref() {
+                key_budget::record_usage(key, budget_tokens(&log, true));
+            }
             monitor.log_request(log).await;
         });
 
@@ -341,6 +507,10 @@ pub async fn monitor_middleware(
                 if log.status >= 400 {
                     log.error = log.response_body.clone();
                 }
+                log.raw_upstream = raw_transcript_id.as_deref().and_then(take_raw_transcript);
+                if let Some(key) = budget_key.as_deref() {
+                    key_budget::record_usage(key, budget_tokens(&log, false));
+                }
                 monitor.log_request(log).await;
                 Response::from_parts(parts, Body::from(bytes))
             }
@@ -350,9 +520,386 @@ pub async fn monitor_middleware(
                 Response::from_parts(parts, Body::empty())
             }
         }
+    } else if byte_usage.is_some() {
+        // 按字节计量的流式二进制响应 (如语音合成): 转发结束后按实际写出的字节数记录输出
+        let (parts, body) = response.into_parts();
+        let (tx, rx) = tokio::sync::mpsc::channel(64);
+
+        tokio::spawn(async move {
+            let relay = relay_stream(body.into_data_stream(), tx).await;
+            log.output_bytes = Some(relay.data.len() as u64);
+            log.response_body = Some(format!("[{}: {} bytes]", content_type, relay.data.len()));
+            log.duration = start.elapsed().as_millis() as u64;
+            if relay.cancelled {
+                log.status = STATUS_CLIENT_CANCELLED;
+                log.error = Some(CLIENT_CANCELLED_ERROR.to_string());
+            } else if let Some(error) = relay.upstream_error {
+                log.status = log.status.max(502);
+                log.error = Some(format!("upstream stream error: {}", error));
+            }
+            monitor.log_request(log).await;
+        });
+
+        Response::from_parts(parts, Body::from_stream(tokio_stream::wrappers::ReceiverStream::new(rx)))
     } else {
         log.response_body = Some(format!("[{}]", content_type));
         monitor.log_request(log).await;
         response
     }
 }
+
+/// 计入 API Key 预算的 token 数
+///
+/// 流在用量返回前中断 (上游出错或客户端断开) 时, 按校准后的请求输入估算
+fn budget_tokens(log: &ProxyRequestLog, estimate_missing: bool) -> u64 {
+    match (log.input_tokens, log.output_tokens) {
+        (None, None) if estimate_missing => log
+            .request_body
+            .as_deref()
+            .map(|body| get_calibrator().calibrate(estimate_tokens_from_str(body)) as u64)
+            .unwrap_or(0),
+        (input, output) => input.unwrap_or(0) as u64 + output.unwrap_or(0) as u64,
+    }
+}
+
+/// 处理器返回响应前已产生的部分用量
+///
+/// 由监控中间件放入请求扩展; 处理器在自行消费上游流 (如非流式客户端的流收集) 时更新,
+/// 客户端中途断开时用于补记部分 token
+#[derive(Debug, Clone, Default)]
+pub struct PartialUsage(Arc<Mutex<PartialUsageState>>);
+
+#[derive(Debug, Default)]
+struct PartialUsageState {
+    account_email: Option<String>,
+    input_tokens: Option<u32>,
+    output_tokens: u32,
+}
+
+impl PartialUsage {
+    pub fn set_account(&self, email: &str) {
+        if let Ok(mut state) = self.0.lock() {
+            state.account_email = Some(email.to_string());
+        }
+    }
+
+    /// 统计一个 Claude SSE 分片: message_start 的 input_tokens 与增量输出的估算 token
+    pub fn observe_claude_sse(&self, chunk: &[u8]) {
+        let Ok(mut state) = self.0.lock() else {
+            return;
+        };
+        for line in String::from_utf8_lossy(chunk).lines() {
+            let Some(data) = line.trim().strip_prefix("data:") else {
+                continue;
+            };
+            let Ok(event) = serde_json::from_str::<Value>(data.trim()) else {
+                continue;
+            };
+            if let Some(input) = event["message"]["usage"]["input_tokens"].as_u64() {
+                state.input_tokens = Some(input as u32);
+            }
+            let delta = &event["delta"];
+            for key in ["text", "thinking", "partial_json"] {
+                if let Some(text) = delta.get(key).and_then(|v| v.as_str()) {
+                    state.output_tokens += estimate_tokens_from_str(text);
+                }
+            }
+        }
+    }
+}
+
+/// 处理器通过响应扩展显式标记的终止方式 (目前只有本地失败); 未标记时由状态码与错误信息推断
+fn marked_termination(response: &Response) -> Option<Termination> {
+    response
+        .extensions()
+        .get::<LocalFailure>()
+        .map(|_| Termination::LocalError)
+}
+
+/// 响应返回前客户端断开时, axum 直接丢弃中间件 future, 处理器持有的上游流随之释放;
+/// 该守卫在被丢弃时补记一条 client_cancelled 日志
+struct CancelledRequestGuard {
+    log: Option<ProxyRequestLog>,
+    start: Instant,
+    partial_usage: PartialUsage,
+    monitor: Arc<ProxyMonitor>,
+    budget_key: Option<String>,
+}
+
+impl CancelledRequestGuard {
+    /// 处理器已返回响应: 取回日志骨架继续填充 (只能取回一次)
+    fn disarm(&mut self) -> Result<ProxyRequestLog, &'static str> {
+        self.log.take().ok_or("request log already taken")
+    }
+}
+
+impl Drop for CancelledRequestGuard {
+    fn drop(&mut self) {
+        let Some(mut log) = self.log.take() else {
+            return;
+        };
+        // 处理器 panic 时 future 也会在展开过程中被丢弃, 此时不是客户端断开
+        let panicked = std::thread::panicking();
+        if panicked {
+            log.status = 500;
+            log.error = Some("local_error: handler panicked".to_string());
+            log.termination = Termination::LocalError;
+        } else {
+            log.status = STATUS_CLIENT_CANCELLED;
+            log.error = Some(CLIENT_CANCELLED_ERROR.to_string());
+            log.termination = Termination::ClientCancelled;
+        }
+        log.duration = self.start.elapsed().as_millis() as u64;
+        if let Ok(state) = self.partial_usage.0.lock() {
+            log.account_email = state.account_email.clone();
+            log.input_tokens = state.input_tokens;
+            log.output_tokens = (state.output_tokens > 0).then_some(state.output_tokens);
+        }
+        if panicked {
+            tracing::error!("[Monitor] Handler panicked while serving {}", log.url);
+        } else {
+            tracing::info!("[Monitor] Client cancelled {} before the response was ready", log.url);
+        }
+        if let Some(key) = self.budget_key.as_deref() {
+            key_budget::record_usage(key, budget_tokens(&log, true));
+        }
+        let monitor = self.monitor.clone();
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            handle.spawn(async move {
+                monitor.log_request(log).await;
+            });
+        }
+    }
+}
+
+/// SSE 转发结果
+struct RelayOutcome {
+    data: Vec<u8>,
+    tail: Vec<u8>,
+    /// 客户端在流结束前断开
+    cancelled: bool,
+    /// 上游流读取出错 (转发该错误后停止, 不视为客户端断开)
+    upstream_error: Option<String>,
+}
+
+/// 将上游数据流转发给客户端, 同时收集完整内容用于日志
+///
+/// 客户端断开 (axum 丢弃响应 Body) 后立即停止拉取并释放上游流,
+/// reqwest 会随之重置对应的 HTTP/2 stream, 避免上游继续生成计费 token
+async fn relay_stream<S, E>(
+    mut stream: S,
+    tx: tokio::sync::mpsc::Sender<Result<axum::body::Bytes, axum::Error>>,
+) -> RelayOutcome
+where
+    S: futures::Stream<Item = Result<axum::body::Bytes, E>> + Unpin,
+    E: Into<axum::BoxError>,
+{
+    let mut outcome = RelayOutcome {
+        data: Vec::new(),
+        tail: Vec::new(),
+        cancelled: false,
+        upstream_error: None,
+    };
+
+    loop {
+        let chunk_res = tokio::select! {
+            biased;
+            _ = tx.closed() => {
+                outcome.cancelled = true;
+                break;
+            }
+            next = stream.next() => match next {
+                Some(chunk_res) => chunk_res,
+                None => break,
+            },
+        };
+
+        let item = match chunk_res {
+            Ok(chunk) => {
+                outcome.data.extend_from_slice(&chunk);
+
+                if chunk.len() > 8192 {
+                    outcome.tail = chunk.slice(chunk.len() - 8192..).to_vec();
+                } else {
+                    outcome.tail.extend_from_slice(&chunk);
+                    if outcome.tail.len() > 8192 {
+                        outcome.tail.drain(0..outcome.tail.len() - 8192);
+                    }
+                }
+                Ok(chunk)
+            }
+            Err(e) => {
+                // 出错的 Body 随后会中止客户端连接, 之后的发送失败不能再算作客户端断开
+                let error: axum::BoxError = e.into();
+                outcome.upstream_error = Some(error.to_string());
+                let _ = tx.send(Err(axum::Error::new(error))).await;
+                break;
+            }
+        };
+
+        if tx.send(item).await.is_err() {
+            outcome.cancelled = true;
+            break;
+        }
+    }
+
+    drop(stream);
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::response::IntoResponse;
+    use std::time::Duration;
+
+    struct DropSignal(Option<tokio::sync::oneshot::Sender<()>>);
+
+    impl Drop for DropSignal {
+        fn drop(&mut self) {
+            if let Some(tx) = self.0.take() {
+                let _ = tx.send(());
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_client_disconnect_closes_upstream_stream() {
+        let (closed_tx, closed_rx) = tokio::sync::oneshot::channel();
+
+        // mock 上游: 先发一个分片, 之后一直挂起; 连接被关闭时 DropSignal 触发
+        let upstream = async_stream::stream! {
+            let _guard = DropSignal(Some(closed_tx));
+            yield Ok::<_, std::io::Error>(axum::body::Bytes::from_static(
+                b"data: {\"type\":\"message_start\",\"message\":{\"usage\":{\"input_tokens\":12}}}\n\n",
+            ));
+            loop {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                yield Ok(axum::body::Bytes::from_static(b"data: {\"delta\":{\"text\":\"tick\"}}\n\n"));
+            }
+        };
+        let upstream_resp = reqwest::Response::from(
+            axum::http::Response::builder()
+                .body(reqwest::Body::wrap_stream(upstream))
+                .unwrap(),
+        );
+        let stream = Body::from_stream(upstream_resp.bytes_stream()).into_data_stream();
+
+        let (tx, rx) = tokio::sync::mpsc::channel(64);
+        let relay = tokio::spawn(relay_stream(stream, tx));
+
+        let mut client = Body::from_stream(tokio_stream::wrappers::ReceiverStream::new(rx))
+            .into_data_stream();
+        assert!(client.next().await.unwrap().is_ok());
+        assert!(client.next().await.unwrap().is_ok());
+        drop(client);
+
+        tokio::time::timeout(Duration::from_secs(2), closed_rx)
+            .await
+            .expect("upstream stream was not closed after client disconnect")
+            .unwrap();
+
+        let outcome = tokio::time::timeout(Duration::from_secs(2), relay)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(outcome.cancelled);
+        assert!(!outcome.data.is_empty());
+    }
+
+    #[test]
+    fn test_partial_usage_counts_claude_events() {
+        let usage = PartialUsage::default();
+        usage.set_account("a@example.com");
+        usage.observe_claude_sse(
+            b"event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"usage\":{\"input_tokens\":42}}}\n\n",
+        );
+        usage.observe_claude_sse(
+            b"data: {\"type\":\"content_block_delta\",\"delta\":{\"type\":\"text_delta\",\"text\":\"hello world\"}}\n\n",
+        );
+
+        let state = usage.0.lock().unwrap();
+        assert_eq!(state.account_email.as_deref(), Some("a@example.com"));
+        assert_eq!(state.input_tokens, Some(42));
+        assert_eq!(state.output_tokens, estimate_tokens_from_str("hello world"));
+    }
+
+    #[tokio::test]
+    async fn test_relay_completes_without_cancel() {
+        let upstream = futures::stream::iter(vec![
+            Ok::<_, std::io::Error>(axum::body::Bytes::from_static(b"data: a\n\n")),
+            Ok(axum::body::Bytes::from_static(b"data: b\n\n")),
+        ]);
+        let (tx, mut rx) = tokio::sync::mpsc::channel(64);
+        let relay = tokio::spawn(relay_stream(upstream, tx));
+
+        let mut received = 0;
+        while rx.recv().await.is_some() {
+            received += 1;
+        }
+        let outcome = relay.await.unwrap();
+        assert_eq!(received, 2);
+        assert!(!outcome.cancelled);
+        assert_eq!(outcome.data, b"data: a\n\ndata: b\n\n");
+    }
+
+    #[tokio::test]
+    async fn test_relay_upstream_error_is_not_a_cancel() {
+        let upstream = futures::stream::iter(vec![
+            Ok::<_, std::io::Error>(axum::body::Bytes::from_static(b"data: a\n\n")),
+            Err(std::io::Error::new(std::io::ErrorKind::ConnectionReset, "connection reset by peer")),
+            Ok(axum::body::Bytes::from_static(b"data: never\n\n")),
+        ]);
+        let (tx, rx) = tokio::sync::mpsc::channel(64);
+        let relay = tokio::spawn(relay_stream(upstream, tx));
+
+        // 客户端 Body 收到错误后即被丢弃 (与 hyper 中止连接的行为一致)
+        let mut client = Body::from_stream(tokio_stream::wrappers::ReceiverStream::new(rx))
+            .into_data_stream();
+        assert!(client.next().await.unwrap().is_ok());
+        assert!(client.next().await.unwrap().is_err());
+        drop(client);
+
+        let outcome = relay.await.unwrap();
+        assert!(!outcome.cancelled);
+        assert!(outcome.upstream_error.unwrap().contains("connection reset"));
+        assert_eq!(outcome.data, b"data: a\n\n");
+    }
+
+    #[test]
+    fn test_classify_terminations() {
+        assert_eq!(Termination::classify(200, None), Termination::Success);
+        assert_eq!(Termination::classify(STATUS_CLIENT_CANCELLED, Some(CLIENT_CANCELLED_ERROR)), Termination::ClientCancelled);
+        assert_eq!(Termination::classify(429, Some("RESOURCE_EXHAUSTED")), Termination::UpstreamError);
+        assert_eq!(Termination::classify(504, None), Termination::Timeout);
+        assert_eq!(
+            Termination::classify(500, Some("api_error: Upstream stream stalled: no data for 60s")),
+            Termination::Timeout
+        );
+        assert_eq!(
+            Termination::classify(502, Some("upstream stream error: operation timed out")),
+            Termination::Timeout
+        );
+        // 本地失败只认响应扩展, 不再从错误文本推断
+        assert_eq!(
+            Termination::classify(500, Some(r#"{"error":{"message":"Transform error: bad tool"}}"#)),
+            Termination::UpstreamError
+        );
+        let local = crate::proxy::handlers::common::mark_local_failure(
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        );
+        assert_eq!(marked_termination(&local), Some(Termination::LocalError));
+        assert_eq!(marked_termination(&axum::http::StatusCode::BAD_GATEWAY.into_response()), None);
+
+        // 旧日志没有 termination 字段
+        let legacy: ProxyRequestLog = serde_json::from_value(serde_json::json!({
+            "id": "1", "timestamp": 0, "method": "POST", "url": "/v1/messages", "status": 200, "duration": 1,
+            "model": null, "mapped_model": null, "account_email": null, "client_ip": null, "error": null,
+            "request_body": null, "response_body": null, "input_tokens": null, "output_tokens": null, "protocol": null
+        }))
+        .unwrap();
+        assert_eq!(legacy.termination, Termination::Unknown);
+        assert_eq!(Termination::parse(None).as_str(), "unknown");
+        assert_eq!(Termination::parse(Some("timeout")), Termination::Timeout);
+    }
+}
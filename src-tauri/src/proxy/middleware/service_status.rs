@@ -1,9 +1,14 @@
 use axum::{
+    body::Body,
     extract::{Request, State},
     middleware::Next,
     response::{IntoResponse, Response},
     http::StatusCode,
 };
+use futures::StreamExt;
+use crate::proxy::handlers::claude::is_warmup_request;
+use crate::proxy::maintenance::{self, InFlightGuard};
+use crate::proxy::mappers::claude::ClaudeRequest;
 use crate::proxy::server::AppState;
 
 pub async fn service_status_middleware(
@@ -12,7 +17,7 @@ pub async fn service_status_middleware(
     next: Next,
 ) -> Response {
     let path = request.uri().path();
-    
+
     // Always allow Admin API and Auth callback
     if path.starts_with("/api/") || path == "/auth/callback" || path == "/health" {
         return next.run(request).await;
@@ -31,5 +36,58 @@ pub async fn service_status_middleware(
             .into_response();
     }
 
-    next.run(request).await
+    // 维护模式: 拒绝新请求, 但 Warmup 探测与内部端点照常应答
+    let request = if maintenance::is_active() && !path.starts_with("/internal/") && path != "/healthz" {
+        match admit_during_maintenance(request).await {
+            Ok(request) => request,
+            Err(response) => return response,
+        }
+    } else {
+        request
+    };
+
+    // 进行中请求计数 (维护模式据此报告剩余的排空数量)
+    let guard = InFlightGuard::acquire();
+    let response = next.run(request).await;
+
+    let is_stream = response
+        .headers()
+        .get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .map(|ct| ct.contains("text/event-stream"))
+        .unwrap_or(false);
+    if !is_stream {
+        return response;
+    }
+
+    // 流式响应: 计数随响应体释放, 流结束或客户端断开时才算完成
+    let (parts, body) = response.into_parts();
+    let stream = body.into_data_stream().map(move |chunk| {
+        let _ = &guard;
+        chunk
+    });
+    Response::from_parts(parts, Body::from_stream(stream))
+}
+
+/// 维护期间只放行 Claude Code 的 Warmup 请求 (由 handler 直接拦截应答, 不消耗上游)
+async fn admit_during_maintenance(request: Request) -> Result<Request, Response> {
+    let Some(rejection) = maintenance::rejection(request.uri().path()) else {
+        // 维护窗口恰好到期
+        return Ok(request);
+    };
+    if request.uri().path() != "/v1/messages" {
+        return Err(rejection);
+    }
+
+    let (parts, body) = request.into_parts();
+    let Ok(bytes) = axum::body::to_bytes(body, crate::proxy::server::max_body_size()).await else {
+        return Err(rejection);
+    };
+    let is_warmup = serde_json::from_slice::<ClaudeRequest>(&bytes)
+        .map(|req| is_warmup_request(&req))
+        .unwrap_or(false);
+    if !is_warmup {
+        return Err(rejection);
+    }
+    Ok(Request::from_parts(parts, Body::from(bytes)))
 }
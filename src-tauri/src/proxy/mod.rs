@@ -25,6 +25,15 @@ pub mod signature_cache;   // Signature Cache (v3.3.16)
 pub mod cli_sync;          // CLI 配置同步 (v3.3.35)
 pub mod opencode_sync;     // OpenCode 配置同步
 pub mod debug_logger;      // 调试日志
+pub mod benchmark;         // 进程内基准测试 (诊断)
+pub mod warm_pool;         // 模型预热池
+pub mod failure_patterns;  // 上游失败模式监控
+pub mod model_health;      // 模型健康度 (按物理模型的成功率 / 错误签名 / 延迟)
+pub mod key_budget;        // API Key 每日预算
+pub mod maintenance;       // 维护模式 (停止接收新请求)
+pub mod session_prewarm;   // 新会话隐式缓存预热 (CacheFirst)
+pub mod ports;             // 默认端口选择与客户端接入地址
+pub mod image_store;       // 生成图片临时存储 (response_format=url)
 
 
 pub use config::ProxyConfig;
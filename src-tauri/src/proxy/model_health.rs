@@ -0,0 +1,369 @@
+// 模型健康度
+// 账号健康之外还需要模型健康: 按实际路由的物理模型统计成功率、常见错误签名与延迟中位数,
+// 让用户一眼看出 "gemini-3-pro 这一小时不稳定, flash 正常"。
+// 窗口按 15 分钟分桶、保留 6 小时, 过期桶随记录与查询淘汰; 跟踪的模型数、每桶延迟样本数与
+// 签名数都有上限, 内存占用不随出现过的模型数量增长。成功率低于阈值的模型标记为 degraded,
+// 供 /v1/models?available=true 等路由逻辑通过 is_degraded 查询。
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use crate::proxy::failure_patterns::{error_signature, SignatureCount};
+
+/// 分桶长度 (秒)
+const BUCKET_SECS: i64 = 15 * 60;
+/// 保留的桶数 (6 小时)
+const MAX_BUCKETS: usize = 24;
+/// 判定 degraded 使用最近的桶数 (1 小时)
+const RECENT_BUCKETS: usize = 4;
+/// 最近窗口内至少这么多请求才判定, 避免少量请求误报
+const MIN_RECENT_REQUESTS: u64 = 10;
+/// 最近窗口成功率低于该值时标记为 degraded
+const DEGRADED_SUCCESS_RATE: f64 = 0.8;
+/// 最多跟踪的模型数, 超出时淘汰最久未出现的模型
+const MAX_MODELS: usize = 64;
+/// 每桶保留的延迟样本数
+const MAX_LATENCY_SAMPLES: usize = 128;
+/// 每桶保留的错误签名数 (超出的失败只计数)
+const MAX_BUCKET_SIGNATURES: usize = 8;
+/// 返回的常见错误签名数
+const TOP_SIGNATURES: usize = 5;
+
+/// 单个时间桶的请求计数
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HealthBucket {
+    /// 桶起始时间 (unix 秒)
+    pub start: i64,
+    pub success: u64,
+    pub failure: u64,
+}
+
+/// 单个模型的健康度 (get_model_health 返回)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ModelHealth {
+    /// 物理模型名 (mapped_model)
+    pub model: String,
+    /// 6 小时窗口内的请求数与成功率
+    pub requests: u64,
+    pub success_rate: f64,
+    /// 最近 1 小时的请求数与成功率
+    pub recent_requests: u64,
+    pub recent_success_rate: f64,
+    /// 成功请求的延迟中位数 (ms)
+    pub median_latency_ms: Option<u64>,
+    pub top_errors: Vec<SignatureCount>,
+    /// 最近 1 小时成功率低于阈值
+    pub degraded: bool,
+    /// 按时间升序的分桶计数 (用于趋势展示)
+    pub buckets: Vec<HealthBucket>,
+}
+
+#[derive(Debug, Default)]
+struct Bucket {
+    counts: HealthBucket,
+    latencies: Vec<u64>,
+    signatures: HashMap<String, u64>,
+}
+
+impl Bucket {
+    fn new(start: i64) -> Self {
+        Self {
+            counts: HealthBucket { start, ..Default::default() },
+            ..Default::default()
+        }
+    }
+
+    fn record(&mut self, success: bool, latency_ms: u64, signature: Option<String>) {
+        if success {
+            // 样本满后循环覆盖, 保持每桶内存固定
+            let seen = self.counts.success as usize;
+            if self.latencies.len() < MAX_LATENCY_SAMPLES {
+                self.latencies.push(latency_ms);
+            } else {
+                self.latencies[seen % MAX_LATENCY_SAMPLES] = latency_ms;
+            }
+            self.counts.success += 1;
+            return;
+        }
+        self.counts.failure += 1;
+        if let Some(signature) = signature {
+            if self.signatures.len() < MAX_BUCKET_SIGNATURES
+                || self.signatures.contains_key(&signature)
+            {
+                *self.signatures.entry(signature).or_default() += 1;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct ModelWindow {
+    buckets: VecDeque<Bucket>,
+}
+
+impl ModelWindow {
+    fn prune(&mut self, now: i64) {
+        let oldest = bucket_start(now) - (MAX_BUCKETS as i64 - 1) * BUCKET_SECS;
+        while self.buckets.front().is_some_and(|b| b.counts.start < oldest) {
+            self.buckets.pop_front();
+        }
+    }
+
+    fn bucket_mut(&mut self, now: i64) -> &mut Bucket {
+        let start = bucket_start(now);
+        if !self.buckets.back().is_some_and(|b| b.counts.start >= start) {
+            self.buckets.push_back(Bucket::new(start));
+        }
+        self.buckets.back_mut().expect("bucket just pushed")
+    }
+
+    fn last_seen(&self) -> i64 {
+        self.buckets.back().map(|b| b.counts.start).unwrap_or(i64::MIN)
+    }
+
+    /// 最近 RECENT_BUCKETS 个时间桶 (按时间而非条目数) 的 (请求数, 成功数)
+    fn recent(&self, now: i64) -> (u64, u64) {
+        let since = bucket_start(now) - (RECENT_BUCKETS as i64 - 1) * BUCKET_SECS;
+        self.buckets
+            .iter()
+            .filter(|b| b.counts.start >= since)
+            .fold((0, 0), |(total, ok), b| {
+                (total + b.counts.success + b.counts.failure, ok + b.counts.success)
+            })
+    }
+
+    fn is_degraded(&self, now: i64) -> bool {
+        let (total, ok) = self.recent(now);
+        total >= MIN_RECENT_REQUESTS && (ok as f64) < total as f64 * DEGRADED_SUCCESS_RATE
+    }
+
+    fn health(&self, model: &str, now: i64) -> ModelHealth {
+        let success: u64 = self.buckets.iter().map(|b| b.counts.success).sum();
+        let failure: u64 = self.buckets.iter().map(|b| b.counts.failure).sum();
+        let (recent_requests, recent_success) = self.recent(now);
+
+        let mut latencies: Vec<u64> = self
+            .buckets
+            .iter()
+            .flat_map(|b| b.latencies.iter().copied())
+            .collect();
+        latencies.sort_unstable();
+        let median_latency_ms = latencies.get(latencies.len() / 2).copied();
+
+        let mut signatures: HashMap<&str, u64> = HashMap::new();
+        for (signature, count) in self.buckets.iter().flat_map(|b| b.signatures.iter()) {
+            *signatures.entry(signature).or_default() += count;
+        }
+        let mut top_errors: Vec<SignatureCount> = signatures
+            .into_iter()
+            .map(|(signature, count)| SignatureCount {
+                signature: signature.to_string(),
+                count: count as usize,
+            })
+            .collect();
+        top_errors.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.signature.cmp(&b.signature)));
+        top_errors.truncate(TOP_SIGNATURES);
+
+        ModelHealth {
+            model: model.to_string(),
+            requests: success + failure,
+            success_rate: rate(success, success + failure),
+            recent_requests,
+            recent_success_rate: rate(recent_success, recent_requests),
+            median_latency_ms,
+            top_errors,
+            degraded: self.is_degraded(now),
+            buckets: self.buckets.iter().map(|b| b.counts.clone()).collect(),
+        }
+    }
+}
+
+fn bucket_start(ts: i64) -> i64 {
+    ts - ts.rem_euclid(BUCKET_SECS)
+}
+
+fn rate(ok: u64, total: u64) -> f64 {
+    if total == 0 {
+        1.0
+    } else {
+        ok as f64 / total as f64
+    }
+}
+
+/// 是否计入模型健康度: 只统计上游侧的结果 (成功、429 与 5xx);
+/// 其余 4xx (参数错误、鉴权、客户端取消等) 与上下文超限都是请求侧的问题, 不代表模型不健康
+fn is_counted(status: u16, error: Option<&str>) -> bool {
+    let prompt_too_long = error.is_some_and(|e| {
+        e.starts_with(crate::proxy::mappers::error_classifier::PROMPT_TOO_LONG_CODE)
+    });
+    !prompt_too_long && matches!(status, 200..=399 | 429 | 500..=599)
+}
+
+#[derive(Default)]
+struct ModelHealthStore {
+    models: HashMap<String, ModelWindow>,
+}
+
+impl ModelHealthStore {
+    fn record(&mut self, now: i64, model: &str, status: u16, latency_ms: u64, error: Option<&str>) {
+        if !is_counted(status, error) {
+            return;
+        }
+        let key = model.to_lowercase();
+        if !self.models.contains_key(&key) && self.models.len() >= MAX_MODELS {
+            let oldest = self
+                .models
+                .iter()
+                .min_by_key(|(_, w)| w.last_seen())
+                .map(|(k, _)| k.clone());
+            if let Some(oldest) = oldest {
+                self.models.remove(&oldest);
+            }
+        }
+
+        let success = (200..400).contains(&status);
+        let signature = (!success).then(|| error_signature(status, error.unwrap_or("")));
+        let window = self.models.entry(key).or_default();
+        let was_degraded = window.is_degraded(now);
+        window.prune(now);
+        window.bucket_mut(now).record(success, latency_ms, signature);
+
+        let degraded = window.is_degraded(now);
+        if degraded && !was_degraded {
+            tracing::warn!(
+                "[ModelHealth] {} is degraded: success rate in the last hour dropped below {}%",
+                model,
+                DEGRADED_SUCCESS_RATE * 100.0
+            );
+        } else if !degraded && was_degraded {
+            tracing::info!("[ModelHealth] {} recovered", model);
+        }
+    }
+
+    fn snapshot(&mut self, now: i64) -> Vec<ModelHealth> {
+        for window in self.models.values_mut() {
+            window.prune(now);
+        }
+        self.models.retain(|_, w| !w.buckets.is_empty());
+        let mut list: Vec<ModelHealth> = self
+            .models
+            .iter()
+            .map(|(model, window)| window.health(model, now))
+            .collect();
+        list.sort_by(|a, b| a.model.cmp(&b.model));
+        list
+    }
+}
+
+static STORE: Lazy<Mutex<ModelHealthStore>> = Lazy::new(|| Mutex::new(ModelHealthStore::default()));
+
+/// 记录一次请求结果 (由 ProxyMonitor 调用, model 为实际路由的物理模型)
+pub fn record_outcome(model: &str, status: u16, latency_ms: u64, error: Option<&str>) {
+    STORE
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .record(chrono::Utc::now().timestamp(), model, status, latency_ms, error);
+}
+
+/// 各模型的健康度 (按模型名排序)
+pub fn snapshot() -> Vec<ModelHealth> {
+    STORE
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .snapshot(chrono::Utc::now().timestamp())
+}
+
+/// 模型最近 1 小时成功率是否低于阈值
+pub fn is_degraded(model: &str) -> bool {
+    STORE
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .models
+        .get(&model.to_lowercase())
+        .is_some_and(|w| w.is_degraded(chrono::Utc::now().timestamp()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OVERLOADED: &str = r#"{"error":{"code":503,"message":"The model is overloaded. Please try again later.","status":"UNAVAILABLE"}}"#;
+
+    #[test]
+    fn test_degraded_model_is_flagged_and_recovers() {
+        let mut store = ModelHealthStore::default();
+        let now = 1_760_000_000;
+        for i in 0..6 {
+            store.record(now, "gemini-3-pro-preview", 200, 1000 + i * 100, None);
+            store.record(now, "gemini-3-flash", 200, 300, None);
+        }
+        for _ in 0..6 {
+            store.record(now, "gemini-3-pro-preview", 503, 50, Some(OVERLOADED));
+        }
+        // 请求侧问题 (客户端取消、鉴权、参数错误、内容过大) 不计入
+        for status in [499, 401, 400, 404, 413, 422] {
+            store.record(now, "gemini-3-flash", status, 10, None);
+        }
+        store.record(now, "gemini-3-flash", 429, 10, None);
+
+        let health = store.snapshot(now);
+        assert_eq!(health.len(), 2);
+        let flash = &health[0];
+        assert_eq!(flash.model, "gemini-3-flash");
+        // 只有上游限流 (429) 计入
+        assert_eq!(flash.requests, 7);
+        assert!(!flash.degraded);
+
+        let pro = &health[1];
+        assert_eq!(pro.requests, 12);
+        assert!(pro.degraded);
+        assert_eq!(pro.success_rate, 0.5);
+        assert_eq!(pro.median_latency_ms, Some(1300));
+        assert_eq!(pro.top_errors[0].count, 6);
+        assert!(pro.top_errors[0].signature.starts_with("503: The model is overloaded"));
+
+        // 一小时后只看最近窗口: 恢复正常, 但 6 小时窗口仍保留历史
+        let later = now + RECENT_BUCKETS as i64 * BUCKET_SECS;
+        for _ in 0..10 {
+            store.record(later, "gemini-3-pro-preview", 200, 800, None);
+        }
+        let pro = store
+            .snapshot(later)
+            .into_iter()
+            .find(|h| h.model == "gemini-3-pro-preview")
+            .unwrap();
+        assert!(!pro.degraded);
+        assert_eq!(pro.recent_requests, 10);
+        assert_eq!(pro.requests, 22);
+        assert_eq!(pro.buckets.len(), 2);
+
+        // 6 小时后旧桶全部过期
+        let expired = now + MAX_BUCKETS as i64 * BUCKET_SECS;
+        let health = store.snapshot(expired);
+        assert_eq!(health.len(), 1);
+        assert_eq!(health[0].requests, 10);
+    }
+
+    #[test]
+    fn test_memory_is_bounded() {
+        let mut store = ModelHealthStore::default();
+        let now = 1_760_000_000;
+        for i in 0..(MAX_MODELS + 10) {
+            store.record(now + i as i64 * BUCKET_SECS, &format!("model-{}", i), 200, 100, None);
+        }
+        assert_eq!(store.models.len(), MAX_MODELS);
+        assert!(!store.models.contains_key("model-0"));
+
+        for i in 0..1_000u64 {
+            let status = if i % 2 == 0 { 200 } else { 500 };
+            let error = format!("error variant {}", char::from(b'a' + (i % 26) as u8));
+            store.record(now, "gemini-3-flash", status, i, Some(&error));
+        }
+        let bucket = store.models["gemini-3-flash"].buckets.back().unwrap();
+        assert_eq!(bucket.latencies.len(), MAX_LATENCY_SAMPLES);
+        assert_eq!(bucket.signatures.len(), MAX_BUCKET_SIGNATURES);
+        assert_eq!(bucket.counts.failure, 500);
+    }
+}
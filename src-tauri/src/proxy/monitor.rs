@@ -4,6 +4,74 @@ use tokio::sync::RwLock;
 use tauri::Emitter;
 use std::sync::atomic::{AtomicBool, Ordering};
 
+/// 客户端在响应完成前断开 (nginx 约定的 499)
+pub const STATUS_CLIENT_CANCELLED: u16 = 499;
+/// 客户端断开时写入日志的错误标记
+pub const CLIENT_CANCELLED_ERROR: &str = "client_cancelled";
+
+/// 请求的终止方式 (区分客户端断开与服务端错误, 单独计入统计)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Termination {
+    Success,
+    /// 客户端在响应完成前断开
+    ClientCancelled,
+    /// 上游返回错误状态, 或上游流中途出错
+    UpstreamError,
+    /// 代理本地失败 (请求转换错误 / 处理器 panic)
+    LocalError,
+    /// 上游超时或流停滞
+    Timeout,
+    /// 旧版本写入、没有该字段的日志
+    #[default]
+    Unknown,
+}
+
+impl Termination {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Success => "success",
+            Self::ClientCancelled => CLIENT_CANCELLED_ERROR,
+            Self::UpstreamError => "upstream_error",
+            Self::LocalError => "local_error",
+            Self::Timeout => "timeout",
+            Self::Unknown => "unknown",
+        }
+    }
+
+    /// 解析数据库中的取值; 缺失或无法识别的值视为 unknown
+    pub fn parse(value: Option<&str>) -> Self {
+        match value {
+            Some("success") => Self::Success,
+            Some(CLIENT_CANCELLED_ERROR) => Self::ClientCancelled,
+            Some("upstream_error") => Self::UpstreamError,
+            Some("local_error") => Self::LocalError,
+            Some("timeout") => Self::Timeout,
+            _ => Self::Unknown,
+        }
+    }
+
+    /// 根据最终状态码与错误信息推断终止方式 (调用方未显式指定时)。
+    /// 本地失败不从错误文本推断, 由处理器通过 `LocalFailure` 响应扩展标记
+    pub fn classify(status: u16, error: Option<&str>) -> Self {
+        if status == STATUS_CLIENT_CANCELLED {
+            return Self::ClientCancelled;
+        }
+        if (200..400).contains(&status) {
+            return Self::Success;
+        }
+        if status == 408 || status == 504 {
+            return Self::Timeout;
+        }
+        let error = error.unwrap_or_default().to_lowercase();
+        if error.contains("timeout") || error.contains("timed out") || error.contains("stalled") {
+            Self::Timeout
+        } else {
+            Self::UpstreamError
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProxyRequestLog {
     pub id: String,
@@ -22,6 +90,38 @@ pub struct ProxyRequestLog {
     pub input_tokens: Option<u32>,
     pub output_tokens: Option<u32>,
     pub protocol: Option<String>,     // 协议类型: "openai", "anthropic", "gemini"
+    /// tee 模式下记录的原始上游 SSE (仅详情接口返回)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_upstream: Option<String>,
+    /// 客户端声明的 service_tier 对应的实际生效等级
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub service_tier: Option<String>,
+    /// 上游 responseId (向 Google 支持反馈问题时使用)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upstream_response_id: Option<String>,
+    /// 上游实际响应的 modelVersion
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upstream_model_version: Option<String>,
+    /// 分发决策: 由哪个 provider 处理及原因
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider_decision: Option<crate::proxy::providers::ProviderDecision>,
+    /// 请求以原始消息模式发送 (跳过消息规范化的调试请求)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_messages: Option<bool>,
+    /// 应用的系统提示注入模板名 (不记录模板内容)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_injections: Option<Vec<String>>,
+    /// 重试中的换号记录 (如图片生成的 "task 0 attempt 1: a@x -> rotate (429)": 任务序号、第几次尝试、放弃的账号与原因)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account_rotations: Option<Vec<String>>,
+    /// 音频等非 token 计费端点的请求 / 响应字节数 (此时 input_tokens / output_tokens 为空)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_bytes: Option<u64>,
+    /// 终止方式 (旧日志为 unknown)
+    #[serde(default)]
+    pub termination: Termination,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -30,6 +130,184 @@ pub struct ProxyStats {
     pub success_count: u64,
     pub error_count: u64,
     pub avg_latency: f64, // [NEW] Average latency in ms
+    #[serde(default)]
+    pub tool_loop_recoveries: u64, // Requests repaired by tool loop recovery (since startup)
+    #[serde(default)]
+    pub context_summary_calls: u64, // Layer-3 summary calls (since startup)
+    #[serde(default)]
+    pub context_summary_input_tokens: u64,
+    #[serde(default)]
+    pub context_summary_output_tokens: u64,
+    #[serde(default)]
+    pub compaction_requests: u64, // Claude Code /compact requests (since startup)
+    #[serde(default)]
+    pub warmup_intercepts: std::collections::HashMap<String, u64>, // Intercepted warmups per client version (since startup)
+    #[serde(default)]
+    pub post_process_hits: std::collections::HashMap<String, u64>, // Text post-processing rule applications (since startup)
+    #[serde(default)]
+    pub paced_delayed_requests: u64, // Leases delayed by per-account pacing (since startup)
+    #[serde(default)]
+    pub paced_spilled_requests: u64, // Leases moved to another account by pacing (since startup)
+    #[serde(default)]
+    pub providers: std::collections::HashMap<String, crate::proxy::providers::ProviderStats>,
+    #[serde(default)]
+    pub session_prewarm: crate::proxy::session_prewarm::SessionPrewarmStats, // Session prewarm effect (since startup)
+    #[serde(default)]
+    pub cache_intent: crate::proxy::token_manager::CacheIntentStats, // Sessions holding their binding for a long cache_control TTL
+    #[serde(default)]
+    pub connection_resets: crate::proxy::upstream::connection::ConnectionResetStats, // Pooled connection resets and same-account retries (since startup)
+    #[serde(default)]
+    pub provider_dispatch_reasons: std::collections::HashMap<String, u64>, // Requests routed to extra providers per reason (since startup)
+    #[serde(default)]
+    pub tool_schema: crate::proxy::mappers::claude::request::ToolSchemaStats, // Tool declaration reuse and schema tokens per session (since startup)
+    #[serde(default)]
+    pub duplicate_text: crate::proxy::mappers::claude::streaming::DuplicateTextStats, // Resent upstream text chunks trimmed from streams (since startup)
+    #[serde(default)]
+    pub strict_tool_violations: std::collections::HashMap<String, u64>, // OpenAI strict tool calls failing their schema, per tool name (since startup)
+    #[serde(default)]
+    pub terminations: std::collections::HashMap<String, u64>, // Requests per termination kind; client cancellations are not counted as errors
+    #[serde(default)]
+    pub debug_log_write_failures: u64, // Debug payloads that could not be written to debug_logging.output_dir (since startup)
+    #[serde(default)]
+    pub multi_candidate_responses: u64, // Responses whose extra Gemini candidates were dropped for single-message protocols (since startup)
+}
+
+/// get_proxy_stats 可选的统计分区 (调用方按需选择, 避免高频轮询时做重查询)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatsSection {
+    /// 账号数量与负载明细
+    Accounts,
+    /// 请求总数 / 成功率 / 平均延迟等 (即旧版 ProxyStats)
+    Latency,
+    /// 最近 24 小时各模型用量
+    Models,
+    /// 主端口与额外监听端口状态
+    Listeners,
+    /// 模型预热池状态
+    WarmPool,
+    /// API Key 每日预算用量
+    KeyBudgets,
+}
+
+impl StatsSection {
+    /// 解析逗号分隔的分区列表 (管理 API 的 query 参数)
+    pub fn parse_list(input: &str) -> Result<Vec<StatsSection>, String> {
+        input
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                serde_json::from_value(serde_json::Value::String(s.to_string()))
+                    .map_err(|_| format!("Unknown stats section: {}", s))
+            })
+            .collect()
+    }
+}
+
+/// 账号分区
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AccountsStats {
+    pub total: usize,
+    pub effective: usize,
+    pub loads: Vec<crate::proxy::token_manager::AccountLoadEntry>,
+}
+
+/// get_proxy_stats 返回值: 只包含调用方请求的分区
+/// latency 分区平铺在顶层, 与旧版 ProxyStats 的字段保持一致
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProxyStatsReport {
+    #[serde(flatten, default, skip_serializing_if = "Option::is_none")]
+    pub latency: Option<ProxyStats>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accounts: Option<AccountsStats>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub models: Option<Vec<crate::modules::token_stats::ModelTokenStats>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub listeners: Option<Vec<crate::proxy::server::listeners::ListenerStatus>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warm_pool: Option<crate::proxy::warm_pool::WarmPoolStatus>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_budgets: Option<Vec<crate::proxy::key_budget::KeyBudgetStatus>>,
+}
+
+impl ProxyStatsReport {
+    /// 按分区收集统计; 服务未启动时 (state 为 None) 只能提供 latency 与 models
+    pub async fn collect(
+        monitor: Option<&ProxyMonitor>,
+        state: Option<&crate::proxy::server::AppState>,
+        sections: &[StatsSection],
+    ) -> Self {
+        let mut report = ProxyStatsReport::default();
+        for section in sections {
+            match section {
+                StatsSection::Latency => {
+                    report.latency = Some(match monitor {
+                        Some(monitor) => monitor.get_stats().await,
+                        None => ProxyStats::default(),
+                    });
+                }
+                StatsSection::Models => {
+                    let models = tokio::task::spawn_blocking(|| {
+                        crate::modules::token_stats::get_model_stats(24)
+                    })
+                    .await;
+                    report.models = Some(match models {
+                        Ok(Ok(models)) => models,
+                        Ok(Err(e)) => {
+                            tracing::error!("Failed to get model stats: {}", e);
+                            Vec::new()
+                        }
+                        Err(e) => {
+                            tracing::error!("Spawn blocking failed for model stats: {}", e);
+                            Vec::new()
+                        }
+                    });
+                }
+                StatsSection::Accounts => {
+                    report.accounts = Some(match state {
+                        Some(state) => AccountsStats {
+                            total: state.token_manager.len(),
+                            effective: state.token_manager.effective_len().await,
+                            loads: state.token_manager.account_loads().await,
+                        },
+                        None => AccountsStats::default(),
+                    });
+                }
+                StatsSection::Listeners => {
+                    report.listeners = Some(match state {
+                        Some(state) => {
+                            let running = *state.is_running.read().await;
+                            state.listeners.snapshot(state.port, running).await
+                        }
+                        None => Vec::new(),
+                    });
+                }
+                StatsSection::WarmPool => {
+                    report.warm_pool = Some(match state {
+                        Some(state) => {
+                            crate::proxy::warm_pool::status(&*state.experimental.read().await)
+                        }
+                        None => Default::default(),
+                    });
+                }
+                StatsSection::KeyBudgets => {
+                    report.key_budgets = Some(crate::proxy::key_budget::status());
+                }
+            }
+        }
+        report
+    }
+}
+
+/// 未指定 sections 的旧调用方式: 只在首次调用时提示一次, 避免轮询刷屏
+pub fn warn_legacy_stats_call() {
+    static WARNED: AtomicBool = AtomicBool::new(false);
+    if !WARNED.swap(true, Ordering::Relaxed) {
+        tracing::warn!(
+            "[Deprecated] get_proxy_stats without `sections` is deprecated; pass sections (e.g. [\"latency\"]) explicitly"
+        );
+    }
 }
 
 pub struct ProxyMonitor {
@@ -78,10 +356,50 @@ impl ProxyMonitor {
         self.enabled.load(Ordering::Relaxed)
     }
 
-    pub async fn log_request(&self, log: ProxyRequestLog) {
+    pub async fn log_request(&self, mut log: ProxyRequestLog) {
+        if log.termination == Termination::Unknown {
+            log.termination = Termination::classify(log.status, log.error.as_deref());
+        }
+
         // [OPTIMIZED] Removed redundant token stats recording here.
         // It is handled asynchronously along with duplicate DB logging below to prevent double-counting.
 
+        // 负载评分所需的账号吞吐与加权选择的成功率, 与监控开关无关
+        if let Some(email) = &log.account_email {
+            let tokens = log.input_tokens.unwrap_or(0) as u64 + log.output_tokens.unwrap_or(0) as u64;
+            crate::proxy::token_manager::record_throughput(email, tokens);
+            // 上下文超限是请求本身的问题, 不计入账号成功率
+            let prompt_too_long = log.error.as_deref().is_some_and(|e| {
+                e.starts_with(crate::proxy::mappers::error_classifier::PROMPT_TOO_LONG_CODE)
+            });
+            if !prompt_too_long {
+                crate::proxy::token_manager::record_request_outcome(email, log.status);
+            }
+            if let Some(model) = log.mapped_model.as_deref().or(log.model.as_deref()) {
+                crate::proxy::token_manager::record_quota_usage(email, model, tokens);
+            }
+        }
+
+        // 上游失败模式检测, 新进入警告状态时只通知一次
+        if let Some(warning) =
+            crate::proxy::failure_patterns::record_outcome(log.status, log.error.as_deref())
+        {
+            if let Some(app) = &self.app_handle {
+                let _ = app.emit("proxy://upstream-failure-pattern", &warning);
+            }
+        }
+
+        // 调试载荷首次写入失败时通知一次 (例如输出目录位于不可用的网络驱动器)
+        if let Some(warning) = crate::proxy::debug_logger::take_write_failure_warning() {
+            if let Some(app) = &self.app_handle {
+                let _ = app.emit("proxy://debug-log-write-failed", &warning);
+            }
+        }
+
+        if let Some(model) = log.mapped_model.as_deref().or(log.model.as_deref()) {
+            crate::proxy::model_health::record_outcome(model, log.status, log.duration, log.error.as_deref());
+        }
+
         if !self.is_enabled() {
             return;
         }
@@ -90,11 +408,12 @@ impl ProxyMonitor {
         {
             let mut stats = self.stats.write().await;
             stats.total_requests += 1;
-            if log.status >= 200 && log.status < 400 {
-                stats.success_count += 1;
-            } else {
-                stats.error_count += 1;
+            match log.termination {
+                Termination::Success => stats.success_count += 1,
+                Termination::ClientCancelled => {}
+                _ => stats.error_count += 1,
             }
+            *stats.terminations.entry(log.termination.as_str().to_string()).or_insert(0) += 1;
         }
 
         // Add log to memory
@@ -135,9 +454,7 @@ impl ProxyMonitor {
                 log_to_save.output_tokens,
             ) {
                 let model = log_to_save.model.clone().unwrap_or_else(|| "unknown".to_string());
-                if let Err(e) = crate::modules::token_stats::record_usage(account, &model, input, output) {
-                    tracing::debug!("Failed to record token stats: {}", e);
-                }
+                crate::modules::stats_journal::record_usage(account, &model, input, output);
             }
         });
 
@@ -160,6 +477,17 @@ impl ProxyMonitor {
                 input_tokens: log.input_tokens,
                 output_tokens: log.output_tokens,
                 protocol: log.protocol.clone(),
+                raw_upstream: None,
+                service_tier: log.service_tier.clone(),
+                upstream_response_id: log.upstream_response_id.clone(),
+                upstream_model_version: log.upstream_model_version.clone(),
+                provider_decision: log.provider_decision.clone(),
+                raw_messages: log.raw_messages,
+                system_injections: log.system_injections.clone(),
+                account_rotations: log.account_rotations.clone(),
+                input_bytes: log.input_bytes,
+                output_bytes: log.output_bytes,
+                termination: log.termination,
             };
             let _ = app.emit("proxy://request", &log_summary);
         }
@@ -192,7 +520,7 @@ impl ProxyMonitor {
             crate::modules::proxy_db::get_stats()
         }).await;
 
-        match db_result {
+        let mut stats = match db_result {
             Ok(Ok(stats)) => stats,
             Ok(Err(e)) => {
                 tracing::error!("Failed to get stats from DB: {}", e);
@@ -202,7 +530,30 @@ impl ProxyMonitor {
                 tracing::error!("Spawn blocking failed for get_stats: {}", e);
                 self.stats.read().await.clone()
             }
-        }
+        };
+        stats.tool_loop_recoveries =
+            crate::proxy::mappers::claude::thinking_utils::tool_loop_recovery_count();
+        let summary = crate::proxy::handlers::claude::context_summary_usage();
+        stats.context_summary_calls = summary.calls;
+        stats.context_summary_input_tokens = summary.input_tokens;
+        stats.context_summary_output_tokens = summary.output_tokens;
+        stats.compaction_requests = crate::proxy::handlers::claude::compaction_count();
+        stats.warmup_intercepts = crate::proxy::handlers::claude::warmup_intercept_counts();
+        stats.post_process_hits = crate::proxy::common::post_process::hit_counts();
+        let pacing = crate::proxy::token_manager::pacing_stats();
+        stats.paced_delayed_requests = pacing.delayed;
+        stats.paced_spilled_requests = pacing.spilled;
+        stats.providers = crate::proxy::providers::provider_stats();
+        stats.session_prewarm = crate::proxy::session_prewarm::stats();
+        stats.cache_intent = crate::proxy::token_manager::cache_intent_stats();
+        stats.connection_resets = crate::proxy::upstream::connection::stats();
+        stats.provider_dispatch_reasons = crate::proxy::providers::dispatch_reason_counts();
+        stats.tool_schema = crate::proxy::mappers::claude::request::tool_schema_stats();
+        stats.duplicate_text = crate::proxy::mappers::claude::streaming::duplicate_text_stats();
+        stats.strict_tool_violations = crate::proxy::mappers::openai::strict_tools::violation_counts();
+        stats.debug_log_write_failures = crate::proxy::debug_logger::write_failures();
+        stats.multi_candidate_responses = crate::proxy::mappers::candidates::multi_candidate_responses();
+        stats
     }
     
     pub async fn get_logs_filtered(
@@ -0,0 +1,115 @@
+// 端口选择与客户端接入地址
+// 全新安装时默认端口经常与其他本地 LLM 工具冲突: 首次运行在优先区间内挑选空闲端口。
+// 端口变更时记录历史, 方便用户找回之前配置到客户端里的端口。
+// 各协议的规范 base URL (含协议期望的 /v1 后缀) 统一在这里生成。
+
+use serde::{Deserialize, Serialize};
+use std::net::TcpListener;
+use std::ops::RangeInclusive;
+
+use super::config::ListenerProtocol;
+
+/// 默认端口
+pub const DEFAULT_PORT: u16 = 8045;
+/// 首次运行时依次尝试的端口区间
+const PREFERRED_PORTS: RangeInclusive<u16> = DEFAULT_PORT..=8064;
+/// 保留的历史端口数
+const MAX_PORT_HISTORY: usize = 10;
+
+/// 首次运行的默认端口: 优先区间内第一个可绑定的端口, 全部被占用时回退到 DEFAULT_PORT
+pub fn pick_default_port() -> u16 {
+    let port = first_free_port(PREFERRED_PORTS, is_port_free).unwrap_or(DEFAULT_PORT);
+    if port != DEFAULT_PORT {
+        tracing::info!("Default port {} is in use, picked free port {}", DEFAULT_PORT, port);
+    }
+    port
+}
+
+fn first_free_port(range: RangeInclusive<u16>, is_free: impl Fn(u16) -> bool) -> Option<u16> {
+    range.into_iter().find(|port| is_free(*port))
+}
+
+fn is_port_free(port: u16) -> bool {
+    TcpListener::bind(("127.0.0.1", port)).is_ok()
+}
+
+/// 端口变更时把旧端口记入历史 (最近的在前, 去重, 不含当前端口)
+pub fn record_port_change(history: &mut Vec<u16>, previous: u16, current: u16) {
+    if previous != current {
+        history.retain(|p| *p != previous);
+        history.insert(0, previous);
+    }
+    history.retain(|p| *p != current);
+    history.truncate(MAX_PORT_HISTORY);
+}
+
+/// 各协议可直接粘贴到客户端的 base URL
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProxyEndpoints {
+    /// Anthropic SDK / Claude Code (SDK 自行追加 /v1/messages)
+    pub anthropic: String,
+    /// OpenAI SDK / Codex (需要 /v1 后缀)
+    pub openai: String,
+    /// Gemini SDK (SDK 自行追加 /v1beta)
+    pub gemini: String,
+}
+
+impl ProxyEndpoints {
+    pub fn for_port(port: u16) -> Self {
+        let base = format!("http://127.0.0.1:{}", port);
+        Self {
+            anthropic: base.clone(),
+            openai: format!("{}/v1", base),
+            gemini: base,
+        }
+    }
+
+    pub fn url(&self, protocol: ListenerProtocol) -> &str {
+        match protocol {
+            ListenerProtocol::Anthropic => &self.anthropic,
+            ListenerProtocol::OpenAI => &self.openai,
+            ListenerProtocol::Gemini => &self.gemini,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_free_port_skips_occupied() {
+        assert_eq!(first_free_port(8045..=8050, |p| p > 8046), Some(8047));
+        assert_eq!(first_free_port(8045..=8046, |_| false), None);
+    }
+
+    #[test]
+    fn test_port_history() {
+        let mut history = Vec::new();
+        record_port_change(&mut history, 8045, 8046);
+        record_port_change(&mut history, 8046, 9000);
+        assert_eq!(history, vec![8046, 8045]);
+
+        // 切回历史端口: 从历史中移除当前端口, 旧端口排到最前
+        record_port_change(&mut history, 9000, 8045);
+        assert_eq!(history, vec![9000, 8046]);
+
+        // 端口未变化时不记录
+        record_port_change(&mut history, 8045, 8045);
+        assert_eq!(history, vec![9000, 8046]);
+
+        for port in 10_000..10_020 {
+            record_port_change(&mut history, port, port + 1);
+        }
+        assert_eq!(history.len(), MAX_PORT_HISTORY);
+        assert_eq!(history[0], 10_019);
+    }
+
+    #[test]
+    fn test_endpoint_urls() {
+        let endpoints = ProxyEndpoints::for_port(8046);
+        assert_eq!(endpoints.url(ListenerProtocol::Anthropic), "http://127.0.0.1:8046");
+        assert_eq!(endpoints.url(ListenerProtocol::OpenAI), "http://127.0.0.1:8046/v1");
+        assert_eq!(endpoints.url(ListenerProtocol::Gemini), "http://127.0.0.1:8046");
+    }
+}
@@ -0,0 +1,31 @@
+// 通用 Anthropic / OpenAI 兼容 provider (OpenRouter, 自建 vLLM 等)
+
+use serde_json::Value;
+
+use super::Provider;
+use crate::proxy::config::ProviderConfig;
+
+pub struct CompatibleProvider {
+    config: ProviderConfig,
+}
+
+impl CompatibleProvider {
+    pub fn new(config: ProviderConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl Provider for CompatibleProvider {
+    fn name(&self) -> &str {
+        &self.config.id
+    }
+
+    fn config(&self) -> &ProviderConfig {
+        &self.config
+    }
+
+    fn prepare_body(&self, body: &mut Value, _message_count: usize) {
+        // 第三方端点通常不接受 Anthropic 的 cache_control 扩展字段
+        super::zai_anthropic::deep_remove_cache_control(body);
+    }
+}
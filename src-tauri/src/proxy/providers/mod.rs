@@ -1,2 +1,620 @@
+// 额外上游 Provider 抽象
+// Google 账号池之外的 Anthropic / OpenAI 兼容端点 (z.ai, OpenRouter, 自建 vLLM ...)
+// 按配置顺序组成分发层级, 每个 provider 有独立的分发模式与模型白名单。
+
+pub mod compatible;
 pub mod zai_anthropic;
+pub mod zai_models;
+
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    response::{IntoResponse, Response},
+};
+use bytes::Bytes;
+use dashmap::DashMap;
+use futures::future::BoxFuture;
+use futures::StreamExt;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use tokio::time::Duration;
+
+use crate::proxy::config::{effective_providers, ListenerProtocol, ProviderConfig};
+use crate::proxy::server::AppState;
+use crate::proxy::ZaiDispatchMode;
+
+/// 连续失败达到该次数后视为不健康, 在冷却期内跳过
+const UNHEALTHY_FAILURES: u32 = 3;
+const UNHEALTHY_COOLDOWN_SECS: i64 = 60;
+
+/// 每个 provider 的累计统计 (随 proxy stats 返回)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProviderStats {
+    pub requests: u64,
+    pub errors: u64,
+    pub consecutive_failures: u32,
+    pub last_status: Option<u16>,
+    pub last_error: Option<String>,
+    pub last_used: Option<i64>,
+    pub last_failure: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderHealth {
+    pub healthy: bool,
+    pub consecutive_failures: u32,
+    pub last_error: Option<String>,
+}
+
+static PROVIDER_STATS: Lazy<DashMap<String, ProviderStats>> = Lazy::new(DashMap::new);
+/// 分发原因 -> 次数 (启动以来)
+static DISPATCH_REASONS: Lazy<DashMap<&'static str, u64>> = Lazy::new(DashMap::new);
+
+/// 响应头: 实际服务请求的 provider ("google" 表示 Google 账号池)
+pub const PROVIDER_HEADER: &str = "X-Provider";
+pub const GOOGLE_PROVIDER: &str = "google";
+
+/// 请求被分发到额外 provider 的原因
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DispatchReason {
+    /// Exclusive 模式: 该协议的请求全部交给 provider
+    Exclusive,
+    /// Fallback: 号池中没有 Google 账号
+    NoGoogleAccounts,
+    /// Fallback: Google 账号对该模型全部不可用 (限流 / 配额保护 / 熔断)
+    AllUnavailable { model: String },
+    /// Pooled: 轮询槽位落在 provider 上
+    PooledSlot,
+}
+
+impl DispatchReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DispatchReason::Exclusive => "exclusive",
+            DispatchReason::NoGoogleAccounts => "no_google_accounts",
+            DispatchReason::AllUnavailable { .. } => "all_unavailable",
+            DispatchReason::PooledSlot => "pooled_slot",
+        }
+    }
+}
+
+/// 单个请求的分发决策 (写入请求日志与调试载荷)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProviderDecision {
+    /// provider id, Google 账号池为 "google"
+    pub provider: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dispatch_mode: Option<ZaiDispatchMode>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<DispatchReason>,
+}
+
+impl ProviderDecision {
+    /// 请求由 Google 账号池处理
+    pub fn google() -> Self {
+        Self {
+            provider: GOOGLE_PROVIDER.to_string(),
+            dispatch_mode: None,
+            reason: None,
+        }
+    }
+
+    /// 附加 X-Provider 响应头, 并将决策放入响应扩展供监控中间件记录
+    pub fn attach(self, mut response: Response) -> Response {
+        crate::proxy::handlers::common::set_header_lossy(&mut response, PROVIDER_HEADER, &self.provider);
+        response.extensions_mut().insert(self);
+        response
+    }
+}
+
+/// 各分发原因的累计次数 (随 proxy stats 返回)
+pub fn dispatch_reason_counts() -> HashMap<String, u64> {
+    DISPATCH_REASONS
+        .iter()
+        .map(|e| (e.key().to_string(), *e.value()))
+        .collect()
+}
+
+/// select_provider 的结果
+pub struct SelectedProvider {
+    pub provider: Arc<dyn Provider>,
+    pub decision: ProviderDecision,
+}
+
+pub fn provider_stats() -> HashMap<String, ProviderStats> {
+    PROVIDER_STATS
+        .iter()
+        .map(|e| (e.key().clone(), e.value().clone()))
+        .collect()
+}
+
+fn record_result(provider: &str, status: Option<u16>, error: Option<String>) {
+    let now = chrono::Utc::now().timestamp();
+    let mut stats = PROVIDER_STATS.entry(provider.to_string()).or_default();
+    stats.requests += 1;
+    stats.last_used = Some(now);
+    stats.last_status = status;
+    let failed = error.is_some() || status.is_some_and(|s| s == 429 || s >= 500);
+    if failed {
+        stats.errors += 1;
+        stats.consecutive_failures += 1;
+        stats.last_failure = Some(now);
+        stats.last_error = error.or_else(|| status.map(|s| format!("HTTP {}", s)));
+    } else {
+        stats.consecutive_failures = 0;
+    }
+}
+
+/// 单次转发请求
+pub struct ProviderRequest<'a> {
+    pub method: Method,
+    /// 协议路径, 例如 `/v1/messages` 或 `/v1/chat/completions`
+    pub path: &'a str,
+    pub headers: &'a HeaderMap,
+    pub body: Value,
+    /// 消息条数 (用于 z.ai 的 rewind 检测)
+    pub message_count: usize,
+}
+
+/// 额外上游 provider
+pub trait Provider: Send + Sync {
+    fn name(&self) -> &str;
+    fn config(&self) -> &ProviderConfig;
+
+    /// 将请求模型映射为上游模型
+    fn map_model(&self, model: &str) -> String {
+        self.config()
+            .model_mapping
+            .get(model)
+            .cloned()
+            .unwrap_or_else(|| model.to_string())
+    }
+
+    /// 发送前对请求体的额外处理
+    fn prepare_body(&self, _body: &mut Value, _message_count: usize) {}
+
+    fn protocols(&self) -> &[ListenerProtocol] {
+        &self.config().protocols
+    }
+
+    fn dispatch_mode(&self) -> ZaiDispatchMode {
+        self.config().dispatch_mode.clone()
+    }
+
+    fn supports_model(&self, model: &str) -> bool {
+        self.config().allows_model(model)
+    }
+
+    fn health(&self) -> ProviderHealth {
+        let stats = PROVIDER_STATS
+            .get(self.name())
+            .map(|s| s.clone())
+            .unwrap_or_default();
+        let cooled_down = stats
+            .last_failure
+            .map(|t| chrono::Utc::now().timestamp() - t > UNHEALTHY_COOLDOWN_SECS)
+            .unwrap_or(true);
+        ProviderHealth {
+            healthy: stats.consecutive_failures < UNHEALTHY_FAILURES || cooled_down,
+            consecutive_failures: stats.consecutive_failures,
+            last_error: stats.last_error,
+        }
+    }
+
+    /// 转发并缓冲完整响应 (count_tokens 等非流式请求)
+    fn forward_json<'a>(
+        &'a self,
+        state: &'a AppState,
+        request: ProviderRequest<'a>,
+    ) -> BoxFuture<'a, Response> {
+        Box::pin(forward_http(self, state, request, false))
+    }
+
+    /// 转发并透传响应流 (SSE 与非 SSE 均适用)
+    fn forward_stream<'a>(
+        &'a self,
+        state: &'a AppState,
+        request: ProviderRequest<'a>,
+    ) -> BoxFuture<'a, Response> {
+        Box::pin(forward_http(self, state, request, true))
+    }
+}
+
+/// 由当前配置构建有序的 provider 列表
+pub async fn registry(state: &AppState) -> Vec<Arc<dyn Provider>> {
+    let zai = state.zai.read().await.clone();
+    let configs = effective_providers(&zai, &state.providers.read().await);
+    configs
+        .into_iter()
+        .map(|config| -> Arc<dyn Provider> {
+            if config.id == "zai" {
+                Arc::new(zai_anthropic::ZaiProvider::new(zai.clone(), config))
+            } else {
+                Arc::new(compatible::CompatibleProvider::new(config))
+            }
+        })
+        .collect()
+}
+
+/// 为请求选择 provider; 返回 None 表示走 Google 账号池
+pub async fn select_provider(
+    state: &AppState,
+    protocol: ListenerProtocol,
+    model: &str,
+    trace_id: &str,
+) -> Option<SelectedProvider> {
+    let candidates: Vec<Arc<dyn Provider>> = registry(state)
+        .await
+        .into_iter()
+        .filter(|p| p.protocols().contains(&protocol) && p.supports_model(model))
+        .filter(|p| {
+            let health = p.health();
+            if !health.healthy {
+                tracing::debug!(
+                    "[{}] Provider {} skipped (unhealthy, {} consecutive failures)",
+                    trace_id,
+                    p.name(),
+                    health.consecutive_failures
+                );
+            }
+            health.healthy
+        })
+        .collect();
+    if candidates.is_empty() {
+        return None;
+    }
+
+    let modes: Vec<ZaiDispatchMode> = candidates.iter().map(|p| p.dispatch_mode()).collect();
+    let google_accounts = state.token_manager.len();
+    let normalized = crate::proxy::common::model_mapping::normalize_to_standard_id(model)
+        .unwrap_or_else(|| model.to_string());
+    let google_available = if modes.contains(&ZaiDispatchMode::Fallback) && google_accounts > 0 {
+        state
+            .token_manager
+            .has_available_account("claude", &normalized)
+            .await
+    } else {
+        google_accounts > 0
+    };
+    let rr_slot = if modes.contains(&ZaiDispatchMode::Pooled) {
+        state.provider_rr.fetch_add(1, Ordering::Relaxed)
+    } else {
+        0
+    };
+
+    let (index, reason) = decide(&modes, google_accounts, google_available, rr_slot, &normalized)?;
+    let provider = candidates[index].clone();
+    tracing::info!(
+        "[{}] Dispatching {} request for {} to provider {} ({:?}, reason: {})",
+        trace_id,
+        protocol.as_str(),
+        model,
+        provider.name(),
+        modes[index],
+        reason.as_str()
+    );
+    *DISPATCH_REASONS.entry(reason.as_str()).or_insert(0) += 1;
+
+    let decision = ProviderDecision {
+        provider: provider.name().to_string(),
+        dispatch_mode: Some(modes[index].clone()),
+        reason: Some(reason),
+    };
+    Some(SelectedProvider { provider, decision })
+}
+
+/// 分发决策: Exclusive 优先; Pooled 与 Google 账号轮流占槽; Fallback 仅在 Google 不可用时
+fn decide(
+    modes: &[ZaiDispatchMode],
+    google_accounts: usize,
+    google_available: bool,
+    rr_slot: usize,
+    model: &str,
+) -> Option<(usize, DispatchReason)> {
+    if let Some(i) = modes.iter().position(|m| *m == ZaiDispatchMode::Exclusive) {
+        return Some((i, DispatchReason::Exclusive));
+    }
+
+    let pooled: Vec<usize> = (0..modes.len())
+        .filter(|i| modes[*i] == ZaiDispatchMode::Pooled)
+        .collect();
+    if !pooled.is_empty() {
+        let slot = rr_slot % (google_accounts + pooled.len()).max(1);
+        if slot < pooled.len() {
+            return Some((pooled[slot], DispatchReason::PooledSlot));
+        }
+    }
+
+    if !google_available {
+        let reason = if google_accounts == 0 {
+            DispatchReason::NoGoogleAccounts
+        } else {
+            DispatchReason::AllUnavailable { model: model.to_string() }
+        };
+        return modes
+            .iter()
+            .position(|m| *m == ZaiDispatchMode::Fallback)
+            .map(|i| (i, reason));
+    }
+    None
+}
+
+fn join_base_url(base: &str, path: &str) -> String {
+    let base = base.trim_end_matches('/');
+    if path.starts_with('/') {
+        format!("{}{}", base, path)
+    } else {
+        format!("{}/{}", base, path)
+    }
+}
+
+fn build_client(
+    upstream_proxy: Option<crate::proxy::config::UpstreamProxyConfig>,
+    timeout_secs: u64,
+) -> Result<reqwest::Client, String> {
+    let mut builder = reqwest::Client::builder()
+        .timeout(Duration::from_secs(timeout_secs.max(5)));
+
+    if let Some(config) = upstream_proxy {
+        if config.enabled && !config.url.is_empty() {
+            let proxy = reqwest::Proxy::all(&config.url)
+                .map_err(|e| format!("Invalid upstream proxy url: {}", e))?;
+            builder = builder.proxy(proxy);
+        }
+    }
+
+    builder
+        .tcp_nodelay(true) // [FIX #307] Disable Nagle's algorithm to improve latency for small requests
+        .build()
+        .map_err(|e| format!("Failed to build HTTP client: {}", e))
+}
+
+fn copy_passthrough_headers(incoming: &HeaderMap) -> HeaderMap {
+    // Only forward a conservative set of headers to avoid leaking the local proxy key or cookies.
+    let mut out = HeaderMap::new();
+
+    for (k, v) in incoming.iter() {
+        let key = k.as_str().to_ascii_lowercase();
+        match key.as_str() {
+            "content-type" | "accept" | "anthropic-version" | "user-agent" => {
+                out.insert(k.clone(), v.clone());
+            }
+            // Some clients use these for streaming; safe to pass through.
+            "accept-encoding" | "cache-control" => {
+                out.insert(k.clone(), v.clone());
+            }
+            _ => {}
+        }
+    }
+
+    out
+}
+
+fn set_upstream_auth(headers: &mut HeaderMap, incoming: &HeaderMap, api_key: &str, anthropic: bool) {
+    // Anthropic 端点保持与客户端相同的鉴权方式:
+    // - 客户端用 x-api-key 时替换之, 用 Authorization 时替换为 Bearer, 都没有则默认 x-api-key
+    // OpenAI 端点总是使用 Bearer
+    let has_x_api_key = incoming.contains_key("x-api-key");
+    let has_auth = incoming.contains_key(header::AUTHORIZATION);
+
+    if anthropic && (has_x_api_key || !has_auth) {
+        if let Ok(v) = HeaderValue::from_str(api_key) {
+            headers.insert("x-api-key", v);
+        }
+    }
+
+    if !anthropic || has_auth {
+        if let Ok(v) = HeaderValue::from_str(&format!("Bearer {}", api_key)) {
+            headers.insert(header::AUTHORIZATION, v);
+        }
+    }
+}
+
+async fn forward_http<P: Provider + ?Sized>(
+    provider: &P,
+    state: &AppState,
+    request: ProviderRequest<'_>,
+    streaming: bool,
+) -> Response {
+    let config = provider.config();
+    if config.api_key.trim().is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("{} api_key is not set", provider.name()),
+        )
+            .into_response();
+    }
+
+    let mut body = request.body;
+    let mapped_model = match body.get("model").and_then(|v| v.as_str()) {
+        Some(model) => {
+            let mapped = provider.map_model(model);
+            body["model"] = Value::String(mapped.clone());
+            Some(mapped)
+        }
+        None => None,
+    };
+    provider.prepare_body(&mut body, request.message_count);
+
+    let url = join_base_url(&config.base_url, request.path);
+    let timeout_secs = state.request_timeout.max(5);
+    let upstream_proxy = state.upstream_proxy.read().await.clone();
+    let client = match build_client(Some(upstream_proxy), timeout_secs) {
+        Ok(c) => c,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+    };
+
+    let anthropic = request.path.starts_with("/v1/messages");
+    let mut headers = copy_passthrough_headers(request.headers);
+    set_upstream_auth(&mut headers, request.headers, &config.api_key, anthropic);
+
+    // Ensure JSON content type.
+    headers
+        .entry(header::CONTENT_TYPE)
+        .or_insert(HeaderValue::from_static("application/json"));
+
+    // [FIX #307] Explicitly serialize body to Vec<u8> to ensure Content-Length is set correctly.
+    // This avoids "Transfer-Encoding: chunked" for small bodies which caused connection errors.
+    let body_bytes = serde_json::to_vec(&body).unwrap_or_default();
+    tracing::debug!(
+        "Forwarding request to provider {} (len: {} bytes): {}",
+        provider.name(),
+        body_bytes.len(),
+        url
+    );
+
+    let resp = match client
+        .request(request.method, &url)
+        .headers(headers)
+        .body(body_bytes) // Use .body(Vec<u8>) instead of .json()
+        .send()
+        .await
+    {
+        Ok(r) => r,
+        Err(e) => {
+            record_result(provider.name(), None, Some(e.to_string()));
+            return (
+                StatusCode::BAD_GATEWAY,
+                format!(
+                    "Upstream request failed: {}",
+                    crate::proxy::common::redact::sanitize_upstream_error(&e.to_string(), None)
+                ),
+            )
+                .into_response();
+        }
+    };
+
+    let status = StatusCode::from_u16(resp.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
+    record_result(provider.name(), Some(status.as_u16()), None);
+
+    // 日志归属: 监控中间件从这两个响应头读取账号与实际模型
+    let mut out = Response::builder()
+        .status(status)
+        .header("X-Account-Email", format!("provider:{}", provider.name()));
+    if let Some(model) = &mapped_model {
+        out = out.header("X-Mapped-Model", model.as_str());
+    }
+    if let Some(ct) = resp.headers().get(header::CONTENT_TYPE) {
+        out = out.header(header::CONTENT_TYPE, ct.clone());
+    }
+
+    let body = if streaming {
+        let stream = resp.bytes_stream().map(|chunk| match chunk {
+            Ok(b) => Ok::<Bytes, std::io::Error>(b),
+            Err(e) => Ok(Bytes::from(format!("Upstream stream error: {}", e))),
+        });
+        Body::from_stream(stream)
+    } else {
+        match resp.bytes().await {
+            Ok(bytes) => Body::from(bytes),
+            Err(e) => {
+                return (
+                    StatusCode::BAD_GATEWAY,
+                    format!("Upstream read failed: {}", e),
+                )
+                    .into_response();
+            }
+        }
+    };
+
+    out.body(body).unwrap_or_else(|_| {
+        (StatusCode::INTERNAL_SERVER_ERROR, "Failed to build response").into_response()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ZaiDispatchMode::*;
+
+    #[test]
+    fn test_decide_respects_modes_in_order() {
+        let m = "claude-opus-4-5";
+        // Exclusive 总是优先, 与顺序中的位置无关
+        assert_eq!(decide(&[Fallback, Exclusive], 3, true, 0, m), Some((1, DispatchReason::Exclusive)));
+
+        // Fallback 只在 Google 不可用时生效, 取第一个
+        assert_eq!(decide(&[Fallback, Fallback], 3, true, 0, m), None);
+        assert_eq!(
+            decide(&[Pooled, Fallback, Fallback], 0, false, 5, m),
+            Some((0, DispatchReason::PooledSlot))
+        );
+        assert_eq!(
+            decide(&[Fallback, Fallback], 0, false, 0, m),
+            Some((0, DispatchReason::NoGoogleAccounts))
+        );
+        assert_eq!(
+            decide(&[Fallback], 3, false, 0, m),
+            Some((0, DispatchReason::AllUnavailable { model: m.to_string() }))
+        );
+
+        // Pooled: 2 个 Google 账号 + 1 个 provider, 每 3 次占 1 个槽
+        let picks: Vec<_> = (0..6).map(|slot| decide(&[Pooled], 2, true, slot, m)).collect();
+        assert_eq!(picks.iter().filter(|p| p.is_some()).count(), 2);
+    }
+
+    #[test]
+    fn test_provider_decision_serialization_and_header() {
+        let decision = ProviderDecision {
+            provider: "zai".to_string(),
+            dispatch_mode: Some(Fallback),
+            reason: Some(DispatchReason::AllUnavailable { model: "claude-opus-4-5".to_string() }),
+        };
+        assert_eq!(
+            serde_json::to_value(&decision).unwrap(),
+            serde_json::json!({
+                "provider": "zai",
+                "dispatch_mode": "fallback",
+                "reason": { "kind": "all_unavailable", "model": "claude-opus-4-5" }
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(ProviderDecision::google()).unwrap(),
+            serde_json::json!({ "provider": "google" })
+        );
+
+        let response = decision.clone().attach(StatusCode::OK.into_response());
+        assert_eq!(response.headers().get(PROVIDER_HEADER).unwrap(), "zai");
+        assert_eq!(response.extensions().get::<ProviderDecision>(), Some(&decision));
+    }
+
+    #[test]
+    fn test_effective_providers_migrates_zai() {
+        let zai = crate::proxy::ZaiConfig {
+            enabled: true,
+            api_key: "k".to_string(),
+            dispatch_mode: Fallback,
+            ..Default::default()
+        };
+        let vllm = ProviderConfig {
+            id: "vllm".to_string(),
+            enabled: true,
+            base_url: "http://127.0.0.1:8000".to_string(),
+            api_key: "x".to_string(),
+            protocols: vec![ListenerProtocol::OpenAI],
+            dispatch_mode: Fallback,
+            models: vec!["qwen*".to_string()],
+            model_mapping: HashMap::new(),
+        };
+
+        let list = effective_providers(&zai, std::slice::from_ref(&vllm));
+        let ids: Vec<_> = list.iter().map(|p| p.id.as_str()).collect();
+        assert_eq!(ids, vec!["zai", "vllm"]);
+        assert!(list[1].allows_model("qwen3-32b"));
+        assert!(!list[1].allows_model("claude-opus-4-5"));
 
+        // 显式 "zai" 条目决定顺序
+        let explicit = ProviderConfig {
+            id: "zai".to_string(),
+            ..vllm.clone()
+        };
+        let list = effective_providers(&zai, &[vllm, explicit]);
+        assert_eq!(list[1].id, "zai");
+        assert_eq!(list[1].api_key, "k");
+    }
+}
@@ -0,0 +1,371 @@
+// z.ai 模型列表缓存
+// 模型选择器与 /v1/models 共用同一份缓存: TTL 内不访问上游, 获取失败时回退到最近一次成功的快照
+// (响应带 stale 标记), 快照持久化到 zai_models.json, 并始终合并配置中手动补充的模型 ID。
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tokio::time::Duration;
+
+use crate::proxy::config::UpstreamProxyConfig;
+use crate::proxy::ZaiConfig;
+
+const STORE_FILE: &str = "zai_models.json";
+
+/// base_url -> 最近一次成功获取的快照
+static SNAPSHOTS: Lazy<Mutex<HashMap<String, ZaiModelSnapshot>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+static STORE_PATH: Lazy<Mutex<Option<PathBuf>>> = Lazy::new(|| Mutex::new(None));
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ZaiModelSnapshot {
+    models: Vec<String>,
+    /// 获取时间 (unix 秒)
+    fetched_at: i64,
+}
+
+/// 模型列表 (fetch_zai_models 的返回值)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ZaiModelList {
+    pub models: Vec<String>,
+    /// 上游获取失败, 返回的是旧快照
+    pub stale: bool,
+    /// 快照的获取时间 (unix 秒), 仅有手动模型时为 None
+    pub fetched_at: Option<i64>,
+    /// 本次获取失败的原因
+    pub error: Option<String>,
+}
+
+/// 获取模型列表: TTL 内直接返回缓存, `force_refresh` 时总是访问上游
+pub async fn list_models(
+    zai: &ZaiConfig,
+    upstream_proxy: &UpstreamProxyConfig,
+    request_timeout: u64,
+    force_refresh: bool,
+) -> Result<ZaiModelList, String> {
+    ensure_loaded();
+    list_models_with(zai, force_refresh, || fetch_remote(zai, upstream_proxy, request_timeout)).await
+}
+
+/// 只读缓存 (/v1/models 使用, 从不访问上游): 快照 + 手动模型
+pub fn cached_models(zai: &ZaiConfig) -> Vec<String> {
+    ensure_loaded();
+    cached(zai)
+}
+
+/// /v1/models 中追加的 z.ai 模型 (z.ai 未启用或分发关闭时为空)
+pub async fn listed_models(state: &crate::proxy::server::AppState) -> Vec<String> {
+    let zai = state.zai.read().await.clone();
+    if !zai.enabled || zai.dispatch_mode == crate::proxy::ZaiDispatchMode::Off {
+        return Vec::new();
+    }
+    cached_models(&zai)
+}
+
+fn cached(zai: &ZaiConfig) -> Vec<String> {
+    let snapshot = snapshot_for(&zai.base_url);
+    merge(snapshot.map(|s| s.models).unwrap_or_default(), &zai.custom_models)
+}
+
+async fn list_models_with<F, Fut>(
+    zai: &ZaiConfig,
+    force_refresh: bool,
+    fetch: F,
+) -> Result<ZaiModelList, String>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<Vec<String>, String>>,
+{
+    let snapshot = snapshot_for(&zai.base_url);
+    let now = chrono::Utc::now().timestamp();
+
+    if let Some(snapshot) = &snapshot {
+        if !force_refresh && now - snapshot.fetched_at < zai.models_cache_ttl_secs as i64 {
+            return Ok(ZaiModelList {
+                models: merge(snapshot.models.clone(), &zai.custom_models),
+                stale: false,
+                fetched_at: Some(snapshot.fetched_at),
+                error: None,
+            });
+        }
+    }
+
+    match fetch().await {
+        Ok(models) => {
+            let fresh = ZaiModelSnapshot {
+                models,
+                fetched_at: now,
+            };
+            if let Ok(mut snapshots) = SNAPSHOTS.lock() {
+                snapshots.insert(zai.base_url.clone(), fresh.clone());
+            }
+            save();
+            Ok(ZaiModelList {
+                models: merge(fresh.models, &zai.custom_models),
+                stale: false,
+                fetched_at: Some(now),
+                error: None,
+            })
+        }
+        Err(e) => {
+            tracing::warn!("[ZaiModels] Fetch failed, falling back to snapshot: {}", e);
+            match snapshot {
+                Some(snapshot) => Ok(ZaiModelList {
+                    models: merge(snapshot.models, &zai.custom_models),
+                    stale: true,
+                    fetched_at: Some(snapshot.fetched_at),
+                    error: Some(e),
+                }),
+                None if !zai.custom_models.is_empty() => Ok(ZaiModelList {
+                    models: merge(Vec::new(), &zai.custom_models),
+                    stale: true,
+                    fetched_at: None,
+                    error: Some(e),
+                }),
+                None => Err(e),
+            }
+        }
+    }
+}
+
+fn snapshot_for(base_url: &str) -> Option<ZaiModelSnapshot> {
+    SNAPSHOTS.lock().ok()?.get(base_url).cloned()
+}
+
+/// 合并手动模型并去重排序
+fn merge(mut models: Vec<String>, custom: &[String]) -> Vec<String> {
+    models.extend(custom.iter().map(|m| m.trim().to_string()));
+    models.retain(|s| !s.trim().is_empty());
+    models.sort();
+    models.dedup();
+    models
+}
+
+/// 首次使用时从数据目录加载快照
+fn ensure_loaded() {
+    let Ok(mut store) = STORE_PATH.lock() else {
+        return;
+    };
+    if store.is_some() {
+        return;
+    }
+    let dir = match crate::modules::account::get_data_dir() {
+        Ok(dir) => dir,
+        Err(e) => {
+            tracing::warn!("[ZaiModels] Data dir unavailable, snapshot will not persist: {}", e);
+            return;
+        }
+    };
+    let path = dir.join(STORE_FILE);
+    if let Ok(content) = std::fs::read_to_string(&path) {
+        match serde_json::from_str::<HashMap<String, ZaiModelSnapshot>>(&content) {
+            Ok(loaded) => {
+                if let Ok(mut snapshots) = SNAPSHOTS.lock() {
+                    for (base_url, snapshot) in loaded {
+                        snapshots.entry(base_url).or_insert(snapshot);
+                    }
+                }
+            }
+            Err(e) => tracing::warn!("[ZaiModels] Ignoring unreadable {:?}: {}", path, e),
+        }
+    }
+    *store = Some(path);
+}
+
+fn save() {
+    let Some(path) = STORE_PATH.lock().ok().and_then(|p| p.clone()) else {
+        return;
+    };
+    let json = match SNAPSHOTS.lock() {
+        Ok(snapshots) => serde_json::to_string_pretty(&*snapshots),
+        Err(_) => return,
+    };
+    match json {
+        Ok(json) => {
+            if let Err(e) = std::fs::write(&path, json) {
+                tracing::warn!("[ZaiModels] Failed to write {:?}: {}", path, e);
+            }
+        }
+        Err(e) => tracing::warn!("[ZaiModels] Failed to serialize snapshot: {}", e),
+    }
+}
+
+/// Join base URL with path
+fn join_base_url(base: &str, path: &str) -> String {
+    let base = base.trim_end_matches('/');
+    let path = if path.starts_with('/') {
+        path.to_string()
+    } else {
+        format!("/{}", path)
+    };
+    format!("{}{}", base, path)
+}
+
+/// Extract model IDs from JSON response
+fn extract_model_ids(value: &serde_json::Value) -> Vec<String> {
+    let mut out = Vec::new();
+
+    fn push_from_item(out: &mut Vec<String>, item: &serde_json::Value) {
+        match item {
+            serde_json::Value::String(s) => out.push(s.to_string()),
+            serde_json::Value::Object(map) => {
+                if let Some(id) = map.get("id").and_then(|v| v.as_str()) {
+                    out.push(id.to_string());
+                } else if let Some(name) = map.get("name").and_then(|v| v.as_str()) {
+                    out.push(name.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    match value {
+        serde_json::Value::Array(arr) => {
+            for item in arr {
+                push_from_item(&mut out, item);
+            }
+        }
+        serde_json::Value::Object(map) => {
+            if let Some(data) = map.get("data") {
+                if let serde_json::Value::Array(arr) = data {
+                    for item in arr {
+                        push_from_item(&mut out, item);
+                    }
+                }
+            }
+            if let Some(models) = map.get("models") {
+                match models {
+                    serde_json::Value::Array(arr) => {
+                        for item in arr {
+                            push_from_item(&mut out, item);
+                        }
+                    }
+                    other => push_from_item(&mut out, other),
+                }
+            }
+        }
+        _ => {}
+    }
+
+    out
+}
+
+/// Fetch available models from the configured z.ai Anthropic-compatible API (`/v1/models`).
+async fn fetch_remote(
+    zai: &ZaiConfig,
+    upstream_proxy: &UpstreamProxyConfig,
+    request_timeout: u64,
+) -> Result<Vec<String>, String> {
+    if zai.base_url.trim().is_empty() {
+        return Err("z.ai base_url is empty".to_string());
+    }
+    if zai.api_key.trim().is_empty() {
+        return Err("z.ai api_key is not set".to_string());
+    }
+
+    let url = join_base_url(&zai.base_url, "/v1/models");
+
+    let mut builder = reqwest::Client::builder().timeout(Duration::from_secs(request_timeout.max(5)));
+    if upstream_proxy.enabled && !upstream_proxy.url.is_empty() {
+        let proxy = reqwest::Proxy::all(&upstream_proxy.url)
+            .map_err(|e| format!("Invalid upstream proxy url: {}", e))?;
+        builder = builder.proxy(proxy);
+    }
+    let client = builder
+        .build()
+        .map_err(|e| format!("Failed to build HTTP client: {}", e))?;
+
+    let resp = client
+        .get(&url)
+        .header("Authorization", format!("Bearer {}", zai.api_key))
+        .header("x-api-key", zai.api_key.as_str())
+        .header("anthropic-version", "2023-06-01")
+        .header("accept", "application/json")
+        .send()
+        .await
+        .map_err(|e| format!("Upstream request failed: {}", e))?;
+
+    let status = resp.status();
+    let text = resp.text().await.map_err(|e| format!("Failed to read response: {}", e))?;
+
+    if !status.is_success() {
+        let preview = if text.len() > 4000 { &text[..4000] } else { &text };
+        return Err(format!("Upstream returned {}: {}", status, preview));
+    }
+
+    let json: serde_json::Value =
+        serde_json::from_str(&text).map_err(|e| format!("Invalid JSON response: {}", e))?;
+    Ok(merge(extract_model_ids(&json), &[]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn zai(base_url: &str, custom: &[&str]) -> ZaiConfig {
+        ZaiConfig {
+            base_url: base_url.to_string(),
+            custom_models: custom.iter().map(|s| s.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_extract_model_ids_shapes() {
+        let openai = serde_json::json!({ "data": [{ "id": "glm-4.7" }, { "name": "glm-4.5-air" }] });
+        let plain = serde_json::json!(["glm-4.6"]);
+        assert_eq!(extract_model_ids(&openai), vec!["glm-4.7", "glm-4.5-air"]);
+        assert_eq!(extract_model_ids(&plain), vec!["glm-4.6"]);
+    }
+
+    #[tokio::test]
+    async fn test_cache_hit_within_ttl_and_force_refresh() {
+        let config = zai("https://zai.test/cache", &["my-glm"]);
+        let calls = AtomicUsize::new(0);
+        let fetch = || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Ok(vec!["glm-4.7".to_string(), "glm-4.6".to_string()])
+        };
+
+        let first = list_models_with(&config, false, fetch).await.unwrap();
+        assert_eq!(first.models, vec!["glm-4.6", "glm-4.7", "my-glm"]);
+        assert!(!first.stale);
+
+        list_models_with(&config, false, fetch).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        list_models_with(&config, true, fetch).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // /v1/models 只读缓存
+        assert_eq!(cached(&config), vec!["glm-4.6", "glm-4.7", "my-glm"]);
+    }
+
+    #[tokio::test]
+    async fn test_failure_falls_back_to_stale_snapshot() {
+        let config = zai("https://zai.test/fallback", &[]);
+        list_models_with(&config, false, || async { Ok(vec!["glm-4.7".to_string()]) })
+            .await
+            .unwrap();
+
+        let stale = list_models_with(&config, true, || async { Err("connection refused".to_string()) })
+            .await
+            .unwrap();
+        assert!(stale.stale);
+        assert_eq!(stale.models, vec!["glm-4.7"]);
+        assert_eq!(stale.error.as_deref(), Some("connection refused"));
+
+        // 没有快照也没有手动模型时返回错误, 只有手动模型时返回 stale 列表
+        let empty = zai("https://zai.test/empty", &[]);
+        assert!(list_models_with(&empty, false, || async { Err("down".to_string()) }).await.is_err());
+        let custom_only = zai("https://zai.test/custom", &["glm-custom"]);
+        let list = list_models_with(&custom_only, false, || async { Err("down".to_string()) })
+            .await
+            .unwrap();
+        assert!(list.stale);
+        assert_eq!(list.models, vec!["glm-custom"]);
+    }
+}
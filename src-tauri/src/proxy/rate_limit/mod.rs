@@ -14,7 +14,11 @@ pub use types::{RateLimitReason, RateLimitInfo};
 pub use parsing::{parse_rate_limit_reason, parse_retry_time_from_body, parse_duration_string};
 
 use dashmap::DashMap;
-use std::time::{Duration, SystemTime};
+use std::collections::VecDeque;
+use std::sync::RwLock;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::proxy::sticky_config::RateLimitPolicy;
 
 /// Failure count expiry time: 1 hour (reset count if no failures within this period)
 const FAILURE_COUNT_EXPIRY_SECONDS: u64 = 3600;
@@ -24,6 +28,10 @@ pub struct RateLimitTracker {
     limits: DashMap<String, RateLimitInfo>,
     /// Consecutive failure counts (for intelligent exponential backoff), with timestamp for auto-expiry
     failure_counts: DashMap<String, (u32, SystemTime)>,
+    /// 最近的 5xx 时间点 (用于 server_error_threshold 判定)
+    server_errors: DashMap<String, VecDeque<Instant>>,
+    /// 限流策略 (随调度配置热更新)
+    policy: RwLock<RateLimitPolicy>,
 }
 
 impl RateLimitTracker {
@@ -31,6 +39,38 @@ impl RateLimitTracker {
         Self {
             limits: DashMap::new(),
             failure_counts: DashMap::new(),
+            server_errors: DashMap::new(),
+            policy: RwLock::new(RateLimitPolicy::default()),
+        }
+    }
+
+    pub fn policy(&self) -> RateLimitPolicy {
+        self.policy.read().map(|p| p.clone()).unwrap_or_default()
+    }
+
+    pub fn set_policy(&self, policy: RateLimitPolicy) {
+        if let Ok(mut current) = self.policy.write() {
+            *current = policy;
+        }
+    }
+
+    /// 记录一次 5xx, 返回窗口内是否已达到阈值 (达到后清空计数)
+    pub fn record_server_error(&self, account_id: &str, threshold: u32, window_secs: u64) -> bool {
+        if threshold <= 1 {
+            return true;
+        }
+        let now = Instant::now();
+        let window = Duration::from_secs(window_secs);
+        let mut hits = self.server_errors.entry(account_id.to_string()).or_default();
+        while hits.front().is_some_and(|t| now.duration_since(*t) > window) {
+            hits.pop_front();
+        }
+        hits.push_back(now);
+        if hits.len() as u32 >= threshold {
+            hits.clear();
+            true
+        } else {
+            false
         }
     }
 
@@ -85,6 +125,7 @@ impl RateLimitTracker {
         if self.failure_counts.remove(account_id).is_some() {
             tracing::debug!("Account {} request succeeded, failure count reset", account_id);
         }
+        self.server_errors.remove(account_id);
 
         // 1. Clear account-level global rate limit
         self.limits.remove(account_id);
@@ -129,20 +170,13 @@ impl RateLimitTracker {
         let key = self.get_limit_key(account_id, model.as_deref());
         self.limits.insert(key, info);
 
-        if let Some(m) = &model {
-            tracing::info!(
-                "Account {} model {} precisely locked until quota refresh, {} seconds remaining",
-                account_id,
-                m,
-                retry_sec
-            );
-        } else {
-            tracing::info!(
-                "Account {} precisely locked until quota refresh, {} seconds remaining",
-                account_id,
-                retry_sec
-            );
-        }
+        tracing::warn!(
+            "[RateLimit] Limiter set: account={} model={} cause={:?} (quota reset time) duration={}s",
+            account_id,
+            model.as_deref().unwrap_or("*"),
+            reason,
+            retry_sec
+        );
     }
 
     /// Precisely lock account using ISO 8601 time string
@@ -194,8 +228,8 @@ impl RateLimitTracker {
         model: Option<String>,
         backoff_steps: &[u64],
     ) -> Option<RateLimitInfo> {
-        // Support 429 (rate limit) and 500/503/529 (backend soft avoidance)
-        if status != 429 && status != 500 && status != 503 && status != 529 {
+        let policy = self.policy();
+        if !policy.applies_to(status) {
             return None;
         }
 
@@ -203,8 +237,10 @@ impl RateLimitTracker {
         let reason = if status == 429 {
             tracing::warn!("Google 429 Error Body: {}", body);
             parse_rate_limit_reason(body)
-        } else {
+        } else if status >= 500 {
             RateLimitReason::ServerError
+        } else {
+            RateLimitReason::Unknown
         };
 
         let mut retry_after_sec = None;
@@ -295,13 +331,15 @@ impl RateLimitTracker {
                     }
                     RateLimitReason::ServerError => {
                         // 5xx error
-                        tracing::warn!("Detected 5xx error ({}), executing 8s soft avoidance...", status);
-                        8
+                        let lockout = policy.default_duration(status, 8);
+                        tracing::warn!("Detected 5xx error ({}), executing {}s soft avoidance...", status, lockout);
+                        lockout
                     }
                     RateLimitReason::Unknown => {
                         // Unknown reason
-                        tracing::debug!("Cannot parse 429 rate limit reason, using default 60s");
-                        60
+                        let lockout = policy.default_duration(status, 60);
+                        tracing::debug!("Cannot parse rate limit reason ({}), using default {}s", status, lockout);
+                        lockout
                     }
                 }
             }
@@ -327,10 +365,12 @@ impl RateLimitTracker {
         self.limits.insert(key, info.clone());
 
         tracing::warn!(
-            "Account {} [{}] rate limit type: {:?}, reset delay: {}s",
+            "[RateLimit] Limiter set: account={} model={} cause={:?} (HTTP {}{}) duration={}s",
             account_id,
-            status,
+            if use_model_key { model.as_deref().unwrap_or("*") } else { "*" },
             reason,
+            status,
+            if retry_after_sec.is_some() { ", explicit retry time" } else { "" },
             retry_sec
         );
 
@@ -471,4 +511,39 @@ mod tests {
         // Due to time passing, it might be 1 or 2
         assert!(wait >= 1 && wait <= 2);
     }
+
+    #[test]
+    fn test_server_error_threshold() {
+        let tracker = RateLimitTracker::new();
+        assert!(!tracker.record_server_error("acc1", 3, 60));
+        assert!(!tracker.record_server_error("acc1", 3, 60));
+        assert!(tracker.record_server_error("acc1", 3, 60));
+        // 达到阈值后重新计数
+        assert!(!tracker.record_server_error("acc1", 3, 60));
+
+        // 成功请求清空计数
+        tracker.mark_success("acc1", None);
+        assert!(!tracker.record_server_error("acc1", 3, 60));
+        assert!(!tracker.record_server_error("acc1", 3, 60));
+
+        // 阈值 <= 1 时每次都生效
+        assert!(tracker.record_server_error("acc2", 1, 60));
+    }
+
+    #[test]
+    fn test_policy_controls_statuses_and_durations() {
+        let tracker = RateLimitTracker::new();
+        let policy = RateLimitPolicy {
+            limited_statuses: vec![429, 502],
+            default_durations: std::collections::HashMap::from([(502, 30)]),
+            ..Default::default()
+        };
+        tracker.set_policy(policy);
+        assert!(tracker.parse_from_error("acc1", 500, None, "", None, &[]).is_none());
+        assert!(!tracker.is_rate_limited("acc1", None));
+
+        let info = tracker.parse_from_error("acc1", 502, None, "", None, &[]).unwrap();
+        assert_eq!(info.retry_after_sec, 30);
+        assert_eq!(info.reason, RateLimitReason::ServerError);
+    }
 }
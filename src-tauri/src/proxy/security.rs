@@ -1,10 +1,11 @@
-use crate::proxy::config::{ProxyAuthMode, ProxyConfig, SecurityMonitorConfig};
+use crate::proxy::config::{KeyScope, ProxyAuthMode, ProxyConfig, ScopedApiKey, SecurityMonitorConfig};
 
 #[derive(Debug, Clone)]
 pub struct ProxySecurityConfig {
     pub auth_mode: ProxyAuthMode,
     pub api_key: String,
     pub admin_password: Option<String>,
+    pub api_keys: Vec<ScopedApiKey>,
     pub allow_lan_access: bool,
     pub port: u16,
     pub security_monitor: SecurityMonitorConfig,
@@ -16,6 +17,7 @@ impl ProxySecurityConfig {
             auth_mode: config.auth_mode.clone(),
             api_key: config.api_key.clone(),
             admin_password: config.admin_password.clone(),
+            api_keys: config.api_keys.clone(),
             allow_lan_access: config.allow_lan_access,
             port: config.port,
             security_monitor: config.security_monitor.clone(),
@@ -34,6 +36,46 @@ impl ProxySecurityConfig {
             ref other => other.clone(),
         }
     }
+
+    fn admin_password(&self) -> Option<&str> {
+        self.admin_password.as_deref().filter(|p| !p.is_empty())
+    }
+
+    /// 是否配置了任何可用的凭据
+    pub fn has_credentials(&self) -> bool {
+        !self.api_key.is_empty()
+            || self.admin_password().is_some()
+            || self.api_keys.iter().any(|k| !k.key.is_empty())
+    }
+
+    /// 客户端 Key 拥有的权限; 未知 Key 返回 None
+    ///
+    /// 与旧行为保持一致: api_key 可调用模型接口, 未设置 admin_password 时同时拥有管理权限;
+    /// admin_password 只用于管理接口。
+    pub fn scopes_for_key(&self, key: &str) -> Option<Vec<KeyScope>> {
+        if key.is_empty() {
+            return None;
+        }
+        let mut scopes = Vec::new();
+        if key == self.api_key {
+            scopes.push(KeyScope::Inference);
+            if self.admin_password().is_none() {
+                scopes.extend([KeyScope::ReadStats, KeyScope::Admin]);
+            }
+        }
+        if self.admin_password() == Some(key) {
+            scopes.extend([KeyScope::ReadStats, KeyScope::Admin]);
+        }
+        for entry in self.api_keys.iter().filter(|k| k.key == key) {
+            scopes.extend(entry.scopes.iter().copied());
+        }
+        if scopes.is_empty() {
+            return None;
+        }
+        scopes.sort_by_key(|s| *s as u8);
+        scopes.dedup();
+        Some(scopes)
+    }
 }
 
 #[cfg(test)]
@@ -46,6 +88,7 @@ mod tests {
             auth_mode: ProxyAuthMode::Auto,
             api_key: "sk-test".to_string(),
             admin_password: None,
+            api_keys: Vec::new(),
             allow_lan_access: false,
             port: 8080,
             security_monitor: SecurityMonitorConfig::default(),
@@ -59,6 +102,7 @@ mod tests {
             auth_mode: ProxyAuthMode::Auto,
             api_key: "sk-test".to_string(),
             admin_password: None,
+            api_keys: Vec::new(),
             allow_lan_access: true,
             port: 8080,
             security_monitor: SecurityMonitorConfig::default(),
@@ -13,6 +13,7 @@ use crate::modules::{account, logger};
 use crate::proxy::server::types::{
     AccountListResponse, AccountResponse, AddAccountRequest, AppState, BindDeviceRequest,
     ErrorResponse, ModelQuota, QuotaResponse, ReorderRequest, BulkDeleteRequest,
+    BulkPauseRequest, BulkPriorityRequest, BulkTagsRequest, AccountNotesRequest,
     SubmitCodeRequest, SwitchRequest, ToggleProxyRequest, to_account_response,
 };
 
@@ -65,6 +66,9 @@ pub async fn list_accounts(
                 protected_models: acc.protected_models.into_iter().collect(),
                 quota,
                 device_bound: acc.device_profile.is_some(),
+                tags: acc.tags,
+                notes: acc.notes,
+                last_error: acc.last_error,
                 last_used: acc.last_used,
             }
         })
@@ -118,6 +122,9 @@ pub async fn get_current_account(
                 protected_models: acc.protected_models.into_iter().collect(),
                 quota,
                 device_bound: acc.device_profile.is_some(),
+                tags: acc.tags,
+                notes: acc.notes,
+                last_error: acc.last_error,
                 last_used: acc.last_used,
             }
         })
@@ -248,16 +255,122 @@ pub async fn refresh_all_quotas(
     Ok(Json(stats))
 }
 
+/// Apply a batch operation and reload the token pool once
+async fn run_account_batch(
+    state: &AppState,
+    account_ids: &[String],
+    op: account::BatchOperation,
+    force: bool,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    let protect_last_usable = *state.is_running.read().await && !force;
+    let is_delete = matches!(op, account::BatchOperation::Delete);
+    let result = account::apply_batch(account_ids, &op, protect_last_usable, |ids| {
+        if is_delete {
+            state.token_manager.remove_accounts(ids);
+        }
+    });
+
+    if let Err(e) = state.token_manager.load_accounts().await {
+        logger::log_error(&format!(
+            "[API] Failed to reload accounts after batch {}: {}",
+            op.name(),
+            e
+        ));
+    }
+
+    let results = result.map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse { error: e }),
+        )
+    })?;
+    Ok(Json(results))
+}
+
 pub async fn delete_accounts(
+    State(state): State<AppState>,
     Json(payload): Json<BulkDeleteRequest>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
-    crate::modules::account::delete_accounts(&payload.account_ids).map_err(|e| {
+    run_account_batch(
+        &state,
+        &payload.account_ids,
+        account::BatchOperation::Delete,
+        payload.force,
+    )
+    .await
+}
+
+pub async fn pause_accounts(
+    State(state): State<AppState>,
+    Json(payload): Json<BulkPauseRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    run_account_batch(
+        &state,
+        &payload.account_ids,
+        account::BatchOperation::Pause,
+        payload.force,
+    )
+    .await
+}
+
+pub async fn set_accounts_priority(
+    State(state): State<AppState>,
+    Json(payload): Json<BulkPriorityRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    run_account_batch(
+        &state,
+        &payload.account_ids,
+        account::BatchOperation::SetPriority(payload.priority),
+        false,
+    )
+    .await
+}
+
+pub async fn set_accounts_tags(
+    State(state): State<AppState>,
+    Json(payload): Json<BulkTagsRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    run_account_batch(
+        &state,
+        &payload.account_ids,
+        account::BatchOperation::SetTags {
+            add: payload.add,
+            remove: payload.remove,
+        },
+        false,
+    )
+    .await
+}
+
+pub async fn set_account_notes(
+    State(state): State<AppState>,
+    Path(account_id): Path<String>,
+    Json(payload): Json<AccountNotesRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    run_account_batch(
+        &state,
+        &[account_id],
+        account::BatchOperation::SetNotes(payload.notes),
+        false,
+    )
+    .await
+}
+
+pub async fn get_account_health(
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    let accounts = state.account_service.list_accounts().await.map_err(|e| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(ErrorResponse { error: e }),
         )
     })?;
-    Ok(StatusCode::OK)
+    let token_manager = (*state.is_running.read().await).then_some(state.token_manager.as_ref());
+    let health: Vec<crate::proxy::token_manager::AccountHealth> = accounts
+        .iter()
+        .map(|acc| crate::proxy::token_manager::AccountHealth::new(acc, token_manager))
+        .collect();
+    Ok(Json(health))
 }
 
 pub async fn reorder_accounts(
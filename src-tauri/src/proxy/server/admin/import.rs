@@ -9,8 +9,8 @@ use axum::{
     Json,
 };
 
-use crate::modules::{account, migration};
-use crate::proxy::server::types::{AppState, CustomDbRequest, ErrorResponse, to_account_response, AccountResponse};
+use crate::modules::{account, antigravity_import, migration};
+use crate::proxy::server::types::{AppState, AntigravityImportRequest, CustomDbRequest, ErrorResponse, to_account_response, AccountResponse};
 
 // ============================================================================
 // Import Handlers
@@ -67,6 +67,19 @@ pub async fn import_from_db(
     Ok(Json(to_account_response(&account, &current_id)))
 }
 
+pub async fn import_from_antigravity(
+    State(state): State<AppState>,
+    Json(payload): Json<AntigravityImportRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    let report = antigravity_import::import_from_antigravity(payload.confirmed.as_deref()).await;
+
+    if !report.imported.is_empty() {
+        let _ = state.token_manager.load_accounts().await;
+    }
+
+    Ok(Json(report))
+}
+
 pub async fn import_custom_db(
     State(state): State<AppState>,
     Json(payload): Json<CustomDbRequest>,
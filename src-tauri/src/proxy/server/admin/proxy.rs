@@ -10,9 +10,12 @@ use axum::{
 };
 
 use crate::modules::logger;
+use crate::proxy::monitor::{ProxyStatsReport, StatsSection};
+use crate::proxy::ports::ProxyEndpoints;
 use crate::proxy::server::types::{
-    AppState, ErrorResponse, LogsFilterQuery, OpencodeConfigContentRequest, OpencodeSyncRequest,
-    OpencodeSyncStatusRequest, UpdateMappingWrapper,
+    AppState, EndpointUrlQuery, ErrorResponse, LogsFilterQuery, OpencodeConfigContentRequest, OpencodeSyncRequest,
+    OpencodeSyncStatusRequest, StatsQuery, UpdateBackgroundRoutingWrapper, UpdateMappingWrapper, UpdateRetryConfigWrapper,
+    UpdateSchedulingConfigWrapper,
 };
 
 // ============================================================================
@@ -22,31 +25,54 @@ use crate::proxy::server::types::{
 pub async fn get_proxy_status(
     State(state): State<AppState>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
-    let active_accounts = state.token_manager.len();
+    // 只读取缓存值, 详细统计见 /api/proxy/stats
+    let active_accounts = state.token_manager.effective_len_cached();
     let is_running = { *state.is_running.read().await };
-    
+
     Ok(Json(serde_json::json!({
         "running": is_running,
         "port": state.port,
         "base_url": format!("http://127.0.0.1:{}", state.port),
+        "endpoints": is_running.then(|| ProxyEndpoints::for_port(state.port)),
         "active_accounts": active_accounts,
+        "uptime_secs": if is_running { crate::proxy::server::uptime_secs() } else { 0 },
+        "upstream_warning": crate::proxy::failure_patterns::current_warning(),
+        "maintenance": crate::proxy::maintenance::status(),
     })))
 }
 
+/// 协议对应的客户端 base URL (与 copy_endpoint_url 命令一致)
+pub async fn get_endpoint_url(
+    State(state): State<AppState>,
+    axum::extract::Query(params): axum::extract::Query<EndpointUrlQuery>,
+) -> impl IntoResponse {
+    Json(ProxyEndpoints::for_port(state.port).url(params.protocol).to_string())
+}
+
 pub async fn start_proxy_service(State(state): State<AppState>) -> impl IntoResponse {
     // 1. Persist config (fix #1166)
-    if let Ok(mut config) = crate::modules::config::load_app_config() {
-        config.proxy.auto_start = true;
-        let _ = crate::modules::config::save_app_config(&config);
-    }
+    let proxy_config = match crate::modules::config::load_app_config() {
+        Ok(mut config) => {
+            config.proxy.auto_start = true;
+            let _ = crate::modules::config::save_app_config(&config);
+            Some(config.proxy)
+        }
+        Err(_) => None,
+    };
 
     // 2. Load accounts if first start
     if let Err(e) = state.token_manager.load_accounts().await {
         logger::log_error(&format!("[API] Failed to load accounts on start: {}", e));
     }
 
+    // 3. Additional listeners follow the service state
+    if let Some(proxy_config) = proxy_config {
+        state.listeners.start_all(&state, &proxy_config).await;
+    }
+
     let mut running = state.is_running.write().await;
     *running = true;
+    crate::proxy::server::mark_running(true);
     logger::log_info("[API] Proxy service enabled (persisted)");
     StatusCode::OK
 }
@@ -58,45 +84,116 @@ pub async fn stop_proxy_service(State(state): State<AppState>) -> impl IntoRespo
         let _ = crate::modules::config::save_app_config(&config);
     }
 
+    state.listeners.stop_all().await;
+    crate::proxy::image_store::clear();
+
     let mut running = state.is_running.write().await;
     *running = false;
+    crate::proxy::server::mark_running(false);
     logger::log_info("[API] Proxy service disabled (Axum mode / persisted)");
     StatusCode::OK
 }
 
+pub async fn set_maintenance_mode(Json(payload): Json<serde_json::Value>) -> impl IntoResponse {
+    let enabled = payload
+        .get("enabled")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let message = payload
+        .get("message")
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string());
+    let duration_secs = payload.get("durationSecs").and_then(|v| v.as_u64());
+
+    let status = crate::proxy::maintenance::set(enabled, message, duration_secs);
+    logger::log_info(&format!(
+        "[API] Maintenance mode {}",
+        if status.enabled { "enabled" } else { "disabled" }
+    ));
+    Json(status)
+}
+
 pub async fn update_model_mapping(
     State(state): State<AppState>,
     Json(payload): Json<UpdateMappingWrapper>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
     let config = payload.config;
 
-    // 1. Hot-update memory state
-    {
-        let mut mapping = state.custom_mapping.write().await;
-        *mapping = config.custom_mapping.clone();
-    }
-
-    // 2. Persist to disk (fix #1149)
-    let mut app_config = crate::modules::config::load_app_config().map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse { error: e }),
-        )
-    })?;
-
-    app_config.proxy.custom_mapping = config.custom_mapping;
-
-    crate::modules::config::save_app_config(&app_config).map_err(|e| {
+    // Swap the in-memory mapping, then persist to disk (fix #1149); rolled back if saving fails
+    let _guard = crate::proxy::common::model_mapping::swap_custom_mapping(
+        &state.custom_mapping,
+        config.custom_mapping.clone(),
+        crate::modules::config::save_custom_mapping,
+    )
+    .await
+    .map_err(|e| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(ErrorResponse { error: e }),
         )
     })?;
+    state.listeners.refresh_mapping(&config.custom_mapping).await;
 
     logger::log_info("[API] Model mapping hot-updated and saved via API");
     Ok(StatusCode::OK)
 }
 
+pub async fn get_proxy_scheduling_config(State(state): State<AppState>) -> impl IntoResponse {
+    Json(state.token_manager.get_sticky_config().await)
+}
+
+pub async fn update_proxy_scheduling_config(
+    State(state): State<AppState>,
+    Json(payload): Json<UpdateSchedulingConfigWrapper>,
+) -> impl IntoResponse {
+    match crate::commands::proxy::scheduling::save_scheduling_config(payload.config) {
+        Ok(config) => {
+            state.token_manager.update_sticky_config(config.clone()).await;
+            logger::log_info("[API] Scheduling config hot-updated and saved via API");
+            Json(config).into_response()
+        }
+        Err(e) => (StatusCode::BAD_REQUEST, Json(e)).into_response(),
+    }
+}
+
+pub async fn get_proxy_retry_config() -> impl IntoResponse {
+    match crate::commands::proxy::scheduling::get_proxy_retry_config().await {
+        Ok(config) => Json(config).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(e)).into_response(),
+    }
+}
+
+pub async fn update_proxy_retry_config(Json(payload): Json<UpdateRetryConfigWrapper>) -> impl IntoResponse {
+    match crate::commands::proxy::scheduling::update_proxy_retry_config(payload.config).await {
+        Ok(config) => {
+            logger::log_info("[API] Retry policy hot-updated and saved via API");
+            Json(config).into_response()
+        }
+        Err(e) => (StatusCode::BAD_REQUEST, Json(e)).into_response(),
+    }
+}
+
+pub async fn get_background_routing() -> impl IntoResponse {
+    match crate::commands::proxy::scheduling::get_background_routing().await {
+        Ok(config) => Json(config).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(e)).into_response(),
+    }
+}
+
+pub async fn update_background_routing(
+    State(state): State<AppState>,
+    Json(payload): Json<UpdateBackgroundRoutingWrapper>,
+) -> impl IntoResponse {
+    match crate::commands::proxy::scheduling::save_background_routing(payload.config) {
+        Ok(app_config) => {
+            *state.experimental.write().await = app_config.proxy.experimental.clone();
+            logger::log_info("[API] Background task routing hot-updated and saved via API");
+            Json(app_config.proxy.experimental.background_routing).into_response()
+        }
+        Err(e) => (StatusCode::BAD_REQUEST, Json(e)).into_response(),
+    }
+}
+
 pub async fn generate_api_key() -> impl IntoResponse {
     let new_key = format!("sk-{}", uuid::Uuid::new_v4().to_string().replace("-", ""));
     Json(new_key)
@@ -134,6 +231,22 @@ pub async fn clear_rate_limit(
     }
 }
 
+pub async fn retry_project_setup(
+    State(state): State<AppState>,
+    Path(account_id): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    state
+        .token_manager
+        .retry_project_setup(&account_id)
+        .await
+        .map_err(|e| (StatusCode::CONFLICT, Json(ErrorResponse { error: e })))?;
+    logger::log_info(&format!(
+        "[API] Project setup verified for account {}",
+        account_id
+    ));
+    Ok(StatusCode::OK)
+}
+
 // ============================================================================
 // Monitor Control
 // ============================================================================
@@ -158,9 +271,26 @@ pub async fn set_proxy_monitor_enabled(
 
 pub async fn get_proxy_stats(
     State(state): State<AppState>,
+    axum::extract::Query(params): axum::extract::Query<StatsQuery>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
-    let stats = state.monitor.get_stats().await;
-    Ok(Json(stats))
+    let sections = match params.sections.as_deref() {
+        Some(raw) => StatsSection::parse_list(raw)
+            .map_err(|error| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error })))?,
+        None => {
+            crate::proxy::monitor::warn_legacy_stats_call();
+            vec![StatsSection::Latency]
+        }
+    };
+    let report = ProxyStatsReport::collect(Some(&state.monitor), Some(&state), &sections).await;
+    Ok(Json(report))
+}
+
+pub async fn get_calibration_state() -> impl IntoResponse {
+    Json(crate::proxy::mappers::estimation_calibrator::get_calibrator().state())
+}
+
+pub async fn get_model_health() -> impl IntoResponse {
+    Json(crate::proxy::model_health::snapshot())
 }
 
 // ============================================================================
@@ -257,62 +387,33 @@ pub async fn get_proxy_log_detail(
 // ============================================================================
 
 pub async fn fetch_zai_models(
-    Path(_id): Path<String>,
+    State(state): State<AppState>,
     Json(payload): Json<serde_json::Value>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
-    let zai_config = payload.get("zai").ok_or_else(|| {
-        (
-            StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                error: "Missing zai config".to_string(),
-            }),
-        )
-    })?;
-
-    let api_key = zai_config
-        .get("api_key")
-        .and_then(|v| v.as_str())
-        .unwrap_or("");
-    let base_url = zai_config
-        .get("base_url")
-        .and_then(|v| v.as_str())
-        .unwrap_or("https://api.z.ai");
+    let bad_request = |error: String| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error }));
+
+    let zai: crate::proxy::ZaiConfig = payload
+        .get("zai")
+        .cloned()
+        .ok_or_else(|| bad_request("Missing zai config".to_string()))
+        .and_then(|v| serde_json::from_value(v).map_err(|e| bad_request(e.to_string())))?;
+    let upstream_proxy = match payload.get("upstreamProxy").cloned() {
+        Some(v) => serde_json::from_value(v).map_err(|e| bad_request(e.to_string()))?,
+        None => state.upstream_proxy.read().await.clone(),
+    };
+    let request_timeout = payload
+        .get("requestTimeout")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(state.request_timeout);
+    let force_refresh = payload
+        .get("forceRefresh")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
 
-    let client = reqwest::Client::new();
-    let resp = client
-        .get(format!("{}/v1/models", base_url))
-        .header("Authorization", format!("Bearer {}", api_key))
-        .send()
+    crate::proxy::providers::zai_models::list_models(&zai, &upstream_proxy, request_timeout, force_refresh)
         .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: e.to_string(),
-                }),
-            )
-        })?;
-
-    let data: serde_json::Value = resp.json().await.map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: e.to_string(),
-            }),
-        )
-    })?;
-
-    let models = data
-        .get("data")
-        .and_then(|v| v.as_array())
-        .map(|arr| {
-            arr.iter()
-                .filter_map(|m| m.get("id").and_then(|id| id.as_str().map(|s| s.to_string())))
-                .collect::<Vec<String>>()
-        })
-        .unwrap_or_default();
-
-    Ok(Json(models))
+        .map(Json)
+        .map_err(|e| (StatusCode::BAD_GATEWAY, Json(ErrorResponse { error: e })))
 }
 
 // ============================================================================
@@ -69,20 +69,24 @@ pub async fn save_config(
 ) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
     let new_config = payload.config;
     
-    // 1. Persist to disk
-    crate::modules::config::save_app_config(&new_config).map_err(|e| {
+    // Swap the model mapping, persist to disk and refresh per-listener mappings as one serialized update; rolled back if saving fails
+    let mapping_guard = crate::proxy::common::model_mapping::swap_custom_mapping(
+        &state.custom_mapping,
+        new_config.proxy.custom_mapping.clone(),
+        |_| crate::modules::config::save_app_config(&new_config),
+    )
+    .await
+    .map_err(|e| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(ErrorResponse { error: e }),
         )
     })?;
-
-    // 2. Hot-update memory state
-    // Update model mapping
-    {
-        let mut mapping = state.custom_mapping.write().await;
-        *mapping = new_config.clone().proxy.custom_mapping;
-    }
+    state
+        .listeners
+        .refresh_mapping(&new_config.proxy.custom_mapping)
+        .await;
+    drop(mapping_guard);
 
     // Update upstream proxy
     {
@@ -108,6 +112,12 @@ pub async fn save_config(
         *exp = new_config.clone().proxy.experimental;
     }
 
+    // Update per-key budgets
+    crate::proxy::key_budget::set_config(&new_config.proxy.key_budgets);
+    crate::proxy::common::thinking_defaults::set_config(&new_config.proxy);
+    crate::proxy::common::system_injection::set_config(&new_config.proxy);
+    crate::proxy::image_store::set_ttl_secs(new_config.proxy.image_url_ttl_secs);
+
     Ok(StatusCode::OK)
 }
 
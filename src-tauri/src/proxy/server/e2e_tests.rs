@@ -0,0 +1,850 @@
+//! End-to-end tests: 真实路由 + 脚本化 v1internal 上游
+//!
+//! 每个场景为上游预先编排一串响应 (429 后成功、首字节延迟、畸形 chunk、签名 400、空流 ...),
+//! 请求经 routes::build_proxy_routes 进入真实 handler / mapper 链路,
+//! 断言客户端看到的结果以及上游实际收到的调用 (换号、请求体修复等)。
+
+use axum::body::Body;
+use axum::http::{HeaderMap, Request, StatusCode};
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tower::ServiceExt;
+
+use crate::proxy::config::RetryConfig;
+use crate::proxy::handlers::common::{ErrorProtocol, SSE_CONTENT_TYPE};
+use crate::proxy::middleware::{auth_middleware, content_type_middleware};
+use crate::proxy::server::types::AppState;
+use crate::proxy::upstream::client::UpstreamClient;
+use crate::proxy::upstream::transport::{UpstreamCall, UpstreamTransport};
+
+/// 单次上游调用的脚本化响应
+enum Scripted {
+    /// 非 2xx 响应
+    Error(u16, &'static str),
+    /// SSE 响应: 首字节前延迟, 之后原样写出各个 chunk (可以是畸形数据, 为空即空流)
+    Sse(Duration, Vec<String>),
+    /// 首字节前连接被重置
+    Reset,
+    /// 连接阶段超时 (与 UpstreamClient 的错误格式一致, 没有上游状态码)
+    ConnectTimeout,
+}
+
+/// 按脚本依次回放响应, 并记录收到的调用
+struct ScriptedUpstream {
+    script: Mutex<VecDeque<Scripted>>,
+    calls: Mutex<Vec<UpstreamCall>>,
+}
+
+impl ScriptedUpstream {
+    fn new(script: Vec<Scripted>) -> Self {
+        Self {
+            script: Mutex::new(script.into()),
+            calls: Mutex::default(),
+        }
+    }
+
+    fn calls(&self) -> Vec<UpstreamCall> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    fn remaining(&self) -> usize {
+        self.script.lock().unwrap().len()
+    }
+}
+
+impl UpstreamTransport for ScriptedUpstream {
+    fn send(&self, call: UpstreamCall) -> BoxFuture<'_, Result<reqwest::Response, String>> {
+        self.calls.lock().unwrap().push(call);
+        let next = self.script.lock().unwrap().pop_front();
+        Box::pin(async move {
+            let builder = axum::http::Response::builder();
+            let response = match next {
+                None => return Err("scripted upstream exhausted".to_string()),
+                Some(Scripted::ConnectTimeout) => {
+                    return Err("HTTP request failed at scripted [connect_timeout_error]: TCP connect timed out".to_string())
+                }
+                Some(Scripted::Error(status, body)) => builder
+                    .status(status)
+                    .header("content-type", "application/json")
+                    .body(reqwest::Body::from(body)),
+                Some(Scripted::Sse(delay, chunks)) => {
+                    let stream = async_stream::stream! {
+                        if !delay.is_zero() {
+                            tokio::time::sleep(delay).await;
+                        }
+                        for chunk in chunks {
+                            yield Ok::<Bytes, std::io::Error>(Bytes::from(chunk));
+                        }
+                    };
+                    builder
+                        .status(200)
+                        .header("content-type", "text/event-stream")
+                        .body(reqwest::Body::wrap_stream(stream))
+                }
+                Some(Scripted::Reset) => {
+                    let stream = futures::stream::once(async {
+                        Err::<Bytes, std::io::Error>(std::io::Error::new(
+                            std::io::ErrorKind::ConnectionReset,
+                            "connection reset by peer",
+                        ))
+                    });
+                    builder
+                        .status(200)
+                        .header("content-type", "text/event-stream")
+                        .body(reqwest::Body::wrap_stream(stream))
+                }
+            };
+            response
+                .map(reqwest::Response::from)
+                .map_err(|e| e.to_string())
+        })
+    }
+}
+
+const RATE_LIMITED: &str = r#"{"error":{"code":429,"message":"Resource has been exhausted (e.g. check quota).","status":"RESOURCE_EXHAUSTED"}}"#;
+const INVALID_SIGNATURE: &str = r#"{"error":{"code":400,"message":"messages.1.content.0: Invalid `signature` in `thinking` block","status":"INVALID_ARGUMENT"}}"#;
+const THINKING_UNSUPPORTED: &str = r#"{"error":{"code":400,"message":"Invalid JSON payload received. Unknown name \"thinkingConfig\" at 'request.generation_config': Cannot find field.","status":"INVALID_ARGUMENT"}}"#;
+const INTERNAL_ERROR: &str = r#"{"error":{"code":500,"message":"Internal error encountered.","status":"INTERNAL"}}"#;
+const INVALID_ARGUMENT: &str = r#"{"error":{"code":400,"message":"Request contains an invalid argument.","status":"INVALID_ARGUMENT"}}"#;
+
+/// v1internal SSE 事件 (包在 response 中), 最后一个事件带 finishReason 与 usage
+fn sse_event(text: &str, last: bool) -> String {
+    let mut candidate = json!({
+        "content": { "role": "model", "parts": [{ "text": text }] },
+        "index": 0
+    });
+    let mut response = json!({ "modelVersion": "gemini-2.5-flash", "responseId": "scripted" });
+    if last {
+        candidate["finishReason"] = json!("STOP");
+        response["usageMetadata"] =
+            json!({ "promptTokenCount": 12, "candidatesTokenCount": 4, "totalTokenCount": 16 });
+    }
+    response["candidates"] = json!([candidate]);
+    format!("data: {}\n\n", json!({ "response": response }))
+}
+
+/// 完整的成功流
+fn success(parts: &[&str]) -> Scripted {
+    delayed_success(Duration::ZERO, parts)
+}
+
+fn delayed_success(delay: Duration, parts: &[&str]) -> Scripted {
+    let events = parts
+        .iter()
+        .enumerate()
+        .map(|(i, text)| sse_event(text, i + 1 == parts.len()))
+        .collect();
+    Scripted::Sse(delay, events)
+}
+
+fn empty_stream() -> Scripted {
+    Scripted::Sse(Duration::ZERO, Vec::new())
+}
+
+/// 缩短 peek 超时并去掉退避基准 (只剩抖动), 让重试场景在秒级内完成;
+/// 只在 Harness 请求的任务内生效, 不改动全局配置
+fn fast_retry_config() -> RetryConfig {
+    RetryConfig {
+        peek_timeout_secs: 1,
+        base_delay_ms: 0,
+        ..RetryConfig::default()
+    }
+}
+
+struct Harness {
+    state: AppState,
+    upstream: Arc<ScriptedUpstream>,
+    _data_dir: tempfile::TempDir,
+}
+
+struct HarnessResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: String,
+}
+
+impl HarnessResponse {
+    fn json(&self) -> Value {
+        serde_json::from_str(&self.body).unwrap_or(Value::Null)
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|v| v.to_str().ok())
+    }
+}
+
+impl Harness {
+    fn new(accounts: usize, script: Vec<Scripted>) -> Self {
+        let upstream = Arc::new(ScriptedUpstream::new(script));
+        let mock = Arc::new(crate::proxy::benchmark::MockUpstream::new(&Default::default()));
+        let data_dir = tempfile::tempdir().unwrap();
+        let state = AppState {
+            upstream: Arc::new(UpstreamClient::with_transport(upstream.clone())),
+            ..crate::proxy::benchmark::benchmark_state(mock, accounts, data_dir.path())
+        };
+        Self { state, upstream, _data_dir: data_dir }
+    }
+
+    async fn post(&self, path: &str, body: Value) -> HarnessResponse {
+        self.send(
+            Request::post(path)
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+        )
+        .await
+    }
+
+    /// 与生产一致: 路由外层依次挂上鉴权与响应 Content-Type 中间件
+    async fn send(&self, request: Request<Body>) -> HarnessResponse {
+        crate::proxy::config::with_retry_config(fast_retry_config(), async {
+            let response = super::routes::build_proxy_routes()
+                .route("/healthz", axum::routing::get(super::routes::health_check))
+                .layer(axum::middleware::from_fn_with_state(self.state.security.clone(), auth_middleware))
+                .layer(axum::middleware::from_fn(content_type_middleware))
+                .with_state(self.state.clone())
+                .oneshot(request)
+                .await
+                .unwrap();
+            let status = response.status();
+            let headers = response.headers().clone();
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            HarnessResponse {
+                status,
+                headers,
+                body: String::from_utf8_lossy(&bytes).into_owned(),
+            }
+        })
+        .await
+    }
+
+    async fn chat(&self, stream: bool, prompt: &str) -> HarnessResponse {
+        self.post(
+            "/v1/chat/completions",
+            json!({
+                "model": "gemini-2.5-flash",
+                "stream": stream,
+                "messages": [{ "role": "user", "content": prompt }]
+            }),
+        )
+        .await
+    }
+
+    async fn messages(&self, stream: bool, prompt: &str) -> HarnessResponse {
+        self.post(
+            "/v1/messages",
+            json!({
+                "model": "claude-sonnet-4-5",
+                "max_tokens": 1024,
+                "stream": stream,
+                "messages": [{ "role": "user", "content": prompt }]
+            }),
+        )
+        .await
+    }
+
+    fn tokens(&self) -> Vec<String> {
+        self.upstream.calls().into_iter().map(|call| call.access_token).collect()
+    }
+}
+
+/// SSE 响应体中可解析的 data 负载
+fn sse_payloads(body: &str) -> Vec<Value> {
+    body.lines()
+        .filter_map(|line| line.strip_prefix("data: "))
+        .filter_map(|data| serde_json::from_str(data.trim()).ok())
+        .collect()
+}
+
+fn openai_stream_text(body: &str) -> String {
+    sse_payloads(body)
+        .iter()
+        .filter_map(|chunk| chunk["choices"][0]["delta"]["content"].as_str())
+        .collect()
+}
+
+fn claude_stream_text(body: &str) -> String {
+    sse_payloads(body)
+        .iter()
+        .filter(|event| event["type"] == "content_block_delta" && event["delta"]["type"] == "text_delta")
+        .filter_map(|event| event["delta"]["text"].as_str())
+        .collect()
+}
+
+// ===== OpenAI /v1/chat/completions =====
+
+#[tokio::test]
+async fn test_openai_429_rotates_to_next_account() {
+    let harness = Harness::new(2, vec![Scripted::Error(429, RATE_LIMITED), success(&["Hello ", "world"])]);
+    let response = harness.chat(true, "e2e openai 429 rotation").await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(openai_stream_text(&response.body), "Hello world");
+    assert!(response.body.contains("data: [DONE]"));
+    let tokens = harness.tokens();
+    assert_eq!(tokens.len(), 2);
+    assert_ne!(tokens[0], tokens[1], "429 must rotate to another account");
+    assert!(response.header("X-Account-Email").is_some());
+}
+
+#[tokio::test]
+async fn test_openai_signature_400_appends_recovery_prompt() {
+    let harness = Harness::new(2, vec![Scripted::Error(400, INVALID_SIGNATURE), success(&["Recovered"])]);
+    let response = harness.chat(false, "e2e openai signature repair").await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json()["choices"][0]["message"]["content"], "Recovered");
+    let calls = harness.upstream.calls();
+    assert_eq!(calls.len(), 2);
+    assert!(!calls[0].body.to_string().contains("[System Recovery]"));
+    assert!(calls[1].body.to_string().contains("[System Recovery]"));
+}
+
+#[tokio::test]
+async fn test_openai_peek_timeout_retries() {
+    let harness = Harness::new(
+        2,
+        vec![
+            delayed_success(Duration::from_millis(2500), &["too late"]),
+            success(&["On time"]),
+        ],
+    );
+    let response = harness.chat(true, "e2e openai peek timeout").await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(openai_stream_text(&response.body), "On time");
+    assert_eq!(harness.upstream.calls().len(), 2);
+}
+
+#[tokio::test]
+async fn test_openai_slow_first_byte_within_peek_timeout() {
+    let harness = Harness::new(2, vec![delayed_success(Duration::from_millis(300), &["Slow ", "start"])]);
+    let response = harness.chat(true, "e2e openai slow first byte").await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(openai_stream_text(&response.body), "Slow start");
+    assert_eq!(harness.upstream.calls().len(), 1);
+}
+
+#[tokio::test]
+async fn test_openai_empty_stream_retries_for_non_stream_client() {
+    let harness = Harness::new(2, vec![empty_stream(), success(&["Second try"])]);
+    let response = harness.chat(false, "e2e openai empty stream").await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json()["choices"][0]["message"]["content"], "Second try");
+    assert_eq!(harness.upstream.calls().len(), 2);
+}
+
+#[tokio::test]
+async fn test_openai_reset_during_peek_retries() {
+    let harness = Harness::new(2, vec![Scripted::Reset, success(&["After reset"])]);
+    let response = harness.chat(true, "e2e openai reset").await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(openai_stream_text(&response.body), "After reset");
+    assert!(!response.body.contains("stream_error"));
+    assert_eq!(harness.upstream.calls().len(), 2);
+}
+
+#[tokio::test]
+async fn test_openai_malformed_chunk_mid_stream_is_skipped() {
+    let harness = Harness::new(
+        1,
+        vec![Scripted::Sse(
+            Duration::ZERO,
+            vec![
+                sse_event("Hello ", false),
+                "data: {\"response\": {\"candidates\": [\n\n".to_string(),
+                sse_event("world", true),
+            ],
+        )],
+    );
+    let response = harness.chat(true, "e2e openai malformed chunk").await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(openai_stream_text(&response.body), "Hello world");
+    assert!(response.body.contains("data: [DONE]"));
+    assert_eq!(harness.upstream.calls().len(), 1);
+}
+
+#[tokio::test]
+async fn test_openai_non_stream_collects_sse_into_json() {
+    let harness = Harness::new(1, vec![success(&["Collected ", "from ", "SSE"])]);
+    let response = harness.chat(false, "e2e openai collection").await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(response.header(crate::proxy::mappers::stream_completeness::TRUNCATED_HEADER).is_none());
+    let body = response.json();
+    assert_eq!(body["object"], "chat.completion");
+    assert_eq!(body["choices"][0]["message"]["content"], "Collected from SSE");
+    assert_eq!(body["choices"][0]["finish_reason"], "stop");
+    assert_eq!(body["usage"]["total_tokens"], 16);
+
+    let calls = harness.upstream.calls();
+    assert_eq!(calls[0].method, "streamGenerateContent");
+    assert_eq!(calls[0].query_string.as_deref(), Some("alt=sse"));
+}
+
+#[tokio::test]
+async fn test_openai_non_retryable_400_is_returned() {
+    let harness = Harness::new(2, vec![Scripted::Error(400, INVALID_ARGUMENT), success(&["unused"])]);
+    let response = harness.chat(false, "e2e openai invalid argument").await;
+
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert_eq!(harness.upstream.calls().len(), 1);
+    assert_eq!(harness.upstream.remaining(), 1);
+}
+
+#[tokio::test]
+async fn test_openai_thinking_rejected_on_last_attempt_returns_that_error() {
+    // 唯一的目标模型名, 避免学习到的 "不支持 thinking" 影响其他测试
+    let harness = Harness::new(1, vec![Scripted::Error(500, INTERNAL_ERROR), Scripted::Error(400, THINKING_UNSUPPORTED)]);
+    harness.state.custom_mapping.write().await.insert(
+        "e2e-chat-thinking-last".to_string(),
+        "gemini-e2e-chat-last-thinking".to_string(),
+    );
+    let response = harness
+        .post(
+            "/v1/chat/completions",
+            json!({
+                "model": "e2e-chat-thinking-last",
+                "messages": [{ "role": "user", "content": "e2e openai thinking on last attempt" }]
+            }),
+        )
+        .await;
+
+    // 最后一次尝试不再以 "All accounts exhausted" 结束, 而是返回上游的 400
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert!(!response.body.contains("All accounts exhausted"), "{}", response.body);
+    let calls = harness.upstream.calls();
+    assert_eq!(calls.len(), 2);
+    assert!(calls[1].body["request"]["generationConfig"].get("thinkingConfig").is_some());
+}
+
+// ===== Claude /v1/messages =====
+
+#[tokio::test]
+async fn test_claude_429_rotates_to_next_account() {
+    let harness = Harness::new(2, vec![Scripted::Error(429, RATE_LIMITED), success(&["Rotated"])]);
+    let response = harness.messages(false, "e2e claude 429 rotation").await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json()["content"][0]["text"], "Rotated");
+    let tokens = harness.tokens();
+    assert_eq!(tokens.len(), 2);
+    assert_ne!(tokens[0], tokens[1], "429 must rotate to another account");
+}
+
+#[tokio::test]
+async fn test_claude_connect_timeout_on_last_attempt_returns_that_error() {
+    let harness = Harness::new(1, vec![Scripted::ConnectTimeout, Scripted::ConnectTimeout]);
+    let response = harness.messages(false, "e2e claude connect timeout").await;
+
+    // 最终错误来自最后一次的连接超时, 而不是默认的 503
+    assert_eq!(response.status, StatusCode::GATEWAY_TIMEOUT);
+    let message = response.json()["error"]["message"].as_str().unwrap_or_default().to_string();
+    assert!(message.contains("504"), "{}", message);
+    assert!(message.contains("TCP connect timed out"), "{}", message);
+    assert_eq!(harness.upstream.calls().len(), 2);
+}
+
+#[tokio::test]
+async fn test_claude_thinking_rejected_retries_without_thinking() {
+    // 唯一的目标模型名, 避免学习到的 "不支持 thinking" 影响其他测试
+    let harness = Harness::new(1, vec![Scripted::Error(400, THINKING_UNSUPPORTED), success(&["No thoughts"])]);
+    harness.state.custom_mapping.write().await.insert(
+        "e2e-thinking-probe".to_string(),
+        "claude-e2e-thinking-probe".to_string(),
+    );
+    let response = harness
+        .post(
+            "/v1/messages",
+            json!({
+                "model": "e2e-thinking-probe",
+                "max_tokens": 4096,
+                "thinking": { "type": "enabled", "budget_tokens": 2048 },
+                "messages": [{ "role": "user", "content": "e2e claude thinking fallback" }]
+            }),
+        )
+        .await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json()["content"][0]["text"], "No thoughts");
+    let calls = harness.upstream.calls();
+    assert_eq!(calls.len(), 2);
+    assert!(calls[0].body["request"]["generationConfig"].get("thinkingConfig").is_some());
+    assert!(calls[1].body["request"]["generationConfig"].get("thinkingConfig").is_none());
+    assert_eq!(calls[0].access_token, calls[1].access_token);
+}
+
+#[tokio::test]
+async fn test_claude_empty_stream_retries_for_non_stream_client() {
+    let harness = Harness::new(2, vec![empty_stream(), success(&["Second try"])]);
+    let response = harness.messages(false, "e2e claude empty stream").await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json()["content"][0]["text"], "Second try");
+    assert_eq!(harness.upstream.calls().len(), 2);
+}
+
+#[tokio::test]
+async fn test_claude_stream_skips_malformed_chunk() {
+    let harness = Harness::new(
+        1,
+        vec![Scripted::Sse(
+            Duration::ZERO,
+            vec![
+                sse_event("Hello ", false),
+                "data: {not json}\n\n".to_string(),
+                sse_event("world", true),
+            ],
+        )],
+    );
+    let response = harness.messages(true, "e2e claude malformed chunk").await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.header("content-type"), Some(SSE_CONTENT_TYPE));
+    assert!(response.body.contains("event: message_start"));
+    assert_eq!(claude_stream_text(&response.body), "Hello world");
+    assert!(response.body.contains("event: message_stop"));
+    assert_eq!(harness.upstream.calls().len(), 1);
+}
+
+// ===== Gemini /v1beta =====
+
+#[tokio::test]
+async fn test_gemini_thinking_rejected_on_last_attempt_returns_that_error() {
+    // 单账号只有一次尝试; 唯一的模型名, 避免学习到的 "不支持 thinking" 影响其他测试
+    let harness = Harness::new(1, vec![Scripted::Error(400, THINKING_UNSUPPORTED), success(&["unused"])]);
+    let response = harness
+        .post(
+            "/v1beta/models/gemini-e2e-native-thinking-last:generateContent",
+            json!({
+                "contents": [{ "role": "user", "parts": [{ "text": "e2e gemini thinking on last attempt" }] }],
+                "generationConfig": { "thinkingConfig": { "thinkingBudget": 1024 } }
+            }),
+        )
+        .await;
+
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert!(!response.body.contains("All accounts exhausted"), "{}", response.body);
+    assert_eq!(harness.upstream.calls().len(), 1);
+    assert_eq!(harness.upstream.remaining(), 1);
+}
+
+// ===== 响应头 =====
+
+/// 协议端点的错误响应必须是该协议格式的 JSON
+fn assert_protocol_error(context: &str, path: &str, response: &HarnessResponse) {
+    assert_eq!(response.header("content-type"), Some("application/json"), "{}", context);
+    let body = response.json();
+    assert!(body["error"]["message"].is_string(), "{}: {}", context, response.body);
+    let protocol = ErrorProtocol::for_path(path).unwrap();
+    assert!(protocol.matches_error(&body), "{} ({:?}): {}", context, protocol, response.body);
+}
+
+#[tokio::test]
+async fn test_every_route_error_is_protocol_json() {
+    let harness = Harness::new(1, Vec::new());
+
+    for endpoint in super::routes::proxy_endpoints() {
+        let path = endpoint.path.replace(":model", "gemini-2.5-flash");
+        for method in endpoint.methods {
+            let response = harness
+                .send(
+                    Request::builder()
+                        .method(*method)
+                        .uri(&path)
+                        .header("content-type", "application/json")
+                        .body(Body::from("{not json"))
+                        .unwrap(),
+                )
+                .await;
+            let context = format!("{} {} -> {}", method, path, response.status);
+            if *method == "POST" {
+                assert!(response.status.is_client_error(), "{}", context);
+            }
+            if response.status.is_client_error() || response.status.is_server_error() {
+                assert_protocol_error(&context, &path, &response);
+            } else {
+                assert_eq!(response.header("content-type"), Some("application/json"), "{}", context);
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_probe_endpoints_answer_head() {
+    let harness = Harness::new(1, Vec::new());
+
+    for path in ["/v1/models", "/v1/models/claude", "/v1beta/models", "/healthz"] {
+        let response = harness.send(Request::head(path).body(Body::empty()).unwrap()).await;
+        assert_eq!(response.status, StatusCode::OK, "HEAD {}", path);
+        assert_eq!(response.header("content-type"), Some("application/json"), "HEAD {}", path);
+        assert!(response.body.is_empty(), "HEAD {}", path);
+
+        let response = harness.send(Request::put(path).body(Body::empty()).unwrap()).await;
+        assert_eq!(response.status, StatusCode::METHOD_NOT_ALLOWED, "PUT {}", path);
+        if ErrorProtocol::for_path(path).is_some() {
+            assert_protocol_error(&format!("PUT {}", path), path, &response);
+        }
+    }
+}
+
+/// 三种协议的非流式请求
+fn protocol_requests() -> [(&'static str, Value); 3] {
+    [
+        (
+            "/v1/chat/completions",
+            json!({ "model": "gemini-2.5-flash", "messages": [{ "role": "user", "content": "e2e error shape" }] }),
+        ),
+        (
+            "/v1/messages",
+            json!({
+                "model": "claude-sonnet-4-5",
+                "max_tokens": 1024,
+                "messages": [{ "role": "user", "content": "e2e error shape" }]
+            }),
+        ),
+        (
+            "/v1beta/models/gemini-2.5-flash:generateContent",
+            json!({ "contents": [{ "role": "user", "parts": [{ "text": "e2e error shape" }] }] }),
+        ),
+    ]
+}
+
+#[tokio::test]
+async fn test_upstream_errors_use_client_protocol_shape() {
+    // 上游返回 Gemini 格式的错误, 客户端看到的必须是各自协议的格式
+    for (path, body) in protocol_requests() {
+        let script = (0..6).map(|_| Scripted::Error(400, INVALID_ARGUMENT)).collect();
+        let harness = Harness::new(1, script);
+        let response = harness.post(path, body).await;
+        let context = format!("upstream error on {} -> {}", path, response.status);
+
+        assert!(response.status.is_client_error() || response.status.is_server_error(), "{}", context);
+        assert_protocol_error(&context, path, &response);
+        assert!(!harness.upstream.calls().is_empty(), "{}", context);
+    }
+}
+
+#[tokio::test]
+async fn test_auth_errors_use_client_protocol_shape() {
+    let harness = Harness::new(1, Vec::new());
+    {
+        let mut security = harness.state.security.write().await;
+        security.auth_mode = crate::proxy::ProxyAuthMode::Strict;
+        security.api_key = "sk-e2e".to_string();
+        security.api_keys = vec![crate::proxy::config::ScopedApiKey {
+            key: "sk-stats".to_string(),
+            label: String::new(),
+            scopes: vec![crate::proxy::config::KeyScope::ReadStats],
+        }];
+    }
+
+    for (path, body) in protocol_requests() {
+        // 未携带 Key: 鉴权中间件返回空体 401
+        let response = harness.post(path, body.clone()).await;
+        let context = format!("missing key on {} -> {}", path, response.status);
+        assert_eq!(response.status, StatusCode::UNAUTHORIZED, "{}", context);
+        assert_protocol_error(&context, path, &response);
+
+        // Key 缺少 inference 权限: 403, 附加的 missing_scope 字段保留
+        let response = harness
+            .send(
+                Request::post(path)
+                    .header("content-type", "application/json")
+                    .header("x-api-key", "sk-stats")
+                    .body(Body::from(body.to_string()))
+                    .unwrap(),
+            )
+            .await;
+        let context = format!("missing scope on {} -> {}", path, response.status);
+        assert_eq!(response.status, StatusCode::FORBIDDEN, "{}", context);
+        assert_protocol_error(&context, path, &response);
+        assert_eq!(response.json()["error"]["missing_scope"], "inference", "{}", context);
+    }
+    assert!(harness.upstream.calls().is_empty());
+}
+
+#[tokio::test]
+async fn test_streaming_routes_declare_utf8_event_stream() {
+    let cases = [
+        (
+            "/v1/chat/completions",
+            json!({
+                "model": "gemini-2.5-flash",
+                "stream": true,
+                "messages": [{ "role": "user", "content": "e2e sse chat" }]
+            }),
+        ),
+        (
+            "/v1/completions",
+            json!({ "model": "gemini-2.5-flash", "stream": true, "prompt": "e2e sse completions" }),
+        ),
+        (
+            "/v1/messages",
+            json!({
+                "model": "claude-sonnet-4-5",
+                "max_tokens": 1024,
+                "stream": true,
+                "messages": [{ "role": "user", "content": "e2e sse messages" }]
+            }),
+        ),
+        (
+            "/v1beta/models/gemini-2.5-flash:streamGenerateContent?alt=sse",
+            json!({ "contents": [{ "role": "user", "parts": [{ "text": "e2e sse gemini" }] }] }),
+        ),
+    ];
+
+    for (path, body) in cases {
+        let harness = Harness::new(1, vec![success(&["你好"])]);
+        let response = harness.post(path, body).await;
+
+        assert_eq!(response.status, StatusCode::OK, "{}", path);
+        assert_eq!(response.header("content-type"), Some(SSE_CONTENT_TYPE), "{}", path);
+        assert!(response.body.contains("你好"), "{}: {}", path, response.body);
+    }
+}
+
+// ===== /v1/audio/speech =====
+
+/// v1internal SSE 事件: 一段 24kHz PCM 音频
+fn speech_event(pcm: &[u8], last: bool) -> String {
+    let mut candidate = json!({
+        "content": { "role": "model", "parts": [{ "inlineData": {
+            "mimeType": "audio/L16;codec=pcm;rate=24000",
+            "data": crate::proxy::audio::AudioProcessor::encode_to_base64(pcm)
+        } }] }
+    });
+    if last {
+        candidate["finishReason"] = json!("STOP");
+    }
+    format!("data: {}\n\n", json!({ "response": { "candidates": [candidate] } }))
+}
+
+#[tokio::test]
+async fn test_speech_streams_wav_from_stream_generate_content() {
+    let harness = Harness::new(
+        1,
+        vec![Scripted::Sse(Duration::ZERO, vec![speech_event(b"abcd", false), speech_event(b"efgh", true)])],
+    );
+    let response = harness
+        .post("/v1/audio/speech", json!({ "model": "tts-1", "input": "e2e speech", "response_format": "wav" }))
+        .await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.header("content-type"), Some("audio/wav"));
+    assert!(response.body.starts_with("RIFF"));
+    assert!(response.body.ends_with("abcdefgh"), "{:?}", response.body);
+    let calls = harness.upstream.calls();
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].method, "streamGenerateContent");
+    assert_eq!(calls[0].query_string.as_deref(), Some("alt=sse"));
+    assert_eq!(calls[0].body["model"], "gemini-2.5-flash-preview-tts");
+
+    // pcm 直接转发原始采样
+    let harness = Harness::new(1, vec![Scripted::Sse(Duration::ZERO, vec![speech_event(b"pcm!", true)])]);
+    let response = harness
+        .post("/v1/audio/speech", json!({ "model": "tts-1", "input": "e2e pcm", "response_format": "pcm" }))
+        .await;
+    assert_eq!(response.header("content-type"), Some("audio/pcm"));
+    assert_eq!(response.body, "pcm!");
+}
+
+#[tokio::test]
+async fn test_speech_compressed_format_requires_zai() {
+    let harness = Harness::new(1, Vec::new());
+    let response = harness
+        .post("/v1/audio/speech", json!({ "model": "tts-1", "input": "e2e mp3", "response_format": "mp3" }))
+        .await;
+
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    let body = response.json();
+    assert_eq!(body["error"]["type"], "invalid_request_error");
+    assert_eq!(body["error"]["code"], "unsupported_response_format");
+    assert!(harness.upstream.calls().is_empty());
+}
+
+#[tokio::test]
+async fn test_speech_compressed_format_goes_to_zai_with_accounts() {
+    let received: Arc<Mutex<Option<Value>>> = Arc::default();
+    let app = axum::Router::new().route(
+        "/api/paas/v4/audio/speech",
+        axum::routing::post({
+            let received = received.clone();
+            move |axum::Json(body): axum::Json<Value>| async move {
+                *received.lock().unwrap() = Some(body);
+                ([("content-type", "audio/mpeg")], "ID3zai-mp3")
+            }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    // Google 账号可用, 但只返回 PCM; mp3 直接交给 z.ai
+    let harness = Harness::new(1, Vec::new());
+    *harness.state.zai.write().await = crate::proxy::ZaiConfig {
+        enabled: true,
+        base_url: format!("http://{}/api/anthropic", addr),
+        api_key: "zai-key".to_string(),
+        dispatch_mode: crate::proxy::ZaiDispatchMode::Fallback,
+        ..Default::default()
+    };
+    let response = harness
+        .post("/v1/audio/speech", json!({ "model": "tts-1", "input": "e2e zai mp3", "response_format": "mp3" }))
+        .await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.header("content-type"), Some("audio/mpeg"));
+    assert_eq!(response.header("X-Provider"), Some("zai"));
+    assert_eq!(response.body, "ID3zai-mp3");
+    assert_eq!(received.lock().unwrap().clone().unwrap()["response_format"], "mp3");
+    assert!(harness.upstream.calls().is_empty());
+}
+
+#[tokio::test]
+async fn test_speech_falls_back_to_zai_without_accounts() {
+    let received: Arc<Mutex<Option<Value>>> = Arc::default();
+    let app = axum::Router::new().route(
+        "/api/paas/v4/audio/speech",
+        axum::routing::post({
+            let received = received.clone();
+            move |axum::Json(body): axum::Json<Value>| async move {
+                *received.lock().unwrap() = Some(body);
+                ([("content-type", "audio/wav")], "RIFFzai-audio")
+            }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let harness = Harness::new(0, Vec::new());
+    *harness.state.zai.write().await = crate::proxy::ZaiConfig {
+        enabled: true,
+        base_url: format!("http://{}/api/anthropic", addr),
+        api_key: "zai-key".to_string(),
+        dispatch_mode: crate::proxy::ZaiDispatchMode::Fallback,
+        ..Default::default()
+    };
+    let response = harness
+        .post("/v1/audio/speech", json!({ "model": "tts-1", "input": "e2e zai", "voice": "alloy" }))
+        .await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.header("content-type"), Some("audio/wav"));
+    assert_eq!(response.header("X-Provider"), Some("zai"));
+    assert_eq!(response.body, "RIFFzai-audio");
+    let body = received.lock().unwrap().clone().unwrap();
+    assert_eq!(body["model"], "glm-tts");
+    assert_eq!(body["response_format"], "wav");
+    assert!(body.get("voice").is_none(), "OpenAI voices are not sent to z.ai");
+    assert!(harness.upstream.calls().is_empty());
+}
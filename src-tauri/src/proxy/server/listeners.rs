@@ -0,0 +1,499 @@
+//! Additional proxy listeners
+//!
+//! Extra ports that expose only a subset of the AI protocols (e.g. 8045 for
+//! Anthropic only, 8046 for OpenAI only). All listeners share the primary
+//! `AppState` (TokenManager, monitor, upstream); each one gets its own copy of
+//! the model mapping and security config so it can overlay mappings and use a
+//! dedicated API key.
+
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{oneshot, Mutex, RwLock};
+
+use crate::proxy::config::{ListenerProtocol, ProxyAuthMode, ProxyConfig, ProxyListenerConfig};
+use crate::proxy::middleware::SecurityState;
+use crate::proxy::server::{routes, AppState};
+use crate::proxy::ProxySecurityConfig;
+
+/// 单个监听端口的状态 (get_proxy_status 返回)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListenerStatus {
+    pub port: u16,
+    pub protocols: Vec<ListenerProtocol>,
+    pub base_url: String,
+    /// 主端口 (同时承载管理接口)
+    #[serde(default)]
+    pub primary: bool,
+    pub running: bool,
+    /// 绑定失败等错误 (按端口单独报告)
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+impl ListenerStatus {
+    pub fn primary(port: u16, running: bool, protocols: Vec<ListenerProtocol>) -> Self {
+        Self {
+            port,
+            protocols,
+            base_url: format!("http://127.0.0.1:{}", port),
+            primary: true,
+            running,
+            error: None,
+        }
+    }
+
+    fn extra(config: &ProxyListenerConfig, error: Option<String>) -> Self {
+        Self {
+            port: config.port,
+            protocols: config.allowed_protocols(),
+            base_url: format!("http://127.0.0.1:{}", config.port),
+            primary: false,
+            running: error.is_none(),
+            error,
+        }
+    }
+}
+
+struct ListenerInstance {
+    config: ProxyListenerConfig,
+    shutdown_tx: oneshot::Sender<()>,
+    custom_mapping: Arc<RwLock<HashMap<String, String>>>,
+    security: Arc<RwLock<ProxySecurityConfig>>,
+}
+
+/// 请求所在端口开放的协议, 由协议过滤中间件写入请求扩展 (公共端点据此选择响应格式)
+#[derive(Debug, Clone)]
+pub struct AllowedProtocols(pub Arc<Vec<ListenerProtocol>>);
+
+impl AllowedProtocols {
+    pub fn contains(&self, protocol: ListenerProtocol) -> bool {
+        self.0.contains(&protocol)
+    }
+}
+
+/// 额外监听端口注册表, 随反代服务统一启停
+pub struct ListenerRegistry {
+    security_monitor: SecurityState,
+    instances: Mutex<Vec<ListenerInstance>>,
+    statuses: Mutex<Vec<ListenerStatus>>,
+    /// 主端口开放的协议 (proxy.primary_protocols), 随反代启动更新
+    primary_protocols: RwLock<Arc<Vec<ListenerProtocol>>>,
+}
+
+impl ListenerRegistry {
+    pub fn new(security_monitor: SecurityState) -> Self {
+        Self {
+            security_monitor,
+            instances: Mutex::new(Vec::new()),
+            statuses: Mutex::new(Vec::new()),
+            primary_protocols: RwLock::new(Arc::new(ListenerProtocol::ALL.to_vec())),
+        }
+    }
+
+    /// 主端口当前开放的协议
+    pub async fn primary_protocols(&self) -> Arc<Vec<ListenerProtocol>> {
+        self.primary_protocols.read().await.clone()
+    }
+
+    /// 按配置启动全部额外监听端口 (先停止已有的), 单个端口失败不影响其它端口
+    pub async fn start_all(&self, base: &AppState, config: &ProxyConfig) -> Vec<ListenerStatus> {
+        self.stop_all().await;
+        *self.primary_protocols.write().await =
+            Arc::new(ListenerProtocol::allowed(&config.primary_protocols));
+
+        let host = config.get_bind_address().to_string();
+        let global_mapping = base.custom_mapping.read().await.clone();
+        let global_security = base.security.read().await.clone();
+
+        let mut instances = Vec::new();
+        let mut statuses = Vec::new();
+        // base.port 为管理服务 (主服务) 实际绑定的端口
+        let mut used_ports = vec![base.port];
+
+        // 管理服务使用独立端口时, 反代主端口作为全协议监听端口启动
+        let mut listener_configs = config.listeners.clone();
+        if config.port != base.port {
+            listener_configs.insert(
+                0,
+                ProxyListenerConfig {
+                    port: config.port,
+                    protocols: config.primary_protocols.clone(),
+                    api_key: None,
+                    custom_mapping: HashMap::new(),
+                },
+            );
+        }
+
+        for listener_config in &listener_configs {
+            if used_ports.contains(&listener_config.port) {
+                let err = format!("Port {} is already used by another listener", listener_config.port);
+                tracing::error!("[Listener] {}", err);
+                statuses.push(ListenerStatus::extra(listener_config, Some(err)));
+                continue;
+            }
+            used_ports.push(listener_config.port);
+
+            let custom_mapping = Arc::new(RwLock::new(overlay_mapping(
+                &global_mapping,
+                &listener_config.custom_mapping,
+            )));
+            let security = Arc::new(RwLock::new(listener_security(
+                &global_security,
+                listener_config,
+            )));
+            let state = AppState {
+                custom_mapping: custom_mapping.clone(),
+                security: security.clone(),
+                port: listener_config.port,
+                ..base.clone()
+            };
+
+            let addr = format!("{}:{}", host, listener_config.port);
+            let tcp = match tokio::net::TcpListener::bind(&addr).await {
+                Ok(tcp) => tcp,
+                Err(e) => {
+                    let err = format!("Failed to bind address {}: {}", addr, e);
+                    tracing::error!("[Listener] {}", err);
+                    statuses.push(ListenerStatus::extra(listener_config, Some(err)));
+                    continue;
+                }
+            };
+
+            let app = build_listener_app(
+                state,
+                self.security_monitor.clone(),
+                listener_config.allowed_protocols(),
+            );
+            let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+            super::spawn_server_loop(tcp, app, shutdown_rx);
+
+            tracing::info!(
+                "[Listener] Started on http://{} (protocols: {:?})",
+                addr,
+                listener_config.allowed_protocols()
+            );
+            statuses.push(ListenerStatus::extra(listener_config, None));
+            instances.push(ListenerInstance {
+                config: listener_config.clone(),
+                shutdown_tx,
+                custom_mapping,
+                security,
+            });
+        }
+
+        *self.instances.lock().await = instances;
+        *self.statuses.lock().await = statuses.clone();
+        statuses
+    }
+
+    /// 停止全部额外监听端口 (与主服务一样优雅排空连接)
+    pub async fn stop_all(&self) {
+        let instances = std::mem::take(&mut *self.instances.lock().await);
+        for instance in instances {
+            let _ = instance.shutdown_tx.send(());
+            tracing::info!("[Listener] Stop signal sent to port {}", instance.config.port);
+        }
+        self.statuses.lock().await.clear();
+    }
+
+    /// 当前额外监听端口状态 (含绑定失败的)
+    pub async fn statuses(&self) -> Vec<ListenerStatus> {
+        self.statuses.lock().await.clone()
+    }
+
+    /// 主端口 + 额外端口的完整列表
+    pub async fn snapshot(&self, primary_port: u16, running: bool) -> Vec<ListenerStatus> {
+        let protocols = self.primary_protocols().await.to_vec();
+        let mut all = vec![ListenerStatus::primary(primary_port, running, protocols)];
+        if running {
+            all.extend(self.statuses().await);
+        }
+        all
+    }
+
+    /// 全局映射热更新后重新叠加各端口的映射
+    pub async fn refresh_mapping(&self, global: &HashMap<String, String>) {
+        for instance in self.instances.lock().await.iter() {
+            *instance.custom_mapping.write().await =
+                overlay_mapping(global, &instance.config.custom_mapping);
+        }
+    }
+
+    /// 全局安全配置热更新后重新应用各端口的 API Key
+    pub async fn refresh_security(&self, global: &ProxySecurityConfig) {
+        for instance in self.instances.lock().await.iter() {
+            *instance.security.write().await = listener_security(global, &instance.config);
+        }
+    }
+}
+
+fn overlay_mapping(
+    global: &HashMap<String, String>,
+    overlay: &HashMap<String, String>,
+) -> HashMap<String, String> {
+    let mut merged = global.clone();
+    merged.extend(overlay.iter().map(|(k, v)| (k.clone(), v.clone())));
+    merged
+}
+
+fn listener_security(
+    global: &ProxySecurityConfig,
+    config: &ProxyListenerConfig,
+) -> ProxySecurityConfig {
+    let mut security = global.clone();
+    security.port = config.port;
+    if let Some(key) = config.api_key.as_ref().filter(|k| !k.is_empty()) {
+        security.api_key = key.clone();
+        // 独立 Key 必须生效, 不随 auto/off 放行
+        if matches!(security.effective_auth_mode(), ProxyAuthMode::Off) {
+            security.auth_mode = ProxyAuthMode::AllExceptHealth;
+        }
+    }
+    security
+}
+
+/// 按路径识别协议面; 无法归类的公共端点 (detect/warmup/telemetry) 返回 None
+pub fn classify_path(path: &str) -> Option<ListenerProtocol> {
+    if path.starts_with("/v1beta/") || path == "/v1beta" {
+        return Some(ListenerProtocol::Gemini);
+    }
+    if path.starts_with("/v1/messages") || path == "/v1/models/claude" || path.starts_with("/mcp/") {
+        return Some(ListenerProtocol::Anthropic);
+    }
+    if path.starts_with("/v1/chat/")
+        || path == "/v1/completions"
+        || path == "/v1/responses"
+        || path == "/v1/embeddings"
+        || path.starts_with("/v1/images/")
+        || path.starts_with("/v1/audio/")
+    {
+        return Some(ListenerProtocol::OpenAI);
+    }
+    None
+}
+
+async fn protocol_filter_middleware(
+    State(allowed): State<Arc<Vec<ListenerProtocol>>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    filter_protocols(allowed, request, next).await
+}
+
+/// 主端口的协议过滤 (开放的协议随反代启动时的配置更新)
+pub(super) async fn primary_protocol_filter_middleware(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let allowed = state.listeners.primary_protocols().await;
+    filter_protocols(allowed, request, next).await
+}
+
+async fn filter_protocols(
+    allowed: Arc<Vec<ListenerProtocol>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    if let Some(protocol) = classify_path(request.uri().path()) {
+        if !allowed.contains(&protocol) {
+            tracing::warn!(
+                "[Listener] Rejected {} request on restricted port: {}",
+                protocol.as_str(),
+                request.uri().path()
+            );
+            return (
+                StatusCode::FORBIDDEN,
+                Json(serde_json::json!({
+                    "error": {
+                        "type": "protocol_not_allowed",
+                        "message": format!(
+                            "The {} protocol is not enabled on this port",
+                            protocol.as_str()
+                        ),
+                    }
+                })),
+            )
+                .into_response();
+        }
+    }
+    request.extensions_mut().insert(AllowedProtocols(allowed));
+    next.run(request).await
+}
+
+fn build_listener_app(
+    state: AppState,
+    security_monitor: SecurityState,
+    protocols: Vec<ListenerProtocol>,
+) -> axum::Router {
+    use crate::proxy::middleware::{
+        account_privacy_middleware, auth_middleware, content_type_middleware, cors_layer,
+        ip_filter_middleware, monitor_middleware, service_status_middleware,
+    };
+
+    routes::build_proxy_routes()
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            auth_middleware,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            monitor_middleware,
+        ))
+        .layer(axum::middleware::from_fn(account_privacy_middleware))
+        .layer(axum::middleware::from_fn_with_state(
+            Arc::new(protocols),
+            protocol_filter_middleware,
+        ))
+        .route("/healthz", axum::routing::get(routes::health_check))
+        .route(
+            crate::proxy::image_store::CONTENT_ROUTE,
+            axum::routing::get(crate::proxy::handlers::openai::handle_image_content),
+        )
+        .layer(axum::middleware::from_fn(ip_filter_middleware))
+        .layer(axum::Extension(security_monitor))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            service_status_middleware,
+        ))
+        .layer(axum::middleware::from_fn(content_type_middleware))
+        .layer(cors_layer())
+        .layer(axum::extract::DefaultBodyLimit::max(super::max_body_size()))
+        .with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use tower::ServiceExt;
+
+    async fn call(protocols: Vec<ListenerProtocol>, method: &str, path: &str) -> (StatusCode, serde_json::Value) {
+        let data_dir = tempfile::tempdir().unwrap();
+        let upstream = Arc::new(crate::proxy::benchmark::MockUpstream::new(&Default::default()));
+        let app = routes::build_proxy_routes()
+            .layer(axum::middleware::from_fn_with_state(
+                Arc::new(protocols),
+                protocol_filter_middleware,
+            ))
+            .with_state(crate::proxy::benchmark::benchmark_state(upstream, 1, data_dir.path()));
+        let response = app
+            .oneshot(
+                axum::http::Request::builder()
+                    .method(method)
+                    .uri(path)
+                    .header("content-type", "application/json")
+                    .body(Body::from("{}"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap_or_default())
+    }
+
+    #[tokio::test]
+    async fn test_model_list_follows_listener_protocol() {
+        let (status, body) = call(ListenerProtocol::ALL.to_vec(), "GET", "/v1/models").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["object"], "list");
+
+        let (status, body) = call(vec![ListenerProtocol::Anthropic], "GET", "/v1/models").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["has_more"], false);
+        assert_eq!(body["data"][0]["type"], "model");
+        assert!(body["data"][0]["created_at"].as_str().unwrap().ends_with('Z'));
+
+        let (status, body) = call(vec![ListenerProtocol::Gemini], "GET", "/v1/models").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body["models"][0]["name"].as_str().unwrap().starts_with("models/"));
+
+        let (status, body) =
+            call(vec![ListenerProtocol::Anthropic], "POST", "/v1/chat/completions").await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["error"]["type"], "protocol_not_allowed");
+    }
+
+
+    #[test]
+    fn test_classify_path() {
+        assert_eq!(classify_path("/v1/messages"), Some(ListenerProtocol::Anthropic));
+        assert_eq!(
+            classify_path("/v1/messages/count_tokens"),
+            Some(ListenerProtocol::Anthropic)
+        );
+        assert_eq!(classify_path("/v1/chat/completions"), Some(ListenerProtocol::OpenAI));
+        assert_eq!(classify_path("/v1/responses"), Some(ListenerProtocol::OpenAI));
+        assert_eq!(classify_path("/v1/embeddings"), Some(ListenerProtocol::OpenAI));
+        // 模型列表为公共端点, 按端口开放的协议返回对应格式
+        assert_eq!(classify_path("/v1/models"), None);
+        assert_eq!(
+            classify_path("/v1beta/models/gemini-2.5-pro:generateContent"),
+            Some(ListenerProtocol::Gemini)
+        );
+        assert_eq!(classify_path("/v1/models/detect"), None);
+        assert_eq!(classify_path("/healthz"), None);
+    }
+
+    #[test]
+    fn test_listener_security_key_forces_auth() {
+        let global = ProxySecurityConfig::from_proxy_config(&ProxyConfig {
+            api_key: "sk-global".to_string(),
+            ..Default::default()
+        });
+        assert!(matches!(global.effective_auth_mode(), ProxyAuthMode::Off));
+
+        let listener = ProxyListenerConfig {
+            port: 8046,
+            protocols: vec![ListenerProtocol::OpenAI],
+            api_key: Some("sk-librechat".to_string()),
+            custom_mapping: HashMap::new(),
+        };
+        let security = listener_security(&global, &listener);
+        assert_eq!(security.api_key, "sk-librechat");
+        assert!(matches!(security.effective_auth_mode(), ProxyAuthMode::AllExceptHealth));
+
+        // 未设置独立 Key 时沿用全局策略
+        let inherit = ProxyListenerConfig { api_key: None, ..listener };
+        let security = listener_security(&global, &inherit);
+        assert_eq!(security.api_key, "sk-global");
+        assert!(matches!(security.effective_auth_mode(), ProxyAuthMode::Off));
+    }
+
+    #[test]
+    fn test_overlay_mapping_overrides_global() {
+        let global = HashMap::from([
+            ("claude-opus-4".to_string(), "gemini-3-pro".to_string()),
+            ("gpt-4o".to_string(), "gemini-2.5-flash".to_string()),
+        ]);
+        let overlay = HashMap::from([("claude-opus-4".to_string(), "gemini-2.5-flash".to_string())]);
+        let merged = overlay_mapping(&global, &overlay);
+        assert_eq!(merged.get("claude-opus-4").unwrap(), "gemini-2.5-flash");
+        assert_eq!(merged.get("gpt-4o").unwrap(), "gemini-2.5-flash");
+    }
+
+    #[test]
+    fn test_allowed_protocols_defaults_to_all() {
+        let listener: ProxyListenerConfig = serde_json::from_value(serde_json::json!({
+            "port": 8046,
+        }))
+        .unwrap();
+        assert_eq!(listener.allowed_protocols(), ListenerProtocol::ALL.to_vec());
+
+        let listener: ProxyListenerConfig = serde_json::from_value(serde_json::json!({
+            "port": 8046,
+            "protocols": ["openai", "openai"],
+        }))
+        .unwrap();
+        assert_eq!(listener.allowed_protocols(), vec![ListenerProtocol::OpenAI]);
+    }
+}
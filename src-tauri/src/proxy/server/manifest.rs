@@ -0,0 +1,283 @@
+//! Capability manifest (`/.well-known/antigravity.json`)
+//!
+//! 供自动配置的工具一次性获取本代理支持的协议、端点与可选功能, 无需鉴权。
+//! 端点列表取自 routes::proxy_endpoints (与实际注册的路由同源), 可选功能与模型别名按当前配置计算。
+
+use axum::{extract::State, Json};
+use serde::Serialize;
+use std::sync::OnceLock;
+
+use crate::proxy::config::ListenerProtocol;
+use crate::proxy::server::listeners::classify_path;
+use crate::proxy::server::routes::{proxy_endpoints, OptionalFeature, ProxyEndpoint};
+use crate::proxy::server::types::AppState;
+
+pub const MANIFEST_PATH: &str = "/.well-known/antigravity.json";
+
+/// 启动时从路由表取一次端点列表
+fn endpoints() -> &'static [ProxyEndpoint] {
+    static ENDPOINTS: OnceLock<Vec<ProxyEndpoint>> = OnceLock::new();
+    ENDPOINTS.get_or_init(proxy_endpoints)
+}
+
+#[derive(Debug, Serialize)]
+pub struct CapabilityManifest {
+    pub name: &'static str,
+    pub version: &'static str,
+    pub max_request_bytes: usize,
+    pub protocols: Vec<ProtocolCapabilities>,
+    /// 不属于单一协议的公共端点 (如 /v1/models/detect)
+    pub common_endpoints: Vec<EndpointCapabilities>,
+    pub features: Vec<FeatureCapability>,
+    /// 可用的模型名 (含自定义映射别名, 不含映射目标)
+    pub models: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProtocolCapabilities {
+    pub protocol: ListenerProtocol,
+    pub base_paths: Vec<String>,
+    pub streaming: bool,
+    pub endpoints: Vec<EndpointCapabilities>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EndpointCapabilities {
+    pub path: &'static str,
+    pub methods: &'static [&'static str],
+    pub streaming: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FeatureCapability {
+    pub feature: OptionalFeature,
+    pub enabled: bool,
+}
+
+impl From<&ProxyEndpoint> for EndpointCapabilities {
+    fn from(endpoint: &ProxyEndpoint) -> Self {
+        Self {
+            path: endpoint.path,
+            methods: endpoint.methods,
+            streaming: endpoint.streaming,
+        }
+    }
+}
+
+/// 端点所在的顶层路径段, 例如 `/v1beta/models/:model` -> `/v1beta`
+fn base_path(path: &str) -> String {
+    let first = path.trim_start_matches('/').split('/').next().unwrap_or_default();
+    format!("/{}", first)
+}
+
+/// 按端点列表与当前配置生成清单; `enabled` 判断端点在当前配置下是否可用
+pub fn build_manifest(
+    endpoints: &[ProxyEndpoint],
+    enabled: impl Fn(&ProxyEndpoint) -> bool,
+    models: Vec<String>,
+) -> CapabilityManifest {
+    let active: Vec<&ProxyEndpoint> = endpoints.iter().filter(|e| enabled(e)).collect();
+
+    let protocols = ListenerProtocol::ALL
+        .iter()
+        .filter_map(|protocol| {
+            let members: Vec<&&ProxyEndpoint> = active
+                .iter()
+                .filter(|e| classify_path(e.path) == Some(*protocol))
+                .collect();
+            if members.is_empty() {
+                return None;
+            }
+            let mut base_paths: Vec<String> = members.iter().map(|e| base_path(e.path)).collect();
+            base_paths.sort();
+            base_paths.dedup();
+            Some(ProtocolCapabilities {
+                protocol: *protocol,
+                base_paths,
+                streaming: members.iter().any(|e| e.streaming),
+                endpoints: members.iter().map(|e| EndpointCapabilities::from(**e)).collect(),
+            })
+        })
+        .collect();
+
+    let common_endpoints = active
+        .iter()
+        .filter(|e| classify_path(e.path).is_none())
+        .map(|e| EndpointCapabilities::from(*e))
+        .collect();
+
+    let features = OptionalFeature::ALL
+        .iter()
+        .map(|feature| FeatureCapability {
+            feature: *feature,
+            enabled: active.iter().any(|e| e.feature == Some(*feature)),
+        })
+        .collect();
+
+    CapabilityManifest {
+        name: "antigravity-manager",
+        version: env!("CARGO_PKG_VERSION"),
+        max_request_bytes: super::max_body_size(),
+        protocols,
+        common_endpoints,
+        features,
+        models,
+    }
+}
+
+/// GET /.well-known/antigravity.json
+pub async fn handle_manifest(State(state): State<AppState>) -> Json<CapabilityManifest> {
+    let zai = state.zai.read().await.clone();
+    let models = crate::proxy::handlers::common::listed_models(&state)
+        .await
+        .into_iter()
+        .map(|model| model.id)
+        .collect();
+
+    Json(build_manifest(
+        endpoints(),
+        |endpoint| match endpoint.feature {
+            Some(OptionalFeature::Mcp) => {
+                crate::proxy::handlers::mcp::mcp_route_enabled(endpoint.path, &zai)
+            }
+            _ => true,
+        },
+        models,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::Request, http::StatusCode, routing::get, Router};
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    fn test_app(data_dir: &tempfile::TempDir) -> Router {
+        let upstream = std::sync::Arc::new(crate::proxy::benchmark::MockUpstream::new(
+            &Default::default(),
+        ));
+        super::super::routes::build_proxy_routes()
+            .route(MANIFEST_PATH, get(handle_manifest))
+            .with_state(crate::proxy::benchmark::benchmark_state(upstream, 1, data_dir.path()))
+    }
+
+    /// 路径参数替换为占位值
+    fn concrete_path(path: &str) -> String {
+        path.split('/')
+            .map(|segment| if segment.starts_with(':') { "placeholder" } else { segment })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    #[test]
+    fn test_base_path_uses_first_segment() {
+        assert_eq!(base_path("/v1beta/models/:model"), "/v1beta");
+        assert_eq!(base_path("/v1/chat/completions"), "/v1");
+        assert_eq!(base_path("/mcp/web_reader/mcp"), "/mcp");
+    }
+
+    #[test]
+    fn test_disabled_endpoints_drop_their_feature() {
+        let manifest = build_manifest(
+            &proxy_endpoints(),
+            |endpoint| endpoint.feature != Some(OptionalFeature::Images),
+            Vec::new(),
+        );
+        let enabled = |feature| {
+            manifest
+                .features
+                .iter()
+                .find(|f| f.feature == feature)
+                .map(|f| f.enabled)
+                .unwrap()
+        };
+        assert!(!enabled(OptionalFeature::Images));
+        assert!(enabled(OptionalFeature::Embeddings));
+        // 没有对应路由的功能不会被声明为可用
+        assert!(!enabled(OptionalFeature::Batches));
+
+        let openai = manifest
+            .protocols
+            .iter()
+            .find(|p| p.protocol == ListenerProtocol::OpenAI)
+            .unwrap();
+        assert!(openai.endpoints.iter().all(|e| !e.path.starts_with("/v1/images/")));
+        assert_eq!(openai.base_paths, vec!["/v1".to_string()]);
+    }
+
+    /// 每个公布的 (方法, 路径) 在空请求下的确切状态码: GET / HEAD 直接返回列表或模型信息,
+    /// JSON 端点因缺少 Content-Type 返回 415, multipart 端点因缺少 boundary 返回 400
+    fn expected_status(method: &str, path: &str) -> Option<StatusCode> {
+        const JSON_POST: StatusCode = StatusCode::UNSUPPORTED_MEDIA_TYPE;
+        const MULTIPART_POST: StatusCode = StatusCode::BAD_REQUEST;
+        let status = match (method, path) {
+            ("GET" | "HEAD", "/v1/models" | "/v1/models/claude" | "/v1beta/models" | "/v1beta/models/:model") => {
+                StatusCode::OK
+            }
+            (
+                "POST",
+                "/v1/chat/completions"
+                | "/v1/completions"
+                | "/v1/responses"
+                | "/v1/embeddings"
+                | "/v1/images/generations"
+                | "/v1/audio/speech"
+                | "/v1/messages"
+                | "/v1/messages/count_tokens"
+                | "/v1beta/models/:model"
+                | "/v1beta/models/:model/countTokens"
+                | "/v1/models/detect",
+            ) => JSON_POST,
+            ("POST", "/v1/images/edits" | "/v1/audio/transcriptions") => MULTIPART_POST,
+            _ => return None,
+        };
+        Some(status)
+    }
+
+    #[tokio::test]
+    async fn test_every_manifest_route_is_served() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let response = test_app(&data_dir)
+            .oneshot(Request::get(MANIFEST_PATH).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let manifest: Value = serde_json::from_slice(&bytes).unwrap();
+
+        let mut endpoints: Vec<Value> = manifest["common_endpoints"].as_array().unwrap().clone();
+        for protocol in manifest["protocols"].as_array().unwrap() {
+            endpoints.extend(protocol["endpoints"].as_array().unwrap().iter().cloned());
+        }
+        assert!(endpoints.len() > 10);
+
+        for endpoint in endpoints {
+            let declared = endpoint["path"].as_str().unwrap();
+            let path = concrete_path(declared);
+            for method in endpoint["methods"].as_array().unwrap() {
+                let method = method.as_str().unwrap();
+                let expected = expected_status(method, declared).unwrap_or_else(|| {
+                    panic!("{} {} has no expected status; add it to expected_status", method, declared)
+                });
+                let response = test_app(&data_dir)
+                    .oneshot(
+                        Request::builder()
+                            .method(method)
+                            .uri(&path)
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+                assert_eq!(
+                    response.status(),
+                    expected,
+                    "{} {} is listed in the manifest but answered with an unexpected status",
+                    method,
+                    path
+                );
+            }
+        }
+    }
+}
@@ -4,22 +4,52 @@
 //! It has been refactored from a monolithic 2000+ line file into organized submodules.
 
 pub mod admin;
+pub mod listeners;
+pub mod manifest;
 pub mod oauth;
 pub mod routes;
 pub mod types;
 
+#[cfg(test)]
+mod e2e_tests;
+
 // Re-export main types for external use
 pub use types::AppState;
 
 use crate::proxy::TokenManager;
 use dashmap::DashSet;
-use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::sync::OnceLock;
 use tokio::sync::oneshot;
 use tokio::sync::RwLock;
 use tracing::{debug, error};
 
+/// 服务进入运行状态的时间 (unix 秒, 0 表示未运行), 供 get_proxy_status 计算 uptime
+static RUNNING_SINCE: AtomicI64 = AtomicI64::new(0);
+
+/// 记录运行状态切换 (重复设置为运行时保留最初的启动时间)
+pub fn mark_running(running: bool) {
+    if running {
+        let _ = RUNNING_SINCE.compare_exchange(
+            0,
+            chrono::Utc::now().timestamp(),
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+        );
+    } else {
+        RUNNING_SINCE.store(0, Ordering::Relaxed);
+    }
+}
+
+/// 当前运行时长 (秒), 未运行时为 0
+pub fn uptime_secs() -> u64 {
+    match RUNNING_SINCE.load(Ordering::Relaxed) {
+        0 => 0,
+        since => (chrono::Utc::now().timestamp() - since).max(0) as u64,
+    }
+}
+
 // =============================================================================
 // [FIX] Global queue for pending account reloads
 // When update_account_quota updates protected_models, account ID is added here
@@ -62,6 +92,8 @@ pub fn take_pending_reload_accounts() -> Vec<String> {
 #[derive(Clone)]
 pub struct AxumServer {
     shutdown_tx: Arc<tokio::sync::Mutex<Option<oneshot::Sender<()>>>>,
+    /// 预热池后台任务, 停止服务时中止
+    warm_pool_task: Arc<tokio::sync::Mutex<Option<tokio::task::JoinHandle<()>>>>,
     custom_mapping: Arc<tokio::sync::RwLock<std::collections::HashMap<String, String>>>,
     proxy_state: Arc<tokio::sync::RwLock<crate::proxy::config::UpstreamProxyConfig>>,
     security_state: Arc<RwLock<crate::proxy::ProxySecurityConfig>>,
@@ -74,6 +106,8 @@ pub struct AxumServer {
     pub upstream: Arc<crate::proxy::upstream::client::UpstreamClient>,
     /// [FIX] Exposed TokenManager for proxy service reuse
     pub token_manager: Arc<TokenManager>,
+    /// Shared state used to spawn additional listeners
+    app_state: AppState,
 }
 
 impl AxumServer {
@@ -83,9 +117,30 @@ impl AxumServer {
             let mut m = self.custom_mapping.write().await;
             *m = config.custom_mapping.clone();
         }
+        self.app_state.listeners.refresh_mapping(&config.custom_mapping).await;
         tracing::debug!("Model mapping (Custom) hot-reloaded");
     }
 
+    /// Replace the model mapping in one swap and persist it; the swap is rolled back if `persist` fails
+    pub async fn replace_mapping<F>(
+        &self,
+        mapping: std::collections::HashMap<String, String>,
+        persist: F,
+    ) -> Result<(), String>
+    where
+        F: FnOnce(&std::collections::HashMap<String, String>) -> Result<(), String>,
+    {
+        let _guard = crate::proxy::common::model_mapping::swap_custom_mapping(
+            &self.custom_mapping,
+            mapping.clone(),
+            persist,
+        )
+        .await?;
+        self.app_state.listeners.refresh_mapping(&mapping).await;
+        tracing::debug!("Model mapping (Custom) hot-reloaded");
+        Ok(())
+    }
+
     /// Update upstream proxy configuration
     pub async fn update_proxy(&self, new_config: crate::proxy::config::UpstreamProxyConfig) {
         let mut proxy = self.proxy_state.write().await;
@@ -97,10 +152,19 @@ impl AxumServer {
         tracing::info!("Upstream proxy config hot-reloaded (including HTTP Client)");
     }
 
+    /// Update upstream timeout and connection pool configuration (hot-reload)
+    pub async fn update_upstream_timeouts(&self, config: &crate::proxy::config::ProxyConfig) {
+        self.upstream.set_timeouts(config.upstream_timeouts.clone()).await;
+        self.upstream.set_pool_config(config.upstream_pool.clone()).await;
+    }
+
     /// Update security configuration
     pub async fn update_security(&self, config: &crate::proxy::config::ProxyConfig) {
-        let mut sec = self.security_state.write().await;
-        *sec = crate::proxy::ProxySecurityConfig::from_proxy_config(config);
+        let security = crate::proxy::ProxySecurityConfig::from_proxy_config(config);
+        crate::proxy::common::redact::set_allow_emails(config.expose_account_emails_in_errors);
+        crate::proxy::common::redact::set_account_header_privacy(config.account_header_privacy);
+        self.app_state.listeners.refresh_security(&security).await;
+        *self.security_state.write().await = security;
         tracing::info!("Proxy security config hot-reloaded");
     }
 
@@ -111,6 +175,12 @@ impl AxumServer {
         tracing::info!("z.ai config hot-reloaded");
     }
 
+    /// Update extra upstream providers
+    pub async fn update_providers(&self, config: &crate::proxy::config::ProxyConfig) {
+        *self.app_state.providers.write().await = config.providers.clone();
+        tracing::info!("Providers hot-reloaded ({} configured)", config.providers.len());
+    }
+
     /// Update experimental configuration
     pub async fn update_experimental(&self, config: &crate::proxy::config::ProxyConfig) {
         let mut exp = self.experimental.write().await;
@@ -144,9 +214,36 @@ impl AxumServer {
     pub async fn set_running(&self, running: bool) {
         let mut r = self.is_running.write().await;
         *r = running;
+        mark_running(running);
         tracing::info!("Proxy service running state updated to: {}", running);
     }
 
+    /// Start all additional listeners from config (bind errors reported per port)
+    pub async fn start_listeners(
+        &self,
+        config: &crate::proxy::config::ProxyConfig,
+    ) -> Vec<listeners::ListenerStatus> {
+        self.app_state.listeners.start_all(&self.app_state, config).await
+    }
+
+    /// Stop all additional listeners
+    pub async fn stop_listeners(&self) {
+        self.app_state.listeners.stop_all().await;
+    }
+
+    /// 按分区收集统计 (get_proxy_stats)
+    pub async fn stats_report(
+        &self,
+        sections: &[crate::proxy::monitor::StatsSection],
+    ) -> crate::proxy::monitor::ProxyStatsReport {
+        crate::proxy::monitor::ProxyStatsReport::collect(
+            Some(&self.app_state.monitor),
+            Some(&self.app_state),
+            sections,
+        )
+        .await
+    }
+
     /// Start the Axum server
     pub async fn start(
         host: String,
@@ -155,6 +252,8 @@ impl AxumServer {
         custom_mapping: std::collections::HashMap<String, String>,
         _request_timeout: u64,
         upstream_proxy: crate::proxy::config::UpstreamProxyConfig,
+        upstream_timeouts: crate::proxy::config::UpstreamTimeoutConfig,
+        upstream_pool: crate::proxy::config::UpstreamPoolConfig,
         user_agent_override: Option<String>,
         security_config: crate::proxy::ProxySecurityConfig,
         zai_config: crate::proxy::ZaiConfig,
@@ -176,15 +275,22 @@ impl AxumServer {
         let is_running_state = Arc::new(RwLock::new(true));
 
         // Create upstream client once and share between AppState and AxumServer
-        let upstream_client = Arc::new(crate::proxy::upstream::client::UpstreamClient::new(Some(
-            upstream_proxy.clone(),
-        )));
+        let upstream_client = Arc::new(crate::proxy::upstream::client::UpstreamClient::with_settings(
+            Some(upstream_proxy.clone()),
+            upstream_timeouts,
+            upstream_pool,
+        ));
 
         // Initialize User-Agent override if configured
         if user_agent_override.is_some() {
             upstream_client.set_user_agent_override(user_agent_override).await;
         }
 
+        // Create security monitor state for IP filtering
+        let security_monitor_state: crate::proxy::middleware::SecurityState = Arc::new(
+            RwLock::new(crate::proxy::config::SecurityMonitorConfig::default()),
+        );
+
         let state = AppState {
             token_manager: token_manager.clone(),
             custom_mapping: custom_mapping_state.clone(),
@@ -195,6 +301,7 @@ impl AxumServer {
             upstream_proxy: proxy_state.clone(),
             upstream: upstream_client.clone(),
             zai: zai_state.clone(),
+            providers: Arc::new(RwLock::new(Vec::new())),
             provider_rr: provider_rr.clone(),
             zai_vision_mcp: zai_vision_mcp_state,
             monitor: monitor.clone(),
@@ -209,24 +316,28 @@ impl AxumServer {
             cloudflared_state: cloudflared_state.clone(),
             is_running: is_running_state.clone(),
             port,
+            listeners: Arc::new(listeners::ListenerRegistry::new(
+                security_monitor_state.clone(),
+            )),
         };
 
         // Build routes
         use crate::proxy::middleware::{
-            admin_auth_middleware, auth_middleware, cors_layer, ip_filter_middleware,
-            monitor_middleware, service_status_middleware, SecurityState,
+            account_privacy_middleware, admin_auth_middleware, auth_middleware,
+            content_type_middleware, cors_layer, ip_filter_middleware, monitor_middleware,
+            service_status_middleware,
         };
 
-        // Create security monitor state for IP filtering
-        let security_monitor_state: SecurityState = Arc::new(RwLock::new(
-            crate::proxy::config::SecurityMonitorConfig::default(),
-        ));
-
         // Initialize security database
         if let Err(e) = crate::modules::security_db::init_db() {
             tracing::warn!("[Security] Failed to initialize security database: {}", e);
         }
 
+        // Model warm-pool (opt-in, checks config on every tick)
+        let warm_pool_task = crate::proxy::warm_pool::spawn(state.clone());
+        // Expired generated images (response_format=url)
+        crate::proxy::image_store::spawn_sweeper();
+
         // 1. Build proxy routes (AI endpoints with auth)
         let proxy_routes = routes::build_proxy_routes()
             .layer(axum::middleware::from_fn_with_state(
@@ -236,6 +347,13 @@ impl AxumServer {
             .layer(axum::middleware::from_fn_with_state(
                 state.clone(),
                 monitor_middleware,
+            ))
+            // 位于 monitor 外层: 日志记录真实邮箱, 客户端按隐私配置
+            .layer(axum::middleware::from_fn(account_privacy_middleware))
+            // 主端口同样可限制协议 (proxy.primary_protocols)
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                listeners::primary_protocol_filter_middleware,
             ));
 
         // 2. Build admin routes (forced auth)
@@ -244,10 +362,7 @@ impl AxumServer {
         );
 
         // 3. Combine and apply global layers
-        let max_body_size: usize = std::env::var("ABV_MAX_BODY_SIZE")
-            .ok()
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(100 * 1024 * 1024); // Default 100MB
+        let max_body_size = max_body_size();
         tracing::info!("Request body size limit: {} MB", max_body_size / 1024 / 1024);
 
         let app = axum::Router::new()
@@ -257,6 +372,13 @@ impl AxumServer {
             .route("/auth/callback", axum::routing::get(oauth::handle_oauth_callback))
             // Health check endpoint (no IP filter)
             .route("/healthz", axum::routing::get(routes::health_check))
+            // Capability manifest (no auth)
+            .route(manifest::MANIFEST_PATH, axum::routing::get(manifest::handle_manifest))
+            // Generated images for response_format=url (no auth, unguessable id)
+            .route(
+                crate::proxy::image_store::CONTENT_ROUTE,
+                axum::routing::get(crate::proxy::handlers::openai::handle_image_content),
+            )
             // Apply global monitoring and status layers
             .layer(axum::middleware::from_fn(ip_filter_middleware))
             .layer(axum::Extension(security_monitor_state.clone()))
@@ -264,6 +386,8 @@ impl AxumServer {
                 state.clone(),
                 service_status_middleware,
             ))
+            // 协议错误统一为 JSON、SSE 补全 charset (覆盖鉴权 / IP 过滤等中间件的响应)
+            .layer(axum::middleware::from_fn(content_type_middleware))
             .layer(cors_layer())
             .layer(axum::extract::DefaultBodyLimit::max(max_body_size))
             .with_state(state.clone());
@@ -283,17 +407,22 @@ impl AxumServer {
 
         // Bind address
         let addr = format!("{}:{}", host, port);
-        let listener = tokio::net::TcpListener::bind(&addr)
-            .await
-            .map_err(|e| format!("Failed to bind address {}: {}", addr, e))?;
+        let listener = match tokio::net::TcpListener::bind(&addr).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                warm_pool_task.abort();
+                return Err(format!("Failed to bind address {}: {}", addr, e));
+            }
+        };
 
         tracing::info!("Proxy server started on http://{}", addr);
 
         // Create shutdown channel
-        let (shutdown_tx, mut shutdown_rx) = oneshot::channel::<()>();
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
 
         let server_instance = Self {
             shutdown_tx: Arc::new(tokio::sync::Mutex::new(Some(shutdown_tx))),
+            warm_pool_task: Arc::new(tokio::sync::Mutex::new(Some(warm_pool_task))),
             custom_mapping: custom_mapping_state.clone(),
             proxy_state,
             security_state,
@@ -305,120 +434,10 @@ impl AxumServer {
             is_running: is_running_state,
             upstream: upstream_client,
             token_manager: token_manager.clone(),
+            app_state: state,
         };
 
-        // [PERF] Connection limiter to prevent resource exhaustion under high load
-        // Default: 10K concurrent connections (configurable via ABV_MAX_CONNECTIONS env)
-        let max_connections: usize = std::env::var("ABV_MAX_CONNECTIONS")
-            .ok()
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(10_000);
-        let connection_semaphore = Arc::new(tokio::sync::Semaphore::new(max_connections));
-        tracing::info!(
-            "Connection limiter initialized: max {} concurrent connections",
-            max_connections
-        );
-
-        // Start server in a new task
-        let handle = tokio::spawn(async move {
-            use hyper_util::rt::{TokioExecutor, TokioIo};
-            use hyper_util::service::TowerToHyperService;
-
-            // [PERF] Track active connections for monitoring
-            let active_connections = Arc::new(AtomicUsize::new(0));
-
-            loop {
-                tokio::select! {
-                    res = listener.accept() => {
-                        match res {
-                            Ok((stream, remote_addr)) => {
-                                // [PERF] Acquire semaphore permit before spawning
-                                let permit = match connection_semaphore.clone().try_acquire_owned() {
-                                    Ok(p) => p,
-                                    Err(_) => {
-                                        // Connection limit reached - reject gracefully
-                                        tracing::warn!(
-                                            "Connection limit reached ({} active), rejecting new connection from {}",
-                                            active_connections.load(std::sync::atomic::Ordering::Relaxed),
-                                            remote_addr
-                                        );
-                                        // Drop stream immediately to reject connection
-                                        drop(stream);
-                                        continue;
-                                    }
-                                };
-
-                                let io = TokioIo::new(stream);
-                                let active_count = active_connections.clone();
-                                active_count.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-
-                                // [FIX] Inject ConnectInfo for real IP extraction
-                                use tower::util::ServiceExt;
-                                use hyper::body::Incoming;
-                                let app_with_info = app.clone().map_request(move |mut req: axum::http::Request<Incoming>| {
-                                    req.extensions_mut().insert(axum::extract::ConnectInfo(remote_addr));
-                                    req
-                                });
-
-                                let service = TowerToHyperService::new(app_with_info);
-
-                                tokio::task::spawn(async move {
-                                    // [PERF] Try HTTP/2 first via auto-detection, fallback to HTTP/1.1
-                                    // Using hyper's auto HTTP version detection
-                                    let result = hyper_util::server::conn::auto::Builder::new(TokioExecutor::new())
-                                        .http1()
-                                        .keep_alive(true)
-                                        .http2()
-                                        .max_concurrent_streams(250)
-                                        .serve_connection_with_upgrades(io, service)
-                                        .await;
-
-                                    if let Err(err) = result {
-                                        debug!("Connection handler finished or errored: {:?}", err);
-                                    }
-
-                                    // [PERF] Release connection count and permit
-                                    active_count.fetch_sub(1, std::sync::atomic::Ordering::Relaxed);
-                                    drop(permit);
-                                });
-                            }
-                            Err(e) => {
-                                error!("Failed to accept connection: {:?}", e);
-                            }
-                        }
-                    }
-                    _ = &mut shutdown_rx => {
-                        tracing::info!("Proxy server stopped listening, waiting for active connections to drain...");
-                        
-                        // [PERF] Graceful shutdown: wait for active connections to complete
-                        // Maximum wait time: 30 seconds
-                        let drain_start = std::time::Instant::now();
-                        let max_drain_time = std::time::Duration::from_secs(30);
-                        
-                        loop {
-                            let active = active_connections.load(std::sync::atomic::Ordering::Relaxed);
-                            if active == 0 {
-                                tracing::info!("All connections drained successfully");
-                                break;
-                            }
-                            
-                            if drain_start.elapsed() > max_drain_time {
-                                tracing::warn!(
-                                    "Graceful shutdown timeout reached with {} active connections, forcing shutdown",
-                                    active
-                                );
-                                break;
-                            }
-                            
-                            tracing::debug!("Waiting for {} active connections to drain...", active);
-                            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-                        }
-                        
-                        break;
-                    }
-                }
-            }
-        });
+        let handle = spawn_server_loop(listener, app, shutdown_rx);
 
         Ok((server_instance, handle))
     }
@@ -426,7 +445,12 @@ impl AxumServer {
     /// Stop the server with graceful connection draining
     pub fn stop(&self) {
         let tx_mutex = self.shutdown_tx.clone();
+        let warm_pool_task = self.warm_pool_task.clone();
         tokio::spawn(async move {
+            if let Some(task) = warm_pool_task.lock().await.take() {
+                task.abort();
+                tracing::debug!("Warm-pool task aborted");
+            }
             let mut lock = tx_mutex.lock().await;
             if let Some(tx) = lock.take() {
                 let _ = tx.send(());
@@ -435,3 +459,139 @@ impl AxumServer {
         });
     }
 }
+
+/// Request body size limit shared by all listeners
+pub(crate) fn max_body_size() -> usize {
+    std::env::var("ABV_MAX_BODY_SIZE")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(100 * 1024 * 1024) // Default 100MB
+}
+
+/// 接受本次连接的本地地址 (请求扩展); 主端口与额外监听端口各自不同, 用于生成指向该监听端口的链接
+#[derive(Debug, Clone, Copy)]
+pub struct LocalAddr(pub std::net::SocketAddr);
+
+/// Accept loop with connection limiting and graceful draining on shutdown
+pub(crate) fn spawn_server_loop(
+    listener: tokio::net::TcpListener,
+    app: axum::Router,
+    mut shutdown_rx: oneshot::Receiver<()>,
+) -> tokio::task::JoinHandle<()> {
+    // [PERF] Connection limiter to prevent resource exhaustion under high load
+    // Default: 10K concurrent connections (configurable via ABV_MAX_CONNECTIONS env)
+    let max_connections: usize = std::env::var("ABV_MAX_CONNECTIONS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(10_000);
+    let connection_semaphore = Arc::new(tokio::sync::Semaphore::new(max_connections));
+    tracing::info!(
+        "Connection limiter initialized: max {} concurrent connections",
+        max_connections
+    );
+
+    // Start server in a new task
+    tokio::spawn(async move {
+        use hyper_util::rt::{TokioExecutor, TokioIo};
+        use hyper_util::service::TowerToHyperService;
+
+        // [PERF] Track active connections for monitoring
+        let active_connections = Arc::new(AtomicUsize::new(0));
+
+        loop {
+            tokio::select! {
+                res = listener.accept() => {
+                    match res {
+                        Ok((stream, remote_addr)) => {
+                            // [PERF] Acquire semaphore permit before spawning
+                            let permit = match connection_semaphore.clone().try_acquire_owned() {
+                                Ok(p) => p,
+                                Err(_) => {
+                                    // Connection limit reached - reject gracefully
+                                    tracing::warn!(
+                                        "Connection limit reached ({} active), rejecting new connection from {}",
+                                        active_connections.load(std::sync::atomic::Ordering::Relaxed),
+                                        remote_addr
+                                    );
+                                    // Drop stream immediately to reject connection
+                                    drop(stream);
+                                    continue;
+                                }
+                            };
+
+                            let local_addr = stream.local_addr().ok();
+                            let io = TokioIo::new(stream);
+                            let active_count = active_connections.clone();
+                            active_count.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+                            // [FIX] Inject ConnectInfo for real IP extraction
+                            use tower::util::ServiceExt;
+                            use hyper::body::Incoming;
+                            let app_with_info = app.clone().map_request(move |mut req: axum::http::Request<Incoming>| {
+                                req.extensions_mut().insert(axum::extract::ConnectInfo(remote_addr));
+                                if let Some(local_addr) = local_addr {
+                                    req.extensions_mut().insert(LocalAddr(local_addr));
+                                }
+                                req
+                            });
+
+                            let service = TowerToHyperService::new(app_with_info);
+
+                            tokio::task::spawn(async move {
+                                // [PERF] Try HTTP/2 first via auto-detection, fallback to HTTP/1.1
+                                // Using hyper's auto HTTP version detection
+                                let result = hyper_util::server::conn::auto::Builder::new(TokioExecutor::new())
+                                    .http1()
+                                    .keep_alive(true)
+                                    .http2()
+                                    .max_concurrent_streams(250)
+                                    .serve_connection_with_upgrades(io, service)
+                                    .await;
+
+                                if let Err(err) = result {
+                                    debug!("Connection handler finished or errored: {:?}", err);
+                                }
+
+                                // [PERF] Release connection count and permit
+                                active_count.fetch_sub(1, std::sync::atomic::Ordering::Relaxed);
+                                drop(permit);
+                            });
+                        }
+                        Err(e) => {
+                            error!("Failed to accept connection: {:?}", e);
+                        }
+                    }
+                }
+                _ = &mut shutdown_rx => {
+                    tracing::info!("Proxy server stopped listening, waiting for active connections to drain...");
+                    
+                    // [PERF] Graceful shutdown: wait for active connections to complete
+                    // Maximum wait time: 30 seconds
+                    let drain_start = std::time::Instant::now();
+                    let max_drain_time = std::time::Duration::from_secs(30);
+                    
+                    loop {
+                        let active = active_connections.load(std::sync::atomic::Ordering::Relaxed);
+                        if active == 0 {
+                            tracing::info!("All connections drained successfully");
+                            break;
+                        }
+                        
+                        if drain_start.elapsed() > max_drain_time {
+                            tracing::warn!(
+                                "Graceful shutdown timeout reached with {} active connections, forcing shutdown",
+                                active
+                            );
+                            break;
+                        }
+                        
+                        tracing::debug!("Waiting for {} active connections to drain...", active);
+                        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+                    }
+                    
+                    break;
+                }
+            }
+        }
+    })
+}
@@ -3,7 +3,7 @@
 //! This module defines all API routes and builds the router.
 
 use axum::{
-    routing::{any, delete, get, post},
+    routing::{any, delete, get, post, MethodRouter},
     Router,
 };
 
@@ -43,6 +43,7 @@ pub fn build_admin_routes() -> Router<AppState> {
         .route("/accounts/import/v1", post(admin::import_v1_accounts))
         .route("/accounts/import/db", post(admin::import_from_db))
         .route("/accounts/import/db-custom", post(admin::import_custom_db))
+        .route("/accounts/import/antigravity", post(admin::import_from_antigravity))
         .route("/accounts/sync/db", post(admin::sync_account_from_db))
         // Statistics (legacy paths)
         .route("/stats/summary", get(admin::get_token_stats_summary))
@@ -71,13 +72,31 @@ pub fn build_admin_routes() -> Router<AppState> {
         )
         // Proxy control
         .route("/proxy/status", get(admin::get_proxy_status))
+        .route("/proxy/endpoint-url", get(admin::get_endpoint_url))
         .route("/proxy/start", post(admin::start_proxy_service))
         .route("/proxy/stop", post(admin::stop_proxy_service))
+        .route("/proxy/maintenance", post(admin::set_maintenance_mode))
         .route("/proxy/mapping", post(admin::update_model_mapping))
+        .route(
+            "/proxy/scheduling",
+            get(admin::get_proxy_scheduling_config).post(admin::update_proxy_scheduling_config),
+        )
+        .route(
+            "/proxy/retry",
+            get(admin::get_proxy_retry_config).post(admin::update_proxy_retry_config),
+        )
+        .route(
+            "/proxy/background-routing",
+            get(admin::get_background_routing).post(admin::update_background_routing),
+        )
         .route("/proxy/api-key/generate", post(admin::generate_api_key))
         .route("/proxy/session-bindings/clear", post(admin::clear_proxy_session_bindings))
         .route("/proxy/rate-limits", delete(admin::clear_all_rate_limits))
         .route("/proxy/rate-limits/:accountId", delete(admin::clear_rate_limit))
+        .route(
+            "/proxy/project-setup/:accountId/retry",
+            post(admin::retry_project_setup),
+        )
         // [FIX #820] Preferred account
         .route(
             "/proxy/preferred-account",
@@ -101,6 +120,8 @@ pub fn build_admin_routes() -> Router<AppState> {
         // System
         .route("/system/open-folder", post(admin::open_folder))
         .route("/proxy/stats", get(admin::get_proxy_stats))
+        .route("/proxy/calibration", get(admin::get_calibration_state))
+        .route("/proxy/model-health", get(admin::get_model_health))
         // Logs
         .route("/logs", get(admin::get_proxy_logs_filtered))
         .route("/logs/count", get(admin::get_proxy_logs_count_filtered))
@@ -132,8 +153,13 @@ pub fn build_admin_routes() -> Router<AppState> {
         )
         // Account bulk operations
         .route("/accounts/bulk-delete", post(admin::delete_accounts))
+        .route("/accounts/bulk-pause", post(admin::pause_accounts))
+        .route("/accounts/bulk-priority", post(admin::set_accounts_priority))
+        .route("/accounts/bulk-tags", post(admin::set_accounts_tags))
         .route("/accounts/export", post(admin::export_accounts))
         .route("/accounts/reorder", post(admin::reorder_accounts))
+        .route("/accounts/health", get(admin::get_account_health))
+        .route("/accounts/:accountId/notes", post(admin::set_account_notes))
         .route("/accounts/:accountId/quota", get(admin::fetch_account_quota))
         .route("/accounts/:accountId/toggle-proxy", post(admin::toggle_proxy_status))
         // Warmup
@@ -159,73 +185,194 @@ pub fn build_admin_routes() -> Router<AppState> {
         .route("/auth/url", get(oauth::prepare_oauth_url_web))
 }
 
-/// Build the proxy API routes (AI endpoints)
-pub fn build_proxy_routes() -> Router<AppState> {
+/// 代理路由表中的一条对外端点; 能力清单 (/.well-known/antigravity.json) 由此生成,
+/// 与实际注册的路由同源, 不会与路由表脱节
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProxyEndpoint {
+    pub path: &'static str,
+    pub methods: &'static [&'static str],
+    /// 是否支持流式响应
+    pub streaming: bool,
+    /// 所属可选功能 (未标注的为协议核心端点)
+    pub feature: Option<OptionalFeature>,
+}
+
+/// 能力清单中的可选功能; 路由表中没有对应端点的功能视为未启用
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OptionalFeature {
+    Images,
+    Audio,
+    Embeddings,
+    Batches,
+    /// z.ai MCP 反代 (还需在配置中启用)
+    Mcp,
+}
+
+impl OptionalFeature {
+    pub const ALL: [OptionalFeature; 5] = [
+        OptionalFeature::Images,
+        OptionalFeature::Audio,
+        OptionalFeature::Embeddings,
+        OptionalFeature::Batches,
+        OptionalFeature::Mcp,
+    ];
+}
+
+/// 注册路由的同时记录对外端点
+struct ProxyRouteTable {
+    router: Router<AppState>,
+    endpoints: Vec<ProxyEndpoint>,
+}
+
+impl ProxyRouteTable {
+    fn new() -> Self {
+        Self {
+            router: Router::new(),
+            endpoints: Vec::new(),
+        }
+    }
+
+    fn endpoint(
+        mut self,
+        endpoint: ProxyEndpoint,
+        handler: MethodRouter<AppState>,
+    ) -> Self {
+        self.router = self.router.route(endpoint.path, handler);
+        self.endpoints.push(endpoint);
+        self
+    }
+
+    /// 不对外公布的路由 (遥测拦截等)
+    fn hidden(mut self, path: &str, handler: MethodRouter<AppState>) -> Self {
+        self.router = self.router.route(path, handler);
+        self
+    }
+}
+
+const fn endpoint(
+    path: &'static str,
+    methods: &'static [&'static str],
+    streaming: bool,
+    feature: Option<OptionalFeature>,
+) -> ProxyEndpoint {
+    ProxyEndpoint {
+        path,
+        methods,
+        streaming,
+        feature,
+    }
+}
+
+// axum 的 get 路由同时响应 HEAD (去掉响应体), 供客户端探测端点
+const GET: &[&str] = &["GET", "HEAD"];
+const POST: &[&str] = &["POST"];
+const GET_POST: &[&str] = &["GET", "HEAD", "POST"];
+const ANY: &[&str] = &["GET", "POST", "DELETE"];
+
+fn proxy_route_table() -> ProxyRouteTable {
     use crate::proxy::handlers;
+    use OptionalFeature::{Audio, Embeddings, Images, Mcp};
 
-    Router::new()
+    ProxyRouteTable::new()
         // OpenAI Protocol
-        .route("/v1/models", get(handlers::openai::handle_list_models))
-        .route(
-            "/v1/chat/completions",
+        .endpoint(
+            endpoint("/v1/models", GET, false, None),
+            get(handlers::openai::handle_list_models),
+        )
+        .endpoint(
+            endpoint("/v1/chat/completions", POST, true, None),
             post(handlers::openai::handle_chat_completions),
         )
-        .route(
-            "/v1/completions",
+        .endpoint(
+            endpoint("/v1/completions", POST, true, None),
             post(handlers::openai::handle_completions),
         )
-        .route("/v1/responses", post(handlers::openai::handle_completions)) // Codex CLI compat
-        .route(
-            "/v1/images/generations",
+        // Codex CLI compat
+        .endpoint(
+            endpoint("/v1/responses", POST, true, None),
+            post(handlers::openai::handle_completions),
+        )
+        .endpoint(
+            endpoint("/v1/embeddings", POST, false, Some(Embeddings)),
+            post(handlers::openai::handle_embeddings),
+        )
+        .endpoint(
+            endpoint("/v1/images/generations", POST, false, Some(Images)),
             post(handlers::openai::handle_images_generations),
         )
-        .route(
-            "/v1/images/edits",
+        .endpoint(
+            endpoint("/v1/images/edits", POST, false, Some(Images)),
             post(handlers::openai::handle_images_edits),
         )
-        .route(
-            "/v1/audio/transcriptions",
+        .endpoint(
+            endpoint("/v1/audio/transcriptions", POST, false, Some(Audio)),
             post(handlers::audio::handle_audio_transcription),
         )
+        .endpoint(
+            endpoint("/v1/audio/speech", POST, false, Some(Audio)),
+            post(handlers::audio::handle_audio_speech),
+        )
         // Claude Protocol
-        .route("/v1/messages", post(handlers::claude::handle_messages))
-        .route(
-            "/v1/messages/count_tokens",
+        .endpoint(
+            endpoint("/v1/messages", POST, true, None),
+            post(handlers::claude::handle_messages),
+        )
+        .endpoint(
+            endpoint("/v1/messages/count_tokens", POST, false, None),
             post(handlers::claude::handle_count_tokens),
         )
-        .route(
-            "/v1/models/claude",
+        .endpoint(
+            endpoint("/v1/models/claude", GET, false, None),
             get(handlers::claude::handle_list_models),
         )
         // z.ai MCP (optional reverse-proxy)
-        .route(
-            "/mcp/web_search_prime/mcp",
+        .endpoint(
+            endpoint("/mcp/web_search_prime/mcp", ANY, true, Some(Mcp)),
             any(handlers::mcp::handle_web_search_prime),
         )
-        .route(
-            "/mcp/web_reader/mcp",
+        .endpoint(
+            endpoint("/mcp/web_reader/mcp", ANY, true, Some(Mcp)),
             any(handlers::mcp::handle_web_reader),
         )
-        .route(
-            "/mcp/zai-mcp-server/mcp",
+        .endpoint(
+            endpoint("/mcp/zai-mcp-server/mcp", ANY, true, Some(Mcp)),
             any(handlers::mcp::handle_zai_mcp_server),
         )
         // Gemini Protocol (Native)
-        .route("/v1beta/models", get(handlers::gemini::handle_list_models))
-        .route(
-            "/v1beta/models/:model",
+        .endpoint(
+            endpoint("/v1beta/models", GET, false, None),
+            get(handlers::gemini::handle_list_models),
+        )
+        .endpoint(
+            endpoint("/v1beta/models/:model", GET_POST, true, None),
             get(handlers::gemini::handle_get_model).post(handlers::gemini::handle_generate),
         )
-        .route(
-            "/v1beta/models/:model/countTokens",
+        .endpoint(
+            endpoint("/v1beta/models/:model/countTokens", POST, false, None),
             post(handlers::gemini::handle_count_tokens),
         )
+        // 未实现的 Gemini 端点 (tunedModels / corpora / generateAnswer 等) 返回 501
+        .hidden("/v1beta/*rest", any(handlers::gemini::handle_unsupported))
         // Common endpoints
-        .route("/v1/models/detect", post(handlers::common::handle_detect_model))
-        .route("/internal/warmup", post(handlers::warmup::handle_warmup))
+        .endpoint(
+            endpoint("/v1/models/detect", POST, false, None),
+            post(handlers::common::handle_detect_model),
+        )
+        .hidden("/internal/warmup", post(handlers::warmup::handle_warmup))
         // Telemetry intercept
-        .route("/v1/api/event_logging/batch", post(silent_ok))
-        .route("/v1/api/event_logging", post(silent_ok))
+        .hidden("/v1/api/event_logging/batch", post(silent_ok))
+        .hidden("/v1/api/event_logging", post(silent_ok))
+}
+
+/// Build the proxy API routes (AI endpoints)
+pub fn build_proxy_routes() -> Router<AppState> {
+    proxy_route_table().router
+}
+
+/// 代理路由表中对外公布的端点 (与 build_proxy_routes 注册的路由一致)
+pub fn proxy_endpoints() -> Vec<ProxyEndpoint> {
+    proxy_route_table().endpoints
 }
 
 /// Health check handler
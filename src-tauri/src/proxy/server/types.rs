@@ -22,6 +22,8 @@ pub struct AppState {
     pub upstream_proxy: Arc<tokio::sync::RwLock<crate::proxy::config::UpstreamProxyConfig>>,
     pub upstream: Arc<crate::proxy::upstream::client::UpstreamClient>,
     pub zai: Arc<RwLock<crate::proxy::ZaiConfig>>,
+    /// 额外上游 provider (不含由 zai 迁移的条目, 见 config::effective_providers)
+    pub providers: Arc<RwLock<Vec<crate::proxy::config::ProviderConfig>>>,
     pub provider_rr: Arc<AtomicUsize>,
     pub zai_vision_mcp: Arc<crate::proxy::zai_vision_mcp::ZaiVisionMcpState>,
     pub monitor: Arc<crate::proxy::monitor::ProxyMonitor>,
@@ -34,6 +36,8 @@ pub struct AppState {
     pub cloudflared_state: Arc<crate::commands::cloudflared::CloudflaredState>,
     pub is_running: Arc<RwLock<bool>>,
     pub port: u16,
+    /// 额外监听端口 (按协议限制)
+    pub listeners: Arc<crate::proxy::server::listeners::ListenerRegistry>,
 }
 
 // Implement FromRef for security state extraction in middleware
@@ -67,6 +71,9 @@ pub struct AccountResponse {
     pub protected_models: Vec<String>,
     pub quota: Option<QuotaResponse>,
     pub device_bound: bool,
+    pub tags: Vec<String>,
+    pub notes: Option<String>,
+    pub last_error: Option<crate::models::AccountLastError>,
     pub last_used: i64,
 }
 
@@ -117,6 +124,19 @@ fn default_bind_mode() -> String {
     "generate".to_string()
 }
 
+/// GET /api/proxy/endpoint-url?protocol=openai
+#[derive(Deserialize, Debug)]
+pub struct EndpointUrlQuery {
+    pub protocol: crate::proxy::config::ListenerProtocol,
+}
+
+/// GET /api/proxy/stats?sections=accounts,latency
+#[derive(Deserialize, Debug, Default)]
+pub struct StatsQuery {
+    #[serde(default)]
+    pub sections: Option<String>,
+}
+
 #[derive(Deserialize, Debug, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct LogsFilterQuery {
@@ -142,6 +162,21 @@ pub struct UpdateMappingWrapper {
     pub config: crate::proxy::config::ProxyConfig,
 }
 
+#[derive(Deserialize)]
+pub struct UpdateSchedulingConfigWrapper {
+    pub config: crate::proxy::sticky_config::StickySessionConfig,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateRetryConfigWrapper {
+    pub config: crate::proxy::config::RetryConfig,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateBackgroundRoutingWrapper {
+    pub config: crate::proxy::config::BackgroundRoutingConfig,
+}
+
 #[derive(Deserialize, Debug, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct StatsPeriodQuery {
@@ -154,6 +189,34 @@ pub struct StatsPeriodQuery {
 pub struct BulkDeleteRequest {
     #[serde(rename = "accountIds")]
     pub account_ids: Vec<String>,
+    /// 代理运行时允许删除最后一个可用账号
+    #[serde(default)]
+    pub force: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkPauseRequest {
+    pub account_ids: Vec<String>,
+    #[serde(default)]
+    pub force: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkPriorityRequest {
+    pub account_ids: Vec<String>,
+    pub priority: i32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkTagsRequest {
+    pub account_ids: Vec<String>,
+    #[serde(default)]
+    pub add: Vec<String>,
+    #[serde(default)]
+    pub remove: Vec<String>,
 }
 
 #[derive(Deserialize)]
@@ -162,6 +225,13 @@ pub struct ReorderRequest {
     pub account_ids: Vec<String>,
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountNotesRequest {
+    #[serde(default)]
+    pub notes: Option<String>,
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ToggleProxyRequest {
@@ -187,6 +257,13 @@ pub struct CustomDbRequest {
     pub path: String,
 }
 
+#[derive(Deserialize)]
+pub struct AntigravityImportRequest {
+    /// 确认导入的候选账号 ID; 省略时只扫描
+    #[serde(default)]
+    pub confirmed: Option<Vec<String>>,
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CliSyncStatusRequest {
@@ -278,6 +355,9 @@ pub fn to_account_response(
             is_forbidden: q.is_forbidden,
         }),
         device_bound: account.device_profile.is_some(),
+        tags: account.tags.clone(),
+        notes: account.notes.clone(),
+        last_error: account.last_error.clone(),
         last_used: account.last_used,
     }
 }
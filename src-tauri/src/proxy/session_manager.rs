@@ -77,7 +77,16 @@ impl SessionManager {
     }
 
     /// 根据 OpenAI 请求生成稳定的会话指纹
+    ///
+    /// 优先级:
+    /// 1. metadata.session_id / conversation_id / user_id (客户端显式提供)
+    /// 2. 第一条用户消息的 SHA256 哈希
     pub fn extract_openai_session_id(request: &OpenAIRequest) -> String {
+        if let Some(hint) = request.metadata_session_hint() {
+            tracing::debug!("[SessionManager-OpenAI] Using metadata session hint: {}", hint);
+            return hint.to_string();
+        }
+
         let mut hasher = Sha256::new();
 
         let mut content_found = false;
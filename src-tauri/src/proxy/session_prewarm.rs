@@ -0,0 +1,273 @@
+// 会话预热 (CacheFirst 模式)
+// 新会话的首个请求同时承担 token 刷新与冷缓存, 用户最能感知的是第二轮的延迟。
+// 开启后, 请求建立新的会话绑定时, 异步向绑定账号发送一个极小的 generateContent,
+// 携带转换后的 systemInstruction 前缀 (截断, 不含工具), 让 Gemini 的隐式缓存在第二轮之前就绪。
+// 预热直接调用上游, 不经过监控中间件 (不计入用户统计); 每个会话最多一次; 默认关闭。
+// 按会话记录 cachedContentTokenCount 命中情况, 区分预热 / 未预热会话以衡量效果。
+
+use crate::proxy::sticky_config::{SchedulingMode, StickySessionConfig};
+use crate::proxy::upstream::client::UpstreamClient;
+use crate::proxy::upstream::timeout::TimeoutProfile;
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// 预热携带的 systemInstruction 最大字符数
+const MAX_SYSTEM_CHARS: usize = 32_000;
+/// 最多跟踪的会话数, 超出时淘汰最久未活动的会话
+const MAX_TRACKED_SESSIONS: usize = 2_000;
+
+#[derive(Debug, Clone, Default)]
+struct SessionCache {
+    prewarmed: bool,
+    requests: u64,
+    /// 首轮之后的请求数 (首轮无论是否预热都是冷缓存, 不参与命中率)
+    follow_ups: u64,
+    /// 首轮之后 cachedContentTokenCount > 0 的请求数
+    cache_hits: u64,
+    last_seen: i64,
+}
+
+static SESSIONS: Lazy<DashMap<String, SessionCache>> = Lazy::new(DashMap::new);
+static PREWARMS_SENT: AtomicU64 = AtomicU64::new(0);
+static PREWARM_FAILURES: AtomicU64 = AtomicU64::new(0);
+
+/// 一类会话的缓存命中统计
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CacheHitStats {
+    pub sessions: u64,
+    pub follow_up_requests: u64,
+    pub cache_hits: u64,
+    /// cache_hits / follow_up_requests (无数据时为 0)
+    pub hit_rate: f64,
+}
+
+/// 会话预热统计 (启动以来)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SessionPrewarmStats {
+    pub prewarms_sent: u64,
+    pub prewarm_failures: u64,
+    pub prewarmed: CacheHitStats,
+    pub cold: CacheHitStats,
+}
+
+pub fn stats() -> SessionPrewarmStats {
+    let mut prewarmed = CacheHitStats::default();
+    let mut cold = CacheHitStats::default();
+    for entry in SESSIONS.iter() {
+        let bucket = if entry.prewarmed { &mut prewarmed } else { &mut cold };
+        bucket.sessions += 1;
+        bucket.follow_up_requests += entry.follow_ups;
+        bucket.cache_hits += entry.cache_hits;
+    }
+    for bucket in [&mut prewarmed, &mut cold] {
+        if bucket.follow_up_requests > 0 {
+            bucket.hit_rate = bucket.cache_hits as f64 / bucket.follow_up_requests as f64;
+        }
+    }
+    SessionPrewarmStats {
+        prewarms_sent: PREWARMS_SENT.load(Ordering::Relaxed),
+        prewarm_failures: PREWARM_FAILURES.load(Ordering::Relaxed),
+        prewarmed,
+        cold,
+    }
+}
+
+/// 记录一次完成的响应的缓存命中 (按会话)
+pub fn record_usage(session_id: &str, cached_tokens: u32) {
+    if session_id.is_empty() {
+        return;
+    }
+    evict_if_full(session_id);
+    let mut entry = SESSIONS.entry(session_id.to_string()).or_default();
+    entry.requests += 1;
+    entry.last_seen = chrono::Utc::now().timestamp();
+    if entry.requests > 1 {
+        entry.follow_ups += 1;
+        if cached_tokens > 0 {
+            entry.cache_hits += 1;
+        }
+    }
+}
+
+/// 会话是否需要预热: 开启且为 CacheFirst 模式, 同一会话只返回一次 true
+fn claim(config: &StickySessionConfig, session_id: &str) -> bool {
+    if !config.session_prewarm || config.mode != SchedulingMode::CacheFirst || session_id.is_empty() {
+        return false;
+    }
+    evict_if_full(session_id);
+    let mut entry = SESSIONS.entry(session_id.to_string()).or_default();
+    if entry.prewarmed {
+        return false;
+    }
+    entry.prewarmed = true;
+    entry.last_seen = chrono::Utc::now().timestamp();
+    true
+}
+
+fn evict_if_full(session_id: &str) {
+    if SESSIONS.len() < MAX_TRACKED_SESSIONS || SESSIONS.contains_key(session_id) {
+        return;
+    }
+    let oldest = SESSIONS
+        .iter()
+        .min_by_key(|e| e.last_seen)
+        .map(|e| e.key().clone());
+    if let Some(key) = oldest {
+        SESSIONS.remove(&key);
+    }
+}
+
+/// 由转换后的 v1internal 请求构造预热请求: 保留截断后的 systemInstruction, 去掉工具, 单个占位消息, 只生成 1 个 token
+fn build_prewarm_body(gemini_body: &Value) -> Option<Value> {
+    let mut body = gemini_body.clone();
+    let request = body.get_mut("request")?.as_object_mut()?;
+    let system = cap_system_instruction(request.get("systemInstruction")?.clone());
+
+    request.remove("tools");
+    request.remove("toolConfig");
+    request.insert("systemInstruction".to_string(), system);
+    request.insert(
+        "contents".to_string(),
+        json!([{ "role": "user", "parts": [{ "text": "." }] }]),
+    );
+    request.insert("generationConfig".to_string(), json!({ "maxOutputTokens": 1 }));
+    body["requestId"] = json!(format!("prewarm-{}", uuid::Uuid::new_v4()));
+    Some(body)
+}
+
+/// 按顺序保留 parts, 总文本不超过 MAX_SYSTEM_CHARS (隐式缓存按前缀匹配)
+fn cap_system_instruction(mut system: Value) -> Value {
+    let Some(parts) = system.get_mut("parts").and_then(|p| p.as_array_mut()) else {
+        return system;
+    };
+    let mut remaining = MAX_SYSTEM_CHARS;
+    let mut keep = 0;
+    for part in parts.iter_mut() {
+        if remaining == 0 {
+            break;
+        }
+        keep += 1;
+        if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
+            let len = text.chars().count();
+            if len > remaining {
+                part["text"] = json!(text.chars().take(remaining).collect::<String>());
+                remaining = 0;
+            } else {
+                remaining -= len;
+            }
+        }
+    }
+    parts.truncate(keep);
+    system
+}
+
+/// 新会话绑定后触发预热 (异步, 不影响当前请求)
+pub fn maybe_prewarm(
+    config: &StickySessionConfig,
+    upstream: Arc<UpstreamClient>,
+    access_token: &str,
+    email: &str,
+    session_id: &str,
+    gemini_body: &Value,
+    trace_id: &str,
+) {
+    let Some(body) = build_prewarm_body(gemini_body) else {
+        return;
+    };
+    if !claim(config, session_id) {
+        return;
+    }
+
+    let access_token = access_token.to_string();
+    let email = email.to_string();
+    let session_id = session_id.to_string();
+    let trace_id = trace_id.to_string();
+    tokio::spawn(async move {
+        let result = upstream
+            .call_v1_internal_with_headers(
+                "generateContent",
+                &access_token,
+                body,
+                None,
+                std::collections::HashMap::new(),
+                TimeoutProfile::Quick,
+            )
+            .await;
+        match result {
+            Ok(response) if response.status().is_success() => {
+                PREWARMS_SENT.fetch_add(1, Ordering::Relaxed);
+                tracing::info!(
+                    "[{}][Prewarm] Primed implicit cache on {} for session {}",
+                    trace_id,
+                    email,
+                    session_id
+                );
+            }
+            Ok(response) => {
+                PREWARM_FAILURES.fetch_add(1, Ordering::Relaxed);
+                tracing::warn!(
+                    "[{}][Prewarm] Upstream returned {} on {}",
+                    trace_id,
+                    response.status(),
+                    email
+                );
+            }
+            Err(e) => {
+                PREWARM_FAILURES.fetch_add(1, Ordering::Relaxed);
+                tracing::warn!("[{}][Prewarm] Request failed on {}: {}", trace_id, email, e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prewarm_body_and_once_per_session() {
+        let long_text = "x".repeat(MAX_SYSTEM_CHARS + 10);
+        let gemini_body = json!({
+            "project": "p",
+            "requestId": "agent-1",
+            "model": "gemini-3-pro",
+            "request": {
+                "contents": [{ "role": "user", "parts": [{ "text": "hello" }] }],
+                "systemInstruction": { "role": "user", "parts": [{ "text": "You are Claude." }, { "text": long_text }, { "text": "tail" }] },
+                "tools": [{ "functionDeclarations": [] }],
+                "toolConfig": {},
+                "generationConfig": { "maxOutputTokens": 64000, "thinkingConfig": { "includeThoughts": true } }
+            }
+        });
+
+        let body = build_prewarm_body(&gemini_body).unwrap();
+        let request = &body["request"];
+        assert!(request.get("tools").is_none() && request.get("toolConfig").is_none());
+        assert_eq!(request["generationConfig"], json!({ "maxOutputTokens": 1 }));
+        let parts = request["systemInstruction"]["parts"].as_array().unwrap();
+        assert_eq!(parts.len(), 2);
+        let total: usize = parts.iter().map(|p| p["text"].as_str().unwrap().chars().count()).sum();
+        assert_eq!(total, MAX_SYSTEM_CHARS);
+        assert!(body["requestId"].as_str().unwrap().starts_with("prewarm-"));
+
+        // 默认关闭; 仅 CacheFirst 下生效, 同一会话只预热一次
+        let mut config = StickySessionConfig::default();
+        assert!(!claim(&config, "prewarm-test-session"));
+        config.session_prewarm = true;
+        assert!(!claim(&config, "prewarm-test-session"));
+        config.mode = SchedulingMode::CacheFirst;
+        assert!(claim(&config, "prewarm-test-session"));
+        assert!(!claim(&config, "prewarm-test-session"));
+
+        // 首轮不计入命中率
+        record_usage("prewarm-test-session", 0);
+        record_usage("prewarm-test-session", 1200);
+        let entry = SESSIONS.get("prewarm-test-session").unwrap().clone();
+        assert_eq!((entry.requests, entry.follow_ups, entry.cache_hits), (2, 1, 1));
+        assert!(stats().prewarmed.sessions >= 1);
+    }
+}
@@ -1,4 +1,9 @@
+use crate::error::FieldViolation;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// 缓存优先模式下最大等待时间的合法范围 (秒)
+pub const MAX_WAIT_SECONDS_RANGE: std::ops::RangeInclusive<u64> = 1..=600;
 
 /// 调度模式枚举
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
@@ -8,6 +13,7 @@ pub enum SchedulingMode {
     /// 平衡模式 (Balance): 锁定同一账号，限流时立即切换到备选账号，兼顾成功率和性能
     Balance,
     /// 性能优先 (Performance-first): 纯轮询模式 (Round-robin)，账号负载最均衡，但不利用缓存
+    /// performance_weighted 开启时改为按健康分加权随机
     PerformanceFirst,
     /// 指定账号 (Selected): 仅在指定的账号列表中进行负载均衡
     Selected,
@@ -39,6 +45,217 @@ pub struct StickySessionConfig {
     /// - false: 宽松模式，selected_accounts 不可用时 fallback 到其他账号
     #[serde(default)]
     pub strict_selected: bool,
+    /// 哪些上游错误会把账号标记为限流
+    #[serde(default)]
+    pub rate_limit_policy: RateLimitPolicy,
+    /// 新会话绑定账号时的负载评分权重
+    #[serde(default)]
+    pub load_weights: LoadScoreWeights,
+    /// 性能优先模式下按健康分加权随机选择账号 (false 为纯轮询)
+    #[serde(default)]
+    pub performance_weighted: bool,
+    /// 加权选择的随机种子 (设置后选择序列可复现, 用于测试/排查)
+    #[serde(default)]
+    pub weighted_seed: Option<u64>,
+    /// 同一账号两次新租约之间的最小间隔 (毫秒, 0 = 关闭), 用于避免每分钟突发限流
+    #[serde(default)]
+    pub min_request_interval_ms: u64,
+    /// 按账号覆盖最小间隔 (AccountID -> 毫秒, 0 = 该账号不限速)
+    #[serde(default)]
+    pub account_min_interval_ms: std::collections::HashMap<String, u64>,
+    /// 需要等待的时间不超过该值时原地延迟, 否则换到其他账号 (毫秒)
+    #[serde(default = "default_pacing_max_delay_ms")]
+    pub pacing_max_delay_ms: u64,
+    /// 估算剩余日配额低于该百分比的账号只在没有其他候选时使用 (None = 不按估算调整)
+    #[serde(default)]
+    pub quota_estimate_min_remaining_pct: Option<f64>,
+    /// 缓存优先模式下, 新会话绑定账号后异步预热该账号的隐式缓存 (默认关闭)
+    #[serde(default)]
+    pub session_prewarm: bool,
+}
+
+fn default_pacing_max_delay_ms() -> u64 {
+    1000
+}
+
+impl StickySessionConfig {
+    /// 账号生效的最小请求间隔 (按账号覆盖优先)
+    pub fn min_interval_for(&self, account_id: &str) -> u64 {
+        self.account_min_interval_ms
+            .get(account_id)
+            .copied()
+            .unwrap_or(self.min_request_interval_ms)
+    }
+}
+
+/// 校验调度配置时需要的外部信息
+#[derive(Debug, Default, Clone)]
+pub struct ValidationContext {
+    /// 已存在的账号 ID
+    pub account_ids: HashSet<String>,
+    /// 已知的模型名 (内置 + 自定义映射, 均为小写)
+    pub known_models: HashSet<String>,
+}
+
+impl ValidationContext {
+    fn is_known_model(&self, model: &str) -> bool {
+        self.known_models.contains(&model.to_lowercase())
+    }
+}
+
+impl StickySessionConfig {
+    /// 校验并规范化配置, 一次性返回所有不合法的字段
+    ///
+    /// 规范化: 账号列表去重, 模型名统一为标准 ID (无法识别的保留原名), 每个账号的模型列表去重。
+    pub fn validate(mut self, ctx: &ValidationContext) -> Result<Self, Vec<FieldViolation>> {
+        let mut violations = Vec::new();
+
+        if !MAX_WAIT_SECONDS_RANGE.contains(&self.max_wait_seconds) {
+            violations.push(FieldViolation::new(
+                "max_wait_seconds",
+                format!(
+                    "must be between {} and {}",
+                    MAX_WAIT_SECONDS_RANGE.start(),
+                    MAX_WAIT_SECONDS_RANGE.end()
+                ),
+            ));
+        }
+
+        let mut seen = HashSet::new();
+        self.selected_accounts.retain(|id| seen.insert(id.clone()));
+        for (i, id) in self.selected_accounts.iter().enumerate() {
+            if !ctx.account_ids.contains(id) {
+                violations.push(FieldViolation::new(
+                    format!("selected_accounts[{}]", i),
+                    format!("unknown account id: {}", id),
+                ));
+            }
+        }
+
+        if self.mode == SchedulingMode::Selected && self.strict_selected && self.selected_accounts.is_empty() {
+            violations.push(FieldViolation::new(
+                "selected_accounts",
+                "strict selected mode requires at least one account",
+            ));
+        }
+
+        let mut models_by_account = std::collections::HashMap::new();
+        for (account_id, models) in std::mem::take(&mut self.selected_models) {
+            if !ctx.account_ids.contains(&account_id) {
+                violations.push(FieldViolation::new(
+                    format!("selected_models.{}", account_id),
+                    format!("unknown account id: {}", account_id),
+                ));
+            }
+            let mut seen = HashSet::new();
+            let mut normalized = Vec::with_capacity(models.len());
+            for (i, model) in models.into_iter().enumerate() {
+                if !ctx.is_known_model(&model) {
+                    violations.push(FieldViolation::new(
+                        format!("selected_models.{}[{}]", account_id, i),
+                        format!("unknown model: {}", model),
+                    ));
+                }
+                let model = crate::proxy::common::model_mapping::normalize_to_standard_id(&model)
+                    .unwrap_or(model);
+                if seen.insert(model.clone()) {
+                    normalized.push(model);
+                }
+            }
+            models_by_account.insert(account_id, normalized);
+        }
+        self.selected_models = models_by_account;
+
+        for (name, value) in [
+            ("sessions", self.load_weights.sessions),
+            ("in_flight", self.load_weights.in_flight),
+            ("throughput", self.load_weights.throughput),
+        ] {
+            if !value.is_finite() || value < 0.0 {
+                violations.push(FieldViolation::new(
+                    format!("load_weights.{}", name),
+                    "must be a finite, non-negative number",
+                ));
+            }
+        }
+
+        for account_id in self.account_min_interval_ms.keys() {
+            if !ctx.account_ids.contains(account_id) {
+                violations.push(FieldViolation::new(
+                    format!("account_min_interval_ms.{}", account_id),
+                    format!("unknown account id: {}", account_id),
+                ));
+            }
+        }
+
+        if let Some(pct) = self.quota_estimate_min_remaining_pct {
+            if !(0.0..=100.0).contains(&pct) {
+                violations.push(FieldViolation::new(
+                    "quota_estimate_min_remaining_pct",
+                    "must be between 0 and 100",
+                ));
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(self)
+        } else {
+            violations.sort_by(|a, b| a.field.cmp(&b.field));
+            Err(violations)
+        }
+    }
+
+    /// 旧版本配置迁移: 把超出范围的值拉回合法区间, 返回是否有改动
+    ///
+    /// 只处理不依赖账号/模型列表的字段, 加载配置时调用, 不会因为旧配置而拒绝启动。
+    pub fn migrate_legacy(&mut self) -> bool {
+        let mut changed = false;
+
+        let clamped = self
+            .max_wait_seconds
+            .clamp(*MAX_WAIT_SECONDS_RANGE.start(), *MAX_WAIT_SECONDS_RANGE.end());
+        if clamped != self.max_wait_seconds {
+            self.max_wait_seconds = clamped;
+            changed = true;
+        }
+
+        let defaults = LoadScoreWeights::default();
+        for (value, default) in [
+            (&mut self.load_weights.sessions, defaults.sessions),
+            (&mut self.load_weights.in_flight, defaults.in_flight),
+            (&mut self.load_weights.throughput, defaults.throughput),
+        ] {
+            if !value.is_finite() || *value < 0.0 {
+                *value = default;
+                changed = true;
+            }
+        }
+
+        let before = self.selected_accounts.len();
+        let mut seen = HashSet::new();
+        self.selected_accounts.retain(|id| seen.insert(id.clone()));
+        changed |= before != self.selected_accounts.len();
+
+        changed
+    }
+
+    /// 清理已不存在的账号引用 (账号被删除后残留的 ID), 返回是否有改动
+    pub fn prune_unknown_accounts(&mut self, known: &HashSet<String>) -> bool {
+        let before = (
+            self.selected_accounts.len(),
+            self.selected_models.len(),
+            self.account_min_interval_ms.len(),
+        );
+        self.selected_accounts.retain(|id| known.contains(id));
+        self.selected_models.retain(|id, _| known.contains(id));
+        self.account_min_interval_ms.retain(|id, _| known.contains(id));
+        before
+            != (
+                self.selected_accounts.len(),
+                self.selected_models.len(),
+                self.account_min_interval_ms.len(),
+            )
+    }
 }
 
 impl Default for StickySessionConfig {
@@ -49,6 +266,203 @@ impl Default for StickySessionConfig {
             selected_accounts: Vec::new(),
             selected_models: std::collections::HashMap::new(),
             strict_selected: false,
+            rate_limit_policy: RateLimitPolicy::default(),
+            load_weights: LoadScoreWeights::default(),
+            performance_weighted: false,
+            weighted_seed: None,
+            min_request_interval_ms: 0,
+            account_min_interval_ms: std::collections::HashMap::new(),
+            pacing_max_delay_ms: default_pacing_max_delay_ms(),
+            quota_estimate_min_remaining_pct: None,
+            session_prewarm: false,
+        }
+    }
+}
+
+/// 账号负载评分权重: score = sessions * 活跃会话数 + in_flight * 进行中请求数
+///                        + throughput * 最近窗口内 token 数 / 10k
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoadScoreWeights {
+    pub sessions: f64,
+    pub in_flight: f64,
+    pub throughput: f64,
+    /// 会话在该时长内有请求才计为活跃 (秒)
+    pub active_session_secs: u64,
+}
+
+impl Default for LoadScoreWeights {
+    fn default() -> Self {
+        Self {
+            sessions: 1.0,
+            in_flight: 1.0,
+            throughput: 0.5,
+            active_session_secs: 1800,
+        }
+    }
+}
+
+/// 限流标记策略
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimitPolicy {
+    /// 会设置限流锁的上游状态码
+    pub limited_statuses: Vec<u16>,
+    /// 无 Retry-After / quotaResetDelay 时的默认锁定秒数 (status -> 秒)
+    /// 作用于 5xx 以及无法识别原因的 429; 配额耗尽仍按 backoff_steps 递增
+    pub default_durations: std::collections::HashMap<u16, u64>,
+    /// 5xx 在窗口内累计达到该次数才设置限流锁 (1 = 每次都锁)
+    pub server_error_threshold: u32,
+    /// 5xx 计数窗口 (秒)
+    pub server_error_window_secs: u64,
+}
+
+impl RateLimitPolicy {
+    pub fn applies_to(&self, status: u16) -> bool {
+        self.limited_statuses.contains(&status)
+    }
+
+    /// 无显式重试时间时的默认锁定秒数
+    pub fn default_duration(&self, status: u16, fallback: u64) -> u64 {
+        self.default_durations.get(&status).copied().unwrap_or(fallback)
+    }
+}
+
+impl Default for RateLimitPolicy {
+    fn default() -> Self {
+        Self {
+            limited_statuses: vec![429, 500, 503, 529],
+            default_durations: std::collections::HashMap::from([
+                (429, 60),
+                (500, 8),
+                (503, 8),
+                (529, 8),
+            ]),
+            // 单次瞬时 500 不应让健康账号下线
+            server_error_threshold: 3,
+            server_error_window_secs: 60,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> ValidationContext {
+        ValidationContext {
+            account_ids: HashSet::from(["acc-1".to_string(), "acc-2".to_string()]),
+            known_models: HashSet::from([
+                "gemini-3-flash".to_string(),
+                "claude-sonnet-4-5".to_string(),
+            ]),
         }
     }
+
+    #[test]
+    fn test_validate_normalizes_valid_config() {
+        let config = StickySessionConfig {
+            mode: SchedulingMode::Selected,
+            selected_accounts: vec!["acc-1".into(), "acc-2".into(), "acc-1".into()],
+            selected_models: std::collections::HashMap::from([(
+                "acc-1".to_string(),
+                vec!["Gemini-3-Flash".to_string(), "gemini-3-flash".to_string()],
+            )]),
+            ..Default::default()
+        };
+
+        let normalized = config.validate(&context()).unwrap();
+        assert_eq!(normalized.selected_accounts, vec!["acc-1", "acc-2"]);
+        assert_eq!(normalized.selected_models["acc-1"], vec!["gemini-3-flash"]);
+    }
+
+    #[test]
+    fn test_validate_collects_all_violations() {
+        let config = StickySessionConfig {
+            mode: SchedulingMode::Selected,
+            strict_selected: true,
+            max_wait_seconds: 0,
+            selected_accounts: Vec::new(),
+            selected_models: std::collections::HashMap::from([(
+                "ghost".to_string(),
+                vec!["no-such-model".to_string()],
+            )]),
+            load_weights: LoadScoreWeights {
+                throughput: f64::NAN,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let fields: Vec<String> = config
+            .validate(&context())
+            .unwrap_err()
+            .into_iter()
+            .map(|v| v.field)
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                "load_weights.throughput",
+                "max_wait_seconds",
+                "selected_accounts",
+                "selected_models.ghost",
+                "selected_models.ghost[0]",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_migrate_legacy_clamps_out_of_range_values() {
+        // 旧版本没有范围限制, 可能保存了 0 或超大的等待时间
+        let mut config: StickySessionConfig = serde_json::from_value(serde_json::json!({
+            "mode": "CacheFirst",
+            "max_wait_seconds": 3600,
+            "selected_accounts": ["acc-1", "acc-1"]
+        }))
+        .unwrap();
+
+        assert!(config.migrate_legacy());
+        assert_eq!(config.max_wait_seconds, 600);
+        assert_eq!(config.selected_accounts, vec!["acc-1"]);
+        assert!(!config.migrate_legacy());
+    }
+
+    #[test]
+    fn test_prune_unknown_accounts_drops_deleted_ids() {
+        let mut config = StickySessionConfig {
+            selected_accounts: vec!["acc-1".into(), "deleted".into()],
+            selected_models: std::collections::HashMap::from([
+                ("acc-2".to_string(), vec!["gemini-3-flash".to_string()]),
+                ("deleted".to_string(), vec!["gemini-3-flash".to_string()]),
+            ]),
+            account_min_interval_ms: std::collections::HashMap::from([("deleted".to_string(), 500)]),
+            ..Default::default()
+        };
+
+        assert!(config.prune_unknown_accounts(&context().account_ids));
+        assert_eq!(config.selected_accounts, vec!["acc-1"]);
+        assert!(config.selected_models.contains_key("acc-2"));
+        assert!(!config.selected_models.contains_key("deleted"));
+        assert!(config.account_min_interval_ms.is_empty());
+        assert!(!config.prune_unknown_accounts(&context().account_ids));
+    }
+
+    #[test]
+    fn test_rate_limit_policy_from_partial_config() {
+        let config: StickySessionConfig = serde_json::from_value(serde_json::json!({
+            "mode": "Balance",
+            "rate_limit_policy": {
+                "limited_statuses": [429, 502],
+                "default_durations": { "502": 30 }
+            }
+        }))
+        .unwrap();
+        let policy = config.rate_limit_policy;
+        assert!(policy.applies_to(502));
+        assert!(!policy.applies_to(500));
+        assert_eq!(policy.default_duration(502, 8), 30);
+        assert_eq!(policy.default_duration(503, 8), 8);
+        assert_eq!(policy.server_error_threshold, 3);
+    }
 }
@@ -69,6 +69,8 @@ impl TokenManager {
             return Err(format!("账号目录不存在: {:?}", accounts_dir));
         }
 
+        super::quota_estimate::attach_store(&self.data_dir);
+
         self.tokens.clear();
         self.current_index.store(0, Ordering::SeqCst);
         {
@@ -382,6 +384,18 @@ impl TokenManager {
             }
         }
 
+        match account
+            .get("last_error")
+            .and_then(|v| serde_json::from_value::<crate::models::AccountLastError>(v.clone()).ok())
+        {
+            Some(last_error) => {
+                self.last_errors.insert(account_id.clone(), last_error);
+            }
+            None => {
+                self.last_errors.remove(&account_id);
+            }
+        }
+
         let health_score = self
             .health_scores
             .get(&account_id)
@@ -425,6 +439,14 @@ impl TokenManager {
                 .and_then(|q| q.get("is_forbidden"))
                 .and_then(|v| v.as_bool())
                 .unwrap_or(false),
+            project_setup: account
+                .get("project_setup_required")
+                .and_then(|v| serde_json::from_value(v.clone()).ok()),
+            priority: account
+                .get("priority")
+                .and_then(|v| v.as_i64())
+                .map(|p| p as i32)
+                .unwrap_or(0),
         }))
     }
 }
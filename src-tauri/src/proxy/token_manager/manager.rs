@@ -2,7 +2,7 @@
 
 use dashmap::DashMap;
 use std::path::PathBuf;
-use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use tokio_util::sync::CancellationToken;
 
@@ -10,6 +10,9 @@ use super::models::ProxyToken;
 use crate::proxy::rate_limit::RateLimitTracker;
 use crate::proxy::sticky_config::StickySessionConfig;
 
+/// Idle time after which a sticky session (and state keyed by it) expires
+pub const SESSION_TTL: std::time::Duration = std::time::Duration::from_secs(24 * 3600);
+
 /// Central token manager for Google account pool
 pub struct TokenManager {
     pub(crate) tokens: Arc<DashMap<String, ProxyToken>>,
@@ -20,6 +23,8 @@ pub struct TokenManager {
     pub(crate) sticky_config: Arc<tokio::sync::RwLock<StickySessionConfig>>,
     pub(crate) session_accounts: Arc<DashMap<String, (String, std::time::Instant)>>,
     pub(crate) health_scores: Arc<DashMap<String, f32>>,
+    /// 流中途停滞次数 (account_id -> 次数)
+    pub(crate) stall_counts: Arc<DashMap<String, u64>>,
     pub(crate) active_requests: Arc<DashMap<String, AtomicUsize>>,
     pub(crate) circuit_breaker_config: Arc<tokio::sync::RwLock<crate::models::CircuitBreakerConfig>>,
     pub circuit_breaker: DashMap<String, (std::time::Instant, String)>,
@@ -29,6 +34,12 @@ pub struct TokenManager {
     cancel_token: CancellationToken,
     /// [NEW] Handle for auto-cleanup background task
     auto_cleanup_handle: Arc<tokio::sync::Mutex<Option<tokio::task::JoinHandle<()>>>>,
+    /// 最近一次计算的有效账号数 (get_proxy_status 在锁竞争时直接读取)
+    effective_len_cache: Arc<AtomicUsize>,
+    /// OAuth 刷新单飞协调 (进行中的刷新与最近结果)
+    pub(crate) refresh: Arc<super::refresh::RefreshCoordinator>,
+    /// 账号最近一次失败 (account_id -> 错误), 与账号文件的 last_error 同步
+    pub(crate) last_errors: Arc<DashMap<String, crate::models::AccountLastError>>,
 }
 
 impl TokenManager {
@@ -43,6 +54,7 @@ impl TokenManager {
             sticky_config: Arc::new(tokio::sync::RwLock::new(StickySessionConfig::default())),
             session_accounts: Arc::new(DashMap::new()),
             health_scores: Arc::new(DashMap::new()),
+            stall_counts: Arc::new(DashMap::new()),
             active_requests: Arc::new(DashMap::new()),
             circuit_breaker_config: Arc::new(tokio::sync::RwLock::new(
                 crate::models::CircuitBreakerConfig::default(),
@@ -51,6 +63,9 @@ impl TokenManager {
             preferred_account_id: Arc::new(tokio::sync::RwLock::new(None)),
             cancel_token: CancellationToken::new(),
             auto_cleanup_handle: Arc::new(tokio::sync::Mutex::new(None)),
+            effective_len_cache: Arc::new(AtomicUsize::new(0)),
+            refresh: Arc::new(super::refresh::RefreshCoordinator::default()),
+            last_errors: Arc::new(DashMap::new()),
         }
     }
 
@@ -113,11 +128,15 @@ impl TokenManager {
                         if session_cleanup_interval >= 40 {
                             session_cleanup_interval = 0;
                             let now = std::time::Instant::now();
-                            let expiry = std::time::Duration::from_secs(24 * 3600);
+                            let expiry = SESSION_TTL;
                             let mut removed_sessions = 0;
 
-                            session_map.retain(|_, (_, ts)| {
-                                if now.duration_since(*ts) > expiry {
+                            // 缓存意图有效期内的会话保留绑定
+                            super::selection::purge_expired_cache_intents();
+                            session_map.retain(|session_id, (_, ts)| {
+                                if now.duration_since(*ts) > expiry
+                                    && !super::selection::has_cache_intent(session_id)
+                                {
                                     removed_sessions += 1;
                                     false
                                 } else {
@@ -208,6 +227,10 @@ impl TokenManager {
 
     /// Report account failure for circuit breaker
     pub fn report_account_failure(&self, account_id: &str, status_code: u16, error_msg: &str) {
+        let last_error = crate::models::AccountLastError::new(status_code, error_msg);
+        self.last_errors.insert(account_id.to_string(), last_error);
+        self.persist_last_error(account_id);
+
         let should_block = matches!(status_code, 402 | 429 | 401);
 
         if should_block {
@@ -225,6 +248,35 @@ impl TokenManager {
         }
     }
 
+    /// 成功请求后清除账号的 last_error (仅在存在记录时写盘)
+    pub(crate) fn clear_last_error(&self, account_id: &str) {
+        if self.last_errors.remove(account_id).is_some() {
+            self.persist_last_error(account_id);
+        }
+    }
+
+    /// 将内存中的 last_error 写回账号文件 (后台执行, 不阻塞请求)。
+    /// 在账号文件锁内读改写, 不会与并发的保存操作互相覆盖; 写入时取最新值, 后台任务乱序执行也不会留下过期记录
+    fn persist_last_error(&self, account_id: &str) {
+        if !self.tokens.contains_key(account_id) {
+            return;
+        }
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let data_dir = self.data_dir.clone();
+        let last_errors = self.last_errors.clone();
+        let account_id = account_id.to_string();
+        handle.spawn_blocking(move || {
+            let result = crate::modules::account::storage::update_account_in(&data_dir, &account_id, |account| {
+                account.last_error = last_errors.get(&account_id).map(|e| e.clone());
+            });
+            if let Err(e) = result {
+                tracing::warn!("Failed to persist last_error for {}: {}", account_id, e);
+            }
+        });
+    }
+
     /// Report account needing validation (Gemini 403 VALIDATION_REQUIRED)
     pub fn report_account_validation_required(&self, account_id: &str, verification_url: &str) {
         // [FIX] Check if account exists in index before writing
@@ -295,6 +347,18 @@ impl TokenManager {
         tracing::warn!("📉 Health score decreased for account {}", account_id);
     }
 
+    /// Report a mid-stream upstream stall - counted per account and fed into both health scores
+    pub fn report_stream_stall(&self, account_id: &str, email: &str) {
+        *self.stall_counts.entry(account_id.to_string()).or_insert(0) += 1;
+        self.record_failure(account_id);
+        super::selection::record_stall(email);
+    }
+
+    /// Number of mid-stream stalls seen on an account since the service started
+    pub fn stall_count(&self, account_id: &str) -> u64 {
+        self.stall_counts.get(account_id).map(|c| *c).unwrap_or(0)
+    }
+
     /// Report 429 penalty - heavily decrease health score
     pub fn report_429_penalty(&self, account_id: &str) {
         if let Some(mut score) = self.health_scores.get_mut(account_id) {
@@ -330,14 +394,28 @@ impl TokenManager {
     /// Get effective account count (considering scheduling mode)
     pub async fn effective_len(&self) -> usize {
         let config = self.sticky_config.read().await;
-        if matches!(
+        self.compute_effective_len(&config)
+    }
+
+    /// 非阻塞版本: 配置正在被写入时返回上一次的计数, 供高频轮询的状态接口使用
+    pub fn effective_len_cached(&self) -> usize {
+        match self.sticky_config.try_read() {
+            Ok(config) => self.compute_effective_len(&config),
+            Err(_) => self.effective_len_cache.load(Ordering::Relaxed),
+        }
+    }
+
+    fn compute_effective_len(&self, config: &StickySessionConfig) -> usize {
+        let len = if matches!(
             config.mode,
             crate::proxy::sticky_config::SchedulingMode::Selected
         ) {
             config.selected_accounts.len()
         } else {
             self.tokens.len()
-        }
+        };
+        self.effective_len_cache.store(len, Ordering::Relaxed);
+        len
     }
 
     // =========================================================================
@@ -370,12 +448,14 @@ impl TokenManager {
     /// race conditions where persist_token() could recreate the account.
     pub fn remove_account(&self, account_id: &str) {
         // Remove from token pool
-        if self.tokens.remove(account_id).is_some() {
+        if let Some((_, token)) = self.tokens.remove(account_id) {
             tracing::info!("🗑️ Removed account {} from token pool", account_id);
+            super::selection::forget_account(account_id, &token.email);
         }
 
-        // Remove health score
+        // Remove health score and stall counter
         self.health_scores.remove(account_id);
+        self.stall_counts.remove(account_id);
 
         // Remove from circuit breaker
         self.circuit_breaker.remove(account_id);
@@ -461,3 +541,60 @@ pub(crate) fn truncate_reason(reason: &str, max_len: usize) -> String {
         format!("{}...", &reason[..max_len - 3])
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::selection::tests::synthetic_token;
+    use super::super::AccountHealth;
+    use super::*;
+
+    #[test]
+    fn test_last_error_is_recorded_and_cleared_on_success() {
+        let manager = TokenManager::new(std::env::temp_dir());
+        manager.tokens.insert("acc-1".to_string(), synthetic_token("acc-1"));
+        let token = crate::models::TokenData::new(String::new(), String::new(), 3600, None, None, None);
+        let mut account = crate::models::Account::new("acc-1".to_string(), "acc-1@example.com".to_string(), token);
+        account.notes = Some("work project, don't burn".to_string());
+
+        manager.report_account_failure("acc-1", 402, &"billing state unavailable ".repeat(40));
+        let health = AccountHealth::new(&account, Some(&manager));
+        let last_error = health.last_error.expect("last_error recorded");
+        assert_eq!(last_error.status, 402);
+        assert!(last_error.message.ends_with("...") && last_error.message.chars().count() == 303);
+        assert!(health.in_pool);
+        assert!(health.circuit_breaker.is_some());
+        assert_eq!(health.notes.as_deref(), Some("work project, don't burn"));
+
+        manager.mark_account_success("acc-1@example.com", None);
+        assert!(AccountHealth::new(&account, Some(&manager)).last_error.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_last_error_persist_keeps_concurrent_account_updates() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let manager = TokenManager::new(data_dir.path().to_path_buf());
+        manager.tokens.insert("acc-1".to_string(), synthetic_token("acc-1"));
+        let token = crate::models::TokenData::new(String::new(), String::new(), 3600, None, None, None);
+        let account = crate::models::Account::new("acc-1".to_string(), "acc-1@example.com".to_string(), token);
+        crate::modules::account::storage::save_account_in(data_dir.path(), &account).unwrap();
+
+        manager.report_account_failure("acc-1", 429, "rate limited");
+        crate::modules::account::storage::update_account_in(data_dir.path(), "acc-1", |account| {
+            account.notes = Some("edited while persisting".to_string());
+        })
+        .unwrap();
+
+        let mut persisted = None;
+        for _ in 0..100 {
+            let account = crate::modules::account::storage::load_account_in(data_dir.path(), "acc-1").unwrap();
+            if account.last_error.is_some() {
+                persisted = Some(account);
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        let persisted = persisted.expect("last_error persisted");
+        assert_eq!(persisted.last_error.unwrap().status, 429);
+        assert_eq!(persisted.notes.as_deref(), Some("edited while persisting"));
+    }
+}
@@ -8,7 +8,17 @@ mod quota;
 mod selection;  // Now a directory module with submodules
 mod rate_limiting;
 mod persistence;
+mod refresh;
+mod quota_estimate;
 mod scheduling;
+mod verification;
+mod project_setup;
 
 // Re-export main types
-pub use manager::TokenManager;
+pub use manager::{TokenManager, SESSION_TTL};
+pub use selection::{pacing_stats, record_request_outcome, record_throughput, AccountLoadEntry, PacingStats};
+pub use selection::{cache_intent_stats, record_cache_intent, CacheIntentStats};
+pub use quota_estimate::record_quota_usage;
+pub(crate) use models::ProxyToken;
+pub use models::{AccountHealth, RequestPriority, TokenLease};
+pub use verification::{AccountVerification, VerificationProgress, VerificationStatus};
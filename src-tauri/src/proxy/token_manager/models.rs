@@ -29,6 +29,16 @@ impl Drop for TokenLease {
     }
 }
 
+/// 请求的调度优先级 (来自客户端的 service_tier 等提示)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RequestPriority {
+    /// 默认行为
+    #[default]
+    Normal,
+    /// 可排队的请求: 避开服务活跃会话的账号, 可接受更长的等待
+    Low,
+}
+
 /// Represents a proxy-enabled Google account token
 #[derive(Debug, Clone)]
 pub struct ProxyToken {
@@ -51,4 +61,47 @@ pub struct ProxyToken {
     pub validation_blocked: bool,       // [FIX] Temporary block for VALIDATION_REQUIRED
     pub validation_blocked_until: i64,  // [FIX] Timestamp until which account is blocked
     pub is_forbidden: bool,
+    /// 项目未开通 (403 onboarding), 重试设置成功前不参与调度
+    pub project_setup: Option<crate::models::ProjectSetupRequired>,
+    /// 用户设置的调度优先级 (越大越优先)
+    pub priority: i32,
+}
+
+/// 账号健康概览 (get_account_health): 备注、最近一次失败与号池状态, 不含 token
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct AccountHealth {
+    pub account_id: String,
+    pub email: String,
+    pub notes: Option<String>,
+    pub last_error: Option<crate::models::AccountLastError>,
+    pub disabled: bool,
+    pub proxy_disabled: bool,
+    /// 是否在运行中的号池内 (代理未运行时为 false)
+    pub in_pool: bool,
+    pub health_score: Option<f32>,
+    /// 熔断中时的原因
+    pub circuit_breaker: Option<String>,
+}
+
+impl AccountHealth {
+    /// 号池内的账号使用内存中的 last_error (写盘是异步的), 其余读取账号文件
+    pub fn new(account: &crate::models::Account, token_manager: Option<&super::TokenManager>) -> Self {
+        let id = account.id.as_str();
+        let in_pool = token_manager.is_some_and(|tm| tm.has_account(id));
+        let last_error = match token_manager.filter(|_| in_pool) {
+            Some(tm) => tm.last_errors.get(id).map(|e| e.clone()),
+            None => account.last_error.clone(),
+        };
+        Self {
+            account_id: account.id.clone(),
+            email: account.email.clone(),
+            notes: account.notes.clone(),
+            last_error,
+            disabled: account.disabled,
+            proxy_disabled: account.proxy_disabled,
+            in_pool,
+            health_score: token_manager.and_then(|tm| tm.health_scores.get(id).map(|s| *s)),
+            circuit_breaker: token_manager.and_then(|tm| tm.circuit_breaker.get(id).map(|e| e.1.clone())),
+        }
+    }
 }
@@ -267,27 +267,10 @@ impl TokenManager {
 
         tracing::info!("[Warmup] Token for {} is expiring, refreshing...", email);
 
-        match crate::modules::oauth::refresh_access_token(&refresh_token, Some(&account_id)).await {
-            Ok(token_response) => {
+        match self.refresh_token_shared(&account_id, &refresh_token).await {
+            Ok(refreshed) => {
                 tracing::info!("[Warmup] Token refresh successful for {}", email);
-                let new_now = chrono::Utc::now().timestamp();
-
-                if let Some(mut entry) = self.tokens.get_mut(&account_id) {
-                    entry.access_token = token_response.access_token.clone();
-                    entry.expires_in = token_response.expires_in;
-                    entry.timestamp = new_now;
-                }
-
-                let _ = self
-                    .save_refreshed_token(&account_id, &token_response)
-                    .await;
-
-                Ok((
-                    token_response.access_token,
-                    project_id,
-                    email.to_string(),
-                    0,
-                ))
+                Ok((refreshed.access_token, project_id, email.to_string(), 0))
             }
             Err(e) => Err(format!(
                 "[Warmup] Token refresh failed for {}: {}",
@@ -0,0 +1,191 @@
+// Project Setup Required State
+// 项目未开通 (onboarding / GCA tier) 的账号保留在号池中以便展示引导信息, 但不参与调度;
+// 用户完成开通后调用 retry_project_setup 重新探测, 成功即恢复。状态写入账号文件, 重启后保持。
+
+use super::manager::TokenManager;
+use super::verification::{AccountProber, GoogleProber, VerificationStatus};
+use crate::models::ProjectSetupRequired;
+
+impl TokenManager {
+    /// 标记账号需要完成项目设置 (不计入限流 / 熔断, 也不标记为 forbidden)
+    pub async fn mark_project_setup_required(&self, account_id: &str, setup: ProjectSetupRequired) {
+        let Some(mut token) = self.tokens.get_mut(account_id) else {
+            return;
+        };
+        tracing::warn!(
+            "⚙️ Account {} requires project setup (project: {}): {}",
+            token.email,
+            setup.project_id.as_deref().unwrap_or("unknown"),
+            setup.reason
+        );
+        token.project_setup = Some(setup.clone());
+        drop(token);
+
+        self.session_accounts.retain(|_, (aid, _)| aid != account_id);
+        if let Err(e) = self.persist_project_setup(account_id, Some(&setup)) {
+            tracing::debug!("保存项目设置状态失败 ({}): {}", account_id, e);
+        }
+    }
+
+    /// 账号当前的项目设置状态
+    pub fn project_setup_state(&self, account_id: &str) -> Option<ProjectSetupRequired> {
+        self.tokens.get(account_id).and_then(|t| t.project_setup.clone())
+    }
+
+    /// 重新探测项目是否已开通; 成功时清除状态并重新解析 project_id
+    pub async fn retry_project_setup(&self, account_id: &str) -> Result<(), String> {
+        self.retry_project_setup_with(&GoogleProber, account_id).await
+    }
+
+    pub(crate) async fn retry_project_setup_with(
+        &self,
+        prober: &dyn AccountProber,
+        account_id: &str,
+    ) -> Result<(), String> {
+        let mut token = self
+            .tokens
+            .get(account_id)
+            .map(|t| t.clone())
+            .ok_or_else(|| format!("账号 {} 不在号池中", account_id))?;
+
+        let refreshed = self
+            .refresh_token_shared(&token.account_id, &token.refresh_token)
+            .await?;
+        // 强制重新走 loadCodeAssist, 不使用缓存或兜底的 project_id
+        token.project_id = None;
+        let probe = prober.probe(&token, &refreshed.access_token).await;
+        let detail = probe.detail.clone().unwrap_or_default();
+
+        match probe.status {
+            VerificationStatus::ProjectMisconfigured | VerificationStatus::Failed => {
+                if let Some(setup) = crate::proxy::common::project_setup::detect(403, &detail) {
+                    self.mark_project_setup_required(account_id, setup).await;
+                }
+                Err(detail)
+            }
+            _ => {
+                if let Some(mut entry) = self.tokens.get_mut(account_id) {
+                    entry.project_setup = None;
+                }
+                if let Err(e) = self.persist_project_setup(account_id, None) {
+                    tracing::debug!("清除项目设置状态失败 ({}): {}", account_id, e);
+                }
+                let _ = self.clear_project_id_cache(account_id).await;
+                tracing::info!("✅ Project setup verified for {}", token.email);
+                Ok(())
+            }
+        }
+    }
+
+    fn persist_project_setup(
+        &self,
+        account_id: &str,
+        setup: Option<&ProjectSetupRequired>,
+    ) -> Result<(), String> {
+        let path = match self.tokens.get(account_id) {
+            Some(entry) => entry.account_path.clone(),
+            None => self.data_dir.join("accounts").join(format!("{}.json", account_id)),
+        };
+        let mut content: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(&path).map_err(|e| format!("读取文件失败: {}", e))?,
+        )
+        .map_err(|e| format!("解析 JSON 失败: {}", e))?;
+
+        match setup {
+            Some(setup) => {
+                content["project_setup_required"] =
+                    serde_json::to_value(setup).map_err(|e| format!("序列化失败: {}", e))?;
+            }
+            None => {
+                if let Some(obj) = content.as_object_mut() {
+                    obj.remove("project_setup_required");
+                }
+            }
+        }
+
+        let json_str = serde_json::to_string_pretty(&content)
+            .map_err(|e| format!("序列化 JSON 失败: {}", e))?;
+        std::fs::write(&path, json_str).map_err(|e| format!("写入文件失败: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::models::ProxyToken;
+    use super::super::refresh::{RefreshCoordinator, TokenRefresher};
+    use super::super::verification::ProbeResult;
+    use crate::modules::oauth::TokenResponse;
+    use futures::future::BoxFuture;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    struct StubRefresher;
+
+    impl TokenRefresher for StubRefresher {
+        fn refresh<'a>(
+            &'a self,
+            _refresh_token: &'a str,
+            _account_id: &'a str,
+        ) -> BoxFuture<'a, Result<TokenResponse, String>> {
+            Box::pin(async move {
+                Ok(TokenResponse {
+                    access_token: "fresh".to_string(),
+                    expires_in: 3600,
+                    token_type: "Bearer".to_string(),
+                    refresh_token: None,
+                })
+            })
+        }
+    }
+
+    /// 在 `onboarded` 置位前返回 SERVICE_DISABLED
+    struct OnboardingProber {
+        onboarded: AtomicBool,
+    }
+
+    impl AccountProber for OnboardingProber {
+        fn probe<'a>(&'a self, token: &'a ProxyToken, _access_token: &'a str) -> BoxFuture<'a, ProbeResult> {
+            Box::pin(async move {
+                assert!(token.project_id.is_none(), "retry must re-resolve the project");
+                if self.onboarded.load(Ordering::SeqCst) {
+                    return ProbeResult { status: VerificationStatus::Ok, detail: None, reset_time: None };
+                }
+                ProbeResult {
+                    status: VerificationStatus::ProjectMisconfigured,
+                    detail: Some(
+                        "loadCodeAssist 返回错误 403 Forbidden: {\"error\":{\"code\":403,\"message\":\"Cloud Code Private API has not been used in project 42 before or it is disabled.\",\"details\":[{\"reason\":\"SERVICE_DISABLED\",\"metadata\":{\"consumer\":\"projects/42\",\"activationUrl\":\"https://console.example/enable?project=42\"}}]}}"
+                            .to_string(),
+                    ),
+                    reset_time: None,
+                }
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_project_setup_excludes_account_until_retry_succeeds() {
+        let mut manager = TokenManager::new(std::env::temp_dir());
+        manager.refresh = Arc::new(RefreshCoordinator {
+            refresher: Some(Arc::new(StubRefresher)),
+            ..Default::default()
+        });
+        manager.tokens.insert(
+            "fresh-acc".to_string(),
+            super::super::selection::tests::synthetic_token("fresh-acc"),
+        );
+        let prober = OnboardingProber { onboarded: AtomicBool::new(false) };
+
+        let err = manager.retry_project_setup_with(&prober, "fresh-acc").await.unwrap_err();
+        assert!(err.contains("SERVICE_DISABLED"));
+        let state = manager.project_setup_state("fresh-acc").expect("state should be set");
+        assert_eq!(state.project_id.as_deref(), Some("42"));
+        assert_eq!(state.setup_url.as_deref(), Some("https://console.example/enable?project=42"));
+        assert!(manager.get_token("agent", false, None, "gemini-2.5-flash").await.is_err());
+        assert!(!manager.rate_limit_tracker.is_rate_limited("fresh-acc", None));
+
+        prober.onboarded.store(true, Ordering::SeqCst);
+        manager.retry_project_setup_with(&prober, "fresh-acc").await.unwrap();
+        assert!(manager.project_setup_state("fresh-acc").is_none());
+    }
+}
@@ -0,0 +1,494 @@
+// Remaining-Quota Estimation
+// 上游不提供每日配额余量, 这里根据观测到的 429 节奏估算:
+// 每天第一次 RESOURCE_EXHAUSTED 时记下当天已消耗的 token 作为该日的"预算样本",
+// 近 7 天的样本按天线性衰减加权平均得到日预算, 再与今日用量相减得到剩余比例。
+// 结果只是估算, 始终附带可信度; 学到的样本与今日用量持久化到 quota_estimates.json,
+// 重启后当天的燃尽进度不会清零。
+
+use super::models::ProxyToken;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Mutex;
+
+/// 样本的有效期 (天), 权重在此期间线性衰减到 0
+const DECAY_DAYS: i64 = 7;
+const STORE_FILE: &str = "quota_estimates.json";
+/// 今日用量最多每隔这么久写回一次 (秒); 学到新样本时立即写回
+const USAGE_PERSIST_INTERVAL_SECS: i64 = 60;
+
+static ESTIMATOR: Lazy<Mutex<Estimator>> = Lazy::new(|| Mutex::new(Estimator::default()));
+static STORE_PATH: Lazy<Mutex<Option<PathBuf>>> = Lazy::new(|| Mutex::new(None));
+/// 上次写回用量的时间 (unix 秒)
+static LAST_USAGE_SAVE: AtomicI64 = AtomicI64::new(0);
+
+/// 估算可信度
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EstimateConfidence {
+    Low,
+    Medium,
+    High,
+}
+
+impl EstimateConfidence {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Low => "low",
+            Self::Medium => "medium",
+            Self::High => "high",
+        }
+    }
+}
+
+/// 单个模型族的剩余配额估算 (账号负载明细中展示)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuotaEstimate {
+    pub family: String,
+    /// 估算的日预算 (token)
+    pub budget_tokens: u64,
+    /// 今日已观测到的用量 (token)
+    pub used_today: u64,
+    /// 估算剩余比例 (0 ~ 100)
+    pub remaining_pct: f64,
+    pub confidence: EstimateConfidence,
+    /// 参与估算的样本天数
+    pub samples: usize,
+    /// 预计的每日配额重置时间 (unix 秒, 配额时区次日零点)
+    pub resets_at: i64,
+    /// 例: "estimated 42% of daily quota remaining (low confidence)"
+    pub summary: String,
+}
+
+/// 配额按模型族计算 (与上游的配额分组大致对应)
+fn model_family(model: &str) -> &'static str {
+    let lower = model.to_lowercase();
+    if lower.contains("claude") {
+        "claude"
+    } else if lower.contains("image") {
+        "gemini-image"
+    } else if lower.contains("flash") {
+        "gemini-flash"
+    } else if lower.contains("gemini") {
+        "gemini-pro"
+    } else {
+        "other"
+    }
+}
+
+/// 配额时区 (默认太平洋时间, 与上游每日配额重置对齐) 的日序号
+fn today() -> i64 {
+    crate::utils::time::quota_zone().day_index(chrono::Utc::now().timestamp())
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct BudgetSample {
+    day: i64,
+    tokens: u64,
+}
+
+/// 当天的用量统计
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct DayUsage {
+    day: i64,
+    used: u64,
+    exhausted: bool,
+    /// 统计不是从当天开始的 (如中途启动), 用量偏小, 不用于学习预算
+    partial: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct FamilyState {
+    #[serde(default)]
+    samples: Vec<BudgetSample>,
+    #[serde(default)]
+    usage: Option<DayUsage>,
+}
+
+impl FamilyState {
+    fn usage_for(&mut self, day: i64) -> &mut DayUsage {
+        let usage = self.usage.get_or_insert(DayUsage {
+            day,
+            used: 0,
+            exhausted: false,
+            partial: true,
+        });
+        if usage.day != day {
+            // 跨天时前一天一直在统计, 新的一天是完整的
+            *usage = DayUsage {
+                day,
+                used: 0,
+                exhausted: false,
+                partial: false,
+            };
+        }
+        usage
+    }
+
+    fn prune(&mut self, day: i64) {
+        self.samples.retain(|s| day - s.day < DECAY_DAYS);
+    }
+
+    /// 加载时调用: 只保留今天的用量; 更早的用量之后没有持续统计, 新的一天需从不完整开始
+    fn drop_stale_usage(&mut self, day: i64) {
+        if self.usage.is_some_and(|u| u.day != day) {
+            self.usage = None;
+        }
+    }
+
+    /// 日预算的加权平均、可信度与样本数
+    fn budget(&self, day: i64) -> Option<(f64, EstimateConfidence, usize)> {
+        let weighted: Vec<(f64, f64)> = self
+            .samples
+            .iter()
+            .filter(|s| (0..DECAY_DAYS).contains(&(day - s.day)))
+            .map(|s| (1.0 - (day - s.day) as f64 / DECAY_DAYS as f64, s.tokens as f64))
+            .collect();
+        let total_weight: f64 = weighted.iter().map(|(w, _)| w).sum();
+        if weighted.is_empty() || total_weight <= 0.0 {
+            return None;
+        }
+
+        let mean = weighted.iter().map(|(w, v)| w * v).sum::<f64>() / total_weight;
+        if mean <= 0.0 {
+            return None;
+        }
+        let variance =
+            weighted.iter().map(|(w, v)| w * (v - mean).powi(2)).sum::<f64>() / total_weight;
+        let spread = variance.sqrt() / mean;
+        // 有效样本数: 旧样本权重低, 贡献也少
+        let effective =
+            total_weight.powi(2) / weighted.iter().map(|(w, _)| w * w).sum::<f64>();
+
+        let confidence = if effective >= 4.0 && spread <= 0.25 {
+            EstimateConfidence::High
+        } else if effective >= 2.0 && spread <= 0.5 {
+            EstimateConfidence::Medium
+        } else {
+            EstimateConfidence::Low
+        };
+        Some((mean, confidence, weighted.len()))
+    }
+
+    fn estimate(&self, family: &str, day: i64) -> Option<QuotaEstimate> {
+        let (budget, confidence, samples) = self.budget(day)?;
+        let (used, exhausted) = match self.usage {
+            Some(u) if u.day == day => (u.used, u.exhausted),
+            _ => (0, false),
+        };
+        let remaining_pct = if exhausted {
+            0.0
+        } else {
+            ((1.0 - used as f64 / budget) * 100.0).clamp(0.0, 100.0)
+        };
+        Some(QuotaEstimate {
+            family: family.to_string(),
+            budget_tokens: budget.round() as u64,
+            used_today: used,
+            remaining_pct,
+            confidence,
+            samples,
+            resets_at: crate::utils::time::quota_zone().next_day_start(chrono::Utc::now().timestamp()),
+            summary: format!(
+                "estimated {:.0}% of daily quota remaining ({} confidence)",
+                remaining_pct,
+                confidence.as_str()
+            ),
+        })
+    }
+}
+
+/// email -> 模型族 -> 状态
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Estimator {
+    #[serde(default)]
+    accounts: HashMap<String, HashMap<String, FamilyState>>,
+}
+
+impl Estimator {
+    fn state(&mut self, email: &str, family: &str) -> &mut FamilyState {
+        self.accounts
+            .entry(email.to_string())
+            .or_default()
+            .entry(family.to_string())
+            .or_default()
+    }
+
+    fn record_usage(&mut self, email: &str, family: &str, tokens: u64, day: i64) {
+        self.state(email, family).usage_for(day).used += tokens;
+    }
+
+    /// 记录一次配额耗尽, 当天第一次且统计完整时学习一个预算样本 (返回是否学到新样本)
+    fn record_exhausted(&mut self, email: &str, family: &str, day: i64) -> bool {
+        let state = self.state(email, family);
+        let usage = state.usage_for(day);
+        if usage.exhausted {
+            return false;
+        }
+        usage.exhausted = true;
+        if usage.partial || usage.used == 0 {
+            return false;
+        }
+        let sample = BudgetSample {
+            day,
+            tokens: usage.used,
+        };
+        state.samples.push(sample);
+        state.prune(day);
+        true
+    }
+
+    fn estimates(&self, email: &str, day: i64) -> Vec<QuotaEstimate> {
+        let mut estimates: Vec<QuotaEstimate> = self
+            .accounts
+            .get(email)
+            .map(|families| {
+                families
+                    .iter()
+                    .filter_map(|(family, state)| state.estimate(family, day))
+                    .collect()
+            })
+            .unwrap_or_default();
+        estimates.sort_by(|a, b| a.family.cmp(&b.family));
+        estimates
+    }
+
+    fn remaining_pct(&self, email: &str, family: &str, day: i64) -> Option<f64> {
+        self.accounts
+            .get(email)?
+            .get(family)?
+            .estimate(family, day)
+            .map(|e| e.remaining_pct)
+    }
+
+    /// 还有其他候选时移除估算剩余低于阈值的账号
+    fn retain_sufficient(&self, tokens: &mut Vec<ProxyToken>, family: &str, day: i64, min_remaining_pct: f64) {
+        let is_low = |t: &ProxyToken| {
+            self.remaining_pct(&t.email, family, day)
+                .is_some_and(|pct| pct < min_remaining_pct)
+        };
+        if tokens.iter().any(|t| !is_low(t)) {
+            tokens.retain(|t| !is_low(t));
+        }
+    }
+}
+
+/// 记录一次请求消耗的 token (由 ProxyMonitor 在请求结束时调用), 定期写回磁盘
+pub fn record_quota_usage(email: &str, model: &str, tokens: u64) {
+    if tokens == 0 {
+        return;
+    }
+    if let Ok(mut estimator) = ESTIMATOR.lock() {
+        estimator.record_usage(email, model_family(model), tokens, today());
+    }
+    let now = chrono::Utc::now().timestamp();
+    let last = LAST_USAGE_SAVE.load(Ordering::Relaxed);
+    if now - last >= USAGE_PERSIST_INTERVAL_SECS
+        && LAST_USAGE_SAVE
+            .compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+    {
+        save();
+    }
+}
+
+/// 记录一次配额耗尽的 429 (RESOURCE_EXHAUSTED), 学到新样本时写回磁盘
+pub(crate) fn record_quota_exhausted(email: &str, model: &str) {
+    let family = model_family(model);
+    let learned = ESTIMATOR
+        .lock()
+        .map(|mut estimator| estimator.record_exhausted(email, family, today()))
+        .unwrap_or(false);
+    if learned {
+        tracing::info!("[QuotaEstimate] Learned daily budget sample for {} ({})", email, family);
+        save();
+    }
+}
+
+/// 账号各模型族的剩余配额估算
+pub(crate) fn quota_estimates(email: &str) -> Vec<QuotaEstimate> {
+    ESTIMATOR
+        .lock()
+        .map(|estimator| estimator.estimates(email, today()))
+        .unwrap_or_default()
+}
+
+/// 估算剩余低于阈值 (百分比) 的账号只作兜底: 还有其他候选时从候选列表中移除, 无估算的账号不受影响。
+/// 在各调度模式选择账号之前调用, 粘性会话、最小负载、轮询与 P2C 都只会在剩余账号中选择
+pub(crate) fn deprioritize_low_remaining(tokens: &mut Vec<ProxyToken>, model: &str, min_remaining_pct: f64) {
+    if let Ok(estimator) = ESTIMATOR.lock() {
+        estimator.retain_sufficient(tokens, model_family(model), today(), min_remaining_pct);
+    }
+}
+
+/// 绑定持久化文件并加载已学到的样本 (同一目录只加载一次)
+pub(crate) fn attach_store(data_dir: &Path) {
+    let path = data_dir.join(STORE_FILE);
+    let Ok(mut store) = STORE_PATH.lock() else {
+        return;
+    };
+    if store.as_ref() == Some(&path) {
+        return;
+    }
+
+    let loaded = match std::fs::read_to_string(&path) {
+        Ok(content) => match serde_json::from_str::<Estimator>(&content) {
+            Ok(estimator) => estimator,
+            Err(e) => {
+                tracing::warn!("[QuotaEstimate] Ignoring unreadable {:?}: {}", path, e);
+                Estimator::default()
+            }
+        },
+        Err(_) => Estimator::default(),
+    };
+    if let Ok(mut estimator) = ESTIMATOR.lock() {
+        let day = today();
+        *estimator = loaded;
+        for state in estimator.accounts.values_mut().flat_map(|f| f.values_mut()) {
+            state.prune(day);
+            state.drop_stale_usage(day);
+        }
+    }
+    *store = Some(path);
+}
+
+fn save() {
+    let Some(path) = STORE_PATH.lock().ok().and_then(|p| p.clone()) else {
+        return;
+    };
+    let json = match ESTIMATOR.lock() {
+        Ok(estimator) => serde_json::to_string_pretty(&*estimator),
+        Err(_) => return,
+    };
+    match json {
+        Ok(json) => {
+            if let Err(e) = crate::utils::atomic_file::write_atomic(&path, json.as_bytes()) {
+                tracing::warn!("[QuotaEstimate] Failed to write {:?}: {}", path, e);
+            }
+        }
+        Err(e) => tracing::warn!("[QuotaEstimate] Failed to serialize estimates: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = 20_000;
+
+    /// 从前一天开始统计, 使 `day` 成为完整的一天
+    fn full_day(estimator: &mut Estimator, email: &str, family: &str, day: i64, used: u64) {
+        estimator.record_usage(email, family, 0, day - 1);
+        estimator.record_usage(email, family, used, day);
+    }
+
+    #[test]
+    fn test_learns_budget_and_burns_down() {
+        let mut estimator = Estimator::default();
+        full_day(&mut estimator, "a@x", "claude", DAY, 1_000_000);
+        assert!(estimator.record_exhausted("a@x", "claude", DAY));
+        // 同一天的后续 429 不再学习
+        assert!(!estimator.record_exhausted("a@x", "claude", DAY));
+
+        estimator.record_usage("a@x", "claude", 250_000, DAY + 1);
+        let estimates = estimator.estimates("a@x", DAY + 1);
+        assert_eq!(estimates.len(), 1);
+        let estimate = &estimates[0];
+        assert_eq!(estimate.budget_tokens, 1_000_000);
+        assert!((estimate.remaining_pct - 75.0).abs() < 1e-9);
+        assert_eq!(estimate.confidence, EstimateConfidence::Low);
+        assert_eq!(
+            estimate.summary,
+            "estimated 75% of daily quota remaining (low confidence)"
+        );
+
+        estimator.record_exhausted("a@x", "claude", DAY + 1);
+        assert_eq!(estimator.remaining_pct("a@x", "claude", DAY + 1), Some(0.0));
+    }
+
+    #[test]
+    fn test_partial_day_is_not_learned() {
+        let mut estimator = Estimator::default();
+        // 中途开始统计: 用量不完整, 不能当作预算
+        estimator.record_usage("b@x", "gemini-pro", 10_000, DAY);
+        assert!(!estimator.record_exhausted("b@x", "gemini-pro", DAY));
+        assert!(estimator.estimates("b@x", DAY).is_empty());
+    }
+
+    #[test]
+    fn test_samples_decay_and_raise_confidence() {
+        let mut estimator = Estimator::default();
+        for offset in 0..5 {
+            let day = DAY + offset;
+            full_day(&mut estimator, "c@x", "gemini-flash", day, 2_000_000 + offset as u64 * 10_000);
+            estimator.record_exhausted("c@x", "gemini-flash", day);
+        }
+        let estimate = &estimator.estimates("c@x", DAY + 5)[0];
+        assert_eq!(estimate.samples, 5);
+        assert_eq!(estimate.confidence, EstimateConfidence::High);
+
+        // 旧样本权重衰减, 可信度随之下降
+        let estimate = &estimator.estimates("c@x", DAY + 8)[0];
+        assert_eq!(estimate.samples, 3);
+        assert_eq!(estimate.confidence, EstimateConfidence::Medium);
+
+        // 7 天后样本全部过期
+        assert!(estimator.estimates("c@x", DAY + 4 + DECAY_DAYS).is_empty());
+    }
+
+    #[test]
+    fn test_today_usage_survives_reload() {
+        let mut estimator = Estimator::default();
+        full_day(&mut estimator, "d@x", "claude", DAY, 1_000_000);
+        estimator.record_exhausted("d@x", "claude", DAY);
+        estimator.record_usage("d@x", "claude", 400_000, DAY + 1);
+
+        // 模拟重启: 序列化后重新加载, 今日用量与完整性都保留
+        let json = serde_json::to_string(&estimator).unwrap();
+        let mut reloaded: Estimator = serde_json::from_str(&json).unwrap();
+        for state in reloaded.accounts.values_mut().flat_map(|f| f.values_mut()) {
+            state.drop_stale_usage(DAY + 1);
+        }
+        assert!((reloaded.remaining_pct("d@x", "claude", DAY + 1).unwrap() - 60.0).abs() < 1e-9);
+        reloaded.record_usage("d@x", "claude", 600_000, DAY + 1);
+        assert!(reloaded.record_exhausted("d@x", "claude", DAY + 1));
+
+        // 隔天才重启: 旧用量丢弃, 新的一天从不完整开始
+        let mut stale: Estimator = serde_json::from_str(&json).unwrap();
+        for state in stale.accounts.values_mut().flat_map(|f| f.values_mut()) {
+            state.drop_stale_usage(DAY + 3);
+        }
+        stale.record_usage("d@x", "claude", 10_000, DAY + 3);
+        assert!(!stale.record_exhausted("d@x", "claude", DAY + 3));
+    }
+
+    #[test]
+    fn test_low_remaining_accounts_are_fallback_only() {
+        use super::super::selection::tests::synthetic_token;
+
+        let mut estimator = Estimator::default();
+        full_day(&mut estimator, "low@example.com", "claude", DAY, 1_000_000);
+        estimator.record_exhausted("low@example.com", "claude", DAY);
+        estimator.record_usage("low@example.com", "claude", 950_000, DAY + 1);
+
+        // 还有其他账号时, 剩余 5% 的账号不进入候选 (各调度模式都只在剩余账号中选择)
+        let mut tokens = vec![synthetic_token("low"), synthetic_token("fresh")];
+        estimator.retain_sufficient(&mut tokens, "claude", DAY + 1, 10.0);
+        assert_eq!(tokens.iter().map(|t| t.account_id.as_str()).collect::<Vec<_>>(), ["fresh"]);
+
+        // 只剩低余量账号时仍可兜底
+        let mut tokens = vec![synthetic_token("low")];
+        estimator.retain_sufficient(&mut tokens, "claude", DAY + 1, 10.0);
+        assert_eq!(tokens.len(), 1);
+    }
+
+    #[test]
+    fn test_model_family() {
+        assert_eq!(model_family("claude-sonnet-4-5-thinking"), "claude");
+        assert_eq!(model_family("gemini-3-flash"), "gemini-flash");
+        assert_eq!(model_family("gemini-3-pro-image"), "gemini-image");
+        assert_eq!(model_family("gemini-3-pro-high"), "gemini-pro");
+    }
+}
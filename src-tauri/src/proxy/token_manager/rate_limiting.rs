@@ -20,6 +20,11 @@ impl TokenManager {
             .email_to_account_id(email)
             .unwrap_or_else(|| email.to_string());
 
+        let policy = self.rate_limit_tracker.policy();
+        if !self.should_set_limiter(&key, status, &policy) {
+            return;
+        }
+
         self.rate_limit_tracker.parse_from_error(
             &key,
             status,
@@ -30,6 +35,40 @@ impl TokenManager {
         );
     }
 
+    /// 按限流策略判断本次错误是否应设置限流锁 (5xx 需在窗口内累计达到阈值)
+    fn should_set_limiter(
+        &self,
+        account_id: &str,
+        status: u16,
+        policy: &crate::proxy::sticky_config::RateLimitPolicy,
+    ) -> bool {
+        if !policy.applies_to(status) {
+            tracing::debug!(
+                "[RateLimit] HTTP {} for {} is not a limiter status, ignored",
+                status,
+                account_id
+            );
+            return false;
+        }
+        if status >= 500
+            && !self.rate_limit_tracker.record_server_error(
+                account_id,
+                policy.server_error_threshold,
+                policy.server_error_window_secs,
+            )
+        {
+            tracing::info!(
+                "[RateLimit] HTTP {} for {} below threshold ({} within {}s), limiter not set",
+                status,
+                account_id,
+                policy.server_error_threshold,
+                policy.server_error_window_secs
+            );
+            return false;
+        }
+        true
+    }
+
     /// Mark account as rate limited (async version with real-time quota refresh)
     pub async fn mark_rate_limited_async(
         &self,
@@ -39,6 +78,19 @@ impl TokenManager {
         error_body: &str,
         model: Option<&str>,
     ) {
+        // 加权选择的 429 惩罚与熔断开关无关
+        if status == 429 {
+            super::selection::record_rate_limited(email);
+            // 只有配额耗尽用于估算日预算 (每分钟限流与容量不足不算)
+            if let Some(m) = model {
+                if crate::proxy::rate_limit::parse_rate_limit_reason(error_body)
+                    == crate::proxy::rate_limit::RateLimitReason::QuotaExhausted
+                {
+                    super::quota_estimate::record_quota_exhausted(email, m);
+                }
+            }
+        }
+
         let config = self.circuit_breaker_config.read().await.clone();
         if !config.enabled {
             return;
@@ -48,10 +100,16 @@ impl TokenManager {
             .email_to_account_id(email)
             .unwrap_or_else(|| email.to_string());
 
+        let policy = self.rate_limit_tracker.policy();
+        if !self.should_set_limiter(&account_id, status, &policy) {
+            return;
+        }
+
         let has_explicit_retry_time =
             retry_after_header.is_some() || error_body.contains("quotaResetDelay");
 
-        if has_explicit_retry_time {
+        // 5xx 是服务端故障而非配额问题, 不按配额刷新时间锁定, 只做短暂规避
+        if has_explicit_retry_time || status >= 500 {
             if let Some(m) = model {
                 tracing::debug!(
                     "账号 {} 的模型 {} 的 429 响应包含 quotaResetDelay",
@@ -130,6 +188,19 @@ impl TokenManager {
         self.rate_limit_tracker.is_rate_limited(account_id, model)
     }
 
+    /// Shortest remaining cooldown across the pool (seconds), used as the client's `Retry-After`.
+    /// None when rate limits are not enforced or some account is not cooling down.
+    pub async fn min_cooldown_secs(&self, model: Option<&str>) -> Option<u64> {
+        if !self.circuit_breaker_config.read().await.enabled {
+            return None;
+        }
+        self.tokens
+            .iter()
+            .map(|entry| self.rate_limit_tracker.get_remaining_wait(entry.key(), model))
+            .min()
+            .filter(|secs| *secs > 0)
+    }
+
     /// Get remaining wait time for rate limit reset
     #[allow(dead_code)]
     pub fn get_rate_limit_reset_seconds(&self, account_id: &str) -> Option<u64> {
@@ -156,6 +227,7 @@ impl TokenManager {
     pub fn mark_account_success(&self, email: &str, model: Option<&str>) {
         if let Some(account_id) = self.email_to_account_id(email) {
             self.rate_limit_tracker.mark_success(&account_id, model);
+            self.clear_last_error(&account_id);
         } else {
             self.rate_limit_tracker.mark_success(email, model);
         }
@@ -0,0 +1,278 @@
+// Single-Flight OAuth Token Refresh
+// 同一账号的并发刷新只发出一次请求, 其余请求等待同一结果; 失败结果短暂缓存, 避免刷新风暴触发 Google 风控
+
+use super::manager::TokenManager;
+use crate::modules::oauth::TokenResponse;
+use dashmap::DashMap;
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// 刷新失败后在该时间内直接返回缓存的错误, 不再重试
+const REFRESH_FAILURE_TTL: Duration = Duration::from_secs(5);
+
+/// 可替换的 OAuth 刷新实现 (默认调用 Google token 端点, 测试可注入)
+pub trait TokenRefresher: Send + Sync {
+    fn refresh<'a>(
+        &'a self,
+        refresh_token: &'a str,
+        account_id: &'a str,
+    ) -> BoxFuture<'a, Result<TokenResponse, String>>;
+}
+
+/// 刷新后的 access token
+#[derive(Debug, Clone)]
+pub(crate) struct RefreshedToken {
+    pub access_token: String,
+    pub expires_in: i64,
+    /// 过期时间戳 (unix 秒)
+    pub timestamp: i64,
+}
+
+/// 最近一次刷新结果 (账号负载明细中展示)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RefreshOutcome {
+    /// 完成时间 (unix 秒)
+    pub finished_at: i64,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+struct RefreshRecord {
+    outcome: RefreshOutcome,
+    finished: Instant,
+    token: Option<RefreshedToken>,
+}
+
+/// 每个账号的单飞锁、进行中的刷新与最近结果
+#[derive(Default)]
+pub(crate) struct RefreshCoordinator {
+    locks: DashMap<String, Arc<tokio::sync::Mutex<()>>>,
+    /// account_id -> 刷新开始时间 (unix 秒)
+    in_flight: DashMap<String, i64>,
+    records: DashMap<String, RefreshRecord>,
+    pub(super) refresher: Option<Arc<dyn TokenRefresher>>,
+}
+
+impl RefreshCoordinator {
+    pub fn is_in_flight(&self, account_id: &str) -> bool {
+        self.in_flight.contains_key(account_id)
+    }
+
+    pub fn last_outcome(&self, account_id: &str) -> Option<RefreshOutcome> {
+        self.records.get(account_id).map(|r| r.outcome.clone())
+    }
+
+    /// 已有未过期的刷新结果, 或失败仍在冷却期内 (含等待期间刚失败的刷新) 时, 直接复用该结果
+    fn reusable(&self, account_id: &str, arrived: Instant) -> Option<Result<RefreshedToken, String>> {
+        let record = self.records.get(account_id)?;
+        if let Some(token) = &record.token {
+            if token.timestamp - 300 > chrono::Utc::now().timestamp() {
+                return Some(Ok(token.clone()));
+            }
+        }
+        if !record.outcome.success
+            && (record.finished >= arrived || record.finished.elapsed() < REFRESH_FAILURE_TTL)
+        {
+            return Some(Err(record.outcome.error.clone().unwrap_or_default()));
+        }
+        None
+    }
+
+    fn record(&self, account_id: &str, result: &Result<RefreshedToken, String>) {
+        let outcome = RefreshOutcome {
+            finished_at: chrono::Utc::now().timestamp(),
+            success: result.is_ok(),
+            error: result.as_ref().err().cloned(),
+        };
+        self.records.insert(
+            account_id.to_string(),
+            RefreshRecord {
+                outcome,
+                finished: Instant::now(),
+                token: result.as_ref().ok().cloned(),
+            },
+        );
+    }
+}
+
+/// 标记刷新进行中, 请求被取消时也会清除标记
+struct InFlightGuard<'a> {
+    in_flight: &'a DashMap<String, i64>,
+    account_id: &'a str,
+}
+
+impl<'a> InFlightGuard<'a> {
+    fn new(in_flight: &'a DashMap<String, i64>, account_id: &'a str) -> Self {
+        in_flight.insert(account_id.to_string(), chrono::Utc::now().timestamp());
+        Self { in_flight, account_id }
+    }
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        self.in_flight.remove(self.account_id);
+    }
+}
+
+impl TokenManager {
+    /// 刷新账号的 access token (同一账号的并发调用共享一次刷新)
+    ///
+    /// 成功时同时更新内存中的 token 并写回账号文件。
+    pub(crate) async fn refresh_token_shared(
+        &self,
+        account_id: &str,
+        refresh_token: &str,
+    ) -> Result<RefreshedToken, String> {
+        let arrived = Instant::now();
+        let lock = self
+            .refresh
+            .locks
+            .entry(account_id.to_string())
+            .or_default()
+            .clone();
+        let _guard = lock.lock().await;
+
+        if let Some(result) = self.refresh.reusable(account_id, arrived) {
+            tracing::debug!("[Refresh] Reusing refresh result for {}", account_id);
+            return result;
+        }
+
+        let response = {
+            let _in_flight = InFlightGuard::new(&self.refresh.in_flight, account_id);
+            match &self.refresh.refresher {
+                Some(refresher) => refresher.refresh(refresh_token, account_id).await,
+                None => crate::modules::oauth::refresh_access_token(refresh_token, Some(account_id)).await,
+            }
+        };
+
+        let result = response.map(|token_response| {
+            let refreshed = RefreshedToken {
+                access_token: token_response.access_token.clone(),
+                expires_in: token_response.expires_in,
+                timestamp: chrono::Utc::now().timestamp() + token_response.expires_in,
+            };
+            if let Some(mut entry) = self.tokens.get_mut(account_id) {
+                entry.access_token = refreshed.access_token.clone();
+                entry.expires_in = refreshed.expires_in;
+                entry.timestamp = refreshed.timestamp;
+            }
+            (refreshed, token_response)
+        });
+
+        let result = match result {
+            Ok((refreshed, token_response)) => {
+                if let Err(e) = self.save_refreshed_token(account_id, &token_response).await {
+                    tracing::debug!("保存刷新后的 token 失败 ({}): {}", account_id, e);
+                }
+                Ok(refreshed)
+            }
+            Err(e) => Err(e),
+        };
+        self.refresh.record(account_id, &result);
+        result
+    }
+
+    /// 账号是否有进行中的 OAuth 刷新
+    pub fn refresh_in_flight(&self, account_id: &str) -> bool {
+        self.refresh.is_in_flight(account_id)
+    }
+
+    /// 账号最近一次 OAuth 刷新结果
+    pub fn last_refresh_outcome(&self, account_id: &str) -> Option<RefreshOutcome> {
+        self.refresh.last_outcome(account_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::models::ProxyToken;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct CountingRefresher {
+        calls: AtomicUsize,
+        fail: bool,
+    }
+
+    impl TokenRefresher for CountingRefresher {
+        fn refresh<'a>(
+            &'a self,
+            _refresh_token: &'a str,
+            _account_id: &'a str,
+        ) -> BoxFuture<'a, Result<TokenResponse, String>> {
+            Box::pin(async move {
+                self.calls.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                if self.fail {
+                    return Err("upstream unavailable".to_string());
+                }
+                Ok(TokenResponse {
+                    access_token: "fresh-token".to_string(),
+                    expires_in: 3600,
+                    token_type: "Bearer".to_string(),
+                    refresh_token: None,
+                })
+            })
+        }
+    }
+
+    fn expired_token(id: &str) -> ProxyToken {
+        ProxyToken {
+            access_token: "stale-token".to_string(),
+            refresh_token: "refresh".to_string(),
+            timestamp: chrono::Utc::now().timestamp() - 10,
+            ..super::super::selection::tests::synthetic_token(id)
+        }
+    }
+
+    fn manager_with_refresher(id: &str, fail: bool) -> (TokenManager, Arc<CountingRefresher>) {
+        let refresher = Arc::new(CountingRefresher {
+            calls: AtomicUsize::new(0),
+            fail,
+        });
+        let mut manager = TokenManager::new(std::env::temp_dir());
+        manager.refresh = Arc::new(RefreshCoordinator {
+            refresher: Some(refresher.clone()),
+            ..Default::default()
+        });
+        manager.tokens.insert(id.to_string(), expired_token(id));
+        (manager, refresher)
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_get_token_refreshes_once() {
+        let (manager, refresher) = manager_with_refresher("refresh-burst", false);
+
+        let leases = futures::future::join_all(
+            (0..50).map(|_| manager.get_token("agent", false, None, "gemini-2.5-flash")),
+        )
+        .await;
+
+        assert_eq!(refresher.calls.load(Ordering::SeqCst), 1);
+        for lease in leases {
+            assert_eq!(lease.unwrap().access_token, "fresh-token");
+        }
+        assert!(!manager.refresh_in_flight("refresh-burst"));
+        assert!(manager.last_refresh_outcome("refresh-burst").unwrap().success);
+    }
+
+    #[tokio::test]
+    async fn test_failed_refresh_is_not_retried_within_ttl() {
+        let (manager, refresher) = manager_with_refresher("refresh-fail", true);
+
+        for _ in 0..3 {
+            let err = manager
+                .refresh_token_shared("refresh-fail", "refresh")
+                .await
+                .unwrap_err();
+            assert_eq!(err, "upstream unavailable");
+        }
+
+        assert_eq!(refresher.calls.load(Ordering::SeqCst), 1);
+        let outcome = manager.last_refresh_outcome("refresh-fail").unwrap();
+        assert!(!outcome.success);
+        assert_eq!(outcome.error.as_deref(), Some("upstream unavailable"));
+    }
+}
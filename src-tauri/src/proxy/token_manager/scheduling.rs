@@ -11,11 +11,18 @@ impl TokenManager {
 
     /// Update scheduling configuration
     pub async fn update_sticky_config(&self, new_config: StickySessionConfig) {
+        self.rate_limit_tracker
+            .set_policy(new_config.rate_limit_policy.clone());
         let mut config = self.sticky_config.write().await;
         *config = new_config;
         tracing::debug!("Scheduling configuration updated: {:?}", *config);
     }
 
+    /// Whether the session is currently bound to an account
+    pub fn has_session_binding(&self, session_id: &str) -> bool {
+        self.session_accounts.contains_key(session_id)
+    }
+
     /// Clear session binding for a specific session
     #[allow(dead_code)]
     pub fn clear_session_binding(&self, session_id: &str) {
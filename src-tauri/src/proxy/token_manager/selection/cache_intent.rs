@@ -0,0 +1,145 @@
+// Session Cache Intent
+// 客户端以 cache_control {"ttl": "1h"} 标记长期复用的前缀时, 记录该会话的缓存意图:
+// 意图有效期内会话绑定不会因空闲过期而清除, Balance 模式下绑定账号短暂限流时也像 CacheFirst 一样原地等待,
+// 而不是立即换号 (换号会丢失 Gemini 隐式缓存)。cache_control 本身仍在发往上游前清除。
+
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// 默认的 ephemeral 缓存时长; 不超过该值的提示不视为长期缓存意图
+pub const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(300);
+/// 最多跟踪的会话数, 超出时先清理过期意图, 仍满则淘汰最早到期的会话
+const MAX_TRACKED_SESSIONS: usize = 2_000;
+/// 统计中展示的会话数
+const TOP_SESSIONS: usize = 20;
+
+#[derive(Debug, Clone, Copy)]
+struct CacheIntent {
+    ttl: Duration,
+    expires_at: Instant,
+}
+
+/// session_id -> 缓存意图 (每次带提示的请求都会续期)
+static INTENTS: Lazy<DashMap<String, CacheIntent>> = Lazy::new(DashMap::new);
+/// 因缓存意图而等待绑定账号限流结束 (而非换号) 的次数
+static HELD_BINDINGS: AtomicU64 = AtomicU64::new(0);
+
+/// 单个会话的缓存意图
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionCacheIntent {
+    pub session_id: String,
+    /// 客户端请求的缓存时长 (秒)
+    pub ttl_secs: u64,
+    /// 距意图过期的剩余时间 (秒)
+    pub expires_in_secs: u64,
+}
+
+/// 缓存意图统计 (启动以来)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CacheIntentStats {
+    pub active_sessions: u64,
+    pub held_bindings: u64,
+    pub sessions: Vec<SessionCacheIntent>,
+}
+
+/// 记录会话请求的缓存时长; 不超过默认 ephemeral 时长的提示忽略
+pub fn record_cache_intent(session_id: &str, ttl: Duration) {
+    if session_id.is_empty() || ttl <= DEFAULT_CACHE_TTL {
+        return;
+    }
+    let now = Instant::now();
+    if INTENTS.len() >= MAX_TRACKED_SESSIONS && !INTENTS.contains_key(session_id) {
+        purge_expired();
+        if INTENTS.len() >= MAX_TRACKED_SESSIONS {
+            let oldest = INTENTS
+                .iter()
+                .min_by_key(|e| e.expires_at)
+                .map(|e| e.key().clone());
+            if let Some(key) = oldest {
+                INTENTS.remove(&key);
+            }
+        }
+    }
+    let is_new = INTENTS
+        .insert(
+            session_id.to_string(),
+            CacheIntent {
+                ttl,
+                expires_at: now + ttl,
+            },
+        )
+        .is_none();
+    if is_new {
+        tracing::debug!(
+            "Cache Intent: Session {} requested {}s cache TTL",
+            session_id,
+            ttl.as_secs()
+        );
+    }
+}
+
+/// 会话是否有未过期的缓存意图
+pub(crate) fn has_cache_intent(session_id: &str) -> bool {
+    INTENTS
+        .get(session_id)
+        .is_some_and(|intent| intent.expires_at > Instant::now())
+}
+
+pub(crate) fn record_held_binding() {
+    HELD_BINDINGS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// 清除过期意图, 返回清除数量
+pub(crate) fn purge_expired() -> usize {
+    let now = Instant::now();
+    let before = INTENTS.len();
+    INTENTS.retain(|_, intent| intent.expires_at > now);
+    before.saturating_sub(INTENTS.len())
+}
+
+pub fn cache_intent_stats() -> CacheIntentStats {
+    let now = Instant::now();
+    let mut sessions: Vec<SessionCacheIntent> = INTENTS
+        .iter()
+        .filter(|e| e.expires_at > now)
+        .map(|e| SessionCacheIntent {
+            session_id: e.key().clone(),
+            ttl_secs: e.ttl.as_secs(),
+            expires_in_secs: (e.expires_at - now).as_secs(),
+        })
+        .collect();
+    let active_sessions = sessions.len() as u64;
+    sessions.sort_by(|a, b| b.expires_in_secs.cmp(&a.expires_in_secs));
+    sessions.truncate(TOP_SESSIONS);
+
+    CacheIntentStats {
+        active_sessions,
+        held_bindings: HELD_BINDINGS.load(Ordering::Relaxed),
+        sessions,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_long_ttl_hints_are_recorded() {
+        record_cache_intent("intent-short", Duration::from_secs(300));
+        assert!(!has_cache_intent("intent-short"));
+
+        record_cache_intent("intent-long", Duration::from_secs(3600));
+        assert!(has_cache_intent("intent-long"));
+        let stats = cache_intent_stats();
+        let session = stats
+            .sessions
+            .iter()
+            .find(|s| s.session_id == "intent-long")
+            .unwrap();
+        assert_eq!(session.ttl_secs, 3600);
+        assert!(session.expires_in_secs > 3500);
+    }
+}
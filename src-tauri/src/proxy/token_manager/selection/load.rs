@@ -0,0 +1,201 @@
+// Least-Loaded Selection Logic
+// 新会话绑定时按负载评分选择账号 (活跃会话 / 进行中请求 / 最近 token 吞吐)
+
+use super::super::manager::TokenManager;
+use super::super::models::ProxyToken;
+use crate::proxy::sticky_config::LoadScoreWeights;
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+/// 吞吐统计窗口
+const THROUGHPUT_WINDOW: Duration = Duration::from_secs(300);
+/// 吞吐按每 10k token 计 1 分
+const THROUGHPUT_UNIT: f64 = 10_000.0;
+
+/// email -> 最近窗口内的 (时间, token 数)
+static RECENT_TOKENS: Lazy<DashMap<String, VecDeque<(Instant, u64)>>> = Lazy::new(DashMap::new);
+
+/// 记录一次请求消耗的 token (由 ProxyMonitor 在请求结束时调用)
+pub fn record_throughput(email: &str, tokens: u64) {
+    if tokens == 0 {
+        return;
+    }
+    let now = Instant::now();
+    let mut entry = RECENT_TOKENS.entry(email.to_string()).or_default();
+    entry.push_back((now, tokens));
+    while entry
+        .front()
+        .is_some_and(|(t, _)| now.duration_since(*t) > THROUGHPUT_WINDOW)
+    {
+        entry.pop_front();
+    }
+}
+
+pub(crate) fn forget(email: &str) {
+    RECENT_TOKENS.remove(email);
+}
+
+fn recent_tokens(email: &str) -> u64 {
+    let now = Instant::now();
+    RECENT_TOKENS
+        .get(email)
+        .map(|q| {
+            q.iter()
+                .filter(|(t, _)| now.duration_since(*t) <= THROUGHPUT_WINDOW)
+                .map(|(_, n)| *n)
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/// 单个账号的负载分量
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct AccountLoad {
+    pub sessions: usize,
+    pub in_flight: usize,
+    pub recent_tokens: u64,
+}
+
+impl AccountLoad {
+    pub fn score(&self, weights: &LoadScoreWeights) -> f64 {
+        weights.sessions * self.sessions as f64
+            + weights.in_flight * self.in_flight as f64
+            + weights.throughput * self.recent_tokens as f64 / THROUGHPUT_UNIT
+    }
+}
+
+/// 账号负载明细 (get_proxy_stats 的 accounts 分区)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountLoadEntry {
+    pub account_id: String,
+    pub email: String,
+    pub sessions: usize,
+    pub in_flight: usize,
+    pub recent_tokens: u64,
+    pub score: f64,
+    /// 加权选择使用的健康分 (成功率 × 剩余配额 / 429 惩罚)
+    pub health_weight: f64,
+    /// 最近 1000 次加权选择中的占比
+    pub selection_share: f64,
+    /// 是否有进行中的 OAuth 刷新
+    #[serde(default)]
+    pub refresh_in_flight: bool,
+    /// 最近一次 OAuth 刷新结果
+    #[serde(default)]
+    pub last_refresh: Option<super::super::refresh::RefreshOutcome>,
+    /// 按 429 节奏估算的各模型族剩余日配额
+    #[serde(default)]
+    pub quota_estimates: Vec<super::super::quota_estimate::QuotaEstimate>,
+    /// 项目未开通时的引导信息 (开通链接 / 项目 ID), 期间账号不参与调度
+    #[serde(default)]
+    pub project_setup: Option<crate::models::ProjectSetupRequired>,
+    /// 服务启动以来的流中途停滞次数
+    #[serde(default)]
+    pub stalls: u64,
+}
+
+impl TokenManager {
+    /// 所有账号的负载明细 (按分数升序)
+    pub async fn account_loads(&self) -> Vec<AccountLoadEntry> {
+        let weights = self.sticky_config.read().await.load_weights.clone();
+        let tokens: Vec<ProxyToken> = self.tokens.iter().map(|e| e.value().clone()).collect();
+        let mut entries: Vec<AccountLoadEntry> = tokens
+            .iter()
+            .map(|token| {
+                let load = self.account_load(token, &weights);
+                AccountLoadEntry {
+                    account_id: token.account_id.clone(),
+                    email: token.email.clone(),
+                    sessions: load.sessions,
+                    in_flight: load.in_flight,
+                    recent_tokens: load.recent_tokens,
+                    score: load.score(&weights),
+                    health_weight: super::weighted::health_weight(token, ""),
+                    selection_share: super::weighted::selection_share(&token.email),
+                    refresh_in_flight: self.refresh_in_flight(&token.account_id),
+                    last_refresh: self.last_refresh_outcome(&token.account_id),
+                    quota_estimates: super::super::quota_estimate::quota_estimates(&token.email),
+                    project_setup: token.project_setup.clone(),
+                    stalls: self.stall_count(&token.account_id),
+                }
+            })
+            .collect();
+        entries.sort_by(|a, b| a.score.total_cmp(&b.score).then_with(|| a.email.cmp(&b.email)));
+        entries
+    }
+
+    /// 账号当前负载: 绑定的活跃会话数 + 进行中请求数 + 最近吞吐
+    pub(crate) fn account_load(&self, token: &ProxyToken, weights: &LoadScoreWeights) -> AccountLoad {
+        let window = Duration::from_secs(weights.active_session_secs);
+        let sessions = self
+            .session_accounts
+            .iter()
+            .filter(|e| e.value().0 == token.account_id && e.value().1.elapsed() <= window)
+            .count();
+        let in_flight = self
+            .active_requests
+            .get(&token.account_id)
+            .map(|c| c.load(Ordering::SeqCst))
+            .unwrap_or(0);
+        AccountLoad {
+            sessions,
+            in_flight,
+            recent_tokens: recent_tokens(&token.email),
+        }
+    }
+
+    /// 为尚未绑定的新会话选择负载最低的可用账号并绑定
+    /// 分数相同时保持 tokens_snapshot 的优先级顺序
+    pub(crate) async fn select_least_loaded(
+        &self,
+        tokens_snapshot: &[ProxyToken],
+        attempted: &HashSet<String>,
+        normalized_target: &str,
+        quota_protection_enabled: bool,
+        session_id: &str,
+        weights: &LoadScoreWeights,
+    ) -> Option<ProxyToken> {
+        let mut best: Option<(&ProxyToken, AccountLoad, f64)> = None;
+
+        for candidate in tokens_snapshot {
+            if attempted.contains(&candidate.account_id) {
+                continue;
+            }
+            if quota_protection_enabled && candidate.protected_models.contains(normalized_target) {
+                continue;
+            }
+            if self
+                .is_rate_limited(&candidate.account_id, Some(normalized_target))
+                .await
+            {
+                continue;
+            }
+
+            let load = self.account_load(candidate, weights);
+            let score = load.score(weights);
+            if best.as_ref().is_none_or(|(_, _, s)| score < *s) {
+                best = Some((candidate, load, score));
+            }
+        }
+
+        let (token, load, score) = best?;
+        self.session_accounts.insert(
+            session_id.to_string(),
+            (token.account_id.clone(), Instant::now()),
+        );
+        tracing::debug!(
+            "Least-Loaded: Bound session {} to {} (score {:.2}: sessions={}, in_flight={}, recent_tokens={})",
+            session_id,
+            token.email,
+            score,
+            load.sessions,
+            load.in_flight,
+            load.recent_tokens
+        );
+        Some(token.clone())
+    }
+}
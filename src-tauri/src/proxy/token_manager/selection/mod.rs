@@ -3,15 +3,40 @@
 
 mod scoring;
 mod sticky;
+mod cache_intent;
 mod round_robin;
 mod token_ops;
 mod p2c;
+mod load;
+mod weighted;
+mod pacing;
+mod priority;
+
+pub use cache_intent::{cache_intent_stats, record_cache_intent, CacheIntentStats};
+pub(crate) use cache_intent::{has_cache_intent, purge_expired as purge_expired_cache_intents};
+pub use load::{record_throughput, AccountLoadEntry};
+pub use pacing::{pacing_stats, PacingStats};
+pub use weighted::{record_rate_limited, record_request_outcome, record_stall};
 
 use super::manager::TokenManager;
-use super::models::{ProxyToken, TokenLease};
+use super::models::{ProxyToken, RequestPriority, TokenLease};
+use pacing::PaceDecision;
 use std::collections::HashSet;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
+/// 清理账号在全局选择状态 (健康信号 / 吞吐 / 限速槽) 中的记录
+pub(super) fn forget_account(account_id: &str, email: &str) {
+    weighted::forget(email);
+    load::forget(email);
+    pacing::forget(account_id);
+}
+
+/// 是否参与 60s 锁定 / last_used 记录
+/// 图片生成、Embeddings 与 Layer-3 摘要等辅助请求不应改变会话的账号锁定
+pub(super) fn tracks_last_used(quota_group: &str) -> bool {
+    !matches!(quota_group, "image_gen" | "embed" | "context_summary")
+}
+
 impl TokenManager {
     /// Get a token with timeout protection
     pub async fn get_token(
@@ -20,13 +45,68 @@ impl TokenManager {
         force_rotate: bool,
         session_id: Option<&str>,
         target_model: &str,
+    ) -> Result<TokenLease, String> {
+        self.get_token_with_priority(quota_group, force_rotate, session_id, target_model, RequestPriority::Normal)
+            .await
+    }
+
+    /// Get a token with a scheduling priority (e.g. from the client's `service_tier`)
+    ///
+    /// Low-priority requests avoid accounts serving active sessions, never bind a session,
+    /// and tolerate longer pacing / acquisition waits when the pool is tight.
+    pub async fn get_token_with_priority(
+        &self,
+        quota_group: &str,
+        force_rotate: bool,
+        session_id: Option<&str>,
+        target_model: &str,
+        priority: RequestPriority,
     ) -> Result<TokenLease, String> {
         // [FIX] Timeout for deadlock detection - reduced from 120s to 5s
+        const TOKEN_ACQUISITION_TIMEOUT_SECS: u64 = 5;
+        // 低优先级请求可以排队等待
+        const LOW_PRIORITY_ACQUISITION_TIMEOUT_SECS: u64 = 30;
+        let timeout_secs = match priority {
+            RequestPriority::Normal => TOKEN_ACQUISITION_TIMEOUT_SECS,
+            RequestPriority::Low => LOW_PRIORITY_ACQUISITION_TIMEOUT_SECS,
+        };
+        let timeout_duration = std::time::Duration::from_secs(timeout_secs);
+        match tokio::time::timeout(
+            timeout_duration,
+            self.get_token_internal(quota_group, force_rotate, session_id, target_model, None, priority),
+        )
+        .await
+        {
+            Ok(result) => result,
+            Err(_) => Err(format!(
+                "Token acquisition timeout ({}s) - system too busy or deadlock detected",
+                timeout_secs
+            )),
+        }
+    }
+
+    /// Get a token for an auxiliary request (e.g. Layer-3 `context_summary`),
+    /// preferring any account other than `exclude_account_id` so the auxiliary
+    /// call does not consume the session account's budget.
+    /// Falls back to the excluded account when it is the only one available.
+    pub async fn get_token_excluding(
+        &self,
+        quota_group: &str,
+        target_model: &str,
+        exclude_account_id: &str,
+    ) -> Result<TokenLease, String> {
         const TOKEN_ACQUISITION_TIMEOUT_SECS: u64 = 5;
         let timeout_duration = std::time::Duration::from_secs(TOKEN_ACQUISITION_TIMEOUT_SECS);
         match tokio::time::timeout(
             timeout_duration,
-            self.get_token_internal(quota_group, force_rotate, session_id, target_model),
+            self.get_token_internal(
+                quota_group,
+                true,
+                None,
+                target_model,
+                Some(exclude_account_id),
+                RequestPriority::Normal,
+            ),
         )
         .await
         {
@@ -38,6 +118,32 @@ impl TokenManager {
         }
     }
 
+    /// Emails of the lowest-priority accounts usable for `target_model`
+    /// (filtered like regular selection, not rate-limited), lowest priority first.
+    /// Used by background warm-ups so they never compete with the preferred accounts.
+    pub async fn low_priority_accounts(&self, target_model: &str) -> Vec<String> {
+        let mut tokens_snapshot: Vec<ProxyToken> =
+            self.tokens.iter().map(|e| e.value().clone()).collect();
+        let normalized_target =
+            crate::proxy::common::model_mapping::normalize_to_standard_id(target_model)
+                .unwrap_or_else(|| target_model.to_string());
+        let cb_enabled = self.circuit_breaker_config.read().await.enabled;
+
+        self.filter_tokens(&mut tokens_snapshot, target_model, &normalized_target, cb_enabled);
+        self.sort_tokens(&mut tokens_snapshot);
+
+        let mut available = Vec::new();
+        for token in tokens_snapshot {
+            if !self.is_rate_limited(&token.account_id, Some(&normalized_target)).await {
+                available.push(token.email);
+            }
+        }
+
+        // 只使用优先级较低的后半部分账号
+        let keep = available.len().div_ceil(2);
+        available.into_iter().rev().take(keep).collect()
+    }
+
     /// Internal token selection logic
     async fn get_token_internal(
         &self,
@@ -45,6 +151,8 @@ impl TokenManager {
         force_rotate: bool,
         session_id: Option<&str>,
         target_model: &str,
+        exclude_account_id: Option<&str>,
+        priority: RequestPriority,
     ) -> Result<TokenLease, String> {
         // [FIX] Process pending reload accounts from quota protection
         let pending_accounts = crate::proxy::server::take_pending_reload_accounts();
@@ -73,7 +181,10 @@ impl TokenManager {
 
         // ===== [FIX #820] Fixed Account Mode: Prioritize preferred account =====
         if let Some(token_lease) = self.try_preferred_account(&tokens_snapshot, &normalized_target, quota_protection_enabled).await {
-            return Ok(token_lease);
+            let excluded = exclude_account_id == Some(token_lease.account_id.as_str()) && total > 1;
+            if !excluded {
+                return Ok(token_lease);
+            }
         }
         // ===== [END FIX #820] =====
 
@@ -90,6 +201,13 @@ impl TokenManager {
             ));
         }
 
+        // Avoid the excluded account (e.g. the session's sticky account) when others remain
+        if let Some(excluded_id) = exclude_account_id {
+            if tokens_snapshot.iter().any(|t| t.account_id != excluded_id) {
+                tokens_snapshot.retain(|t| t.account_id != excluded_id);
+            }
+        }
+
         // Sort tokens by priority
         self.sort_tokens(&mut tokens_snapshot);
 
@@ -125,8 +243,13 @@ impl TokenManager {
         // Apply selected mode filtering
         self.apply_selected_mode_filter(&mut tokens_snapshot, target_model, &normalized_target, &scheduling)?;
 
+        // 估算剩余日配额偏低的账号只作兜底 (在所有调度模式的选择之前移出候选)
+        if let Some(min_pct) = scheduling.quota_estimate_min_remaining_pct {
+            super::quota_estimate::deprioritize_low_remaining(&mut tokens_snapshot, &normalized_target, min_pct);
+        }
+
         let total = tokens_snapshot.len();
-        let last_used_account_id = if quota_group != "image_gen" {
+        let last_used_account_id = if tracks_last_used(quota_group) {
             let last_used = self.last_used_account.lock().await;
             last_used.clone()
         } else {
@@ -137,12 +260,28 @@ impl TokenManager {
         let mut last_error: Option<String> = None;
         let mut need_update_last_used: Option<(String, std::time::Instant)> = None;
 
+        let low_priority = priority == RequestPriority::Low;
+
         for attempt in 0..total {
             let rotate = force_rotate || attempt > 0;
             let mut target_token: Option<ProxyToken> = None;
 
+            // 低优先级: 不使用/建立会话绑定, 选未满载账号中负载最高且不服务活跃会话的
+            if low_priority {
+                target_token = self
+                    .select_low_priority(
+                        &tokens_snapshot,
+                        &attempted,
+                        &normalized_target,
+                        quota_protection_enabled,
+                        &scheduling.load_weights,
+                    )
+                    .await;
+            }
+
             // Sticky session handling
             if !rotate
+                && !low_priority
                 && session_id.is_some()
                 && scheduling.mode != crate::proxy::sticky_config::SchedulingMode::PerformanceFirst
             {
@@ -158,10 +297,34 @@ impl TokenManager {
                     .await;
             }
 
+            // 新会话: 绑定到负载最低的账号
+            if target_token.is_none()
+                && !rotate
+                && !low_priority
+                && scheduling.mode != crate::proxy::sticky_config::SchedulingMode::PerformanceFirst
+            {
+                if let Some(sid) = session_id.filter(|s| !self.session_accounts.contains_key(*s)) {
+                    target_token = self
+                        .select_least_loaded(
+                            &tokens_snapshot,
+                            &attempted,
+                            &normalized_target,
+                            quota_protection_enabled,
+                            sid,
+                            &scheduling.load_weights,
+                        )
+                        .await;
+                    if let Some(t) = &target_token {
+                        need_update_last_used = Some((t.account_id.clone(), std::time::Instant::now()));
+                    }
+                }
+            }
+
             // 60s lock handling
             if target_token.is_none()
                 && !rotate
-                && quota_group != "image_gen"
+                && !low_priority
+                && tracks_last_used(quota_group)
                 && scheduling.mode != crate::proxy::sticky_config::SchedulingMode::PerformanceFirst
             {
                 target_token = self.try_60s_lock(
@@ -207,6 +370,16 @@ impl TokenManager {
                 }
             };
 
+            // 最小请求间隔: 短等待原地延迟, 长等待换到其他账号 (最后一个候选与低优先级请求只能等待)
+            match self.pace_lease(&token.account_id, &scheduling, low_priority || attempt + 1 == total) {
+                PaceDecision::Ready => {}
+                PaceDecision::Delay(wait) => tokio::time::sleep(wait).await,
+                PaceDecision::Spill => {
+                    attempted.insert(token.account_id.clone());
+                    continue;
+                }
+            }
+
             // Refresh token if needed
             if let Err(e) = self.try_refresh_token(&mut token, &mut attempted, &mut last_error, quota_group, &last_used_account_id, &mut need_update_last_used).await {
                 if e == "continue" {
@@ -228,7 +401,7 @@ impl TokenManager {
 
             // Update last used if needed
             if let Some((new_account_id, new_time)) = need_update_last_used {
-                if quota_group != "image_gen" {
+                if tracks_last_used(quota_group) {
                     let mut last_used = self.last_used_account.lock().await;
                     if new_account_id.is_empty() {
                         *last_used = None;
@@ -286,7 +459,7 @@ impl TokenManager {
                         .protected_models
                         .contains(normalized_target);
 
-                if !is_rate_limited && !is_quota_protected {
+                if !is_rate_limited && !is_quota_protected && preferred_token.project_setup.is_none() {
                     tracing::info!(
                         "🔒 [FIX #820] Using preferred account: {} (fixed mode)",
                         preferred_token.email
@@ -298,19 +471,11 @@ impl TokenManager {
                     let now = chrono::Utc::now().timestamp();
                     if now >= token.timestamp - 300 {
                         tracing::debug!("Preferred account {} token expiring, refreshing...", token.email);
-                        // [FIX #1583] Pass account_id for proper context
-                        match crate::modules::oauth::refresh_access_token(&token.refresh_token, Some(&token.account_id)).await {
-                            Ok(token_response) => {
-                                token.access_token = token_response.access_token.clone();
-                                token.expires_in = token_response.expires_in;
-                                token.timestamp = now + token_response.expires_in;
-
-                                if let Some(mut entry) = self.tokens.get_mut(&token.account_id) {
-                                    entry.access_token = token.access_token.clone();
-                                    entry.expires_in = token.expires_in;
-                                    entry.timestamp = token.timestamp;
-                                }
-                                let _ = self.save_refreshed_token(&token.account_id, &token_response).await;
+                        match self.refresh_token_shared(&token.account_id, &token.refresh_token).await {
+                            Ok(refreshed) => {
+                                token.access_token = refreshed.access_token;
+                                token.expires_in = refreshed.expires_in;
+                                token.timestamp = refreshed.timestamp;
                             }
                             Err(e) => {
                                 tracing::warn!("Preferred account token refresh failed: {}", e);
@@ -370,6 +535,12 @@ impl TokenManager {
                         }
                     };
 
+                    // 固定账号模式无法换号, 限速只做延迟
+                    let scheduling = self.sticky_config.read().await.clone();
+                    if let PaceDecision::Delay(wait) = self.pace_lease(&token.account_id, &scheduling, true) {
+                        tokio::time::sleep(wait).await;
+                    }
+
                     // Increment active requests
                     self.active_requests
                         .entry(token.account_id.clone())
@@ -446,6 +617,15 @@ impl TokenManager {
                 return false;
             }
 
+            // 项目未开通: 用户完成设置并重试前不参与调度 (不计入限流)
+            if t.project_setup.is_some() {
+                tracing::info!(
+                    "  ⛔ {} - SKIP: Project setup required",
+                    t.email
+                );
+                return false;
+            }
+
             // [FIX] Validation blocked check (VALIDATION_REQUIRED temporary block)
             if t.validation_blocked {
                 let now = chrono::Utc::now().timestamp();
@@ -689,6 +869,19 @@ impl TokenManager {
     ) -> Option<ProxyToken> {
         use crate::proxy::sticky_config::SchedulingMode;
 
+        if scheduling.mode == SchedulingMode::PerformanceFirst && scheduling.performance_weighted {
+            return self
+                .select_weighted(
+                    tokens_snapshot,
+                    attempted,
+                    normalized_target,
+                    quota_protection_enabled,
+                    scheduling.weighted_seed,
+                    need_update_last_used,
+                )
+                .await;
+        }
+
         if scheduling.mode == SchedulingMode::P2C {
             // Pre-filter rate limited accounts for P2C (async context)
             let mut available_for_p2c: Vec<ProxyToken> = Vec::new();
@@ -790,3 +983,260 @@ impl TokenManager {
         }
     }
 }
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+
+    pub(in crate::proxy::token_manager) fn synthetic_token(id: &str) -> ProxyToken {
+        ProxyToken {
+            account_id: id.to_string(),
+            access_token: format!("token-{}", id),
+            refresh_token: String::new(),
+            expires_in: 3600,
+            timestamp: chrono::Utc::now().timestamp() + 3600,
+            email: format!("{}@example.com", id),
+            account_path: std::env::temp_dir().join(format!("{}.json", id)),
+            project_id: Some("test-project".to_string()),
+            subscription_tier: None,
+            remaining_quota: None,
+            protected_models: HashSet::new(),
+            health_score: 1.0,
+            model_quotas: Default::default(),
+            verification_needed: false,
+            verification_url: None,
+            reset_time: None,
+            validation_blocked: false,
+            validation_blocked_until: 0,
+            is_forbidden: false,
+            project_setup: None,
+            priority: 0,
+        }
+    }
+
+    fn manager_with(ids: &[&str]) -> TokenManager {
+        let manager = TokenManager::new(std::env::temp_dir());
+        for id in ids {
+            manager.tokens.insert(id.to_string(), synthetic_token(id));
+        }
+        manager
+    }
+
+    #[test]
+    fn test_auxiliary_requests_do_not_track_last_used() {
+        assert!(tracks_last_used("agent"));
+        assert!(!tracks_last_used("image_gen"));
+        assert!(!tracks_last_used("embed"));
+        assert!(!tracks_last_used("context_summary"));
+    }
+
+    #[test]
+    fn test_sort_tokens_respects_user_priority() {
+        let manager = TokenManager::new(std::env::temp_dir());
+        let ultra = ProxyToken {
+            subscription_tier: Some("ULTRA".to_string()),
+            ..synthetic_token("prio-ultra")
+        };
+        let preferred = ProxyToken {
+            subscription_tier: Some("FREE".to_string()),
+            priority: 5,
+            ..synthetic_token("prio-free")
+        };
+        let mut tokens = vec![ultra, preferred];
+        manager.sort_tokens(&mut tokens);
+        assert_eq!(tokens[0].account_id, "prio-free");
+    }
+
+    #[tokio::test]
+    async fn test_get_token_excluding_prefers_other_account() {
+        let manager = manager_with(&["session-acc", "other-acc"]);
+
+        for _ in 0..4 {
+            let lease = manager
+                .get_token_excluding("context_summary", "gemini-2.5-flash", "session-acc")
+                .await
+                .unwrap();
+            assert_eq!(lease.account_id, "other-acc");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_token_excluding_falls_back_when_pool_is_single() {
+        let manager = manager_with(&["session-acc"]);
+
+        let lease = manager
+            .get_token_excluding("context_summary", "gemini-2.5-flash", "session-acc")
+            .await
+            .unwrap();
+        assert_eq!(lease.account_id, "session-acc");
+    }
+
+    #[tokio::test]
+    async fn test_new_sessions_spread_across_least_loaded_accounts() {
+        let ids = ["load-a", "load-b", "load-c", "load-d"];
+        let manager = manager_with(&ids);
+
+        // 模拟 12 个新会话, 每个会话首个请求保持进行中
+        let mut leases = Vec::new();
+        for i in 0..12 {
+            let sid = format!("sim-session-{}", i);
+            let lease = manager
+                .get_token("agent", false, Some(&sid), "gemini-2.5-flash")
+                .await
+                .unwrap();
+            leases.push(lease);
+        }
+
+        let counts: Vec<usize> = ids
+            .iter()
+            .map(|id| {
+                manager
+                    .session_accounts
+                    .iter()
+                    .filter(|e| e.value().0 == *id)
+                    .count()
+            })
+            .collect();
+        assert_eq!(counts.iter().sum::<usize>(), 12);
+        assert!(counts.iter().all(|c| c.abs_diff(3) <= 1), "uneven spread: {:?}", counts);
+    }
+
+    #[tokio::test]
+    async fn test_low_priority_avoids_session_accounts_and_prefers_loaded() {
+        let manager = manager_with(&["prio-session", "prio-busy", "prio-idle"]);
+        manager.session_accounts.insert(
+            "live-session".to_string(),
+            ("prio-session".to_string(), std::time::Instant::now()),
+        );
+        manager.tokens.get_mut("prio-busy").unwrap().subscription_tier = Some("pro".to_string());
+        manager
+            .active_requests
+            .insert("prio-busy".to_string(), AtomicUsize::new(1));
+
+        let lease = manager
+            .get_token_with_priority("agent", false, Some("batch-session"), "gemini-2.5-flash", RequestPriority::Low)
+            .await
+            .unwrap();
+        assert_eq!(lease.account_id, "prio-busy");
+        // 低优先级请求不建立会话绑定
+        assert!(!manager.session_accounts.contains_key("batch-session"));
+    }
+
+    #[tokio::test]
+    async fn test_weighted_selection_distribution_with_seed() {
+        let ids = ["wsel-a", "wsel-b", "wsel-c"];
+        let manager = manager_with(&ids);
+        // 健康分按剩余配额: 1.0 / 0.5 / 0.25
+        for (id, quota) in ids.iter().zip([100, 50, 25]) {
+            manager.tokens.get_mut(*id).unwrap().remaining_quota = Some(quota);
+        }
+        let snapshot: Vec<ProxyToken> = ids
+            .iter()
+            .map(|id| manager.tokens.get(*id).unwrap().clone())
+            .collect();
+
+        let run = |seed: u64, n: usize| {
+            let manager = &manager;
+            let snapshot = &snapshot;
+            async move {
+                let mut picks = Vec::with_capacity(n);
+                for _ in 0..n {
+                    let mut need_update = None;
+                    let token = manager
+                        .select_weighted(snapshot, &HashSet::new(), "gemini-3-flash", false, Some(seed), &mut need_update)
+                        .await
+                        .unwrap();
+                    picks.push(token.account_id);
+                }
+                picks
+            }
+        };
+
+        let picks = run(7, 10_000).await;
+        let expected = [1.0 / 1.75, 0.5 / 1.75, 0.25 / 1.75];
+        for (id, want) in ids.iter().zip(expected) {
+            let share = picks.iter().filter(|p| p == id).count() as f64 / picks.len() as f64;
+            assert!((share - want).abs() < 0.02, "{} share {:.3} expected {:.3}", id, share, want);
+        }
+
+        // 最近选择分布在账号负载明细中可见
+        let loads = manager.account_loads().await;
+        let a = loads.iter().find(|e| e.account_id == "wsel-a").unwrap();
+        assert_eq!(a.health_weight, 1.0);
+        assert!((a.selection_share - expected[0]).abs() < 0.06);
+
+        // 相同种子重新开始时序列可复现
+        let first = run(11, 200).await;
+        run(12, 1).await;
+        let second = run(11, 200).await;
+        assert_eq!(first, second);
+    }
+
+    #[tokio::test]
+    async fn test_min_cooldown_across_pool() {
+        use crate::proxy::rate_limit::RateLimitReason;
+        use std::time::{Duration, SystemTime};
+
+        let manager = manager_with(&["cool-a", "cool-b"]);
+        let lock = |id: &str, secs: u64, model: Option<&str>| {
+            manager.rate_limit_tracker.set_lockout_until(
+                id,
+                SystemTime::now() + Duration::from_secs(secs),
+                RateLimitReason::RateLimitExceeded,
+                model.map(str::to_string),
+            );
+        };
+
+        lock("cool-a", 120, None);
+        // 仍有账号可用时不给出 Retry-After
+        assert_eq!(manager.min_cooldown_secs(Some("gemini-3-flash")).await, None);
+
+        lock("cool-b", 45, Some("gemini-3-flash"));
+        let wait = manager.min_cooldown_secs(Some("gemini-3-flash")).await.unwrap();
+        assert!((40..=45).contains(&wait), "wait {}", wait);
+        // 模型级冷却不影响其他模型
+        assert_eq!(manager.min_cooldown_secs(Some("gemini-3-pro-high")).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_cache_intent_holds_binding_through_short_rate_limit() {
+        use crate::proxy::rate_limit::RateLimitReason;
+        use std::time::{Duration, SystemTime};
+
+        let manager = manager_with(&["intent-bound", "intent-other"]);
+        let bind_and_limit = |sid: &str| {
+            manager.session_accounts.insert(
+                sid.to_string(),
+                ("intent-bound".to_string(), std::time::Instant::now()),
+            );
+            manager.rate_limit_tracker.set_lockout_until(
+                "intent-bound",
+                SystemTime::now() + Duration::from_millis(1500),
+                RateLimitReason::RateLimitExceeded,
+                None,
+            );
+        };
+        let bound_to = |sid: &str| manager.session_accounts.get(sid).unwrap().0.clone();
+
+        // 无缓存意图: Balance 模式立即换号
+        bind_and_limit("intent-plain");
+        let lease = manager
+            .get_token("agent", false, Some("intent-plain"), "gemini-2.5-flash")
+            .await
+            .unwrap();
+        assert_eq!(lease.account_id, "intent-other");
+        assert_eq!(bound_to("intent-plain"), "intent-other");
+        drop(lease);
+
+        // 1h 缓存意图: 等待限流结束, 保持原绑定
+        record_cache_intent("intent-hinted", Duration::from_secs(3600));
+        bind_and_limit("intent-hinted");
+        let lease = manager
+            .get_token("agent", false, Some("intent-hinted"), "gemini-2.5-flash")
+            .await
+            .unwrap();
+        assert_eq!(lease.account_id, "intent-bound");
+        assert_eq!(bound_to("intent-hinted"), "intent-bound");
+        assert!(cache_intent_stats().held_bindings >= 1);
+    }
+}
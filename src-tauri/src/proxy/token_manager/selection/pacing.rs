@@ -0,0 +1,145 @@
+// Per-Account Request Pacing
+// 同一账号的新租约之间保持最小间隔, 避免同一秒内的突发请求触发每分钟限流
+// 只在 get_token 分配新租约时生效, 已租出的请求 (包括其流式响应) 不受影响
+
+use super::super::manager::TokenManager;
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// account_id -> 下一次允许发出新租约的时间 (已预留的时间槽)
+static NEXT_SLOT: Lazy<DashMap<String, Instant>> = Lazy::new(DashMap::new);
+
+static PACED_DELAYED: AtomicU64 = AtomicU64::new(0);
+static PACED_SPILLED: AtomicU64 = AtomicU64::new(0);
+
+/// 限速统计 (启动以来)
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct PacingStats {
+    /// 原地延迟后发出的请求数
+    pub delayed: u64,
+    /// 因等待过长而换到其他账号的请求数
+    pub spilled: u64,
+}
+
+pub fn pacing_stats() -> PacingStats {
+    PacingStats {
+        delayed: PACED_DELAYED.load(Ordering::Relaxed),
+        spilled: PACED_SPILLED.load(Ordering::Relaxed),
+    }
+}
+
+pub(crate) fn forget(account_id: &str) {
+    NEXT_SLOT.remove(account_id);
+}
+
+/// 一次租约的限速结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PaceDecision {
+    /// 无需等待
+    Ready,
+    /// 已预留时间槽, 等待后发出
+    Delay(Duration),
+    /// 等待超过阈值, 应换到其他账号
+    Spill,
+}
+
+/// 为账号预留下一个时间槽
+///
+/// 并发请求依次预留 `interval` 间隔的时间槽, 因此同一秒内的 4 个请求会被摊开而不是同时发出。
+/// `force` 为 true 时 (没有其他可换的账号) 总是延迟而不换号。
+fn reserve_slot(account_id: &str, interval: Duration, max_delay: Duration, force: bool, now: Instant) -> PaceDecision {
+    if interval.is_zero() {
+        return PaceDecision::Ready;
+    }
+
+    let mut slot = NEXT_SLOT.entry(account_id.to_string()).or_insert(now);
+    let start = (*slot).max(now);
+    let wait = start - now;
+    if !wait.is_zero() && wait > max_delay && !force {
+        return PaceDecision::Spill;
+    }
+
+    *slot = start + interval;
+    if wait.is_zero() {
+        PaceDecision::Ready
+    } else {
+        PaceDecision::Delay(wait)
+    }
+}
+
+impl TokenManager {
+    /// 新租约的限速判定 (min_request_interval_ms 为 0 时直接放行)
+    pub(crate) fn pace_lease(
+        &self,
+        account_id: &str,
+        scheduling: &crate::proxy::sticky_config::StickySessionConfig,
+        force: bool,
+    ) -> PaceDecision {
+        let interval = Duration::from_millis(scheduling.min_interval_for(account_id));
+        let max_delay = Duration::from_millis(scheduling.pacing_max_delay_ms);
+        let decision = reserve_slot(account_id, interval, max_delay, force, Instant::now());
+
+        match decision {
+            PaceDecision::Ready => {}
+            PaceDecision::Delay(wait) => {
+                PACED_DELAYED.fetch_add(1, Ordering::Relaxed);
+                tracing::debug!("[Pacing] Delaying lease on {} by {}ms", account_id, wait.as_millis());
+            }
+            PaceDecision::Spill => {
+                PACED_SPILLED.fetch_add(1, Ordering::Relaxed);
+                tracing::debug!("[Pacing] Spilling request away from {}", account_id);
+            }
+        }
+        decision
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_burst_is_spread_across_slots() {
+        let now = Instant::now();
+        let interval = Duration::from_millis(500);
+        let max_delay = Duration::from_millis(1000);
+        let account = "pacing-burst";
+
+        assert_eq!(reserve_slot(account, interval, max_delay, false, now), PaceDecision::Ready);
+        assert_eq!(
+            reserve_slot(account, interval, max_delay, false, now),
+            PaceDecision::Delay(Duration::from_millis(500))
+        );
+        assert_eq!(
+            reserve_slot(account, interval, max_delay, false, now),
+            PaceDecision::Delay(Duration::from_millis(1000))
+        );
+        // 第 4 个请求需等待 1.5s, 超过阈值 -> 换号, 且不占用时间槽
+        assert_eq!(reserve_slot(account, interval, max_delay, false, now), PaceDecision::Spill);
+        assert_eq!(
+            reserve_slot(account, interval, max_delay, true, now),
+            PaceDecision::Delay(Duration::from_millis(1500))
+        );
+
+        // 间隔过后恢复正常
+        assert_eq!(
+            reserve_slot(account, interval, max_delay, false, now + Duration::from_secs(5)),
+            PaceDecision::Ready
+        );
+    }
+
+    #[test]
+    fn test_zero_interval_disables_pacing() {
+        let now = Instant::now();
+        for _ in 0..10 {
+            assert_eq!(
+                reserve_slot("pacing-disabled", Duration::ZERO, Duration::ZERO, false, now),
+                PaceDecision::Ready
+            );
+        }
+        assert!(!NEXT_SLOT.contains_key("pacing-disabled"));
+    }
+}
@@ -0,0 +1,68 @@
+// Low-Priority Selection Logic
+// service_tier=standard_only 等可排队请求: 避开正在服务活跃会话的账号,
+// 在未满载的账号中选择负载最高的, 把空闲账号留给延迟敏感的会话; 不绑定会话
+
+use super::super::manager::TokenManager;
+use super::super::models::ProxyToken;
+use super::scoring::concurrency_limit;
+use crate::proxy::sticky_config::LoadScoreWeights;
+use std::collections::HashSet;
+
+impl TokenManager {
+    /// 为低优先级请求选择账号, 没有未满载的可用账号时返回 None (交给常规选择)
+    pub(crate) async fn select_low_priority(
+        &self,
+        tokens_snapshot: &[ProxyToken],
+        attempted: &HashSet<String>,
+        normalized_target: &str,
+        quota_protection_enabled: bool,
+        weights: &LoadScoreWeights,
+    ) -> Option<ProxyToken> {
+        // (账号, 是否没有活跃会话, 负载分)
+        let mut best: Option<(&ProxyToken, bool, f64)> = None;
+
+        for candidate in tokens_snapshot {
+            if attempted.contains(&candidate.account_id) {
+                continue;
+            }
+            if quota_protection_enabled && candidate.protected_models.contains(normalized_target) {
+                continue;
+            }
+            if self
+                .is_rate_limited(&candidate.account_id, Some(normalized_target))
+                .await
+            {
+                continue;
+            }
+
+            let load = self.account_load(candidate, weights);
+            if load.in_flight >= concurrency_limit(&candidate.subscription_tier) {
+                continue;
+            }
+            let idle_sessions = load.sessions == 0;
+            let score = load.score(weights);
+            let better = match &best {
+                None => true,
+                Some((_, best_idle, best_score)) => {
+                    if idle_sessions != *best_idle {
+                        idle_sessions
+                    } else {
+                        score > *best_score
+                    }
+                }
+            };
+            if better {
+                best = Some((candidate, idle_sessions, score));
+            }
+        }
+
+        let (token, idle_sessions, score) = best?;
+        tracing::debug!(
+            "Low-Priority: Selected {} (score {:.2}, serving sessions: {})",
+            token.email,
+            score,
+            !idle_sessions
+        );
+        Some(token.clone())
+    }
+}
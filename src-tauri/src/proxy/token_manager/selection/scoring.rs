@@ -4,24 +4,25 @@ use super::super::manager::TokenManager;
 use super::super::models::ProxyToken;
 use std::sync::atomic::Ordering;
 
+/// 按订阅等级的并发上限, 进行中请求达到上限视为满载
+pub(super) fn concurrency_limit(tier: &Option<String>) -> usize {
+    match tier.as_deref() {
+        Some(t) if t.contains("ultra") => 8,
+        Some(t) if t.contains("pro") => 3,
+        Some(_) => 1,
+        None => 1,
+    }
+}
+
 impl TokenManager {
-    /// Sort tokens by priority (tier, health, reset_time, connections, quota)
+    /// Sort tokens by priority (user priority, tier, health, reset_time, connections, quota)
     pub(crate) fn sort_tokens(&self, tokens: &mut Vec<ProxyToken>) {
         // [FIX] Reset time threshold: differences < 10 minutes are considered equal priority
         const RESET_TIME_THRESHOLD_SECS: i64 = 600;
 
         tokens.sort_by(|a, b| {
-            let get_concurrency_limit = |tier: &Option<String>| -> usize {
-                match tier.as_deref() {
-                    Some(t) if t.contains("ultra") => 8,
-                    Some(t) if t.contains("pro") => 3,
-                    Some(_) => 1,
-                    None => 1,
-                }
-            };
-
-            let limit_a = get_concurrency_limit(&a.subscription_tier);
-            let limit_b = get_concurrency_limit(&b.subscription_tier);
+            let limit_a = concurrency_limit(&a.subscription_tier);
+            let limit_b = concurrency_limit(&b.subscription_tier);
 
             let active_a = self
                 .active_requests
@@ -46,7 +47,13 @@ impl TokenManager {
                 }
             }
 
-            // 2. Compare by subscription tier (ULTRA > PRO > FREE)
+            // 2. User-assigned account priority (higher first)
+            let priority_cmp = b.priority.cmp(&a.priority);
+            if priority_cmp != std::cmp::Ordering::Equal {
+                return priority_cmp;
+            }
+
+            // 3. Compare by subscription tier (ULTRA > PRO > FREE)
             let tier_priority = |tier: &Option<String>| match tier.as_deref() {
                 Some(t) if t.contains("ultra") || t.contains("ULTRA") => 0,
                 Some(t) if t.contains("pro") || t.contains("PRO") => 1,
@@ -60,7 +67,7 @@ impl TokenManager {
                 return tier_cmp;
             }
 
-            // 3. Compare by health score (higher is better)
+            // 4. Compare by health score (higher is better)
             let health_cmp = b
                 .health_score
                 .partial_cmp(&a.health_score)
@@ -69,7 +76,7 @@ impl TokenManager {
                 return health_cmp;
             }
 
-            // 4. [FIX] Compare by reset time (earlier/closer is better)
+            // 5. [FIX] Compare by reset time (earlier/closer is better)
             // Differences < 10 minutes are considered equal priority to avoid frequent switching
             let reset_a = a.reset_time.unwrap_or(i64::MAX);
             let reset_b = b.reset_time.unwrap_or(i64::MAX);
@@ -82,13 +89,13 @@ impl TokenManager {
                 }
             }
 
-            // 5. Compare by active connections (fewer is better)
+            // 6. Compare by active connections (fewer is better)
             let active_cmp = active_a.cmp(&active_b);
             if active_cmp != std::cmp::Ordering::Equal {
                 return active_cmp;
             }
 
-            // 6. Compare by remaining quota (higher is better)
+            // 7. Compare by remaining quota (higher is better)
             let quota_a = a.remaining_quota.unwrap_or(0);
             let quota_b = b.remaining_quota.unwrap_or(0);
             quota_b.cmp(&quota_a)
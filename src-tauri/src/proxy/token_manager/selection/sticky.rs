@@ -30,16 +30,23 @@ impl TokenManager {
                     .rate_limit_tracker
                     .get_remaining_wait(&key, Some(normalized_target));
 
+                // 带长期缓存意图的会话在 Balance 模式下同样等待短暂限流, 换号会丢失隐式缓存
+                let cache_intent = scheduling.mode == SchedulingMode::Balance
+                    && super::cache_intent::has_cache_intent(session_id);
                 if reset_sec > 0
-                    && scheduling.mode == SchedulingMode::CacheFirst
+                    && (scheduling.mode == SchedulingMode::CacheFirst || cache_intent)
                     && reset_sec <= scheduling.max_wait_seconds
                 {
                     tracing::info!(
-                        "Sticky Session: Account {} limited ({}s), waiting...",
+                        "Sticky Session: Account {} limited ({}s), waiting...{}",
                         bound_token.email,
-                        reset_sec
+                        reset_sec,
+                        if cache_intent { " (cache intent)" } else { "" }
                     );
                     tokio::time::sleep(std::time::Duration::from_secs(reset_sec)).await;
+                    if cache_intent {
+                        super::cache_intent::record_held_binding();
+                    }
                 }
 
                 let reset_sec_after_wait = self
@@ -19,25 +19,12 @@ impl TokenManager {
         if now >= token.timestamp - 300 {
             tracing::debug!("账号 {} 的 token 即将过期，正在刷新...", token.email);
 
-            match crate::modules::oauth::refresh_access_token(&token.refresh_token, Some(&token.account_id)).await {
-                Ok(token_response) => {
+            match self.refresh_token_shared(&token.account_id, &token.refresh_token).await {
+                Ok(refreshed) => {
                     tracing::debug!("Token 刷新成功！");
-                    token.access_token = token_response.access_token.clone();
-                    token.expires_in = token_response.expires_in;
-                    token.timestamp = now + token_response.expires_in;
-
-                    if let Some(mut entry) = self.tokens.get_mut(&token.account_id) {
-                        entry.access_token = token.access_token.clone();
-                        entry.expires_in = token.expires_in;
-                        entry.timestamp = token.timestamp;
-                    }
-
-                    if let Err(e) = self
-                        .save_refreshed_token(&token.account_id, &token_response)
-                        .await
-                    {
-                        tracing::debug!("保存刷新后的 token 失败 ({}): {}", token.email, e);
-                    }
+                    token.access_token = refreshed.access_token;
+                    token.expires_in = refreshed.expires_in;
+                    token.timestamp = refreshed.timestamp;
                 }
                 Err(e) => {
                     tracing::error!("Token 刷新失败 ({}): {}", token.email, e);
@@ -54,7 +41,7 @@ impl TokenManager {
                     *last_error = Some(format!("Token refresh failed: {}", e));
                     attempted.insert(token.account_id.clone());
 
-                    if quota_group != "image_gen" {
+                    if super::tracks_last_used(quota_group) {
                         if matches!(last_used_account_id, Some((id, _)) if id == &token.account_id)
                         {
                             *need_update_last_used =
@@ -103,6 +90,18 @@ impl TokenManager {
                 Ok(pid)
             }
             Err(e) => {
+                // 项目未开通: 不使用兜底 project_id, 标记后换号
+                let setup = e
+                    .contains("403")
+                    .then(|| crate::proxy::common::project_setup::detect(403, &e))
+                    .flatten();
+                if let Some(setup) = setup {
+                    self.mark_project_setup_required(&token.account_id, setup).await;
+                    *last_error = Some(format!("Project setup required for {}", token.email));
+                    attempted.insert(token.account_id.clone());
+                    return Err("continue".to_string());
+                }
+
                 tracing::warn!(
                     "Failed to fetch project_id for {}: {}, fallback to default {}",
                     token.email,
@@ -116,7 +115,7 @@ impl TokenManager {
                 }
                 let _ = self.save_project_id(&token.account_id, &fallback).await;
 
-                if quota_group != "image_gen"
+                if super::tracks_last_used(quota_group)
                     && matches!(last_used_account_id, Some((id, _)) if id == &token.account_id)
                 {
                     *need_update_last_used = Some((token.account_id.clone(), std::time::Instant::now()));
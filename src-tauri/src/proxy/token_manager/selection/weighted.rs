@@ -0,0 +1,273 @@
+// Weighted Random Selection Logic
+// PerformanceFirst 的加权变体: 选中概率正比于账号健康分
+// 健康分 = 最近成功率 (EWMA) × 剩余配额比例 / (1 + 429 惩罚), 均为增量维护, 选择为 O(账号数)
+
+use super::super::manager::TokenManager;
+use super::super::models::ProxyToken;
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// 成功率 EWMA 的平滑系数
+const SUCCESS_ALPHA: f64 = 0.1;
+/// 429 惩罚的半衰期 (秒)
+const PENALTY_HALF_LIFE_SECS: f64 = 60.0;
+/// 健康分下限, 保证低分账号仍有少量探测流量
+const MIN_WEIGHT: f64 = 0.01;
+/// 选择分布统计的窗口 (最近 N 次选择)
+const DISTRIBUTION_WINDOW: usize = 1000;
+
+#[derive(Debug, Clone, Copy)]
+struct HealthSignals {
+    success_rate: f64,
+    penalty: f64,
+    penalty_at: Instant,
+}
+
+impl Default for HealthSignals {
+    fn default() -> Self {
+        Self {
+            success_rate: 1.0,
+            penalty: 0.0,
+            penalty_at: Instant::now(),
+        }
+    }
+}
+
+impl HealthSignals {
+    fn penalty_now(&self, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.penalty_at).as_secs_f64();
+        self.penalty * 0.5_f64.powf(elapsed / PENALTY_HALF_LIFE_SECS)
+    }
+}
+
+/// email -> 健康信号
+static HEALTH: Lazy<DashMap<String, HealthSignals>> = Lazy::new(DashMap::new);
+
+/// 最近的选择记录与各账号计数 (增量维护)
+#[derive(Default)]
+struct SelectionWindow {
+    recent: VecDeque<String>,
+    counts: HashMap<String, usize>,
+}
+
+static SELECTIONS: Lazy<Mutex<SelectionWindow>> = Lazy::new(|| Mutex::new(SelectionWindow::default()));
+
+/// 配置了种子时使用的确定性 RNG (种子变化时重建)
+static SEEDED_RNG: Lazy<Mutex<Option<(u64, StdRng)>>> = Lazy::new(|| Mutex::new(None));
+
+/// 请求结果对账号成功率的贡献: 成功为 1, 上游 / 账号侧失败 (5xx、429、401/402/403) 为 0;
+/// 其它 4xx (参数错误、客户端取消等) 是请求本身的问题, 不计入
+fn outcome_sample(status: u16) -> Option<f64> {
+    match status {
+        0..=399 => Some(1.0),
+        401..=403 | 429 | 500.. => Some(0.0),
+        _ => None,
+    }
+}
+
+/// 记录请求最终结果 (由 ProxyMonitor 在请求结束时调用)
+pub fn record_request_outcome(email: &str, status: u16) {
+    let Some(success) = outcome_sample(status) else {
+        return;
+    };
+    let mut entry = HEALTH.entry(email.to_string()).or_default();
+    entry.success_rate = entry.success_rate * (1.0 - SUCCESS_ALPHA) + success * SUCCESS_ALPHA;
+}
+
+/// 记录一次流中途停滞: 客户端看到的是 200, 但按失败计入成功率
+pub fn record_stall(email: &str) {
+    let mut entry = HEALTH.entry(email.to_string()).or_default();
+    entry.success_rate *= 1.0 - SUCCESS_ALPHA;
+}
+
+/// 记录一次上游 429 (由限流标记调用, 包含被重试掉的 429)
+pub fn record_rate_limited(email: &str) {
+    let now = Instant::now();
+    let mut entry = HEALTH.entry(email.to_string()).or_default();
+    entry.penalty = entry.penalty_now(now) + 1.0;
+    entry.penalty_at = now;
+}
+
+/// 移除账号的健康信号与选择记录
+pub(crate) fn forget(email: &str) {
+    HEALTH.remove(email);
+    let mut window = SELECTIONS.lock().unwrap_or_else(|e| e.into_inner());
+    window.recent.retain(|e| e != email);
+    window.counts.remove(email);
+}
+
+/// 账号当前健康分 (0.01 ~ 1.0)
+pub(crate) fn health_weight(token: &ProxyToken, normalized_target: &str) -> f64 {
+    let signals = HEALTH.get(&token.email).map(|s| *s).unwrap_or_default();
+    let quota = token
+        .model_quotas
+        .get(normalized_target)
+        .copied()
+        .or(token.remaining_quota)
+        .map(|pct| (pct as f64 / 100.0).clamp(0.0, 1.0))
+        .unwrap_or(1.0);
+    let score = signals.success_rate * quota / (1.0 + signals.penalty_now(Instant::now()));
+    score.clamp(MIN_WEIGHT, 1.0)
+}
+
+/// 最近 DISTRIBUTION_WINDOW 次加权选择中该账号所占比例
+pub(crate) fn selection_share(email: &str) -> f64 {
+    let window = SELECTIONS.lock().unwrap_or_else(|e| e.into_inner());
+    if window.recent.is_empty() {
+        return 0.0;
+    }
+    window.counts.get(email).copied().unwrap_or(0) as f64 / window.recent.len() as f64
+}
+
+fn record_selection(email: &str) {
+    let mut window = SELECTIONS.lock().unwrap_or_else(|e| e.into_inner());
+    window.recent.push_back(email.to_string());
+    *window.counts.entry(email.to_string()).or_insert(0) += 1;
+    if window.recent.len() > DISTRIBUTION_WINDOW {
+        if let Some(old) = window.recent.pop_front() {
+            if let Some(count) = window.counts.get_mut(&old) {
+                *count -= 1;
+                if *count == 0 {
+                    window.counts.remove(&old);
+                }
+            }
+        }
+    }
+}
+
+/// 按权重抽取下标
+fn pick_weighted<R: Rng>(weights: &[f64], rng: &mut R) -> Option<usize> {
+    let total: f64 = weights.iter().sum();
+    if weights.is_empty() || total <= 0.0 {
+        return None;
+    }
+    let mut r = rng.gen_range(0.0..total);
+    for (idx, w) in weights.iter().enumerate() {
+        if r < *w {
+            return Some(idx);
+        }
+        r -= w;
+    }
+    Some(weights.len() - 1)
+}
+
+fn pick_with_seed(weights: &[f64], seed: Option<u64>) -> Option<usize> {
+    match seed {
+        Some(seed) => {
+            let mut guard = SEEDED_RNG.lock().unwrap_or_else(|e| e.into_inner());
+            if guard.as_ref().map(|(s, _)| *s) != Some(seed) {
+                *guard = Some((seed, StdRng::seed_from_u64(seed)));
+            }
+            let (_, rng) = guard.as_mut().expect("seeded rng initialized above");
+            pick_weighted(weights, rng)
+        }
+        None => pick_weighted(weights, &mut rand::thread_rng()),
+    }
+}
+
+impl TokenManager {
+    /// Select token with probability proportional to its health weight
+    pub(crate) async fn select_weighted(
+        &self,
+        tokens_snapshot: &[ProxyToken],
+        attempted: &HashSet<String>,
+        normalized_target: &str,
+        quota_protection_enabled: bool,
+        seed: Option<u64>,
+        need_update_last_used: &mut Option<(String, std::time::Instant)>,
+    ) -> Option<ProxyToken> {
+        let mut candidates: Vec<&ProxyToken> = Vec::with_capacity(tokens_snapshot.len());
+        for candidate in tokens_snapshot {
+            if attempted.contains(&candidate.account_id) {
+                continue;
+            }
+            if quota_protection_enabled && candidate.protected_models.contains(normalized_target) {
+                continue;
+            }
+            if self
+                .is_rate_limited(&candidate.account_id, Some(normalized_target))
+                .await
+            {
+                continue;
+            }
+            candidates.push(candidate);
+        }
+
+        let weights: Vec<f64> = candidates
+            .iter()
+            .map(|t| health_weight(t, normalized_target))
+            .collect();
+        let selected = candidates[pick_with_seed(&weights, seed)?];
+
+        record_selection(&selected.email);
+        tracing::debug!(
+            "[Weighted] Selected {} (weight {:.3} of {:.3})",
+            selected.email,
+            health_weight(selected, normalized_target),
+            weights.iter().sum::<f64>()
+        );
+        *need_update_last_used = Some((selected.account_id.clone(), std::time::Instant::now()));
+        Some(selected.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seeded_pick_is_deterministic() {
+        let weights = [0.2, 0.5, 0.3];
+        let mut a = StdRng::seed_from_u64(42);
+        let mut b = StdRng::seed_from_u64(42);
+        let seq_a: Vec<_> = (0..100).map(|_| pick_weighted(&weights, &mut a)).collect();
+        let seq_b: Vec<_> = (0..100).map(|_| pick_weighted(&weights, &mut b)).collect();
+        assert_eq!(seq_a, seq_b);
+        assert_eq!(pick_weighted(&[], &mut a), None);
+    }
+
+    #[test]
+    fn test_health_weight_signals() {
+        let mut token = ProxyToken {
+            email: "weighted-signals@example.com".to_string(),
+            ..super::super::tests::synthetic_token("weighted-signals")
+        };
+        assert_eq!(health_weight(&token, "gemini-3-flash"), 1.0);
+
+        token.remaining_quota = Some(40);
+        assert!((health_weight(&token, "gemini-3-flash") - 0.4).abs() < 1e-9);
+        token.model_quotas.insert("gemini-3-flash".to_string(), 10);
+        assert!((health_weight(&token, "gemini-3-flash") - 0.1).abs() < 1e-9);
+
+        token.model_quotas.clear();
+        token.remaining_quota = None;
+        record_rate_limited(&token.email);
+        assert!(health_weight(&token, "gemini-3-flash") < 0.51);
+
+        for _ in 0..100 {
+            record_request_outcome("weighted-failing@example.com", 500);
+        }
+        token.email = "weighted-failing@example.com".to_string();
+        assert_eq!(health_weight(&token, "gemini-3-flash"), MIN_WEIGHT);
+    }
+
+    #[test]
+    fn test_client_errors_do_not_lower_health() {
+        let token = ProxyToken {
+            email: "weighted-client-errors@example.com".to_string(),
+            ..super::super::tests::synthetic_token("weighted-client-errors")
+        };
+        for status in [400, 404, 413, 422, 499] {
+            record_request_outcome(&token.email, status);
+        }
+        assert_eq!(health_weight(&token, "gemini-3-flash"), 1.0);
+
+        record_request_outcome(&token.email, 429);
+        assert!(health_weight(&token, "gemini-3-flash") < 1.0);
+    }
+}
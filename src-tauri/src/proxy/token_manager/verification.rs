@@ -0,0 +1,507 @@
+// Account Verification Sweep
+// 启动时 (可选) 或手动对所有账号做一次体检: 刷新 token + 最小能力探测 (loadCodeAssist / 模型配额),
+// 在服务就绪前把失效账号标记出来, 避免第一批真实请求才踩到 invalid_grant / 403 / 配额耗尽。
+// 结果按 TTL 缓存到 account_verification.json, 重启时未过期的结果直接复用而不重复探测;
+// 缓存绑定 refresh token 指纹, 账号重新登录 (换了 token) 后旧结论不再复用。
+
+use super::manager::TokenManager;
+use super::models::ProxyToken;
+use crate::proxy::rate_limit::RateLimitReason;
+use futures::future::BoxFuture;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// 同时体检的账号数上限 (避免启动时对 Google 形成突发请求)
+const VERIFY_CONCURRENCY: usize = 3;
+const STORE_FILE: &str = "account_verification.json";
+
+/// 账号体检结论
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VerificationStatus {
+    Ok,
+    /// refresh token 已失效 (invalid_grant), 需要重新登录
+    NeedsReauth,
+    /// 无法解析 project 或账号被拒绝访问 (403)
+    ProjectMisconfigured,
+    /// 所有模型配额均已耗尽
+    QuotaExhausted,
+    /// 网络等临时错误, 不做标记
+    Failed,
+}
+
+/// 单个账号的体检结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountVerification {
+    pub account_id: String,
+    pub email: String,
+    pub status: VerificationStatus,
+    pub detail: Option<String>,
+    /// 配额耗尽时最早的恢复时间 (RFC3339)
+    pub reset_time: Option<String>,
+    /// 体检时间 (unix 秒)
+    pub checked_at: i64,
+    /// 是否直接复用了缓存结果
+    #[serde(default)]
+    pub cached: bool,
+    /// 体检时 refresh token 的指纹 (截断的 SHA-256), 用于判断缓存是否仍对应同一凭据
+    #[serde(default)]
+    pub token_fingerprint: String,
+}
+
+/// 体检进度 (每完成一个账号推送一次)
+#[derive(Debug, Clone, Serialize)]
+pub struct VerificationProgress {
+    pub done: usize,
+    pub total: usize,
+    pub result: AccountVerification,
+}
+
+/// 探测结果
+#[derive(Debug, Clone)]
+pub struct ProbeResult {
+    pub status: VerificationStatus,
+    pub detail: Option<String>,
+    pub reset_time: Option<String>,
+}
+
+impl ProbeResult {
+    fn ok() -> Self {
+        Self {
+            status: VerificationStatus::Ok,
+            detail: None,
+            reset_time: None,
+        }
+    }
+
+    fn with(status: VerificationStatus, detail: String) -> Self {
+        Self {
+            status,
+            detail: Some(detail),
+            reset_time: None,
+        }
+    }
+}
+
+/// 可替换的能力探测实现 (默认调用 loadCodeAssist 与模型配额接口, 测试可注入)
+pub trait AccountProber: Send + Sync {
+    fn probe<'a>(
+        &'a self,
+        token: &'a ProxyToken,
+        access_token: &'a str,
+    ) -> BoxFuture<'a, ProbeResult>;
+}
+
+/// 默认探测: 缺少 project 时解析一次, 再拉取模型配额
+pub struct GoogleProber;
+
+impl AccountProber for GoogleProber {
+    fn probe<'a>(
+        &'a self,
+        token: &'a ProxyToken,
+        access_token: &'a str,
+    ) -> BoxFuture<'a, ProbeResult> {
+        Box::pin(async move {
+            if token.project_id.is_none() {
+                if let Err(e) = crate::proxy::project_resolver::fetch_project_id(access_token).await {
+                    return ProbeResult::with(VerificationStatus::ProjectMisconfigured, e);
+                }
+            }
+            match crate::modules::quota::fetch_quota(access_token, &token.email).await {
+                Ok((quota, _)) => classify_quota(&quota),
+                Err(e) => ProbeResult::with(VerificationStatus::Failed, e.to_string()),
+            }
+        })
+    }
+}
+
+/// 根据配额数据判定: 403 视为 project 配置问题, 全部模型 0% 视为配额耗尽
+fn classify_quota(quota: &crate::models::QuotaData) -> ProbeResult {
+    if quota.is_forbidden {
+        return ProbeResult::with(
+            VerificationStatus::ProjectMisconfigured,
+            "403 Forbidden while fetching models".to_string(),
+        );
+    }
+    if !quota.models.is_empty() && quota.models.iter().all(|m| m.percentage <= 0) {
+        let reset_time = quota
+            .models
+            .iter()
+            .map(|m| m.reset_time.clone())
+            .filter(|r| !r.is_empty())
+            .min();
+        return ProbeResult {
+            status: VerificationStatus::QuotaExhausted,
+            detail: Some("all models report 0% remaining".to_string()),
+            reset_time,
+        };
+    }
+    ProbeResult::ok()
+}
+
+fn load_store(data_dir: &Path) -> HashMap<String, AccountVerification> {
+    let path = data_dir.join(STORE_FILE);
+    let Ok(content) = std::fs::read_to_string(&path) else {
+        return HashMap::new();
+    };
+    serde_json::from_str(&content).unwrap_or_else(|e| {
+        tracing::warn!("[Verify] Ignoring unreadable {:?}: {}", path, e);
+        HashMap::new()
+    })
+}
+
+/// refresh token 指纹 (缓存文件中不保存明文)
+fn token_fingerprint(refresh_token: &str) -> String {
+    let digest = Sha256::digest(refresh_token.as_bytes());
+    digest[..8].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn save_store(data_dir: &Path, store: &HashMap<String, AccountVerification>) {
+    let path = data_dir.join(STORE_FILE);
+    match serde_json::to_string_pretty(store) {
+        Ok(json) => {
+            if let Err(e) = crate::utils::atomic_file::write_atomic(&path, json.as_bytes()) {
+                tracing::warn!("[Verify] Failed to write {:?}: {}", path, e);
+            }
+        }
+        Err(e) => tracing::warn!("[Verify] Failed to serialize results: {}", e),
+    }
+}
+
+impl TokenManager {
+    /// 体检所有已加载账号 (最多 3 个并发), 并在返回前按结论标记账号
+    ///
+    /// `cache_ttl_secs` 内的历史结果直接复用 (`force` 时忽略缓存); 临时失败的结果不缓存。
+    pub async fn verify_all_accounts(
+        &self,
+        cache_ttl_secs: u64,
+        force: bool,
+        on_progress: impl Fn(&VerificationProgress),
+    ) -> Vec<AccountVerification> {
+        self.verify_all_accounts_with(&GoogleProber, cache_ttl_secs, force, on_progress)
+            .await
+    }
+
+    pub(crate) async fn verify_all_accounts_with(
+        &self,
+        prober: &dyn AccountProber,
+        cache_ttl_secs: u64,
+        force: bool,
+        on_progress: impl Fn(&VerificationProgress),
+    ) -> Vec<AccountVerification> {
+        let tokens: Vec<ProxyToken> = self.tokens.iter().map(|e| e.value().clone()).collect();
+        let total = tokens.len();
+        let mut store = load_store(&self.data_dir);
+        let now = chrono::Utc::now().timestamp();
+
+        let mut results = Vec::with_capacity(total);
+        let mut pending = Vec::new();
+        for token in tokens {
+            let fingerprint = token_fingerprint(&token.refresh_token);
+            let fresh = store.get(&token.account_id).filter(|r| {
+                !force
+                    && now - r.checked_at < cache_ttl_secs as i64
+                    && r.token_fingerprint == fingerprint
+            });
+            match fresh {
+                Some(previous) => results.push(AccountVerification {
+                    cached: true,
+                    ..previous.clone()
+                }),
+                None => pending.push(token),
+            }
+        }
+
+        for (index, result) in results.iter().enumerate() {
+            self.apply_verification(result).await;
+            on_progress(&VerificationProgress {
+                done: index + 1,
+                total,
+                result: result.clone(),
+            });
+        }
+
+        let mut probes = futures::stream::iter(pending)
+            .map(|token| self.verify_account(prober, token))
+            .buffer_unordered(VERIFY_CONCURRENCY);
+        while let Some(result) = probes.next().await {
+            self.apply_verification(&result).await;
+            results.push(result);
+            on_progress(&VerificationProgress {
+                done: results.len(),
+                total,
+                result: results[results.len() - 1].clone(),
+            });
+        }
+        drop(probes);
+
+        for result in &results {
+            if result.status == VerificationStatus::Failed {
+                store.remove(&result.account_id);
+            } else if !result.cached {
+                store.insert(result.account_id.clone(), result.clone());
+            }
+        }
+        store.retain(|id, _| results.iter().any(|r| &r.account_id == id));
+        let data_dir = self.data_dir.clone();
+        let _ = tokio::task::spawn_blocking(move || save_store(&data_dir, &store)).await;
+
+        let unhealthy = results
+            .iter()
+            .filter(|r| r.status != VerificationStatus::Ok)
+            .count();
+        tracing::info!(
+            "[Verify] Checked {} account(s): {} healthy, {} flagged",
+            total,
+            total - unhealthy,
+            unhealthy
+        );
+        results
+    }
+
+    async fn verify_account(&self, prober: &dyn AccountProber, token: ProxyToken) -> AccountVerification {
+        let token_fingerprint = token_fingerprint(&token.refresh_token);
+        let probe = match self
+            .refresh_token_shared(&token.account_id, &token.refresh_token)
+            .await
+        {
+            Ok(refreshed) => prober.probe(&token, &refreshed.access_token).await,
+            Err(e) if e.contains("invalid_grant") => {
+                ProbeResult::with(VerificationStatus::NeedsReauth, e)
+            }
+            Err(e) => ProbeResult::with(VerificationStatus::Failed, e),
+        };
+        AccountVerification {
+            account_id: token.account_id,
+            email: token.email,
+            status: probe.status,
+            detail: probe.detail,
+            reset_time: probe.reset_time,
+            checked_at: chrono::Utc::now().timestamp(),
+            cached: false,
+            token_fingerprint,
+        }
+    }
+
+    /// 按体检结论标记账号 (与请求路径上遇到同类错误时的处理一致)
+    async fn apply_verification(&self, result: &AccountVerification) {
+        if !self.tokens.contains_key(&result.account_id) {
+            return;
+        }
+        let detail = result.detail.as_deref().unwrap_or_default();
+        match result.status {
+            VerificationStatus::NeedsReauth => {
+                tracing::warn!("[Verify] {} needs re-authorization", result.email);
+                let _ = self
+                    .disable_account(&result.account_id, &format!("invalid_grant: {}", detail))
+                    .await;
+                self.tokens.remove(&result.account_id);
+            }
+            VerificationStatus::ProjectMisconfigured => {
+                if let Some(setup) = crate::proxy::common::project_setup::detect(403, detail) {
+                    self.mark_project_setup_required(&result.account_id, setup).await;
+                } else if let Err(e) = self.set_forbidden(&result.account_id, detail).await {
+                    tracing::warn!("[Verify] Failed to mark {} forbidden: {}", result.email, e);
+                    self.tokens.remove(&result.account_id);
+                }
+            }
+            VerificationStatus::QuotaExhausted => {
+                if let Some(reset_time) = &result.reset_time {
+                    self.rate_limit_tracker.set_lockout_until_iso(
+                        &result.account_id,
+                        reset_time,
+                        RateLimitReason::QuotaExhausted,
+                        None,
+                    );
+                }
+            }
+            VerificationStatus::Ok | VerificationStatus::Failed => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::refresh::{RefreshCoordinator, TokenRefresher};
+    use crate::models::quota::ModelQuota;
+    use crate::models::QuotaData;
+    use crate::modules::oauth::TokenResponse;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    struct StubRefresher;
+
+    impl TokenRefresher for StubRefresher {
+        fn refresh<'a>(
+            &'a self,
+            refresh_token: &'a str,
+            _account_id: &'a str,
+        ) -> BoxFuture<'a, Result<TokenResponse, String>> {
+            Box::pin(async move {
+                if refresh_token == "revoked" {
+                    return Err("{\"error\": \"invalid_grant\"}".to_string());
+                }
+                Ok(TokenResponse {
+                    access_token: format!("access-{}", refresh_token),
+                    expires_in: 3600,
+                    token_type: "Bearer".to_string(),
+                    refresh_token: None,
+                })
+            })
+        }
+    }
+
+    /// 记录最大并发数; refresh token 为 "empty" 的账号返回配额耗尽
+    #[derive(Default)]
+    struct CountingProber {
+        calls: AtomicUsize,
+        active: AtomicUsize,
+        max_active: AtomicUsize,
+    }
+
+    impl AccountProber for CountingProber {
+        fn probe<'a>(
+            &'a self,
+            _token: &'a ProxyToken,
+            access_token: &'a str,
+        ) -> BoxFuture<'a, ProbeResult> {
+            Box::pin(async move {
+                self.calls.fetch_add(1, Ordering::SeqCst);
+                let active = self.active.fetch_add(1, Ordering::SeqCst) + 1;
+                self.max_active.fetch_max(active, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(30)).await;
+                self.active.fetch_sub(1, Ordering::SeqCst);
+                if access_token == "access-empty" {
+                    return ProbeResult {
+                        status: VerificationStatus::QuotaExhausted,
+                        detail: None,
+                        reset_time: Some("2099-01-01T00:00:00Z".to_string()),
+                    };
+                }
+                ProbeResult::ok()
+            })
+        }
+    }
+
+    fn token(id: &str, refresh_token: &str) -> ProxyToken {
+        ProxyToken {
+            access_token: "stale".to_string(),
+            refresh_token: refresh_token.to_string(),
+            timestamp: 0,
+            ..super::super::selection::tests::synthetic_token(id)
+        }
+    }
+
+    fn manager(name: &str, tokens: &[(&str, &str)]) -> TokenManager {
+        let dir = std::env::temp_dir().join(format!("verify-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut manager = TokenManager::new(dir);
+        manager.refresh = Arc::new(RefreshCoordinator {
+            refresher: Some(Arc::new(StubRefresher)),
+            ..Default::default()
+        });
+        for (id, refresh_token) in tokens {
+            manager.tokens.insert(id.to_string(), token(id, refresh_token));
+        }
+        manager
+    }
+
+    #[tokio::test]
+    async fn test_sweep_is_bounded_and_reports_progress() {
+        let ids: Vec<String> = (0..8).map(|i| format!("acc{}", i)).collect();
+        let pairs: Vec<(&str, &str)> = ids.iter().map(|id| (id.as_str(), "ok")).collect();
+        let manager = manager("bounded", &pairs);
+        let prober = CountingProber::default();
+        let progress = std::sync::Mutex::new(Vec::new());
+
+        let results = manager
+            .verify_all_accounts_with(&prober, 3600, false, |p| {
+                progress.lock().unwrap().push((p.done, p.total))
+            })
+            .await;
+
+        assert_eq!(results.len(), 8);
+        assert!(results.iter().all(|r| r.status == VerificationStatus::Ok));
+        assert!(prober.max_active.load(Ordering::SeqCst) <= VERIFY_CONCURRENCY);
+        let progress = progress.into_inner().unwrap();
+        assert_eq!(progress.len(), 8);
+        assert_eq!(progress.last(), Some(&(8, 8)));
+    }
+
+    #[tokio::test]
+    async fn test_failures_are_classified_and_marked() {
+        let manager = manager("classify", &[("good", "ok"), ("revoked", "revoked"), ("empty", "empty")]);
+        let prober = CountingProber::default();
+
+        let results = manager.verify_all_accounts_with(&prober, 3600, false, |_| {}).await;
+        let status = |id: &str| results.iter().find(|r| r.account_id == id).unwrap().status;
+
+        assert_eq!(status("good"), VerificationStatus::Ok);
+        assert_eq!(status("revoked"), VerificationStatus::NeedsReauth);
+        assert_eq!(status("empty"), VerificationStatus::QuotaExhausted);
+        // 失效账号移出号池, 配额耗尽的账号锁定到恢复时间
+        assert!(!manager.tokens.contains_key("revoked"));
+        assert!(manager.rate_limit_tracker.is_rate_limited("empty", None));
+        assert!(!manager.rate_limit_tracker.is_rate_limited("good", None));
+    }
+
+    #[tokio::test]
+    async fn test_cached_results_skip_probe_until_ttl_or_force() {
+        let manager = manager("cache", &[("a", "ok"), ("b", "ok")]);
+        let prober = CountingProber::default();
+
+        manager.verify_all_accounts_with(&prober, 3600, false, |_| {}).await;
+        assert_eq!(prober.calls.load(Ordering::SeqCst), 2);
+
+        let again = manager.verify_all_accounts_with(&prober, 3600, false, |_| {}).await;
+        assert_eq!(prober.calls.load(Ordering::SeqCst), 2);
+        assert!(again.iter().all(|r| r.cached));
+
+        manager.verify_all_accounts_with(&prober, 3600, true, |_| {}).await;
+        assert_eq!(prober.calls.load(Ordering::SeqCst), 4);
+
+        manager.verify_all_accounts_with(&prober, 0, false, |_| {}).await;
+        assert_eq!(prober.calls.load(Ordering::SeqCst), 6);
+    }
+
+    #[tokio::test]
+    async fn test_cached_result_is_not_reused_after_token_change() {
+        let manager = manager("fingerprint", &[("a", "ok")]);
+        let prober = CountingProber::default();
+
+        manager.verify_all_accounts_with(&prober, 3600, false, |_| {}).await;
+        assert_eq!(prober.calls.load(Ordering::SeqCst), 1);
+
+        // 账号重新登录后 refresh token 变化, 旧结论不再复用
+        manager.tokens.insert("a".to_string(), token("a", "relogin"));
+        let again = manager.verify_all_accounts_with(&prober, 3600, false, |_| {}).await;
+        assert_eq!(prober.calls.load(Ordering::SeqCst), 2);
+        assert!(!again[0].cached);
+    }
+
+    #[test]
+    fn test_classify_quota() {
+        let mut quota = QuotaData::new();
+        assert_eq!(classify_quota(&quota).status, VerificationStatus::Ok);
+
+        quota.models = vec![
+            ModelQuota { name: "a".into(), percentage: 0, reset_time: "2030-01-02T00:00:00Z".into() },
+            ModelQuota { name: "b".into(), percentage: 0, reset_time: "2030-01-01T00:00:00Z".into() },
+        ];
+        let exhausted = classify_quota(&quota);
+        assert_eq!(exhausted.status, VerificationStatus::QuotaExhausted);
+        assert_eq!(exhausted.reset_time.as_deref(), Some("2030-01-01T00:00:00Z"));
+
+        quota.models[0].percentage = 40;
+        assert_eq!(classify_quota(&quota).status, VerificationStatus::Ok);
+
+        quota.is_forbidden = true;
+        assert_eq!(classify_quota(&quota).status, VerificationStatus::ProjectMisconfigured);
+    }
+}
@@ -20,21 +20,60 @@ const V1_INTERNAL_BASE_URL_FALLBACKS: [&str; 3] = [
     V1_INTERNAL_BASE_URL_PROD,    // 优先级 3: Prod (仅作为兜底)
 ];
 
+/// 下载远程资源时最多跟随的重定向次数
+const MAX_RESOURCE_REDIRECTS: usize = 5;
+
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use super::connection;
+use super::resource_guard::{self, FetchError};
+use super::timeout::{self, TimeoutProfile};
+use super::transport::{UpstreamCall, UpstreamTransport};
+use crate::proxy::config::{UpstreamPoolConfig, UpstreamProxyConfig, UpstreamTimeoutConfig};
+use crate::proxy::mappers::error_classifier::classify_stream_error;
 
 pub struct UpstreamClient {
     http_client: RwLock<Client>,
+    resource_client: RwLock<Client>, // 下载客户端引用的远程资源: 不自动跟随重定向, 只连接公网地址
     user_agent_override: RwLock<Option<String>>,
+    proxy_config: RwLock<Option<UpstreamProxyConfig>>,
+    timeouts: RwLock<UpstreamTimeoutConfig>,
+    pool: RwLock<UpstreamPoolConfig>,
     preferred_endpoint_index: AtomicUsize, // [NEW] Sticky endpoint index
+    transport: Option<Arc<dyn UpstreamTransport>>, // 注入的传输层 (mock 上游), None 时走真实网络
 }
 
 impl UpstreamClient {
-    pub fn new(proxy_config: Option<crate::proxy::config::UpstreamProxyConfig>) -> Self {
-        let client = Self::build_http_client(proxy_config);
+    pub fn new(proxy_config: Option<UpstreamProxyConfig>) -> Self {
+        Self::with_settings(proxy_config, UpstreamTimeoutConfig::default(), UpstreamPoolConfig::default())
+    }
+
+    /// 使用指定的分阶段超时与连接池配置构建客户端
+    pub fn with_settings(
+        proxy_config: Option<UpstreamProxyConfig>,
+        timeouts: UpstreamTimeoutConfig,
+        pool: UpstreamPoolConfig,
+    ) -> Self {
+        let client = Self::build_http_client(proxy_config.clone(), &timeouts, &pool);
+        let resource_client = Self::build_resource_client(proxy_config.as_ref());
         Self { 
             http_client: RwLock::new(client),
+            resource_client: RwLock::new(resource_client),
             user_agent_override: RwLock::new(None),
+            proxy_config: RwLock::new(proxy_config),
+            timeouts: RwLock::new(timeouts),
+            pool: RwLock::new(pool),
             preferred_endpoint_index: AtomicUsize::new(0),
+            transport: None,
+        }
+    }
+
+    /// 使用自定义传输层构建客户端 (诊断/基准测试用的进程内 mock 上游)
+    pub fn with_transport(transport: Arc<dyn UpstreamTransport>) -> Self {
+        Self {
+            transport: Some(transport),
+            ..Self::new(None)
         }
     }
 
@@ -52,30 +91,70 @@ impl UpstreamClient {
     }
 
     /// [NEW] 重建并热更新内部 HTTP 客户端
-    pub async fn rebuild_client(&self, proxy_config: Option<crate::proxy::config::UpstreamProxyConfig>) {
-        let new_client = Self::build_http_client(proxy_config);
+    pub async fn rebuild_client(&self, proxy_config: Option<UpstreamProxyConfig>) {
+        let timeouts = self.timeouts.read().await.clone();
+        let pool = self.pool.read().await.clone();
+        let new_client = Self::build_http_client(proxy_config.clone(), &timeouts, &pool);
+        *self.resource_client.write().await = Self::build_resource_client(proxy_config.as_ref());
+        *self.proxy_config.write().await = proxy_config;
         let mut writer = self.http_client.write().await;
         *writer = new_client;
         tracing::info!("UpstreamClient underlying HTTP client has been reloaded");
     }
 
+    /// 热更新分阶段超时 (连接预算变化时重建 HTTP 客户端)
+    pub async fn set_timeouts(&self, timeouts: UpstreamTimeoutConfig) {
+        let rebuild = timeout::connect_budget(&timeouts) != timeout::connect_budget(&*self.timeouts.read().await);
+        *self.timeouts.write().await = timeouts.clone();
+        if rebuild {
+            let proxy_config = self.proxy_config.read().await.clone();
+            self.rebuild_client(proxy_config).await;
+        }
+        tracing::info!("UpstreamClient timeouts updated: {:?}", timeouts);
+    }
+
+    /// 热更新连接池 / HTTP/2 保活配置 (变化时重建 HTTP 客户端)
+    pub async fn set_pool_config(&self, pool: UpstreamPoolConfig) {
+        if *self.pool.read().await == pool {
+            return;
+        }
+        *self.pool.write().await = pool.clone();
+        let proxy_config = self.proxy_config.read().await.clone();
+        self.rebuild_client(proxy_config).await;
+        tracing::info!("UpstreamClient pool config updated: {:?}", pool);
+    }
+
     /// 内部构建 HTTP Client 的逻辑
-    fn build_http_client(proxy_config: Option<crate::proxy::config::UpstreamProxyConfig>) -> Client {
-        // [PERF] Connection pool size configurable via env, default 64 for high-load scenarios
+    ///
+    /// 总时长不在 Client 级别设置, 由每次调用的 `TimeoutProfile` 决定 (流式请求不设上限)
+    fn build_http_client(
+        proxy_config: Option<UpstreamProxyConfig>,
+        timeouts: &UpstreamTimeoutConfig,
+        pool: &UpstreamPoolConfig,
+    ) -> Client {
+        // [PERF] Connection pool size configurable via env (overrides config) for high-load scenarios
         let pool_size: usize = std::env::var("ABV_POOL_SIZE")
             .ok()
             .and_then(|s| s.parse().ok())
-            .unwrap_or(64);
+            .unwrap_or(pool.pool_max_idle_per_host);
 
         let mut builder = Client::builder()
             // Connection settings (optimized for high concurrency)
-            .connect_timeout(Duration::from_secs(20))
-            .pool_max_idle_per_host(pool_size)             // [PERF] Increased from 16 to 64
-            .pool_idle_timeout(Duration::from_secs(90))    // Keep idle connections for 90s
+            .connect_timeout(timeout::connect_budget(timeouts))
+            .pool_max_idle_per_host(pool_size)
+            // 空闲超时低于 Google 的空闲断开时间, 避免复用已被对端关闭的连接
+            .pool_idle_timeout(Duration::from_secs(pool.pool_idle_timeout_secs.max(1)))
             .tcp_keepalive(Duration::from_secs(60))        // TCP keepalive probe every 60s
-            .timeout(Duration::from_secs(600))
+            .http2_adaptive_window(pool.http2_adaptive_window)
             .user_agent(crate::constants::USER_AGENT.as_str());
 
+        if pool.http2_keep_alive_interval_secs > 0 {
+            builder = builder
+                .http2_keep_alive_interval(Duration::from_secs(pool.http2_keep_alive_interval_secs))
+                .http2_keep_alive_timeout(Duration::from_secs(pool.http2_keep_alive_timeout_secs.max(1)))
+                .http2_keep_alive_while_idle(true);
+        }
+
         if let Some(config) = proxy_config {
             if config.enabled && !config.url.is_empty() {
                 if let Ok(proxy) = reqwest::Proxy::all(&config.url) {
@@ -88,6 +167,24 @@ impl UpstreamClient {
         builder.build().expect("Failed to create HTTP client")
     }
 
+    /// 构建下载远程资源用的 HTTP Client
+    ///
+    /// 重定向由 `fetch_resource` 逐跳校验后手动跟随; 直连时解析器拒绝非公网地址
+    fn build_resource_client(proxy_config: Option<&UpstreamProxyConfig>) -> Client {
+        let mut builder = Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .dns_resolver(Arc::new(resource_guard::PublicOnlyResolver))
+            .user_agent(crate::constants::USER_AGENT.as_str());
+
+        if let Some(config) = proxy_config.filter(|c| c.enabled && !c.url.is_empty()) {
+            if let Ok(proxy) = reqwest::Proxy::all(&config.url) {
+                builder = builder.proxy(proxy);
+            }
+        }
+
+        builder.build().expect("Failed to create resource HTTP client")
+    }
+
     /// 构建 v1internal URL
     /// 
     /// 构建 API 请求地址
@@ -123,10 +220,20 @@ impl UpstreamClient {
         body: Value,
         query_string: Option<&str>,
     ) -> Result<Response, String> {
-        self.call_v1_internal_with_headers(method, access_token, body, query_string, std::collections::HashMap::new()).await
+        self.call_v1_internal_with_headers(
+            method,
+            access_token,
+            body,
+            query_string,
+            std::collections::HashMap::new(),
+            TimeoutProfile::for_method(method),
+        )
+        .await
     }
 
     /// [FIX #765] 调用 v1internal API，支持透传额外的 Headers
+    ///
+    /// `profile` 决定本次调用的超时预算 (标题生成等轻量调用可使用 `TimeoutProfile::Quick`)
     pub async fn call_v1_internal_with_headers(
         &self,
         method: &str,
@@ -134,7 +241,20 @@ impl UpstreamClient {
         body: Value,
         query_string: Option<&str>,
         extra_headers: std::collections::HashMap<String, String>,
+        profile: TimeoutProfile,
     ) -> Result<Response, String> {
+        if let Some(transport) = &self.transport {
+            return transport
+                .send(UpstreamCall {
+                    method: method.to_string(),
+                    access_token: access_token.to_string(),
+                    body,
+                    query_string: query_string.map(str::to_string),
+                    extra_headers,
+                })
+                .await;
+        }
+
         // 构建 Headers (所有端点复用)
         let mut headers = header::HeaderMap::new();
         headers.insert(
@@ -169,6 +289,10 @@ impl UpstreamClient {
 
         let mut last_err: Option<String> = None;
 
+        let timeouts = self.timeouts.read().await.clone();
+        let first_byte_timeout = profile.first_byte(&timeouts);
+        let total_timeout = profile.total(&timeouts);
+
         // 获取 Client 读锁
         let client_guard = self.http_client.read().await;
 
@@ -192,12 +316,29 @@ impl UpstreamClient {
             // Has next if this is not the last attempt
             let has_next = attempt_idx + 1 < indices.len();
 
-            let response = client_guard
-                .post(&url)
-                .headers(headers.clone())
-                .json(&body)
-                .send()
-                .await;
+            let build_request = || {
+                let request = client_guard
+                    .post(&url)
+                    .headers(headers.clone())
+                    .json(&body);
+                match total_timeout {
+                    Some(total) => request.timeout(total),
+                    None => request,
+                }
+            };
+            let send = Self::send_with_reset_retry(method, base_url, build_request);
+
+            let response = match first_byte_timeout {
+                Some(limit) => match tokio::time::timeout(limit, send).await {
+                    Ok(result) => result.map_err(|e| Self::describe_send_error(base_url, &e)),
+                    Err(_) => Err(format!(
+                        "HTTP request failed at {} [timeout_error]: no response headers within {}s",
+                        base_url,
+                        limit.as_secs()
+                    )),
+                },
+                None => send.await.map_err(|e| Self::describe_send_error(base_url, &e)),
+            };
 
             match response {
                 Ok(resp) => {
@@ -248,8 +389,7 @@ impl UpstreamClient {
                     // 不可重试的错误或已是最后一个端点，直接返回
                     return Ok(resp);
                 }
-                Err(e) => {
-                    let msg = format!("HTTP request failed at {}: {}", base_url, e);
+                Err(msg) => {
                     tracing::debug!("{}", msg);
                     last_err = Some(msg);
 
@@ -270,8 +410,110 @@ impl UpstreamClient {
         Err(last_err.unwrap_or_else(|| "All endpoints failed".to_string()))
     }
 
+    /// 发送请求; 复用的池化连接在收到任何响应字节前被重置时, 幂等调用在同一账号 / 同一端点立即重试一次
+    /// (否则该失败会走端点 Fallback 并最终导致换号)
+    async fn send_with_reset_retry(
+        method: &str,
+        base_url: &str,
+        build_request: impl Fn() -> reqwest::RequestBuilder,
+    ) -> Result<Response, reqwest::Error> {
+        match build_request().send().await {
+            Err(e) if connection::is_connection_reset(&e) => {
+                connection::record_reset();
+                if !connection::is_idempotent(method) {
+                    return Err(e);
+                }
+                tracing::warn!(
+                    "Pooled connection reset before response at {} (method={}), retrying once: {}",
+                    base_url,
+                    method,
+                    e
+                );
+                let result = build_request().send().await;
+                connection::record_retry(result.is_ok());
+                result
+            }
+            other => other,
+        }
+    }
+
+    /// 格式化发送失败的错误信息, 附带错误分类 (如 `connect_timeout_error`) 供重试策略识别
+    fn describe_send_error(base_url: &str, error: &reqwest::Error) -> String {
+        let (error_type, _, _) = classify_stream_error(error);
+        format!("HTTP request failed at {} [{}]: {}", base_url, error_type, error)
+    }
+
+    /// 下载客户端请求中引用的远程资源 (如 image_url), 与上游共用代理配置
+    ///
+    /// 每一跳 (含重定向) 都要求目标为公网地址; 超过 `max_bytes` 或超时即返回错误,
+    /// 成功时返回内容与响应的 Content-Type (不含参数)
+    pub async fn fetch_resource(
+        &self,
+        url: &str,
+        max_bytes: usize,
+        timeout: Duration,
+    ) -> Result<(bytes::Bytes, Option<String>), FetchError> {
+        use futures::StreamExt;
+
+        let client = self.resource_client.read().await.clone();
+        let fetch = async {
+            let mut url = url::Url::parse(url).map_err(|e| FetchError::Failed(e.to_string()))?;
+            let mut redirects = 0;
+            let response = loop {
+                resource_guard::check_url(&url).await?;
+                let response = client
+                    .get(url.clone())
+                    .send()
+                    .await
+                    .map_err(|e| FetchError::Failed(e.to_string()))?;
+                if !response.status().is_redirection() {
+                    break response;
+                }
+                redirects += 1;
+                if redirects > MAX_RESOURCE_REDIRECTS {
+                    return Err(FetchError::Failed("too many redirects".to_string()));
+                }
+                let location = response
+                    .headers()
+                    .get(header::LOCATION)
+                    .and_then(|v| v.to_str().ok())
+                    .ok_or_else(|| FetchError::Failed("redirect without Location".to_string()))?;
+                url = url.join(location).map_err(|e| FetchError::Failed(e.to_string()))?;
+            };
+
+            let status = response.status();
+            if !status.is_success() {
+                return Err(FetchError::Failed(format!("HTTP {}", status.as_u16())));
+            }
+            if response.content_length().is_some_and(|len| len > max_bytes as u64) {
+                return Err(FetchError::TooLarge(max_bytes));
+            }
+            let content_type = response
+                .headers()
+                .get(header::CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.split(';').next())
+                .map(|v| v.trim().to_ascii_lowercase());
+
+            let mut body = Vec::new();
+            let mut stream = response.bytes_stream();
+            while let Some(chunk) = stream.next().await {
+                let chunk = chunk.map_err(|e| FetchError::Failed(e.to_string()))?;
+                if body.len() + chunk.len() > max_bytes {
+                    return Err(FetchError::TooLarge(max_bytes));
+                }
+                body.extend_from_slice(&chunk);
+            }
+            Ok((bytes::Bytes::from(body), content_type))
+        };
+
+        tokio::time::timeout(timeout, fetch)
+            .await
+            .map_err(|_| FetchError::Failed(format!("timed out after {}s", timeout.as_secs())))?
+    }
+
     /// 获取可用模型列表
-    /// 
+    ///
     /// 获取远端模型列表，支持多端点自动 Fallback
     #[allow(dead_code)] // API ready for future model discovery feature
     pub async fn fetch_available_models(&self, access_token: &str) -> Result<Value, String> {
@@ -298,6 +540,7 @@ impl UpstreamClient {
         );
 
         let mut last_err: Option<String> = None;
+        let quick_timeout = TimeoutProfile::Quick.total(&*self.timeouts.read().await);
         let client_guard = self.http_client.read().await;
 
         // [FIX] Adaptive Routing for models
@@ -320,6 +563,7 @@ impl UpstreamClient {
                 .post(&url)
                 .headers(headers.clone())
                 .json(&serde_json::json!({}))
+                .timeout(quick_timeout.unwrap_or(Duration::from_secs(20)))
                 .send()
                 .await;
 
@@ -0,0 +1,129 @@
+// 池化连接重置的识别与重试统计
+// 复用已被上游关闭的池化连接时, 请求会在收到任何响应字节前被 reset。
+// 这类失败与账号无关, 幂等调用在同一账号 / 同一端点立即重试一次即可, 无需换号。
+
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static CONNECTION_RESETS: AtomicU64 = AtomicU64::new(0);
+static RETRY_RECOVERED: AtomicU64 = AtomicU64::new(0);
+static RETRY_FAILED: AtomicU64 = AtomicU64::new(0);
+
+/// 错误链中表示连接在响应前被关闭的特征 (hyper / h2 / io)
+const RESET_MARKERS: [&str; 5] = [
+    "connection reset",
+    "connection closed before message completed",
+    "broken pipe",
+    "refused stream",
+    "not a result of an error", // h2 GOAWAY (NO_ERROR)
+];
+
+/// 可安全重发的 v1internal 方法 (不产生服务端状态)
+const IDEMPOTENT_METHODS: [&str; 5] = [
+    "generateContent",
+    "streamGenerateContent",
+    "countTokens",
+    "loadCodeAssist",
+    "fetchAvailableModels",
+];
+
+/// 连接重置统计 (启动以来)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConnectionResetStats {
+    /// 收到响应前连接被重置的请求数
+    pub connection_resets: u64,
+    /// 同账号立即重试后成功拿到响应的请求数
+    pub recovered_by_retry: u64,
+    /// 重试仍失败的请求数
+    pub retry_failed: u64,
+}
+
+pub fn stats() -> ConnectionResetStats {
+    ConnectionResetStats {
+        connection_resets: CONNECTION_RESETS.load(Ordering::Relaxed),
+        recovered_by_retry: RETRY_RECOVERED.load(Ordering::Relaxed),
+        retry_failed: RETRY_FAILED.load(Ordering::Relaxed),
+    }
+}
+
+pub fn record_reset() {
+    CONNECTION_RESETS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn record_retry(recovered: bool) {
+    if recovered {
+        RETRY_RECOVERED.fetch_add(1, Ordering::Relaxed);
+    } else {
+        RETRY_FAILED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+pub fn is_idempotent(method: &str) -> bool {
+    IDEMPOTENT_METHODS.contains(&method)
+}
+
+/// 发送失败是否为复用连接被重置 (超时与新建连接失败不算, 由端点 Fallback / 换号处理)
+pub fn is_connection_reset(error: &reqwest::Error) -> bool {
+    !error.is_timeout() && !error.is_connect() && chain_indicates_reset(error)
+}
+
+fn chain_indicates_reset(error: &(dyn std::error::Error + 'static)) -> bool {
+    let mut current = Some(error);
+    while let Some(e) = current {
+        if let Some(io) = e.downcast_ref::<std::io::Error>() {
+            if matches!(
+                io.kind(),
+                std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionAborted
+                    | std::io::ErrorKind::BrokenPipe
+            ) {
+                return true;
+            }
+        }
+        let message = e.to_string().to_lowercase();
+        if RESET_MARKERS.iter().any(|marker| message.contains(marker)) {
+            return true;
+        }
+        current = e.source();
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Wrapped(std::io::Error);
+
+    impl std::fmt::Display for Wrapped {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "error sending request")
+        }
+    }
+
+    impl std::error::Error for Wrapped {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            Some(&self.0)
+        }
+    }
+
+    #[test]
+    fn test_reset_detection_walks_error_chain() {
+        let reset = Wrapped(std::io::Error::new(std::io::ErrorKind::ConnectionReset, "peer"));
+        assert!(chain_indicates_reset(&reset));
+
+        let incomplete = Wrapped(std::io::Error::other("connection closed before message completed"));
+        assert!(chain_indicates_reset(&incomplete));
+
+        let refused = Wrapped(std::io::Error::new(std::io::ErrorKind::ConnectionRefused, "refused"));
+        assert!(!chain_indicates_reset(&refused));
+    }
+
+    #[test]
+    fn test_only_idempotent_methods_are_retried() {
+        assert!(is_idempotent("generateContent"));
+        assert!(is_idempotent("streamGenerateContent"));
+        assert!(!is_idempotent("onboardUser"));
+    }
+}
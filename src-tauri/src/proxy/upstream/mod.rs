@@ -2,5 +2,10 @@
 // 对应上游通讯接口
 
 pub mod client;
+pub mod connection;
 pub mod retry;
 pub mod models;
+pub mod resource_guard;
+pub mod timeout;
+pub mod transport;
+pub mod response_ids;
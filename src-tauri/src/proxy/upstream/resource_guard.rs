@@ -0,0 +1,154 @@
+// 客户端引用的远程资源 (如 image_url) 的地址校验, 防止借代理访问内网 (SSRF)
+// 每一跳 (含重定向) 都解析主机并要求所有地址均为公网地址;
+// 直连时 DNS 解析器再校验一次实际连接的地址, 避免解析结果在校验后被改写 (DNS rebinding)。
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use url::{Host, Url};
+
+/// 远程资源下载失败的原因
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FetchError {
+    /// 目标 (或重定向目标) 不是公网地址
+    Blocked,
+    /// 超过大小上限 (字节)
+    TooLarge(usize),
+    /// 其他失败 (状态码、连接错误、超时等); 细节只用于日志, 不回显给客户端
+    Failed(String),
+}
+
+/// 是否为可对外访问的公网地址 (排除回环、私有、链路本地、保留与文档网段等)
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => is_public_ipv4(v4),
+        IpAddr::V6(v6) => is_public_ipv6(v6),
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || a == 0 // 0.0.0.0/8
+        || (a == 100 && (64..128).contains(&b)) // 100.64.0.0/10 运营商 NAT
+        || (a == 192 && b == 0 && c == 0) // 192.0.0.0/24 IETF 协议分配
+        || (a == 198 && (18..20).contains(&b)) // 198.18.0.0/15 基准测试
+        || a >= 240) // 240.0.0.0/4 保留
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    if let Some(v4) = ip.to_ipv4_mapped() {
+        return is_public_ipv4(v4);
+    }
+    let segments = ip.segments();
+    // 64:ff9b::/96 NAT64 按内嵌的 IPv4 判断
+    if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+        let [hi, lo] = [segments[6], segments[7]];
+        return is_public_ipv4(Ipv4Addr::new((hi >> 8) as u8, hi as u8, (lo >> 8) as u8, lo as u8));
+    }
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        || segments[..6] == [0; 6] // ::/96 IPv4 兼容地址
+        || (segments[0] & 0xfe00) == 0xfc00 // fc00::/7 唯一本地
+        || (segments[0] & 0xffc0) == 0xfe80 // fe80::/10 链路本地
+        || (segments[0] & 0xffc0) == 0xfec0 // fec0::/10 站点本地
+        || (segments[0] == 0x2001 && segments[1] == 0x0db8) // 2001:db8::/32 文档
+        || (segments[0] == 0x2001 && segments[1] == 0) // 2001::/32 Teredo
+        || segments[0] == 0x2002) // 2002::/16 6to4
+}
+
+/// 校验一跳 URL: 仅允许 http(s), 主机解析出的所有地址都必须是公网地址
+pub async fn check_url(url: &Url) -> Result<(), FetchError> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(FetchError::Failed(format!("unsupported scheme '{}'", url.scheme())));
+    }
+    let port = url.port_or_known_default().unwrap_or(80);
+    let addrs: Vec<IpAddr> = match url.host() {
+        Some(Host::Ipv4(ip)) => vec![IpAddr::V4(ip)],
+        Some(Host::Ipv6(ip)) => vec![IpAddr::V6(ip)],
+        Some(Host::Domain(domain)) => tokio::net::lookup_host((domain, port))
+            .await
+            .map_err(|e| FetchError::Failed(format!("dns lookup failed: {}", e)))?
+            .map(|addr| addr.ip())
+            .collect(),
+        None => return Err(FetchError::Failed("missing host".to_string())),
+    };
+    if addrs.is_empty() {
+        return Err(FetchError::Failed("dns lookup returned no address".to_string()));
+    }
+    if addrs.into_iter().all(is_public_ip) {
+        Ok(())
+    } else {
+        Err(FetchError::Blocked)
+    }
+}
+
+/// 只返回公网地址的 DNS 解析器; 任一地址非公网即拒绝整个解析结果
+pub struct PublicOnlyResolver;
+
+impl Resolve for PublicOnlyResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0)).await?.collect();
+            if addrs.is_empty() || !addrs.iter().all(|addr| is_public_ip(addr.ip())) {
+                return Err(format!("{} does not resolve to a public address", name.as_str()).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_private_and_reserved_addresses_are_not_public() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "255.255.255.255",
+            "240.0.0.1",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::ffff:169.254.169.254",
+            "64:ff9b::a00:1",
+        ] {
+            assert!(!is_public_ip(ip.parse().unwrap()), "{} should be blocked", ip);
+        }
+        for ip in ["8.8.8.8", "142.250.72.14", "2606:4700:4700::1111", "::ffff:8.8.8.8"] {
+            assert!(is_public_ip(ip.parse().unwrap()), "{} should be allowed", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_url_rejects_internal_hosts() {
+        for url in [
+            "http://127.0.0.1:8045/v1/models",
+            "http://[::1]/",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://localhost/",
+        ] {
+            let url = Url::parse(url).unwrap();
+            assert_eq!(check_url(&url).await, Err(FetchError::Blocked), "{}", url);
+        }
+
+        let url = Url::parse("ftp://8.8.8.8/cat.png").unwrap();
+        assert!(matches!(check_url(&url).await, Err(FetchError::Failed(_))));
+    }
+}
@@ -0,0 +1,229 @@
+// 上游 responseId / modelVersion 捕获
+// Google 支持排查问题时会索要 responseId, 而协议转换会丢弃这两个字段。
+// 这里在原始上游流上旁路提取 (取第一个携带它们的分片), 通过响应头返回给客户端,
+// 并经响应 extensions 交给监控中间件写入请求日志。
+
+use axum::response::Response;
+use bytes::Bytes;
+use futures::StreamExt;
+use serde_json::Value;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+use crate::proxy::handlers::common::set_header_lossy;
+
+pub const RESPONSE_ID_HEADER: &str = "X-Upstream-Response-Id";
+pub const MODEL_VERSION_HEADER: &str = "X-Upstream-Model-Version";
+
+type UpstreamStream = Pin<Box<dyn futures::Stream<Item = Result<Bytes, reqwest::Error>> + Send>>;
+
+/// 上游响应标识
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UpstreamIds {
+    pub response_id: Option<String>,
+    pub model_version: Option<String>,
+}
+
+impl UpstreamIds {
+    /// 从 Gemini 响应 (含 v1internal 的 `response` 包装) 中提取
+    pub fn from_json(value: &Value) -> Self {
+        let inner = value.get("response").unwrap_or(value);
+        let field = |name: &str| {
+            inner
+                .get(name)
+                .and_then(|v| v.as_str())
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_string())
+        };
+        Self {
+            response_id: field("responseId"),
+            model_version: field("modelVersion"),
+        }
+    }
+
+    /// 从完整的 SSE 文本中提取 (调试载荷使用)
+    pub fn from_sse(text: &str) -> Self {
+        let mut ids = Self::default();
+        for line in text.lines() {
+            if ids.is_complete() {
+                break;
+            }
+            ids.merge_line(line);
+        }
+        ids
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.response_id.is_some() && self.model_version.is_some()
+    }
+
+    /// 只补齐缺失的字段 (以最先出现的值为准)
+    fn merge(&mut self, other: UpstreamIds) {
+        if self.response_id.is_none() {
+            self.response_id = other.response_id;
+        }
+        if self.model_version.is_none() {
+            self.model_version = other.model_version;
+        }
+    }
+
+    fn merge_line(&mut self, line: &str) {
+        let Some(data) = line.trim().strip_prefix("data:") else {
+            return;
+        };
+        if !data.contains("\"responseId\"") && !data.contains("\"modelVersion\"") {
+            return;
+        }
+        if let Ok(json) = serde_json::from_str::<Value>(data.trim()) {
+            self.merge(Self::from_json(&json));
+        }
+    }
+}
+
+/// 捕获结果的共享槽位: 流式响应的头部在首个分片后发出, 之后分片才出现的字段由中间件在流结束时补记
+#[derive(Debug, Clone, Default)]
+pub struct UpstreamIdsSlot(Arc<Mutex<UpstreamIds>>);
+
+impl UpstreamIdsSlot {
+    pub fn get(&self) -> UpstreamIds {
+        self.0.lock().map(|ids| ids.clone()).unwrap_or_default()
+    }
+
+    fn update(&self, f: impl FnOnce(&mut UpstreamIds)) {
+        if let Ok(mut ids) = self.0.lock() {
+            f(&mut ids);
+        }
+    }
+}
+
+impl From<UpstreamIds> for UpstreamIdsSlot {
+    fn from(ids: UpstreamIds) -> Self {
+        Self(Arc::new(Mutex::new(ids)))
+    }
+}
+
+/// 包装上游 SSE 流: 透传所有分片, 同时提取 responseId / modelVersion 写入槽位
+pub fn capture_stream(stream: UpstreamStream, slot: UpstreamIdsSlot) -> UpstreamStream {
+    let wrapped = async_stream::stream! {
+        let mut inner = stream;
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut done = false;
+        while let Some(item) = inner.next().await {
+            match &item {
+                Ok(bytes) if !done => {
+                    buffer.extend_from_slice(bytes);
+                    while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
+                        let line: Vec<u8> = buffer.drain(..=pos).collect();
+                        slot.update(|ids| ids.merge_line(&String::from_utf8_lossy(&line)));
+                    }
+                    if slot.get().is_complete() {
+                        done = true;
+                        buffer = Vec::new();
+                    }
+                }
+                _ => {}
+            }
+            yield item;
+        }
+        if !done && !buffer.is_empty() {
+            slot.update(|ids| ids.merge_line(&String::from_utf8_lossy(&buffer)));
+        }
+    };
+    Box::pin(wrapped)
+}
+
+/// 写入 X-Upstream-Response-Id / X-Upstream-Model-Version 响应头, 并把槽位放入 extensions
+pub fn attach(mut response: Response, slot: &UpstreamIdsSlot) -> Response {
+    let ids = slot.get();
+    if let Some(id) = &ids.response_id {
+        set_header_lossy(&mut response, RESPONSE_ID_HEADER, id);
+    }
+    if let Some(version) = &ids.model_version {
+        set_header_lossy(&mut response, MODEL_VERSION_HEADER, version);
+    }
+    response.extensions_mut().insert(slot.clone());
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WITH_IDS: &str = concat!(
+        "data: {\"response\":{\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"Hi\"}]}}],",
+        "\"modelVersion\":\"gemini-2.5-pro-002\",\"responseId\":\"resp-abc123\"}}\n\n",
+        "data: {\"response\":{\"candidates\":[{\"finishReason\":\"STOP\"}]}}\n\n",
+    );
+    const WITHOUT_IDS: &str = concat!(
+        "data: {\"response\":{\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"Hi\"}]}}]}}\n\n",
+        "data: {\"response\":{\"candidates\":[{\"finishReason\":\"STOP\"}]}}\n\n",
+    );
+
+    fn upstream(chunks: Vec<String>) -> UpstreamStream {
+        Box::pin(futures::stream::iter(chunks.into_iter().map(|c| Ok(Bytes::from(c)))))
+    }
+
+    async fn drain(stream: UpstreamStream) -> String {
+        let chunks: Vec<_> = stream.collect().await;
+        chunks
+            .into_iter()
+            .map(|c| String::from_utf8(c.unwrap().to_vec()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_from_json_handles_wrapped_and_plain_responses() {
+        let wrapped = serde_json::json!({
+            "response": { "responseId": "resp-1", "modelVersion": "gemini-2.5-flash" }
+        });
+        let plain = serde_json::json!({ "responseId": "resp-2" });
+
+        assert_eq!(
+            UpstreamIds::from_json(&wrapped),
+            UpstreamIds {
+                response_id: Some("resp-1".to_string()),
+                model_version: Some("gemini-2.5-flash".to_string()),
+            }
+        );
+        assert_eq!(UpstreamIds::from_json(&plain).response_id.as_deref(), Some("resp-2"));
+        assert_eq!(UpstreamIds::from_json(&serde_json::json!({})), UpstreamIds::default());
+    }
+
+    #[tokio::test]
+    async fn test_capture_stream_across_split_chunks() {
+        // 分片在 JSON 中间切开, 且字段出现在第二个事件
+        let slot = UpstreamIdsSlot::default();
+        let text = format!("{}{}", WITHOUT_IDS, WITH_IDS);
+        let (a, b) = text.split_at(WITHOUT_IDS.len() + 40);
+
+        let output = drain(capture_stream(upstream(vec![a.to_string(), b.to_string()]), slot.clone())).await;
+
+        assert_eq!(output, text);
+        let ids = slot.get();
+        assert_eq!(ids.response_id.as_deref(), Some("resp-abc123"));
+        assert_eq!(ids.model_version.as_deref(), Some("gemini-2.5-pro-002"));
+    }
+
+    #[tokio::test]
+    async fn test_capture_stream_without_ids_leaves_slot_empty() {
+        let slot = UpstreamIdsSlot::default();
+        let output = drain(capture_stream(upstream(vec![WITHOUT_IDS.to_string()]), slot.clone())).await;
+
+        assert_eq!(output, WITHOUT_IDS);
+        assert_eq!(slot.get(), UpstreamIds::default());
+        assert_eq!(UpstreamIds::from_sse(WITHOUT_IDS), UpstreamIds::default());
+    }
+
+    #[test]
+    fn test_attach_sets_headers_only_for_known_ids() {
+        let slot = UpstreamIdsSlot::from(UpstreamIds::from_sse(WITH_IDS));
+        let response = attach(Response::new(axum::body::Body::empty()), &slot);
+        assert_eq!(response.headers()[RESPONSE_ID_HEADER], "resp-abc123");
+        assert_eq!(response.headers()[MODEL_VERSION_HEADER], "gemini-2.5-pro-002");
+        assert!(response.extensions().get::<UpstreamIdsSlot>().is_some());
+
+        let empty = attach(Response::new(axum::body::Body::empty()), &UpstreamIdsSlot::default());
+        assert!(!empty.headers().contains_key(RESPONSE_ID_HEADER));
+        assert!(!empty.headers().contains_key(MODEL_VERSION_HEADER));
+    }
+}
@@ -0,0 +1,89 @@
+// 上游调用的超时档位
+// 连接/握手超时在 Client 级别生效; 档位决定单次调用的响应头等待时间与总时长
+
+use crate::proxy::config::UpstreamTimeoutConfig;
+use tokio::time::Duration;
+
+/// 单次 v1internal 调用的超时档位
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeoutProfile {
+    /// 流式: 仅限制等待响应头的时间, 不设总时长上限
+    Stream,
+    /// 非流式: 读取超时覆盖等待与读取完整响应
+    Standard,
+    /// 轻量辅助调用 (标题生成、预热等): 紧凑的总预算
+    Quick,
+}
+
+impl TimeoutProfile {
+    /// 按 v1internal 方法名选择默认档位 (countTokens 只做计数, 使用紧凑预算)
+    pub fn for_method(method: &str) -> Self {
+        match method {
+            "streamGenerateContent" => Self::Stream,
+            "countTokens" => Self::Quick,
+            _ => Self::Standard,
+        }
+    }
+
+    /// 等待响应头的超时
+    pub fn first_byte(self, config: &UpstreamTimeoutConfig) -> Option<Duration> {
+        match self {
+            Self::Stream => Some(secs(config.first_byte_timeout_secs)),
+            Self::Standard | Self::Quick => None,
+        }
+    }
+
+    /// 整个请求 (含响应体) 的超时, 流式为 None
+    pub fn total(self, config: &UpstreamTimeoutConfig) -> Option<Duration> {
+        match self {
+            Self::Stream => None,
+            Self::Standard => Some(secs(config.read_timeout_secs)),
+            Self::Quick => Some(secs(config.quick_timeout_secs)),
+        }
+    }
+}
+
+/// Client 级连接预算: reqwest 的 connect_timeout 同时覆盖 TCP 连接 (含 DNS 与代理隧道) 与 TLS 握手,
+/// 握手预算叠加在 TCP 预算之上, 挂起的握手最多占用两者之和后即失败换号
+pub fn connect_budget(config: &UpstreamTimeoutConfig) -> Duration {
+    secs(config.connect_timeout_secs) + secs(config.tls_handshake_timeout_secs)
+}
+
+fn secs(value: u64) -> Duration {
+    Duration::from_secs(value.max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_profile_has_no_total_cap() {
+        let config = UpstreamTimeoutConfig::default();
+        let profile = TimeoutProfile::for_method("streamGenerateContent");
+        assert_eq!(profile, TimeoutProfile::Stream);
+        assert_eq!(profile.total(&config), None);
+        assert_eq!(profile.first_byte(&config), Some(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_non_stream_profiles_use_total_budget() {
+        let config = UpstreamTimeoutConfig::default();
+        assert_eq!(TimeoutProfile::for_method("generateContent"), TimeoutProfile::Standard);
+        assert_eq!(TimeoutProfile::for_method("countTokens"), TimeoutProfile::Quick);
+        assert_eq!(TimeoutProfile::Standard.total(&config), Some(Duration::from_secs(600)));
+        assert_eq!(TimeoutProfile::Quick.total(&config), Some(Duration::from_secs(20)));
+        assert_eq!(TimeoutProfile::Quick.first_byte(&config), None);
+    }
+
+    #[test]
+    fn test_connect_budget_includes_tls_handshake() {
+        let config = UpstreamTimeoutConfig {
+            connect_timeout_secs: 0,
+            ..UpstreamTimeoutConfig::default()
+        };
+        // 0 会被钳制为 1 秒, 避免无限等待或立即失败
+        assert_eq!(connect_budget(&config), Duration::from_secs(6));
+        assert_eq!(connect_budget(&UpstreamTimeoutConfig::default()), Duration::from_secs(15));
+    }
+}
@@ -0,0 +1,26 @@
+// 上游传输层抽象
+// 默认由 UpstreamClient 直连 v1internal; 诊断/基准测试可注入进程内 mock 上游
+
+use futures::future::BoxFuture;
+use reqwest::Response;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// 一次 v1internal 调用的完整参数
+#[derive(Debug, Clone)]
+pub struct UpstreamCall {
+    /// v1internal 方法名 (generateContent / streamGenerateContent ...)
+    pub method: String,
+    pub access_token: String,
+    pub body: Value,
+    pub query_string: Option<String>,
+    pub extra_headers: HashMap<String, String>,
+}
+
+/// 可替换的上游传输实现
+///
+/// 注入后 `UpstreamClient::call_v1_internal*` 会绕过真实网络与端点 Fallback,
+/// 直接把调用交给该实现, handler/mapper 链路保持不变
+pub trait UpstreamTransport: Send + Sync {
+    fn send(&self, call: UpstreamCall) -> BoxFuture<'_, Result<Response, String>>;
+}
@@ -0,0 +1,193 @@
+// 模型预热池 (Warm Pool)
+// 长时间空闲后首个请求会有明显的冷启动延迟; 开启后在反代运行且近期有真实请求时,
+// 定期用低优先级账号向配置的模型发送 1-token 请求。
+// 预热请求直接走 UpstreamClient, 不经过监控中间件, 因此不会计入用户可见的日志/统计。
+
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use crate::proxy::config::ExperimentalConfig;
+use crate::proxy::server::AppState;
+
+/// 检查周期
+const TICK_INTERVAL: Duration = Duration::from_secs(30);
+/// 账号池被限流时暂停预热的时长
+const SUSPEND_SECS: i64 = 15 * 60;
+
+/// 最近一次真实请求时间 (unix 秒)
+static LAST_ACTIVITY: AtomicI64 = AtomicI64::new(0);
+/// 预热池暂停截止时间 (unix 秒)
+static SUSPENDED_UNTIL: AtomicI64 = AtomicI64::new(0);
+/// 账号轮换游标
+static ROTATION: AtomicUsize = AtomicUsize::new(0);
+/// 各模型最近一次成功预热时间 (unix 秒)
+static LAST_WARM: Lazy<DashMap<String, i64>> = Lazy::new(DashMap::new);
+
+/// 预热池状态 (随 proxy status 返回)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WarmPoolStatus {
+    pub enabled: bool,
+    /// 因限流暂停时的恢复时间 (unix 秒)
+    #[serde(default)]
+    pub suspended_until: Option<i64>,
+    /// model -> 最近一次成功预热时间 (unix 秒)
+    #[serde(default)]
+    pub last_warm: HashMap<String, i64>,
+}
+
+/// 记录一次真实客户端请求 (由监控中间件调用)
+pub fn record_activity() {
+    LAST_ACTIVITY.store(chrono::Utc::now().timestamp(), Ordering::Relaxed);
+}
+
+pub fn status(config: &ExperimentalConfig) -> WarmPoolStatus {
+    let now = chrono::Utc::now().timestamp();
+    let suspended_until = SUSPENDED_UNTIL.load(Ordering::Relaxed);
+    WarmPoolStatus {
+        enabled: config.enable_warm_pool,
+        suspended_until: (suspended_until > now).then_some(suspended_until),
+        last_warm: LAST_WARM
+            .iter()
+            .map(|e| (e.key().clone(), *e.value()))
+            .collect(),
+    }
+}
+
+/// 当前时刻需要预热的模型
+fn due_models(config: &ExperimentalConfig, now: i64, last_activity: i64) -> Vec<String> {
+    if !config.enable_warm_pool || config.warm_pool_models.is_empty() {
+        return Vec::new();
+    }
+    // 近期无真实请求 (例如夜间) 不预热
+    let idle_window = config.warm_pool_idle_minutes.max(1) as i64 * 60;
+    if last_activity == 0 || now - last_activity > idle_window {
+        return Vec::new();
+    }
+    if SUSPENDED_UNTIL.load(Ordering::Relaxed) > now {
+        return Vec::new();
+    }
+
+    let interval = config.warm_pool_interval_minutes.max(1) as i64 * 60;
+    config
+        .warm_pool_models
+        .iter()
+        .filter(|model| {
+            LAST_WARM
+                .get(model.as_str())
+                .map(|ts| now - *ts >= interval)
+                .unwrap_or(true)
+        })
+        .cloned()
+        .collect()
+}
+
+fn suspend(reason: &str) {
+    let until = chrono::Utc::now().timestamp() + SUSPEND_SECS;
+    SUSPENDED_UNTIL.store(until, Ordering::Relaxed);
+    tracing::warn!(
+        "[Warmup-Pool] Suspended for {}s: {}",
+        SUSPEND_SECS,
+        reason
+    );
+}
+
+/// 启动预热池后台任务 (随 Axum 服务常驻, 每个周期检查配置与运行状态)
+pub fn spawn(state: AppState) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(TICK_INTERVAL);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        loop {
+            ticker.tick().await;
+
+            if !*state.is_running.read().await {
+                continue;
+            }
+            let config = state.experimental.read().await.clone();
+            let now = chrono::Utc::now().timestamp();
+            for model in due_models(&config, now, LAST_ACTIVITY.load(Ordering::Relaxed)) {
+                if let Err(e) = warm_model(&state, &model).await {
+                    tracing::debug!("[Warmup-Pool] {} skipped: {}", model, e);
+                }
+                if SUSPENDED_UNTIL.load(Ordering::Relaxed) > now {
+                    break;
+                }
+            }
+        }
+    })
+}
+
+async fn warm_model(state: &AppState, model: &str) -> Result<(), String> {
+    let candidates = state.token_manager.low_priority_accounts(model).await;
+    if candidates.is_empty() {
+        suspend(&format!("no non-rate-limited account for {}", model));
+        return Err("account pool is rate-limited".to_string());
+    }
+    let email = &candidates[ROTATION.fetch_add(1, Ordering::Relaxed) % candidates.len()];
+
+    let (access_token, project_id, _, _) = state.token_manager.get_token_by_email(email).await?;
+    let body = crate::proxy::handlers::warmup::build_warmup_body(model, &project_id)?;
+
+    let response = state
+        .upstream
+        .call_v1_internal_with_headers(
+            "generateContent",
+            &access_token,
+            body,
+            None,
+            std::collections::HashMap::new(),
+            crate::proxy::upstream::timeout::TimeoutProfile::Quick,
+        )
+        .await?;
+    let status = response.status();
+
+    if status.is_success() {
+        LAST_WARM.insert(model.to_string(), chrono::Utc::now().timestamp());
+        tracing::info!("[Warmup-Pool] Warmed {} via {}", model, email);
+        return Ok(());
+    }
+
+    if status.as_u16() == 429 {
+        suspend(&format!("429 while warming {} via {}", model, email));
+    }
+    Err(format!("HTTP {}", status))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn warm_config(models: &[&str]) -> ExperimentalConfig {
+        ExperimentalConfig {
+            enable_warm_pool: true,
+            warm_pool_models: models.iter().map(|m| m.to_string()).collect(),
+            warm_pool_interval_minutes: 8,
+            warm_pool_idle_minutes: 30,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_due_models_respects_idle_window_and_interval() {
+        let now = 1_700_000_000;
+        let config = warm_config(&["warm-pool-test-a", "warm-pool-test-b"]);
+
+        // 从未有过真实请求 / 空闲超过窗口
+        assert!(due_models(&config, now, 0).is_empty());
+        assert!(due_models(&config, now, now - 31 * 60).is_empty());
+
+        // 关闭时不预热
+        let disabled = ExperimentalConfig {
+            enable_warm_pool: false,
+            ..config.clone()
+        };
+        assert!(due_models(&disabled, now, now).is_empty());
+
+        LAST_WARM.insert("warm-pool-test-a".to_string(), now - 60);
+        LAST_WARM.insert("warm-pool-test-b".to_string(), now - 9 * 60);
+        assert_eq!(due_models(&config, now, now - 60), vec!["warm-pool-test-b"]);
+    }
+}
@@ -0,0 +1,46 @@
+// 原子写文件
+// 先写同目录下的临时文件并 fsync, 再 rename 覆盖目标; 写入中途崩溃时目标文件保持旧内容, 不会出现半截 JSON。
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// 目标文件对应的临时文件 (同目录, 保证 rename 不跨文件系统)
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+/// 原子写入 (阻塞 IO, 异步上下文中应放到 spawn_blocking)
+pub fn write_atomic(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let tmp = temp_path(path);
+    let result = (|| {
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_atomic_replaces_content_without_leftovers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("store.json");
+
+        write_atomic(&path, b"{\"v\":1}").unwrap();
+        write_atomic(&path, b"{\"v\":2}").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{\"v\":2}");
+        assert!(!temp_path(&path).exists());
+        assert_eq!(temp_path(&path).file_name().unwrap(), "store.json.tmp");
+    }
+}
@@ -1,2 +1,4 @@
+pub mod atomic_file;
 pub mod http;
 pub mod protobuf;
+pub mod time;
@@ -0,0 +1,247 @@
+// 时区与每日边界
+// 上游的每日配额在太平洋时间零点重置, 而统计图表应按用户本地日期分桶, 两者都不能直接用 UTC 做日期运算。
+// 这里维护两个可配置时区: quota (配额估算 / Key 每日预算) 与 display (统计分桶 / 日志导出时间),
+// 所有"按天"的计算都经过 `Zone`, 以正确处理夏令时切换日 (23 / 25 小时) 与重复的本地小时。
+
+use chrono::{
+    DateTime, Datelike, FixedOffset, Local, LocalResult, NaiveDate, NaiveDateTime, NaiveTime,
+    Offset, TimeZone, Timelike, Utc,
+};
+use chrono_tz::Tz;
+use once_cell::sync::Lazy;
+use std::sync::RwLock;
+
+pub const DEFAULT_QUOTA_TIMEZONE: &str = "America/Los_Angeles";
+/// 使用系统本地时区
+pub const LOCAL_TIMEZONE: &str = "local";
+
+/// 1970-01-01 距公元元年的天数 (日序号以 Unix 纪元为 0)
+const UNIX_EPOCH_DAYS_FROM_CE: i64 = 719_163;
+/// 零点落在夏令时间隙中时, 向后查找首个有效本地时间的步长 (分钟) 与上限
+const GAP_STEP_MINUTES: i64 = 15;
+const GAP_MAX_STEPS: i64 = 4 * 24;
+
+static ZONES: Lazy<RwLock<Zones>> = Lazy::new(|| RwLock::new(Zones::default()));
+
+#[derive(Debug, Clone, Copy)]
+struct Zones {
+    quota: Zone,
+    display: Zone,
+}
+
+impl Default for Zones {
+    fn default() -> Self {
+        Self {
+            quota: Zone::Named(chrono_tz::America::Los_Angeles),
+            display: Zone::Local,
+        }
+    }
+}
+
+/// 系统本地时区或 IANA 命名时区
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Zone {
+    Local,
+    Named(Tz),
+}
+
+impl Zone {
+    /// 解析时区名; 空字符串或 "local" 表示系统本地时区
+    pub fn parse(name: &str) -> Result<Self, String> {
+        let name = name.trim();
+        if name.is_empty() || name.eq_ignore_ascii_case(LOCAL_TIMEZONE) {
+            return Ok(Self::Local);
+        }
+        name.parse::<Tz>()
+            .map(Self::Named)
+            .map_err(|_| format!("Unknown time zone '{}' (expected an IANA name such as Asia/Shanghai, or \"local\")", name))
+    }
+
+    fn utc(ts: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(ts, 0).unwrap_or_default()
+    }
+
+    /// `ts` (unix 秒) 对应的本地时间
+    pub fn naive_at(&self, ts: i64) -> NaiveDateTime {
+        match self {
+            Self::Local => Self::utc(ts).with_timezone(&Local).naive_local(),
+            Self::Named(tz) => Self::utc(ts).with_timezone(tz).naive_local(),
+        }
+    }
+
+    /// `ts` 时刻的 UTC 偏移
+    pub fn offset_at(&self, ts: i64) -> FixedOffset {
+        match self {
+            Self::Local => *Self::utc(ts).with_timezone(&Local).offset(),
+            Self::Named(tz) => Self::utc(ts).with_timezone(tz).offset().fix(),
+        }
+    }
+
+    /// 本地时间对应的 unix 秒 (间隙中为 None, 重复时为两个候选)
+    fn resolve(&self, naive: NaiveDateTime) -> LocalResult<i64> {
+        match self {
+            Self::Local => Local.from_local_datetime(&naive).map(|t| t.timestamp()),
+            Self::Named(tz) => tz.from_local_datetime(&naive).map(|t| t.timestamp()),
+        }
+    }
+
+    /// 本地日期 `date` 的起点 (unix 秒); 零点不存在时取当天首个有效时刻
+    pub fn day_start(&self, date: NaiveDate) -> i64 {
+        let midnight = date.and_time(NaiveTime::MIN);
+        for step in 0..=GAP_MAX_STEPS {
+            match self.resolve(midnight + chrono::Duration::minutes(step * GAP_STEP_MINUTES)) {
+                LocalResult::Single(ts) | LocalResult::Ambiguous(ts, _) => return ts,
+                LocalResult::None => continue,
+            }
+        }
+        midnight.and_utc().timestamp()
+    }
+
+    pub fn date_at(&self, ts: i64) -> NaiveDate {
+        self.naive_at(ts).date()
+    }
+
+    /// 本地日序号 (1970-01-01 为 0), 跨夏令时切换也逐日递增 1
+    pub fn day_index(&self, ts: i64) -> i64 {
+        self.date_at(ts).num_days_from_ce() as i64 - UNIX_EPOCH_DAYS_FROM_CE
+    }
+
+    /// 下一个本地零点 (unix 秒)
+    pub fn next_day_start(&self, ts: i64) -> i64 {
+        let today = self.date_at(ts);
+        match today.succ_opt() {
+            Some(tomorrow) => self.day_start(tomorrow),
+            None => ts + 86_400,
+        }
+    }
+
+    /// 日分桶标签, 如 "2024-01-15"
+    pub fn day_label(&self, ts: i64) -> String {
+        self.date_at(ts).format("%Y-%m-%d").to_string()
+    }
+
+    /// 周分桶标签, 如 "2024-W02" (周一为一周起点, 与 SQLite `%W` 一致)
+    pub fn week_label(&self, ts: i64) -> String {
+        self.date_at(ts).format("%Y-W%W").to_string()
+    }
+
+    /// 小时分桶标签, 如 "2024-01-15 14:00"。
+    /// 夏令时结束时重复出现的本地小时, 第二次会带上 UTC 偏移 (如 "2024-11-03 01:00 -08:00"),
+    /// 两个小时各自成桶且按时间顺序排列。
+    pub fn hour_label(&self, ts: i64) -> String {
+        let naive = self.naive_at(ts);
+        let hour_start = naive.date().and_time(NaiveTime::MIN) + chrono::Duration::hours(naive.hour() as i64);
+        let label = hour_start.format("%Y-%m-%d %H:00").to_string();
+        match self.resolve(hour_start) {
+            LocalResult::Ambiguous(_, later) if ts >= later => format!("{} {}", label, self.offset_at(ts)),
+            _ => label,
+        }
+    }
+
+    /// 带偏移的 RFC 3339 时间
+    pub fn rfc3339(&self, ts: i64) -> String {
+        Self::utc(ts).with_timezone(&self.offset_at(ts)).to_rfc3339()
+    }
+}
+
+/// 应用配置中的两个时区 (启动加载与保存配置时调用); 任一无效时保持原设置并返回错误
+pub fn set_zones(quota_timezone: &str, display_timezone: &str) -> Result<(), String> {
+    let quota = Zone::parse(quota_timezone).map_err(|e| format!("quota_timezone: {}", e))?;
+    let display = Zone::parse(display_timezone).map_err(|e| format!("display_timezone: {}", e))?;
+    if let Ok(mut zones) = ZONES.write() {
+        *zones = Zones { quota, display };
+    }
+    Ok(())
+}
+
+fn zones() -> Zones {
+    ZONES.read().map(|z| *z).unwrap_or_default()
+}
+
+/// 每日配额 / Key 预算的日界时区
+pub fn quota_zone() -> Zone {
+    zones().quota
+}
+
+/// 统计分桶与日志导出时间的时区
+pub fn display_zone() -> Zone {
+    zones().display
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn la() -> Zone {
+        Zone::parse("America/Los_Angeles").unwrap()
+    }
+
+    fn utc_ts(y: i32, m: u32, d: u32, h: u32) -> i64 {
+        Utc.with_ymd_and_hms(y, m, d, h, 0, 0).unwrap().timestamp()
+    }
+
+    #[test]
+    fn test_parse_zone_names() {
+        assert_eq!(Zone::parse("").unwrap(), Zone::Local);
+        assert_eq!(Zone::parse(" Local ").unwrap(), Zone::Local);
+        assert_eq!(Zone::parse("Asia/Shanghai").unwrap(), Zone::Named(chrono_tz::Asia::Shanghai));
+        assert!(Zone::parse("Mars/Olympus").is_err());
+    }
+
+    #[test]
+    fn test_quota_day_boundary_is_pacific_midnight() {
+        let zone = la();
+        // 2026-01-15 00:00 PST = 08:00 UTC
+        let midnight = utc_ts(2026, 1, 15, 8);
+        assert_eq!(zone.day_label(midnight - 1), "2026-01-14");
+        assert_eq!(zone.day_label(midnight), "2026-01-15");
+        assert_eq!(zone.day_index(midnight) - zone.day_index(midnight - 1), 1);
+        assert_eq!(zone.next_day_start(midnight - 3600), midnight);
+        assert_eq!(zone.next_day_start(midnight), utc_ts(2026, 1, 16, 8));
+    }
+
+    #[test]
+    fn test_spring_forward_day_has_23_hour_buckets() {
+        let zone = la();
+        // 2026-03-08: 02:00 PST 跳到 03:00 PDT
+        let start = zone.day_start(NaiveDate::from_ymd_opt(2026, 3, 8).unwrap());
+        let end = zone.next_day_start(start);
+        assert_eq!(start, utc_ts(2026, 3, 8, 8));
+        assert_eq!(end - start, 23 * 3600);
+
+        let labels: Vec<String> = (start..end).step_by(3600).map(|ts| zone.hour_label(ts)).collect();
+        assert_eq!(labels.iter().collect::<HashSet<_>>().len(), 23);
+        assert!(!labels.contains(&"2026-03-08 02:00".to_string()));
+        assert!(labels.iter().all(|l| l.starts_with("2026-03-08")));
+        assert_eq!(zone.day_index(end) - zone.day_index(start), 1);
+    }
+
+    #[test]
+    fn test_fall_back_day_has_25_ordered_hour_buckets() {
+        let zone = la();
+        // 2026-11-01: 02:00 PDT 回拨到 01:00 PST, 01:00 出现两次
+        let start = zone.day_start(NaiveDate::from_ymd_opt(2026, 11, 1).unwrap());
+        let end = zone.next_day_start(start);
+        assert_eq!(start, utc_ts(2026, 11, 1, 7));
+        assert_eq!(end - start, 25 * 3600);
+
+        let labels: Vec<String> = (start..end).step_by(3600).map(|ts| zone.hour_label(ts)).collect();
+        assert_eq!(labels.iter().collect::<HashSet<_>>().len(), 25);
+        assert_eq!(labels[1], "2026-11-01 01:00");
+        assert_eq!(labels[2], "2026-11-01 01:00 -08:00");
+        assert_eq!(labels[3], "2026-11-01 02:00");
+        // 按字符串排序 (BTreeMap 分桶) 仍保持时间顺序
+        let mut sorted = labels.clone();
+        sorted.sort();
+        assert_eq!(sorted, labels);
+        assert!((start..end).step_by(3600).all(|ts| zone.day_label(ts) == "2026-11-01"));
+    }
+
+    #[test]
+    fn test_rfc3339_uses_zone_offset() {
+        let zone = la();
+        assert_eq!(zone.rfc3339(utc_ts(2026, 7, 1, 12)), "2026-07-01T05:00:00-07:00");
+        assert_eq!(zone.rfc3339(utc_ts(2026, 12, 1, 12)), "2026-12-01T04:00:00-08:00");
+    }
+}
@@ -5,6 +5,7 @@ import { useEffect, useState } from 'react';
 import { RouterProvider } from 'react-router-dom';
 import { listen } from '@tauri-apps/api/event';
 import { useQueryClient } from '@tanstack/react-query';
+import { useTranslation } from 'react-i18next';
 
 import { router } from './router';
 import { QueryProvider, I18nProvider } from './providers';
@@ -26,6 +27,7 @@ function AppContent() {
   const { config, loadConfig } = useConfigStore();
   const checkDebugConsoleEnabled = useDebugConsole(s => s.checkEnabled);
   const queryClient = useQueryClient();
+  const { t } = useTranslation();
 
   // Invalidate accounts queries (replaces fetchCurrentAccount/fetchAccounts)
   const refreshAccounts = () => {
@@ -74,12 +76,20 @@ function AppContent() {
       })
     );
 
+    // Listen for the first debug log write failure (emitted once per run)
+    unlistenPromises.push(
+      listen<{ path: string; error: string }>('proxy://debug-log-write-failed', (event) => {
+        console.warn('[App] Debug log write failed:', event.payload);
+        showToast(t('monitor.debug_log_write_failed', event.payload), 'warning', 8000);
+      })
+    );
+
     return () => {
       Promise.all(unlistenPromises).then(unlisteners => {
         unlisteners.forEach(unlisten => unlisten());
       });
     };
-  }, [queryClient]);
+  }, [queryClient, t]);
 
   // Update notification state
   const [showUpdateNotification, setShowUpdateNotification] = useState(false);
@@ -7,5 +7,8 @@ export type {
   QuotaData, 
   ModelQuota, 
   DeviceProfile, 
-  DeviceProfileVersion 
+  DeviceProfileVersion,
+  ProjectSetupRequired,
+  AccountLastError,
+  AccountHealth
 } from './types';
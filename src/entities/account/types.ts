@@ -21,10 +21,47 @@ export interface Account {
   validation_blocked_reason?: string;
   verification_needed?: boolean;
   verification_url?: string;
+  project_setup_required?: ProjectSetupRequired;
+  /** 账号标签, 如从官方客户端导入的 "imported" */
+  tags?: string[];
+  /** 调度优先级, 越高越先被选中 */
+  priority?: number;
+  /** 用户备注 */
+  notes?: string;
+  /** 最近一次上游失败, 下一次成功请求后清除 */
+  last_error?: AccountLastError;
   created_at: number;
   last_used: number;
 }
 
+export interface AccountLastError {
+  /** unix 秒 */
+  at: number;
+  status: number;
+  message: string;
+}
+
+/** get_account_health 的单个账号概览 (不含 token) */
+export interface AccountHealth {
+  account_id: string;
+  email: string;
+  notes?: string | null;
+  last_error?: AccountLastError | null;
+  disabled: boolean;
+  proxy_disabled: boolean;
+  in_pool: boolean;
+  health_score?: number | null;
+  circuit_breaker?: string | null;
+}
+
+/** 项目未开通 (403 onboarding), 完成设置后调用 retry_project_setup */
+export interface ProjectSetupRequired {
+  reason: string;
+  project_id?: string | null;
+  setup_url?: string | null;
+  detected_at: number;
+}
+
 export interface TokenData {
   access_token: string;
   refresh_token: string;
@@ -7,10 +7,15 @@ export type {
   DebugLoggingConfig,
   SchedulingMode,
   StickySessionConfig,
+  RetryConfig,
+  RateLimitPolicy,
+  LoadScoreWeights,
   ZaiDispatchMode,
+  ProviderConfig,
   ZaiMcpConfig,
   ZaiModelDefaults,
   ZaiConfig,
+  ZaiModelList,
   ScheduledWarmupConfig,
   QuotaProtectionConfig,
   PinnedQuotaModelsConfig,
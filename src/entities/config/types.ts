@@ -6,27 +6,169 @@ export interface UpstreamProxyConfig {
   url: string;
 }
 
+export interface UpstreamTimeoutConfig {
+  connect_timeout_secs: number;
+  tls_handshake_timeout_secs: number;
+  first_byte_timeout_secs: number;
+  read_timeout_secs: number;
+  quick_timeout_secs: number;
+}
+
+export interface UpstreamPoolConfig {
+  pool_idle_timeout_secs: number;
+  pool_max_idle_per_host: number;
+  http2_keep_alive_interval_secs: number; // 0 = disabled
+  http2_keep_alive_timeout_secs: number;
+  http2_adaptive_window: boolean;
+}
+
+export type KeyScope = "inference" | "read_stats" | "admin";
+
+export interface ScopedApiKey {
+  key: string;
+  label?: string;
+  /** 未填写时拥有全部权限 */
+  scopes?: KeyScope[];
+}
+
 export interface ProxyConfig {
   enabled: boolean;
   allow_lan_access?: boolean;
   auth_mode?: "off" | "strict" | "all_except_health" | "auto";
   port: number;
+  /** 之前使用过的端口 (最近的在前), 由后端在保存时维护 */
+  port_history?: number[];
+  admin_port?: number | null;
   api_key: string;
   admin_password?: string;
+  /** 额外的带权限范围的 API Key (如仪表盘只读 Key) */
+  api_keys?: ScopedApiKey[];
   auto_start: boolean;
   custom_mapping?: Record<string, string>;
   request_timeout: number;
   enable_logging: boolean;
   debug_logging?: DebugLoggingConfig;
   upstream_proxy: UpstreamProxyConfig;
+  upstream_timeouts?: UpstreamTimeoutConfig;
+  upstream_pool?: UpstreamPoolConfig;
   zai?: ZaiConfig;
+  providers?: ProviderConfig[];
+  thinking_overrides?: Record<string, boolean>;
+  thinking_defaults?: Record<string, ThinkingDefaultPolicy>;
+  thinking_budget_defaults?: Record<string, number>;
+  mapping_thinking_defaults?: Record<string, ThinkingDefaultPolicy>;
+  post_process?: PostProcessConfig;
+  key_budgets?: Record<string, KeyBudget>;
+  startup_verification?: StartupVerificationConfig;
+  account_header_privacy?: "full" | "pseudonym" | "omit";
   scheduling?: StickySessionConfig;
+  retry?: RetryConfig;
+  /** 命名的系统提示注入模板 */
+  system_injection_profiles?: Record<string, SystemInjectionProfile>;
+  /** API Key -> 注入的模板名 */
+  key_system_injections?: Record<string, string[]>;
+  /** 自定义映射条目 (custom_mapping 的 key) -> 注入的模板名 */
+  mapping_system_injections?: Record<string, string[]>;
+  /** response_format=url 时生成图片的保留时长 (秒) */
+  image_url_ttl_secs?: number;
   experimental?: ExperimentalConfig;
 }
 
+/** 系统提示注入模板, 以分隔标记包裹后放在客户端 system 内容之前或之后 */
+export interface SystemInjectionProfile {
+  text: string;
+  position?: "prepend" | "append";
+}
+
+/** 主链路重试策略, 修改后对新请求立即生效 */
+export interface RetryConfig {
+  /** 1-10, 仍受账号池大小约束 (最多为账号数 + 1) */
+  max_attempts: number;
+  /** 流式首块等待超时 (秒), 5-600 */
+  peek_timeout_secs: number;
+  /** 退避基准 (毫秒), 内置退避按 base_delay_ms / 2000 缩放, 0-60000 */
+  base_delay_ms: number;
+  /** 流中途停滞看门狗 (秒), 0 表示关闭, 否则 10-3600 */
+  stall_timeout_secs: number;
+  /** 流式响应先缓存的输出字符数, 缓存期间停滞时透明换号重试, 0-10000 */
+  stall_retry_chars: number;
+}
+
+/** 客户端未指定 thinking 时的默认策略 */
+export type ThinkingDefaultPolicy = "on" | "off" | "client_only";
+
+/** 单个 API Key 的每日预算, 0 表示不限制 */
+export interface KeyBudget {
+  tokens_per_day: number;
+  requests_per_day: number;
+}
+
+/** 启动账号体检 */
+export interface StartupVerificationConfig {
+  enabled: boolean;
+  cache_ttl_secs: number;
+}
+
+export type VerificationStatus =
+  | "ok"
+  | "needs_reauth"
+  | "project_misconfigured"
+  | "quota_exhausted"
+  | "failed";
+
+export interface AccountVerification {
+  account_id: string;
+  email: string;
+  status: VerificationStatus;
+  detail: string | null;
+  reset_time: string | null;
+  checked_at: number;
+  cached: boolean;
+  /** 体检时 refresh token 的指纹 (缓存是否仍对应同一凭据) */
+  token_fingerprint?: string;
+}
+
+/** proxy://verification-progress 事件载荷 */
+export interface VerificationProgress {
+  done: number;
+  total: number;
+  result: AccountVerification;
+}
+
+export interface ReplaceRule {
+  name?: string | null;
+  find: string;
+  replace?: string;
+  regex?: boolean;
+}
+
+export interface PostProcessProfile {
+  rules: ReplaceRule[];
+  stop_phrases: string[];
+}
+
+export interface PostProcessConfig {
+  enabled: boolean;
+  global: PostProcessProfile;
+  per_model?: Record<string, PostProcessProfile>;
+}
+
+export interface ProviderConfig {
+  id: string;
+  enabled?: boolean;
+  base_url: string;
+  api_key: string;
+  protocols?: Array<"anthropic" | "openai" | "gemini">;
+  dispatch_mode?: ZaiDispatchMode;
+  models?: string[];
+  model_mapping?: Record<string, string>;
+}
+
 export interface DebugLoggingConfig {
   enabled: boolean;
   output_dir?: string;
+  allow_raw_stream?: boolean;
+  allow_raw_messages?: boolean;
 }
 
 export type SchedulingMode =
@@ -42,6 +184,29 @@ export interface StickySessionConfig {
   selected_accounts: string[];
   selected_models: Record<string, string[]>;
   strict_selected: boolean;
+  rate_limit_policy?: RateLimitPolicy;
+  load_weights?: LoadScoreWeights;
+  performance_weighted?: boolean;
+  weighted_seed?: number | null;
+  min_request_interval_ms?: number;
+  account_min_interval_ms?: Record<string, number>;
+  pacing_max_delay_ms?: number;
+  quota_estimate_min_remaining_pct?: number | null;
+  session_prewarm?: boolean;
+}
+
+export interface LoadScoreWeights {
+  sessions: number;
+  in_flight: number;
+  throughput: number;
+  active_session_secs: number;
+}
+
+export interface RateLimitPolicy {
+  limited_statuses: number[];
+  default_durations: Record<string, number>;
+  server_error_threshold: number;
+  server_error_window_secs: number;
 }
 
 export type ZaiDispatchMode = "off" | "exclusive" | "pooled" | "fallback";
@@ -67,6 +232,16 @@ export interface ZaiConfig {
   model_mapping?: Record<string, string>;
   models: ZaiModelDefaults;
   mcp: ZaiMcpConfig;
+  custom_models?: string[];
+  models_cache_ttl_secs?: number;
+}
+
+/** fetch_zai_models 返回值; stale 表示上游获取失败, 返回的是旧快照 */
+export interface ZaiModelList {
+  models: string[];
+  stale: boolean;
+  fetched_at: number | null;
+  error: string | null;
 }
 
 export interface ScheduledWarmupConfig {
@@ -86,9 +261,41 @@ export interface PinnedQuotaModelsConfig {
 
 export interface ExperimentalConfig {
   enable_usage_scaling: boolean;
+  /** 用量缩放开启时, 从 input_tokens 中扣除系统提示注入的估算 token */
+  hide_system_injections_in_usage?: boolean;
   context_compression_threshold_l1?: number;
   context_compression_threshold_l2?: number;
   context_compression_threshold_l3?: number;
+  never_auto_enable_thinking?: boolean;
+  /** count_tokens queries upstream countTokens (opt-in, default off; falls back to the local estimate) */
+  enable_upstream_count_tokens?: boolean;
+  /** retry: regenerate once with a corrective note; annotate: add validation_error to the tool call */
+  strict_tool_violation_mode?: 'retry' | 'annotate';
+  /** Request Gemini's VALIDATED function calling mode for strict tools (falls back when rejected) */
+  strict_tools_validated_mode?: boolean;
+  enable_tool_result_digests?: boolean;
+  tool_result_digest_budget?: number;
+  /** preserve: pass through to providers that accept it (dropped for Gemini); drop; marker: short text placeholder */
+  redacted_thinking_mode?: 'preserve' | 'drop' | 'marker';
+  /** Drop content chunks the upstream replays from the start of the stream after an internal retry (off by default) */
+  dedup_resent_chunks?: boolean;
+  background_routing?: BackgroundRoutingConfig;
+}
+
+export type BackgroundTaskType =
+  | 'title_generation'
+  | 'simple_summary'
+  | 'context_compression'
+  | 'prompt_suggestion'
+  | 'system_message'
+  | 'environment_probe'
+  | 'compaction';
+
+/** Model used for detected background tasks; unlisted types keep the built-in default */
+export interface BackgroundRoutingConfig {
+  /** false: background tasks keep the client's model */
+  enabled: boolean;
+  routes: Partial<Record<BackgroundTaskType, string>>;
 }
 
 export interface CircuitBreakerConfig {
@@ -117,6 +324,8 @@ export interface AppConfig {
   pinned_quota_models: PinnedQuotaModelsConfig;
   circuit_breaker: CircuitBreakerConfig;
   validation_block_minutes?: number;
+  quota_timezone?: string; // IANA zone for daily quota resets / key budgets, default "America/Los_Angeles"
+  display_timezone?: string; // Stats buckets and log export times, default "local"
   proxy: ProxyConfig;
 }
 
@@ -6,6 +6,7 @@ export { accountKeys } from './keys';
 export {
   useAccounts,
   useCurrentAccount,
+  useAccountHealth,
   useDeviceProfiles,
   type DeviceProfilesResponse,
 } from './queries';
@@ -14,6 +15,10 @@ export {
   useAddAccount,
   useDeleteAccount,
   useDeleteAccounts,
+  usePauseAccounts,
+  useSetAccountsPriority,
+  useSetAccountsTags,
+  useSetAccountNotes,
   useSwitchAccount,
   useRefreshQuota,
   useRefreshAllQuotas,
@@ -27,6 +32,7 @@ export {
   useImportV1Accounts,
   useImportFromDb,
   useImportFromCustomDb,
+  useImportFromAntigravity,
   useSyncAccountFromDb,
   useBindDeviceProfile,
   useBindDeviceProfileWithProfile,
@@ -35,4 +41,7 @@ export {
   useRestoreDeviceVersion,
   useDeleteDeviceVersion,
   type RefreshStats,
+  type AntigravityImportReport,
+  type AntigravityImportItem,
+  type BatchItemResult,
 } from './mutations';
@@ -8,5 +8,6 @@ export const accountKeys = {
   details: () => [...accountKeys.all, 'detail'] as const,
   detail: (id: string) => [...accountKeys.details(), id] as const,
   current: () => [...accountKeys.all, 'current'] as const,
+  health: () => [...accountKeys.all, 'health'] as const,
   deviceProfiles: (id: string) => [...accountKeys.detail(id), 'device-profiles'] as const,
 };
@@ -16,6 +16,35 @@ export interface RefreshStats {
   details: string[];
 }
 
+export type AntigravityImportStatus =
+  | 'pending_confirmation'
+  | 'imported'
+  | 'skipped'
+  | 'failed'
+  | 'not_found'
+  | 'unreadable';
+
+export interface AntigravityImportItem {
+  source: string;
+  candidate_id?: string | null;
+  email?: string | null;
+  project_id?: string | null;
+  already_imported: boolean;
+  status: AntigravityImportStatus;
+  message?: string | null;
+}
+
+export interface AntigravityImportReport {
+  items: AntigravityImportItem[];
+  imported: Account[];
+}
+
+export interface BatchItemResult {
+  account_id: string;
+  ok: boolean;
+  error?: string;
+}
+
 // Service functions
 async function addAccount(email: string, refreshToken: string): Promise<Account> {
   return await invoke<Account>('add_account', { email, refreshToken });
@@ -25,8 +54,25 @@ async function deleteAccount(accountId: string): Promise<void> {
   return await invoke<void>('delete_account', { accountId });
 }
 
-async function deleteAccounts(accountIds: string[]): Promise<void> {
-  return await invoke<void>('delete_accounts', { accountIds });
+/** force 跳过 "代理运行时不能删除最后一个可用账号" 的保护 */
+async function deleteAccounts({ accountIds, force }: { accountIds: string[]; force?: boolean }): Promise<BatchItemResult[]> {
+  return await invoke<BatchItemResult[]>('delete_accounts', { accountIds, force: force ?? false });
+}
+
+async function pauseAccounts({ accountIds, force }: { accountIds: string[]; force?: boolean }): Promise<BatchItemResult[]> {
+  return await invoke<BatchItemResult[]>('pause_accounts', { accountIds, force: force ?? false });
+}
+
+async function setAccountsPriority({ accountIds, priority }: { accountIds: string[]; priority: number }): Promise<BatchItemResult[]> {
+  return await invoke<BatchItemResult[]>('set_accounts_priority', { accountIds, priority });
+}
+
+async function setAccountsTags({ accountIds, add, remove }: { accountIds: string[]; add: string[]; remove: string[] }): Promise<BatchItemResult[]> {
+  return await invoke<BatchItemResult[]>('set_accounts_tags', { accountIds, add, remove });
+}
+
+async function setAccountNotes({ accountId, notes }: { accountId: string; notes: string | null }): Promise<BatchItemResult[]> {
+  return await invoke<BatchItemResult[]>('set_account_notes', { accountId, notes });
 }
 
 async function switchAccount(accountId: string): Promise<void> {
@@ -77,6 +123,11 @@ async function importFromDb(): Promise<Account> {
   return await invoke<Account>('import_from_db');
 }
 
+/** 不传 confirmed 时只扫描官方客户端, 传入候选 ID 时导入这些账号 */
+async function importFromAntigravity(confirmed?: string[]): Promise<AntigravityImportReport> {
+  return await invoke<AntigravityImportReport>('import_from_antigravity', { confirmed: confirmed ?? null });
+}
+
 async function importFromCustomDb(path: string): Promise<Account> {
   return await invoke<Account>('import_custom_db', { path });
 }
@@ -143,18 +194,58 @@ export function useDeleteAccount() {
   });
 }
 
+/** 结果逐个账号返回 (部分失败不抛错), 提示由调用方根据结果决定 */
 export function useDeleteAccounts() {
   const queryClient = useQueryClient();
-  const { t } = useTranslation();
 
   return useMutation({
     mutationFn: deleteAccounts,
+    onSettled: () => {
+      queryClient.invalidateQueries({ queryKey: accountKeys.all });
+    },
+  });
+}
+
+export function usePauseAccounts() {
+  const queryClient = useQueryClient();
+
+  return useMutation({
+    mutationFn: pauseAccounts,
     onSuccess: () => {
       queryClient.invalidateQueries({ queryKey: accountKeys.all });
-      showToast(t('accounts.toast.bulk_delete_success', 'Accounts deleted'), 'success');
     },
-    onError: (error) => {
-      showToast(`${t('accounts.toast.delete_error', 'Failed to delete')}: ${error}`, 'error');
+  });
+}
+
+export function useSetAccountsPriority() {
+  const queryClient = useQueryClient();
+
+  return useMutation({
+    mutationFn: setAccountsPriority,
+    onSuccess: () => {
+      queryClient.invalidateQueries({ queryKey: accountKeys.all });
+    },
+  });
+}
+
+export function useSetAccountsTags() {
+  const queryClient = useQueryClient();
+
+  return useMutation({
+    mutationFn: setAccountsTags,
+    onSuccess: () => {
+      queryClient.invalidateQueries({ queryKey: accountKeys.all });
+    },
+  });
+}
+
+export function useSetAccountNotes() {
+  const queryClient = useQueryClient();
+
+  return useMutation({
+    mutationFn: setAccountNotes,
+    onSuccess: () => {
+      queryClient.invalidateQueries({ queryKey: accountKeys.all });
     },
   });
 }
@@ -327,6 +418,24 @@ export function useImportV1Accounts() {
   });
 }
 
+export function useImportFromAntigravity() {
+  const queryClient = useQueryClient();
+  const { t } = useTranslation();
+
+  return useMutation({
+    mutationFn: importFromAntigravity,
+    onSuccess: (report) => {
+      if (report.imported.length > 0) {
+        queryClient.invalidateQueries({ queryKey: accountKeys.all });
+        showToast(t('accounts.toast.import_success', 'Import completed'), 'success');
+      }
+    },
+    onError: (error) => {
+      showToast(`${t('accounts.toast.import_error', 'Import failed')}: ${error}`, 'error');
+    },
+  });
+}
+
 export function useImportFromDb() {
   const queryClient = useQueryClient();
   const { t } = useTranslation();
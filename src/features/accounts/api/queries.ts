@@ -3,7 +3,7 @@
 
 import { useQuery } from '@tanstack/react-query';
 import { invoke } from '@/shared/api';
-import type { Account, QuotaData, DeviceProfile, DeviceProfileVersion } from '@/entities/account';
+import type { Account, AccountHealth, QuotaData, DeviceProfile, DeviceProfileVersion } from '@/entities/account';
 import { accountKeys } from './keys';
 
 // Response types
@@ -30,6 +30,10 @@ async function getCurrentAccount(): Promise<Account | null> {
   return await invoke<Account | null>('get_current_account');
 }
 
+async function getAccountHealth(): Promise<AccountHealth[]> {
+  return await invoke<AccountHealth[]>('get_account_health');
+}
+
 async function getDeviceProfiles(accountId: string): Promise<DeviceProfilesResponse> {
   return await invoke<DeviceProfilesResponse>('get_device_profiles', { accountId });
 }
@@ -52,6 +56,13 @@ export function useCurrentAccount() {
   });
 }
 
+export function useAccountHealth() {
+  return useQuery({
+    queryKey: accountKeys.health(),
+    queryFn: getAccountHealth,
+  });
+}
+
 export function useDeviceProfiles(accountId: string) {
   return useQuery({
     queryKey: accountKeys.deviceProfiles(accountId),
@@ -10,6 +10,9 @@ export {
   useAddAccount,
   useDeleteAccount,
   useDeleteAccounts,
+  usePauseAccounts,
+  useSetAccountsPriority,
+  useSetAccountsTags,
   useSwitchAccount,
   useRefreshQuota,
   useRefreshAllQuotas,
@@ -23,6 +26,7 @@ export {
   useImportV1Accounts,
   useImportFromDb,
   useImportFromCustomDb,
+  useImportFromAntigravity,
   useSyncAccountFromDb,
   useBindDeviceProfile,
   useBindDeviceProfileWithProfile,
@@ -31,6 +35,9 @@ export {
   useRestoreDeviceVersion,
   useDeleteDeviceVersion,
   type RefreshStats,
+  type AntigravityImportReport,
+  type AntigravityImportItem,
+  type BatchItemResult,
   type DeviceProfilesResponse,
 } from './api';
 
@@ -2,7 +2,7 @@ import { useState, useEffect, useRef, memo } from 'react';
 import { createPortal } from 'react-dom';
 import { 
     Plus, Database, Globe, Key, Loader2, CheckCircle2, XCircle, 
-    Copy, Check, Link2, Sparkles, Upload, FolderOpen, X
+    Copy, Check, Link2, Sparkles, Upload, FolderOpen, X, Search
 } from 'lucide-react';
 import { motion, AnimatePresence } from 'framer-motion';
 import { useTranslation } from 'react-i18next';
@@ -19,8 +19,10 @@ import {
     useCancelOAuthLogin, 
     useImportFromDb, 
     useImportV1Accounts, 
-    useImportFromCustomDb 
+    useImportFromCustomDb,
+    useImportFromAntigravity
 } from '@/features/accounts';
+import type { AntigravityImportItem } from '@/features/accounts';
 
 interface AddAccountDialogProps {
     onAdd: (email: string, refreshToken: string) => Promise<void>;
@@ -87,6 +89,7 @@ export function AddAccountDialog({ onAdd, children }: AddAccountDialogProps) {
     const importFromDbMutation = useImportFromDb();
     const importV1Mutation = useImportV1Accounts();
     const importCustomDbMutation = useImportFromCustomDb();
+    const importAntigravityMutation = useImportFromAntigravity();
     
     const [isOpen, setIsOpen] = useState(false);
     const [activeTab, setActiveTab] = useState<TabType>(isTauri() ? 'oauth' : 'token');
@@ -94,6 +97,9 @@ export function AddAccountDialog({ onAdd, children }: AddAccountDialogProps) {
     const [oauthUrl, setOauthUrl] = useState('');
     const [oauthUrlCopied, setOauthUrlCopied] = useState(false);
     const [manualCode, setManualCode] = useState('');
+    // 官方客户端扫描结果与勾选的候选账号
+    const [antigravityItems, setAntigravityItems] = useState<AntigravityImportItem[] | null>(null);
+    const [antigravitySelected, setAntigravitySelected] = useState<string[]>([]);
 
     // UI State
     const [status, setStatus] = useState<Status>('idle');
@@ -199,6 +205,8 @@ export function AddAccountDialog({ onAdd, children }: AddAccountDialogProps) {
         setOauthUrl('');
         setOauthUrlCopied(false);
         setManualCode('');
+        setAntigravityItems(null);
+        setAntigravitySelected([]);
     };
 
     const handleAction = async (
@@ -402,6 +410,49 @@ export function AddAccountDialog({ onAdd, children }: AddAccountDialogProps) {
     const handleImportDb = () => handleAction(t('accounts.add.tabs.import'), () => importFromDbMutation.mutateAsync());
     const handleImportV1 = () => handleAction(t('accounts.add.import.btn_v1'), () => importV1Mutation.mutateAsync());
 
+    const handleScanAntigravity = async () => {
+        setStatus('loading');
+        setMessage(`${t('accounts.add.import.btn_scan_antigravity')}...`);
+        try {
+            const report = await importAntigravityMutation.mutateAsync(undefined);
+            const selectable = report.items.filter(i => i.candidate_id && !i.already_imported);
+            setAntigravityItems(report.items);
+            setAntigravitySelected(selectable.map(i => i.candidate_id as string));
+            setStatus('idle');
+            setMessage('');
+        } catch (error) {
+            setStatus('error');
+            setMessage(`${t('accounts.add.import.btn_scan_antigravity')} ${t('common.error')}: ${String(error)}`);
+        }
+    };
+
+    const toggleAntigravityCandidate = (id: string) => {
+        setAntigravitySelected(prev => prev.includes(id) ? prev.filter(x => x !== id) : [...prev, id]);
+    };
+
+    const handleImportAntigravity = async () => {
+        if (antigravitySelected.length === 0) return;
+        setStatus('loading');
+        setMessage(`${t('accounts.add.import.btn_import_selected')}...`);
+        try {
+            const report = await importAntigravityMutation.mutateAsync(antigravitySelected);
+            setAntigravityItems(report.items);
+            setAntigravitySelected([]);
+            const failed = report.items.filter(i => i.status === 'failed').length;
+            setStatus(report.imported.length > 0 ? 'success' : 'error');
+            setMessage(t('accounts.add.import.antigravity_result', { imported: report.imported.length, failed }));
+            if (report.imported.length > 0 && failed === 0) {
+                setTimeout(() => {
+                    setIsOpen(false);
+                    resetState();
+                }, 1500);
+            }
+        } catch (error) {
+            setStatus('error');
+            setMessage(`${t('accounts.add.import.btn_import_selected')} ${t('common.error')}: ${String(error)}`);
+        }
+    };
+
     const handleImportCustomDb = async () => {
         if (!isTauri()) {
             alert(t('common.tauri_api_not_loaded') || 'Desktop app required');
@@ -764,6 +815,88 @@ export function AddAccountDialog({ onAdd, children }: AddAccountDialogProps) {
                                                     <div className="flex-1 h-px bg-white/5" />
                                                 </div>
 
+                                                {/* Official Antigravity client import */}
+                                                <div className="p-4 bg-zinc-800/30 border border-white/5 rounded-xl space-y-3">
+                                                    <div className="flex items-center gap-3">
+                                                        <div className="p-2 rounded-lg bg-amber-500/10 text-amber-400">
+                                                            <Search className="w-4 h-4" />
+                                                        </div>
+                                                        <div>
+                                                            <h4 className="font-bold text-white text-sm">{t('accounts.add.import.scheme_antigravity')}</h4>
+                                                            <p className="text-[11px] text-zinc-500">{t('accounts.add.import.scheme_antigravity_desc')}</p>
+                                                        </div>
+                                                    </div>
+                                                    {antigravityItems && (
+                                                        <div className="space-y-1.5 max-h-40 overflow-y-auto">
+                                                            {antigravityItems.length === 0 && (
+                                                                <p className="text-[11px] text-zinc-500">{t('accounts.add.import.antigravity_none')}</p>
+                                                            )}
+                                                            {antigravityItems.map((item, idx) => {
+                                                                const id = item.candidate_id;
+                                                                const selectable = !!id && !item.already_imported && item.status === 'pending_confirmation';
+                                                                return (
+                                                                    <label
+                                                                        key={id ?? `${item.source}-${idx}`}
+                                                                        className={cn(
+                                                                            "flex items-center gap-2 px-2 py-1.5 rounded-lg text-[11px] border border-white/5",
+                                                                            selectable ? "cursor-pointer hover:bg-white/5 text-zinc-300" : "text-zinc-500"
+                                                                        )}
+                                                                        title={item.source}
+                                                                    >
+                                                                        {selectable && (
+                                                                            <input
+                                                                                type="checkbox"
+                                                                                className="checkbox checkbox-xs"
+                                                                                checked={antigravitySelected.includes(id as string)}
+                                                                                onChange={() => toggleAntigravityCandidate(id as string)}
+                                                                                disabled={isDisabled}
+                                                                            />
+                                                                        )}
+                                                                        <span className="truncate flex-1">{item.email || item.source}</span>
+                                                                        <span className="shrink-0 font-mono">
+                                                                            {item.already_imported
+                                                                                ? t('accounts.add.import.antigravity_already')
+                                                                                : t(`accounts.add.import.antigravity_status.${item.status}`)}
+                                                                        </span>
+                                                                        {item.message && (
+                                                                            <span className="shrink-0 text-red-400 truncate max-w-[8rem]" title={item.message}>{item.message}</span>
+                                                                        )}
+                                                                    </label>
+                                                                );
+                                                            })}
+                                                        </div>
+                                                    )}
+                                                    <div className="grid grid-cols-2 gap-2">
+                                                        <motion.button
+                                                            whileHover={{ scale: 1.02 }}
+                                                            whileTap={{ scale: 0.98 }}
+                                                            onClick={handleScanAntigravity}
+                                                            disabled={isDisabled}
+                                                            className="py-2.5 bg-zinc-700/50 border border-white/5 text-zinc-300 font-medium rounded-xl hover:bg-amber-500/10 hover:border-amber-500/30 hover:text-amber-400 transition-all flex items-center justify-center gap-2 disabled:opacity-50"
+                                                        >
+                                                            <Search className="w-4 h-4" />
+                                                            {t('accounts.add.import.btn_scan_antigravity')}
+                                                        </motion.button>
+                                                        <motion.button
+                                                            whileHover={{ scale: 1.02 }}
+                                                            whileTap={{ scale: 0.98 }}
+                                                            onClick={handleImportAntigravity}
+                                                            disabled={isDisabled || antigravitySelected.length === 0}
+                                                            className="py-2.5 bg-zinc-700/50 border border-white/5 text-zinc-300 font-medium rounded-xl hover:bg-amber-500/10 hover:border-amber-500/30 hover:text-amber-400 transition-all flex items-center justify-center gap-2 disabled:opacity-50"
+                                                        >
+                                                            <Upload className="w-4 h-4" />
+                                                            {t('accounts.add.import.btn_import_selected', { count: antigravitySelected.length })}
+                                                        </motion.button>
+                                                    </div>
+                                                </div>
+
+                                                {/* Divider */}
+                                                <div className="flex items-center gap-4">
+                                                    <div className="flex-1 h-px bg-white/5" />
+                                                    <span className="text-[10px] font-bold text-zinc-600 uppercase">{t('accounts.add.import.or')}</span>
+                                                    <div className="flex-1 h-px bg-white/5" />
+                                                </div>
+
                                                 {/* V1 Import */}
                                                 <div className="p-4 bg-zinc-800/30 border border-white/5 rounded-xl space-y-3">
                                                     <div className="flex items-center gap-3">
@@ -23,6 +23,8 @@ export {
   useClearProxyLogs,
   useClearSessionBindings,
   useClearRateLimits,
+  useRetryProjectSetup,
+  useSetMaintenanceMode,
   useSetProxyMonitorEnabled,
   useInstallCloudflared,
   useStartCloudflared,
@@ -103,6 +103,48 @@ export function useClearRateLimits() {
   });
 }
 
+export interface SetMaintenanceModeInput {
+  enabled: boolean;
+  message?: string;
+  /** 自动退出前的时长 (秒); 不填则需手动退出 */
+  durationSecs?: number;
+}
+
+export function useSetMaintenanceMode() {
+  const queryClient = useQueryClient();
+  const { t } = useTranslation();
+
+  return useMutation({
+    mutationFn: (input: SetMaintenanceModeInput) => invoke<void>('set_maintenance_mode', { ...input }),
+    onSuccess: (_, input) => {
+      queryClient.invalidateQueries({ queryKey: proxyKeys.status() });
+      showToast(
+        input.enabled
+          ? t('proxy.toast.maintenance_on', 'Maintenance mode enabled')
+          : t('proxy.toast.maintenance_off', 'Maintenance mode disabled'),
+        'info',
+      );
+    },
+    onError: (error) => {
+      showToast(`${t('proxy.toast.maintenance_error', 'Failed to change maintenance mode')}: ${error}`, 'error');
+    },
+  });
+}
+
+export function useRetryProjectSetup() {
+  const { t } = useTranslation();
+
+  return useMutation({
+    mutationFn: (accountId: string) => invoke<void>('retry_project_setup', { accountId }),
+    onSuccess: () => {
+      showToast(t('proxy.toast.project_setup_verified', 'Project setup verified'), 'success');
+    },
+    onError: (error) => {
+      showToast(`${t('proxy.toast.project_setup_error', 'Project setup still incomplete')}: ${error}`, 'error');
+    },
+  });
+}
+
 export function useSetProxyMonitorEnabled() {
   const queryClient = useQueryClient();
 
@@ -54,7 +54,7 @@ export function useProxyStatus() {
 export function useProxyStats() {
   return useQuery({
     queryKey: proxyKeys.stats(),
-    queryFn: () => invoke<ProxyStats>('get_proxy_stats'),
+    queryFn: () => invoke<ProxyStats>('get_proxy_stats', { sections: ['latency'] }),
     refetchInterval: 10000,
   });
 }
@@ -34,6 +34,7 @@ const SchedulingSettings: React.FC<SchedulingSettingsProps> = ({ config, onChang
 
     const handleChangeMode = (mode: SchedulingMode) => {
         onChange({
+            ...config,
             mode,
             max_wait_seconds: maxWaitSeconds,
             selected_accounts: Array.from(selectedAccounts),
@@ -44,6 +45,7 @@ const SchedulingSettings: React.FC<SchedulingSettingsProps> = ({ config, onChang
 
     const handleChangeWait = (seconds: number) => {
         onChange({
+            ...config,
             mode: currentMode,
             max_wait_seconds: seconds,
             selected_accounts: Array.from(selectedAccounts),
@@ -54,6 +56,7 @@ const SchedulingSettings: React.FC<SchedulingSettingsProps> = ({ config, onChang
 
     const handleToggleStrict = (strict: boolean) => {
         onChange({
+            ...config,
             mode: currentMode,
             max_wait_seconds: maxWaitSeconds,
             selected_accounts: Array.from(selectedAccounts),
@@ -70,6 +73,7 @@ const SchedulingSettings: React.FC<SchedulingSettingsProps> = ({ config, onChang
             newSet.add(accountId);
         }
         onChange({
+            ...config,
             mode: currentMode,
             max_wait_seconds: maxWaitSeconds,
             selected_accounts: Array.from(newSet),
@@ -81,6 +85,7 @@ const SchedulingSettings: React.FC<SchedulingSettingsProps> = ({ config, onChang
     const toggleAllAccounts = () => {
         if (selectedAccounts.size === accounts.length) {
             onChange({
+                ...config,
                 mode: currentMode,
                 max_wait_seconds: maxWaitSeconds,
                 selected_accounts: [],
@@ -89,6 +94,7 @@ const SchedulingSettings: React.FC<SchedulingSettingsProps> = ({ config, onChang
             });
         } else {
             onChange({
+                ...config,
                 mode: currentMode,
                 max_wait_seconds: maxWaitSeconds,
                 selected_accounts: accounts.map(a => a.id),
@@ -109,6 +115,7 @@ const SchedulingSettings: React.FC<SchedulingSettingsProps> = ({ config, onChang
         }
 
         onChange({
+            ...config,
             mode: currentMode,
             max_wait_seconds: maxWaitSeconds,
             selected_accounts: Array.from(selectedAccounts),
@@ -75,6 +75,8 @@ export function useAccountsPage() {
   const [detailsAccount, setDetailsAccount] = useState<Account | null>(null);
   const [deleteConfirmId, setDeleteConfirmId] = useState<string | null>(null);
   const [isBatchDelete, setIsBatchDelete] = useState(false);
+  // 后端拒绝删除最后一个可用账号后, 再次确认时以 force 重试
+  const [batchDeleteForce, setBatchDeleteForce] = useState(false);
   const [toggleProxyConfirm, setToggleProxyConfirm] = useState<{ accountId: string; enable: boolean } | null>(null);
   const [isWarmupConfirmOpen, setIsWarmupConfirmOpen] = useState(false);
   const [refreshingIds, setRefreshingIds] = useState<Set<string>>(new Set());
@@ -289,20 +291,38 @@ export function useAccountsPage() {
 
   const handleBatchDelete = useCallback(() => {
     if (selectedIds.size === 0) return;
+    setBatchDeleteForce(false);
     setIsBatchDelete(true);
   }, [selectedIds]);
 
   const executeBatchDelete = useCallback(async () => {
+    const force = batchDeleteForce;
     setIsBatchDelete(false);
+    setBatchDeleteForce(false);
     try {
       const ids = Array.from(selectedIds);
-      await deleteAccountsMutation.mutateAsync(ids);
-      setSelectedIds(new Set());
-      showToast(t('common.success'), 'success');
+      const results = await deleteAccountsMutation.mutateAsync({ accountIds: ids, force });
+      const failed = results.filter(r => !r.ok);
+      // 失败的账号保持选中, 便于重试
+      setSelectedIds(new Set(failed.map(r => r.account_id)));
+      if (failed.length === 0) {
+        showToast(t('accounts.toast.bulk_delete_success', 'Accounts deleted'), 'success');
+      } else {
+        showToast(t('accounts.toast.bulk_delete_partial', {
+          success: results.length - failed.length,
+          fail: failed.length,
+          error: failed[0].error ?? '',
+        }), 'warning', 5000);
+      }
     } catch (error) {
-      showToast(`${t('common.error')}: ${error}`, 'error');
+      if (!force && String(error).includes('last usable account')) {
+        setBatchDeleteForce(true);
+        setIsBatchDelete(true);
+        return;
+      }
+      showToast(`${t('accounts.toast.delete_error', 'Failed to delete')}: ${error}`, 'error');
     }
-  }, [selectedIds, deleteAccountsMutation, t]);
+  }, [selectedIds, batchDeleteForce, deleteAccountsMutation, t]);
 
   const handleDelete = useCallback((accountId: string) => {
     setDeleteConfirmId(accountId);
@@ -585,6 +605,7 @@ export function useAccountsPage() {
     setDeleteConfirmId,
     isBatchDelete,
     setIsBatchDelete,
+    batchDeleteForce,
     toggleProxyConfirm,
     setToggleProxyConfirm,
     isWarmupConfirmOpen,
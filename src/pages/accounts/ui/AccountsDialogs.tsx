@@ -16,6 +16,8 @@ interface AccountsDialogsProps {
   // Delete dialog
   deleteConfirmId: string | null;
   isBatchDelete: boolean;
+  /** 批量删除会移除最后一个可用账号, 需再次确认 */
+  batchDeleteForce: boolean;
   selectedCount: number;
   onConfirmDelete: () => void;
   onCancelDelete: () => void;
@@ -43,6 +45,7 @@ export const AccountsDialogs = memo(function AccountsDialogs({
   onCloseDevice,
   deleteConfirmId,
   isBatchDelete,
+  batchDeleteForce,
   selectedCount,
   onConfirmDelete,
   onCancelDelete,
@@ -75,7 +78,9 @@ export const AccountsDialogs = memo(function AccountsDialogs({
         isOpen={!!deleteConfirmId || isBatchDelete}
         title={isBatchDelete ? t('accounts.dialog.batch_delete_title') : t('accounts.dialog.delete_title')}
         message={isBatchDelete
-          ? t('accounts.dialog.batch_delete_msg', { count: selectedCount })
+          ? (batchDeleteForce
+            ? t('accounts.dialog.batch_delete_force_msg')
+            : t('accounts.dialog.batch_delete_msg', { count: selectedCount }))
           : t('accounts.dialog.delete_msg')
         }
         type="confirm"
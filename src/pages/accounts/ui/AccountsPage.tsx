@@ -42,6 +42,7 @@ export const AccountsPage = memo(function AccountsPage() {
     deleteConfirmId,
     setDeleteConfirmId,
     isBatchDelete,
+    batchDeleteForce,
     setIsBatchDelete,
     toggleProxyConfirm,
     setToggleProxyConfirm,
@@ -193,6 +194,7 @@ export const AccountsPage = memo(function AccountsPage() {
         onCloseDevice={() => setDeviceAccount(null)}
         deleteConfirmId={deleteConfirmId}
         isBatchDelete={isBatchDelete}
+        batchDeleteForce={batchDeleteForce}
         selectedCount={selectedIds.size}
         onConfirmDelete={isBatchDelete ? executeBatchDelete : executeDelete}
         onCancelDelete={() => { setDeleteConfirmId(null); setIsBatchDelete(false); }}
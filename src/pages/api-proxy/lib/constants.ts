@@ -1,11 +1,98 @@
 // File: src/pages/api-proxy/lib/constants.ts
 // Types and constants for API Proxy page
 
+import type { ProjectSetupRequired } from '@/entities/account';
+
 export interface ProxyStatus {
     running: boolean;
     port: number;
     base_url: string;
+    /** 各协议可直接粘贴到客户端的 base URL (服务未运行时为空) */
+    endpoints?: ProxyEndpoints | null;
     active_accounts: number;
+    uptime_secs?: number;
+    upstream_warning?: FailurePatternWarning | null;
+    admin_server?: AdminServerStatus;
+    maintenance?: MaintenanceStatus;
+}
+
+/** 维护模式: 拒绝新请求, 进行中的请求 (含流式) 继续完成 */
+export interface MaintenanceStatus {
+    enabled: boolean;
+    message?: string | null;
+    started_at?: number | null;
+    /** 自动退出时间 (秒级时间戳); 为空时需手动退出 */
+    until?: number | null;
+    /** 仍在进行中的请求数 */
+    draining: number;
+}
+
+/** get_proxy_stats 可选分区; 状态接口只返回廉价字段, 详细统计按需拉取 */
+export type StatsSection = 'accounts' | 'latency' | 'models' | 'listeners' | 'warm_pool' | 'key_budgets';
+
+export interface KeyBudgetStatus {
+    key: string;
+    tokens_per_day: number;
+    requests_per_day: number;
+    tokens_used: number;
+    requests_used: number;
+    reset_at: number;
+    exhausted: boolean;
+}
+
+export interface AccountLoadEntry {
+    account_id: string;
+    email: string;
+    sessions: number;
+    in_flight: number;
+    recent_tokens: number;
+    score: number;
+    health_weight: number;
+    selection_share: number;
+    refresh_in_flight?: boolean;
+    stalls?: number;
+    last_refresh?: RefreshOutcome | null;
+    quota_estimates?: QuotaEstimate[];
+    project_setup?: ProjectSetupRequired | null;
+}
+
+export interface QuotaEstimate {
+    family: string;
+    budget_tokens: number;
+    used_today: number;
+    remaining_pct: number;
+    confidence: 'low' | 'medium' | 'high';
+    samples: number;
+    /** Estimated daily reset (unix seconds, next midnight in quota_timezone) */
+    resets_at: number;
+    summary: string;
+}
+
+export interface RefreshOutcome {
+    finished_at: number;
+    success: boolean;
+    error?: string | null;
+}
+
+export interface AccountsStats {
+    total: number;
+    effective: number;
+    loads: AccountLoadEntry[];
+}
+
+export interface AdminServerStatus {
+    running: boolean;
+    port: number;
+    base_url: string;
+    error?: string | null;
+}
+
+export interface FailurePatternWarning {
+    signature: string;
+    count: number;
+    total: number;
+    first_seen: number;
+    last_seen: number;
 }
 
 export interface CloudflaredStatus {
@@ -20,6 +107,24 @@ export type ProtocolType = 'openai' | 'anthropic' | 'gemini';
 export type CloudflaredMode = 'quick' | 'auth';
 
 export const DEFAULT_PROXY_PORT = 8045;
+
+/** 各协议的客户端 base URL (与后端 ProxyEndpoints 一致, OpenAI 带 /v1 后缀) */
+export interface ProxyEndpoints {
+    anthropic: string;
+    openai: string;
+    gemini: string;
+}
+
+export function endpointsForPort(port: number): ProxyEndpoints {
+    const base = `http://127.0.0.1:${port}`;
+    return { anthropic: base, openai: `${base}/v1`, gemini: base };
+}
+
+/** 运行中使用实际监听端口, 未运行时使用已配置端口 (修改端口后示例代码随之更新) */
+export function resolveEndpoints(status: ProxyStatus, configPort?: number): ProxyEndpoints {
+    if (status.running && status.endpoints) return status.endpoints;
+    return endpointsForPort(status.running ? status.port : (configPort || DEFAULT_PROXY_PORT));
+}
 export const DEFAULT_REQUEST_TIMEOUT = 120;
 
 export const MODEL_PRESETS: Record<string, string> = {
@@ -5,11 +5,11 @@ import { useState, useEffect, useMemo, useCallback } from 'react';
 import { useTranslation } from 'react-i18next';
 import { invoke } from '@/shared/api';
 import { copyToClipboard } from '@/shared/lib';
-import type { AppConfig, ProxyConfig, StickySessionConfig, ExperimentalConfig, CircuitBreakerConfig } from '@/entities/config';
+import type { AppConfig, ProxyConfig, StickySessionConfig, ExperimentalConfig, CircuitBreakerConfig, ZaiModelList } from '@/entities/config';
 import { showToast } from '@/shared/ui';
 import { useProxyModels } from '@/shared/hooks';
 import type { ProxyStatus, CloudflaredStatus, ProtocolType, CloudflaredMode } from '../lib/constants';
-import { MODEL_PRESETS } from '../lib/constants';
+import { MODEL_PRESETS, resolveEndpoints } from '../lib/constants';
 
 export function useApiProxy() {
     const { t } = useTranslation();
@@ -179,21 +179,21 @@ export function useApiProxy() {
             selected_accounts: updates.selected_accounts ?? currentScheduling.selected_accounts ?? [],
             strict_selected: updates.strict_selected ?? currentScheduling.strict_selected ?? false
         };
-        const newAppConfig = {
-            ...appConfig,
-            proxy: { ...appConfig.proxy, scheduling: newScheduling }
-        };
-        saveConfig(newAppConfig);
-        
-        // [FIX] Sync scheduling config with running proxy service
+        // The backend is the only writer: it validates, persists and returns the normalized config
         try {
-            await invoke('update_proxy_scheduling_config', { config: newScheduling });
-            console.debug('[Scheduling] Config synced with running proxy service');
+            const persisted = await invoke<StickySessionConfig>('update_proxy_scheduling_config', { config: newScheduling });
+            setAppConfig({
+                ...appConfig,
+                proxy: { ...appConfig.proxy, scheduling: persisted }
+            });
         } catch (error) {
-            // Service may not be running - this is fine, config is saved for next start
-            console.debug('[Scheduling] Service not running, config saved for next start:', error);
+            const appError = error as { type?: string; details?: { violations?: { field: string; message: string }[] } };
+            const message = appError?.type === 'ConfigViolations'
+                ? (appError.details?.violations ?? []).map(v => `${v.field}: ${v.message}`).join('; ')
+                : String(error);
+            showToast(`${t('common.error')}: ${message}`, 'error');
         }
-    }, [appConfig, saveConfig]);
+    }, [appConfig, t]);
 
     const updateExperimentalConfig = useCallback((updates: Partial<ExperimentalConfig>) => {
         if (!appConfig) return;
@@ -376,16 +376,20 @@ export function useApiProxy() {
     }, [t]);
 
     // Z.AI handlers
-    const refreshZaiModels = useCallback(async () => {
+    const refreshZaiModels = useCallback(async (forceRefresh = false) => {
         if (!appConfig?.proxy.zai) return;
         setZaiModelsLoading(true);
         try {
-            const models = await invoke<string[]>('fetch_zai_models', {
+            const list = await invoke<ZaiModelList>('fetch_zai_models', {
                 zai: appConfig.proxy.zai,
                 upstreamProxy: appConfig.proxy.upstream_proxy,
                 requestTimeout: appConfig.proxy.request_timeout,
+                forceRefresh,
             });
-            setZaiAvailableModels(models);
+            setZaiAvailableModels(list.models);
+            if (list.stale) {
+                console.warn('z.ai model list is stale:', list.error);
+            }
         } catch (error: any) {
             console.error('Failed to fetch z.ai models:', error);
         } finally {
@@ -522,15 +526,15 @@ export function useApiProxy() {
 
     // Python example generator
     const getPythonExample = useCallback((modelId: string) => {
-        const port = status.running ? status.port : (appConfig?.proxy.port || 8045);
-        const baseUrl = `http://127.0.0.1:${port}/v1`;
+        const endpoints = resolveEndpoints(status, appConfig?.proxy.port);
+        const baseUrl = endpoints.openai;
         const apiKey = appConfig?.proxy.api_key || 'YOUR_API_KEY';
 
         if (selectedProtocol === 'anthropic') {
             return `from anthropic import Anthropic
  
 client = Anthropic(
-    base_url="http://127.0.0.1:${port}",
+    base_url="${endpoints.anthropic}",
     api_key="${apiKey}"
 )
 
@@ -550,7 +554,7 @@ import google.generativeai as genai
 genai.configure(
     api_key="${apiKey}",
     transport='rest',
-    client_options={'api_endpoint': 'http://127.0.0.1:${port}'}
+    client_options={'api_endpoint': '${endpoints.gemini}'}
 )
 
 model = genai.GenerativeModel('${modelId}')
@@ -22,7 +22,7 @@ import { DebouncedSlider } from '@/shared/ui/debounced-slider';
 import { CircuitBreaker, SchedulingSettings } from '@/features/settings';
 import { CliSyncCard } from '@/features/proxy';
 import type { AppConfig, ProxyConfig, StickySessionConfig, ExperimentalConfig, CircuitBreakerConfig } from '@/entities/config';
-import type { ProxyStatus, CloudflaredStatus, CloudflaredMode } from '../lib/constants';
+import { resolveEndpoints, type ProxyStatus, type CloudflaredStatus, type CloudflaredMode } from '../lib/constants';
 
 interface ExternalProvidersSectionProps {
     appConfig: AppConfig;
@@ -52,7 +52,7 @@ interface ExternalProvidersSectionProps {
     updateZaiDefaultModels: (updates: Partial<NonNullable<ProxyConfig['zai']>['models']>) => void;
     upsertZaiModelMapping: (from: string, to: string) => void;
     removeZaiModelMapping: (from: string) => void;
-    refreshZaiModels: () => void;
+    refreshZaiModels: (forceRefresh?: boolean) => void;
     handleCfInstall: () => void;
     handleCfToggle: (enable: boolean) => void;
     handleCfCopyUrl: () => void;
@@ -106,7 +106,7 @@ export function ExternalProvidersSection({
 
             {/* CLI Sync Card */}
             <CliSyncCard
-                proxyUrl={status.running ? status.base_url : `http://127.0.0.1:${appConfig.proxy.port || 8045}`}
+                proxyUrl={resolveEndpoints(status, appConfig.proxy.port).anthropic}
                 apiKey={appConfig.proxy.api_key}
             />
 
@@ -173,7 +173,7 @@ export function ExternalProvidersSection({
                                 {t('proxy.config.zai.models.title')}
                             </h4>
                             <button
-                                onClick={refreshZaiModels}
+                                onClick={() => refreshZaiModels(true)}
                                 disabled={zaiModelsLoading || !appConfig.proxy.zai?.api_key}
                                 className="btn btn-ghost btn-xs gap-1"
                             >
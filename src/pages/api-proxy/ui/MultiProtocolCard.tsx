@@ -3,7 +3,7 @@
 
 import { useTranslation } from 'react-i18next';
 import { Code, Copy, CheckCircle } from 'lucide-react';
-import type { ProtocolType, ProxyStatus } from '../lib/constants';
+import { resolveEndpoints, type ProtocolType, type ProxyStatus } from '../lib/constants';
 import type { AppConfig } from '@/entities/config';
 
 interface MultiProtocolCardProps {
@@ -24,7 +24,8 @@ export function MultiProtocolCard({
     onCopy,
 }: MultiProtocolCardProps) {
     const { t } = useTranslation();
-    const baseUrl = status.running ? status.base_url : `http://127.0.0.1:${appConfig.proxy.port || 8045}`;
+    const endpoints = resolveEndpoints(status, appConfig.proxy.port);
+    const baseUrl = endpoints.anthropic;
 
     return (
         <div className="bg-white dark:bg-base-100 rounded-xl shadow-sm border border-gray-100 dark:border-base-200 overflow-hidden">
@@ -55,7 +56,7 @@ export function MultiProtocolCard({
                     >
                         <div className="flex items-center justify-between mb-2">
                             <span className="text-xs font-bold text-blue-600">{t('proxy.multi_protocol.openai_label')}</span>
-                            <button onClick={(e) => { e.stopPropagation(); onCopy(`${baseUrl}/v1`, 'openai'); }} className="btn btn-ghost btn-xs">
+                            <button onClick={(e) => { e.stopPropagation(); onCopy(endpoints.openai, 'openai'); }} className="btn btn-ghost btn-xs">
                                 {copied === 'openai' ? <CheckCircle size={14} /> : <div className="flex items-center gap-1 text-[10px] uppercase font-bold tracking-tighter"><Copy size={12} /> {t('proxy.multi_protocol.copy_base', { defaultValue: 'Base' })}</div>}
                             </button>
                         </div>
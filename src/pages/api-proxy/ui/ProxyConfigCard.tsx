@@ -9,7 +9,8 @@ import {
     CheckCircle,
     Settings,
     X,
-    Edit2
+    Edit2,
+    AlertTriangle
 } from 'lucide-react';
 import { HelpTooltip } from '@/shared/ui';
 import type { AppConfig, ProxyConfig } from '@/entities/config';
@@ -96,6 +97,27 @@ export function ProxyConfigCard({
             </div>
 
             <div className="p-3 space-y-3">
+                {/* Upstream failure pattern warning */}
+                {status.running && status.upstream_warning && (
+                    <div className="flex items-start gap-2 p-2.5 rounded-lg border border-amber-200 dark:border-amber-900/50 bg-amber-50 dark:bg-amber-900/10">
+                        <AlertTriangle size={16} className="text-amber-600 dark:text-amber-500 shrink-0 mt-0.5" />
+                        <div className="min-w-0 space-y-1">
+                            <p className="text-xs font-medium text-amber-700 dark:text-amber-400">
+                                {t('proxy.status.upstream_warning_title')}
+                            </p>
+                            <p className="text-[11px] text-amber-700/90 dark:text-amber-400/90">
+                                {t('proxy.status.upstream_warning_desc', {
+                                    count: status.upstream_warning.count,
+                                    total: status.upstream_warning.total,
+                                })}
+                            </p>
+                            <code className="block text-[10px] font-mono text-gray-600 dark:text-gray-400 break-all">
+                                {status.upstream_warning.signature}
+                            </code>
+                        </div>
+                    </div>
+                )}
+
                 {/* Port, Timeout, Auto-start */}
                 <div className="grid grid-cols-1 md:grid-cols-3 gap-3">
                     <div>
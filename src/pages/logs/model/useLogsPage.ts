@@ -27,12 +27,43 @@ export interface ProxyRequestLog {
   output_tokens?: number;
   account_email?: string;
   protocol?: string;
+  raw_upstream?: string;
+  service_tier?: string;
+  upstream_response_id?: string;
+  upstream_model_version?: string;
+  provider_decision?: ProviderDecision;
+  raw_messages?: boolean;
+  /** 应用的系统提示注入模板名 */
+  system_injections?: string[];
+  account_rotations?: string[];
+  /** 按字节计量的用量 (语音合成等) */
+  input_bytes?: number;
+  output_bytes?: number;
+  termination?: Termination;
+}
+
+/** How a request ended; logs written before this field existed report 'unknown' */
+export type Termination = 'success' | 'client_cancelled' | 'upstream_error' | 'local_error' | 'timeout' | 'unknown';
+
+export type DispatchReason =
+  | { kind: 'exclusive' }
+  | { kind: 'no_google_accounts' }
+  | { kind: 'all_unavailable'; model: string }
+  | { kind: 'pooled_slot' };
+
+export interface ProviderDecision {
+  /** provider id, "google" for the Google account pool */
+  provider: string;
+  dispatch_mode?: 'exclusive' | 'pooled' | 'fallback';
+  reason?: DispatchReason;
 }
 
 export interface ProxyStats {
   total_requests: number;
   success_count: number;
   error_count: number;
+  /** Requests per termination kind (client cancellations are not counted in error_count) */
+  terminations?: Partial<Record<Termination, number>>;
 }
 
 export type QuickFilterType = '' | '__ERROR__' | 'completions' | 'gemini' | 'claude' | 'images';
@@ -147,7 +178,7 @@ export function useLogsPage() {
           limit: pageSize,
           offset
         }),
-        invoke<ProxyStats>('get_proxy_stats'),
+        invoke<ProxyStats>('get_proxy_stats', { sections: ['latency'] }),
       ]);
 
       if (isMountedRef.current) {
@@ -295,7 +326,7 @@ export function useLogsPage() {
             // Update stats in background
             try {
               const [currentStats, count] = await Promise.all([
-                invoke<ProxyStats>('get_proxy_stats'),
+                invoke<ProxyStats>('get_proxy_stats', { sections: ['latency'] }),
                 invoke<number>('get_proxy_logs_count_filtered', { filter: '', errorsOnly: false })
               ]);
               if (isMountedRef.current) {
@@ -6,7 +6,7 @@ import { useTranslation } from 'react-i18next';
 import { motion, AnimatePresence } from 'framer-motion';
 import { X, Copy, Check } from 'lucide-react';
 
-import { formatCompactNumber, copyToClipboard } from '@/shared/lib';
+import { formatBytes, formatCompactNumber, copyToClipboard } from '@/shared/lib';
 import type { ProxyRequestLog } from '../model';
 
 interface LogDetailModalProps {
@@ -22,6 +22,8 @@ export const LogDetailModal = memo(function LogDetailModal({
 }: LogDetailModalProps) {
   const { t } = useTranslation();
   const [copiedField, setCopiedField] = useState<string | null>(null);
+  // 语音合成等按字节计量的请求没有 token 数
+  const byteUsage = log?.input_bytes != null || log?.output_bytes != null;
 
   const handleCopy = async (content: string, field: string) => {
     const success = await copyToClipboard(content);
@@ -129,14 +131,14 @@ export const LogDetailModal = memo(function LogDetailModal({
                   </div>
                   <div className="space-y-1">
                     <span className="block text-zinc-500 dark:text-zinc-400 uppercase font-bold text-[10px] tracking-widest">
-                      {t('logs.detail.tokens', 'Tokens')}
+                      {byteUsage ? t('logs.detail.bytes', 'Bytes') : t('logs.detail.tokens', 'Tokens')}
                     </span>
                     <div className="flex gap-2 text-xs font-mono">
                       <span className="px-2 py-1 rounded bg-blue-100 dark:bg-blue-900/40 text-blue-700 dark:text-blue-300 border border-blue-200 dark:border-blue-800/50 font-bold">
-                        In: {formatCompactNumber(log.input_tokens ?? 0)}
+                        In: {byteUsage ? formatBytes(log.input_bytes ?? 0) : formatCompactNumber(log.input_tokens ?? 0)}
                       </span>
                       <span className="px-2 py-1 rounded bg-green-100 dark:bg-green-900/40 text-green-700 dark:text-green-300 border border-green-200 dark:border-green-800/50 font-bold">
-                        Out: {formatCompactNumber(log.output_tokens ?? 0)}
+                        Out: {byteUsage ? formatBytes(log.output_bytes ?? 0) : formatCompactNumber(log.output_tokens ?? 0)}
                       </span>
                     </div>
                   </div>
@@ -41,7 +41,7 @@ export function useTokenStats() {
             let accountTrend: AccountTrendPoint[] = [];
 
             // Fetch generic request stats
-            const reqStats = await invoke<ProxyStats>('get_proxy_stats');
+            const reqStats = await invoke<ProxyStats>('get_proxy_stats', { sections: ['latency'] });
             setRequestStats(reqStats);
 
             switch (timeRange) {
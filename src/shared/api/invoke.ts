@@ -17,6 +17,11 @@ const COMMAND_MAPPING: Record<string, { url: string; method: 'GET' | 'POST' | 'D
   'add_account': { url: '/api/accounts', method: 'POST' },
   'delete_account': { url: '/api/accounts/:accountId', method: 'DELETE' },
   'delete_accounts': { url: '/api/accounts/bulk-delete', method: 'POST' },
+  'pause_accounts': { url: '/api/accounts/bulk-pause', method: 'POST' },
+  'set_accounts_priority': { url: '/api/accounts/bulk-priority', method: 'POST' },
+  'set_accounts_tags': { url: '/api/accounts/bulk-tags', method: 'POST' },
+  'set_account_notes': { url: '/api/accounts/:accountId/notes', method: 'POST' },
+  'get_account_health': { url: '/api/accounts/health', method: 'GET' },
   'fetch_account_quota': { url: '/api/accounts/:accountId/quota', method: 'GET' },
   'refresh_account_quota': { url: '/api/accounts/:accountId/quota', method: 'GET' },
   'refresh_all_quotas': { url: '/api/accounts/refresh', method: 'POST' },
@@ -37,18 +42,29 @@ const COMMAND_MAPPING: Record<string, { url: string; method: 'GET' | 'POST' | 'D
 
   // Proxy Control & Status
   'get_proxy_status': { url: '/api/proxy/status', method: 'GET' },
+  'copy_endpoint_url': { url: '/api/proxy/endpoint-url', method: 'GET' },
   'start_proxy_service': { url: '/api/proxy/start', method: 'POST' },
   'stop_proxy_service': { url: '/api/proxy/stop', method: 'POST' },
+  'set_maintenance_mode': { url: '/api/proxy/maintenance', method: 'POST' },
   'update_model_mapping': { url: '/api/proxy/mapping', method: 'POST' },
+  'get_proxy_scheduling_config': { url: '/api/proxy/scheduling', method: 'GET' },
+  'update_proxy_scheduling_config': { url: '/api/proxy/scheduling', method: 'POST' },
+  'get_proxy_retry_config': { url: '/api/proxy/retry', method: 'GET' },
+  'update_proxy_retry_config': { url: '/api/proxy/retry', method: 'POST' },
+  'get_background_routing': { url: '/api/proxy/background-routing', method: 'GET' },
+  'update_background_routing': { url: '/api/proxy/background-routing', method: 'POST' },
   'generate_api_key': { url: '/api/proxy/api-key/generate', method: 'POST' },
   'clear_proxy_session_bindings': { url: '/api/proxy/session-bindings/clear', method: 'POST' },
   'clear_proxy_rate_limit': { url: '/api/proxy/rate-limits/:accountId', method: 'DELETE' },
   'clear_all_proxy_rate_limits': { url: '/api/proxy/rate-limits', method: 'DELETE' },
+  'retry_project_setup': { url: '/api/proxy/project-setup/:accountId/retry', method: 'POST' },
 
   'fetch_zai_models': { url: '/api/zai/models/fetch', method: 'POST' },
   'load_config': { url: '/api/config', method: 'GET' },
   'save_config': { url: '/api/config', method: 'POST' },
   'get_proxy_stats': { url: '/api/proxy/stats', method: 'GET' },
+  'get_calibration_state': { url: '/api/proxy/calibration', method: 'GET' },
+  'get_model_health': { url: '/api/proxy/model-health', method: 'GET' },
   'set_proxy_monitor_enabled': { url: '/api/proxy/monitor/toggle', method: 'POST' },
 
   // Logs & Monitoring
@@ -113,6 +129,7 @@ const COMMAND_MAPPING: Record<string, { url: string; method: 'GET' | 'POST' | 'D
   'import_v1_accounts': { url: '/api/accounts/import/v1', method: 'POST' },
   'import_from_db': { url: '/api/accounts/import/db', method: 'POST' },
   'import_custom_db': { url: '/api/accounts/import/db-custom', method: 'POST' },
+  'import_from_antigravity': { url: '/api/accounts/import/antigravity', method: 'POST' },
   'sync_account_from_db': { url: '/api/accounts/sync/db', method: 'POST' },
 
   // System Extra
@@ -149,6 +166,31 @@ const COMMAND_MAPPING: Record<string, { url: string; method: 'GET' | 'POST' | 'D
   'export_accounts_by_ids': { url: '/api/accounts/export', method: 'POST' },
 };
 
+/**
+ * Structured command error ({ type, message, details }), e.g. ProxyCommandError
+ */
+export interface CommandError {
+  type: string;
+  message: string;
+  details?: Record<string, unknown>;
+}
+
+/**
+ * Keep `${error}` / String(error) rendering the human-readable message for structured errors,
+ * so UIs that still treat errors as strings keep working while `error.type` stays available.
+ */
+function withLegacyMessage(error: unknown): unknown {
+  if (
+    error && typeof error === 'object' &&
+    typeof (error as CommandError).type === 'string' &&
+    typeof (error as CommandError).message === 'string'
+  ) {
+    const message = (error as CommandError).message;
+    return Object.assign(Object.create({ toString: () => message }), error) as CommandError;
+  }
+  return error;
+}
+
 /**
  * Universal invoke function - uses Tauri IPC in desktop, HTTP API in web
  */
@@ -159,7 +201,7 @@ export async function invoke<T>(cmd: string, args?: Record<string, unknown>): Pr
       return await tauriInvoke<T>(cmd, args);
     } catch (error) {
       console.error(`[Tauri Invoke Error] ${cmd}:`, error);
-      throw error;
+      throw withLegacyMessage(error);
     }
   }
 
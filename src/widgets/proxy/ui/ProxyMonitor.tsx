@@ -6,7 +6,7 @@ import { invoke } from '@/shared/api';
 import { Trash2, Search, X, Copy, CheckCircle, ChevronLeft, ChevronRight, RefreshCw, User } from 'lucide-react';
 
 import { AppConfig } from '@/entities/config';
-import { formatCompactNumber } from '@/shared/lib';
+import { formatBytes, formatCompactNumber } from '@/shared/lib';
 import { useAccounts } from '@/features/accounts';
 import { isTauri, copyToClipboard } from '@/shared/lib';
 
@@ -27,14 +27,103 @@ interface ProxyRequestLog {
     output_tokens?: number;
     account_email?: string;
     protocol?: string;  // "openai" | "anthropic" | "gemini"
+    raw_upstream?: string;  // tee 调试模式下的原始上游 SSE
+    service_tier?: string;  // 客户端请求的 service_tier (原样回显)
+    upstream_response_id?: string;  // 上游 responseId (反馈给 Google 支持)
+    upstream_model_version?: string;
+    provider_decision?: {  // 分发决策: 由哪个 provider 处理及原因
+        provider: string;
+        dispatch_mode?: string;
+        reason?: { kind: string; model?: string };
+    };
+    raw_messages?: boolean;  // 原始消息调试模式 (跳过消息规范化)
+    system_injections?: string[];  // 应用的系统提示注入模板名
+    account_rotations?: string[];  // 重试中的换号记录
+    input_bytes?: number;  // 按字节计量的用量 (语音合成等)
+    output_bytes?: number;
+    termination?: string;  // 终止方式: success / client_cancelled / upstream_error / local_error / timeout / unknown
 }
 
 interface ProxyStats {
     total_requests: number;
     success_count: number;
     error_count: number;
+    terminations?: Record<string, number>;  // 按终止方式拆分 (客户端断开不计入 error_count)
+    debug_log_write_failures?: number;  // 调试载荷写入失败次数 (启动以来)
+    multi_candidate_responses?: number;  // 多余候选被忽略的响应数 (启动以来)
+}
+
+interface ModelHealth {
+    model: string;
+    requests: number;
+    success_rate: number;
+    recent_requests: number;
+    recent_success_rate: number;
+    median_latency_ms?: number | null;
+    top_errors: { signature: string; count: number }[];
+    degraded: boolean;
 }
 
+// Model health strip: per physical model success rate over the last hour
+const ModelHealthStrip: React.FC<{ models: ModelHealth[]; t: any }> = ({ models, t }) => {
+    if (models.length === 0) return null;
+    return (
+        <div className="flex flex-wrap items-center gap-2">
+            <span className="text-[10px] font-bold text-gray-400 uppercase">{t('monitor.model_health.title')}</span>
+            {models.map(m => {
+                const tooltip = [
+                    t('monitor.model_health.tooltip', {
+                        requests: m.recent_requests,
+                        latency: m.median_latency_ms != null ? `${m.median_latency_ms}ms` : '-'
+                    }),
+                    ...(m.top_errors.length > 0
+                        ? [`${t('monitor.model_health.top_errors')}:`, ...m.top_errors.map(e => `${e.count}× ${e.signature}`)]
+                        : [])
+                ].join('\n');
+                return (
+                    <span
+                        key={m.model}
+                        title={tooltip}
+                        className={`px-2 py-0.5 rounded-full text-[10px] border font-mono ${m.degraded
+                            ? 'bg-red-50 dark:bg-red-900/20 border-red-300 text-red-600'
+                            : 'bg-white dark:bg-base-200 border-gray-200 dark:border-base-300 text-gray-600 dark:text-gray-300'
+                            }`}
+                    >
+                        {m.model} {Math.round(m.recent_success_rate * 100)}%
+                        {m.degraded && <span className="ml-1 font-bold">{t('monitor.model_health.degraded')}</span>}
+                    </span>
+                );
+            })}
+        </div>
+    );
+};
+
+// Termination breakdown: how requests ended (client cancellations are not errors)
+const TERMINATION_KINDS: { key: string; className: string }[] = [
+    { key: 'success', className: 'text-green-500' },
+    { key: 'client_cancelled', className: 'text-gray-400' },
+    { key: 'upstream_error', className: 'text-red-500' },
+    { key: 'local_error', className: 'text-orange-500' },
+    { key: 'timeout', className: 'text-yellow-600' },
+    { key: 'unknown', className: 'text-gray-400' },
+];
+
+const TerminationStrip: React.FC<{ terminations?: Record<string, number>; t: any }> = ({ terminations, t }) => {
+    if (!terminations) return null;
+    const kinds = TERMINATION_KINDS.filter(k => (terminations[k.key] ?? 0) > 0);
+    if (kinds.length === 0) return null;
+    return (
+        <div className="flex flex-wrap items-center gap-3 text-[10px]">
+            <span className="font-bold text-gray-400 uppercase">{t('monitor.terminations.title')}</span>
+            {kinds.map(k => (
+                <span key={k.key} className={`font-mono ${k.className}`}>
+                    {t(`monitor.terminations.${k.key}`)} {formatCompactNumber(terminations[k.key])}
+                </span>
+            ))}
+        </div>
+    );
+};
+
 interface ProxyMonitorProps {
     className?: string;
 }
@@ -108,6 +197,8 @@ const LogTable: React.FC<LogTableProps> = ({
                             <td className="text-right text-[9px]" style={{ width: '90px' }}>
                                 {log.input_tokens != null && <div>I: {formatCompactNumber(log.input_tokens)}</div>}
                                 {log.output_tokens != null && <div>O: {formatCompactNumber(log.output_tokens)}</div>}
+                                {log.input_bytes != null && <div>I: {formatBytes(log.input_bytes)}</div>}
+                                {log.output_bytes != null && <div>O: {formatBytes(log.output_bytes)}</div>}
                             </td>
                             <td className="text-right" style={{ width: '80px' }}>{log.duration}ms</td>
                             <td className="text-right text-[10px]" style={{ width: '80px' }}>
@@ -141,6 +232,7 @@ export const ProxyMonitor: React.FC<ProxyMonitorProps> = ({ className }) => {
     const { t } = useTranslation();
     const [logs, setLogs] = useState<ProxyRequestLog[]>([]);
     const [stats, setStats] = useState<ProxyStats>({ total_requests: 0, success_count: 0, error_count: 0 });
+    const [modelHealth, setModelHealth] = useState<ModelHealth[]>([]);
     const [filter, setFilter] = useState('');
     const [accountFilter, setAccountFilter] = useState('');
     const [selectedLog, setSelectedLog] = useState<ProxyRequestLog | null>(null);
@@ -227,11 +319,17 @@ export const ProxyMonitor: React.FC<ProxyMonitorProps> = ({ className }) => {
             }
 
             const currentStats = await Promise.race([
-                invoke<ProxyStats>('get_proxy_stats'),
+                invoke<ProxyStats>('get_proxy_stats', { sections: ['latency'] }),
                 timeoutPromise
             ]) as ProxyStats;
 
             if (currentStats) setStats(currentStats);
+
+            const health = await Promise.race([
+                invoke<ModelHealth[]>('get_model_health'),
+                timeoutPromise
+            ]) as ModelHealth[];
+            if (Array.isArray(health)) setModelHealth(health);
         } catch (e: any) {
             console.error("Failed to load proxy data", e);
             if (e.message === 'Request timeout') {
@@ -329,13 +427,15 @@ export const ProxyMonitor: React.FC<ProxyMonitorProps> = ({ className }) => {
 
                         // Fetch stats and total count from backend instead of local calculation
                         try {
-                            const [currentStats, count] = await Promise.all([
-                                invoke<ProxyStats>('get_proxy_stats'),
-                                invoke<number>('get_proxy_logs_count_filtered', { filter: '', errorsOnly: false })
+                            const [currentStats, count, health] = await Promise.all([
+                                invoke<ProxyStats>('get_proxy_stats', { sections: ['latency'] }),
+                                invoke<number>('get_proxy_logs_count_filtered', { filter: '', errorsOnly: false }),
+                                invoke<ModelHealth[]>('get_model_health')
                             ]);
                             if (isMountedRef.current) {
                                 if (currentStats) setStats(currentStats);
                                 setTotalCount(count);
+                                if (Array.isArray(health)) setModelHealth(health);
                             }
                         } catch (e) {
                             console.error('Failed to fetch stats:', e);
@@ -502,6 +602,9 @@ export const ProxyMonitor: React.FC<ProxyMonitorProps> = ({ className }) => {
                     ))}
                     {(filter || accountFilter) && <button onClick={() => { setFilter(''); setAccountFilter(''); }} className="text-[10px] text-blue-500"> {t('monitor.filters.reset')} </button>}
                 </div>
+
+                <ModelHealthStrip models={modelHealth} t={t} />
+                <TerminationStrip terminations={stats.terminations} t={t} />
             </div>
 
             <LogTable